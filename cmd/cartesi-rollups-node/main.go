@@ -5,8 +5,11 @@ package main
 
 import (
 	"context"
+	"flag"
+	"fmt"
 	"log/slog"
 	"os"
+	"strings"
 	"os/signal"
 	"syscall"
 	"time"
@@ -24,6 +27,13 @@ var (
 )
 
 func main() {
+	if len(os.Args) > 1 && os.Args[1] == "support-bundle" {
+		os.Exit(supportBundle(os.Args[2:]))
+	}
+	if len(os.Args) > 1 && os.Args[1] == "doctor" {
+		os.Exit(doctor())
+	}
+
 	startTime := time.Now()
 
 	ctx, stop := signal.NotifyContext(context.Background(), syscall.SIGINT, syscall.SIGTERM)
@@ -67,3 +77,61 @@ func main() {
 		os.Exit(1)
 	}
 }
+
+// stringList is a flag that may be given more than once.
+type stringList []string
+
+func (l *stringList) String() string {
+	return strings.Join(*l, ",")
+}
+
+func (l *stringList) Set(value string) error {
+	*l = append(*l, value)
+	return nil
+}
+
+// supportBundle writes an archive with the state of the node to attach to
+// bug reports, reading the node config from the environment like the node.
+func supportBundle(args []string) int {
+	flags := flag.NewFlagSet("support-bundle", flag.ExitOnError)
+	output := flags.String(
+		"output",
+		fmt.Sprintf("support-bundle-%v.tar.gz", time.Now().UTC().Format("20060102T150405Z")),
+		"path of the archive to create",
+	)
+	var logFiles stringList
+	flags.Var(&logFiles, "log-file", "log file to include the tail of; may be repeated")
+	logBytes := flags.Int64("log-bytes", 1<<20, "bytes read from the end of each log file")
+	flags.Parse(args)
+
+	config := config.FromEnv()
+	file, err := os.Create(*output)
+	if err != nil {
+		fmt.Fprintf(os.Stderr, "failed to create support bundle: %v\n", err)
+		return 1
+	}
+	defer file.Close()
+
+	opts := node.SupportBundleOptions{
+		Version:  buildVersion,
+		LogFiles: logFiles,
+		LogBytes: *logBytes,
+	}
+	if err := node.WriteSupportBundle(context.Background(), config, opts, file); err != nil {
+		fmt.Fprintf(os.Stderr, "failed to write support bundle: %v\n", err)
+		return 1
+	}
+	fmt.Printf("Wrote support bundle to %v\n", *output)
+	return 0
+}
+
+// doctor runs the checks of the node config and dependencies, reading the
+// config from the environment like the node.
+func doctor() int {
+	checks := node.Doctor(context.Background(), config.FromEnv())
+	fmt.Print(node.FormatDoctor(checks))
+	if node.DoctorFailed(checks) {
+		return 1
+	}
+	return 0
+}
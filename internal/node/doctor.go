@@ -0,0 +1,98 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+package node
+
+import (
+	"context"
+	"errors"
+	"fmt"
+	"net"
+	"net/url"
+	"strings"
+
+	"github.com/cartesi/rollups-node/internal/node/config"
+)
+
+const defaultPostgresPort = "5432"
+
+// DoctorCheck is the outcome of one of the checks of the doctor.
+type DoctorCheck struct {
+	Name    string
+	Skipped bool
+	Err     error
+}
+
+// Doctor runs the checks the node runs before starting, and checks that the
+// dependencies of the node are reachable, without starting it.
+func Doctor(ctx context.Context, c config.NodeConfig) []DoctorCheck {
+	checks := []DoctorCheck{{
+		Name: "chain id",
+		Err:  validateChainId(ctx, c.BlockchainID, c.BlockchainHttpEndpoint.Value),
+	}}
+	if c.FeatureDisableMachineHashCheck {
+		checks = append(checks, DoctorCheck{Name: "machine hash", Skipped: true})
+	} else {
+		checks = append(checks, DoctorCheck{
+			Name: "machine hash",
+			Err: validateMachineHash(
+				ctx,
+				c.SnapshotDir,
+				c.ContractsApplicationAddress,
+				c.BlockchainHttpEndpoint.Value,
+			),
+		})
+	}
+	checks = append(checks, DoctorCheck{
+		Name: "postgres",
+		Err:  checkPostgres(ctx, c.PostgresEndpoint.Value),
+	})
+	return checks
+}
+
+// FormatDoctor lists the checks one per line.
+func FormatDoctor(checks []DoctorCheck) string {
+	var sb strings.Builder
+	for _, check := range checks {
+		switch {
+		case check.Skipped:
+			fmt.Fprintf(&sb, "skipped: %v\n", check.Name)
+		case check.Err != nil:
+			fmt.Fprintf(&sb, "FAIL: %v: %v\n", check.Name, check.Err)
+		default:
+			fmt.Fprintf(&sb, "ok: %v\n", check.Name)
+		}
+	}
+	return sb.String()
+}
+
+// DoctorFailed tells whether any of the checks failed.
+func DoctorFailed(checks []DoctorCheck) bool {
+	for _, check := range checks {
+		if check.Err != nil {
+			return true
+		}
+	}
+	return false
+}
+
+// checkPostgres checks whether the database accepts connections. The
+// endpoint isn't included in the errors, since it may have the password.
+func checkPostgres(ctx context.Context, endpoint string) error {
+	u, err := url.Parse(endpoint)
+	if err != nil || u.Hostname() == "" {
+		return errors.New("endpoint isn't a postgres:// URL with a host")
+	}
+	port := u.Port()
+	if port == "" {
+		port = defaultPostgresPort
+	}
+	ctx, cancel := context.WithTimeout(ctx, probeTimeout)
+	defer cancel()
+	var dialer net.Dialer
+	conn, err := dialer.DialContext(ctx, "tcp", net.JoinHostPort(u.Hostname(), port))
+	if err != nil {
+		return fmt.Errorf("connect: %w", err)
+	}
+	return conn.Close()
+}
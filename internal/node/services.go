@@ -183,6 +183,10 @@ func newGraphQLServer(c config.NodeConfig, workDir string) services.CommandServi
 	s.Env = append(s.Env, fmt.Sprintf("GRAPHQL_PORT=%v", getPort(c, portOffsetGraphQLServer)))
 	s.Env = append(s.Env, fmt.Sprintf("GRAPHQL_HEALTHCHECK_PORT=%v",
 		getPort(c, portOffsetGraphQLHealthcheck)))
+	s.Env = append(s.Env, fmt.Sprintf("GRAPHQL_CHAIN_HTTP_ENDPOINT=%v",
+		c.BlockchainHttpEndpoint.Value))
+	s.Env = append(s.Env, fmt.Sprintf("GRAPHQL_DAPP_ADDRESS=%v",
+		c.ContractsApplicationAddress))
 	s.Env = append(s.Env, os.Environ()...)
 	s.WorkDir = workDir
 	return s
@@ -0,0 +1,305 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+package node
+
+import (
+	"archive/tar"
+	"bytes"
+	"compress/gzip"
+	"context"
+	"fmt"
+	"io"
+	"net"
+	"net/http"
+	"os"
+	"path/filepath"
+	"reflect"
+	"runtime"
+	"strings"
+	"time"
+
+	"github.com/cartesi/rollups-node/internal/node/config"
+)
+
+const (
+	redactedText = "[REDACTED]"
+	probeTimeout = 2 * time.Second
+	// Secrets shorter than this are not scrubbed from the bundle, to avoid
+	// replacing common substrings of the collected files.
+	minSecretLength = 4
+)
+
+// SupportBundleOptions selects what goes in the support bundle besides the
+// node state.
+type SupportBundleOptions struct {
+	Version string
+	// Log files whose tails are included in the bundle.
+	LogFiles []string
+	// Bytes read from the end of each log file.
+	LogBytes int64
+}
+
+// bundleService is a service of the node probed for the support bundle.
+type bundleService struct {
+	name string
+	port int
+	// Whether the service serves Prometheus metrics at /metrics.
+	metrics bool
+}
+
+func bundleServices(c config.NodeConfig) []bundleService {
+	services := []bundleService{
+		{"advance-runner", getPort(c, portOffsetAdvanceRunner), false},
+		{"dispatcher", getPort(c, portOffsetDispatcher), true},
+		{"graphql-server", getPort(c, portOffsetGraphQLServer), true},
+		{"indexer", getPort(c, portOffsetIndexer), false},
+		{"inspect-server", getPort(c, portOffsetInspectServer), false},
+		{"redis", getPort(c, portOffsetRedis), false},
+		{"server-manager", getPort(c, portOffsetServerManager), false},
+		{"state-server", getPort(c, portOffsetStateServer), false},
+	}
+	if !c.FeatureDisableClaimer {
+		services = append(services,
+			bundleService{"authority-claimer", getPort(c, portOffsetAuthorityClaimer), true})
+	}
+	if c.FeatureHostMode {
+		services = append(services,
+			bundleService{"host-runner", getPort(c, portOffsetHostRunnerHealthcheck), false})
+	}
+	return services
+}
+
+// supportBundle collects the files of the bundle, scrubbing the secrets of
+// the config from all of them before they are written.
+type supportBundle struct {
+	files   []bundleFile
+	errors  []string
+	secrets []string
+}
+
+type bundleFile struct {
+	name     string
+	contents []byte
+}
+
+func (b *supportBundle) add(name string, contents []byte) {
+	b.files = append(b.files, bundleFile{name, contents})
+}
+
+func (b *supportBundle) addError(format string, args ...any) {
+	b.errors = append(b.errors, fmt.Sprintf(format, args...))
+}
+
+func (b *supportBundle) scrub(contents []byte) []byte {
+	for _, secret := range b.secrets {
+		contents = bytes.ReplaceAll(contents, []byte(secret), []byte(redactedText))
+	}
+	return contents
+}
+
+// WriteSupportBundle writes a gzipped tar archive with the state of the node
+// to attach to bug reports: version info, the config with secrets redacted,
+// the output of the doctor, the health and metrics of the services, the
+// machine snapshot metadata, and the tails of the given log files.
+//
+// The node doesn't have to be running; what can't be collected is listed in
+// errors.txt.
+func WriteSupportBundle(
+	ctx context.Context,
+	c config.NodeConfig,
+	opts SupportBundleOptions,
+	w io.Writer,
+) error {
+	bundle := supportBundle{secrets: configSecrets(c)}
+
+	bundle.add("version.txt", []byte(fmt.Sprintf(
+		"node: %v\ngo: %v\nplatform: %v/%v\ncollected at: %v\n",
+		opts.Version,
+		runtime.Version(),
+		runtime.GOOS,
+		runtime.GOARCH,
+		time.Now().UTC().Format(time.RFC3339),
+	)))
+	bundle.add("config.txt", []byte(describeConfig(c)))
+	bundle.add("doctor.txt", []byte(FormatDoctor(Doctor(ctx, c))))
+	collectServices(ctx, c, &bundle)
+	collectSnapshot(c, &bundle)
+	for _, path := range opts.LogFiles {
+		contents, err := readTail(path, opts.LogBytes)
+		if err != nil {
+			bundle.addError("read log %v: %v", path, err)
+			continue
+		}
+		bundle.add(filepath.Join("logs", filepath.Base(path)), contents)
+	}
+	if len(bundle.errors) > 0 {
+		bundle.add("errors.txt", []byte(strings.Join(bundle.errors, "\n")+"\n"))
+	}
+
+	gz := gzip.NewWriter(w)
+	archive := tar.NewWriter(gz)
+	for _, file := range bundle.files {
+		contents := bundle.scrub(file.contents)
+		header := &tar.Header{
+			Name:    filepath.ToSlash(filepath.Join("support-bundle", file.name)),
+			Mode:    0o644,
+			Size:    int64(len(contents)),
+			ModTime: time.Now(),
+		}
+		if err := archive.WriteHeader(header); err != nil {
+			return fmt.Errorf("write support bundle: %w", err)
+		}
+		if _, err := archive.Write(contents); err != nil {
+			return fmt.Errorf("write support bundle: %w", err)
+		}
+	}
+	if err := archive.Close(); err != nil {
+		return fmt.Errorf("write support bundle: %w", err)
+	}
+	if err := gz.Close(); err != nil {
+		return fmt.Errorf("write support bundle: %w", err)
+	}
+	return nil
+}
+
+// describeConfig lists the config one field per line. The Redacted fields,
+// including the ones of the auth method, are printed by their String method.
+func describeConfig(c config.NodeConfig) string {
+	var sb strings.Builder
+	value := reflect.ValueOf(c)
+	for i := 0; i < value.NumField(); i++ {
+		fmt.Fprintf(&sb, "%v: %+v\n", value.Type().Field(i).Name, value.Field(i).Interface())
+	}
+	return sb.String()
+}
+
+// configSecrets returns the string values of the Redacted fields of the
+// config, so they can be scrubbed from logs and metrics as well.
+func configSecrets(c config.NodeConfig) []string {
+	var secrets []string
+	var walk func(value reflect.Value)
+	walk = func(value reflect.Value) {
+		switch value.Kind() {
+		case reflect.Interface, reflect.Pointer:
+			if !value.IsNil() {
+				walk(value.Elem())
+			}
+		case reflect.Struct:
+			if strings.HasPrefix(value.Type().Name(), "Redacted[") {
+				secret := value.FieldByName("Value")
+				if secret.Kind() == reflect.String && len(secret.String()) >= minSecretLength {
+					secrets = append(secrets, secret.String())
+				}
+				return
+			}
+			for i := 0; i < value.NumField(); i++ {
+				walk(value.Field(i))
+			}
+		}
+	}
+	walk(reflect.ValueOf(c))
+	return secrets
+}
+
+// collectServices checks whether each service accepts connections and
+// saves the metrics of the ones that serve them.
+func collectServices(ctx context.Context, c config.NodeConfig, bundle *supportBundle) {
+	var health strings.Builder
+	client := http.Client{Timeout: probeTimeout}
+	for _, service := range bundleServices(c) {
+		address := fmt.Sprintf("%v:%v", localhost, service.port)
+		conn, err := net.DialTimeout("tcp", address, probeTimeout)
+		if err != nil {
+			fmt.Fprintf(&health, "%v (%v): down: %v\n", service.name, address, err)
+			continue
+		}
+		conn.Close()
+		fmt.Fprintf(&health, "%v (%v): up\n", service.name, address)
+
+		if !service.metrics {
+			continue
+		}
+		metrics, err := httpGet(ctx, &client, fmt.Sprintf("http://%v/metrics", address))
+		if err != nil {
+			bundle.addError("get metrics of %v: %v", service.name, err)
+			continue
+		}
+		bundle.add(filepath.Join("metrics", service.name+".prom"), metrics)
+	}
+	bundle.add("health.txt", []byte(health.String()))
+}
+
+func httpGet(ctx context.Context, client *http.Client, url string) ([]byte, error) {
+	req, err := http.NewRequestWithContext(ctx, http.MethodGet, url, nil)
+	if err != nil {
+		return nil, err
+	}
+	resp, err := client.Do(req)
+	if err != nil {
+		return nil, err
+	}
+	defer resp.Body.Close()
+	if resp.StatusCode != http.StatusOK {
+		return nil, fmt.Errorf("unexpected status %v", resp.Status)
+	}
+	return io.ReadAll(resp.Body)
+}
+
+// collectSnapshot lists the files of the machine snapshot the node starts
+// from, along with its hash.
+func collectSnapshot(c config.NodeConfig, bundle *supportBundle) {
+	var sb strings.Builder
+	fmt.Fprintf(&sb, "directory: %v\n", c.SnapshotDir)
+	if hash, err := readHash(c.SnapshotDir); err != nil {
+		fmt.Fprintf(&sb, "hash: unavailable: %v\n", err)
+	} else {
+		fmt.Fprintf(&sb, "hash: 0x%v\n", hash)
+	}
+	entries, err := os.ReadDir(c.SnapshotDir)
+	if err != nil {
+		bundle.addError("list snapshot: %v", err)
+	}
+	for _, entry := range entries {
+		info, err := entry.Info()
+		if err != nil {
+			bundle.addError("stat snapshot file %v: %v", entry.Name(), err)
+			continue
+		}
+		fmt.Fprintf(&sb, "%v %12d %v %v\n",
+			info.Mode(),
+			info.Size(),
+			info.ModTime().UTC().Format(time.RFC3339),
+			entry.Name(),
+		)
+	}
+	bundle.add("snapshot.txt", []byte(sb.String()))
+}
+
+// readTail reads up to maxBytes from the end of the file, starting at the
+// first full line.
+func readTail(path string, maxBytes int64) ([]byte, error) {
+	file, err := os.Open(path)
+	if err != nil {
+		return nil, err
+	}
+	defer file.Close()
+	info, err := file.Stat()
+	if err != nil {
+		return nil, err
+	}
+	offset := max(info.Size()-maxBytes, 0)
+	if _, err := file.Seek(offset, io.SeekStart); err != nil {
+		return nil, err
+	}
+	contents, err := io.ReadAll(file)
+	if err != nil {
+		return nil, err
+	}
+	if offset > 0 {
+		if newline := bytes.IndexByte(contents, '\n'); newline >= 0 {
+			contents = contents[newline+1:]
+		}
+	}
+	return contents, nil
+}
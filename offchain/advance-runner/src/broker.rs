@@ -35,6 +35,9 @@ pub enum BrokerFacadeError {
 
 pub type Result<T> = std::result::Result<T, BrokerFacadeError>;
 
+/// Name under which the runner is registered as a consumer of the inputs
+const CONSUMER: &str = "advance-runner";
+
 pub struct BrokerFacade {
     client: Broker,
     inputs_stream: RollupsInputsStream,
@@ -52,8 +55,16 @@ impl BrokerFacade {
         reader_mode: bool,
     ) -> Result<Self> {
         tracing::trace!(?config, "connecting to broker");
-        let client = Broker::new(config).await.context(BrokerInternalSnafu)?;
+        let mut client =
+            Broker::new(config).await.context(BrokerInternalSnafu)?;
         let inputs_stream = RollupsInputsStream::new(&dapp_metadata);
+        // The inputs are replayed from the start on every run, so the runner
+        // registers without ever acknowledging them, and the stream is only
+        // compacted past the maximum lag
+        client
+            .register_consumer(&inputs_stream, CONSUMER)
+            .await
+            .context(BrokerInternalSnafu)?;
         let outputs_stream = RollupsOutputsStream::new(&dapp_metadata);
         let claims_stream = RollupsClaimsStream::new(dapp_metadata.chain_id);
         Ok(Self {
@@ -86,6 +97,16 @@ impl BrokerFacade {
         }
     }
 
+    /// Id of the last input event consumed
+    pub fn last_id(&self) -> &str {
+        &self.last_id
+    }
+
+    /// Consumes the input events from the start of the stream again
+    pub fn rewind(&mut self) {
+        self.last_id = INITIAL_ID.to_owned();
+    }
+
     /// Produce the rollups claim if it isn't in the stream yet
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn produce_rollups_claim(
@@ -155,6 +176,7 @@ mod tests {
         Address, DAppMetadata, Hash, InputMetadata, Payload,
         RollupsAdvanceStateInput, RollupsData, ADDRESS_SIZE, HASH_SIZE,
     };
+    use std::time::Duration;
     use test_fixtures::BrokerFixture;
     use testcontainers::clients::Cli;
 
@@ -173,7 +195,7 @@ mod tests {
             };
             let config = BrokerConfig {
                 redis_endpoint: fixture.redis_endpoint().to_owned(),
-                consume_timeout: 10,
+                consume_timeout: Duration::from_millis(10),
                 backoff,
             };
             let facade = BrokerFacade::new(config, dapp_metadata, false)
@@ -4,6 +4,10 @@
 use clap::Parser;
 use std::time::Duration;
 
+pub use crate::policy::InputPolicy;
+use crate::policy::InputPolicyCLIConfig;
+use crate::quarantine::QuarantineCLIConfig;
+pub use crate::quarantine::{PoisonInputAction, Quarantine};
 use crate::server_manager::ServerManagerCLIConfig;
 pub use crate::server_manager::ServerManagerConfig;
 use log::{LogConfig, LogEnvCliConfig};
@@ -14,12 +18,17 @@ pub use rollups_events::{
 #[derive(Debug, Clone)]
 pub struct AdvanceRunnerConfig {
     pub server_manager_config: ServerManagerConfig,
+    pub verifier_config: Option<ServerManagerConfig>,
     pub broker_config: BrokerConfig,
     pub dapp_metadata: DAppMetadata,
     pub log_config: LogConfig,
     pub backoff_max_elapsed_duration: Duration,
     pub healthcheck_port: u16,
     pub reader_mode: bool,
+    pub input_policy: InputPolicy,
+    pub quarantine: Quarantine,
+    pub advance_timeout: Duration,
+    pub epoch_pipeline_depth: usize,
 }
 
 impl AdvanceRunnerConfig {
@@ -31,23 +40,45 @@ impl AdvanceRunnerConfig {
         let server_manager_config =
             ServerManagerConfig::parse_from_cli(cli_config.sm_cli_config);
 
+        let verifier_config =
+            cli_config.verifier_server_manager_endpoint.map(|endpoint| {
+                ServerManagerConfig {
+                    server_manager_endpoint: endpoint,
+                    session_id: cli_config.verifier_session_id,
+                    ..server_manager_config.clone()
+                }
+            });
+
         let log_config = LogConfig::initialize(cli_config.log_cli_config);
 
         let backoff_max_elapsed_duration =
-            Duration::from_millis(cli_config.backoff_max_elapsed_duration);
+            cli_config.backoff_max_elapsed_duration;
 
         let healthcheck_port = cli_config.healthcheck_port;
 
         let reader_mode = cli_config.reader_mode;
 
+        let input_policy = cli_config.input_policy_cli_config.into();
+
+        let quarantine = cli_config.quarantine_cli_config.into();
+
+        let advance_timeout = cli_config.advance_timeout;
+
+        let epoch_pipeline_depth = cli_config.epoch_pipeline_depth;
+
         Self {
             server_manager_config,
+            verifier_config,
             broker_config,
             dapp_metadata,
             log_config,
             backoff_max_elapsed_duration,
             healthcheck_port,
             reader_mode,
+            input_policy,
+            quarantine,
+            advance_timeout,
+            epoch_pipeline_depth,
         }
     }
 }
@@ -68,9 +99,30 @@ struct CLIConfig {
     #[command(flatten)]
     pub log_cli_config: LogEnvCliConfig,
 
-    /// The max elapsed time for backoff in ms
-    #[arg(long, env, default_value = "120000")]
-    backoff_max_elapsed_duration: u64,
+    #[command(flatten)]
+    input_policy_cli_config: InputPolicyCLIConfig,
+
+    #[command(flatten)]
+    quarantine_cli_config: QuarantineCLIConfig,
+
+    /// The max elapsed time for backoff, such as `2m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "2m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    backoff_max_elapsed_duration: Duration,
+
+    /// Time the machine may take to advance with an input, such as `10m` (a bare number is
+    /// in ms), after which the attempt fails and counts towards the quarantine of the input
+    #[arg(
+        long,
+        env,
+        default_value = "10m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    advance_timeout: Duration,
 
     /// Port of health check
     #[arg(
@@ -82,4 +134,20 @@ struct CLIConfig {
 
     #[arg(long, env)]
     reader_mode: bool,
+
+    /// Server-manager gRPC endpoint of the verification machine; when set, every epoch is
+    /// replayed on it and its claim is only produced if both machines agree on the epoch hash
+    #[arg(long, env)]
+    verifier_server_manager_endpoint: Option<String>,
+
+    /// Session id used in the verification server-manager
+    #[arg(long, env, default_value = "default_rollups_verifier_id")]
+    verifier_session_id: String,
+
+    /// Number of finished epochs that may wait to be verified and claimed while the inputs
+    /// of the next epochs are processed, which speeds up catching up with a backlog of epochs.
+    /// The claims are still produced in order. With 0, each epoch is claimed before the
+    /// inputs of the next one are processed
+    #[arg(long, env, default_value_t = 0)]
+    epoch_pipeline_depth: usize,
 }
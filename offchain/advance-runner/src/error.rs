@@ -3,7 +3,7 @@
 
 use snafu::Snafu;
 
-use crate::{broker, runner, server_manager};
+use crate::{broker, runner, server_manager, verifier};
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -23,4 +23,7 @@ pub enum AdvanceRunnerError {
 
     #[snafu(display("runner error"))]
     RunnerError { source: runner::RunnerError },
+
+    #[snafu(display("verifier error"))]
+    VerifierError { source: verifier::VerifierError },
 }
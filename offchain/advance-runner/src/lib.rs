@@ -4,26 +4,43 @@
 use backoff::ExponentialBackoffBuilder;
 use broker::BrokerFacade;
 use config::AdvanceRunnerConfig;
-use runner::Runner;
+use http_health_check::Health;
+use runner::{Runner, Settlement};
 use server_manager::ServerManagerFacade;
 use snafu::ResultExt;
+use verifier::Verifier;
 
 pub use broker::BrokerFacadeError;
 pub use error::AdvanceRunnerError;
 pub use runner::RunnerError;
+pub use verifier::VerifierError;
 
 mod broker;
 pub mod config;
 mod error;
+mod policy;
+mod quarantine;
 pub mod runner;
 mod server_manager;
+mod verifier;
+
+/// Subsystems of the advance-runner reported at `/healthz` and `/readyz`,
+/// which are up once connected
+const SERVER_MANAGER: &str = "server_manager";
+const BROKER: &str = "broker";
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn run(
     config: AdvanceRunnerConfig,
 ) -> Result<(), AdvanceRunnerError> {
-    let health_handle = http_health_check::start(config.healthcheck_port);
-    let advance_runner_handle = start_advance_runner(config);
+    let health = Health::default();
+    health.register(SERVER_MANAGER, None);
+    health.register(BROKER, None);
+    let health_handle = http_health_check::start_with_health(
+        config.healthcheck_port,
+        health.clone(),
+    );
+    let advance_runner_handle = start_advance_runner(config, health);
     tokio::select! {
         ret = health_handle => {
             ret.context(error::HealthCheckSnafu)
@@ -37,6 +54,7 @@ pub async fn run(
 #[tracing::instrument(level = "trace", skip_all)]
 async fn start_advance_runner(
     config: AdvanceRunnerConfig,
+    health: Health,
 ) -> Result<(), AdvanceRunnerError> {
     let backoff = ExponentialBackoffBuilder::new()
         .with_max_elapsed_time(Some(config.backoff_max_elapsed_duration))
@@ -45,13 +63,38 @@ async fn start_advance_runner(
     let server_manager = ServerManagerFacade::new(
         config.dapp_metadata.dapp_address.clone(),
         config.server_manager_config,
-        backoff,
+        backoff.clone(),
     )
     .await
     .context(error::ServerManagerSnafu)?;
     tracing::trace!("connected to the server-manager");
+    health.up(SERVER_MANAGER);
+
+    let verifier = match config.verifier_config {
+        Some(verifier_config) => {
+            let verifier = Verifier::new(
+                config.dapp_metadata.dapp_address.clone(),
+                verifier_config,
+                backoff,
+            )
+            .await
+            .context(error::VerifierSnafu)?;
+            tracing::trace!("connected to the verification server-manager");
+            Some(verifier)
+        }
+        None => None,
+    };
 
     let broker = BrokerFacade::new(
+        config.broker_config.clone(),
+        config.dapp_metadata.clone(),
+        config.reader_mode,
+    )
+    .await
+    .context(error::BrokerSnafu)?;
+    // The epochs are settled with a connection of their own, so they can be
+    // settled while the runner consumes the inputs of the next epoch
+    let settlement_broker = BrokerFacade::new(
         config.broker_config,
         config.dapp_metadata,
         config.reader_mode,
@@ -59,8 +102,24 @@ async fn start_advance_runner(
     .await
     .context(error::BrokerSnafu)?;
     tracing::trace!("connected the broker");
+    health.up(BROKER);
+
+    if !config.input_policy.is_empty() {
+        tracing::info!(policy = ?config.input_policy, "filtering inputs");
+    }
+    if config.quarantine.is_enabled() {
+        tracing::info!(quarantine = ?config.quarantine, "quarantining inputs");
+    }
 
-    Runner::start(server_manager, broker)
-        .await
-        .context(error::RunnerSnafu)
+    Runner::start(
+        server_manager,
+        broker,
+        Settlement::new(settlement_broker, verifier),
+        config.input_policy,
+        config.quarantine,
+        config.advance_timeout,
+        config.epoch_pipeline_depth,
+    )
+    .await
+    .context(error::RunnerSnafu)
 }
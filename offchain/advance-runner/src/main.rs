@@ -5,11 +5,15 @@ use advance_runner::config::AdvanceRunnerConfig;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets::resolve_env().await?;
     let config = AdvanceRunnerConfig::parse();
 
     log::configure(&config.log_config);
 
     log::log_service_start(&config, "Advance Runner");
 
-    advance_runner::run(config).await.map_err(|e| e.into())
+    tokio::select! {
+        ret = advance_runner::run(config) => ret.map_err(|e| e.into()),
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
@@ -0,0 +1,253 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+use ethers::types::Signature;
+use rollups_events::{Address, InputMetadata, ADDRESS_SIZE};
+use snafu::{ensure, OptionExt, Snafu};
+use std::collections::HashSet;
+
+/// Size of the `r`, `s` and `v` signature appended to signed payloads
+const SIGNATURE_SIZE: usize = 65;
+
+/// Reason why the payload of an input isn't forwarded to the machine
+#[derive(Debug, Snafu, PartialEq)]
+pub enum IneligibleInput {
+    #[snafu(display("sender {} is not in the allowlist", sender))]
+    SenderNotAllowed { sender: String },
+
+    #[snafu(display("sender {} is in the denylist", sender))]
+    SenderDenied { sender: String },
+
+    #[snafu(display("payload is too short to carry a signature"))]
+    MissingSignature,
+
+    #[snafu(display("payload signature is invalid: {}", reason))]
+    InvalidSignature { reason: String },
+
+    #[snafu(display("payload was signed by unknown signer {}", signer))]
+    UnknownSigner { signer: String },
+}
+
+#[derive(Debug, Parser)]
+pub struct InputPolicyCLIConfig {
+    /// Comma-separated list of addresses allowed to send inputs. When set,
+    /// the machine is advanced with an empty payload in place of the inputs
+    /// from other senders
+    #[arg(long, env, value_delimiter = ',', value_parser = parse_address)]
+    input_sender_allowlist: Option<Vec<Address>>,
+
+    /// Comma-separated list of addresses in place of whose inputs the
+    /// machine is advanced with an empty payload
+    #[arg(long, env, value_delimiter = ',', value_parser = parse_address)]
+    input_sender_denylist: Option<Vec<Address>>,
+
+    /// Comma-separated list of addresses allowed to sign inputs. When set,
+    /// the last 65 bytes of every payload must be an EIP-191 signature of the
+    /// rest of the payload by one of them; the machine is advanced with an
+    /// empty payload in place of the other inputs
+    #[arg(long, env, value_delimiter = ',', value_parser = parse_address)]
+    input_signers: Option<Vec<Address>>,
+}
+
+/// Decides which inputs the DApp is willing to process.
+///
+/// The machine is still advanced in place of an ineligible input, with an
+/// empty payload, which the DApp is expected to reject, so the input indices
+/// of the machine and its epochs stay in step with the input box. The
+/// machine state then depends on the policy, so enabling one only makes sense
+/// when every node of the DApp applies the same one.
+#[derive(Debug, Clone, Default)]
+pub struct InputPolicy {
+    allowlist: Option<HashSet<Address>>,
+    denylist: HashSet<Address>,
+    signers: Option<HashSet<Address>>,
+}
+
+impl From<InputPolicyCLIConfig> for InputPolicy {
+    fn from(cli_config: InputPolicyCLIConfig) -> Self {
+        Self {
+            allowlist: cli_config
+                .input_sender_allowlist
+                .map(|list| list.into_iter().collect()),
+            denylist: cli_config
+                .input_sender_denylist
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            signers: cli_config
+                .input_signers
+                .map(|list| list.into_iter().collect()),
+        }
+    }
+}
+
+impl InputPolicy {
+    pub fn new(
+        allowlist: Option<HashSet<Address>>,
+        denylist: HashSet<Address>,
+        signers: Option<HashSet<Address>>,
+    ) -> Self {
+        Self {
+            allowlist,
+            denylist,
+            signers,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allowlist.is_none()
+            && self.denylist.is_empty()
+            && self.signers.is_none()
+    }
+
+    /// Checks whether the payload of the input should be forwarded to the
+    /// machine
+    pub fn check(
+        &self,
+        metadata: &InputMetadata,
+        payload: &[u8],
+    ) -> Result<(), IneligibleInput> {
+        let sender = &metadata.msg_sender;
+        if let Some(allowlist) = &self.allowlist {
+            ensure!(
+                allowlist.contains(sender),
+                SenderNotAllowedSnafu {
+                    sender: format_address(sender),
+                }
+            );
+        }
+        ensure!(
+            !self.denylist.contains(sender),
+            SenderDeniedSnafu {
+                sender: format_address(sender),
+            }
+        );
+        if let Some(signers) = &self.signers {
+            let signer = recover_signer(payload)?;
+            ensure!(
+                signers.contains(&signer),
+                UnknownSignerSnafu {
+                    signer: format_address(&signer),
+                }
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Recovers the signer of the message that precedes the signature at the end
+/// of the payload
+fn recover_signer(payload: &[u8]) -> Result<Address, IneligibleInput> {
+    let split = payload
+        .len()
+        .checked_sub(SIGNATURE_SIZE)
+        .context(MissingSignatureSnafu)?;
+    let (message, signature) = payload.split_at(split);
+    let signature = Signature::try_from(signature).map_err(|e| {
+        IneligibleInput::InvalidSignature {
+            reason: e.to_string(),
+        }
+    })?;
+    let signer = signature.recover(message).map_err(|e| {
+        IneligibleInput::InvalidSignature {
+            reason: e.to_string(),
+        }
+    })?;
+    Ok(Address::new(signer.0))
+}
+
+pub(crate) fn format_address(address: &Address) -> String {
+    format!("0x{}", hex::encode(address.inner()))
+}
+
+fn parse_address(value: &str) -> Result<Address, String> {
+    let value = value.trim();
+    let hex_value = value.strip_prefix("0x").unwrap_or(value);
+    let bytes: [u8; ADDRESS_SIZE] = hex::decode(hex_value)
+        .map_err(|e| format!("invalid address `{}`: {}", value, e))?
+        .try_into()
+        .map_err(|_| format!("address `{}` has the wrong size", value))?;
+    Ok(Address::new(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    fn metadata(sender: Address) -> InputMetadata {
+        InputMetadata {
+            msg_sender: sender,
+            ..Default::default()
+        }
+    }
+
+    fn address(byte: u8) -> Address {
+        Address::new([byte; ADDRESS_SIZE])
+    }
+
+    #[test]
+    fn empty_policy_accepts_everything() {
+        let policy = InputPolicy::default();
+        assert!(policy.is_empty());
+        assert_eq!(policy.check(&metadata(address(1)), b"hello"), Ok(()));
+    }
+
+    #[test]
+    fn it_filters_senders() {
+        let policy = InputPolicy {
+            allowlist: Some([address(1), address(2)].into()),
+            denylist: [address(2)].into(),
+            signers: None,
+        };
+        assert_eq!(policy.check(&metadata(address(1)), b""), Ok(()));
+        assert!(matches!(
+            policy.check(&metadata(address(2)), b""),
+            Err(IneligibleInput::SenderDenied { .. })
+        ));
+        assert!(matches!(
+            policy.check(&metadata(address(3)), b""),
+            Err(IneligibleInput::SenderNotAllowed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_checks_signatures() {
+        let wallet: LocalWallet =
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap();
+        let policy = InputPolicy {
+            signers: Some([Address::new(wallet.address().0)].into()),
+            ..Default::default()
+        };
+
+        let message = b"hello".to_vec();
+        let signature = wallet.sign_message(&message).await.unwrap();
+        let mut payload = message.clone();
+        payload.extend_from_slice(&signature.to_vec());
+        assert_eq!(policy.check(&metadata(address(1)), &payload), Ok(()));
+
+        let mut tampered = payload.clone();
+        tampered[0] = b'j';
+        assert!(matches!(
+            policy.check(&metadata(address(1)), &tampered),
+            Err(IneligibleInput::UnknownSigner { .. })
+        ));
+        assert_eq!(
+            policy.check(&metadata(address(1)), &message),
+            Err(IneligibleInput::MissingSignature)
+        );
+    }
+
+    #[test]
+    fn it_parses_addresses() {
+        assert_eq!(
+            parse_address(" 0x0101010101010101010101010101010101010101"),
+            Ok(address(1))
+        );
+        assert!(parse_address("0x01").is_err());
+        assert!(parse_address("not an address").is_err());
+    }
+}
@@ -0,0 +1,312 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Quarantine of the inputs that keep the machine from advancing.
+//!
+//! The attempts to advance the machine with each input are recorded in a
+//! directory before the input is sent, and the record is removed once the
+//! machine processes it, so an input that hangs or crashes the machine, and
+//! with it the runner, is still on record after the restart. Once an input
+//! reaches the maximum attempts, it is no longer sent to the machine: the
+//! runner either halts with the record as the diagnostic, or advances the
+//! machine with an empty payload in its place, which it does on every later
+//! replay too. Deleting the record sends the input to the machine again, such
+//! as after fixing the DApp.
+
+use clap::{Parser, ValueEnum};
+use rollups_events::InputMetadata;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::policy::format_address;
+
+#[derive(Debug, Snafu)]
+pub enum QuarantineError {
+    #[snafu(display("failed to read quarantine record {}", path.display()))]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("quarantine record {} is corrupted", path.display()))]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("failed to write quarantine record {}", path.display()))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// What is done with an input once it reaches the maximum attempts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PoisonInputAction {
+    /// Stop the runner before sending the input to the machine again
+    #[default]
+    Halt,
+    /// Advance the machine with an empty payload in place of the input, which
+    /// the DApp is expected to reject. Like the input policy, this leaves the
+    /// input out of the machine state, so it only makes sense when every node
+    /// of the DApp quarantines the same input
+    Reject,
+}
+
+#[derive(Debug, Parser)]
+pub struct QuarantineCLIConfig {
+    /// Directory where the attempts to advance the machine with each input
+    /// are recorded; without it, inputs are never quarantined
+    #[arg(long, env)]
+    quarantine_dir: Option<PathBuf>,
+
+    /// Attempts to advance the machine with an input, counting the ones cut
+    /// short by a crash or a restart, after which the input is quarantined
+    #[arg(long, env, default_value_t = 3)]
+    quarantine_max_attempts: u32,
+
+    /// What is done with a quarantined input
+    #[arg(long, env, value_enum, default_value_t = PoisonInputAction::Halt)]
+    quarantine_action: PoisonInputAction,
+}
+
+/// Attempts to advance the machine with an input, and why the last one
+/// failed, as stored in `input-<index>.json`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub input_index: u64,
+    pub epoch_index: u64,
+    pub msg_sender: String,
+    pub block_number: u64,
+    pub payload_hash: String,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Display for QuarantineRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "input {} of epoch {} from {} (block {}, payload hash {}) failed {} attempts",
+            self.input_index,
+            self.epoch_index,
+            self.msg_sender,
+            self.block_number,
+            self.payload_hash,
+            self.attempts
+        )?;
+        if let Some(last_error) = &self.last_error {
+            write!(f, "; last error: {}", last_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether an input may be sent to the machine
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Advance,
+    Quarantined(PoisonInputAction, QuarantineRecord),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Quarantine {
+    dir: Option<PathBuf>,
+    max_attempts: u32,
+    action: PoisonInputAction,
+}
+
+impl From<QuarantineCLIConfig> for Quarantine {
+    fn from(cli_config: QuarantineCLIConfig) -> Self {
+        Self::new(
+            cli_config.quarantine_dir,
+            cli_config.quarantine_max_attempts,
+            cli_config.quarantine_action,
+        )
+    }
+}
+
+impl Quarantine {
+    pub fn new(
+        dir: Option<PathBuf>,
+        max_attempts: u32,
+        action: PoisonInputAction,
+    ) -> Self {
+        Self {
+            dir,
+            max_attempts: max_attempts.max(1),
+            action,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Records an attempt to advance the machine with the input, unless it
+    /// already reached the maximum attempts
+    pub fn begin(
+        &self,
+        input_index: u64,
+        metadata: &InputMetadata,
+        payload: &[u8],
+    ) -> Result<Verdict, QuarantineError> {
+        let Some(dir) = &self.dir else {
+            return Ok(Verdict::Advance);
+        };
+        let path = record_path(dir, input_index);
+        let mut record = match read_record(&path)? {
+            Some(record) => record,
+            None => QuarantineRecord {
+                input_index,
+                epoch_index: metadata.epoch_index,
+                msg_sender: format_address(&metadata.msg_sender),
+                block_number: metadata.block_number,
+                payload_hash: format!(
+                    "0x{}",
+                    hex::encode(Keccak256::digest(payload))
+                ),
+                attempts: 0,
+                last_error: None,
+            },
+        };
+        if record.attempts >= self.max_attempts {
+            return Ok(Verdict::Quarantined(self.action, record));
+        }
+        record.attempts += 1;
+        write_record(dir, &path, &record)?;
+        Ok(Verdict::Advance)
+    }
+
+    /// Keeps why the last attempt failed in the record of the input
+    pub fn fail(
+        &self,
+        input_index: u64,
+        error: impl Display,
+    ) -> Result<(), QuarantineError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = record_path(dir, input_index);
+        if let Some(mut record) = read_record(&path)? {
+            record.last_error = Some(error.to_string());
+            write_record(dir, &path, &record)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the record of an input the machine processed
+    pub fn succeed(&self, input_index: u64) -> Result<(), QuarantineError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = record_path(dir, input_index);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).context(WriteSnafu { path })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn record_path(dir: &Path, input_index: u64) -> PathBuf {
+    dir.join(format!("input-{}.json", input_index))
+}
+
+fn read_record(
+    path: &Path,
+) -> Result<Option<QuarantineRecord>, QuarantineError> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(ReadSnafu { path }),
+    };
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .context(ParseSnafu { path })
+}
+
+/// Writes the record to a temporary file first, so a crash never leaves a
+/// partial record behind
+fn write_record(
+    dir: &Path,
+    path: &Path,
+    record: &QuarantineRecord,
+) -> Result<(), QuarantineError> {
+    let contents =
+        serde_json::to_vec_pretty(record).expect("record should serialize");
+    let tmp_path = path.with_extension("json.tmp");
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&tmp_path, contents))
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .context(WriteSnafu { path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quarantine(dir: &Path, action: PoisonInputAction) -> Quarantine {
+        Quarantine {
+            dir: Some(dir.to_owned()),
+            max_attempts: 2,
+            action,
+        }
+    }
+
+    #[test]
+    fn it_quarantines_inputs_after_the_maximum_attempts() {
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine = quarantine(dir.path(), PoisonInputAction::Reject);
+        let metadata = InputMetadata::default();
+
+        for _ in 0..2 {
+            assert_eq!(
+                quarantine.begin(7, &metadata, b"payload").unwrap(),
+                Verdict::Advance
+            );
+            quarantine.fail(7, "machine crashed").unwrap();
+        }
+        let Verdict::Quarantined(action, record) =
+            quarantine.begin(7, &metadata, b"payload").unwrap()
+        else {
+            panic!("input should be quarantined");
+        };
+        assert_eq!(action, PoisonInputAction::Reject);
+        assert_eq!(record.attempts, 2);
+        assert_eq!(record.last_error.as_deref(), Some("machine crashed"));
+
+        // Deleting the record sends the input to the machine again
+        fs::remove_file(record_path(dir.path(), 7)).unwrap();
+        assert_eq!(
+            quarantine.begin(7, &metadata, b"payload").unwrap(),
+            Verdict::Advance
+        );
+    }
+
+    #[test]
+    fn it_forgets_the_inputs_the_machine_processed() {
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine = quarantine(dir.path(), PoisonInputAction::Halt);
+        let metadata = InputMetadata::default();
+
+        for _ in 0..3 {
+            assert_eq!(
+                quarantine.begin(1, &metadata, b"").unwrap(),
+                Verdict::Advance
+            );
+            quarantine.succeed(1).unwrap();
+        }
+        assert!(!record_path(dir.path(), 1).exists());
+    }
+}
@@ -1,17 +1,44 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use rollups_events::{InputMetadata, RollupsData};
+use rollups_events::{InputMetadata, RollupsData, RollupsOutput};
 use snafu::{ResultExt, Snafu};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 
 use crate::broker::{BrokerFacade, BrokerFacadeError};
-use crate::server_manager::{ServerManagerError, ServerManagerFacade};
+use crate::policy::InputPolicy;
+use crate::quarantine::{
+    PoisonInputAction, Quarantine, QuarantineError, QuarantineRecord, Verdict,
+};
+use crate::server_manager::{
+    FinishedEpoch as ServerManagerEpoch, ServerManagerError,
+    ServerManagerFacade,
+};
+use crate::verifier::{EpochInput, Verifier, VerifierError};
 
 #[derive(Debug, Snafu)]
 pub enum RunnerError {
     #[snafu(display("failed to send advance-state input to server-manager"))]
     AdvanceError { source: ServerManagerError },
 
+    #[snafu(display(
+        "machine didn't advance with input {} within {:?}",
+        input_index,
+        timeout
+    ))]
+    AdvanceTimeoutError { input_index: u64, timeout: Duration },
+
+    #[snafu(display("halted on quarantined input: {}", record))]
+    PoisonInputError { record: QuarantineRecord },
+
+    #[snafu(display("failed to record the attempts of the input"))]
+    QuarantineError { source: QuarantineError },
+
+    #[snafu(display("failed to start the server-manager session again"))]
+    StartSessionError { source: ServerManagerError },
+
     #[snafu(display("failed to finish epoch in server-manager"))]
     FinishEpochError { source: ServerManagerError },
 
@@ -32,6 +59,12 @@ pub enum RunnerError {
 
     #[snafu(display("failed to produce outputs in broker"))]
     ProduceOutputsError { source: BrokerFacadeError },
+
+    #[snafu(display("failed to verify epoch"))]
+    VerifyEpochError { source: VerifierError },
+
+    #[snafu(display("epoch pipeline stopped"))]
+    PipelineStoppedError {},
 }
 
 type Result<T> = std::result::Result<T, RunnerError>;
@@ -39,47 +72,165 @@ type Result<T> = std::result::Result<T, RunnerError>;
 pub struct Runner {
     server_manager: ServerManagerFacade,
     broker: BrokerFacade,
+    input_policy: InputPolicy,
+    quarantine: Quarantine,
+    advance_timeout: Duration,
+    /// Inputs of the open epoch, kept when the epochs are verified
+    epoch_inputs: Option<Vec<EpochInput>>,
+    /// Id of the input event that failed when the server-manager lost the
+    /// session; the events before it are replayed into the new session
+    /// without producing their outputs and claims again
+    replay_until: Option<String>,
+    settlement: SettlementStage,
+}
+
+/// Where the finished epochs are settled
+enum SettlementStage {
+    /// Before the next input is processed
+    Inline(Settlement),
+    /// By a settlement loop running along the runner, which also produces
+    /// the outputs of the runner, so they are produced after the proofs and
+    /// claims of the epochs before them
+    Pipelined {
+        sender: mpsc::UnboundedSender<Publication>,
+        /// Slots of the finished epochs waiting to be settled
+        slots: Arc<Semaphore>,
+    },
+}
+
+/// What the runner sends to the settlement loop, in order
+enum Publication {
+    Outputs(Vec<RollupsOutput>),
+    /// Finished epoch, with the slot it takes in the pipeline
+    Epoch(FinishedEpoch, OwnedSemaphorePermit),
 }
 
 impl Runner {
+    /// Runs the main loop, settling each finished epoch before processing
+    /// the inputs of the next one or, with a `pipeline_depth` above zero,
+    /// while processing them. Up to `pipeline_depth` finished epochs may
+    /// then wait to be settled, in order, before the runner stops to wait
+    /// for them. The outputs of the inputs processed meanwhile are produced
+    /// once the epochs before them are settled.
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn start(
         server_manager: ServerManagerFacade,
         broker: BrokerFacade,
+        settlement: Settlement,
+        input_policy: InputPolicy,
+        quarantine: Quarantine,
+        advance_timeout: Duration,
+        pipeline_depth: usize,
     ) -> Result<()> {
-        let mut runner = Self {
+        let epoch_inputs = settlement.verifier.as_ref().map(|_| vec![]);
+        if pipeline_depth == 0 {
+            let runner = Self {
+                server_manager,
+                broker,
+                input_policy,
+                quarantine,
+                advance_timeout,
+                epoch_inputs,
+                replay_until: None,
+                settlement: SettlementStage::Inline(settlement),
+            };
+            return runner.run().await;
+        }
+
+        tracing::info!(pipeline_depth, "settling epochs in a pipeline");
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let runner = Self {
             server_manager,
             broker,
+            input_policy,
+            quarantine,
+            advance_timeout,
+            epoch_inputs,
+            replay_until: None,
+            settlement: SettlementStage::Pipelined {
+                sender,
+                slots: Arc::new(Semaphore::new(pipeline_depth)),
+            },
         };
+        tokio::try_join!(runner.run(), settlement.run(receiver)).map(|_| ())
+    }
 
+    async fn run(mut self) -> Result<()> {
         tracing::info!("starting runner main loop");
         loop {
-            let event = runner
+            let event = self
                 .broker
                 .consume_input()
                 .await
                 .context(ConsumeInputSnafu)?;
             tracing::info!(?event, "consumed input event");
+            if self.replay_until.as_deref() == Some(self.broker.last_id()) {
+                tracing::info!("replayed the inputs into the new session");
+                self.replay_until = None;
+            }
 
-            match event.data {
+            let result = match event.data {
                 RollupsData::AdvanceStateInput(input) => {
-                    runner
-                        .handle_advance(
-                            event.epoch_index,
-                            event.inputs_sent_count,
-                            input.metadata,
-                            input.payload.into_inner(),
-                        )
-                        .await?;
+                    self.handle_advance(
+                        event.epoch_index,
+                        event.inputs_sent_count,
+                        input.metadata,
+                        input.payload.into_inner(),
+                    )
+                    .await
                 }
                 RollupsData::FinishEpoch {} => {
-                    runner.handle_finish(event.epoch_index).await?;
+                    self.handle_finish(event.epoch_index).await
                 }
+            };
+            match result {
+                Err(
+                    e @ (RunnerError::AdvanceError { .. }
+                    | RunnerError::AdvanceTimeoutError { .. }
+                    | RunnerError::FinishEpochError { .. }),
+                ) => self.recover_session(e).await?,
+                result => result?,
             }
             tracing::info!("waiting for the next input event");
         }
     }
 
+    fn is_replaying(&self) -> bool {
+        self.replay_until.is_some()
+    }
+
+    /// Starts the session again if the server-manager lost it, such as when
+    /// it restarted, and replays into it the input events consumed before
+    /// the one that failed. Otherwise, fails with the error.
+    async fn recover_session(&mut self, error: RunnerError) -> Result<()> {
+        match self.server_manager.has_session().await {
+            Ok(false) => {}
+            Ok(true) => return Err(error),
+            Err(e) => {
+                tracing::warn!(
+                    "failed to get the server-manager status: {}",
+                    e
+                );
+                return Err(error);
+            }
+        }
+        tracing::warn!(
+            "server-manager lost the session ({}); starting it again and replaying the inputs",
+            error_chain(&error)
+        );
+        self.server_manager
+            .start_session()
+            .await
+            .context(StartSessionSnafu)?;
+        // While replaying, the events up to the one that first failed were
+        // already handled, so a failure midway replays up to that one again
+        if !self.is_replaying() {
+            self.replay_until = Some(self.broker.last_id().to_owned());
+        }
+        self.broker.rewind();
+        Ok(())
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     async fn handle_advance(
         &mut self,
@@ -91,25 +242,109 @@ impl Runner {
         tracing::trace!("handling advance state");
 
         let input_index = inputs_sent_count - 1;
-        let outputs = self
-            .server_manager
-            .advance_state(
-                epoch_index,
+        let mut input_payload = input_payload;
+        if let Err(reason) =
+            self.input_policy.check(&input_metadata, &input_payload)
+        {
+            // The machine still advances, so its input index stays in step
+            // with the one of the input box
+            tracing::warn!(
                 input_index,
-                input_metadata,
-                input_payload,
-            )
-            .await
-            .context(AdvanceSnafu)?;
-        tracing::trace!("advance state sent to server-manager");
+                %reason,
+                "input not eligible; advancing the machine with an empty payload in its place"
+            );
+            input_payload = vec![];
+        }
 
-        self.broker
-            .produce_outputs(outputs)
-            .await
-            .context(ProduceOutputsSnafu)?;
-        tracing::trace!("produced outputs in broker");
+        let verdict = self
+            .quarantine
+            .begin(input_index, &input_metadata, &input_payload)
+            .context(QuarantineSnafu)?;
+        let quarantined = match verdict {
+            Verdict::Advance => false,
+            Verdict::Quarantined(PoisonInputAction::Halt, record) => {
+                tracing::error!(
+                    %record,
+                    "input is quarantined; halting before forwarding it to the machine again"
+                );
+                return PoisonInputSnafu { record }.fail();
+            }
+            Verdict::Quarantined(PoisonInputAction::Reject, record) => {
+                // The record is kept, so the input is left out on every
+                // later replay too
+                tracing::error!(
+                    %record,
+                    "input is quarantined; advancing the machine with an empty payload in its place"
+                );
+                input_payload = vec![];
+                true
+            }
+        };
 
-        Ok(())
+        // Kept once the machine processes the input, so an input sent again
+        // to a new session isn't kept twice
+        let epoch_input = self.epoch_inputs.as_ref().map(|_| EpochInput {
+            input_index,
+            metadata: input_metadata.clone(),
+            payload: input_payload.clone(),
+        });
+
+        let advance = self.server_manager.advance_state(
+            epoch_index,
+            input_index,
+            input_metadata,
+            input_payload,
+        );
+        let result =
+            match tokio::time::timeout(self.advance_timeout, advance).await {
+                Ok(result) => result.context(AdvanceSnafu),
+                Err(_) => AdvanceTimeoutSnafu {
+                    input_index,
+                    timeout: self.advance_timeout,
+                }
+                .fail(),
+            };
+        let outputs = match result {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                if !quarantined {
+                    if let Err(record_error) =
+                        self.quarantine.fail(input_index, error_chain(&e))
+                    {
+                        tracing::warn!("{}", record_error);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        if !quarantined {
+            self.quarantine
+                .succeed(input_index)
+                .context(QuarantineSnafu)?;
+        }
+        tracing::trace!("advance state sent to server-manager");
+        if self.is_replaying() {
+            return Ok(());
+        }
+        if let (Some(epoch_inputs), Some(epoch_input)) =
+            (&mut self.epoch_inputs, epoch_input)
+        {
+            epoch_inputs.push(epoch_input);
+        }
+
+        match &self.settlement {
+            SettlementStage::Inline(_) => {
+                self.broker
+                    .produce_outputs(outputs)
+                    .await
+                    .context(ProduceOutputsSnafu)?;
+                tracing::trace!("produced outputs in broker");
+                Ok(())
+            }
+            SettlementStage::Pipelined { sender, .. } => sender
+                .send(Publication::Outputs(outputs))
+                .map_err(|_| RunnerError::PipelineStoppedError {}),
+        }
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -119,28 +354,146 @@ impl Runner {
         let result = self.server_manager.finish_epoch(epoch_index).await;
         tracing::trace!("finished epoch in server-manager");
 
-        match result {
-            Ok((rollups_claim, proofs)) => {
-                self.broker
-                    .produce_outputs(proofs)
-                    .await
-                    .context(ProduceOutputsSnafu)?;
-                tracing::trace!("produced outputs in broker");
-
-                self.broker
-                    .produce_rollups_claim(rollups_claim)
-                    .await
-                    .context(ProduceClaimSnafu)?;
-                tracing::info!("produced epoch claim");
+        let finished = match result {
+            Ok(finished) => Some(finished),
+            Err(source @ ServerManagerError::EmptyEpochError { .. }) => {
+                tracing::warn!("{}", source);
+                None
             }
             Err(source) => {
-                if let ServerManagerError::EmptyEpochError { .. } = source {
-                    tracing::warn!("{}", source)
-                } else {
-                    return Err(RunnerError::FinishEpochError { source });
+                return Err(RunnerError::FinishEpochError { source });
+            }
+        };
+        if self.is_replaying() {
+            return Ok(());
+        }
+        let epoch = FinishedEpoch {
+            epoch_index,
+            inputs: self.epoch_inputs.as_mut().map(std::mem::take),
+            finished,
+        };
+        match &mut self.settlement {
+            SettlementStage::Inline(settlement) => {
+                settlement.settle(epoch).await
+            }
+            SettlementStage::Pipelined { sender, slots } => {
+                if slots.available_permits() == 0 {
+                    tracing::info!(
+                        epoch_index,
+                        "epoch pipeline is full; waiting for the settlement"
+                    );
+                }
+                let slot = Arc::clone(slots)
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| RunnerError::PipelineStoppedError {})?;
+                sender
+                    .send(Publication::Epoch(epoch, slot))
+                    .map_err(|_| RunnerError::PipelineStoppedError {})
+            }
+        }
+    }
+}
+
+/// Error with its sources, in one line
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push_str(": ");
+        chain.push_str(&error.to_string());
+        source = error.source();
+    }
+    chain
+}
+
+/// Epoch finished by the primary server-manager but not settled yet
+struct FinishedEpoch {
+    epoch_index: u64,
+    /// Inputs to replay, when the epochs are verified
+    inputs: Option<Vec<EpochInput>>,
+    /// Response of the server-manager, or `None` when the epoch was empty.
+    /// Its claim and proofs are converted by the settlement.
+    finished: Option<ServerManagerEpoch>,
+}
+
+/// Verifies the finished epochs and produces their proofs and claims.
+///
+/// The epochs are settled in the order they were finished, so the claims are
+/// always produced in order, and a claim is only produced after the verifier
+/// agrees with it.
+pub struct Settlement {
+    broker: BrokerFacade,
+    verifier: Option<Verifier>,
+}
+
+impl Settlement {
+    pub fn new(broker: BrokerFacade, verifier: Option<Verifier>) -> Self {
+        Self { broker, verifier }
+    }
+
+    async fn run(
+        mut self,
+        mut receiver: mpsc::UnboundedReceiver<Publication>,
+    ) -> Result<()> {
+        while let Some(publication) = receiver.recv().await {
+            match publication {
+                Publication::Outputs(outputs) => {
+                    self.broker
+                        .produce_outputs(outputs)
+                        .await
+                        .context(ProduceOutputsSnafu)?;
+                    tracing::trace!("produced outputs in broker");
+                }
+                Publication::Epoch(epoch, slot) => {
+                    // The slot is freed once the epoch stops waiting, as
+                    // the pipeline depth doesn't count the one settling
+                    drop(slot);
+                    self.settle(epoch).await?;
                 }
             }
         }
         Ok(())
     }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    async fn settle(&mut self, epoch: FinishedEpoch) -> Result<()> {
+        tracing::trace!(epoch.epoch_index, "settling epoch");
+
+        let rollups_claim = epoch
+            .finished
+            .as_ref()
+            .map(ServerManagerEpoch::claim)
+            .transpose()
+            .context(GetEpochClaimSnafu)?;
+
+        if let Some(verifier) = &mut self.verifier {
+            verifier
+                .verify_epoch(
+                    epoch.epoch_index,
+                    epoch.inputs.unwrap_or_default(),
+                    rollups_claim.as_ref(),
+                )
+                .await
+                .context(VerifyEpochSnafu)?;
+        }
+
+        if let Some((finished, rollups_claim)) =
+            epoch.finished.zip(rollups_claim)
+        {
+            let proofs = finished.proofs().context(GetEpochClaimSnafu)?;
+            self.broker
+                .produce_outputs(proofs)
+                .await
+                .context(ProduceOutputsSnafu)?;
+            tracing::trace!("produced outputs in broker");
+
+            self.broker
+                .produce_rollups_claim(rollups_claim)
+                .await
+                .context(ProduceClaimSnafu)?;
+            tracing::info!(epoch.epoch_index, "produced epoch claim");
+        }
+        Ok(())
+    }
 }
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use clap::Parser;
+use humane::ByteSize;
+use std::time::Duration;
 
 use grpc_interfaces::cartesi_machine::{
     ConcurrencyConfig, MachineRuntimeConfig,
@@ -14,7 +16,7 @@ pub struct ServerManagerConfig {
     pub machine_snapshot_path: String,
     pub max_decoding_message_size: usize,
     pub session_id: String,
-    pub pending_inputs_sleep_duration: u64,
+    pub pending_inputs_sleep_duration: Duration,
     pub pending_inputs_max_retries: u64,
     pub runtime_config: MachineRuntimeConfig,
     pub deadline_config: DeadlineConfig,
@@ -31,16 +33,18 @@ impl ServerManagerConfig {
         };
 
         let deadline_config = DeadlineConfig {
-            checkin: cli_config.sm_deadline_checkin,
-            advance_state: cli_config.sm_deadline_advance_state,
-            advance_state_increment: cli_config
-                .sm_deadline_advance_state_increment,
-            inspect_state: cli_config.sm_deadline_inspect_state,
-            inspect_state_increment: cli_config
-                .sm_deadline_inspect_state_increment,
-            machine: cli_config.sm_deadline_machine,
-            store: cli_config.sm_deadline_store,
-            fast: cli_config.sm_deadline_fast,
+            checkin: millis(cli_config.sm_deadline_checkin),
+            advance_state: millis(cli_config.sm_deadline_advance_state),
+            advance_state_increment: millis(
+                cli_config.sm_deadline_advance_state_increment,
+            ),
+            inspect_state: millis(cli_config.sm_deadline_inspect_state),
+            inspect_state_increment: millis(
+                cli_config.sm_deadline_inspect_state_increment,
+            ),
+            machine: millis(cli_config.sm_deadline_machine),
+            store: millis(cli_config.sm_deadline_store),
+            fast: millis(cli_config.sm_deadline_fast),
         };
 
         let cycles_config = CyclesConfig {
@@ -55,7 +59,9 @@ impl ServerManagerConfig {
         Self {
             server_manager_endpoint: cli_config.server_manager_endpoint,
             machine_snapshot_path: cli_config.machine_snapshot_path,
-            max_decoding_message_size: cli_config.max_decoding_message_size,
+            max_decoding_message_size: cli_config
+                .max_decoding_message_size
+                .as_usize(),
             session_id: cli_config.session_id,
             pending_inputs_sleep_duration: cli_config
                 .sm_pending_inputs_sleep_duration,
@@ -68,6 +74,11 @@ impl ServerManagerConfig {
     }
 }
 
+/// Converts a deadline to the milliseconds expected by the server-manager
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
 #[derive(Debug, Parser)]
 #[command(name = "server_manager_config")]
 pub struct ServerManagerCLIConfig {
@@ -79,17 +90,29 @@ pub struct ServerManagerCLIConfig {
     #[arg(long, env, default_value = "")]
     pub machine_snapshot_path: String,
 
-    /// Maximum size of a decoded message
-    #[arg(long, env, default_value_t = 100 * 1024 * 1024)]
-    pub max_decoding_message_size: usize,
+    /// Maximum size of a decoded message, such as `100MiB` (a bare number is
+    /// in bytes)
+    #[arg(
+        long,
+        env,
+        default_value = "100MiB",
+        value_parser = humane::parse_byte_size
+    )]
+    pub max_decoding_message_size: ByteSize,
 
     /// Server-manager session id
     #[arg(long, env, default_value = "default_rollups_id")]
     pub session_id: String,
 
-    /// Sleep duration while polling for server-manager pending inputs (in millis)
-    #[arg(long, env, default_value_t = 1000)]
-    pub sm_pending_inputs_sleep_duration: u64,
+    /// Sleep duration while polling for server-manager pending inputs, such
+    /// as `1s` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "1s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_pending_inputs_sleep_duration: Duration,
 
     /// Max number of retries while polling server-manager for pending inputs
     #[arg(long, env, default_value_t = 600)]
@@ -99,37 +122,77 @@ pub struct ServerManagerCLIConfig {
     #[arg(long, env, default_value_t = 0)]
     pub sm_concurrency_update_merkle_tree: u64,
 
-    /// Deadline for receiving checkin from spawned machine server
-    #[arg(long, env, default_value_t = 5 * 1000)]
-    pub sm_deadline_checkin: u64,
-
-    /// Deadline for advancing the state
-    #[arg(long, env, default_value_t = 1000 * 60 * 3)]
-    pub sm_deadline_advance_state: u64,
-
-    /// Deadline for each increment when advancing state
-    #[arg(long, env, default_value_t = 1000 * 10)]
-    pub sm_deadline_advance_state_increment: u64,
-
-    /// Deadline for inspecting state
-    #[arg(long, env, default_value_t = 1000 * 60 * 3)]
-    pub sm_deadline_inspect_state: u64,
-
-    /// Deadline for each increment when inspecting state
-    #[arg(long, env, default_value_t = 1000 * 10)]
-    pub sm_deadline_inspect_state_increment: u64,
-
-    /// Deadline for instantiating a machine
-    #[arg(long, env, default_value_t = 1000 * 60 * 5)]
-    pub sm_deadline_machine: u64,
-
-    /// Deadline for storing a machine
-    #[arg(long, env, default_value_t = 1000 * 60 * 3)]
-    pub sm_deadline_store: u64,
-
-    /// Deadline for quick machine server tasks
-    #[arg(long, env, default_value_t = 1000 * 5)]
-    pub sm_deadline_fast: u64,
+    /// Deadline for receiving checkin from spawned machine server, such as `5s` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_checkin: Duration,
+
+    /// Deadline for advancing the state, such as `3m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "3m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_advance_state: Duration,
+
+    /// Deadline for each increment when advancing state, such as `10s` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "10s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_advance_state_increment: Duration,
+
+    /// Deadline for inspecting state, such as `3m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "3m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_inspect_state: Duration,
+
+    /// Deadline for each increment when inspecting state, such as `10s` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "10s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_inspect_state_increment: Duration,
+
+    /// Deadline for instantiating a machine, such as `5m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "5m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_machine: Duration,
+
+    /// Deadline for storing a machine, such as `3m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "3m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_store: Duration,
+
+    /// Deadline for quick machine server tasks, such as `5s` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub sm_deadline_fast: Duration,
 
     /// Maximum number of cycles that processing the input in an AdvanceState can take
     #[arg(long, env, default_value_t = u64::MAX >> 2)]
@@ -18,7 +18,7 @@ pub enum ServerManagerError {
     MethodCallError {
         method: String,
         request_id: String,
-        source: tonic::Status,
+        source: Box<tonic::Status>,
     },
 
     #[snafu(display("maximum number of retries exceeded"))]
@@ -14,9 +14,9 @@ use grpc_interfaces::cartesi_machine::Void;
 use grpc_interfaces::cartesi_server_manager::server_manager_client::ServerManagerClient;
 use grpc_interfaces::cartesi_server_manager::{
     processed_input::ProcessedInputOneOf, Address, AdvanceStateRequest,
-    EndSessionRequest, FinishEpochRequest, GetEpochStatusRequest,
-    GetSessionStatusRequest, InputMetadata, ProcessedInput,
-    StartSessionRequest,
+    EndSessionRequest, FinishEpochRequest, FinishEpochResponse,
+    GetEpochStatusRequest, GetSessionStatusRequest, InputMetadata,
+    ProcessedInput, StartSessionRequest,
 };
 
 use super::claim::compute_epoch_hash;
@@ -70,7 +70,7 @@ macro_rules! grpc_call {
                     _ => Error::transient,
                 };
                 err_type(ServerManagerError::MethodCallError {
-                    source: status,
+                    source: Box::new(status),
                     method: stringify!($method).to_owned(),
                     request_id,
                 })
@@ -150,24 +150,38 @@ impl ServerManagerFacade {
             )?;
         }
 
+        sm_facade.start_session().await?;
+
+        Ok(sm_facade)
+    }
+
+    /// Whether the server-manager still has the session, which it loses
+    /// when it restarts
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn has_session(&mut self) -> Result<bool> {
+        let response = grpc_call!(self, get_status, Void {})?;
+        Ok(response.session_id.contains(&self.config.session_id))
+    }
+
+    /// Starts the session from the machine snapshot, so the inputs must be
+    /// sent again from the first one
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn start_session(&mut self) -> Result<()> {
         tracing::trace!("starting server-manager session");
 
-        grpc_call!(sm_facade, start_session, {
+        grpc_call!(self, start_session, {
             StartSessionRequest {
-                session_id: sm_facade.config.session_id.clone(),
-                machine_directory: sm_facade
-                    .config
-                    .machine_snapshot_path
-                    .clone(),
-                runtime: Some(sm_facade.config.runtime_config.clone()),
+                session_id: self.config.session_id.clone(),
+                machine_directory: self.config.machine_snapshot_path.clone(),
+                runtime: Some(self.config.runtime_config.clone()),
                 active_epoch_index: 0,
                 processed_input_count: 0,
-                server_cycles: Some(sm_facade.config.cycles_config.clone()),
-                server_deadline: Some(sm_facade.config.deadline_config.clone()),
+                server_cycles: Some(self.config.cycles_config.clone()),
+                server_deadline: Some(self.config.deadline_config.clone()),
             }
         })?;
 
-        Ok(sm_facade)
+        Ok(())
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -272,12 +286,12 @@ impl ServerManagerFacade {
     }
 
     /// Send a finish-epoch request to the server-manager
-    /// Return the epoch claim and the proofs
+    /// Return the finished epoch, whose claim and proofs are converted apart
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn finish_epoch(
         &mut self,
         epoch_index: u64,
-    ) -> Result<(RollupsClaim, Vec<RollupsOutput>)> {
+    ) -> Result<FinishedEpoch> {
         tracing::trace!(epoch_index, "sending finish epoch");
 
         // Wait for pending inputs before sending a finish request
@@ -300,35 +314,13 @@ impl ServerManagerFacade {
             .zip(processed_inputs.last())
             .context(EmptyEpochSnafu { epoch_index })?;
 
-        let vouchers_metadata_hash =
-            convert_hash(get_field!(response.vouchers_epoch_root_hash))?;
-        let notices_metadata_hash =
-            convert_hash(get_field!(response.notices_epoch_root_hash))?;
-        let machine_state_hash =
-            convert_hash(get_field!(response.machine_hash))?;
-        let epoch_hash = compute_epoch_hash(
-            &vouchers_metadata_hash,
-            &notices_metadata_hash,
-            &machine_state_hash,
-        );
-        tracing::trace!(?epoch_hash, "computed epoch hash");
-
-        let mut proofs = vec![];
-        for proof in response.proofs {
-            let proof = convert_proof(proof)?;
-            proofs.push(RollupsOutput::Proof(proof));
-        }
-        tracing::trace!(?proofs, "got proofs");
-
-        let rollups_claim = RollupsClaim {
+        Ok(FinishedEpoch {
             dapp_address: self.dapp_address.clone(),
             epoch_index,
-            epoch_hash,
-            first_index: first_input.input_index as u128,
-            last_index: last_input.input_index as u128,
-        };
-
-        Ok((rollups_claim, proofs))
+            first_index: first_input.input_index,
+            last_index: last_input.input_index,
+            response,
+        })
     }
 
     /// Wait until the server-manager processes all pending inputs
@@ -348,9 +340,7 @@ impl ServerManagerFacade {
                 }
             })?;
             if response.pending_input_count > 0 {
-                let duration = std::time::Duration::from_millis(
-                    self.config.pending_inputs_sleep_duration,
-                );
+                let duration = self.config.pending_inputs_sleep_duration;
                 tracing::debug!(
                     "server-manager has {} pending inputs; sleeping for {} ms",
                     response.pending_input_count,
@@ -369,3 +359,52 @@ impl ServerManagerFacade {
         Err(ServerManagerError::PendingInputsExceededError {})
     }
 }
+
+/// Epoch finished by the server-manager
+pub struct FinishedEpoch {
+    dapp_address: rollups_events::Address,
+    epoch_index: u64,
+    first_index: u64,
+    last_index: u64,
+    response: FinishEpochResponse,
+}
+
+impl FinishedEpoch {
+    /// Return the epoch claim
+    pub fn claim(&self) -> Result<RollupsClaim> {
+        let response = &self.response;
+        let vouchers_root_hash =
+            get_field!(response.vouchers_epoch_root_hash.clone());
+        let notices_root_hash =
+            get_field!(response.notices_epoch_root_hash.clone());
+        let vouchers_metadata_hash = convert_hash(vouchers_root_hash)?;
+        let notices_metadata_hash = convert_hash(notices_root_hash)?;
+        let machine_state_hash =
+            convert_hash(get_field!(response.machine_hash.clone()))?;
+        let epoch_hash = compute_epoch_hash(
+            &vouchers_metadata_hash,
+            &notices_metadata_hash,
+            &machine_state_hash,
+        );
+        tracing::trace!(?epoch_hash, "computed epoch hash");
+
+        Ok(RollupsClaim {
+            dapp_address: self.dapp_address.clone(),
+            epoch_index: self.epoch_index,
+            epoch_hash,
+            first_index: self.first_index as u128,
+            last_index: self.last_index as u128,
+        })
+    }
+
+    /// Return the proofs of the outputs of the epoch
+    pub fn proofs(self) -> Result<Vec<RollupsOutput>> {
+        let mut proofs = vec![];
+        for proof in self.response.proofs {
+            let proof = convert_proof(proof)?;
+            proofs.push(RollupsOutput::Proof(proof));
+        }
+        tracing::trace!(?proofs, "got proofs");
+        Ok(proofs)
+    }
+}
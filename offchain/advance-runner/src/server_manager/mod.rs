@@ -9,4 +9,4 @@ mod facade;
 
 pub use config::{ServerManagerCLIConfig, ServerManagerConfig};
 pub use error::ServerManagerError;
-pub use facade::ServerManagerFacade;
+pub use facade::{FinishedEpoch, ServerManagerFacade};
@@ -0,0 +1,219 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use backoff::ExponentialBackoff;
+use rollups_events::{Address, Hash, InputMetadata, RollupsClaim};
+use snafu::{ResultExt, Snafu};
+
+use crate::server_manager::{
+    ServerManagerConfig, ServerManagerError, ServerManagerFacade,
+};
+
+#[derive(Debug, Snafu)]
+pub enum VerifierError {
+    #[snafu(display("failed to connect to the verification server-manager"))]
+    ConnectionError { source: ServerManagerError },
+
+    #[snafu(display(
+        "failed to replay input {} on the verifier",
+        input_index
+    ))]
+    ReplayError {
+        input_index: u64,
+        source: ServerManagerError,
+    },
+
+    #[snafu(display(
+        "failed to finish epoch {} on the verifier",
+        epoch_index
+    ))]
+    VerifierFinishEpochError {
+        epoch_index: u64,
+        source: ServerManagerError,
+    },
+
+    #[snafu(display(
+        "epoch {} diverged; primary hash is {:?} but verifier got {:?}",
+        epoch_index,
+        primary,
+        verifier
+    ))]
+    EpochHashMismatch {
+        epoch_index: u64,
+        primary: Hash,
+        verifier: Hash,
+    },
+
+    #[snafu(display(
+        "epoch {} is empty on the {} machine only",
+        epoch_index,
+        machine
+    ))]
+    EmptyEpochMismatch { epoch_index: u64, machine: String },
+}
+
+type Result<T> = std::result::Result<T, VerifierError>;
+
+/// Input of an epoch, kept while the epoch is open so it can be replayed
+pub struct EpochInput {
+    pub input_index: u64,
+    pub metadata: InputMetadata,
+    pub payload: Vec<u8>,
+}
+
+/// Replays the inputs of each epoch on an independent server-manager and
+/// checks that it reaches the same epoch hash as the primary one.
+///
+/// The inputs are only sent to the verifier once the epoch is finished, so
+/// the verifier never slows down the processing of inputs.
+pub struct Verifier {
+    server_manager: ServerManagerFacade,
+}
+
+impl Verifier {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn new(
+        dapp_address: Address,
+        config: ServerManagerConfig,
+        backoff: ExponentialBackoff,
+    ) -> Result<Self> {
+        let server_manager =
+            ServerManagerFacade::new(dapp_address, config, backoff)
+                .await
+                .context(ConnectionSnafu)?;
+        Ok(Self { server_manager })
+    }
+
+    /// Replay the inputs and finish the epoch on the verifier.
+    /// The primary claim is `None` when the primary epoch was empty.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn verify_epoch(
+        &mut self,
+        epoch_index: u64,
+        inputs: Vec<EpochInput>,
+        primary_claim: Option<&RollupsClaim>,
+    ) -> Result<()> {
+        tracing::trace!(
+            epoch_index,
+            inputs = inputs.len(),
+            "replaying epoch on the verifier"
+        );
+
+        for input in inputs {
+            self.server_manager
+                .advance_state(
+                    epoch_index,
+                    input.input_index,
+                    input.metadata,
+                    input.payload,
+                )
+                .await
+                .context(ReplaySnafu {
+                    input_index: input.input_index,
+                })?;
+        }
+
+        let result = self
+            .server_manager
+            .finish_epoch(epoch_index)
+            .await
+            .and_then(|epoch| epoch.claim())
+            .map(|claim| claim.epoch_hash);
+        compare_epochs(
+            epoch_index,
+            primary_claim.map(|claim| &claim.epoch_hash),
+            result,
+        )?;
+        tracing::info!(epoch_index, "epoch hash verified");
+        Ok(())
+    }
+}
+
+/// Checks the epoch hash of the verifier against the one of the primary
+/// machine, which is `None` when the primary epoch was empty
+fn compare_epochs(
+    epoch_index: u64,
+    primary: Option<&Hash>,
+    verifier: std::result::Result<Hash, ServerManagerError>,
+) -> Result<()> {
+    match (primary, verifier) {
+        (Some(primary), Ok(verifier)) => {
+            snafu::ensure!(
+                *primary == verifier,
+                EpochHashMismatchSnafu {
+                    epoch_index,
+                    primary: primary.clone(),
+                    verifier,
+                }
+            );
+            Ok(())
+        }
+        (None, Err(ServerManagerError::EmptyEpochError { .. })) => Ok(()),
+        (Some(_), Err(ServerManagerError::EmptyEpochError { .. })) => {
+            Err(VerifierError::EmptyEpochMismatch {
+                epoch_index,
+                machine: "verifier".to_owned(),
+            })
+        }
+        (None, Ok(_)) => Err(VerifierError::EmptyEpochMismatch {
+            epoch_index,
+            machine: "primary".to_owned(),
+        }),
+        (_, Err(source)) => Err(VerifierError::VerifierFinishEpochError {
+            epoch_index,
+            source,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rollups_events::HASH_SIZE;
+
+    fn empty(
+        epoch_index: u64,
+    ) -> std::result::Result<Hash, ServerManagerError> {
+        Err(ServerManagerError::EmptyEpochError { epoch_index })
+    }
+
+    #[test_log::test]
+    fn test_it_accepts_matching_epochs() {
+        let hash = Hash::new([1; HASH_SIZE]);
+        compare_epochs(0, Some(&hash), Ok(hash.clone())).unwrap();
+        compare_epochs(1, None, empty(1)).unwrap();
+    }
+
+    #[test_log::test]
+    fn test_it_rejects_diverging_hashes() {
+        let primary = Hash::new([1; HASH_SIZE]);
+        let verifier = Hash::new([2; HASH_SIZE]);
+        let error = compare_epochs(3, Some(&primary), Ok(verifier.clone()))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            VerifierError::EpochHashMismatch {
+                epoch_index: 3,
+                primary: p,
+                verifier: v,
+            } if p == primary && v == verifier
+        ));
+    }
+
+    #[test_log::test]
+    fn test_it_rejects_epochs_empty_on_one_machine_only() {
+        let hash = Hash::new([1; HASH_SIZE]);
+        let error = compare_epochs(0, Some(&hash), empty(0)).unwrap_err();
+        assert!(matches!(
+            error,
+            VerifierError::EmptyEpochMismatch { machine, .. }
+                if machine == "verifier"
+        ));
+        let error = compare_epochs(0, None, Ok(hash)).unwrap_err();
+        assert!(matches!(
+            error,
+            VerifierError::EmptyEpochMismatch { machine, .. }
+                if machine == "primary"
+        ));
+    }
+}
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use advance_runner::config::{
-    AdvanceRunnerConfig, BrokerConfig, DAppMetadata, ServerManagerConfig,
+    AdvanceRunnerConfig, BrokerConfig, DAppMetadata, InputPolicy, Quarantine,
+    ServerManagerConfig,
 };
 use advance_runner::AdvanceRunnerError;
 use grpc_interfaces::cartesi_machine::{
@@ -28,6 +29,59 @@ impl AdvanceRunnerFixture {
         chain_id: u64,
         dapp_address: Address,
         snapshot_dir: Option<String>,
+    ) -> Self {
+        Self::setup_with_input_filters(
+            server_manager_endpoint,
+            session_id,
+            redis_endpoint,
+            chain_id,
+            dapp_address,
+            snapshot_dir,
+            Default::default(),
+            Default::default(),
+        )
+        .await
+    }
+
+    /// Same as `setup`, with an input policy and a quarantine
+    #[allow(clippy::too_many_arguments)]
+    pub async fn setup_with_input_filters(
+        server_manager_endpoint: String,
+        session_id: String,
+        redis_endpoint: BrokerEndpoint,
+        chain_id: u64,
+        dapp_address: Address,
+        snapshot_dir: Option<String>,
+        input_policy: InputPolicy,
+        quarantine: Quarantine,
+    ) -> Self {
+        Self::setup_with_options(
+            server_manager_endpoint,
+            session_id,
+            redis_endpoint,
+            chain_id,
+            dapp_address,
+            snapshot_dir,
+            input_policy,
+            quarantine,
+            0,
+        )
+        .await
+    }
+
+    /// Same as `setup_with_input_filters`, settling the epochs in a pipeline
+    /// when `epoch_pipeline_depth` is above zero
+    #[allow(clippy::too_many_arguments)]
+    pub async fn setup_with_options(
+        server_manager_endpoint: String,
+        session_id: String,
+        redis_endpoint: BrokerEndpoint,
+        chain_id: u64,
+        dapp_address: Address,
+        snapshot_dir: Option<String>,
+        input_policy: InputPolicy,
+        quarantine: Quarantine,
+        epoch_pipeline_depth: usize,
     ) -> Self {
         let runtime_config = MachineRuntimeConfig {
             concurrency: Some(ConcurrencyConfig {
@@ -58,7 +112,7 @@ impl AdvanceRunnerFixture {
             machine_snapshot_path: snapshot_dir.unwrap_or("".to_owned()),
             max_decoding_message_size: 100 * 1024 * 1024,
             session_id,
-            pending_inputs_sleep_duration: 1000,
+            pending_inputs_sleep_duration: Duration::from_secs(1),
             pending_inputs_max_retries: 10,
             runtime_config,
             deadline_config,
@@ -72,7 +126,7 @@ impl AdvanceRunnerFixture {
 
         let broker_config = BrokerConfig {
             redis_endpoint,
-            consume_timeout: 100,
+            consume_timeout: Duration::from_millis(100),
             backoff: Default::default(),
         };
 
@@ -80,12 +134,17 @@ impl AdvanceRunnerFixture {
 
         let config = AdvanceRunnerConfig {
             server_manager_config,
+            verifier_config: None,
             broker_config,
             dapp_metadata,
             backoff_max_elapsed_duration,
             healthcheck_port: 0,
             log_config: LogConfig::default(),
             reader_mode: false,
+            input_policy,
+            quarantine,
+            advance_timeout: Duration::from_secs(60),
+            epoch_pipeline_depth,
         };
         let handler = RefCell::new(Some(start_advance_runner(config.clone())));
         Self { config, handler }
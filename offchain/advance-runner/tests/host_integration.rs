@@ -1,12 +1,15 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use advance_runner::config::{InputPolicy, PoisonInputAction, Quarantine};
 use fixtures::AdvanceRunnerFixture;
 use rand::Rng;
 use rollups_events::{
-    Hash, InputMetadata, Payload, RollupsAdvanceStateInput, RollupsClaim,
-    RollupsData, RollupsInput, INITIAL_ID,
+    Address, Hash, InputMetadata, Payload, RollupsAdvanceStateInput,
+    RollupsClaim, RollupsData, RollupsInput, RollupsOutput, ADDRESS_SIZE,
+    INITIAL_ID,
 };
+use std::collections::HashSet;
 use test_fixtures::{BrokerFixture, EchoDAppFixture, HostServerManagerFixture};
 use testcontainers::clients::Cli;
 
@@ -20,6 +23,41 @@ struct TestState<'d> {
 
 impl TestState<'_> {
     async fn setup(docker: &Cli) -> TestState<'_> {
+        Self::setup_with_input_filters(
+            docker,
+            Default::default(),
+            Default::default(),
+        )
+        .await
+    }
+
+    async fn setup_with_input_filters(
+        docker: &Cli,
+        input_policy: InputPolicy,
+        quarantine: Quarantine,
+    ) -> TestState<'_> {
+        Self::setup_with_options(docker, input_policy, quarantine, 0).await
+    }
+
+    async fn setup_pipelined(
+        docker: &Cli,
+        epoch_pipeline_depth: usize,
+    ) -> TestState<'_> {
+        Self::setup_with_options(
+            docker,
+            Default::default(),
+            Default::default(),
+            epoch_pipeline_depth,
+        )
+        .await
+    }
+
+    async fn setup_with_options(
+        docker: &Cli,
+        input_policy: InputPolicy,
+        quarantine: Quarantine,
+        epoch_pipeline_depth: usize,
+    ) -> TestState<'_> {
         let broker = BrokerFixture::setup(docker).await;
         let server_manager = HostServerManagerFixture::setup(docker).await;
 
@@ -30,13 +68,16 @@ impl TestState<'_> {
             }
         });
 
-        let advance_runner = AdvanceRunnerFixture::setup(
+        let advance_runner = AdvanceRunnerFixture::setup_with_options(
             server_manager.grpc_endpoint().to_owned(),
             server_manager.session_id().to_owned(),
             broker.redis_endpoint().to_owned(),
             broker.chain_id(),
             broker.dapp_address().to_owned(),
             None,
+            input_policy,
+            quarantine,
+            epoch_pipeline_depth,
         )
         .await;
 
@@ -92,6 +133,97 @@ async fn send_inputs_to_server_manager() {
         .await;
 }
 
+#[test_log::test(tokio::test)]
+async fn advance_runner_advances_past_ineligible_inputs() {
+    let denied = Address::new([1; ADDRESS_SIZE]);
+    let input_policy = InputPolicy::new(None, [denied.clone()].into(), None);
+    let docker = Cli::default();
+    let state = TestState::setup_with_input_filters(
+        &docker,
+        input_policy,
+        Default::default(),
+    )
+    .await;
+
+    tracing::info!("producing an ineligible input and an eligible one");
+    let payload = generate_payload();
+    let senders = [denied, Address::new([2; ADDRESS_SIZE])];
+    for (i, msg_sender) in senders.into_iter().enumerate() {
+        let data = RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+            metadata: InputMetadata {
+                msg_sender,
+                input_index: i as u64,
+                ..Default::default()
+            },
+            payload: payload.clone(),
+            tx_hash: Hash::default(),
+        });
+        state.broker.produce_input_event(data).await;
+    }
+
+    tracing::info!("waiting until both inputs are processed");
+    state.server_manager.assert_session_ready().await;
+    state
+        .server_manager
+        .assert_epoch_status_payloads(0, &[Payload::default(), payload])
+        .await;
+}
+
+#[test_log::test(tokio::test)]
+async fn advance_runner_advances_past_quarantined_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("input-0.json"),
+        r#"{
+            "input_index": 0,
+            "epoch_index": 0,
+            "msg_sender": "0x0000000000000000000000000000000000000000",
+            "block_number": 0,
+            "payload_hash": "0x",
+            "attempts": 1,
+            "last_error": "machine crashed"
+        }"#,
+    )
+    .unwrap();
+    let quarantine = Quarantine::new(
+        Some(dir.path().to_owned()),
+        1,
+        PoisonInputAction::Reject,
+    );
+    let docker = Cli::default();
+    let state = TestState::setup_with_input_filters(
+        &docker,
+        Default::default(),
+        quarantine,
+    )
+    .await;
+
+    tracing::info!("producing a quarantined input and another one");
+    let payloads = [generate_payload(), generate_payload()];
+    for (i, payload) in payloads.iter().enumerate() {
+        let data = RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+            metadata: InputMetadata {
+                input_index: i as u64,
+                ..Default::default()
+            },
+            payload: payload.clone(),
+            tx_hash: Hash::default(),
+        });
+        state.broker.produce_input_event(data).await;
+    }
+
+    tracing::info!("waiting until both inputs are processed");
+    state.server_manager.assert_session_ready().await;
+    state
+        .server_manager
+        .assert_epoch_status_payloads(
+            0,
+            &[Payload::default(), payloads[1].clone()],
+        )
+        .await;
+    assert!(dir.path().join("input-0.json").exists());
+}
+
 #[test_log::test(tokio::test)]
 async fn advance_runner_fails_when_inputs_has_wrong_epoch() {
     let docker = Cli::default();
@@ -186,6 +318,59 @@ async fn advance_runner_generates_claim_after_finishing_epoch() {
     assert_eq!(claims.len(), N);
 }
 
+#[test_log::test(tokio::test)]
+async fn advance_runner_produces_outputs_after_the_proofs_of_previous_epochs() {
+    let docker = Cli::default();
+    let state = TestState::setup_pipelined(&docker, 2).await;
+
+    const N: usize = 3;
+    tracing::info!("producing {} epochs with one input each", N);
+    for i in 0..N {
+        let advance =
+            RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+                metadata: InputMetadata {
+                    input_index: i as u64,
+                    ..Default::default()
+                },
+                payload: generate_payload(),
+                tx_hash: Hash::default(),
+            });
+        let finish = RollupsData::FinishEpoch {};
+        state.broker.produce_input_event(advance).await;
+        state.broker.produce_input_event(finish).await;
+    }
+
+    tracing::info!("waiting until the expected claims are generated");
+    state.server_manager.assert_session_ready().await;
+    let claims = state.broker.consume_n_claims(N).await;
+    assert_eq!(claims.len(), N);
+
+    tracing::info!("checking the order of the outputs");
+    let outputs = state.broker.consume_all_outputs().await;
+    let mut proven = HashSet::new();
+    for output in outputs {
+        let input_index = match output {
+            RollupsOutput::Proof(proof) => {
+                proven.insert(proof.input_index);
+                continue;
+            }
+            RollupsOutput::AdvanceResult(result) => result.input_index,
+            RollupsOutput::Voucher(voucher) => voucher.input_index,
+            RollupsOutput::Notice(notice) => notice.input_index,
+            RollupsOutput::Report(report) => report.input_index,
+        };
+        // Each input is in an epoch of its own
+        if input_index > 0 {
+            assert!(
+                proven.contains(&(input_index - 1)),
+                "outputs of input {} produced before the proofs of the previous epoch",
+                input_index
+            );
+        }
+    }
+    assert_eq!(proven.len(), N);
+}
+
 #[test_log::test(tokio::test)]
 async fn advance_runner_finishes_epoch_when_the_previous_epoch_has_inputs() {
     let docker = Cli::default();
@@ -294,3 +294,51 @@ async fn test_advance_runner_restore_session_after_restart() {
     state.broker.produce_input_event(input).await;
     state.server_manager.assert_epoch_status(1, 2).await;
 }
+
+#[test_log::test(tokio::test)]
+async fn test_advance_runner_recovers_the_session_lost_by_the_server_manager() {
+    let docker = Cli::default();
+    let state = TestState::setup(&docker).await;
+
+    tracing::info!("producing input and finish");
+    let inputs = vec![
+        RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+            metadata: InputMetadata {
+                input_index: 0,
+                ..Default::default()
+            },
+            payload: generate_payload(),
+            tx_hash: Hash::default(),
+        }),
+        RollupsData::FinishEpoch {},
+    ];
+    for input in inputs {
+        state.broker.produce_input_event(input).await;
+    }
+    state.server_manager.assert_session_ready().await;
+    state.server_manager.assert_epoch_finished(0).await;
+    state.broker.consume_n_claims(1).await;
+
+    tracing::info!("ending the session behind the advance_runner");
+    state.server_manager.end_session().await;
+
+    tracing::info!("producing another input and checking");
+    let payload = generate_payload();
+    let input = RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+        metadata: InputMetadata {
+            input_index: 1,
+            ..Default::default()
+        },
+        payload: payload.clone(),
+        tx_hash: Hash::default(),
+    });
+    state.broker.produce_input_event(input).await;
+    state.server_manager.assert_session_ready().await;
+    state
+        .server_manager
+        .assert_epoch_status_payloads(1, &vec![payload])
+        .await;
+
+    tracing::info!("checking that the claim wasn't produced again");
+    assert_eq!(state.broker.consume_all_claims().await.len(), 1);
+}
@@ -0,0 +1,180 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{extract::State, routing::get, Json, Router};
+use rollups_events::{Address, RollupsClaim};
+use scheduler::{seconds_since_epoch, Window};
+use serde::Serialize;
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// A claim held back by a blackout window
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeferredClaim {
+    pub dapp_address: Address,
+    pub epoch_index: u64,
+    pub first_index: u128,
+    pub last_index: u128,
+    /// Seconds since the Unix epoch
+    pub deferred_at: u64,
+}
+
+/// State of the blackout windows, as served at `/blackout`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BlackoutStatus {
+    /// Seconds since the Unix epoch when the current blackout ends, if the
+    /// claimer is in one
+    pub active_until: Option<u64>,
+    pub windows: Vec<String>,
+    pub deferred_claims: Vec<DeferredClaim>,
+}
+
+/// Windows set by the operator when the claimer sends no claims, such as
+/// planned maintenances or chain upgrades. The claims that arrive in them
+/// are kept until the blackout ends, then sent in order.
+#[derive(Clone, Debug, Default)]
+pub struct Blackouts {
+    windows: Vec<Window>,
+    deferred: Arc<Mutex<Vec<(RollupsClaim, u64)>>>,
+}
+
+impl Blackouts {
+    pub fn new(windows: Vec<Window>) -> Self {
+        Self {
+            windows,
+            deferred: Default::default(),
+        }
+    }
+
+    /// End of the blackout `time` is in, which is the latest end among the
+    /// active windows, so adjacent windows make one blackout
+    pub fn active_until(&self, time: SystemTime) -> Option<SystemTime> {
+        let mut end = None;
+        while let Some(later) = self
+            .windows
+            .iter()
+            .filter_map(|window| window.end_if_active(end.unwrap_or(time)))
+            .max()
+        {
+            end = Some(later);
+        }
+        end
+    }
+
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    pub fn defer(&self, rollups_claim: RollupsClaim) {
+        let deferred_at = seconds_since_epoch(SystemTime::now());
+        self.deferred
+            .lock()
+            .unwrap()
+            .push((rollups_claim, deferred_at));
+    }
+
+    /// Removes the deferred claims, in the order they arrived
+    pub fn take_deferred(&self) -> Vec<RollupsClaim> {
+        let deferred = mem::take(&mut *self.deferred.lock().unwrap());
+        deferred.into_iter().map(|(claim, _)| claim).collect()
+    }
+
+    pub fn has_deferred(&self) -> bool {
+        !self.deferred.lock().unwrap().is_empty()
+    }
+
+    pub fn status(&self) -> BlackoutStatus {
+        let deferred_claims = self
+            .deferred
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(claim, deferred_at)| DeferredClaim {
+                dapp_address: claim.dapp_address.clone(),
+                epoch_index: claim.epoch_index,
+                first_index: claim.first_index,
+                last_index: claim.last_index,
+                deferred_at: *deferred_at,
+            })
+            .collect();
+        BlackoutStatus {
+            active_until: self
+                .active_until(SystemTime::now())
+                .map(seconds_since_epoch),
+            windows: self.windows.iter().map(Window::to_string).collect(),
+            deferred_claims,
+        }
+    }
+
+    /// Route that serves the blackout windows and the claims deferred by
+    /// them as JSON (`/blackout`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/blackout", get(get_status))
+            .with_state(self)
+    }
+}
+
+async fn get_status(
+    State(blackouts): State<Blackouts>,
+) -> Json<BlackoutStatus> {
+    Json(blackouts.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn blackouts(windows: &[&str]) -> Blackouts {
+        Blackouts::new(windows.iter().map(|w| w.parse().unwrap()).collect())
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn it_joins_adjacent_windows() {
+        // 2024-03-01T02:00Z is 1709258400
+        let blackouts = blackouts(&[
+            "2024-03-01T02:00Z for 1h",
+            "2024-03-01T03:00Z for 30m",
+            "2024-03-01T05:00Z for 1h",
+        ]);
+        assert_eq!(blackouts.active_until(at(1709258399)), None);
+        assert_eq!(
+            blackouts.active_until(at(1709258400)),
+            Some(at(1709263800))
+        );
+        assert_eq!(blackouts.active_until(at(1709263800)), None);
+        assert_eq!(Blackouts::default().active_until(at(1709258400)), None);
+    }
+
+    #[test]
+    fn it_accounts_for_the_deferred_claims() {
+        let blackouts = blackouts(&["2024-03-01T02:00Z for 1h"]);
+        let claim = |epoch_index| RollupsClaim {
+            epoch_index,
+            ..Default::default()
+        };
+        blackouts.defer(claim(1));
+        blackouts.defer(claim(2));
+        assert!(blackouts.has_deferred());
+
+        let status = blackouts.status();
+        assert_eq!(status.windows, vec!["2024-03-01T02:00:00Z for 3600s"]);
+        let epochs: Vec<_> = status
+            .deferred_claims
+            .iter()
+            .map(|claim| claim.epoch_index)
+            .collect();
+        assert_eq!(epochs, vec![1, 2]);
+
+        assert_eq!(blackouts.take_deferred(), vec![claim(1), claim(2)]);
+        assert!(!blackouts.has_deferred());
+    }
+}
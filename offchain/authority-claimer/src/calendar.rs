@@ -0,0 +1,412 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{
+    extract::State, http::header, response::IntoResponse, routing::get, Json,
+    Router,
+};
+use ethers::types::H160;
+use humane::HumaneError;
+use scheduler::{
+    format_utc_time, parse_utc_time, seconds_since_epoch, ScheduleError,
+};
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::blackout::Blackouts;
+
+#[derive(Debug, Snafu)]
+pub enum ForecastError {
+    #[snafu(display(
+        "`{}` should be `<dapp address>=<first epoch start> every <epoch duration>`",
+        value
+    ))]
+    InvalidForecast { value: String },
+
+    #[snafu(display("invalid DApp address in `{}`", value))]
+    InvalidDAppAddress { value: String },
+
+    #[snafu(display("invalid first epoch start in `{}`", value))]
+    InvalidEpochStart {
+        value: String,
+        source: ScheduleError,
+    },
+
+    #[snafu(display("invalid epoch duration in `{}`", value))]
+    InvalidEpochDuration { value: String, source: HumaneError },
+
+    #[snafu(display(
+        "epoch duration in `{}` must be greater than zero",
+        value
+    ))]
+    ZeroEpochDuration { value: String },
+}
+
+/// Epochs of a tracked DApp, which start at the deployment of the DApp and
+/// last a fixed duration, as
+/// `<dapp address>=<first epoch start> every <epoch duration>`
+/// (`0x70ac...=2024-03-01T00:00Z every 7d`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochForecast {
+    pub dapp_address: H160,
+    pub first_epoch_start: SystemTime,
+    pub epoch_duration: Duration,
+}
+
+impl EpochForecast {
+    /// Index and end of each epoch whose claim window, which starts when the
+    /// epoch ends, overlaps the span from `from` until `until`
+    pub(crate) fn epoch_ends(
+        &self,
+        claim_window: Duration,
+        from: SystemTime,
+        until: SystemTime,
+    ) -> Vec<(u64, SystemTime)> {
+        let start = seconds_since_epoch(self.first_epoch_start);
+        let duration = self.epoch_duration.as_secs();
+        let since = seconds_since_epoch(from)
+            .saturating_sub(claim_window.as_secs())
+            .saturating_sub(start);
+        let until = seconds_since_epoch(until);
+        let mut epochs = vec![];
+        let mut epoch_index = since / duration;
+        loop {
+            let end = start + (epoch_index + 1) * duration;
+            if end >= until {
+                return epochs;
+            }
+            epochs.push((epoch_index, at(end)));
+            epoch_index += 1;
+        }
+    }
+}
+
+impl FromStr for EpochForecast {
+    type Err = ForecastError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (dapp_address, schedule) = value
+            .split_once('=')
+            .context(InvalidForecastSnafu { value })?;
+        let (first_epoch_start, epoch_duration) = schedule
+            .rsplit_once(" every ")
+            .context(InvalidForecastSnafu { value })?;
+        let dapp_address = dapp_address
+            .trim()
+            .parse()
+            .ok()
+            .context(InvalidDAppAddressSnafu { value })?;
+        let first_epoch_start = parse_utc_time(first_epoch_start)
+            .context(InvalidEpochStartSnafu { value })?;
+        let epoch_duration = humane::parse_duration(epoch_duration)
+            .context(InvalidEpochDurationSnafu { value })?;
+        ensure!(
+            epoch_duration.as_secs() > 0,
+            ZeroEpochDurationSnafu { value }
+        );
+        Ok(Self {
+            dapp_address,
+            first_epoch_start,
+            epoch_duration,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DutyKind {
+    /// The claim of an epoch is expected to be sent
+    Claim,
+    /// No claims are sent
+    Blackout,
+}
+
+/// An upcoming duty of the validator, as served at `/duties`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Duty {
+    pub kind: DutyKind,
+    /// Seconds since the Unix epoch
+    pub starts_at: u64,
+    /// Seconds since the Unix epoch
+    pub ends_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dapp_address: Option<H160>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_index: Option<u64>,
+    /// Seconds since the Unix epoch when the blackout the claim window
+    /// starts in ends, since the claim is deferred until then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deferred_until: Option<u64>,
+    /// Blackout window the duty comes from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+}
+
+impl Duty {
+    fn summary(&self) -> String {
+        match (self.kind, self.dapp_address, self.epoch_index) {
+            (DutyKind::Claim, Some(dapp_address), Some(epoch_index)) => {
+                format!("Claim epoch {} of {:?}", epoch_index, dapp_address)
+            }
+            _ => "Claim blackout".to_owned(),
+        }
+    }
+
+    fn uid(&self) -> String {
+        match (self.dapp_address, self.epoch_index) {
+            (Some(dapp_address), Some(epoch_index)) => {
+                format!(
+                    "claim-{:?}-{}@authority-claimer",
+                    dapp_address, epoch_index
+                )
+            }
+            _ => format!("blackout-{}@authority-claimer", self.starts_at),
+        }
+    }
+}
+
+/// Upcoming duties of the validator, forecast from the epochs of the
+/// tracked DApps and the blackout windows, so operators can follow them in
+/// their on-call calendars
+#[derive(Clone, Debug)]
+pub struct DutyCalendar {
+    forecasts: Vec<EpochForecast>,
+    claim_window: Duration,
+    horizon: Duration,
+    blackouts: Blackouts,
+}
+
+impl DutyCalendar {
+    pub fn new(
+        forecasts: Vec<EpochForecast>,
+        claim_window: Duration,
+        horizon: Duration,
+        blackouts: Blackouts,
+    ) -> Self {
+        Self {
+            forecasts,
+            claim_window,
+            horizon,
+            blackouts,
+        }
+    }
+
+    /// Duties that aren't over by `now` and start within the horizon, in
+    /// the order they start
+    pub fn duties(&self, now: SystemTime) -> Vec<Duty> {
+        let until = now + self.horizon;
+        let mut duties = vec![];
+        for forecast in &self.forecasts {
+            let epochs = forecast.epoch_ends(self.claim_window, now, until);
+            for (epoch_index, end) in epochs {
+                duties.push(Duty {
+                    kind: DutyKind::Claim,
+                    starts_at: seconds_since_epoch(end),
+                    ends_at: seconds_since_epoch(end + self.claim_window),
+                    dapp_address: Some(forecast.dapp_address),
+                    epoch_index: Some(epoch_index),
+                    deferred_until: self
+                        .blackouts
+                        .active_until(end)
+                        .map(seconds_since_epoch),
+                    window: None,
+                });
+            }
+        }
+        for window in self.blackouts.windows() {
+            for (start, end) in window.occurrences(now, until) {
+                duties.push(Duty {
+                    kind: DutyKind::Blackout,
+                    starts_at: seconds_since_epoch(start),
+                    ends_at: seconds_since_epoch(end),
+                    dapp_address: None,
+                    epoch_index: None,
+                    deferred_until: None,
+                    window: Some(window.to_string()),
+                });
+            }
+        }
+        duties.sort_by_key(|duty| (duty.starts_at, duty.ends_at));
+        duties
+    }
+
+    /// Renders the duties as an iCalendar feed (RFC 5545)
+    pub fn to_ical(&self, now: SystemTime) -> String {
+        let stamp = ical_time(seconds_since_epoch(now));
+        let mut ical = String::new();
+        let mut line = |line: String| {
+            ical.push_str(&line);
+            ical.push_str("\r\n");
+        };
+        line("BEGIN:VCALENDAR".to_owned());
+        line("VERSION:2.0".to_owned());
+        line("PRODID:-//Cartesi//Authority Claimer//EN".to_owned());
+        for duty in self.duties(now) {
+            line("BEGIN:VEVENT".to_owned());
+            line(format!("UID:{}", duty.uid()));
+            line(format!("DTSTAMP:{}", stamp));
+            line(format!("DTSTART:{}", ical_time(duty.starts_at)));
+            line(format!("DTEND:{}", ical_time(duty.ends_at)));
+            line(format!("SUMMARY:{}", duty.summary()));
+            let description = match (&duty.window, duty.deferred_until) {
+                (Some(window), _) => Some(format!("Window: {}", window)),
+                (None, Some(deferred_until)) => Some(format!(
+                    "Deferred by a blackout until {}",
+                    format_utc_time(at(deferred_until))
+                )),
+                (None, None) => None,
+            };
+            if let Some(description) = description {
+                line(format!("DESCRIPTION:{}", description));
+            }
+            line("END:VEVENT".to_owned());
+        }
+        line("END:VCALENDAR".to_owned());
+        ical
+    }
+
+    /// Routes that serve the upcoming duties as JSON (`/duties`) and as an
+    /// iCalendar feed (`/duties.ics`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/duties", get(get_duties))
+            .route("/duties.ics", get(get_duties_ical))
+            .with_state(self)
+    }
+}
+
+/// Formats the time in the basic form of iCalendar (`20240301T020000Z`)
+fn ical_time(secs: u64) -> String {
+    format_utc_time(at(secs)).replace(['-', ':'], "")
+}
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+async fn get_duties(State(calendar): State<DutyCalendar>) -> Json<Vec<Duty>> {
+    Json(calendar.duties(SystemTime::now()))
+}
+
+async fn get_duties_ical(
+    State(calendar): State<DutyCalendar>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar.to_ical(SystemTime::now()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-01T00:00Z is 1709251200
+    const MARCH_1ST: u64 = 1709251200;
+    const HOUR: u64 = 3600;
+
+    fn calendar(windows: &[&str]) -> DutyCalendar {
+        let forecast =
+            format!("{:?}=2024-03-01T00:00Z every 6h", H160::repeat_byte(0xaa));
+        DutyCalendar::new(
+            vec![forecast.parse().unwrap()],
+            Duration::from_secs(HOUR),
+            Duration::from_secs(24 * HOUR),
+            Blackouts::new(
+                windows.iter().map(|w| w.parse().unwrap()).collect(),
+            ),
+        )
+    }
+
+    #[test]
+    fn it_parses_forecasts() {
+        let forecast: EpochForecast =
+            format!("{:?}=2024-03-01T00:00Z every 7d", H160::repeat_byte(0xaa))
+                .parse()
+                .unwrap();
+        assert_eq!(
+            forecast,
+            EpochForecast {
+                dapp_address: H160::repeat_byte(0xaa),
+                first_epoch_start: at(MARCH_1ST),
+                epoch_duration: Duration::from_secs(7 * 24 * HOUR),
+            }
+        );
+        assert!(matches!(
+            "0xaa every 7d".parse::<EpochForecast>(),
+            Err(ForecastError::InvalidForecast { .. })
+        ));
+        assert!(matches!(
+            "0xaa=2024-03-01T00:00Z every 7d".parse::<EpochForecast>(),
+            Err(ForecastError::InvalidDAppAddress { .. })
+        ));
+        let zero = format!("{:?}=2024-03-01T00:00Z every 0s", H160::zero());
+        assert!(matches!(
+            zero.parse::<EpochForecast>(),
+            Err(ForecastError::ZeroEpochDuration { .. })
+        ));
+    }
+
+    #[test]
+    fn it_forecasts_the_claim_windows() {
+        // Half an hour into the claim window of epoch 0
+        let duties = calendar(&[]).duties(at(MARCH_1ST + 6 * HOUR + 1800));
+        let claims: Vec<_> = duties
+            .iter()
+            .map(|duty| (duty.epoch_index.unwrap(), duty.starts_at))
+            .collect();
+        assert_eq!(
+            claims,
+            vec![
+                (0, MARCH_1ST + 6 * HOUR),
+                (1, MARCH_1ST + 12 * HOUR),
+                (2, MARCH_1ST + 18 * HOUR),
+                (3, MARCH_1ST + 24 * HOUR),
+                (4, MARCH_1ST + 30 * HOUR),
+            ]
+        );
+        assert_eq!(duties[0].ends_at, MARCH_1ST + 7 * HOUR);
+        assert!(calendar(&[]).duties(at(MARCH_1ST - 48 * HOUR)).is_empty());
+    }
+
+    #[test]
+    fn it_flags_the_claims_deferred_by_blackouts() {
+        let calendar = calendar(&["2024-03-01T11:00Z for 2h"]);
+        let duties = calendar.duties(at(MARCH_1ST));
+        let kinds: Vec<_> = duties.iter().map(|duty| duty.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DutyKind::Claim,
+                DutyKind::Blackout,
+                DutyKind::Claim,
+                DutyKind::Claim,
+            ]
+        );
+        assert_eq!(duties[1].starts_at, MARCH_1ST + 11 * HOUR);
+        assert_eq!(duties[2].deferred_until, Some(MARCH_1ST + 13 * HOUR));
+        assert_eq!(duties[3].deferred_until, None);
+    }
+
+    #[test]
+    fn it_renders_an_ical_feed() {
+        let calendar = calendar(&["2024-03-01T11:00Z for 2h"]);
+        let ical = calendar.to_ical(at(MARCH_1ST));
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ical.matches("BEGIN:VEVENT").count(), 4);
+        assert!(ical.contains("DTSTART:20240301T110000Z\r\n"));
+        assert!(ical.contains("SUMMARY:Claim blackout\r\n"));
+        assert!(ical.contains(
+            "DESCRIPTION:Deferred by a blackout until 2024-03-01T13:00:00Z\r\n"
+        ));
+        assert!(ical.contains(&format!(
+            "SUMMARY:Claim epoch 1 of {:?}\r\n",
+            H160::repeat_byte(0xaa)
+        )));
+    }
+}
@@ -2,24 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use async_trait::async_trait;
-use contracts::history::{Claim, History};
+use contracts::{cartesi_dapp::CartesiDApp, history::History};
 use ethers::{
     self,
     contract::ContractError,
-    providers::{
-        Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient,
-    },
-    types::H160,
+    providers::Middleware,
+    types::{H160, H256},
 };
-use rollups_events::{Address, RollupsClaim};
+use http_provider::{HttpClient, HttpProvider};
+use rollups_events::{Address, DAppMetadata, RollupsClaim};
 use snafu::{ensure, ResultExt, Snafu};
 use std::sync::Arc;
 use std::{collections::HashMap, fmt::Debug};
-use tracing::trace;
-use url::{ParseError, Url};
+use tracing::{error, info, trace, warn};
 
-const MAX_RETRIES: u32 = 10;
-const INITIAL_BACKOFF: u64 = 1000;
+use crate::{
+    evidence::{ClaimEvidence, ClaimRecord, EvidenceStore},
+    metrics::AuthorityClaimerMetrics,
+    reload::{self, ProviderSettings, ProviderSettingsReceiver},
+};
 
 /// The `DuplicateChecker` checks if a given claim was already submitted to the blockchain.
 #[async_trait]
@@ -30,6 +31,10 @@ pub trait DuplicateChecker: Debug {
         &mut self,
         rollups_claim: &RollupsClaim,
     ) -> Result<bool, Self::Error>;
+
+    /// Forgets the claim, and the later ones of its DApp, after a reorg
+    /// removed it from the chain; they are read from the chain again.
+    fn forget_rollups_claim(&mut self, rollups_claim: &RollupsClaim);
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -38,28 +43,29 @@ pub trait DuplicateChecker: Debug {
 
 #[derive(Debug)]
 pub struct DefaultDuplicateChecker {
-    provider: Arc<Provider<RetryClient<Http>>>,
-    history: History<Provider<RetryClient<Http>>>,
-    claims: HashMap<Address, Vec<Claim>>,
+    provider: Arc<HttpProvider>,
+    history: History<HttpProvider>,
+    history_address: H160,
+    http_client: HttpClient,
+    claims: HashMap<Address, Vec<ClaimRecord>>,
     confirmations: usize,
     next_block_to_read: u64,
+    settings: ProviderSettingsReceiver,
+    chain_id: u64,
+    metrics: AuthorityClaimerMetrics,
+    evidence_store: Option<EvidenceStore>,
 }
 
 #[derive(Debug, Snafu)]
 pub enum DuplicateCheckerError {
     #[snafu(display("failed to call contract"))]
-    ContractError {
-        source: ContractError<ethers::providers::Provider<RetryClient<Http>>>,
-    },
+    ContractError { source: ContractError<HttpProvider> },
 
     #[snafu(display("failed to call provider"))]
     ProviderError {
         source: ethers::providers::ProviderError,
     },
 
-    #[snafu(display("parser error"))]
-    ParseError { source: ParseError },
-
     #[snafu(display(
         "Depth of `{}` higher than latest block `{}`",
         depth,
@@ -80,31 +86,45 @@ pub enum DuplicateCheckerError {
     },
 }
 
+/// Creates the provider and the History contract bound to it.
+fn create_clients(
+    http_client: &HttpClient,
+    settings: &ProviderSettings,
+    history_address: H160,
+) -> (Arc<HttpProvider>, History<HttpProvider>) {
+    let provider = Arc::new(
+        http_client.provider(settings.provider_http_endpoint.inner().clone()),
+    );
+    let history = History::new(history_address, provider.clone());
+    (provider, history)
+}
+
 impl DefaultDuplicateChecker {
     pub async fn new(
-        http_endpoint: String,
+        http_client: HttpClient,
+        mut settings: ProviderSettingsReceiver,
         history_address: Address,
-        confirmations: usize,
         genesis_block: u64,
+        chain_id: u64,
+        metrics: AuthorityClaimerMetrics,
+        evidence_store: Option<EvidenceStore>,
     ) -> Result<Self, DuplicateCheckerError> {
-        let http = Http::new(Url::parse(&http_endpoint).context(ParseSnafu)?);
-        let retry_client = RetryClient::new(
-            http,
-            Box::new(HttpRateLimitRetryPolicy),
-            MAX_RETRIES,
-            INITIAL_BACKOFF,
-        );
-        let provider = Arc::new(Provider::new(retry_client));
-        let history = History::new(
-            H160(history_address.inner().to_owned()),
-            provider.clone(),
-        );
+        let history_address = H160(history_address.inner().to_owned());
+        let current = settings.borrow_and_update().clone();
+        let (provider, history) =
+            create_clients(&http_client, &current, history_address);
         let mut checker = Self {
             provider,
             history,
+            history_address,
+            http_client,
             claims: HashMap::new(),
-            confirmations,
+            confirmations: current.confirmations,
             next_block_to_read: genesis_block,
+            settings,
+            chain_id,
+            metrics,
+            evidence_store,
         };
         checker.update_claims().await?; // to allow failure during instantiation
         Ok(checker)
@@ -123,7 +143,7 @@ impl DuplicateChecker for DefaultDuplicateChecker {
         let expected_first_index = self
             .claims // HashMap => DappAddress to Vec<Claim>
             .get(&rollups_claim.dapp_address) // Gets a Option<Vec<Claim>>
-            .map(|claims| claims.last()) // Maps to Option<Option<Claim>>
+            .map(|claims| claims.last()) // Maps to Option<Option<ClaimRecord>>
             .flatten() // Back to only one Option
             .map(|claim| claim.last_index + 1) // Maps to a number
             .unwrap_or(0); // If None, unwrap to 0
@@ -132,6 +152,7 @@ impl DuplicateChecker for DefaultDuplicateChecker {
             Ok(false)
         } else if rollups_claim.last_index < expected_first_index {
             // This claim is already on the blockchain.
+            self.check_conflict(rollups_claim).await;
             Ok(true)
         } else {
             // This claim is not on blockchain, but it isn't the one blockchain expects.
@@ -143,10 +164,55 @@ impl DuplicateChecker for DefaultDuplicateChecker {
             })
         }
     }
+
+    fn forget_rollups_claim(&mut self, rollups_claim: &RollupsClaim) {
+        let Some(claims) = self.claims.get_mut(&rollups_claim.dapp_address)
+        else {
+            return;
+        };
+        let Some(position) = claims
+            .iter()
+            .position(|claim| claim.last_index >= rollups_claim.first_index)
+        else {
+            return;
+        };
+        let forgotten = claims.split_off(position);
+        // Reading the events again from the first forgotten claim; the
+        // claims of other DApps read again are skipped by `append_claims`
+        if let Some(block) = forgotten
+            .iter()
+            .filter_map(|claim| claim.block_number)
+            .min()
+        {
+            self.next_block_to_read = self.next_block_to_read.min(block);
+        }
+        info!(
+            "Forgot {} claims of DApp {:?} dropped by a reorg",
+            forgotten.len(),
+            rollups_claim.dapp_address
+        );
+    }
 }
 
 impl DefaultDuplicateChecker {
+    /// Swaps the clients if the provider settings were reloaded.
+    /// The claims cache is kept, since it only depends on the chain.
+    fn reload_clients(&mut self) {
+        if let Some(settings) = reload::changed(&mut self.settings) {
+            info!("Swapping the duplicate checker clients");
+            let (provider, history) = create_clients(
+                &self.http_client,
+                &settings,
+                self.history_address,
+            );
+            self.provider = provider;
+            self.history = history;
+            self.confirmations = settings.confirmations;
+        }
+    }
+
     async fn update_claims(&mut self) -> Result<(), DuplicateCheckerError> {
+        self.reload_clients();
         let depth = self.confirmations as u64;
 
         let latest = self
@@ -168,16 +234,25 @@ impl DefaultDuplicateChecker {
             return Ok(());
         }
 
-        let new_claims: Vec<(Address, Claim)> = self
+        let new_claims: Vec<(Address, ClaimRecord)> = self
             .history
             .new_claim_to_history_filter()
             .from_block(self.next_block_to_read)
             .to_block(latest)
-            .query()
+            .query_with_meta()
             .await
             .context(ContractSnafu)?
             .into_iter()
-            .map(|e| (Address::new(e.dapp.into()), e.claim))
+            .map(|(e, meta)| {
+                let claim = ClaimRecord {
+                    epoch_hash: H256(e.claim.epoch_hash),
+                    first_index: e.claim.first_index,
+                    last_index: e.claim.last_index,
+                    tx_hash: Some(meta.transaction_hash),
+                    block_number: Some(meta.block_number.as_u64()),
+                };
+                (Address::new(e.dapp.into()), claim)
+            })
             .collect();
         trace!(
             "read new claims {:?} from block {} to {}",
@@ -192,15 +267,21 @@ impl DefaultDuplicateChecker {
         Ok(())
     }
 
-    // Appends new claims to the [Address => Vec<Claim>] hashmap cache.
-    fn append_claims(&mut self, new_claims: Vec<(Address, Claim)>) {
+    // Appends new claims to the [Address => Vec<ClaimRecord>] hashmap cache.
+    fn append_claims(&mut self, new_claims: Vec<(Address, ClaimRecord)>) {
         if new_claims.is_empty() {
             return;
         }
         for (dapp_address, new_claim) in new_claims {
             match self.claims.get_mut(&dapp_address) {
                 Some(old_claims) => {
-                    old_claims.push(new_claim);
+                    // Claims read again after a reorg are already cached
+                    let is_new = old_claims.last().map_or(true, |claim| {
+                        new_claim.first_index > claim.last_index
+                    });
+                    if is_new {
+                        old_claims.push(new_claim);
+                    }
                 }
                 None => {
                     self.claims.insert(dapp_address, vec![new_claim]);
@@ -208,4 +289,77 @@ impl DefaultDuplicateChecker {
             }
         }
     }
+
+    /// Raises an alert if the claim on chain for the epoch of an
+    /// already-claimed `rollups_claim` differs from it, along with the
+    /// evidence bundle for triage.
+    async fn check_conflict(&self, rollups_claim: &RollupsClaim) {
+        let Some(on_chain_claim) = self
+            .claims
+            .get(&rollups_claim.dapp_address)
+            .and_then(|claims| {
+                claims.iter().find(|claim| {
+                    claim.first_index <= rollups_claim.first_index
+                        && rollups_claim.first_index <= claim.last_index
+                })
+            })
+            .cloned()
+        else {
+            return;
+        };
+        let local_claim = ClaimRecord {
+            epoch_hash: H256(rollups_claim.epoch_hash.inner().to_owned()),
+            first_index: rollups_claim.first_index,
+            last_index: rollups_claim.last_index,
+            tx_hash: None,
+            block_number: None,
+        };
+        if on_chain_claim.epoch_hash == local_claim.epoch_hash
+            && on_chain_claim.first_index == local_claim.first_index
+            && on_chain_claim.last_index == local_claim.last_index
+        {
+            return;
+        }
+
+        let dapp_address = H160(rollups_claim.dapp_address.inner().to_owned());
+        let machine_hash =
+            CartesiDApp::new(dapp_address, self.provider.clone())
+                .get_template_hash()
+                .call()
+                .await
+                .map(H256)
+                .map_err(|e| {
+                    warn!("Failed to read the DApp template hash: {}", e)
+                })
+                .ok();
+        let evidence = ClaimEvidence::new(
+            self.chain_id,
+            dapp_address,
+            rollups_claim.epoch_index,
+            machine_hash,
+            local_claim,
+            on_chain_claim,
+        );
+
+        self.metrics
+            .conflicting_claims
+            .get_or_create(&DAppMetadata {
+                chain_id: self.chain_id,
+                dapp_address: rollups_claim.dapp_address.clone(),
+            })
+            .inc();
+        match self.evidence_store.as_ref().map(|store| store.store(&evidence))
+        {
+            Some(Ok(path)) => error!(
+                "Conflicting claim detected; evidence stored at `{}`: {:?}",
+                path.display(),
+                evidence
+            ),
+            Some(Err(e)) => error!(
+                "Conflicting claim detected; failed to store evidence ({}): {:?}",
+                e, evidence
+            ),
+            None => error!("Conflicting claim detected: {:?}", evidence),
+        }
+    }
 }
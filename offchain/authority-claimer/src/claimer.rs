@@ -2,13 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use async_trait::async_trait;
+use scheduler::Ticker;
 use snafu::ResultExt;
-use std::fmt::Debug;
-use tracing::{info, trace};
+use std::{fmt::Debug, time::SystemTime};
+use tokio::time;
+use tracing::{info, trace, warn};
 
 use crate::{
-    checker::DuplicateChecker, listener::BrokerListener,
-    sender::TransactionSender,
+    blackout::Blackouts, checker::DuplicateChecker, failover::ClaimLease,
+    listener::BrokerListener, preflight::Preflight, sender::TransactionSender,
 };
 
 /// The `Claimer` starts an event loop that waits for claim messages
@@ -18,6 +20,20 @@ use crate::{
 /// It uses three injected traits, `BrokerListener`, `DuplicateChecker`, and
 /// `TransactionSender`, to, respectivelly, listen for messages, check for
 /// duplicated claims, and send claims to the blockchain.
+///
+/// Between claims, at the ticks of the reorg check, it asks the
+/// `TransactionSender` for the sent claims that a reorg dropped from the
+/// chain, and sends them again.
+///
+/// During the operator's blackout windows, the claims are deferred instead
+/// of sent, and sent once the blackout ends.
+///
+/// Before a claim is sent, it runs through the `Preflight` policies. A claim
+/// that fails any of them is held, along with the claims after it, and they
+/// are checked again on the next reorg check.
+///
+/// In a failover group, the claims are only sent while the node holds the
+/// claim lease, and the claimer stops as soon as it doesn't.
 #[async_trait]
 pub trait Claimer: Sized + Debug {
     type Error: snafu::Error + 'static;
@@ -39,6 +55,9 @@ pub enum ClaimerError<
 
     #[snafu(display("transaction sender error"))]
     TransactionSenderError { source: T::Error },
+
+    #[snafu(display("claim lease is no longer held"))]
+    LeaseNotHeld,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -56,6 +75,10 @@ pub struct DefaultClaimer<
     broker_listener: B,
     duplicate_checker: D,
     transaction_sender: T,
+    reorg_check: Ticker,
+    blackouts: Blackouts,
+    preflight: Preflight,
+    claim_lease: Option<ClaimLease>,
 }
 
 impl<B: BrokerListener, D: DuplicateChecker, T: TransactionSender>
@@ -65,11 +88,19 @@ impl<B: BrokerListener, D: DuplicateChecker, T: TransactionSender>
         broker_listener: B,
         duplicate_checker: D,
         transaction_sender: T,
+        reorg_check: Ticker,
+        blackouts: Blackouts,
+        preflight: Preflight,
+        claim_lease: Option<ClaimLease>,
     ) -> Self {
         Self {
             broker_listener,
             duplicate_checker,
             transaction_sender,
+            reorg_check,
+            blackouts,
+            preflight,
+            claim_lease,
         }
     }
 }
@@ -86,29 +117,335 @@ where
     async fn start(mut self) -> Result<(), Self::Error> {
         trace!("Starting the authority claimer loop");
         loop {
-            let rollups_claim = self
-                .broker_listener
-                .listen()
-                .await
-                .context(BrokerListenerSnafu)?;
-            trace!("Got a claim from the broker: {:?}", rollups_claim);
+            let blackout_end = self.blackouts.active_until(SystemTime::now());
 
-            let is_duplicated_rollups_claim = self
-                .duplicate_checker
-                .is_duplicated_rollups_claim(&rollups_claim)
-                .await
-                .context(DuplicatedClaimSnafu)?;
-            if is_duplicated_rollups_claim {
-                trace!("It was a duplicated claim");
+            // Waiting for a claim can be cancelled, since the listener only
+            // moves to the next claim when it returns one
+            let rollups_claims = tokio::select! {
+                rollups_claim = self.broker_listener.listen() => {
+                    let rollups_claim =
+                        rollups_claim.context(BrokerListenerSnafu)?;
+                    trace!("Got a claim from the broker: {:?}", rollups_claim);
+                    vec![rollups_claim]
+                }
+                _ = self.reorg_check.tick() => {
+                    let (transaction_sender, mut rollups_claims) = self
+                        .transaction_sender
+                        .dropped_rollups_claims()
+                        .await
+                        .context(TransactionSenderSnafu)?;
+                    self.transaction_sender = transaction_sender;
+                    for rollups_claim in &rollups_claims {
+                        warn!(
+                            "Claim {:?} was dropped by a reorg; sending it again",
+                            rollups_claim
+                        );
+                        self.duplicate_checker
+                            .forget_rollups_claim(rollups_claim);
+                    }
+                    rollups_claims.extend(self.preflight.take_held());
+                    rollups_claims
+                }
+                _ = sleep_until(blackout_end), if blackout_end.is_some() => {
+                    info!("Blackout ended");
+                    vec![]
+                }
+            };
+
+            if self.blackouts.active_until(SystemTime::now()).is_some() {
+                for rollups_claim in rollups_claims {
+                    info!(
+                        "Deferring claim {:?} until the blackout ends",
+                        rollups_claim
+                    );
+                    self.blackouts.defer(rollups_claim);
+                }
                 continue;
             }
 
-            info!("Sending a new rollups claim");
-            self.transaction_sender = self
-                .transaction_sender
-                .send_rollups_claim_transaction(rollups_claim)
+            // Outside a blackout, the deferred claims go before any other,
+            // even one that arrived as the blackout ended
+            let mut deferred = self.blackouts.take_deferred();
+            if !deferred.is_empty() {
+                info!("Sending {} deferred claims", deferred.len());
+            }
+            deferred.extend(rollups_claims);
+            let rollups_claims = deferred;
+
+            for rollups_claim in rollups_claims {
+                let is_duplicated_rollups_claim = self
+                    .duplicate_checker
+                    .is_duplicated_rollups_claim(&rollups_claim)
+                    .await
+                    .context(DuplicatedClaimSnafu)?;
+                if is_duplicated_rollups_claim {
+                    trace!("It was a duplicated claim");
+                    continue;
+                }
+
+                if let Some(claim_lease) = &self.claim_lease {
+                    if !claim_lease.is_held() {
+                        return Err(ClaimerError::LeaseNotHeld);
+                    }
+                }
+
+                // Claims are sent in order, so the ones after a held claim
+                // are held too
+                if self.preflight.has_held()
+                    || self.preflight.check(&rollups_claim).await.blocked
+                {
+                    warn!(
+                        "Holding claim {:?} until it passes the pre-flight policies",
+                        rollups_claim
+                    );
+                    self.preflight.hold(rollups_claim);
+                    continue;
+                }
+
+                info!("Sending a new rollups claim");
+                self.transaction_sender = self
+                    .transaction_sender
+                    .send_rollups_claim_transaction(rollups_claim)
+                    .await
+                    .context(TransactionSenderSnafu)?
+            }
+
+            // Deferred and held claims are listened again after a restart
+            if !self.blackouts.has_deferred() && !self.preflight.has_held() {
+                self.broker_listener
+                    .acknowledge()
+                    .await
+                    .context(BrokerListenerSnafu)?;
+            }
+        }
+    }
+}
+
+async fn sleep_until(time: Option<SystemTime>) {
+    let remaining = time
+        .and_then(|time| time.duration_since(SystemTime::now()).ok())
+        .unwrap_or_default();
+    time::sleep(remaining).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reload::ProviderSettings;
+    use clap::Parser;
+    use eth_tx_manager::Priority;
+    use http_provider::{HttpClient, HttpClientCLIConfig};
+    use redacted::{RedactedUrl, Url};
+    use rollups_events::RollupsClaim;
+    use scheduler::{format_utc_time, JobConfig, Scheduler};
+    use snafu::{OptionExt, Snafu};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::sync::{mpsc, watch};
+
+    #[derive(Debug, Snafu)]
+    enum MockError {
+        NoMoreClaims,
+    }
+
+    /// Listens to the claims the test sends
+    #[derive(Debug)]
+    struct MockListener(mpsc::UnboundedReceiver<RollupsClaim>);
+
+    #[async_trait]
+    impl BrokerListener for MockListener {
+        type Error = MockError;
+
+        async fn listen(&mut self) -> Result<RollupsClaim, MockError> {
+            self.0.recv().await.context(NoMoreClaimsSnafu)
+        }
+    }
+
+    /// Epochs whose claims are on chain, which the test reorgs
+    type Chain = Arc<Mutex<Vec<u64>>>;
+
+    #[derive(Debug)]
+    struct MockChecker {
+        chain: Chain,
+        forgotten: Arc<Mutex<Vec<u64>>>,
+    }
+
+    #[async_trait]
+    impl DuplicateChecker for MockChecker {
+        type Error = MockError;
+
+        async fn is_duplicated_rollups_claim(
+            &mut self,
+            rollups_claim: &RollupsClaim,
+        ) -> Result<bool, MockError> {
+            let chain = self.chain.lock().unwrap();
+            Ok(chain.contains(&rollups_claim.epoch_index))
+        }
+
+        fn forget_rollups_claim(&mut self, rollups_claim: &RollupsClaim) {
+            self.forgotten
+                .lock()
+                .unwrap()
+                .push(rollups_claim.epoch_index);
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockSender {
+        chain: Chain,
+        sent: mpsc::UnboundedSender<RollupsClaim>,
+        dropped: Arc<Mutex<Vec<RollupsClaim>>>,
+    }
+
+    #[async_trait]
+    impl TransactionSender for MockSender {
+        type Error = MockError;
+
+        async fn send_rollups_claim_transaction(
+            self,
+            rollups_claim: RollupsClaim,
+        ) -> Result<Self, MockError> {
+            self.chain.lock().unwrap().push(rollups_claim.epoch_index);
+            self.sent.send(rollups_claim).unwrap();
+            Ok(self)
+        }
+
+        async fn dropped_rollups_claims(
+            self,
+        ) -> Result<(Self, Vec<RollupsClaim>), MockError> {
+            let dropped = std::mem::take(&mut *self.dropped.lock().unwrap());
+            Ok((self, dropped))
+        }
+    }
+
+    fn preflight(blackouts: Blackouts) -> Preflight {
+        let http_client_config =
+            HttpClientCLIConfig::parse_from(["http_client_config"]).into();
+        let (_, settings) = watch::channel(ProviderSettings {
+            provider_http_endpoint: RedactedUrl::new(
+                Url::parse("http://localhost:8545").unwrap(),
+            ),
+            confirmations: 1,
+            priority: Priority::Normal,
+        });
+        Preflight::new(
+            vec![],
+            HttpClient::new(&http_client_config).unwrap(),
+            settings,
+            None,
+            0,
+            blackouts,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn claim(epoch_index: u64) -> RollupsClaim {
+        RollupsClaim {
+            epoch_index,
+            ..Default::default()
+        }
+    }
+
+    /// Claimer running with the mocks, and the ends the test drives it with
+    struct TestClaimer {
+        claims: mpsc::UnboundedSender<RollupsClaim>,
+        sent: mpsc::UnboundedReceiver<RollupsClaim>,
+        chain: Chain,
+        dropped: Arc<Mutex<Vec<RollupsClaim>>>,
+        forgotten: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl TestClaimer {
+        fn start(blackouts: Blackouts) -> Self {
+            let (claims, listened) = mpsc::unbounded_channel();
+            let (sent_tx, sent) = mpsc::unbounded_channel();
+            let chain = Chain::default();
+            let dropped = Arc::new(Mutex::new(vec![]));
+            let forgotten = Arc::new(Mutex::new(vec![]));
+            let reorg_check = Scheduler::default().ticker(
+                "claim_reorg_check",
+                &JobConfig::every(Duration::from_millis(10)),
+            );
+            let claimer = DefaultClaimer::new(
+                MockListener(listened),
+                MockChecker {
+                    chain: chain.clone(),
+                    forgotten: forgotten.clone(),
+                },
+                MockSender {
+                    chain: chain.clone(),
+                    sent: sent_tx,
+                    dropped: dropped.clone(),
+                },
+                reorg_check,
+                blackouts.clone(),
+                preflight(blackouts),
+                None,
+            );
+            tokio::spawn(claimer.start());
+            Self {
+                claims,
+                sent,
+                chain,
+                dropped,
+                forgotten,
+            }
+        }
+
+        async fn next_sent(&mut self) -> RollupsClaim {
+            time::timeout(Duration::from_secs(5), self.sent.recv())
                 .await
-                .context(TransactionSenderSnafu)?
+                .expect("the claimer should send a claim")
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_claims_dropped_by_a_reorg_again() {
+        let mut claimer = TestClaimer::start(Blackouts::default());
+        claimer.claims.send(claim(0)).unwrap();
+        claimer.claims.send(claim(1)).unwrap();
+        assert_eq!(claimer.next_sent().await, claim(0));
+        assert_eq!(claimer.next_sent().await, claim(1));
+
+        // A reorg drops the claim of epoch 1 from the chain
+        claimer.chain.lock().unwrap().retain(|epoch| *epoch != 1);
+        claimer.dropped.lock().unwrap().push(claim(1));
+        assert_eq!(claimer.next_sent().await, claim(1));
+        assert_eq!(*claimer.forgotten.lock().unwrap(), vec![1]);
+
+        // Claims already on chain aren't sent again
+        claimer.claims.send(claim(1)).unwrap();
+        claimer.claims.send(claim(2)).unwrap();
+        assert_eq!(claimer.next_sent().await, claim(2));
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_deferred_claims_before_the_ones_after_a_blackout() {
+        let window = format!("{} for 2s", format_utc_time(SystemTime::now()));
+        let blackouts = Blackouts::new(vec![window.parse().unwrap()]);
+        let mut claimer = TestClaimer::start(blackouts.clone());
+        claimer.claims.send(claim(0)).unwrap();
+        while !blackouts.has_deferred() {
+            time::sleep(Duration::from_millis(10)).await;
         }
+
+        // The claim arrives right after the blackout ends
+        let end = blackouts.active_until(SystemTime::now()).unwrap();
+        sleep_until(Some(end)).await;
+        claimer.claims.send(claim(1)).unwrap();
+        assert_eq!(claimer.next_sent().await, claim(0));
+        assert_eq!(claimer.next_sent().await, claim(1));
+
+        // A claim deferred as the blackout ended is sent before the next one
+        blackouts.defer(claim(2));
+        claimer.claims.send(claim(3)).unwrap();
+        assert_eq!(claimer.next_sent().await, claim(2));
+        assert_eq!(claimer.next_sent().await, claim(3));
+        assert!(!blackouts.has_deferred());
     }
 }
@@ -6,12 +6,17 @@ use eth_tx_manager::{
     config::{TxEnvCLIConfig as TxManagerCLIConfig, TxManagerConfig},
     Priority,
 };
+use ethers::types::{H160, U256};
+use http_provider::{HttpClientCLIConfig, HttpClientConfig};
 use log::{LogConfig, LogEnvCliConfig};
 use redacted::Redacted;
-use rollups_events::{BrokerCLIConfig, BrokerConfig};
+use rollups_data::RepositoryCLIConfig;
+use rollups_events::{BrokerCLIConfig, BrokerConfig, Labels};
+use runtimes::RuntimeCLIConfig;
 use rusoto_core::Region;
+use scheduler::{SchedulerCLIConfig, Window};
 use snafu::ResultExt;
-use std::{fs, str::FromStr};
+use std::{fs, path::PathBuf, str::FromStr, time::Duration};
 
 use crate::config::{
     error::{
@@ -21,6 +26,14 @@ use crate::config::{
     },
     AuthorityClaimerConfig, ContractsConfig, TxSigningConfig,
 };
+use crate::{
+    calendar::EpochForecast,
+    failover::FailoverCLIConfig,
+    guard::ChainGuardCLIConfig,
+    ledger::FeeSchedule,
+    preflight::{GasBudget, PreflightPolicy},
+    remote::RemoteEpochHashesCLIConfig,
+};
 
 use super::contracts::ContractsCLIConfig;
 
@@ -47,9 +60,138 @@ pub(crate) struct AuthorityClaimerCLI {
     #[command(flatten)]
     pub contracts_config: ContractsCLIConfig,
 
+    #[command(flatten)]
+    pub http_client_config: HttpClientCLIConfig,
+
+    #[command(flatten)]
+    pub chain_guard_config: ChainGuardCLIConfig,
+
+    #[command(flatten)]
+    pub runtime_config: RuntimeCLIConfig,
+
+    #[command(flatten)]
+    pub remote_epoch_hashes_config: RemoteEpochHashesCLIConfig,
+
+    #[command(flatten)]
+    pub failover_config: FailoverCLIConfig,
+
+    #[command(flatten)]
+    pub scheduler_config: SchedulerCLIConfig,
+
     /// Genesis block for reading blockchain events
     #[arg(long, env, default_value_t = 1)]
     pub genesis_block: u64,
+
+    /// JSON file with provider settings that are re-read on SIGHUP, or on
+    /// Ctrl-Break on Windows
+    /// (`provider_http_endpoint`, `default_confirmations` and `priority`)
+    #[arg(long, env)]
+    pub reload_config_path: Option<String>,
+
+    /// Directory where the evidence of conflicting claims is stored, as one
+    /// JSON file per epoch. Without it, the evidence is only logged
+    #[arg(long, env)]
+    pub claim_evidence_dir: Option<String>,
+
+    /// Record the gas spent and the fees earned by each claimed epoch in the
+    /// database of the `postgres_*` options, served at `/ledger` and
+    /// `/ledger.csv`. Without it, no ledger is kept
+    #[arg(long, env, default_value_t = false)]
+    pub claim_ledger: bool,
+
+    #[command(flatten)]
+    pub repository_config: RepositoryCLIConfig,
+
+    /// Fee in wei earned by the validator for each claimed epoch
+    #[arg(long, env, default_value = "0", value_parser = parse_wei)]
+    pub claim_fee_per_epoch: U256,
+
+    /// Fee in wei earned by the validator for each input of a claimed epoch
+    #[arg(long, env, default_value = "0", value_parser = parse_wei)]
+    pub claim_fee_per_input: U256,
+
+    /// Depth after which a sent claim is no longer watched for reorgs
+    #[arg(long, env, default_value_t = 64)]
+    pub claim_finality_depth: u64,
+
+    /// How often the sent claims are checked for reorgs that dropped them;
+    /// dropped claims are sent again
+    #[arg(
+        long,
+        env,
+        default_value = "1min",
+        value_parser = humane::parse_duration
+    )]
+    pub claim_reorg_check_interval: Duration,
+
+    /// Semicolon-separated windows when no claims are sent, each one as
+    /// `<start> for <duration>`, where the start is a UTC time
+    /// (`2024-03-01T02:00Z for 2h`) or a cron expression (`0 2 * * 0 for
+    /// 90m`). The claims that arrive in a window are deferred, served at
+    /// `/blackout`, and sent once it ends
+    #[arg(long, env, value_delimiter = ';')]
+    pub claim_blackout_windows: Vec<Window>,
+
+    /// Semicolon-separated epochs of the tracked DApps, each one as
+    /// `<dapp address>=<first epoch start> every <epoch duration>`
+    /// (`0x70ac...=2024-03-01T00:00Z every 7d`), from which the claim
+    /// windows in the calendar of duties are forecast, along with the
+    /// earnings served at `/ledger/forecast` when there is a claim ledger
+    #[arg(long, env, value_delimiter = ';')]
+    pub claim_epoch_forecasts: Vec<EpochForecast>,
+
+    /// Comma-separated policies every claim must pass before it is sent,
+    /// in the order they run. A claim that fails any of them is held, along
+    /// with the claims after it, until it passes them on a later reorg
+    /// check; the verdicts are served at `/preflight`
+    #[arg(
+        long,
+        env,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "invariants,divergence,blackout"
+    )]
+    pub claim_preflight_policies: Vec<PreflightPolicy>,
+
+    /// Most wei the claim transactions may spend on gas over each
+    /// `claim_gas_budget_period`, as recorded in the claim ledger, checked by
+    /// the `budget` pre-flight policy
+    #[arg(long, env, value_parser = parse_wei)]
+    pub claim_gas_budget: Option<U256>,
+
+    /// Period of the gas budget of the claims
+    #[arg(
+        long,
+        env,
+        default_value = "1d",
+        value_parser = humane::parse_duration
+    )]
+    pub claim_gas_budget_period: Duration,
+
+    /// How long after the end of an epoch its claim is expected to be sent,
+    /// shown as the length of the claim windows in the calendar of duties
+    #[arg(
+        long,
+        env,
+        default_value = "1h",
+        value_parser = humane::parse_duration
+    )]
+    pub claim_window_duration: Duration,
+
+    /// How far ahead the calendar of duties, served at `/duties` and
+    /// `/duties.ics`, lists the claim windows and the blackouts
+    #[arg(
+        long,
+        env,
+        default_value = "30d",
+        value_parser = humane::parse_duration
+    )]
+    pub duty_calendar_horizon: Duration,
+
+    /// Comma-separated `key=value` labels attached to this validator's
+    /// metrics (e.g. `environment=production,owner_team=infra`)
+    #[arg(long, env, default_value = "")]
+    pub validator_labels: Labels,
 }
 
 impl TryFrom<AuthorityClaimerCLI> for AuthorityClaimerConfig {
@@ -79,7 +221,40 @@ impl TryFrom<AuthorityClaimerCLI> for AuthorityClaimerConfig {
             broker_config,
             log_config,
             contracts_config,
+            http_client_config: HttpClientConfig::from(
+                cli_config.http_client_config,
+            ),
+            chain_guard_config: cli_config.chain_guard_config.into(),
+            runtime_config: cli_config.runtime_config.into(),
+            scheduler_config: cli_config.scheduler_config.into(),
+            remote_epoch_hashes_config: cli_config
+                .remote_epoch_hashes_config
+                .into_config(),
+            failover_config: cli_config.failover_config.into_config(),
             genesis_block: cli_config.genesis_block,
+            reload_config_path: cli_config.reload_config_path,
+            claim_evidence_dir: cli_config.claim_evidence_dir,
+            claim_ledger_config: cli_config
+                .claim_ledger
+                .then(|| cli_config.repository_config.into()),
+            claim_fees: FeeSchedule {
+                per_epoch: cli_config.claim_fee_per_epoch,
+                per_input: cli_config.claim_fee_per_input,
+            },
+            claim_finality_depth: cli_config.claim_finality_depth,
+            claim_reorg_check_interval: cli_config.claim_reorg_check_interval,
+            claim_blackout_windows: cli_config.claim_blackout_windows,
+            claim_epoch_forecasts: cli_config.claim_epoch_forecasts,
+            claim_preflight_policies: cli_config.claim_preflight_policies,
+            claim_gas_budget: cli_config.claim_gas_budget.map(|amount| {
+                GasBudget {
+                    amount,
+                    period: cli_config.claim_gas_budget_period,
+                }
+            }),
+            claim_window_duration: cli_config.claim_window_duration,
+            duty_calendar_horizon: cli_config.duty_calendar_horizon,
+            validator_labels: cli_config.validator_labels,
         })
     }
 }
@@ -118,6 +293,27 @@ pub(crate) struct TxSigningCLIConfig {
     /// AWS KMS signer region
     #[arg(long, env)]
     tx_signing_aws_kms_region: Option<String>,
+
+    /// Address of a key kept off the node, such as on an air-gapped machine,
+    /// whose signatures are given through `tx_signing_offline_dir`; used
+    /// when no other signer is set
+    #[arg(long, env, requires = "tx_signing_offline_dir")]
+    tx_signing_offline_address: Option<H160>,
+
+    /// Directory where the unsigned transactions are queued for the offline
+    /// key, and where their signatures are taken from
+    #[arg(long, env)]
+    tx_signing_offline_dir: Option<PathBuf>,
+
+    /// How often the directory is checked for the signature of a queued
+    /// transaction, such as `5s`
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration
+    )]
+    tx_signing_offline_poll_interval: Duration,
 }
 
 impl TryFrom<TxSigningCLIConfig> for TxSigningConfig {
@@ -154,7 +350,19 @@ impl TryFrom<TxSigningCLIConfig> for TxSigningConfig {
         } else {
             match (cli.tx_signing_aws_kms_key_id, cli.tx_signing_aws_kms_region)
             {
-                (None, _) => Err(TxSigningConfigError::AuthConfigMissing),
+                (None, _) => match (
+                    cli.tx_signing_offline_address,
+                    cli.tx_signing_offline_dir,
+                ) {
+                    (Some(address), Some(dir)) => {
+                        Ok(TxSigningConfig::Offline {
+                            address,
+                            dir,
+                            poll_interval: cli.tx_signing_offline_poll_interval,
+                        })
+                    }
+                    _ => Err(TxSigningConfigError::AuthConfigMissing),
+                },
                 (Some(_), None) => Err(TxSigningConfigError::MissingRegion),
                 (Some(key_id), Some(region)) => {
                     let region = Region::from_str(&region)
@@ -165,3 +373,13 @@ impl TryFrom<TxSigningCLIConfig> for TxSigningConfig {
         }
     }
 }
+
+// ------------------------------------------------------------------------------------------------
+// Auxiliary
+// ------------------------------------------------------------------------------------------------
+
+/// Parses a decimal amount of wei
+fn parse_wei(value: &str) -> Result<U256, String> {
+    U256::from_dec_str(value.trim())
+        .map_err(|e| format!("invalid amount of wei `{}`: {:?}", value, e))
+}
@@ -12,6 +12,8 @@ use types::blockchain_config::RollupsDeployment;
 pub struct ContractsConfig {
     pub history_address: Address,
     pub authority_address: Address,
+    /// Only needed by the `finality` pre-flight policy
+    pub input_box_address: Option<Address>,
 }
 
 #[derive(Debug, Parser)]
@@ -25,6 +27,10 @@ pub struct ContractsCLIConfig {
     #[arg(long, env)]
     pub authority_address: Option<String>,
 
+    /// Input box contract address
+    #[arg(long, env)]
+    pub input_box_address: Option<String>,
+
     /// Path to file with deployment json of the rollups
     #[arg(long, env)]
     pub rollups_deployment_file: Option<PathBuf>,
@@ -43,6 +49,10 @@ impl TryFrom<ContractsCLIConfig> for ContractsConfig {
             .authority_address
             .map(deserialize::<Address>)
             .transpose()?;
+        let mut input_box_address = cli
+            .input_box_address
+            .map(deserialize::<Address>)
+            .transpose()?;
 
         // read file and replace values if they are not set
         if let Some(file) = cli
@@ -60,6 +70,11 @@ impl TryFrom<ContractsCLIConfig> for ContractsConfig {
                 .authority
                 .map(|c| c.address)
                 .flatten());
+            input_box_address = input_box_address.or(file
+                .contracts
+                .input_box
+                .map(|c| c.address)
+                .flatten());
         }
 
         Ok(ContractsConfig {
@@ -67,6 +82,7 @@ impl TryFrom<ContractsCLIConfig> for ContractsConfig {
                 .ok_or(ContractsConfigError::MissingHistoryContractConfig)?,
             authority_address: authority_address
                 .ok_or(ContractsConfigError::MissingAuthorityContractConfig)?,
+            input_box_address,
         })
     }
 }
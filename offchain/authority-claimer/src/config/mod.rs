@@ -10,11 +10,26 @@ pub use error::{AuthorityClaimerConfigError, TxSigningConfigError};
 
 use cli::AuthorityClaimerCLI;
 use eth_tx_manager::{config::TxManagerConfig, Priority};
+use ethers::types::Address;
+use http_provider::HttpClientConfig;
 use http_server::HttpServerConfig;
 use log::LogConfig;
 use redacted::Redacted;
-use rollups_events::BrokerConfig;
+use rollups_data::RepositoryConfig;
+use rollups_events::{BrokerConfig, Labels};
+use runtimes::RuntimeConfig;
 use rusoto_core::Region;
+use scheduler::{SchedulerConfig, Window};
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    calendar::EpochForecast,
+    failover::FailoverConfig,
+    guard::ChainGuardConfig,
+    ledger::FeeSchedule,
+    preflight::{GasBudget, PreflightPolicy},
+    remote::RemoteEpochHashesConfig,
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -30,7 +45,26 @@ pub struct AuthorityClaimerConfig {
     pub broker_config: BrokerConfig,
     pub log_config: LogConfig,
     pub contracts_config: ContractsConfig,
+    pub http_client_config: HttpClientConfig,
+    pub chain_guard_config: ChainGuardConfig,
+    pub runtime_config: RuntimeConfig,
+    pub scheduler_config: SchedulerConfig,
+    pub remote_epoch_hashes_config: Option<RemoteEpochHashesConfig>,
+    pub failover_config: Option<FailoverConfig>,
     pub genesis_block: u64,
+    pub reload_config_path: Option<String>,
+    pub claim_evidence_dir: Option<String>,
+    pub claim_ledger_config: Option<RepositoryConfig>,
+    pub claim_fees: FeeSchedule,
+    pub claim_finality_depth: u64,
+    pub claim_reorg_check_interval: Duration,
+    pub claim_blackout_windows: Vec<Window>,
+    pub claim_epoch_forecasts: Vec<EpochForecast>,
+    pub claim_preflight_policies: Vec<PreflightPolicy>,
+    pub claim_gas_budget: Option<GasBudget>,
+    pub claim_window_duration: Duration,
+    pub duty_calendar_horizon: Duration,
+    pub validator_labels: Labels,
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +82,13 @@ pub enum TxSigningConfig {
         key_id: String,
         region: Region,
     },
+
+    /// Key kept off the node, which signs the queued transactions
+    Offline {
+        address: Address,
+        dir: PathBuf,
+        poll_interval: Duration,
+    },
 }
 
 impl Config {
@@ -0,0 +1,232 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use ethers::types::{H160, H256};
+use serde::Serialize;
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Snafu)]
+pub enum EvidenceError {
+    #[snafu(display("failed to create evidence directory `{}`", path.display()))]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to write evidence file `{}`", path.display()))]
+    WriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to read evidence directory `{}`", path.display()))]
+    ReadDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// A claim as seen by one of the parties of a conflict
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClaimRecord {
+    pub epoch_hash: H256,
+    pub first_index: u128,
+    pub last_index: u128,
+    /// Transaction that submitted the claim, absent for a local claim that
+    /// wasn't submitted
+    pub tx_hash: Option<H256>,
+    pub block_number: Option<u64>,
+}
+
+/// Facts about a conflicting claim, assembled when the conflict is detected
+/// so triage doesn't start by collecting them by hand.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClaimEvidence {
+    pub chain_id: u64,
+    pub dapp_address: H160,
+    pub epoch_index: u64,
+    /// Input range of the epoch, as computed by this node
+    pub first_input_index: u128,
+    pub last_input_index: u128,
+    /// Template hash of the DApp, absent if it couldn't be read
+    pub machine_hash: Option<H256>,
+    pub local_claim: ClaimRecord,
+    pub on_chain_claim: ClaimRecord,
+    /// Seconds since the Unix epoch
+    pub detected_at: u64,
+}
+
+impl ClaimEvidence {
+    pub fn new(
+        chain_id: u64,
+        dapp_address: H160,
+        epoch_index: u64,
+        machine_hash: Option<H256>,
+        local_claim: ClaimRecord,
+        on_chain_claim: ClaimRecord,
+    ) -> Self {
+        let detected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Self {
+            chain_id,
+            dapp_address,
+            epoch_index,
+            first_input_index: local_claim.first_index,
+            last_input_index: local_claim.last_index,
+            machine_hash,
+            local_claim,
+            on_chain_claim,
+            detected_at,
+        }
+    }
+}
+
+/// Keeps the evidence bundles as JSON files, one per conflicting epoch
+#[derive(Clone, Debug)]
+pub struct EvidenceStore {
+    dir: PathBuf,
+}
+
+impl EvidenceStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Writes the bundle and returns its path; the bundle of an epoch that
+    /// conflicts again is overwritten with the latest facts.
+    pub fn store(
+        &self,
+        evidence: &ClaimEvidence,
+    ) -> Result<PathBuf, EvidenceError> {
+        fs::create_dir_all(&self.dir).context(CreateDirSnafu {
+            path: self.dir.clone(),
+        })?;
+        let path = self.dir.join(format!(
+            "{}{}.json",
+            file_prefix(evidence.dapp_address),
+            evidence.epoch_index
+        ));
+        let contents = serde_json::to_vec_pretty(evidence)
+            .expect("evidence should serialize to JSON");
+        fs::write(&path, contents)
+            .context(WriteFileSnafu { path: path.clone() })?;
+        Ok(path)
+    }
+
+    /// Bundles of the conflicts of the DApp still in the store, which the
+    /// operator removes once they are triaged
+    pub fn conflicts(
+        &self,
+        dapp_address: H160,
+    ) -> Result<Vec<PathBuf>, EvidenceError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).context(ReadDirSnafu {
+                    path: self.dir.clone(),
+                })
+            }
+        };
+        let prefix = file_prefix(dapp_address);
+        let mut conflicts = vec![];
+        for entry in entries {
+            let path = entry
+                .context(ReadDirSnafu {
+                    path: self.dir.clone(),
+                })?
+                .path();
+            let is_conflict =
+                path.file_name().and_then(|name| name.to_str()).is_some_and(
+                    |name| name.starts_with(&prefix) && name.ends_with(".json"),
+                );
+            if is_conflict {
+                conflicts.push(path);
+            }
+        }
+        conflicts.sort();
+        Ok(conflicts)
+    }
+}
+
+fn file_prefix(dapp_address: H160) -> String {
+    format!("{:?}-epoch-", dapp_address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(hash: u8, tx_hash: Option<H256>) -> ClaimRecord {
+        ClaimRecord {
+            epoch_hash: H256::repeat_byte(hash),
+            first_index: 10,
+            last_index: 19,
+            tx_hash,
+            block_number: tx_hash.map(|_| 100),
+        }
+    }
+
+    #[test]
+    fn it_stores_the_evidence_as_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EvidenceStore::new(dir.path().join("evidence"));
+        let evidence = ClaimEvidence::new(
+            31337,
+            H160::repeat_byte(0xaa),
+            1,
+            Some(H256::repeat_byte(0xbb)),
+            claim(1, None),
+            claim(2, Some(H256::repeat_byte(0xcc))),
+        );
+
+        let path = store.store(&evidence).unwrap();
+        assert_eq!(
+            path.file_name().unwrap(),
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-epoch-1.json"
+        );
+        let stored: serde_json::Value =
+            serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        assert_eq!(stored["first_input_index"], 10);
+        assert_eq!(stored["last_input_index"], 19);
+        assert_eq!(stored["local_claim"]["tx_hash"], serde_json::Value::Null);
+        assert_eq!(
+            stored["on_chain_claim"]["tx_hash"],
+            format!("{:?}", H256::repeat_byte(0xcc))
+        );
+    }
+
+    #[test]
+    fn it_lists_the_conflicts_of_each_dapp() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EvidenceStore::new(dir.path().join("evidence"));
+        let dapp = H160::repeat_byte(0xaa);
+        assert!(store.conflicts(dapp).unwrap().is_empty());
+
+        for (dapp, epoch_index) in [(dapp, 1), (dapp, 2), (H160::zero(), 1)] {
+            let evidence = ClaimEvidence::new(
+                31337,
+                dapp,
+                epoch_index,
+                None,
+                claim(1, None),
+                claim(2, Some(H256::repeat_byte(0xcc))),
+            );
+            store.store(&evidence).unwrap();
+        }
+        assert_eq!(store.conflicts(dapp).unwrap().len(), 2);
+
+        // Removing a triaged bundle closes its conflict
+        let conflicts = store.conflicts(dapp).unwrap();
+        fs::remove_file(&conflicts[0]).unwrap();
+        assert_eq!(store.conflicts(dapp).unwrap(), conflicts[1..]);
+    }
+}
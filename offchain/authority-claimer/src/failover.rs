@@ -0,0 +1,332 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{extract::State, routing::get, Json, Router};
+use clap::Parser;
+use rollups_events::{Broker, BrokerConfig, BrokerError};
+use scheduler::{JobConfig, Scheduler};
+use serde::Serialize;
+use snafu::{ensure, ResultExt, Snafu};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+use tracing::{info, trace, warn};
+
+#[derive(Debug, Snafu)]
+pub enum FailoverError {
+    #[snafu(display("broker error"))]
+    BrokerError { source: BrokerError },
+
+    #[snafu(display("lost the claim lease `{}`", lease))]
+    LeaseLost { lease: String },
+
+    #[snafu(display("claim lease `{}` expired before it was renewed", lease))]
+    LeaseExpired { lease: String },
+
+    #[snafu(display(
+        "HTTP server stopped while standing by for the claim lease `{}`",
+        lease
+    ))]
+    StoppedStandingBy { lease: String },
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "failover_config")]
+pub struct FailoverCLIConfig {
+    /// Name of the lease in the broker that the nodes of a failover group,
+    /// such as the same validator in different regions, compete for. Only
+    /// the node that holds it sends claims; the others stand by. Without it,
+    /// the claims are always sent
+    #[arg(long, env, requires = "claim_lease_holder")]
+    pub claim_lease_name: Option<String>,
+
+    /// Name of this node in its failover group, such as its region, which
+    /// must be unique within the group
+    #[arg(long, env)]
+    pub claim_lease_holder: Option<String>,
+
+    /// Time to live of the claim lease, which is renewed every third of it.
+    /// A node standing by takes over at most this long after the node that
+    /// held the lease stopped
+    #[arg(
+        long,
+        env,
+        default_value = "15s",
+        value_parser = humane::parse_duration
+    )]
+    pub claim_lease_ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    pub lease: String,
+    pub holder: String,
+    pub ttl: Duration,
+}
+
+impl FailoverCLIConfig {
+    /// The config of the failover group, if a lease is set
+    pub fn into_config(self) -> Option<FailoverConfig> {
+        let lease = self.claim_lease_name?;
+        Some(FailoverConfig {
+            lease,
+            holder: self
+                .claim_lease_holder
+                .expect("the lease holder is required along with its name"),
+            ttl: self.claim_lease_ttl,
+        })
+    }
+}
+
+/// Role of the node in its failover group
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailoverRole {
+    Standby,
+    Active,
+}
+
+/// State of the claim lease, as served at `/failover`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FailoverStatus {
+    pub lease: String,
+    pub holder: String,
+    pub role: FailoverRole,
+    pub fencing_token: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct HeldLease {
+    fencing_token: u64,
+    /// When the lease expires in the broker, at the latest, measured from
+    /// before the request that renewed it was sent
+    valid_until: Instant,
+}
+
+/// Lease that makes the claimer of one node of a failover group send the
+/// claims, while the others consume the same broker and stand by.
+///
+/// The chain can't check fencing tokens, so the claimer checks that the
+/// lease is still held before sending each claim, and the transactions are
+/// only signed, resubmissions included, while the fencing token it was
+/// acquired with is still held. The claimer stops as soon as it can't renew
+/// the lease or its fencing token changes. The node that takes over resumes
+/// from the claims acknowledged in the broker, skipping the ones that are
+/// already on the chain.
+#[derive(Clone, Debug)]
+pub struct ClaimLease {
+    broker: Broker,
+    config: FailoverConfig,
+    held: Arc<Mutex<Option<HeldLease>>>,
+}
+
+impl ClaimLease {
+    pub async fn new(
+        broker_config: BrokerConfig,
+        config: FailoverConfig,
+    ) -> Result<Self, FailoverError> {
+        let broker = Broker::new(broker_config).await.context(BrokerSnafu)?;
+        Ok(Self {
+            broker,
+            config,
+            held: Default::default(),
+        })
+    }
+
+    /// Waits until this node acquires the lease, standing by while another
+    /// node holds it, and returns the fence of the claims sent under it
+    pub async fn acquire(&self) -> ClaimFence {
+        let mut broker = self.broker.clone();
+        info!(
+            "Standing by until `{}` acquires the claim lease `{}`",
+            self.config.holder, self.config.lease
+        );
+        loop {
+            let sent_at = Instant::now();
+            match broker
+                .acquire_lease(
+                    &self.config.lease,
+                    &self.config.holder,
+                    self.config.ttl,
+                )
+                .await
+            {
+                Ok(Some(fencing_token)) => {
+                    info!(
+                        "Acquired the claim lease `{}` with fencing token {}",
+                        self.config.lease, fencing_token
+                    );
+                    self.hold(fencing_token, sent_at);
+                    return ClaimFence {
+                        lease: self.clone(),
+                        fencing_token,
+                    };
+                }
+                Ok(None) => trace!("Another node holds the claim lease"),
+                Err(e) => warn!("Failed to acquire the claim lease: {}", e),
+            }
+            time::sleep(self.renew_interval()).await;
+        }
+    }
+
+    /// Keeps renewing the lease at the ticks of the `scheduler`, and fails
+    /// once it is lost, so the claimer stops and stands by again when
+    /// restarted
+    pub async fn keep(self, scheduler: Scheduler) -> Result<(), FailoverError> {
+        let mut broker = self.broker.clone();
+        let mut renewal = scheduler.ticker(
+            "claim_lease_renewal",
+            &JobConfig::every(self.renew_interval()),
+        );
+        loop {
+            renewal.tick().await;
+            let Some(held) = *self.held.lock().unwrap() else {
+                return self.lost();
+            };
+            let sent_at = Instant::now();
+            match broker
+                .acquire_lease(
+                    &self.config.lease,
+                    &self.config.holder,
+                    self.config.ttl,
+                )
+                .await
+            {
+                Ok(Some(fencing_token))
+                    if fencing_token == held.fencing_token =>
+                {
+                    self.hold(fencing_token, sent_at)
+                }
+                Ok(_) => return self.lost(),
+                Err(e) => {
+                    warn!("Failed to renew the claim lease: {}", e);
+                    if !self.is_held() {
+                        *self.held.lock().unwrap() = None;
+                        return LeaseExpiredSnafu {
+                            lease: self.config.lease.clone(),
+                        }
+                        .fail();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the lease is still held by this node, until it would expire
+    /// in the broker without being renewed
+    pub fn is_held(&self) -> bool {
+        self.held_lease().is_some()
+    }
+
+    /// Fencing token of the lease, while held by this node
+    pub fn fencing_token(&self) -> Option<u64> {
+        self.held_lease().map(|held| held.fencing_token)
+    }
+
+    pub fn status(&self) -> FailoverStatus {
+        let fencing_token = self.fencing_token();
+        FailoverStatus {
+            lease: self.config.lease.clone(),
+            holder: self.config.holder.clone(),
+            role: match fencing_token {
+                Some(_) => FailoverRole::Active,
+                None => FailoverRole::Standby,
+            },
+            fencing_token,
+        }
+    }
+
+    /// Route that serves the role of this node in its failover group as
+    /// JSON (`/failover`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/failover", get(get_status))
+            .with_state(self)
+    }
+
+    fn held_lease(&self) -> Option<HeldLease> {
+        self.held
+            .lock()
+            .unwrap()
+            .filter(|held| Instant::now() < held.valid_until)
+    }
+
+    fn hold(&self, fencing_token: u64, sent_at: Instant) {
+        *self.held.lock().unwrap() = Some(HeldLease {
+            fencing_token,
+            valid_until: sent_at + self.config.ttl,
+        });
+    }
+
+    fn lost(&self) -> Result<(), FailoverError> {
+        *self.held.lock().unwrap() = None;
+        LeaseLostSnafu {
+            lease: self.config.lease.clone(),
+        }
+        .fail()
+    }
+
+    fn renew_interval(&self) -> Duration {
+        self.config.ttl / 3
+    }
+}
+
+/// Fencing token the claim lease was acquired with, checked before each
+/// transaction of the claims is signed
+#[derive(Clone, Debug)]
+pub struct ClaimFence {
+    lease: ClaimLease,
+    fencing_token: u64,
+}
+
+impl ClaimFence {
+    /// Fails unless this node still holds the lease with the same fencing
+    /// token, so a node that lost it doesn't resubmit its claims
+    pub fn check(&self) -> Result<(), FailoverError> {
+        ensure!(
+            self.lease.fencing_token() == Some(self.fencing_token),
+            LeaseLostSnafu {
+                lease: self.lease.config.lease.clone(),
+            }
+        );
+        Ok(())
+    }
+}
+
+async fn get_status(State(lease): State<ClaimLease>) -> Json<FailoverStatus> {
+    Json(lease.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Option<FailoverConfig>, clap::Error> {
+        let mut argv = vec!["failover_config"];
+        argv.extend_from_slice(args);
+        FailoverCLIConfig::try_parse_from(argv)
+            .map(FailoverCLIConfig::into_config)
+    }
+
+    #[test]
+    fn it_requires_a_holder_along_with_the_lease() {
+        assert!(config(&[]).unwrap().is_none());
+        assert!(config(&["--claim-lease-name", "claims"]).is_err());
+
+        let config = config(&[
+            "--claim-lease-name",
+            "claims",
+            "--claim-lease-holder",
+            "eu-west",
+            "--claim-lease-ttl",
+            "30s",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.lease, "claims");
+        assert_eq!(config.holder, "eu-west");
+        assert_eq!(config.ttl, Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,73 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! JSON-RPC node for the tests, which answers each request with what the
+//! test returns for its method and params.
+
+use clap::Parser;
+use http_provider::{HttpClient, HttpClientCLIConfig, HttpProvider};
+use redacted::Url;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+
+type Respond = dyn Fn(&str, &Value) -> Value + Send + Sync;
+
+/// Starts the node and returns a provider connected to it
+pub fn start(
+    respond: impl Fn(&str, &Value) -> Value + Send + Sync + 'static,
+) -> HttpProvider {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url: Url = format!("http://{}", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let respond: Arc<Respond> = Arc::new(respond);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let respond = respond.clone();
+            std::thread::spawn(move || serve(stream.unwrap(), respond));
+        }
+    });
+    let config = HttpClientCLIConfig::parse_from(["http_client_config"]);
+    HttpClient::new(&config.into()).unwrap().provider(url)
+}
+
+fn serve(stream: TcpStream, respond: Arc<Respond>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    loop {
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                return;
+            }
+            let header = header.trim().to_lowercase();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let method = request["method"].as_str().unwrap_or_default();
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": respond(method, &request["params"]),
+        })
+        .to_string();
+        write!(
+            &stream,
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+    }
+}
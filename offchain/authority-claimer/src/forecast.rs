@@ -0,0 +1,337 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use ethers::types::{H160, I256, U256};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    calendar::EpochForecast,
+    ledger::{
+        serialize_decimal, EpochSummary, FeeSchedule, Ledger, LedgerError,
+    },
+};
+
+/// Most recent claimed epochs of a DApp the trend of its inputs is fit to
+const TREND_EPOCHS: usize = 12;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Spans the earnings are forecast for, and their length in days
+const HORIZONS: [(&str, u32); 2] = [("week", 7), ("month", 30)];
+
+/// Expected claims and earnings of a DApp over a horizon. The amounts are
+/// in wei and serialize as decimal strings.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EarningsForecast {
+    pub dapp_address: H160,
+    /// `week` or `month`
+    pub horizon: &'static str,
+    /// Epochs that end within the horizon, each of which gets a claim
+    pub claims: u64,
+    /// Inputs of those epochs, following the trend of the claimed ones
+    pub inputs: u64,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub fees: U256,
+    /// Gas cost of the claims, at the average of the last claimed epochs
+    #[serde(serialize_with = "serialize_decimal")]
+    pub gas_cost: U256,
+    /// Fees minus gas cost; negative when claiming is expected to cost more
+    /// than it earns
+    #[serde(serialize_with = "serialize_decimal")]
+    pub net: I256,
+}
+
+/// Linear trend of the inputs of the epochs of a DApp
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Trend {
+    intercept: f64,
+    slope: f64,
+}
+
+impl Trend {
+    /// Least-squares fit of the inputs to the epoch indices, which is flat
+    /// at the mean unless there are epochs with different indices
+    fn fit(points: &[(u64, u64)]) -> Self {
+        if points.is_empty() {
+            return Self {
+                intercept: 0.0,
+                slope: 0.0,
+            };
+        }
+        let count = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| *x as f64).sum::<f64>() / count;
+        let mean_y = points.iter().map(|(_, y)| *y as f64).sum::<f64>() / count;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (x, y) in points {
+            let dx = *x as f64 - mean_x;
+            covariance += dx * (*y as f64 - mean_y);
+            variance += dx * dx;
+        }
+        let slope = if variance > 0.0 {
+            covariance / variance
+        } else {
+            0.0
+        };
+        Self {
+            intercept: mean_y - slope * mean_x,
+            slope,
+        }
+    }
+
+    /// Expected inputs of an epoch, which are never negative
+    fn inputs(&self, epoch_index: u64) -> u64 {
+        let inputs = self.intercept + self.slope * epoch_index as f64;
+        inputs.round().max(0.0) as u64
+    }
+}
+
+/// Forecasts the earnings of each DApp with an epoch forecast, for each
+/// horizon starting at `now`. The summaries must be ordered by DApp and
+/// epoch, as the ledger returns them.
+pub fn forecast(
+    summaries: &[EpochSummary],
+    epochs: &[EpochForecast],
+    fees: &FeeSchedule,
+    now: SystemTime,
+) -> Vec<EarningsForecast> {
+    let mut claimed = BTreeMap::<H160, Vec<&EpochSummary>>::new();
+    for summary in summaries {
+        claimed
+            .entry(summary.dapp_address)
+            .or_default()
+            .push(summary);
+    }
+
+    let mut forecasts = vec![];
+    for epoch_forecast in epochs {
+        let dapp_address = epoch_forecast.dapp_address;
+        let recent: &[&EpochSummary] = match claimed.get(&dapp_address) {
+            Some(epochs) => {
+                &epochs[epochs.len().saturating_sub(TREND_EPOCHS)..]
+            }
+            None => &[],
+        };
+        let points: Vec<_> = recent
+            .iter()
+            .filter_map(|summary| {
+                summary.inputs.map(|inputs| (summary.epoch_index, inputs))
+            })
+            .collect();
+        let trend = Trend::fit(&points);
+        let gas_per_claim = match recent.len() {
+            0 => U256::zero(),
+            count => {
+                let total =
+                    recent.iter().fold(U256::zero(), |total, summary| {
+                        total + summary.gas_cost
+                    });
+                total / U256::from(count)
+            }
+        };
+
+        for (horizon, days) in HORIZONS {
+            let ends = epoch_forecast.epoch_ends(
+                Duration::ZERO,
+                now,
+                now + DAY * days,
+            );
+            let inputs: Vec<u64> = ends
+                .iter()
+                .map(|(epoch_index, _)| trend.inputs(*epoch_index))
+                .collect();
+            let fees = inputs.iter().fold(U256::zero(), |total, inputs| {
+                total + fees.fee(*inputs as u128)
+            });
+            let gas_cost = gas_per_claim * U256::from(ends.len());
+            forecasts.push(EarningsForecast {
+                dapp_address,
+                horizon,
+                claims: ends.len() as u64,
+                inputs: inputs.iter().sum(),
+                fees,
+                gas_cost,
+                net: I256::from_raw(fees)
+                    .saturating_sub(I256::from_raw(gas_cost)),
+            });
+        }
+    }
+    forecasts
+}
+
+/// Forecasts the earnings of the validator from the ledger of its claimed
+/// epochs, so operators can tell whether claiming for a DApp still pays
+#[derive(Clone, Debug)]
+pub struct EarningsForecaster {
+    ledger: Ledger,
+    epochs: Vec<EpochForecast>,
+}
+
+impl EarningsForecaster {
+    pub fn new(ledger: Ledger, epochs: Vec<EpochForecast>) -> Self {
+        Self { ledger, epochs }
+    }
+
+    pub fn forecast(
+        &self,
+        now: SystemTime,
+    ) -> Result<Vec<EarningsForecast>, LedgerError> {
+        let summaries = self.ledger.summaries()?;
+        Ok(forecast(&summaries, &self.epochs, self.ledger.fees(), now))
+    }
+
+    /// Routes that serve the forecasts as JSON (`/ledger/forecast`) and CSV
+    /// (`/ledger/forecast.csv`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/ledger/forecast", get(get_forecasts))
+            .route("/ledger/forecast.csv", get(get_forecasts_csv))
+            .with_state(self)
+    }
+}
+
+fn to_csv(forecasts: &[EarningsForecast]) -> String {
+    let mut csv = String::from(
+        "dapp_address,horizon,claims,inputs,fees_wei,gas_cost_wei,net_wei\n",
+    );
+    for forecast in forecasts {
+        writeln!(
+            csv,
+            "{:?},{},{},{},{},{},{}",
+            forecast.dapp_address,
+            forecast.horizon,
+            forecast.claims,
+            forecast.inputs,
+            forecast.fees,
+            forecast.gas_cost,
+            forecast.net
+        )
+        .expect("writing to a string never fails");
+    }
+    csv
+}
+
+async fn get_forecasts(
+    State(forecaster): State<EarningsForecaster>,
+) -> Result<Json<Vec<EarningsForecast>>, (StatusCode, String)> {
+    read_forecasts(forecaster).await.map(Json)
+}
+
+async fn get_forecasts_csv(
+    State(forecaster): State<EarningsForecaster>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let forecasts = read_forecasts(forecaster).await?;
+    Ok(([(header::CONTENT_TYPE, "text/csv")], to_csv(&forecasts)))
+}
+
+async fn read_forecasts(
+    forecaster: EarningsForecaster,
+) -> Result<Vec<EarningsForecast>, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || forecaster.forecast(SystemTime::now()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    // 2024-03-01T00:00Z is 1709251200
+    const MARCH_1ST: u64 = 1709251200;
+
+    /// An hour into the day of March, so the epoch ending that day is next
+    fn during_day(day: u32) -> SystemTime {
+        UNIX_EPOCH
+            + Duration::from_secs(MARCH_1ST)
+            + DAY * day
+            + Duration::from_secs(3600)
+    }
+
+    fn summary(epoch_index: u64, inputs: Option<u64>) -> EpochSummary {
+        EpochSummary {
+            dapp_address: H160::repeat_byte(0xaa),
+            epoch_index,
+            claim_transactions: 1,
+            gas_cost: 500.into(),
+            inputs,
+            ..Default::default()
+        }
+    }
+
+    fn daily_epochs() -> Vec<EpochForecast> {
+        let forecast =
+            format!("{:?}=2024-03-01T00:00Z every 1d", H160::repeat_byte(0xaa));
+        vec![forecast.parse().unwrap()]
+    }
+
+    #[test]
+    fn it_fits_the_trend_of_the_inputs() {
+        let trend = Trend::fit(&[(0, 10), (1, 20), (2, 30)]);
+        assert_eq!(trend.inputs(3), 40);
+        assert_eq!(trend.inputs(10), 110);
+
+        let flat = Trend::fit(&[(4, 10), (4, 20)]);
+        assert_eq!(flat.inputs(8), 15);
+
+        // A falling trend bottoms out at no inputs
+        let falling = Trend::fit(&[(0, 20), (1, 10)]);
+        assert_eq!(falling.inputs(5), 0);
+        assert_eq!(Trend::fit(&[]).inputs(1), 0);
+    }
+
+    #[test]
+    fn it_forecasts_the_earnings_of_the_next_epochs() {
+        let summaries = vec![
+            summary(0, Some(10)),
+            summary(1, Some(20)),
+            summary(2, Some(30)),
+        ];
+        let fees = FeeSchedule {
+            per_epoch: 1000.into(),
+            per_input: 10.into(),
+        };
+        let forecasts =
+            forecast(&summaries, &daily_epochs(), &fees, during_day(3));
+        assert_eq!(forecasts.len(), 2);
+
+        // Epochs 3 to 9 end within the week
+        let week = &forecasts[0];
+        assert_eq!(week.horizon, "week");
+        assert_eq!(week.claims, 7);
+        assert_eq!(week.inputs, 40 + 50 + 60 + 70 + 80 + 90 + 100);
+        assert_eq!(week.fees, U256::from(7 * 1000 + 490 * 10_u64));
+        assert_eq!(week.gas_cost, U256::from(7 * 500_u64));
+        assert_eq!(week.net, I256::from(7000 + 4900 - 3500_i64));
+        assert_eq!(forecasts[1].claims, 30);
+    }
+
+    #[test]
+    fn it_forecasts_dapps_without_claims_by_the_fee_per_epoch() {
+        let fees = FeeSchedule {
+            per_epoch: 1000.into(),
+            per_input: 10.into(),
+        };
+        let forecasts = forecast(&[], &daily_epochs(), &fees, during_day(0));
+        assert_eq!(forecasts[0].inputs, 0);
+        assert_eq!(forecasts[0].fees, U256::from(7 * 1000_u64));
+        assert_eq!(forecasts[0].gas_cost, U256::zero());
+        assert!(to_csv(&forecasts)
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with(",week,7,0,7000,0,7000"));
+    }
+}
@@ -0,0 +1,363 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+use ethers::{
+    providers::{Middleware, ProviderError},
+    types::{Address, H256},
+    utils::keccak256,
+};
+use http_provider::HttpProvider;
+use snafu::{ensure, ResultExt, Snafu};
+use std::collections::HashSet;
+
+/// Chain ids of the production networks the node is deployed to
+const DEFAULT_MAINNET_CHAIN_IDS: &str = "1,10,137,8453,42161";
+
+/// Reason why the claimer refuses to start, or to switch to a reloaded
+/// provider
+#[derive(Debug, Snafu)]
+pub enum ChainGuardError {
+    #[snafu(display("failed to read the chain id from the provider"))]
+    ProviderChainId { source: ProviderError },
+
+    #[snafu(display(
+        "configured chain id {} doesn't match the chain id {} reported by the provider",
+        configured,
+        reported
+    ))]
+    ChainIdMismatch { configured: u64, reported: u64 },
+
+    #[snafu(display(
+        "signer {:?} is a mainnet key, but chain {} is not a mainnet; add `{:?}@{}` to the signer chain allowlist if this is intended",
+        signer,
+        chain_id,
+        signer,
+        chain_id
+    ))]
+    MainnetKeyOnTestnet { signer: Address, chain_id: u64 },
+
+    #[snafu(display(
+        "signer {:?} is not a mainnet key, but chain {} is a mainnet; add `{:?}@{}` to the signer chain allowlist if this is intended",
+        signer,
+        chain_id,
+        signer,
+        chain_id
+    ))]
+    TestKeyOnMainnet { signer: Address, chain_id: u64 },
+
+    #[snafu(display(
+        "failed to read the code of {} at {:?}",
+        contract,
+        address
+    ))]
+    ProviderCode {
+        contract: &'static str,
+        address: Address,
+        source: ProviderError,
+    },
+
+    #[snafu(display(
+        "there's no contract at the {} address {:?}",
+        contract,
+        address
+    ))]
+    NoContract {
+        contract: &'static str,
+        address: Address,
+    },
+
+    #[snafu(display(
+        "the code at the {} address {:?} has hash {:?}, which isn't of a known release; check the address, or add the hash to the allowed contract code hashes",
+        contract,
+        address,
+        code_hash
+    ))]
+    UnknownContractCode {
+        contract: &'static str,
+        address: Address,
+        code_hash: H256,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "chain_guard_config")]
+pub struct ChainGuardCLIConfig {
+    /// Comma-separated list of the signer addresses reserved for mainnets.
+    /// When set, the claimer refuses to start with one of them on a chain
+    /// that is not a mainnet, and with any other signer on a mainnet
+    #[arg(long, env, value_delimiter = ',')]
+    mainnet_signer_addresses: Option<Vec<Address>>,
+
+    /// Comma-separated list of the chain ids considered mainnets
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = DEFAULT_MAINNET_CHAIN_IDS
+    )]
+    mainnet_chain_ids: Vec<u64>,
+
+    /// Comma-separated list of `<signer address>@<chain id>` pairs that are
+    /// allowed even though they mix a mainnet key with a test chain or vice
+    /// versa
+    #[arg(long, env, value_delimiter = ',', value_parser = parse_binding)]
+    signer_chain_allowlist: Option<Vec<(Address, u64)>>,
+
+    /// Comma-separated Keccak-256 hashes of deployed code accepted for the
+    /// Authority and History contracts, besides the ones of the releases of
+    /// the rollups contracts the node was built with
+    #[arg(long, env, value_delimiter = ',')]
+    allowed_contract_code_hashes: Option<Vec<H256>>,
+
+    /// Don't check the code deployed at the Authority and History addresses
+    #[arg(long, env, default_value_t = false)]
+    skip_contract_code_check: bool,
+}
+
+/// Binding between the signer key and the kind of chain it may sign for, so
+/// a config mix-up can't put a mainnet validator key behind the settings
+/// tuned for a test chain, or the other way around.
+#[derive(Debug, Clone, Default)]
+pub struct ChainGuardConfig {
+    mainnet_signers: Option<HashSet<Address>>,
+    mainnet_chain_ids: HashSet<u64>,
+    allowlist: HashSet<(Address, u64)>,
+    allowed_code_hashes: HashSet<H256>,
+    skip_code_check: bool,
+}
+
+impl From<ChainGuardCLIConfig> for ChainGuardConfig {
+    fn from(cli_config: ChainGuardCLIConfig) -> Self {
+        Self {
+            mainnet_signers: cli_config
+                .mainnet_signer_addresses
+                .map(|signers| signers.into_iter().collect()),
+            mainnet_chain_ids: cli_config
+                .mainnet_chain_ids
+                .into_iter()
+                .collect(),
+            allowlist: cli_config
+                .signer_chain_allowlist
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            allowed_code_hashes: cli_config
+                .allowed_contract_code_hashes
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            skip_code_check: cli_config.skip_contract_code_check,
+        }
+    }
+}
+
+impl ChainGuardConfig {
+    /// Checks that the signer may sign for the chain
+    pub fn check_signer(
+        &self,
+        signer: Address,
+        chain_id: u64,
+    ) -> Result<(), ChainGuardError> {
+        let Some(mainnet_signers) = &self.mainnet_signers else {
+            return Ok(());
+        };
+        if self.allowlist.contains(&(signer, chain_id)) {
+            tracing::warn!(
+                "signer {:?} is allowlisted for chain {}, skipping the key binding check",
+                signer,
+                chain_id
+            );
+            return Ok(());
+        }
+        let mainnet_key = mainnet_signers.contains(&signer);
+        let mainnet_chain = self.mainnet_chain_ids.contains(&chain_id);
+        ensure!(
+            !mainnet_key || mainnet_chain,
+            MainnetKeyOnTestnetSnafu { signer, chain_id }
+        );
+        ensure!(
+            mainnet_key || !mainnet_chain,
+            TestKeyOnMainnetSnafu { signer, chain_id }
+        );
+        Ok(())
+    }
+
+    /// Checks that the code deployed at the address is a known release of
+    /// the contract, so a mistyped address can't point the claimer at a
+    /// look-alike contract
+    pub async fn check_contract_code(
+        &self,
+        provider: &HttpProvider,
+        contract: &'static str,
+        address: Address,
+    ) -> Result<(), ChainGuardError> {
+        if self.skip_code_check {
+            tracing::warn!(
+                "skipping the check of the code of {} at {:?}",
+                contract,
+                address
+            );
+            return Ok(());
+        }
+        let code = provider
+            .get_code(address, None)
+            .await
+            .context(ProviderCodeSnafu { contract, address })?;
+        ensure!(!code.is_empty(), NoContractSnafu { contract, address });
+        let code_hash = H256(keccak256(&code));
+        ensure!(
+            self.is_known_code(contract, code_hash),
+            UnknownContractCodeSnafu {
+                contract,
+                address,
+                code_hash,
+            }
+        );
+        Ok(())
+    }
+
+    fn is_known_code(&self, contract: &str, code_hash: H256) -> bool {
+        self.allowed_code_hashes.contains(&code_hash)
+            || contracts::known_code_hashes()
+                .any(|(name, hash)| name == contract && H256(hash) == code_hash)
+    }
+}
+
+/// Checks that the provider is connected to the configured chain
+pub async fn check_provider_chain_id(
+    provider: &HttpProvider,
+    chain_id: u64,
+) -> Result<(), ChainGuardError> {
+    let reported = provider
+        .get_chainid()
+        .await
+        .context(ProviderChainIdSnafu)?
+        .as_u64();
+    ensure!(
+        reported == chain_id,
+        ChainIdMismatchSnafu {
+            configured: chain_id,
+            reported,
+        }
+    );
+    Ok(())
+}
+
+fn parse_binding(value: &str) -> Result<(Address, u64), String> {
+    let (signer, chain_id) = value.trim().split_once('@').ok_or_else(|| {
+        format!("`{}` should be `<address>@<chain id>`", value)
+    })?;
+    let signer = signer
+        .parse()
+        .map_err(|e| format!("invalid address in `{}`: {}", value, e))?;
+    let chain_id = chain_id
+        .parse()
+        .map_err(|e| format!("invalid chain id in `{}`: {}", value, e))?;
+    Ok((signer, chain_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_node;
+    use ethers::types::H160;
+    use serde_json::json;
+
+    fn config(args: &[&str]) -> ChainGuardConfig {
+        let mut argv = vec!["chain_guard_config"];
+        argv.extend_from_slice(args);
+        ChainGuardCLIConfig::parse_from(argv).into()
+    }
+
+    const MAINNET_KEY: Address = H160([1; 20]);
+    const TEST_KEY: Address = H160([2; 20]);
+
+    #[test]
+    fn it_skips_the_check_without_mainnet_signers() {
+        let config = config(&[]);
+        assert!(config.check_signer(TEST_KEY, 1).is_ok());
+        assert!(config.check_signer(TEST_KEY, 11155111).is_ok());
+    }
+
+    #[test]
+    fn it_binds_keys_to_the_kind_of_chain() {
+        let config = config(&[
+            "--mainnet-signer-addresses",
+            "0x0101010101010101010101010101010101010101",
+        ]);
+        assert!(config.check_signer(MAINNET_KEY, 1).is_ok());
+        assert!(config.check_signer(TEST_KEY, 11155111).is_ok());
+        assert!(matches!(
+            config.check_signer(MAINNET_KEY, 11155111),
+            Err(ChainGuardError::MainnetKeyOnTestnet { .. })
+        ));
+        assert!(matches!(
+            config.check_signer(TEST_KEY, 42161),
+            Err(ChainGuardError::TestKeyOnMainnet { .. })
+        ));
+    }
+
+    #[test]
+    fn it_accepts_allowlisted_bindings() {
+        let config = config(&[
+            "--mainnet-signer-addresses",
+            "0x0101010101010101010101010101010101010101",
+            "--mainnet-chain-ids",
+            "1",
+            "--signer-chain-allowlist",
+            "0x0101010101010101010101010101010101010101@31337",
+        ]);
+        assert!(config.check_signer(MAINNET_KEY, 31337).is_ok());
+        assert!(config.check_signer(MAINNET_KEY, 11155111).is_err());
+        // Chain 42161 is not a mainnet in this config
+        assert!(config.check_signer(TEST_KEY, 42161).is_ok());
+    }
+
+    #[test]
+    fn it_accepts_the_code_of_known_releases() {
+        let config = config(&[]);
+        let (contract, hash) = contracts::known_code_hashes()
+            .find(|(name, _)| *name == "Authority")
+            .expect("the Authority should have a code hash");
+        assert!(config.is_known_code(contract, H256(hash)));
+        // The code of another contract is not a known Authority
+        assert!(!config.is_known_code("History", H256(hash)));
+        assert!(!config.is_known_code(contract, H256::zero()));
+    }
+
+    #[test]
+    fn it_accepts_allowed_code_hashes() {
+        let hash = H256::repeat_byte(7);
+        let config =
+            config(&["--allowed-contract-code-hashes", &format!("{:?}", hash)]);
+        assert!(config.is_known_code("Authority", hash));
+        assert!(config.is_known_code("History", hash));
+    }
+
+    #[test]
+    fn it_parses_bindings() {
+        assert_eq!(
+            parse_binding("0x0101010101010101010101010101010101010101@1"),
+            Ok((MAINNET_KEY, 1))
+        );
+        assert!(parse_binding("0x0101010101010101010101010101010101010101")
+            .is_err());
+        assert!(parse_binding("0x01@1").is_err());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_a_provider_of_another_chain() {
+        let provider = fake_node::start(|_, _| json!("0x1"));
+        assert!(check_provider_chain_id(&provider, 1).await.is_ok());
+
+        let provider = fake_node::start(|_, _| json!("0xaa36a7"));
+        assert!(matches!(
+            check_provider_chain_id(&provider, 1).await,
+            Err(ChainGuardError::ChainIdMismatch {
+                configured: 1,
+                reported: 11155111,
+            })
+        ));
+    }
+}
@@ -0,0 +1,504 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use ethers::types::{TransactionReceipt, H160, H256, I256, U256};
+use rollups_data::{NewLedgerEntry, Repository};
+use serde::{Deserialize, Serialize, Serializer};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Snafu)]
+pub enum LedgerError {
+    #[snafu(display("failed to access the ledger in the database"))]
+    Repository { source: rollups_data::Error },
+
+    #[snafu(display("ledger entry {} has an invalid {}", id, field))]
+    CorruptedEntry { id: i32, field: &'static str },
+}
+
+/// Accounts of the validator's books
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Account {
+    /// Asset: ether held by the claimer's wallet
+    Wallet,
+    /// Expense: gas paid for the claim transactions
+    ClaimGas,
+    /// Asset: fees owed to the validator by the DApp owner
+    FeesReceivable,
+    /// Revenue: fees earned by claiming epochs
+    FeeRevenue,
+}
+
+impl Account {
+    const ALL: [Account; 4] = [
+        Account::Wallet,
+        Account::ClaimGas,
+        Account::FeesReceivable,
+        Account::FeeRevenue,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Account::Wallet => "wallet",
+            Account::ClaimGas => "claim_gas",
+            Account::FeesReceivable => "fees_receivable",
+            Account::FeeRevenue => "fee_revenue",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Debit,
+    Credit,
+}
+
+impl Side {
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Debit => "debit",
+            Side::Credit => "credit",
+        }
+    }
+}
+
+/// One side of a posting to the ledger. Every posting has a debit and a
+/// credit of the same amount, so the books of an epoch always balance.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub dapp_address: H160,
+    pub epoch_index: u64,
+    pub account: Account,
+    pub side: Side,
+    /// Amount in wei
+    pub amount: U256,
+    /// Claim transaction that originated the entry
+    pub tx_hash: H256,
+    /// Gas used by the claim transaction, only set in the gas entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<U256>,
+    /// Inputs of the claimed epoch, only set in the gas entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<u64>,
+    /// Seconds since the Unix epoch
+    pub recorded_at: u64,
+}
+
+impl From<&LedgerEntry> for NewLedgerEntry {
+    fn from(entry: &LedgerEntry) -> Self {
+        Self {
+            dapp_address: entry.dapp_address.as_bytes().to_vec(),
+            epoch_index: i64::try_from(entry.epoch_index).unwrap_or(i64::MAX),
+            account: entry.account.name().to_owned(),
+            side: entry.side.name().to_owned(),
+            amount: entry.amount.to_string(),
+            tx_hash: entry.tx_hash.as_bytes().to_vec(),
+            gas_used: entry.gas_used.map(|gas_used| gas_used.to_string()),
+            inputs: entry
+                .inputs
+                .map(|inputs| i64::try_from(inputs).unwrap_or(i64::MAX)),
+            recorded_at: UNIX_EPOCH + Duration::from_secs(entry.recorded_at),
+        }
+    }
+}
+
+impl TryFrom<rollups_data::LedgerEntry> for LedgerEntry {
+    type Error = LedgerError;
+
+    fn try_from(row: rollups_data::LedgerEntry) -> Result<Self, LedgerError> {
+        let id = row.id;
+        let corrupted = |field| CorruptedEntrySnafu { id, field };
+        let decimal = |amount: &str, field| {
+            U256::from_dec_str(amount).ok().context(corrupted(field))
+        };
+        Ok(Self {
+            dapp_address: (row.dapp_address.len() == H160::len_bytes())
+                .then(|| H160::from_slice(&row.dapp_address))
+                .context(corrupted("dapp_address"))?,
+            epoch_index: u64::try_from(row.epoch_index)
+                .ok()
+                .context(corrupted("epoch_index"))?,
+            account: Account::ALL
+                .into_iter()
+                .find(|account| account.name() == row.account)
+                .context(corrupted("account"))?,
+            side: [Side::Debit, Side::Credit]
+                .into_iter()
+                .find(|side| side.name() == row.side)
+                .context(corrupted("side"))?,
+            amount: decimal(&row.amount, "amount")?,
+            tx_hash: (row.tx_hash.len() == H256::len_bytes())
+                .then(|| H256::from_slice(&row.tx_hash))
+                .context(corrupted("tx_hash"))?,
+            gas_used: row
+                .gas_used
+                .map(|gas_used| decimal(&gas_used, "gas_used"))
+                .transpose()?,
+            inputs: row
+                .inputs
+                .map(|inputs| u64::try_from(inputs).ok())
+                .map(|inputs| inputs.context(corrupted("inputs")))
+                .transpose()?,
+            recorded_at: row
+                .recorded_at
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Fees the validator earns for each claim, as agreed with the DApp owner
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub per_epoch: U256,
+    pub per_input: U256,
+}
+
+impl FeeSchedule {
+    pub(crate) fn fee(&self, inputs: u128) -> U256 {
+        self.per_epoch + self.per_input * U256::from(inputs)
+    }
+}
+
+/// Profitability of a claimed epoch, derived from its ledger entries. The
+/// amounts are in wei and serialize as decimal strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EpochSummary {
+    pub dapp_address: H160,
+    pub epoch_index: u64,
+    pub claim_transactions: usize,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub gas_used: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub gas_cost: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    pub fees: U256,
+    /// Fees minus gas cost; negative when the epoch cost more than it earned
+    #[serde(serialize_with = "serialize_decimal")]
+    pub net: I256,
+    /// Inputs of the epoch, unless it was claimed before they were recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<u64>,
+}
+
+pub(crate) fn serialize_decimal<T: Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Append-only ledger of the costs and revenues of each claimed epoch, kept
+/// in the repository database so it survives restarts.
+#[derive(Clone, Debug)]
+pub struct Ledger {
+    repository: Repository,
+    fees: FeeSchedule,
+}
+
+impl Ledger {
+    pub fn new(repository: Repository, fees: FeeSchedule) -> Self {
+        Self { repository, fees }
+    }
+
+    /// Posts the gas paid by a confirmed claim transaction and the fees
+    /// earned by the claim.
+    pub fn record_claim(
+        &self,
+        dapp_address: H160,
+        epoch_index: u64,
+        inputs: u128,
+        receipt: &TransactionReceipt,
+    ) -> Result<(), LedgerError> {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let entries = postings(
+            &self.fees,
+            dapp_address,
+            epoch_index,
+            inputs,
+            receipt,
+            recorded_at,
+        );
+        // A single transaction keeps the sides of a posting together
+        self.repository
+            .insert_ledger_entries(entries.iter().map(Into::into).collect())
+            .context(RepositorySnafu)
+    }
+
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>, LedgerError> {
+        self.repository
+            .get_ledger_entries()
+            .context(RepositorySnafu)?
+            .into_iter()
+            .map(LedgerEntry::try_from)
+            .collect()
+    }
+
+    /// Summarizes the entries per epoch, ordered by DApp and epoch
+    pub fn summaries(&self) -> Result<Vec<EpochSummary>, LedgerError> {
+        Ok(summarize(&self.entries()?))
+    }
+
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    /// Routes that serve the per-epoch summaries as JSON (`/ledger`) and
+    /// CSV (`/ledger.csv`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/ledger", get(get_summaries))
+            .route("/ledger.csv", get(get_summaries_csv))
+            .with_state(self)
+    }
+}
+
+/// Entries of the postings of a confirmed claim: the gas it paid and, when
+/// there is a fee schedule, the fees it earned
+fn postings(
+    fees: &FeeSchedule,
+    dapp_address: H160,
+    epoch_index: u64,
+    inputs: u128,
+    receipt: &TransactionReceipt,
+    recorded_at: u64,
+) -> Vec<LedgerEntry> {
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    let gas_price = receipt.effective_gas_price.unwrap_or_default();
+    let entry = |account, side, amount, gas_used: Option<U256>| LedgerEntry {
+        dapp_address,
+        epoch_index,
+        account,
+        side,
+        amount,
+        tx_hash: receipt.transaction_hash,
+        gas_used,
+        inputs: gas_used.map(|_| u64::try_from(inputs).unwrap_or(u64::MAX)),
+        recorded_at,
+    };
+
+    let gas_cost = gas_used * gas_price;
+    let mut entries = vec![
+        entry(Account::ClaimGas, Side::Debit, gas_cost, Some(gas_used)),
+        entry(Account::Wallet, Side::Credit, gas_cost, Some(gas_used)),
+    ];
+    let fee = fees.fee(inputs);
+    if !fee.is_zero() {
+        entries.push(entry(Account::FeesReceivable, Side::Debit, fee, None));
+        entries.push(entry(Account::FeeRevenue, Side::Credit, fee, None));
+    }
+    entries
+}
+
+fn summarize(entries: &[LedgerEntry]) -> Vec<EpochSummary> {
+    let mut summaries = BTreeMap::new();
+    let mut transactions = BTreeMap::<_, Vec<H256>>::new();
+    for entry in entries {
+        let key = (entry.dapp_address, entry.epoch_index);
+        let summary = summaries.entry(key).or_insert_with(|| EpochSummary {
+            dapp_address: entry.dapp_address,
+            epoch_index: entry.epoch_index,
+            ..Default::default()
+        });
+        match (entry.account, entry.side) {
+            (Account::ClaimGas, Side::Debit) => {
+                summary.gas_cost += entry.amount;
+                summary.gas_used += entry.gas_used.unwrap_or_default();
+                summary.inputs = summary.inputs.max(entry.inputs);
+            }
+            (Account::FeeRevenue, Side::Credit) => summary.fees += entry.amount,
+            _ => {}
+        }
+        let hashes = transactions.entry(key).or_default();
+        if !hashes.contains(&entry.tx_hash) {
+            hashes.push(entry.tx_hash);
+        }
+    }
+    summaries
+        .into_iter()
+        .map(|(key, mut summary)| {
+            summary.claim_transactions = transactions[&key].len();
+            summary.net = I256::from_raw(summary.fees)
+                .saturating_sub(I256::from_raw(summary.gas_cost));
+            summary
+        })
+        .collect()
+}
+
+fn to_csv(summaries: &[EpochSummary]) -> String {
+    let mut csv = String::from(
+        "dapp_address,epoch_index,claim_transactions,gas_used,gas_cost_wei,fees_wei,net_wei,inputs\n",
+    );
+    for summary in summaries {
+        writeln!(
+            csv,
+            "{:?},{},{},{},{},{},{},{}",
+            summary.dapp_address,
+            summary.epoch_index,
+            summary.claim_transactions,
+            summary.gas_used,
+            summary.gas_cost,
+            summary.fees,
+            summary.net,
+            summary
+                .inputs
+                .map(|inputs| inputs.to_string())
+                .unwrap_or_default()
+        )
+        .expect("writing to a string never fails");
+    }
+    csv
+}
+
+async fn get_summaries(
+    State(ledger): State<Ledger>,
+) -> Result<Json<Vec<EpochSummary>>, (StatusCode, String)> {
+    read_summaries(ledger).await.map(Json)
+}
+
+async fn get_summaries_csv(
+    State(ledger): State<Ledger>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let summaries = read_summaries(ledger).await?;
+    Ok(([(header::CONTENT_TYPE, "text/csv")], to_csv(&summaries)))
+}
+
+async fn read_summaries(
+    ledger: Ledger,
+) -> Result<Vec<EpochSummary>, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || ledger.summaries())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_fixtures::RepositoryFixture;
+    use testcontainers::clients::Cli;
+
+    fn receipt(tx: u8, gas_used: u64, gas_price: u64) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: H256::repeat_byte(tx),
+            gas_used: Some(gas_used.into()),
+            effective_gas_price: Some(gas_price.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_summarizes_the_epochs() {
+        let fees = FeeSchedule {
+            per_epoch: 1000.into(),
+            per_input: 100.into(),
+        };
+        let dapp = H160::repeat_byte(0xaa);
+        let mut entries = postings(&fees, dapp, 0, 2, &receipt(1, 10, 50), 0);
+        entries.extend(postings(&fees, dapp, 1, 1, &receipt(2, 30, 50), 0));
+
+        // Every posting balances
+        assert_eq!(entries.len(), 8);
+        let total = |side| {
+            entries
+                .iter()
+                .filter(|entry| entry.side == side)
+                .fold(U256::zero(), |total, entry| total + entry.amount)
+        };
+        assert_eq!(total(Side::Debit), total(Side::Credit));
+
+        let summaries = summarize(&entries);
+        assert_eq!(
+            summaries,
+            vec![
+                EpochSummary {
+                    dapp_address: dapp,
+                    epoch_index: 0,
+                    claim_transactions: 1,
+                    gas_used: 10.into(),
+                    gas_cost: 500.into(),
+                    fees: 1200.into(),
+                    net: I256::from(700_i64),
+                    inputs: Some(2),
+                },
+                EpochSummary {
+                    dapp_address: dapp,
+                    epoch_index: 1,
+                    claim_transactions: 1,
+                    gas_used: 30.into(),
+                    gas_cost: 1500.into(),
+                    fees: 1100.into(),
+                    net: I256::from(-400_i64),
+                    inputs: Some(1),
+                },
+            ]
+        );
+        assert_eq!(
+            to_csv(&summaries).lines().nth(2),
+            Some(
+                "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,1,1,30,1500,1100,-400,1"
+            )
+        );
+    }
+
+    #[test]
+    fn it_skips_fee_entries_without_a_fee_schedule() {
+        let entries = postings(
+            &FeeSchedule::default(),
+            H160::zero(),
+            0,
+            5,
+            &receipt(1, 10, 50),
+            0,
+        );
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .all(|entry| entry.account != Account::FeeRevenue));
+    }
+
+    #[test]
+    fn it_keeps_the_entries_in_the_database() {
+        let docker = Cli::default();
+        let fixture = RepositoryFixture::setup(&docker);
+        let fees = FeeSchedule {
+            per_epoch: 1000.into(),
+            per_input: 100.into(),
+        };
+        let ledger = Ledger::new(fixture.repository().clone(), fees.clone());
+        assert!(ledger.entries().unwrap().is_empty());
+
+        let dapp = H160::repeat_byte(0xaa);
+        ledger
+            .record_claim(dapp, 3, 2, &receipt(1, 10, 50))
+            .unwrap();
+        let entries = ledger.entries().unwrap();
+        let recorded_at = entries[0].recorded_at;
+        assert_eq!(
+            entries,
+            postings(&fees, dapp, 3, 2, &receipt(1, 10, 50), recorded_at)
+        );
+
+        // The books survive a restart of the claimer
+        let ledger = Ledger::new(fixture.repository().clone(), fees);
+        assert_eq!(ledger.entries().unwrap(), entries);
+        assert_eq!(ledger.summaries().unwrap()[0].net, I256::from(700_i64));
+    }
+}
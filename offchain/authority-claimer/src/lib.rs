@@ -1,69 +1,283 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+pub mod blackout;
+pub mod calendar;
 pub mod checker;
 pub mod claimer;
 pub mod config;
+pub mod evidence;
+pub mod failover;
+pub mod forecast;
+pub mod guard;
+pub mod ledger;
 pub mod listener;
 pub mod metrics;
+pub mod preflight;
+pub mod reload;
+pub mod remote;
 pub mod sender;
 pub mod signer;
+pub mod watcher;
 
-use config::Config;
+#[cfg(test)]
+mod fake_node;
+
+use axum::Router;
+use config::{Config, TxSigningConfig};
+use http_provider::HttpClient;
+use rollups_data::Repository;
+use runtimes::{RuntimeRole, Runtimes};
+use scheduler::{JobConfig, Scheduler};
 use snafu::Error;
+use std::future;
 use tracing::trace;
 
 use crate::{
+    blackout::Blackouts,
+    calendar::DutyCalendar,
     checker::DefaultDuplicateChecker,
     claimer::{Claimer, DefaultClaimer},
-    listener::DefaultBrokerListener,
+    evidence::EvidenceStore,
+    failover::{ClaimLease, FailoverError},
+    forecast::EarningsForecaster,
+    ledger::Ledger,
+    listener::{ClaimListener, DefaultBrokerListener},
     metrics::AuthorityClaimerMetrics,
+    preflight::Preflight,
+    reload::ProviderSettings,
+    remote::RemoteEpochHashesListener,
     sender::DefaultTransactionSender,
+    signer::SigningQueue,
 };
 
 pub async fn run(config: Config) -> Result<(), Box<dyn Error>> {
     // Creating the metrics and health server.
     let metrics = AuthorityClaimerMetrics::new();
-    let http_server_handle =
-        http_server::start(config.http_server_config, metrics.clone().into());
+    let mut registry = metrics
+        .clone()
+        .into_registry(&config.authority_claimer_config.validator_labels);
+
+    // Building the runtimes the claimer and the HTTP server run in.
+    let runtimes = Runtimes::new(
+        &config.authority_claimer_config.runtime_config,
+        &mut registry,
+    )?;
+
+    // Opening the ledger of the claimed epochs, the blackout windows and the
+    // calendar of duties, served by the HTTP server.
+    let ledger =
+        match config.authority_claimer_config.claim_ledger_config.clone() {
+            Some(repository_config) => {
+                let repository = tokio::task::spawn_blocking(|| {
+                    Repository::new(repository_config)
+                })
+                .await??;
+                Some(Ledger::new(
+                    repository,
+                    config.authority_claimer_config.claim_fees.clone(),
+                ))
+            }
+            None => None,
+        };
+    let blackouts = Blackouts::new(
+        config
+            .authority_claimer_config
+            .claim_blackout_windows
+            .clone(),
+    );
+    let calendar = DutyCalendar::new(
+        config
+            .authority_claimer_config
+            .claim_epoch_forecasts
+            .clone(),
+        config.authority_claimer_config.claim_window_duration,
+        config.authority_claimer_config.duty_calendar_horizon,
+        blackouts.clone(),
+    );
+
+    // Watching for reloads of the provider settings.
+    trace!("Creating the provider settings watcher");
+    let provider_settings = reload::start(
+        ProviderSettings::new(&config.authority_claimer_config)?,
+        config.authority_claimer_config.reload_config_path.clone(),
+    )?;
+
+    // Creating the HTTP client shared by the providers.
+    let http_client =
+        HttpClient::new(&config.authority_claimer_config.http_client_config)?;
+
+    // Creating the pre-flight checks of the claims, whose reports are also
+    // served.
+    let preflight = Preflight::new(
+        config
+            .authority_claimer_config
+            .claim_preflight_policies
+            .clone(),
+        http_client.clone(),
+        provider_settings.clone(),
+        config
+            .authority_claimer_config
+            .contracts_config
+            .input_box_address
+            .clone(),
+        config.authority_claimer_config.genesis_block,
+        blackouts.clone(),
+        config
+            .authority_claimer_config
+            .claim_evidence_dir
+            .clone()
+            .map(EvidenceStore::new),
+        ledger.clone(),
+        config.authority_claimer_config.claim_gas_budget.clone(),
+    );
+
+    // Joining the failover group, whose status is also served.
+    let claim_lease =
+        match config.authority_claimer_config.failover_config.clone() {
+            Some(failover_config) => Some(
+                ClaimLease::new(
+                    config.authority_claimer_config.broker_config.clone(),
+                    failover_config,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+    let routes = ledger
+        .clone()
+        .map(|ledger| {
+            let forecaster = EarningsForecaster::new(
+                ledger.clone(),
+                config
+                    .authority_claimer_config
+                    .claim_epoch_forecasts
+                    .clone(),
+            );
+            ledger.routes().merge(forecaster.routes())
+        })
+        .unwrap_or_default()
+        .merge(blackouts.clone().routes())
+        .merge(preflight.clone().routes())
+        .merge(calendar.routes())
+        .merge(
+            claim_lease
+                .clone()
+                .map(ClaimLease::routes)
+                .unwrap_or_default(),
+        )
+        .merge(match &config.authority_claimer_config.tx_signing_config {
+            TxSigningConfig::Offline { dir, .. } => {
+                SigningQueue::new(dir.clone()).routes()
+            }
+            _ => Router::new(),
+        });
+    let http_server_handle = runtimes.run(
+        RuntimeRole::Api,
+        http_server::start_with_routes(
+            config.http_server_config,
+            registry,
+            routes,
+        ),
+    );
+    tokio::pin!(http_server_handle);
+
+    // Standing by until this node holds the claim lease, before reading
+    // the claims, so it resumes from the ones the previous holder handled.
+    let claim_fence = match &claim_lease {
+        Some(claim_lease) => tokio::select! {
+            ret = &mut http_server_handle => {
+                ret?;
+                return Err(FailoverError::StoppedStandingBy {
+                    lease: claim_lease.status().lease,
+                }
+                .into());
+            }
+            claim_fence = claim_lease.acquire() => Some(claim_fence),
+        },
+        None => None,
+    };
 
     let config = config.authority_claimer_config;
     let chain_id = config.tx_manager_config.chain_id;
 
-    // Creating the broker listener.
-    trace!("Creating the broker listener");
-    let broker_listener =
-        DefaultBrokerListener::new(config.broker_config.clone(), chain_id)
-            .await?;
+    // Creating the listener of the configured source of claims.
+    let broker_listener = match config.remote_epoch_hashes_config.clone() {
+        Some(remote_config) => {
+            trace!("Creating the remote epoch hashes listener");
+            ClaimListener::Remote(RemoteEpochHashesListener::new(
+                remote_config,
+                chain_id,
+            )?)
+        }
+        None => {
+            trace!("Creating the broker listener");
+            ClaimListener::Broker(
+                DefaultBrokerListener::new(
+                    config.broker_config.clone(),
+                    chain_id,
+                )
+                .await?,
+            )
+        }
+    };
 
     // Creating the duplicate checker.
     trace!("Creating the duplicate checker");
     let duplicate_checker = DefaultDuplicateChecker::new(
-        config.tx_manager_config.provider_http_endpoint.clone(),
+        http_client.clone(),
+        provider_settings.clone(),
         config.contracts_config.history_address.clone(),
-        config.tx_manager_config.default_confirmations,
         config.genesis_block,
+        chain_id,
+        metrics.clone(),
+        config.claim_evidence_dir.clone().map(EvidenceStore::new),
     )
     .await?;
 
     // Creating the transaction sender.
     trace!("Creating the transaction sender");
-    let transaction_sender =
-        DefaultTransactionSender::new(config.clone(), chain_id, metrics)
-            .await?;
+    let transaction_sender = DefaultTransactionSender::new(
+        config.clone(),
+        chain_id,
+        metrics,
+        http_client,
+        provider_settings,
+        ledger,
+        claim_fence,
+    )
+    .await?;
 
     // Creating the claimer loop.
+    let scheduler = Scheduler::new(&config.scheduler_config);
+    let reorg_check = scheduler.ticker(
+        "claim_reorg_check",
+        &JobConfig::every(config.claim_reorg_check_interval),
+    );
     let claimer = DefaultClaimer::new(
         broker_listener,
         duplicate_checker,
         transaction_sender,
+        reorg_check,
+        blackouts,
+        preflight,
+        claim_lease.clone(),
     );
-    let claimer_handle = claimer.start();
+    let claimer_handle = runtimes.run(RuntimeRole::Tx, claimer.start());
+
+    // Renewing the claim lease, if any, until it is lost.
+    let lease_handle = runtimes.run(RuntimeRole::Tx, async move {
+        match claim_lease {
+            Some(claim_lease) => claim_lease.keep(scheduler).await,
+            None => future::pending().await,
+        }
+    });
 
     // Starting the HTTP server and the claimer loop.
     tokio::select! {
         ret = http_server_handle => { ret? }
         ret = claimer_handle     => { ret? }
+        ret = lease_handle       => { ret? }
     };
 
     unreachable!()
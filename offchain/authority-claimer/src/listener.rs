@@ -4,11 +4,12 @@
 use async_trait::async_trait;
 use rollups_events::{
     Broker, BrokerConfig, BrokerError, RollupsClaim, RollupsClaimsStream,
-    INITIAL_ID,
 };
 use snafu::ResultExt;
 use std::fmt::Debug;
 
+use crate::remote::{RemoteEpochHashesError, RemoteEpochHashesListener};
+
 /// The `BrokerListener` listens for new claims from the broker
 #[async_trait]
 pub trait BrokerListener: Debug {
@@ -16,12 +17,22 @@ pub trait BrokerListener: Debug {
 
     /// Listen to claims
     async fn listen(&mut self) -> Result<RollupsClaim, Self::Error>;
+
+    /// Acknowledge that the claims listened so far were handled, so they
+    /// aren't listened again after a restart
+    async fn acknowledge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
 // DefaultBrokerListener
 // ------------------------------------------------------------------------------------------------
 
+/// Name under which the claimer acknowledges the claims it handled, so
+/// the claims stream can be compacted past them
+const CONSUMER: &str = "authority-claimer";
+
 #[derive(Debug)]
 pub struct DefaultBrokerListener {
     broker: Broker,
@@ -41,9 +52,9 @@ impl DefaultBrokerListener {
         chain_id: u64,
     ) -> Result<Self, BrokerError> {
         tracing::trace!("Connecting to the broker ({:?})", broker_config);
-        let broker = Broker::new(broker_config).await?;
+        let mut broker = Broker::new(broker_config).await?;
         let stream = RollupsClaimsStream::new(chain_id);
-        let last_claim_id = INITIAL_ID.to_string();
+        let last_claim_id = broker.register_consumer(&stream, CONSUMER).await?;
         Ok(Self {
             broker,
             stream,
@@ -68,6 +79,61 @@ impl BrokerListener for DefaultBrokerListener {
 
         Ok(event.payload)
     }
+
+    async fn acknowledge(&mut self) -> Result<(), Self::Error> {
+        self.broker
+            .acknowledge(&self.stream, CONSUMER, &self.last_claim_id)
+            .await
+            .context(BrokerSnafu)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// ClaimListener
+// ------------------------------------------------------------------------------------------------
+
+/// Listener of the configured source of claims: the broker, fed by a
+/// co-located server-manager, or a remote epoch hashes service
+#[derive(Debug)]
+pub enum ClaimListener {
+    Broker(DefaultBrokerListener),
+    Remote(RemoteEpochHashesListener),
+}
+
+#[derive(Debug, snafu::Snafu)]
+pub enum ClaimListenerError {
+    #[snafu(display("broker listener error"))]
+    BrokerListenerError { source: BrokerListenerError },
+
+    #[snafu(display("remote epoch hashes error"))]
+    RemoteEpochHashesError { source: RemoteEpochHashesError },
+}
+
+#[async_trait]
+impl BrokerListener for ClaimListener {
+    type Error = ClaimListenerError;
+
+    async fn listen(&mut self) -> Result<RollupsClaim, Self::Error> {
+        match self {
+            Self::Broker(listener) => {
+                listener.listen().await.context(BrokerListenerSnafu)
+            }
+            Self::Remote(listener) => {
+                listener.listen().await.context(RemoteEpochHashesSnafu)
+            }
+        }
+    }
+
+    async fn acknowledge(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Self::Broker(listener) => {
+                listener.acknowledge().await.context(BrokerListenerSnafu)
+            }
+            Self::Remote(listener) => {
+                listener.acknowledge().await.context(RemoteEpochHashesSnafu)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -113,7 +179,7 @@ mod tests {
 
         let config = BrokerConfig {
             redis_endpoint,
-            consume_timeout: 300000,
+            consume_timeout: Duration::from_secs(300),
             backoff: ExponentialBackoffBuilder::new()
                 .with_initial_interval(Duration::from_millis(1000))
                 .with_max_elapsed_time(Some(Duration::from_millis(3000)))
@@ -6,6 +6,9 @@ use std::error::Error;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Resolving the secrets referenced by the environment.
+    let secrets = secrets::resolve_env().await?;
+
     // Getting the configuration.
     let config: Config = Config::new().map_err(Box::new)?;
 
@@ -15,5 +18,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     //Log Service info
     log::log_service_start(&config, "Authority Claimer");
 
-    authority_claimer::run(config).await
+    tokio::select! {
+        ret = authority_claimer::run(config) => ret,
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
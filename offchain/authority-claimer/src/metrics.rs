@@ -1,8 +1,8 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use http_server::{CounterRef, FamilyRef, Registry};
-use rollups_events::DAppMetadata;
+use http_server::{CounterRef, FamilyRef, GaugeRef, Registry};
+use rollups_events::{DAppMetadata, Labels};
 
 const METRICS_PREFIX: &str = "cartesi_rollups_authority_claimer";
 
@@ -13,21 +13,46 @@ fn prefixed_metrics(name: &str) -> String {
 #[derive(Debug, Clone, Default)]
 pub struct AuthorityClaimerMetrics {
     pub claims_sent: FamilyRef<DAppMetadata, CounterRef>,
+    pub conflicting_claims: FamilyRef<DAppMetadata, CounterRef>,
+    pub dropped_claims: FamilyRef<DAppMetadata, CounterRef>,
+    pub claim_pending_since: FamilyRef<DAppMetadata, GaugeRef>,
+    pub claim_gas_used: FamilyRef<DAppMetadata, CounterRef>,
 }
 
 impl AuthorityClaimerMetrics {
     pub fn new() -> Self {
         Self::default()
     }
-}
 
-impl From<AuthorityClaimerMetrics> for Registry {
-    fn from(metrics: AuthorityClaimerMetrics) -> Self {
-        let mut registry = Registry::default();
+    /// Creates the registry, adding the operator-defined `labels` to every
+    /// metric.
+    pub fn into_registry(self, labels: &Labels) -> Registry {
+        let mut registry = Registry::with_labels(labels.metric_labels());
         registry.register(
             prefixed_metrics("claims_sent"),
             "Counts the number of claims sent",
-            metrics.claims_sent,
+            self.claims_sent,
+        );
+        registry.register(
+            prefixed_metrics("conflicting_claims"),
+            "Counts the number of claims that conflict with the ones on chain",
+            self.conflicting_claims,
+        );
+        registry.register(
+            prefixed_metrics("dropped_claims"),
+            "Counts the number of sent claims dropped by a reorg",
+            self.dropped_claims,
+        );
+        registry.register(
+            prefixed_metrics("claim_pending_since_seconds"),
+            "UNIX time the pending claim transaction was sent, or zero when \
+            none is pending; claims pending for too long have an old one",
+            self.claim_pending_since,
+        );
+        registry.register(
+            prefixed_metrics("claim_gas_used"),
+            "Counts the gas used by the confirmed claim transactions",
+            self.claim_gas_used,
         );
         registry
     }
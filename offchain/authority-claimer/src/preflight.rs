@@ -0,0 +1,541 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use axum::{extract::State, routing::get, Json, Router};
+use clap::ValueEnum;
+use contracts::{cartesi_dapp::CartesiDApp, input_box::InputBox};
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, H160, H256, U256},
+};
+use http_provider::HttpClient;
+use rollups_events::{Address, RollupsClaim};
+use scheduler::seconds_since_epoch;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt, mem,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+
+use crate::{
+    blackout::Blackouts,
+    evidence::EvidenceStore,
+    ledger::{serialize_decimal, Account, Ledger, Side},
+    reload::ProviderSettingsReceiver,
+};
+
+/// Reports of the last claims checked, served at `/preflight`
+const MAX_REPORTS: usize = 64;
+
+/// Check made on a claim before it is sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreflightPolicy {
+    /// The DApp has a template hash, so there is a machine to claim for
+    MachineHash,
+    /// The claim has an epoch hash and a well-formed input range
+    Invariants,
+    /// No evidence of a conflicting claim of the DApp is left to triage
+    Divergence,
+    /// The gas spent on claims over the budget period is below the budget
+    Budget,
+    /// No blackout window is active
+    Blackout,
+    /// The last input of the epoch was added in a finalized block
+    Finality,
+}
+
+impl fmt::Display for PreflightPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped policies");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Most gas, in wei, the claims may spend over each period
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GasBudget {
+    pub amount: U256,
+    pub period: Duration,
+}
+
+/// Outcome of a policy for a claim
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Verdict {
+    pub policy: PreflightPolicy,
+    pub passed: bool,
+    pub reason: String,
+}
+
+/// Verdicts of the policies for a claim, as served at `/preflight`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PreflightReport {
+    pub dapp_address: Address,
+    pub epoch_index: u64,
+    pub first_index: u128,
+    pub last_index: u128,
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+    /// Whether a failing policy kept the claim from being sent
+    pub blocked: bool,
+    pub verdicts: Vec<Verdict>,
+}
+
+/// State of the pre-flight checks, as served at `/preflight`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PreflightStatus {
+    pub policies: Vec<PreflightPolicy>,
+    #[serde(serialize_with = "serialize_budget")]
+    pub gas_budget: Option<U256>,
+    pub held_claims: usize,
+    /// From the newest to the oldest
+    pub reports: Vec<PreflightReport>,
+}
+
+/// Chain of policies every claim must pass before it is sent. The verdict
+/// of each policy is logged and kept for `/preflight`; a claim that fails
+/// any of them is held, along with the claims after it, and checked again
+/// on the next reorg check.
+#[derive(Clone, Debug)]
+pub struct Preflight {
+    policies: Vec<PreflightPolicy>,
+    http_client: HttpClient,
+    settings: ProviderSettingsReceiver,
+    input_box_address: Option<Address>,
+    genesis_block: u64,
+    blackouts: Blackouts,
+    evidence_store: Option<EvidenceStore>,
+    ledger: Option<Ledger>,
+    gas_budget: Option<GasBudget>,
+    held: Arc<Mutex<Vec<RollupsClaim>>>,
+    reports: Arc<Mutex<VecDeque<PreflightReport>>>,
+}
+
+impl Preflight {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        policies: Vec<PreflightPolicy>,
+        http_client: HttpClient,
+        settings: ProviderSettingsReceiver,
+        input_box_address: Option<Address>,
+        genesis_block: u64,
+        blackouts: Blackouts,
+        evidence_store: Option<EvidenceStore>,
+        ledger: Option<Ledger>,
+        gas_budget: Option<GasBudget>,
+    ) -> Self {
+        Self {
+            policies,
+            http_client,
+            settings,
+            input_box_address,
+            genesis_block,
+            blackouts,
+            evidence_store,
+            ledger,
+            gas_budget,
+            held: Default::default(),
+            reports: Default::default(),
+        }
+    }
+
+    /// Runs every policy on the claim, in order, and records the report
+    pub async fn check(&self, rollups_claim: &RollupsClaim) -> PreflightReport {
+        let mut verdicts = Vec::with_capacity(self.policies.len());
+        for &policy in &self.policies {
+            let outcome = match policy {
+                PreflightPolicy::MachineHash => {
+                    self.check_machine_hash(rollups_claim).await
+                }
+                PreflightPolicy::Invariants => check_invariants(rollups_claim),
+                PreflightPolicy::Divergence => {
+                    self.check_divergence(rollups_claim)
+                }
+                PreflightPolicy::Budget => self.check_budget(SystemTime::now()),
+                PreflightPolicy::Blackout => {
+                    self.check_blackout(SystemTime::now())
+                }
+                PreflightPolicy::Finality => {
+                    self.check_finality(rollups_claim).await
+                }
+            };
+            let (passed, reason) = match outcome {
+                Ok(reason) => (true, reason),
+                Err(reason) => (false, reason),
+            };
+            if passed {
+                info!(
+                    %policy,
+                    epoch_index = rollups_claim.epoch_index,
+                    "Claim passed the pre-flight policy: {}",
+                    reason
+                );
+            } else {
+                warn!(
+                    %policy,
+                    epoch_index = rollups_claim.epoch_index,
+                    "Claim failed the pre-flight policy: {}",
+                    reason
+                );
+            }
+            verdicts.push(Verdict {
+                policy,
+                passed,
+                reason,
+            });
+        }
+
+        let report = PreflightReport {
+            dapp_address: rollups_claim.dapp_address.clone(),
+            epoch_index: rollups_claim.epoch_index,
+            first_index: rollups_claim.first_index,
+            last_index: rollups_claim.last_index,
+            checked_at: seconds_since_epoch(SystemTime::now()),
+            blocked: verdicts.iter().any(|verdict| !verdict.passed),
+            verdicts,
+        };
+        let mut reports = self.reports.lock().unwrap();
+        reports.push_front(report.clone());
+        reports.truncate(MAX_REPORTS);
+        report
+    }
+
+    /// Holds a claim until the next check, after the ones already held
+    pub fn hold(&self, rollups_claim: RollupsClaim) {
+        self.held.lock().unwrap().push(rollups_claim);
+    }
+
+    /// Removes the held claims, in the order they arrived
+    pub fn take_held(&self) -> Vec<RollupsClaim> {
+        mem::take(&mut *self.held.lock().unwrap())
+    }
+
+    pub fn has_held(&self) -> bool {
+        !self.held.lock().unwrap().is_empty()
+    }
+
+    pub fn status(&self) -> PreflightStatus {
+        PreflightStatus {
+            policies: self.policies.clone(),
+            gas_budget: self.gas_budget.as_ref().map(|budget| budget.amount),
+            held_claims: self.held.lock().unwrap().len(),
+            reports: self.reports.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+
+    /// Route that serves the policies and the reports of the last claims
+    /// checked as JSON (`/preflight`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/preflight", get(get_status))
+            .with_state(self)
+    }
+
+    async fn check_machine_hash(
+        &self,
+        rollups_claim: &RollupsClaim,
+    ) -> Result<String, String> {
+        let provider = Arc::new(self.provider());
+        let template_hash =
+            CartesiDApp::new(h160(&rollups_claim.dapp_address), provider)
+                .get_template_hash()
+                .call()
+                .await
+                .map(H256)
+                .map_err(|e| {
+                    format!("failed to read the template hash: {}", e)
+                })?;
+        if template_hash.is_zero() {
+            return Err("the DApp has no template hash".to_owned());
+        }
+        Ok(format!("template hash {:?}", template_hash))
+    }
+
+    fn check_divergence(
+        &self,
+        rollups_claim: &RollupsClaim,
+    ) -> Result<String, String> {
+        let Some(evidence_store) = &self.evidence_store else {
+            return Ok("conflicts aren't kept without an evidence directory"
+                .to_owned());
+        };
+        let conflicts = evidence_store
+            .conflicts(h160(&rollups_claim.dapp_address))
+            .map_err(|e| format!("failed to list the evidence: {}", e))?;
+        if !conflicts.is_empty() {
+            return Err(format!(
+                "{} conflicting claims to triage in the evidence directory",
+                conflicts.len()
+            ));
+        }
+        Ok("no conflicting claims to triage".to_owned())
+    }
+
+    fn check_budget(&self, now: SystemTime) -> Result<String, String> {
+        let Some(budget) = &self.gas_budget else {
+            return Err("no gas budget is set".to_owned());
+        };
+        let Some(ledger) = &self.ledger else {
+            return Err("no claim ledger accounts for the gas spent".to_owned());
+        };
+        let since =
+            seconds_since_epoch(now).saturating_sub(budget.period.as_secs());
+        let spent = ledger
+            .entries()
+            .map_err(|e| format!("failed to read the claim ledger: {}", e))?
+            .iter()
+            .filter(|entry| {
+                entry.account == Account::ClaimGas
+                    && entry.side == Side::Debit
+                    && entry.recorded_at >= since
+            })
+            .fold(U256::zero(), |spent, entry| spent + entry.amount);
+        if spent >= budget.amount {
+            return Err(format!(
+                "spent {} of the {} wei budget",
+                spent, budget.amount
+            ));
+        }
+        Ok(format!(
+            "spent {} of the {} wei budget",
+            spent, budget.amount
+        ))
+    }
+
+    fn check_blackout(&self, now: SystemTime) -> Result<String, String> {
+        match self.blackouts.active_until(now) {
+            Some(end) => Err(format!(
+                "blackout active until {}",
+                seconds_since_epoch(end)
+            )),
+            None => Ok("no active blackout".to_owned()),
+        }
+    }
+
+    async fn check_finality(
+        &self,
+        rollups_claim: &RollupsClaim,
+    ) -> Result<String, String> {
+        let Some(input_box_address) = &self.input_box_address else {
+            return Err("no input box address is set".to_owned());
+        };
+        let provider = Arc::new(self.provider());
+        let mut input_index = [0; 32];
+        U256::from(rollups_claim.last_index).to_big_endian(&mut input_index);
+        let inputs = InputBox::new(h160(input_box_address), provider.clone())
+            .input_added_filter()
+            .topic1(h160(&rollups_claim.dapp_address))
+            .topic2(H256(input_index))
+            .from_block(self.genesis_block)
+            .query_with_meta()
+            .await
+            .map_err(|e| format!("failed to query the last input: {}", e))?;
+        let Some((_, meta)) = inputs.first() else {
+            return Err(format!(
+                "input {} isn't in the input box",
+                rollups_claim.last_index
+            ));
+        };
+        let finalized = provider
+            .get_block(BlockNumber::Finalized)
+            .await
+            .map_err(|e| format!("failed to read the finalized block: {}", e))?
+            .and_then(|block| block.number)
+            .ok_or_else(|| "the chain has no finalized block".to_owned())?;
+        if meta.block_number > finalized {
+            return Err(format!(
+                "last input is in block {}, after the finalized block {}",
+                meta.block_number, finalized
+            ));
+        }
+        Ok(format!(
+            "last input is in block {}, finalized at {}",
+            meta.block_number, finalized
+        ))
+    }
+
+    fn provider(&self) -> http_provider::HttpProvider {
+        let endpoint = self.settings.borrow().provider_http_endpoint.clone();
+        self.http_client.provider(endpoint.inner().clone())
+    }
+}
+
+fn check_invariants(rollups_claim: &RollupsClaim) -> Result<String, String> {
+    if rollups_claim.first_index > rollups_claim.last_index {
+        return Err(format!(
+            "first input {} is after the last input {}",
+            rollups_claim.first_index, rollups_claim.last_index
+        ));
+    }
+    if rollups_claim
+        .epoch_hash
+        .inner()
+        .iter()
+        .all(|byte| *byte == 0)
+    {
+        return Err("the epoch hash is zero".to_owned());
+    }
+    if rollups_claim
+        .dapp_address
+        .inner()
+        .iter()
+        .all(|byte| *byte == 0)
+    {
+        return Err("the DApp address is zero".to_owned());
+    }
+    Ok(format!(
+        "inputs {} to {}",
+        rollups_claim.first_index, rollups_claim.last_index
+    ))
+}
+
+fn h160(address: &Address) -> H160 {
+    H160(address.inner().to_owned())
+}
+
+fn serialize_budget<S: serde::Serializer>(
+    budget: &Option<U256>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match budget {
+        Some(budget) => serialize_decimal(budget, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+async fn get_status(
+    State(preflight): State<Preflight>,
+) -> Json<PreflightStatus> {
+    Json(preflight.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ledger::FeeSchedule, reload::ProviderSettings};
+    use clap::Parser;
+    use eth_tx_manager::Priority;
+    use ethers::types::TransactionReceipt;
+    use http_provider::HttpClientCLIConfig;
+    use redacted::{RedactedUrl, Url};
+    use rollups_events::Hash;
+    use test_fixtures::RepositoryFixture;
+    use testcontainers::clients::Cli;
+    use tokio::sync::watch;
+
+    fn preflight(
+        policies: Vec<PreflightPolicy>,
+        ledger: Option<Ledger>,
+        gas_budget: Option<GasBudget>,
+    ) -> Preflight {
+        let http_client_config =
+            HttpClientCLIConfig::parse_from(["http_client_config"]).into();
+        let (_, settings) = watch::channel(ProviderSettings {
+            provider_http_endpoint: RedactedUrl::new(
+                Url::parse("http://localhost:8545").unwrap(),
+            ),
+            confirmations: 1,
+            priority: Priority::Normal,
+        });
+        Preflight::new(
+            policies,
+            HttpClient::new(&http_client_config).unwrap(),
+            settings,
+            None,
+            0,
+            Blackouts::default(),
+            None,
+            ledger,
+            gas_budget,
+        )
+    }
+
+    fn claim(epoch_index: u64, epoch_hash: u8) -> RollupsClaim {
+        RollupsClaim {
+            dapp_address: Address::new([0xaa; 20]),
+            epoch_index,
+            epoch_hash: Hash::new([epoch_hash; 32]),
+            first_index: epoch_index as u128,
+            last_index: epoch_index as u128,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_blocks_the_claims_that_fail_a_policy() {
+        let preflight = preflight(
+            vec![PreflightPolicy::Invariants, PreflightPolicy::Blackout],
+            None,
+            None,
+        );
+        let report = preflight.check(&claim(1, 1)).await;
+        assert!(!report.blocked);
+        assert_eq!(report.verdicts.len(), 2);
+
+        // Every policy runs, even after one fails
+        let report = preflight.check(&claim(2, 0)).await;
+        assert!(report.blocked);
+        assert_eq!(
+            report.verdicts[0],
+            Verdict {
+                policy: PreflightPolicy::Invariants,
+                passed: false,
+                reason: "the epoch hash is zero".to_owned(),
+            }
+        );
+        assert!(report.verdicts[1].passed);
+
+        let status = preflight.status();
+        let epochs: Vec<_> = status
+            .reports
+            .iter()
+            .map(|report| report.epoch_index)
+            .collect();
+        assert_eq!(epochs, vec![2, 1]);
+        let status = serde_json::to_value(status).unwrap();
+        assert_eq!(status["policies"][0], "invariants");
+        assert_eq!(status["gas_budget"], serde_json::Value::Null);
+
+        preflight.hold(claim(2, 0));
+        preflight.hold(claim(3, 3));
+        assert!(preflight.has_held());
+        assert_eq!(preflight.take_held(), vec![claim(2, 0), claim(3, 3)]);
+        assert!(!preflight.has_held());
+    }
+
+    #[test]
+    fn it_keeps_the_claims_within_the_gas_budget() {
+        let docker = Cli::default();
+        let fixture = RepositoryFixture::setup(&docker);
+        let ledger =
+            Ledger::new(fixture.repository().clone(), FeeSchedule::default());
+        let budget = GasBudget {
+            amount: 1000.into(),
+            period: Duration::from_secs(86400),
+        };
+        assert!(preflight(vec![], None, Some(budget.clone()))
+            .check_budget(SystemTime::now())
+            .is_err());
+
+        let preflight = preflight(vec![], Some(ledger.clone()), Some(budget));
+        let receipt = TransactionReceipt {
+            gas_used: Some(60.into()),
+            effective_gas_price: Some(10.into()),
+            ..Default::default()
+        };
+        ledger.record_claim(H160::zero(), 0, 1, &receipt).unwrap();
+        let now = SystemTime::now();
+        assert_eq!(
+            preflight.check_budget(now),
+            Ok("spent 600 of the 1000 wei budget".to_owned())
+        );
+        ledger.record_claim(H160::zero(), 1, 1, &receipt).unwrap();
+        assert!(preflight.check_budget(now).is_err());
+
+        // The gas spent before the period doesn't count
+        let later = now + Duration::from_secs(2 * 86400);
+        assert!(preflight.check_budget(later).is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_tx_manager::Priority;
+use redacted::RedactedUrl;
+use serde::Deserialize;
+use snafu::{ResultExt, Snafu};
+use std::fs;
+use tokio::sync::watch;
+use tracing::{error, info};
+use url::{ParseError, Url};
+
+use crate::config::AuthorityClaimerConfig;
+
+/// The access-layer settings that can be swapped without restarting the
+/// claimer.
+///
+/// The provider endpoint may embed API keys, so it is kept redacted.
+#[derive(Debug, Clone)]
+pub struct ProviderSettings {
+    pub provider_http_endpoint: RedactedUrl,
+    pub confirmations: usize,
+    pub priority: Priority,
+}
+
+/// Receives the latest validated `ProviderSettings`.
+pub type ProviderSettingsReceiver = watch::Receiver<ProviderSettings>;
+
+#[derive(Debug, Snafu)]
+pub enum ReloadError {
+    #[snafu(display("failed to read reload file `{}`", path))]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to parse reload file `{}`", path))]
+    ParseFile {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("invalid provider URL"))]
+    ProviderUrl { source: ParseError },
+
+    #[snafu(display("failed to install the reload signal handler"))]
+    SignalHandler { source: std::io::Error },
+}
+
+/// The contents of the reload file; missing fields keep their current values.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReloadFile {
+    provider_http_endpoint: Option<String>,
+    default_confirmations: Option<usize>,
+    priority: Option<Priority>,
+}
+
+impl ProviderSettings {
+    pub fn new(config: &AuthorityClaimerConfig) -> Result<Self, ReloadError> {
+        let url = Url::parse(&config.tx_manager_config.provider_http_endpoint)
+            .context(ProviderUrlSnafu)?;
+        Ok(Self {
+            provider_http_endpoint: RedactedUrl::new(url),
+            confirmations: config.tx_manager_config.default_confirmations,
+            priority: config.tx_manager_priority,
+        })
+    }
+
+    /// Returns a copy of the settings with the overrides found in `path`.
+    /// Every field is validated before anything is returned, so a bad file
+    /// never yields partially applied settings.
+    fn apply(&self, path: &str) -> Result<Self, ReloadError> {
+        let contents =
+            fs::read_to_string(path).context(ReadFileSnafu { path })?;
+        let file: ReloadFile =
+            serde_json::from_str(&contents).context(ParseFileSnafu { path })?;
+        let provider_http_endpoint = match file.provider_http_endpoint {
+            Some(endpoint) => RedactedUrl::new(
+                Url::parse(&endpoint).context(ProviderUrlSnafu)?,
+            ),
+            None => self.provider_http_endpoint.clone(),
+        };
+        Ok(Self {
+            provider_http_endpoint,
+            confirmations: file
+                .default_confirmations
+                .unwrap_or(self.confirmations),
+            priority: file.priority.unwrap_or(self.priority),
+        })
+    }
+}
+
+/// Signal that triggers a reload: SIGHUP on unix and Ctrl-Break on Windows,
+/// which has no hangup signal.
+struct ReloadSignal {
+    #[cfg(unix)]
+    inner: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    inner: tokio::signal::windows::CtrlBreak,
+}
+
+impl ReloadSignal {
+    #[cfg(unix)]
+    fn new() -> std::io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let inner = signal(SignalKind::hangup())?;
+        Ok(Self { inner })
+    }
+
+    #[cfg(windows)]
+    fn new() -> std::io::Result<Self> {
+        let inner = tokio::signal::windows::ctrl_break()?;
+        Ok(Self { inner })
+    }
+
+    async fn recv(&mut self) -> Option<()> {
+        self.inner.recv().await
+    }
+}
+
+/// Publishes the initial settings and, if a reload file was configured,
+/// re-reads it on every reload signal (see `ReloadSignal`).
+///
+/// The file is also applied once at startup, so reloaded values survive a
+/// restart. Invalid files are rejected at startup, but only logged on
+/// reload, in which case the current settings are kept.
+pub fn start(
+    initial: ProviderSettings,
+    path: Option<String>,
+) -> Result<ProviderSettingsReceiver, ReloadError> {
+    let Some(path) = path else {
+        let (_tx, rx) = watch::channel(initial);
+        return Ok(rx);
+    };
+
+    let initial = initial.apply(&path)?;
+    let mut reload_signal = ReloadSignal::new().context(SignalHandlerSnafu)?;
+    let (tx, rx) = watch::channel(initial);
+
+    tokio::spawn(async move {
+        while reload_signal.recv().await.is_some() {
+            info!("Reload signal received: reloading `{}`", path);
+            let current = tx.borrow().clone();
+            match current.apply(&path) {
+                Ok(settings) => {
+                    info!("Reloaded provider settings: {:?}", settings);
+                    if tx.send(settings).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    error!("Keeping the current provider settings: {}", err)
+                }
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+/// Returns the new settings if they changed since the last call.
+pub(crate) fn changed(
+    receiver: &mut ProviderSettingsReceiver,
+) -> Option<ProviderSettings> {
+    match receiver.has_changed() {
+        Ok(true) => Some(receiver.borrow_and_update().clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn settings() -> ProviderSettings {
+        ProviderSettings {
+            provider_http_endpoint: RedactedUrl::new(
+                Url::parse("http://localhost:8545").unwrap(),
+            ),
+            confirmations: 1,
+            priority: Priority::Normal,
+        }
+    }
+
+    fn reload_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn apply_overrides_only_the_given_fields() {
+        let file = reload_file(
+            r#"{"provider_http_endpoint": "https://node.example/key"}"#,
+        );
+        let settings = settings().apply(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(
+            settings.provider_http_endpoint.inner().as_str(),
+            "https://node.example/key"
+        );
+        assert_eq!(settings.confirmations, 1);
+        assert_eq!(settings.priority, Priority::Normal);
+    }
+
+    #[test]
+    fn apply_rejects_invalid_url() {
+        let file = reload_file(
+            r#"{"provider_http_endpoint": "not a url", "priority": "High"}"#,
+        );
+        let err = settings().apply(file.path().to_str().unwrap());
+        assert!(matches!(err, Err(ReloadError::ProviderUrl { .. })));
+    }
+
+    #[test]
+    fn apply_rejects_unknown_fields() {
+        let file = reload_file(r#"{"provider_ws_endpoint": "ws://x"}"#);
+        let err = settings().apply(file.path().to_str().unwrap());
+        assert!(matches!(err, Err(ReloadError::ParseFile { .. })));
+    }
+}
@@ -0,0 +1,598 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use async_trait::async_trait;
+use clap::Parser;
+use ethers::{
+    abi::{encode, Token},
+    types::{Signature, H160, H256},
+    utils::keccak256,
+};
+use grpc_interfaces::cartesi_epoch_hashes::{
+    epoch_hashes_client::EpochHashesClient, Attestation, DAppCursor, EpochHash,
+    SubscribeEpochHashesRequest,
+};
+use redacted::Redacted;
+use rollups_events::{Address, Hash, RollupsClaim};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{collections::HashMap, fmt, time::Duration};
+use tonic::{
+    metadata::{errors::InvalidMetadataValue, AsciiMetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Channel, ClientTlsConfig, Endpoint},
+    Request, Status, Streaming,
+};
+use tracing::{info, trace, warn};
+
+use crate::listener::BrokerListener;
+
+/// Scheme of the attestations signed by an attester key
+const SECP256K1_SCHEME: &str = "secp256k1";
+
+#[derive(Debug, Snafu)]
+pub enum RemoteEpochHashesError {
+    #[snafu(display("invalid remote epoch hashes endpoint `{}`", endpoint))]
+    InvalidEndpoint {
+        endpoint: String,
+        source: tonic::transport::Error,
+    },
+
+    #[snafu(display("invalid remote epoch hashes token"))]
+    InvalidToken { source: InvalidMetadataValue },
+
+    #[snafu(display("epoch {} has no attestation", epoch_index))]
+    Unattested { epoch_index: u64 },
+
+    #[snafu(display(
+        "epoch {} was computed from machine {:?}, but {:?} was expected",
+        epoch_index,
+        machine_hash,
+        expected
+    ))]
+    MachineMismatch {
+        epoch_index: u64,
+        machine_hash: H256,
+        expected: H256,
+    },
+
+    #[snafu(display(
+        "epoch {} is attested with the unsupported scheme `{}`",
+        epoch_index,
+        scheme
+    ))]
+    UnsupportedScheme { epoch_index: u64, scheme: String },
+
+    #[snafu(display(
+        "epoch {} is attested by {:?}, which isn't a known attester",
+        epoch_index,
+        attester
+    ))]
+    UnknownAttester { epoch_index: u64, attester: H160 },
+
+    #[snafu(display("epoch {} has a malformed {}", epoch_index, field))]
+    Malformed {
+        epoch_index: u64,
+        field: &'static str,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "remote_epoch_hashes_config")]
+pub struct RemoteEpochHashesCLIConfig {
+    /// gRPC endpoint of an external service that computes the epoch hashes,
+    /// such as `https://epochs.example.com:50051`. When set, the claims are
+    /// read from it instead of the broker
+    #[arg(long, env)]
+    pub remote_epoch_hashes_endpoint: Option<String>,
+
+    /// Token sent to the remote epoch hashes service as a bearer token
+    #[arg(long, env)]
+    pub remote_epoch_hashes_token: Option<String>,
+
+    /// Hash of the machine template the remote epoch hashes must be attested
+    /// to be computed from; other epoch hashes are rejected
+    #[arg(long, env)]
+    pub remote_epoch_hashes_machine_hash: Option<H256>,
+
+    /// Addresses of the keys whose `secp256k1` signatures are accepted as
+    /// the evidence of the attestations, separated by commas
+    #[arg(long, env, value_delimiter = ',')]
+    pub remote_epoch_hashes_attesters: Vec<H160>,
+
+    /// Accept remote epoch hashes without an attestation
+    #[arg(long, env, default_value_t = false)]
+    pub remote_epoch_hashes_allow_unattested: bool,
+
+    /// Time to wait before subscribing to the remote epoch hashes again after
+    /// the stream fails, such as `5s`
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration
+    )]
+    pub remote_epoch_hashes_retry_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteEpochHashesConfig {
+    pub endpoint: String,
+    pub token: Option<Redacted<String>>,
+    pub machine_hash: Option<H256>,
+    pub attesters: Vec<H160>,
+    pub allow_unattested: bool,
+    pub retry_interval: Duration,
+}
+
+impl RemoteEpochHashesCLIConfig {
+    /// The config of the remote service, if an endpoint is set
+    pub fn into_config(self) -> Option<RemoteEpochHashesConfig> {
+        let endpoint = self.remote_epoch_hashes_endpoint?;
+        Some(RemoteEpochHashesConfig {
+            endpoint,
+            token: self.remote_epoch_hashes_token.map(Redacted::new),
+            machine_hash: self.remote_epoch_hashes_machine_hash,
+            attesters: self.remote_epoch_hashes_attesters,
+            allow_unattested: self.remote_epoch_hashes_allow_unattested,
+            retry_interval: self.remote_epoch_hashes_retry_interval,
+        })
+    }
+}
+
+/// Adds the bearer token to the requests
+#[derive(Clone)]
+struct BearerToken(Option<AsciiMetadataValue>);
+
+impl fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(
+        &mut self,
+        mut request: Request<()>,
+    ) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Client = EpochHashesClient<InterceptedService<Channel, BearerToken>>;
+
+/// The `RemoteEpochHashesListener` reads the claims from an external service
+/// that computes the epoch hashes, instead of from the broker fed by a
+/// co-located server-manager.
+///
+/// The stream is subscribed again when it fails, starting after the last
+/// epoch received of each DApp; the epochs received again are skipped.
+#[derive(Debug)]
+pub struct RemoteEpochHashesListener {
+    config: RemoteEpochHashesConfig,
+    chain_id: u64,
+    client: Client,
+    stream: Option<Streaming<EpochHash>>,
+    /// Next epoch to receive of each DApp
+    cursors: HashMap<Address, u64>,
+}
+
+impl RemoteEpochHashesListener {
+    pub fn new(
+        config: RemoteEpochHashesConfig,
+        chain_id: u64,
+    ) -> Result<Self, RemoteEpochHashesError> {
+        let context = || InvalidEndpointSnafu {
+            endpoint: config.endpoint.clone(),
+        };
+        let mut endpoint = Endpoint::from_shared(config.endpoint.clone())
+            .with_context(|_| context())?;
+        if config.endpoint.starts_with("https://") {
+            endpoint = endpoint
+                .tls_config(ClientTlsConfig::new())
+                .with_context(|_| context())?;
+        }
+        let token = config
+            .token
+            .as_ref()
+            .map(|token| format!("Bearer {}", token.inner()).parse())
+            .transpose()
+            .context(InvalidTokenSnafu)?;
+        let client = EpochHashesClient::with_interceptor(
+            endpoint.connect_lazy(),
+            BearerToken(token),
+        );
+        Ok(Self {
+            config,
+            chain_id,
+            client,
+            stream: None,
+            cursors: HashMap::new(),
+        })
+    }
+
+    async fn subscribe(&mut self) -> Result<Streaming<EpochHash>, Status> {
+        trace!(
+            "Subscribing to the remote epoch hashes from {:?}",
+            self.cursors
+        );
+        let request = SubscribeEpochHashesRequest {
+            chain_id: self.chain_id,
+            from_epoch_index: 0,
+            cursors: self
+                .cursors
+                .iter()
+                .map(|(dapp_address, from_epoch_index)| DAppCursor {
+                    dapp_address: dapp_address.inner().to_vec(),
+                    from_epoch_index: *from_epoch_index,
+                })
+                .collect(),
+        };
+        let response = self.client.subscribe_epoch_hashes(request).await?;
+        Ok(response.into_inner())
+    }
+}
+
+impl RemoteEpochHashesListener {
+    /// Moves the cursor of the DApp past the claim, unless its epoch was
+    /// already received
+    fn advance(&mut self, rollups_claim: RollupsClaim) -> Option<RollupsClaim> {
+        let cursor = self
+            .cursors
+            .entry(rollups_claim.dapp_address.clone())
+            .or_default();
+        if rollups_claim.epoch_index < *cursor {
+            trace!(
+                "Skipping epoch {} of DApp {:?}, which was already received",
+                rollups_claim.epoch_index,
+                rollups_claim.dapp_address
+            );
+            return None;
+        }
+        *cursor = rollups_claim.epoch_index + 1;
+        Some(rollups_claim)
+    }
+}
+
+#[async_trait]
+impl BrokerListener for RemoteEpochHashesListener {
+    type Error = RemoteEpochHashesError;
+
+    async fn listen(&mut self) -> Result<RollupsClaim, Self::Error> {
+        loop {
+            let Some(stream) = &mut self.stream else {
+                match self.subscribe().await {
+                    Ok(stream) => self.stream = Some(stream),
+                    Err(status) => {
+                        warn!(
+                            "Failed to subscribe to the remote epoch hashes: {}; retrying in {:?}",
+                            status, self.config.retry_interval
+                        );
+                        tokio::time::sleep(self.config.retry_interval).await;
+                    }
+                }
+                continue;
+            };
+            match stream.message().await {
+                Ok(Some(epoch_hash)) => {
+                    let rollups_claim =
+                        into_claim(&self.config, self.chain_id, epoch_hash)?;
+                    if let Some(rollups_claim) = self.advance(rollups_claim) {
+                        return Ok(rollups_claim);
+                    }
+                    continue;
+                }
+                Ok(None) => {
+                    warn!("The remote epoch hashes stream ended");
+                }
+                Err(status) => {
+                    warn!(
+                        "Failed to read the remote epoch hashes: {}; retrying in {:?}",
+                        status, self.config.retry_interval
+                    );
+                }
+            }
+            self.stream = None;
+            tokio::time::sleep(self.config.retry_interval).await;
+        }
+    }
+}
+
+/// Checks the epoch hash and its attestation, converting it to a claim
+fn into_claim(
+    config: &RemoteEpochHashesConfig,
+    chain_id: u64,
+    epoch_hash: EpochHash,
+) -> Result<RollupsClaim, RemoteEpochHashesError> {
+    let epoch_index = epoch_hash.epoch_index;
+    let dapp_address = <[u8; 20]>::try_from(epoch_hash.dapp_address.as_slice())
+        .ok()
+        .context(MalformedSnafu {
+            epoch_index,
+            field: "DApp address",
+        })?;
+    let hash = <[u8; 32]>::try_from(epoch_hash.epoch_hash.as_slice())
+        .ok()
+        .context(MalformedSnafu {
+            epoch_index,
+            field: "epoch hash",
+        })?;
+    match &epoch_hash.attestation {
+        Some(attestation) => {
+            let machine_hash: H256 =
+                <[u8; 32]>::try_from(attestation.machine_hash.as_slice())
+                    .ok()
+                    .context(MalformedSnafu {
+                        epoch_index,
+                        field: "machine hash",
+                    })?
+                    .into();
+            if let Some(expected) = config.machine_hash {
+                ensure!(
+                    machine_hash == expected,
+                    MachineMismatchSnafu {
+                        epoch_index,
+                        machine_hash,
+                        expected,
+                    }
+                );
+            }
+            let attester =
+                verify_evidence(config, chain_id, &epoch_hash, attestation)?;
+            info!(
+                "Epoch {} was computed by worker `{}` from machine {:?} (attested by {:?})",
+                epoch_index,
+                attestation.worker_id,
+                machine_hash,
+                attester
+            );
+        }
+        None => {
+            ensure!(config.allow_unattested, UnattestedSnafu { epoch_index });
+        }
+    }
+    Ok(RollupsClaim {
+        dapp_address: Address::new(dapp_address),
+        epoch_index,
+        epoch_hash: Hash::new(hash),
+        first_index: epoch_hash.first_index.into(),
+        last_index: epoch_hash.last_index.into(),
+    })
+}
+
+/// Checks that the evidence of the attestation is the signature of one of
+/// the attesters, returning its address
+fn verify_evidence(
+    config: &RemoteEpochHashesConfig,
+    chain_id: u64,
+    epoch_hash: &EpochHash,
+    attestation: &Attestation,
+) -> Result<H160, RemoteEpochHashesError> {
+    let epoch_index = epoch_hash.epoch_index;
+    ensure!(
+        attestation.scheme == SECP256K1_SCHEME,
+        UnsupportedSchemeSnafu {
+            epoch_index,
+            scheme: attestation.scheme.clone(),
+        }
+    );
+    let context = || MalformedSnafu {
+        epoch_index,
+        field: "attestation evidence",
+    };
+    let signature = Signature::try_from(attestation.evidence.as_slice())
+        .ok()
+        .with_context(context)?;
+    let digest = attestation_digest(chain_id, epoch_hash, attestation);
+    let attester = signature.recover(digest).ok().with_context(context)?;
+    ensure!(
+        config.attesters.contains(&attester),
+        UnknownAttesterSnafu {
+            epoch_index,
+            attester,
+        }
+    );
+    Ok(attester)
+}
+
+/// Hash signed by the attesters, as described in the proto; the lengths of
+/// the hashes and address must have been checked
+fn attestation_digest(
+    chain_id: u64,
+    epoch_hash: &EpochHash,
+    attestation: &Attestation,
+) -> H256 {
+    keccak256(encode(&[
+        Token::Uint(chain_id.into()),
+        Token::Address(H160::from_slice(&epoch_hash.dapp_address)),
+        Token::Uint(epoch_hash.epoch_index.into()),
+        Token::FixedBytes(epoch_hash.epoch_hash.clone()),
+        Token::Uint(epoch_hash.first_index.into()),
+        Token::Uint(epoch_hash.last_index.into()),
+        Token::FixedBytes(attestation.machine_hash.clone()),
+        Token::Uint(attestation.computed_at.into()),
+    ]))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    const CHAIN_ID: u64 = 1;
+    const MACHINE_HASH: [u8; 32] = [7; 32];
+    const ATTESTER_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+    const OTHER_KEY: &str =
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    fn wallet(key: &str) -> LocalWallet {
+        key.parse().unwrap()
+    }
+
+    fn config(
+        machine_hash: Option<H256>,
+        allow_unattested: bool,
+    ) -> RemoteEpochHashesConfig {
+        RemoteEpochHashesConfig {
+            endpoint: "http://127.0.0.1:50051".to_string(),
+            token: None,
+            machine_hash,
+            attesters: vec![wallet(ATTESTER_KEY).address()],
+            allow_unattested,
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Signs the attestation of the epoch hash with the key
+    fn sign(mut epoch_hash: EpochHash, key: &str) -> EpochHash {
+        let attestation = epoch_hash.attestation.as_ref().unwrap();
+        let digest = attestation_digest(CHAIN_ID, &epoch_hash, attestation);
+        let evidence = wallet(key).sign_hash(digest).to_vec();
+        epoch_hash.attestation.as_mut().unwrap().evidence = evidence;
+        epoch_hash
+    }
+
+    fn epoch_hash(machine_hash: Option<[u8; 32]>) -> EpochHash {
+        let epoch_hash = EpochHash {
+            dapp_address: vec![1; 20],
+            epoch_index: 3,
+            epoch_hash: vec![2; 32],
+            first_index: 10,
+            last_index: 12,
+            attestation: machine_hash.map(|machine_hash| Attestation {
+                worker_id: "worker-0".to_string(),
+                machine_hash: machine_hash.to_vec(),
+                scheme: SECP256K1_SCHEME.to_string(),
+                evidence: vec![],
+                computed_at: 0,
+            }),
+        };
+        match machine_hash {
+            Some(_) => sign(epoch_hash, ATTESTER_KEY),
+            None => epoch_hash,
+        }
+    }
+
+    fn claim(dapp_address: u8, epoch_index: u64) -> RollupsClaim {
+        RollupsClaim {
+            dapp_address: Address::new([dapp_address; 20]),
+            epoch_index,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_converts_attested_epoch_hashes_to_claims() {
+        let config = config(Some(MACHINE_HASH.into()), false);
+        let claim =
+            into_claim(&config, CHAIN_ID, epoch_hash(Some(MACHINE_HASH)))
+                .expect("epoch hash should be accepted");
+        assert_eq!(claim.dapp_address, Address::new([1; 20]));
+        assert_eq!(claim.epoch_index, 3);
+        assert_eq!(claim.epoch_hash, Hash::new([2; 32]));
+        assert_eq!(claim.first_index, 10);
+        assert_eq!(claim.last_index, 12);
+    }
+
+    #[test]
+    fn it_rejects_epoch_hashes_of_other_machines() {
+        let config = config(Some(MACHINE_HASH.into()), false);
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID, epoch_hash(Some([8; 32]))),
+            Err(RemoteEpochHashesError::MachineMismatch { epoch_index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_unattested_epoch_hashes_unless_allowed() {
+        assert!(matches!(
+            into_claim(&config(None, false), CHAIN_ID, epoch_hash(None)),
+            Err(RemoteEpochHashesError::Unattested { epoch_index: 3 })
+        ));
+        assert!(
+            into_claim(&config(None, true), CHAIN_ID, epoch_hash(None)).is_ok()
+        );
+    }
+
+    #[test]
+    fn it_rejects_attestations_without_valid_evidence() {
+        let config = config(None, true);
+
+        let other_attester = sign(epoch_hash(Some(MACHINE_HASH)), OTHER_KEY);
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID, other_attester),
+            Err(RemoteEpochHashesError::UnknownAttester { .. })
+        ));
+
+        let mut tampered = epoch_hash(Some(MACHINE_HASH));
+        tampered.epoch_hash = vec![3; 32];
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID, tampered),
+            Err(RemoteEpochHashesError::UnknownAttester { .. })
+        ));
+
+        let other_chain = epoch_hash(Some(MACHINE_HASH));
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID + 1, other_chain),
+            Err(RemoteEpochHashesError::UnknownAttester { .. })
+        ));
+
+        let mut no_evidence = epoch_hash(Some(MACHINE_HASH));
+        no_evidence.attestation.as_mut().unwrap().evidence = vec![];
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID, no_evidence),
+            Err(RemoteEpochHashesError::Malformed {
+                field: "attestation evidence",
+                ..
+            })
+        ));
+
+        let mut other_scheme = epoch_hash(Some(MACHINE_HASH));
+        other_scheme.attestation.as_mut().unwrap().scheme = "tdx".to_string();
+        assert!(matches!(
+            into_claim(&config, CHAIN_ID, other_scheme),
+            Err(RemoteEpochHashesError::UnsupportedScheme { .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_malformed_epoch_hashes() {
+        let mut malformed = epoch_hash(Some(MACHINE_HASH));
+        malformed.epoch_hash = vec![2; 31];
+        assert!(matches!(
+            into_claim(&config(None, false), CHAIN_ID, malformed),
+            Err(RemoteEpochHashesError::Malformed {
+                field: "epoch hash",
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_keeps_a_cursor_per_dapp() {
+        let mut listener =
+            RemoteEpochHashesListener::new(config(None, false), CHAIN_ID)
+                .unwrap();
+        assert!(listener.advance(claim(1, 0)).is_some());
+        assert!(listener.advance(claim(1, 1)).is_some());
+        // Another DApp is behind the first one
+        assert!(listener.advance(claim(2, 0)).is_some());
+        // Received again after subscribing again
+        assert!(listener.advance(claim(1, 1)).is_none());
+        assert!(listener.advance(claim(2, 0)).is_none());
+        assert!(listener.advance(claim(2, 1)).is_some());
+        assert_eq!(
+            listener.cursors,
+            HashMap::from([
+                (Address::new([1; 20]), 2),
+                (Address::new([2; 20]), 2)
+            ])
+        );
+    }
+}
@@ -16,23 +16,28 @@ use ethers::{
     self,
     abi::AbiEncode,
     middleware::SignerMiddleware,
-    providers::{
-        Http, HttpRateLimitRetryPolicy, MockProvider, Provider, RetryClient,
-    },
+    providers::{MockProvider, Provider},
     signers::Signer,
     types::{Bytes, NameOrAddress, H160},
 };
+use http_provider::{HttpClient, HttpProvider};
 use rollups_events::{DAppMetadata, RollupsClaim};
 use snafu::{OptionExt, ResultExt, Snafu};
 use std::fmt::Debug;
 use std::sync::Arc;
-use tracing::{info, trace};
-use url::{ParseError, Url};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info, trace, warn};
+use url::Url;
 
 use crate::{
     config::AuthorityClaimerConfig,
+    failover::ClaimFence,
+    guard::{self, ChainGuardError},
+    ledger::Ledger,
     metrics::AuthorityClaimerMetrics,
-    signer::{ConditionalSigner, ConditionalSignerError},
+    reload::{self, ProviderSettings, ProviderSettingsReceiver},
+    signer::{ConditionalSigner, ConditionalSignerError, FencedSigner},
+    watcher::{ClaimWatcher, ClaimWatcherError},
 };
 
 /// The `TransactionSender` sends claims to the blockchain.
@@ -49,14 +54,19 @@ pub trait TransactionSender: Sized + Debug {
         self,
         rollups_claim: RollupsClaim,
     ) -> Result<Self, Self::Error>;
+
+    /// Returns the claims sent earlier whose transactions were removed from
+    /// the chain by a reorg, so they can be sent again.
+    async fn dropped_rollups_claims(
+        self,
+    ) -> Result<(Self, Vec<RollupsClaim>), Self::Error>;
 }
 
 // ------------------------------------------------------------------------------------------------
 // DefaultTransactionSender
 // ------------------------------------------------------------------------------------------------
 
-type Middleware =
-    Arc<SignerMiddleware<Provider<RetryClient<Http>>, ConditionalSigner>>;
+type Middleware = Arc<SignerMiddleware<HttpProvider, FencedSigner>>;
 
 type TransactionManager =
     eth_tx_manager::TransactionManager<Middleware, GasOracle, Database, Time>;
@@ -105,19 +115,29 @@ pub struct DefaultTransactionSender {
     authority: Authority<Provider<MockProvider>>,
     chain_id: u64,
     metrics: AuthorityClaimerMetrics,
+    signer: FencedSigner,
+    database_path: String,
+    chain: Chain,
+    http_client: HttpClient,
+    settings: ProviderSettingsReceiver,
+    ledger: Option<Ledger>,
+    watcher: ClaimWatcher,
 }
 
 #[derive(Debug, Snafu)]
 pub enum TransactionSenderError {
-    #[snafu(display("Invalid provider URL"))]
-    ProviderUrl { source: ParseError },
-
     #[snafu(display("Failed to initialize the transaction signer"))]
     Signer { source: ConditionalSignerError },
 
+    #[snafu(display("Refusing to sign for this chain"))]
+    ChainGuard { source: ChainGuardError },
+
     #[snafu(display("Transaction manager error"))]
     TransactionManager { source: TrasactionManagerError },
 
+    #[snafu(display("Failed to check the sent claims for reorgs"))]
+    ClaimWatcher { source: ClaimWatcherError },
+
     #[snafu(display("Internal ethers-rs error: tx `to` should not be null"))]
     InternalEthers,
 
@@ -129,33 +149,26 @@ pub enum TransactionSenderError {
 
 /// Creates the (layered) middleware instance to be sent to the tx-manager.
 fn create_middleware(
-    conditional_signer: ConditionalSigner,
-    provider_url: String,
-) -> Result<Middleware, TransactionSenderError> {
-    const MAX_RETRIES: u32 = 10;
-    const INITIAL_BACKOFF: u64 = 1000;
-    let url = Url::parse(&provider_url).context(ProviderUrlSnafu)?;
-    let base_layer = Http::new(url);
-    let retry_layer = Provider::new(RetryClient::new(
-        base_layer,
-        Box::new(HttpRateLimitRetryPolicy),
-        MAX_RETRIES,
-        INITIAL_BACKOFF,
-    ));
-    let signer_layer = SignerMiddleware::new(retry_layer, conditional_signer);
-    Ok(Arc::new(signer_layer))
+    http_client: &HttpClient,
+    signer: FencedSigner,
+    provider_url: Url,
+) -> Middleware {
+    let retry_layer = http_client.provider(provider_url);
+    let signer_layer = SignerMiddleware::new(retry_layer, signer);
+    Arc::new(signer_layer)
 }
 
 /// Creates the tx-manager instance.
 /// NOTE: tries to re-instantiate the tx-manager only once.
 async fn create_tx_manager(
-    conditional_signer: &ConditionalSigner,
-    provider_url: String,
+    http_client: &HttpClient,
+    signer: &FencedSigner,
+    provider_url: Url,
     database_path: String,
     chain: Chain,
 ) -> Result<TransactionManager, TransactionSenderError> {
     let middleware =
-        create_middleware(conditional_signer.clone(), provider_url)?;
+        create_middleware(http_client, signer.clone(), provider_url);
     let result = tx_manager!(new, middleware, database_path, chain);
     let tx_manager =
         if let Err(TrasactionManagerError::NonceTooLow { .. }) = result {
@@ -176,6 +189,10 @@ impl DefaultTransactionSender {
         config: AuthorityClaimerConfig,
         chain_id: u64,
         metrics: AuthorityClaimerMetrics,
+        http_client: HttpClient,
+        mut settings: ProviderSettingsReceiver,
+        ledger: Option<Ledger>,
+        fence: Option<ClaimFence>,
     ) -> Result<Self, TransactionSenderError> {
         let chain: Chain = (&config.tx_manager_config).into();
 
@@ -184,9 +201,38 @@ impl DefaultTransactionSender {
                 .await
                 .context(SignerSnafu)?;
 
+        let current = settings.borrow_and_update().clone();
+
+        // Refusing to start with a key or a provider meant for another chain.
+        config
+            .chain_guard_config
+            .check_signer(conditional_signer.address(), chain.id)
+            .context(ChainGuardSnafu)?;
+        let provider = http_client
+            .provider(current.provider_http_endpoint.inner().clone());
+        guard::check_provider_chain_id(&provider, chain.id)
+            .await
+            .context(ChainGuardSnafu)?;
+        let contracts = [
+            ("Authority", &config.contracts_config.authority_address),
+            ("History", &config.contracts_config.history_address),
+        ];
+        for (contract, address) in contracts {
+            let address = H160(*address.inner());
+            config
+                .chain_guard_config
+                .check_contract_code(&provider, contract, address)
+                .await
+                .context(ChainGuardSnafu)?;
+        }
+
+        // Signing only while the claim lease is held, so the transactions
+        // aren't resubmitted once another node takes over.
+        let signer = FencedSigner::new(conditional_signer, fence);
         let tx_manager = create_tx_manager(
-            &conditional_signer,
-            config.tx_manager_config.provider_http_endpoint.clone(),
+            &http_client,
+            &signer,
+            current.provider_http_endpoint.inner().clone(),
             config.tx_manager_config.database_path.clone(),
             chain,
         )
@@ -205,14 +251,75 @@ impl DefaultTransactionSender {
 
         Ok(Self {
             tx_manager,
-            confirmations: config.tx_manager_config.default_confirmations,
-            priority: config.tx_manager_priority,
-            from: conditional_signer.address(),
+            confirmations: current.confirmations,
+            priority: current.priority,
+            from: signer.address(),
             authority,
             chain_id,
             metrics,
+            signer,
+            database_path: config.tx_manager_config.database_path,
+            chain,
+            http_client,
+            settings,
+            ledger,
+            watcher: ClaimWatcher::new(config.claim_finality_depth),
         })
     }
+
+    /// Rebuilds the tx-manager if the provider settings were reloaded.
+    /// Pending transactions are recovered from the tx-manager database.
+    /// The current tx-manager and settings are kept if the new ones can't
+    /// be used, so a bad reload doesn't stop the claimer.
+    async fn reload_tx_manager(mut self) -> Self {
+        let Some(settings) = reload::changed(&mut self.settings) else {
+            return self;
+        };
+        info!("Swapping the transaction sender clients");
+        match self.reload_with(&settings).await {
+            Ok(tx_manager) => Self {
+                tx_manager,
+                confirmations: settings.confirmations,
+                priority: settings.priority,
+                ..self
+            },
+            Err(err) => {
+                error!(
+                    "Keeping the current transaction sender clients: {}",
+                    err
+                );
+                self
+            }
+        }
+    }
+
+    /// Builds a tx-manager with the reloaded `settings`.
+    async fn reload_with(
+        &self,
+        settings: &ProviderSettings,
+    ) -> Result<TransactionManager, TransactionSenderError> {
+        // Refusing to switch to a provider meant for another chain.
+        let provider = self
+            .http_client
+            .provider(settings.provider_http_endpoint.inner().clone());
+        guard::check_provider_chain_id(&provider, self.chain.id)
+            .await
+            .context(ChainGuardSnafu)?;
+        create_tx_manager(
+            &self.http_client,
+            &self.signer,
+            settings.provider_http_endpoint.inner().clone(),
+            self.database_path.clone(),
+            self.chain,
+        )
+        .await
+    }
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
 }
 
 #[async_trait]
@@ -223,23 +330,26 @@ impl TransactionSender for DefaultTransactionSender {
         self,
         rollups_claim: RollupsClaim,
     ) -> Result<Self, Self::Error> {
+        let mut sender = self.reload_tx_manager().await;
         let dapp_address = rollups_claim.dapp_address.clone();
+        let epoch_index = rollups_claim.epoch_index;
+        let inputs = rollups_claim.last_index - rollups_claim.first_index + 1;
 
         let transaction = {
             let submittable_claim = SubmittableClaim(
                 H160(dapp_address.inner().to_owned()),
-                rollups_claim,
+                rollups_claim.clone(),
             );
-            let call = self
+            let call = sender
                 .authority
                 .submit_claim(submittable_claim.into())
-                .from(self.from);
+                .from(sender.from);
             let to = match call.tx.to().context(InternalEthersSnafu)? {
                 NameOrAddress::Address(a) => *a,
                 _ => return Err(TransactionSenderError::InternalConfig),
             };
             Transaction {
-                from: self.from,
+                from: sender.from,
                 to,
                 value: Value::Nothing,
                 call_data: call.tx.data().cloned(),
@@ -248,20 +358,208 @@ impl TransactionSender for DefaultTransactionSender {
 
         trace!("Built claim transaction: `{:?}`", transaction);
 
-        let (tx_manager, receipt) = self
+        let metadata = DAppMetadata {
+            chain_id: sender.chain_id,
+            dapp_address: dapp_address.clone(),
+        };
+        // Cloned out of the family, whose lock can't be held across awaits
+        let pending_since = sender
+            .metrics
+            .claim_pending_since
+            .get_or_create(&metadata)
+            .clone();
+        pending_since.set(unix_time());
+        let result = sender
             .tx_manager
-            .send_transaction(transaction, self.confirmations, self.priority)
-            .await
-            .context(TransactionManagerSnafu)?;
-        self.metrics
-            .claims_sent
-            .get_or_create(&DAppMetadata {
-                chain_id: self.chain_id,
-                dapp_address,
-            })
-            .inc();
+            .send_transaction(
+                transaction,
+                sender.confirmations,
+                sender.priority,
+            )
+            .await;
+        pending_since.set(0);
+        let (tx_manager, receipt) = result.context(TransactionManagerSnafu)?;
+        sender.metrics.claims_sent.get_or_create(&metadata).inc();
+        if let Some(gas_used) = receipt.gas_used {
+            sender
+                .metrics
+                .claim_gas_used
+                .get_or_create(&metadata)
+                .inc_by(gas_used.low_u64());
+        }
         trace!("Claim transaction confirmed: `{:?}`", receipt);
+        sender.watcher.watch(rollups_claim, &receipt);
+
+        // The claim is already on chain, so failing to record it in the
+        // ledger must not fail the claim
+        if let Some(ledger) = sender.ledger.clone() {
+            let dapp_address = H160(dapp_address.inner().to_owned());
+            let recorded = tokio::task::spawn_blocking(move || {
+                ledger.record_claim(dapp_address, epoch_index, inputs, &receipt)
+            })
+            .await;
+            match recorded {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    warn!("Failed to record the claim in the ledger: {}", e)
+                }
+                Err(e) => {
+                    warn!("Failed to record the claim in the ledger: {}", e)
+                }
+            }
+        }
+
+        Ok(Self {
+            tx_manager,
+            ..sender
+        })
+    }
+
+    async fn dropped_rollups_claims(
+        mut self,
+    ) -> Result<(Self, Vec<RollupsClaim>), Self::Error> {
+        let provider_url = self
+            .settings
+            .borrow()
+            .provider_http_endpoint
+            .inner()
+            .clone();
+        let provider = self.http_client.provider(provider_url);
+        let claims = self
+            .watcher
+            .dropped_claims(&provider, self.confirmations)
+            .await
+            .context(ClaimWatcherSnafu)?;
+        for claim in &claims {
+            self.metrics
+                .dropped_claims
+                .get_or_create(&DAppMetadata {
+                    chain_id: self.chain_id,
+                    dapp_address: claim.dapp_address.clone(),
+                })
+                .inc();
+        }
+        Ok((self, claims))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use clap::Parser;
+    use eth_tx_manager::{transaction::Priority, Chain};
+    use ethers::{providers::Provider, signers::Signer, types::H160};
+    use http_provider::{HttpClient, HttpClientCLIConfig, HttpClientConfig};
+    use redacted::{Redacted, RedactedUrl};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tokio::{net::TcpListener, sync::watch};
+    use url::Url;
+
+    use super::{create_tx_manager, Authority, DefaultTransactionSender};
+    use crate::{
+        config::TxSigningConfig,
+        metrics::AuthorityClaimerMetrics,
+        reload::{ProviderSettings, ProviderSettingsReceiver},
+        signer::{ConditionalSigner, FencedSigner},
+        watcher::ClaimWatcher,
+    };
+
+    const CHAIN_ID: u64 = 1;
+
+    const PRIVATE_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    /// Starts a node that answers every request with the chain id 2
+    async fn start_node_of_another_chain() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let routes = Router::new().route(
+            "/",
+            post(|Json(request): Json<Value>| async move {
+                Json(json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": "0x2",
+                }))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, routes).await });
+        url
+    }
+
+    fn settings(url: Url, confirmations: usize) -> ProviderSettings {
+        ProviderSettings {
+            provider_http_endpoint: RedactedUrl::new(url),
+            confirmations,
+            priority: Priority::Normal,
+        }
+    }
+
+    /// Creates a sender with an empty tx-manager database, which is built
+    /// without calling the provider
+    async fn sender(
+        database_path: String,
+        settings: ProviderSettingsReceiver,
+    ) -> DefaultTransactionSender {
+        let current = settings.borrow().clone();
+        let http_client = HttpClient::new(&HttpClientConfig::from(
+            HttpClientCLIConfig::parse_from(["http_client_config"]),
+        ))
+        .unwrap();
+        let tx_signing_config = TxSigningConfig::PrivateKey {
+            private_key: Redacted::new(PRIVATE_KEY.to_string()),
+        };
+        let conditional_signer =
+            ConditionalSigner::new(CHAIN_ID, &tx_signing_config)
+                .await
+                .unwrap();
+        let signer = FencedSigner::new(conditional_signer, None);
+        let chain = Chain::new(CHAIN_ID);
+        let tx_manager = create_tx_manager(
+            &http_client,
+            &signer,
+            current.provider_http_endpoint.inner().clone(),
+            database_path.clone(),
+            chain,
+        )
+        .await
+        .unwrap();
+        let (provider, _mock) = Provider::mocked();
+        DefaultTransactionSender {
+            tx_manager,
+            confirmations: current.confirmations,
+            priority: current.priority,
+            from: signer.address(),
+            authority: Authority::new(H160::zero(), Arc::new(provider)),
+            chain_id: CHAIN_ID,
+            metrics: AuthorityClaimerMetrics::default(),
+            signer,
+            database_path,
+            chain,
+            http_client,
+            settings,
+            ledger: None,
+            watcher: ClaimWatcher::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_tx_manager_when_the_reload_fails() {
+        let database = tempfile::tempdir().unwrap();
+        let database_path =
+            database.path().join("tx.json").to_str().unwrap().to_owned();
+        let initial = Url::parse("http://127.0.0.1:8545").unwrap();
+        let (tx, rx) = watch::channel(settings(initial, 1));
+        let sender = sender(database_path, rx).await;
 
-        Ok(Self { tx_manager, ..self })
+        // The reloaded provider is meant for another chain
+        let url = start_node_of_another_chain().await;
+        tx.send(settings(url, 5)).unwrap();
+        let sender = sender.reload_tx_manager().await;
+        assert_eq!(sender.confirmations, 1);
+        assert_eq!(sender.priority, Priority::Normal);
     }
 }
@@ -0,0 +1,138 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use async_trait::async_trait;
+use ethers::{
+    signers::Signer,
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Signature,
+    },
+};
+use snafu::{ResultExt, Snafu};
+
+use crate::{
+    failover::{ClaimFence, FailoverError},
+    signer::{ConditionalSigner, ConditionalSignerError},
+};
+
+/// The `FencedSigner` only signs the transactions of the claims while the
+/// node holds the claim lease it was acquired with, if any.
+///
+/// The tx-manager signs a transaction again each time it resubmits it, so
+/// a node that lost the lease stops resubmitting its claims.
+#[derive(Debug, Clone)]
+pub struct FencedSigner {
+    signer: ConditionalSigner,
+    fence: Option<ClaimFence>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum FencedSignerError {
+    #[snafu(display("Refusing to sign without the claim lease"))]
+    Fence { source: FailoverError },
+
+    #[snafu(display("Signer error"))]
+    Signer { source: ConditionalSignerError },
+}
+
+impl FencedSigner {
+    pub fn new(signer: ConditionalSigner, fence: Option<ClaimFence>) -> Self {
+        Self { signer, fence }
+    }
+
+    fn check_fence(&self) -> Result<(), FencedSignerError> {
+        match &self.fence {
+            Some(fence) => fence.check().context(FenceSnafu),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for FencedSigner {
+    type Error = FencedSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        self.check_fence()?;
+        self.signer.sign_message(message).await.context(SignerSnafu)
+    }
+
+    async fn sign_transaction(
+        &self,
+        message: &TypedTransaction,
+    ) -> Result<Signature, Self::Error> {
+        self.check_fence()?;
+        self.signer
+            .sign_transaction(message)
+            .await
+            .context(SignerSnafu)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        self.check_fence()?;
+        self.signer
+            .sign_typed_data(payload)
+            .await
+            .context(SignerSnafu)
+    }
+
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.signer.chain_id()
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        Self {
+            signer: self.signer.with_chain_id(chain_id),
+            fence: self.fence,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{
+        transaction::eip2718::TypedTransaction, Address,
+        Eip1559TransactionRequest,
+    };
+    use ethers_signers::Signer;
+    use redacted::Redacted;
+
+    use crate::{
+        config::TxSigningConfig,
+        signer::{ConditionalSigner, FencedSigner},
+    };
+
+    const PRIVATE_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    #[tokio::test]
+    async fn sign_transaction_without_a_claim_lease() {
+        let tx_signing_config = TxSigningConfig::PrivateKey {
+            private_key: Redacted::new(PRIVATE_KEY.to_string()),
+        };
+        let conditional_signer =
+            ConditionalSigner::new(1, &tx_signing_config).await.unwrap();
+        let address = conditional_signer.address();
+        let signer = FencedSigner::new(conditional_signer, None);
+        assert_eq!(signer.address(), address);
+
+        let message = TypedTransaction::Eip1559(
+            Eip1559TransactionRequest::new()
+                .from(address)
+                .to(Address::default())
+                .gas(555),
+        );
+        assert!(signer.sign_transaction(&message).await.is_ok());
+    }
+}
@@ -3,6 +3,12 @@
 
 mod aws_credentials;
 mod aws_signer;
+mod fenced_signer;
+mod offline_signer;
 mod signer;
 
+pub use fenced_signer::{FencedSigner, FencedSignerError};
+pub use offline_signer::{
+    OfflineSigner, OfflineSignerError, SigningQueue, SigningRequest,
+};
 pub use signer::{ConditionalSigner, ConditionalSignerError};
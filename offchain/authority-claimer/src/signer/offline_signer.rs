@@ -0,0 +1,473 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use ethers::{
+    signers::Signer,
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Bytes, Signature, H256,
+    },
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+const REQUEST_EXTENSION: &str = "request.json";
+const SIGNATURE_EXTENSION: &str = "signature";
+
+#[derive(Debug, Snafu)]
+pub enum OfflineSignerError {
+    #[snafu(display("failed to write signing request `{}`", path.display()))]
+    WriteRequest { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to read signing request `{}`", path.display()))]
+    ReadRequest { path: PathBuf, source: io::Error },
+
+    #[snafu(display("signing request `{}` is corrupted", path.display()))]
+    CorruptedRequest {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("failed to read signature `{}`", path.display()))]
+    ReadSignature { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to write signature `{}`", path.display()))]
+    WriteSignature { path: PathBuf, source: io::Error },
+
+    #[snafu(display("no pending signing request for {:?}", sighash))]
+    UnknownRequest { sighash: H256 },
+
+    #[snafu(display("signature of {:?} isn't from {:?}", sighash, from))]
+    InvalidSignature { sighash: H256, from: Address },
+
+    #[snafu(display("offline keys only sign transactions"))]
+    Unsupported,
+}
+
+/// Unsigned transaction waiting for a signature of the offline key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningRequest {
+    /// Hash to be signed
+    pub sighash: H256,
+    pub from: Address,
+    pub chain_id: u64,
+    /// RLP encoding of the unsigned transaction, as taken by signing tools
+    pub unsigned_rlp: Bytes,
+    pub transaction: TypedTransaction,
+    /// Seconds since the Unix epoch
+    pub requested_at: u64,
+}
+
+impl SigningRequest {
+    /// Checks that `signature` is from the key of the request, normalizing
+    /// its `v` to EIP-155, as the local wallets do
+    fn verify(&self, signature: Signature) -> Option<Signature> {
+        let recovery_id = match signature.v {
+            0 | 1 => signature.v,
+            27 | 28 => signature.v - 27,
+            v if v >= 35 => (v - 35) % 2,
+            _ => return None,
+        };
+        let signature = Signature {
+            v: recovery_id + 35 + 2 * self.chain_id,
+            ..signature
+        };
+        let signer = signature.recover(self.sighash).ok()?;
+        (signer == self.from).then_some(signature)
+    }
+}
+
+/// Directory shared with the air-gapped signing machine.
+///
+/// Each request is written as `<sighash>.request.json`, and is answered by
+/// writing its signature, in hex, to `<sighash>.signature`, either by hand,
+/// such as by syncing the files from the signing machine, or through
+/// `POST /signing/<sighash>`. Signatures from other keys are discarded, so
+/// answering a request needs no other credentials.
+#[derive(Debug, Clone)]
+pub struct SigningQueue {
+    dir: PathBuf,
+}
+
+impl SigningQueue {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn request_path(&self, sighash: H256) -> PathBuf {
+        self.dir
+            .join(format!("{:?}.{}", sighash, REQUEST_EXTENSION))
+    }
+
+    fn signature_path(&self, sighash: H256) -> PathBuf {
+        self.dir
+            .join(format!("{:?}.{}", sighash, SIGNATURE_EXTENSION))
+    }
+
+    pub fn push(
+        &self,
+        request: &SigningRequest,
+    ) -> Result<(), OfflineSignerError> {
+        let path = self.request_path(request.sighash);
+        let json = serde_json::to_vec_pretty(request)
+            .expect("signing requests serialize");
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| write_atomically(&path, &json))
+            .context(WriteRequestSnafu { path })
+    }
+
+    fn read_request(
+        &self,
+        sighash: H256,
+    ) -> Result<Option<SigningRequest>, OfflineSignerError> {
+        let path = self.request_path(sighash);
+        match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map(Some)
+                .context(CorruptedRequestSnafu { path }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(ReadRequestSnafu { path }),
+        }
+    }
+
+    /// Requests still waiting for a signature, oldest first
+    pub fn pending(&self) -> Result<Vec<SigningRequest>, OfflineSignerError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).context(ReadRequestSnafu {
+                    path: self.dir.clone(),
+                })
+            }
+        };
+        let mut requests = vec![];
+        for entry in entries {
+            let entry = entry.context(ReadRequestSnafu {
+                path: self.dir.clone(),
+            })?;
+            let name = entry.file_name();
+            let Some(sighash) = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(REQUEST_EXTENSION))
+                .and_then(|name| name.strip_suffix('.'))
+                .and_then(|sighash| sighash.parse().ok())
+            else {
+                continue;
+            };
+            if let Some(request) = self.read_request(sighash)? {
+                requests.push(request);
+            }
+        }
+        requests.sort_by_key(|request| request.requested_at);
+        Ok(requests)
+    }
+
+    /// Stores the signature of a pending request, once checked against it
+    pub fn submit(
+        &self,
+        sighash: H256,
+        signature: Signature,
+    ) -> Result<(), OfflineSignerError> {
+        let request = self
+            .read_request(sighash)?
+            .ok_or(OfflineSignerError::UnknownRequest { sighash })?;
+        let signature = request.verify(signature).ok_or(
+            OfflineSignerError::InvalidSignature {
+                sighash,
+                from: request.from,
+            },
+        )?;
+        let path = self.signature_path(sighash);
+        write_atomically(&path, signature.to_string().as_bytes())
+            .context(WriteSignatureSnafu { path })
+    }
+
+    /// Takes the signature of the request, if it was given. Signatures that
+    /// don't check are removed, so they can be given again.
+    pub fn take_signature(
+        &self,
+        request: &SigningRequest,
+    ) -> Result<Option<Signature>, OfflineSignerError> {
+        let path = self.signature_path(request.sighash);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(ReadSignatureSnafu { path }),
+        };
+        let signature = text
+            .trim()
+            .parse::<Signature>()
+            .ok()
+            .and_then(|signature| request.verify(signature));
+        if signature.is_none() {
+            warn!(
+                "Discarding signature {:?} of {:?}, which isn't from {:?}",
+                path, request.sighash, request.from
+            );
+        }
+        let _ = fs::remove_file(&path);
+        if signature.is_some() {
+            let _ = fs::remove_file(self.request_path(request.sighash));
+        }
+        Ok(signature)
+    }
+
+    /// Routes that serve the pending requests as JSON (`/signing`) and take
+    /// their signatures (`POST /signing/<sighash>`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/signing", get(get_pending))
+            .route("/signing/:sighash", post(post_signature))
+            .with_state(self)
+    }
+}
+
+fn write_atomically(path: &PathBuf, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmittedSignature {
+    /// 65 bytes in hex
+    signature: String,
+}
+
+async fn get_pending(
+    State(queue): State<SigningQueue>,
+) -> Result<Json<Vec<SigningRequest>>, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || queue.pending())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn post_signature(
+    State(queue): State<SigningQueue>,
+    Path(sighash): Path<H256>,
+    Json(submitted): Json<SubmittedSignature>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let signature = submitted
+        .signature
+        .trim()
+        .parse::<Signature>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    tokio::task::spawn_blocking(move || queue.submit(sighash, signature))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(|_| StatusCode::ACCEPTED)
+        .map_err(|e| {
+            let code = match e {
+                OfflineSignerError::UnknownRequest { .. } => {
+                    StatusCode::NOT_FOUND
+                }
+                OfflineSignerError::InvalidSignature { .. } => {
+                    StatusCode::BAD_REQUEST
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (code, e.to_string())
+        })
+}
+
+/// The `OfflineSigner` signs with a key that never touches the node. Each
+/// transaction is queued unsigned, and signing waits until its signature is
+/// given; the tx-manager then broadcasts it as usual.
+///
+/// Since the tx-manager signs a transaction again whenever it bumps its gas,
+/// each bump is a new request.
+#[derive(Debug, Clone)]
+pub struct OfflineSigner {
+    address: Address,
+    chain_id: u64,
+    queue: SigningQueue,
+    poll_interval: Duration,
+}
+
+impl OfflineSigner {
+    pub fn new(
+        address: Address,
+        chain_id: u64,
+        dir: PathBuf,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            address,
+            chain_id,
+            queue: SigningQueue::new(dir),
+            poll_interval,
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for OfflineSigner {
+    type Error = OfflineSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        _message: S,
+    ) -> Result<Signature, Self::Error> {
+        Err(OfflineSignerError::Unsupported)
+    }
+
+    async fn sign_transaction(
+        &self,
+        message: &TypedTransaction,
+    ) -> Result<Signature, Self::Error> {
+        let mut transaction = message.clone();
+        if transaction.chain_id().is_none() {
+            transaction.set_chain_id(self.chain_id);
+        }
+        let request = SigningRequest {
+            sighash: transaction.sighash(),
+            from: self.address,
+            chain_id: transaction
+                .chain_id()
+                .map_or(self.chain_id, |chain_id| chain_id.as_u64()),
+            unsigned_rlp: transaction.rlp(),
+            transaction,
+            requested_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        };
+        self.queue.push(&request)?;
+        info!(
+            "Waiting for the offline signature of transaction {:?}",
+            request.sighash
+        );
+        loop {
+            if let Some(signature) = self.queue.take_signature(&request)? {
+                info!("Got the signature of transaction {:?}", request.sighash);
+                return Ok(signature);
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        _payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        Err(OfflineSignerError::Unsupported)
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::LocalWallet,
+        types::{Eip1559TransactionRequest, TransactionRequest},
+    };
+
+    const CHAIN_ID: u64 = 1;
+    const PRIVATE_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+    const OTHER_PRIVATE_KEY: &str =
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    fn request(
+        wallet: &LocalWallet,
+        transaction: TypedTransaction,
+    ) -> SigningRequest {
+        SigningRequest {
+            sighash: transaction.sighash(),
+            from: wallet.address(),
+            chain_id: CHAIN_ID,
+            unsigned_rlp: transaction.rlp(),
+            transaction,
+            requested_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_takes_the_signatures_of_the_offline_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = SigningQueue::new(dir.path().to_owned());
+        let wallet = PRIVATE_KEY
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(CHAIN_ID);
+        let transaction: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::default())
+            .data(vec![1, 2, 3])
+            .nonce(1)
+            .chain_id(CHAIN_ID)
+            .into();
+        let request = request(&wallet, transaction.clone());
+        queue.push(&request).unwrap();
+        assert_eq!(queue.pending().unwrap(), vec![request.clone()]);
+
+        // Signatures from other keys are refused
+        let other = OTHER_PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+        let signature = other.sign_hash(request.sighash);
+        assert!(matches!(
+            queue.submit(request.sighash, signature),
+            Err(OfflineSignerError::InvalidSignature { .. })
+        ));
+        assert!(matches!(
+            queue.submit(H256::zero(), signature),
+            Err(OfflineSignerError::UnknownRequest { .. })
+        ));
+        assert_eq!(queue.take_signature(&request).unwrap(), None);
+
+        // Air-gapped tools sign the hash with `v` as 27 or 28
+        let signature = wallet.sign_hash(request.sighash);
+        queue.submit(request.sighash, signature).unwrap();
+        let taken = queue.take_signature(&request).unwrap().unwrap();
+        assert_eq!(taken, wallet.sign_transaction(&transaction).await.unwrap());
+        assert!(queue.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_discards_bad_signature_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = SigningQueue::new(dir.path().to_owned());
+        let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+        let transaction: TypedTransaction = TransactionRequest::new()
+            .to(Address::default())
+            .nonce(1)
+            .chain_id(CHAIN_ID)
+            .into();
+        let request = request(&wallet, transaction);
+        queue.push(&request).unwrap();
+        let path = queue.signature_path(request.sighash);
+
+        fs::write(&path, "0x1234").unwrap();
+        assert_eq!(queue.take_signature(&request).unwrap(), None);
+        assert!(!path.exists());
+        assert_eq!(queue.pending().unwrap().len(), 1);
+    }
+}
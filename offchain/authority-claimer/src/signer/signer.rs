@@ -14,7 +14,13 @@ use ethers::{
 };
 use snafu::{ResultExt, Snafu};
 
-use crate::{config::TxSigningConfig, signer::aws_signer::AwsSigner};
+use crate::{
+    config::TxSigningConfig,
+    signer::{
+        aws_signer::AwsSigner,
+        offline_signer::{OfflineSigner, OfflineSignerError},
+    },
+};
 
 /// The `ConditionalSigner` is implementing conditional dispatch (instead of
 /// dynamic dispatch) by hand for objects that implement the `Sender` trait.
@@ -26,6 +32,7 @@ use crate::{config::TxSigningConfig, signer::aws_signer::AwsSigner};
 pub enum ConditionalSigner {
     LocalWallet(LocalWallet),
     AwsSigner(AwsSigner),
+    OfflineSigner(OfflineSigner),
 }
 
 #[derive(Debug, Snafu)]
@@ -35,6 +42,9 @@ pub enum ConditionalSignerError {
 
     #[snafu(display("AWS KMS signer error"))]
     AwsSigner { source: AwsSignerError },
+
+    #[snafu(display("Offline signer error"))]
+    OfflineSigner { source: OfflineSignerError },
 }
 
 impl ConditionalSigner {
@@ -73,6 +83,16 @@ impl ConditionalSigner {
                     .map(ConditionalSigner::AwsSigner)
                     .context(AwsSignerSnafu)
             }
+            TxSigningConfig::Offline {
+                address,
+                dir,
+                poll_interval,
+            } => Ok(ConditionalSigner::OfflineSigner(OfflineSigner::new(
+                address,
+                chain_id,
+                dir,
+                poll_interval,
+            ))),
         }
     }
 }
@@ -94,6 +114,10 @@ impl Signer for ConditionalSigner {
                 .sign_message(message)
                 .await
                 .context(AwsSignerSnafu),
+            Self::OfflineSigner(offline_signer) => offline_signer
+                .sign_message(message)
+                .await
+                .context(OfflineSignerSnafu),
         }
     }
 
@@ -110,6 +134,10 @@ impl Signer for ConditionalSigner {
                 .sign_transaction(message)
                 .await
                 .context(AwsSignerSnafu),
+            Self::OfflineSigner(offline_signer) => offline_signer
+                .sign_transaction(message)
+                .await
+                .context(OfflineSignerSnafu),
         }
     }
 
@@ -126,6 +154,10 @@ impl Signer for ConditionalSigner {
                 .sign_typed_data(payload)
                 .await
                 .context(AwsSignerSnafu),
+            Self::OfflineSigner(offline_signer) => offline_signer
+                .sign_typed_data(payload)
+                .await
+                .context(OfflineSignerSnafu),
         }
     }
 
@@ -133,6 +165,7 @@ impl Signer for ConditionalSigner {
         match &self {
             Self::LocalWallet(local_wallet) => local_wallet.address(),
             Self::AwsSigner(aws_signer) => aws_signer.address(),
+            Self::OfflineSigner(offline_signer) => offline_signer.address(),
         }
     }
 
@@ -140,6 +173,7 @@ impl Signer for ConditionalSigner {
         match &self {
             Self::LocalWallet(local_wallet) => local_wallet.chain_id(),
             Self::AwsSigner(aws_signer) => aws_signer.chain_id(),
+            Self::OfflineSigner(offline_signer) => offline_signer.chain_id(),
         }
     }
 
@@ -151,6 +185,9 @@ impl Signer for ConditionalSigner {
             Self::AwsSigner(aws_signer) => {
                 Self::AwsSigner(aws_signer.clone().with_chain_id(chain_id))
             }
+            Self::OfflineSigner(offline_signer) => Self::OfflineSigner(
+                offline_signer.clone().with_chain_id(chain_id),
+            ),
         }
     }
 }
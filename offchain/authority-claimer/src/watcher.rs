@@ -0,0 +1,420 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use ethers::{
+    providers::{Middleware, ProviderError},
+    types::{TransactionReceipt, H256},
+};
+use http_provider::HttpProvider;
+use rollups_events::RollupsClaim;
+use snafu::{ResultExt, Snafu};
+use std::collections::VecDeque;
+use tracing::{trace, warn};
+
+#[derive(Debug, Snafu)]
+pub enum ClaimWatcherError {
+    #[snafu(display("failed to call provider"))]
+    ProviderError { source: ProviderError },
+}
+
+/// Claim sent by this claimer, along with where its transaction landed
+#[derive(Clone, Debug)]
+struct SentClaim {
+    claim: RollupsClaim,
+    tx_hash: H256,
+    block_number: u64,
+    block_hash: H256,
+}
+
+/// What the chain currently says about a sent claim
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClaimStatus {
+    /// Still in the block it was included in
+    Included,
+    /// Deep enough that no reorg is expected to remove it
+    Final,
+    /// Re-included in another block after a reorg
+    Moved { block_number: u64, block_hash: H256 },
+    /// Removed from the chain, but the blocks that replaced it aren't deep
+    /// enough yet to be sure
+    Unsettled,
+    /// Removed from the chain for good, so it has to be sent again
+    Dropped,
+}
+
+/// Inputs of the status decision, as read from the chain
+#[derive(Clone, Copy, Debug)]
+struct ChainView {
+    latest: u64,
+    /// Hash of the canonical block at the height of the claim
+    canonical_hash: Option<H256>,
+    /// Block of the claim transaction in the canonical chain, if any
+    receipt: Option<(u64, H256)>,
+}
+
+fn claim_status(
+    sent: &SentClaim,
+    view: ChainView,
+    confirmations: u64,
+    finality_depth: u64,
+) -> ClaimStatus {
+    let depth = view.latest.saturating_sub(sent.block_number);
+    if view.canonical_hash == Some(sent.block_hash) {
+        return if depth >= finality_depth {
+            ClaimStatus::Final
+        } else {
+            ClaimStatus::Included
+        };
+    }
+    match view.receipt {
+        Some((block_number, block_hash)) => ClaimStatus::Moved {
+            block_number,
+            block_hash,
+        },
+        None if depth >= confirmations => ClaimStatus::Dropped,
+        None => ClaimStatus::Unsettled,
+    }
+}
+
+/// Keeps track of the claims sent by this claimer until they are final, so
+/// a reorg deeper than the confirmations that removes one of them is noticed
+/// and the claim is sent again, instead of assuming it's still on chain.
+///
+/// The claims are kept in memory; after a restart, the duplicate checker
+/// reads the claims from the chain again, so the dropped ones get claimed
+/// when the dispatcher sends them.
+#[derive(Debug)]
+pub struct ClaimWatcher {
+    sent_claims: VecDeque<SentClaim>,
+    finality_depth: u64,
+}
+
+impl ClaimWatcher {
+    pub fn new(finality_depth: u64) -> Self {
+        Self {
+            sent_claims: VecDeque::new(),
+            finality_depth,
+        }
+    }
+
+    /// Starts watching a claim whose transaction was confirmed
+    pub fn watch(&mut self, claim: RollupsClaim, receipt: &TransactionReceipt) {
+        let (Some(block_number), Some(block_hash)) =
+            (receipt.block_number, receipt.block_hash)
+        else {
+            warn!(
+                "Claim receipt `{:?}` has no block; not watching it",
+                receipt.transaction_hash
+            );
+            return;
+        };
+        self.sent_claims.push_back(SentClaim {
+            claim,
+            tx_hash: receipt.transaction_hash,
+            block_number: block_number.as_u64(),
+            block_hash,
+        });
+    }
+
+    /// Returns the watched claims that were dropped by a reorg, in the order
+    /// they were sent, and stops watching them and the final ones.
+    pub async fn dropped_claims(
+        &mut self,
+        provider: &HttpProvider,
+        confirmations: usize,
+    ) -> Result<Vec<RollupsClaim>, ClaimWatcherError> {
+        if self.sent_claims.is_empty() {
+            return Ok(vec![]);
+        }
+        let latest = provider
+            .get_block_number()
+            .await
+            .context(ProviderSnafu)?
+            .as_u64();
+
+        let mut dropped = vec![];
+        let mut kept = VecDeque::with_capacity(self.sent_claims.len());
+        for mut sent in self.sent_claims.drain(..) {
+            let view = chain_view(provider, &sent, latest).await?;
+            match claim_status(
+                &sent,
+                view,
+                confirmations as u64,
+                self.finality_depth,
+            ) {
+                ClaimStatus::Included | ClaimStatus::Unsettled => {
+                    kept.push_back(sent)
+                }
+                ClaimStatus::Final => {
+                    trace!("Claim `{:?}` is final", sent.tx_hash)
+                }
+                ClaimStatus::Moved {
+                    block_number,
+                    block_hash,
+                } => {
+                    warn!(
+                        "Claim `{:?}` was moved to block {} by a reorg",
+                        sent.tx_hash, block_number
+                    );
+                    sent.block_number = block_number;
+                    sent.block_hash = block_hash;
+                    kept.push_back(sent);
+                }
+                ClaimStatus::Dropped => {
+                    warn!(
+                        "Claim `{:?}` of block {} was dropped by a reorg",
+                        sent.tx_hash, sent.block_number
+                    );
+                    dropped.push(sent.claim);
+                }
+            }
+        }
+        self.sent_claims = kept;
+        Ok(dropped)
+    }
+}
+
+async fn chain_view(
+    provider: &HttpProvider,
+    sent: &SentClaim,
+    latest: u64,
+) -> Result<ChainView, ClaimWatcherError> {
+    let canonical_hash = provider
+        .get_block(sent.block_number)
+        .await
+        .context(ProviderSnafu)?
+        .and_then(|block| block.hash);
+    if canonical_hash == Some(sent.block_hash) {
+        return Ok(ChainView {
+            latest,
+            canonical_hash,
+            receipt: None,
+        });
+    }
+    let receipt = provider
+        .get_transaction_receipt(sent.tx_hash)
+        .await
+        .context(ProviderSnafu)?
+        .and_then(|receipt| {
+            Some((receipt.block_number?.as_u64(), receipt.block_hash?))
+        });
+    Ok(ChainView {
+        latest,
+        canonical_hash,
+        receipt,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_node;
+    use ethers::types::U64;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    const CONFIRMATIONS: u64 = 2;
+    const FINALITY_DEPTH: u64 = 10;
+
+    fn sent_claim() -> SentClaim {
+        SentClaim {
+            claim: RollupsClaim::default(),
+            tx_hash: H256::repeat_byte(0xaa),
+            block_number: 100,
+            block_hash: H256::repeat_byte(1),
+        }
+    }
+
+    fn status(view: ChainView) -> ClaimStatus {
+        claim_status(&sent_claim(), view, CONFIRMATIONS, FINALITY_DEPTH)
+    }
+
+    #[test]
+    fn it_keeps_included_claims_until_final() {
+        let view = ChainView {
+            latest: 105,
+            canonical_hash: Some(H256::repeat_byte(1)),
+            receipt: None,
+        };
+        assert_eq!(status(view), ClaimStatus::Included);
+        let view = ChainView {
+            latest: 110,
+            ..view
+        };
+        assert_eq!(status(view), ClaimStatus::Final);
+    }
+
+    #[test]
+    fn it_follows_claims_moved_by_a_reorg() {
+        let view = ChainView {
+            latest: 105,
+            canonical_hash: Some(H256::repeat_byte(2)),
+            receipt: Some((101, H256::repeat_byte(3))),
+        };
+        assert_eq!(
+            status(view),
+            ClaimStatus::Moved {
+                block_number: 101,
+                block_hash: H256::repeat_byte(3),
+            }
+        );
+    }
+
+    #[test]
+    fn it_drops_claims_once_the_reorg_settles() {
+        let view = ChainView {
+            latest: 101,
+            canonical_hash: Some(H256::repeat_byte(2)),
+            receipt: None,
+        };
+        assert_eq!(status(view), ClaimStatus::Unsettled);
+        let view = ChainView {
+            latest: 102,
+            ..view
+        };
+        assert_eq!(status(view), ClaimStatus::Dropped);
+
+        // The chain became shorter than the block of the claim
+        let view = ChainView {
+            latest: 98,
+            canonical_hash: None,
+            receipt: None,
+        };
+        assert_eq!(status(view), ClaimStatus::Unsettled);
+    }
+
+    fn block(number: u64, hash: H256) -> Value {
+        json!({
+            "hash": hash,
+            "parentHash": H256::zero(),
+            "sha3Uncles": H256::zero(),
+            "miner": ethers::types::Address::zero(),
+            "stateRoot": H256::zero(),
+            "transactionsRoot": H256::zero(),
+            "receiptsRoot": H256::zero(),
+            "logsBloom": ethers::types::Bloom::zero(),
+            "difficulty": "0x0",
+            "totalDifficulty": "0x0",
+            "number": U64::from(number),
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x0",
+            "timestamp": "0x0",
+            "extraData": "0x",
+            "mixHash": H256::zero(),
+            "nonce": "0x0000000000000000",
+            "size": "0x0",
+            "transactions": [],
+            "uncles": [],
+        })
+    }
+
+    fn receipt(tx_hash: H256, number: u64, hash: H256) -> Value {
+        json!({
+            "transactionHash": tx_hash,
+            "transactionIndex": "0x0",
+            "blockHash": hash,
+            "blockNumber": U64::from(number),
+            "from": ethers::types::Address::zero(),
+            "to": ethers::types::Address::zero(),
+            "cumulativeGasUsed": "0x0",
+            "gasUsed": "0x0",
+            "contractAddress": null,
+            "logs": [],
+            "logsBloom": ethers::types::Bloom::zero(),
+            "status": "0x1",
+            "type": "0x2",
+            "effectiveGasPrice": "0x0",
+        })
+    }
+
+    /// Chain of the fake node, which the tests reorg
+    #[derive(Default)]
+    struct Chain {
+        latest: u64,
+        blocks: Vec<(u64, H256)>,
+        receipts: Vec<(H256, u64, H256)>,
+    }
+
+    fn serve(chain: Arc<Mutex<Chain>>) -> HttpProvider {
+        fake_node::start(move |method, params| {
+            let chain = chain.lock().unwrap();
+            match method {
+                "eth_blockNumber" => json!(U64::from(chain.latest)),
+                "eth_getBlockByNumber" => {
+                    let number: U64 =
+                        serde_json::from_value(params[0].clone()).unwrap();
+                    chain
+                        .blocks
+                        .iter()
+                        .find(|(n, _)| *n == number.as_u64())
+                        .map_or(Value::Null, |(n, hash)| block(*n, *hash))
+                }
+                "eth_getTransactionReceipt" => {
+                    let tx_hash: H256 =
+                        serde_json::from_value(params[0].clone()).unwrap();
+                    chain
+                        .receipts
+                        .iter()
+                        .find(|(hash, _, _)| *hash == tx_hash)
+                        .map_or(Value::Null, |(hash, n, block_hash)| {
+                            receipt(*hash, *n, *block_hash)
+                        })
+                }
+                _ => panic!("unexpected method {}", method),
+            }
+        })
+    }
+
+    fn confirmed(tx: u8, block_number: u64, hash: H256) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: H256::repeat_byte(tx),
+            block_number: Some(block_number.into()),
+            block_hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_notices_the_claims_reorged_out_of_the_chain() {
+        let chain = Arc::new(Mutex::new(Chain {
+            latest: 100,
+            blocks: vec![(100, H256::repeat_byte(1))],
+            ..Default::default()
+        }));
+        let provider = serve(chain.clone());
+        let mut watcher = ClaimWatcher::new(FINALITY_DEPTH);
+        let claim = |epoch_index| RollupsClaim {
+            epoch_index,
+            ..Default::default()
+        };
+        watcher.watch(claim(0), &confirmed(0xa0, 100, H256::repeat_byte(1)));
+        watcher.watch(claim(1), &confirmed(0xa1, 100, H256::repeat_byte(1)));
+        let dropped = watcher.dropped_claims(&provider, 2).await.unwrap();
+        assert!(dropped.is_empty());
+
+        // A reorg replaces block 100; the first claim lands again in block
+        // 101, while the second one is gone
+        {
+            let mut chain = chain.lock().unwrap();
+            chain.latest = 101;
+            chain.blocks =
+                vec![(100, H256::repeat_byte(2)), (101, H256::repeat_byte(3))];
+            chain.receipts =
+                vec![(H256::repeat_byte(0xa0), 101, H256::repeat_byte(3))];
+        }
+        let dropped = watcher.dropped_claims(&provider, 2).await.unwrap();
+        assert!(dropped.is_empty(), "the reorg isn't settled yet");
+
+        chain.lock().unwrap().latest = 102;
+        let dropped = watcher.dropped_claims(&provider, 2).await.unwrap();
+        assert_eq!(dropped, vec![claim(1)]);
+
+        // The moved claim is followed in its new block until final
+        assert_eq!(watcher.sent_claims.len(), 1);
+        assert_eq!(watcher.sent_claims[0].block_number, 101);
+        chain.lock().unwrap().latest = 111;
+        let dropped = watcher.dropped_claims(&provider, 2).await.unwrap();
+        assert!(dropped.is_empty());
+        assert!(watcher.sent_claims.is_empty());
+    }
+}
@@ -1,9 +1,11 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use sha3::{Digest, Keccak256};
 use snafu::prelude::*;
 use std::error::Error;
 use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
@@ -11,35 +13,98 @@ use std::str;
 use eth_state_fold_types::contract;
 
 const ROLLUPS_CONTRACTS_URL: &str =
-    "https://registry.npmjs.org/@cartesi/rollups/-/rollups-1.2.0.tgz";
+    "https://registry.npmjs.org/@cartesi/rollups/-/rollups-{version}.tgz";
+
+/// Releases of the rollups contracts that get bindings generated.
+/// Each one ends up in `$OUT_DIR/v<major>_<minor>_<patch>/`.
+const ROLLUPS_CONTRACTS_VERSIONS: &[&str] = &["1.2.0"];
+
+/// Directory with vendored `rollups-<version>.tgz` artifacts.
+/// When set, the artifacts are read from it instead of being downloaded.
+const ROLLUPS_CONTRACTS_DIR_ENV: &str = "ROLLUPS_CONTRACTS_DIR";
+
+const CONTRACTS: &[(&str, &str, &str)] = &[
+    ("inputs", "InputBox", "input_box.rs"),
+    ("consensus/authority", "Authority", "authority.rs"),
+    ("history", "History", "history.rs"),
+    ("dapp", "CartesiDApp", "cartesi_dapp.rs"),
+    ("dapp", "CartesiDAppFactory", "cartesi_dapp_factory.rs"),
+];
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let tempdir = tempfile::tempdir()?;
-    let tarball = tempdir.path().join("rollups.tgz");
-    download_contracts(&tarball)?;
-    unzip_contracts(&tarball, tempdir.path())?;
-
-    let contracts = vec![
-        ("inputs", "InputBox", "input_box.rs"),
-        ("consensus/authority", "Authority", "authority.rs"),
-        ("history", "History", "history.rs"),
-        ("dapp", "CartesiDApp", "cartesi_dapp.rs"),
-    ];
-    for (contract_path, contract_name, bindings_file_name) in contracts {
-        let source_path = path(tempdir.path(), contract_path, contract_name);
-        let output_path: PathBuf =
-            [&std::env::var("OUT_DIR").unwrap(), bindings_file_name]
-                .iter()
-                .collect();
-        let source = File::open(&source_path)?;
-        let output = File::create(&output_path)?;
-        contract::write(contract_name, source, output)?;
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let vendored_dir =
+        std::env::var_os(ROLLUPS_CONTRACTS_DIR_ENV).map(PathBuf::from);
+
+    for version in ROLLUPS_CONTRACTS_VERSIONS {
+        let tempdir = tempfile::tempdir()?;
+        let tarball = contracts_tarball(
+            vendored_dir.as_deref(),
+            version,
+            tempdir.path(),
+        )?;
+        unzip_contracts(&tarball, tempdir.path())?;
+
+        let version_dir = out_dir.join(module_name(version));
+        std::fs::create_dir_all(&version_dir)?;
+        let mut code_hashes = vec![];
+        for (contract_path, contract_name, bindings_file_name) in CONTRACTS {
+            let source_path =
+                path(tempdir.path(), contract_path, contract_name);
+            let output_path = version_dir.join(bindings_file_name);
+            let source = File::open(&source_path)?;
+            let output = File::create(&output_path)?;
+            contract::write(contract_name, source, output)?;
+            code_hashes.push((*contract_name, code_hash(&source_path)?));
+        }
+        write_code_hashes(&version_dir.join("code_hashes.rs"), &code_hashes)?;
     }
 
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed={}", ROLLUPS_CONTRACTS_DIR_ENV);
     Ok(())
 }
 
+/// Converts `1.2.0` into `v1_2_0`, the module that holds its bindings.
+fn module_name(version: &str) -> String {
+    format!("v{}", version.replace('.', "_"))
+}
+
+/// Finds the artifacts of the contracts of `version`, in the vendored
+/// directory when one is given, or downloads them into `download_dir`.
+fn contracts_tarball(
+    vendored_dir: Option<&Path>,
+    version: &str,
+    download_dir: &Path,
+) -> Result<PathBuf, snafu::Whatever> {
+    match vendored_dir {
+        Some(dir) => {
+            let tarball = dir.join(format!("rollups-{}.tgz", version));
+            ensure_whatever!(
+                tarball.is_file(),
+                "{} not found; {} should hold the artifacts of every version \
+                 in ROLLUPS_CONTRACTS_VERSIONS",
+                tarball.display(),
+                ROLLUPS_CONTRACTS_DIR_ENV
+            );
+            Ok(tarball)
+        }
+        None => {
+            let tarball = download_dir.join("rollups.tgz");
+            download_contracts(version, &tarball).with_whatever_context(
+                |_| {
+                    format!(
+                        "failed to download the rollups contracts {}; set {} \
+                         to a directory with rollups-{}.tgz to build offline",
+                        version, ROLLUPS_CONTRACTS_DIR_ENV, version
+                    )
+                },
+            )?;
+            Ok(tarball)
+        }
+    }
+}
+
 fn run_cmd(cmd: &str, args: &[&str]) -> Result<(), snafu::Whatever> {
     let output = Command::new(cmd)
         .args(args)
@@ -53,11 +118,17 @@ fn run_cmd(cmd: &str, args: &[&str]) -> Result<(), snafu::Whatever> {
     Ok(())
 }
 
-fn download_contracts(output: &Path) -> Result<(), snafu::Whatever> {
+fn download_contracts(
+    version: &str,
+    output: &Path,
+) -> Result<(), snafu::Whatever> {
     run_cmd(
         "curl",
         &[
-            ROLLUPS_CONTRACTS_URL,
+            "--fail",
+            "--silent",
+            "--show-error",
+            &ROLLUPS_CONTRACTS_URL.replace("{version}", version),
             "-o",
             output.to_str().expect("failed to convert path"),
         ],
@@ -76,6 +147,30 @@ fn unzip_contracts(file: &Path, target: &Path) -> Result<(), snafu::Whatever> {
     )
 }
 
+/// Keccak-256 of the deployed code in the artifact of a contract
+fn code_hash(artifact_path: &Path) -> Result<[u8; 32], Box<dyn Error>> {
+    let artifact: serde_json::Value =
+        serde_json::from_reader(File::open(artifact_path)?)?;
+    let code = artifact["deployedBytecode"].as_str().ok_or_else(|| {
+        format!("no deployed bytecode in {}", artifact_path.display())
+    })?;
+    let code = hex::decode(code.trim_start_matches("0x"))?;
+    Ok(Keccak256::digest(code).into())
+}
+
+fn write_code_hashes(
+    output_path: &Path,
+    code_hashes: &[(&str, [u8; 32])],
+) -> Result<(), Box<dyn Error>> {
+    let mut output = File::create(output_path)?;
+    writeln!(output, "pub const CODE_HASHES: &[(&str, [u8; 32])] = &[")?;
+    for (contract_name, hash) in code_hashes {
+        writeln!(output, "    ({:?}, {:?}),", contract_name, hash)?;
+    }
+    writeln!(output, "];")?;
+    Ok(())
+}
+
 fn path(basedir: &Path, contract_path: &str, contract_name: &str) -> PathBuf {
     basedir
         .join("package/export/artifacts/contracts")
@@ -1,13 +1,16 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-/// Declares `$contract_name` as a module and includes everything from the `$contract_name` ABI.
+/// Declares `$contract_name` as a module and includes everything from the `$contract_name` ABI
+/// generated for the `$version` release of the rollups contracts.
 macro_rules! contract {
-    ($contract_name: ident) => {
+    ($version: ident, $contract_name: ident) => {
         pub mod $contract_name {
             include!(concat!(
                 env!("OUT_DIR"),
                 "/",
+                stringify!($version),
+                "/",
                 stringify!($contract_name),
                 ".rs"
             ));
@@ -15,7 +18,28 @@ macro_rules! contract {
     };
 }
 
-contract!(input_box);
-contract!(authority);
-contract!(history);
-contract!(cartesi_dapp);
+/// Bindings for the 1.2.0 release of the rollups contracts.
+pub mod v1_2_0 {
+    contract!(v1_2_0, input_box);
+    contract!(v1_2_0, authority);
+    contract!(v1_2_0, history);
+    contract!(v1_2_0, cartesi_dapp);
+    contract!(v1_2_0, cartesi_dapp_factory);
+
+    // Keccak-256 of the deployed code of each contract, by contract name
+    include!(concat!(env!("OUT_DIR"), "/v1_2_0/code_hashes.rs"));
+}
+
+// Stable paths for the bindings of the supported release. Code should import
+// from here, so that supporting a new release only changes these re-exports.
+pub use v1_2_0::{
+    authority, cartesi_dapp, cartesi_dapp_factory, history, input_box,
+};
+
+/// Keccak-256 hashes of the deployed code of the contracts of the supported
+/// releases, by contract name (e.g. `Authority`). The code of the contracts
+/// with immutables, such as `CartesiDApp`, differs between deployments, so
+/// their hashes only match the artifact itself.
+pub fn known_code_hashes() -> impl Iterator<Item = (&'static str, [u8; 32])> {
+    v1_2_0::CODE_HASHES.iter().copied()
+}
@@ -1,6 +1,59 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use std::{env, fmt::Write, fs, path::Path};
+
+/// Writes the version and checksum of each migration, in order, so the
+/// migrations applied to a database can be checked against the ones of the
+/// build
 fn main() {
     println!("cargo:rerun-if-changed=migrations");
+
+    let mut migrations: Vec<_> = fs::read_dir("migrations")
+        .expect("failed to read the migrations directory")
+        .map(|entry| entry.expect("failed to read migration").path())
+        .filter(|path| path.is_dir())
+        .collect();
+    migrations.sort();
+
+    let mut checksums = String::from("&[\n");
+    for migration in migrations {
+        let name = migration.file_name().unwrap().to_string_lossy();
+        // Same version diesel derives from the directory name
+        let version = name.split('_').next().unwrap().replace('-', "");
+        let mut sql = read(&migration.join("up.sql"));
+        sql.push(0);
+        sql.extend(read(&migration.join("down.sql")));
+        writeln!(
+            checksums,
+            "    (\"{}\", \"{:016x}\"),",
+            version,
+            fnv1a(&sql)
+        )
+        .unwrap();
+    }
+    checksums.push(']');
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("migration_checksums.rs"),
+        checksums,
+    )
+    .expect("failed to write the migration checksums");
+}
+
+/// Reads a migration without carriage returns, so a checkout with CRLF line
+/// endings has the same checksum
+fn read(path: &Path) -> Vec<u8> {
+    let mut sql = fs::read(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    sql.retain(|byte| *byte != b'\r');
+    sql
+}
+
+/// 64-bit FNV-1a, which is enough to tell an edited migration apart
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
 }
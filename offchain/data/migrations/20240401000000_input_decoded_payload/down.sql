@@ -0,0 +1,5 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+ALTER TABLE "inputs" DROP "payload_codec";
+ALTER TABLE "inputs" DROP "decoded_payload";
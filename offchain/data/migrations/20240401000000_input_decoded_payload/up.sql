@@ -0,0 +1,5 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+ALTER TABLE "inputs" ADD "decoded_payload" TEXT;
+ALTER TABLE "inputs" ADD "payload_codec" TEXT;
@@ -0,0 +1,9 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE "labels"
+(
+    "name" TEXT NOT NULL,
+    "value" TEXT NOT NULL,
+    CONSTRAINT "labels_pkey" PRIMARY KEY ("name")
+);
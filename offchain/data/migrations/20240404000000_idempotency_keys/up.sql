@@ -0,0 +1,11 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE "idempotency_keys"
+(
+    "key" TEXT NOT NULL,
+    "request" TEXT NOT NULL,
+    "result" TEXT,
+    "created_at" TIMESTAMP NOT NULL,
+    CONSTRAINT "idempotency_keys_pkey" PRIMARY KEY ("key")
+);
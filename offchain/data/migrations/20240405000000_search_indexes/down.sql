@@ -0,0 +1,10 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+DROP INDEX "reports_payload_sha256_idx";
+DROP INDEX "notices_payload_sha256_idx";
+DROP INDEX "vouchers_payload_sha256_idx";
+DROP INDEX "inputs_payload_sha256_idx";
+DROP INDEX "vouchers_destination_idx";
+DROP INDEX "inputs_msg_sender_idx";
+DROP INDEX "inputs_tx_hash_idx";
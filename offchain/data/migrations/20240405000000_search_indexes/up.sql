@@ -0,0 +1,10 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE INDEX "inputs_tx_hash_idx" ON "inputs" ("tx_hash");
+CREATE INDEX "inputs_msg_sender_idx" ON "inputs" ("msg_sender");
+CREATE INDEX "vouchers_destination_idx" ON "vouchers" ("destination");
+CREATE INDEX "inputs_payload_sha256_idx" ON "inputs" (sha256("payload"));
+CREATE INDEX "vouchers_payload_sha256_idx" ON "vouchers" (sha256("payload"));
+CREATE INDEX "notices_payload_sha256_idx" ON "notices" (sha256("payload"));
+CREATE INDEX "reports_payload_sha256_idx" ON "reports" (sha256("payload"));
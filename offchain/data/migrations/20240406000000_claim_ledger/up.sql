@@ -0,0 +1,19 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE "ledger_entries"
+(
+    "id" SERIAL,
+    "dapp_address" BYTEA NOT NULL,
+    "epoch_index" BIGINT NOT NULL,
+    "account" TEXT NOT NULL,
+    "side" TEXT NOT NULL,
+    "amount" TEXT NOT NULL,
+    "tx_hash" BYTEA NOT NULL,
+    "gas_used" TEXT,
+    "inputs" BIGINT,
+    "recorded_at" TIMESTAMP NOT NULL,
+    CONSTRAINT "ledger_entries_pkey" PRIMARY KEY ("id")
+);
+
+CREATE INDEX "ledger_entries_epoch_idx" ON "ledger_entries" ("dapp_address", "epoch_index");
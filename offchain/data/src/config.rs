@@ -6,7 +6,7 @@ use clap::Parser;
 pub use redacted::{RedactedUrl, Url};
 use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RepositoryConfig {
     pub redacted_endpoint: Option<RedactedUrl>,
     pub connection_pool_size: u32,
@@ -40,9 +40,14 @@ pub struct RepositoryCLIConfig {
     #[arg(long, env, default_value_t = 3)]
     postgres_connection_pool_size: u32,
 
-    /// Max elapsed time for timeout
-    #[arg(long, env, default_value = "120000")]
-    postgres_backoff_max_elapsed_duration: u64,
+    /// Max elapsed time for timeout, such as `2m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "2m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    postgres_backoff_max_elapsed_duration: Duration,
 }
 
 impl From<RepositoryCLIConfig> for RepositoryConfig {
@@ -61,11 +66,10 @@ impl From<RepositoryCLIConfig> for RepositoryConfig {
             }
         };
         let connection_pool_size = cli_config.postgres_connection_pool_size;
-        let backoff_max_elapsed_duration = Duration::from_millis(
-            cli_config.postgres_backoff_max_elapsed_duration,
-        );
         let backoff = ExponentialBackoffBuilder::new()
-            .with_max_elapsed_time(Some(backoff_max_elapsed_duration))
+            .with_max_elapsed_time(Some(
+                cli_config.postgres_backoff_max_elapsed_duration,
+            ))
             .build();
         RepositoryConfig {
             redacted_endpoint,
@@ -26,6 +26,9 @@ pub enum Error {
     #[snafu(display("failed to parse cursor"))]
     ParseCursorError { source: std::num::ParseIntError },
 
+    #[snafu(display("malformed cursor"))]
+    MalformedCursorError {},
+
     #[snafu(display(
         "cannot mix forward pagination (first, after) with backward pagination (last, before)"
     ))]
@@ -11,10 +11,16 @@ mod types;
 
 pub use config::{RedactedUrl, RepositoryCLIConfig, RepositoryConfig, Url};
 pub use error::Error;
-pub use migrations::{run_migrations, MigrationError};
-pub use pagination::{Connection, Cursor, Edge, PageInfo};
-pub use repository::Repository;
+pub use migrations::{
+    check_migrations, migration_versions, revert_migrations, run_migrations,
+    MigrationError, SUPPORTED_DOWNGRADES,
+};
+pub use pagination::{Connection, Cursor, Edge, OutputCursor, PageInfo};
+pub use repository::{Repository, SEARCH_LIMIT};
 pub use types::{
-    CompletionStatus, Input, InputQueryFilter, Notice, NoticeQueryFilter,
-    OutputEnum, Proof, Report, ReportQueryFilter, Voucher, VoucherQueryFilter,
+    AsOf, CompletionStatus, Consistency, EpochCounts, IdempotencyKey, Input,
+    InputQueryFilter, InputRangeRows, Label, LedgerEntry, NewLedgerEntry,
+    Notice, NoticeQueryFilter, OutputEnum, OutputLineage, Proof, Report,
+    ReportQueryFilter, SearchField, SearchHit, SearchKind, Voucher,
+    VoucherEntry, VoucherQueryFilter,
 };
@@ -1,13 +1,34 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use diesel::{pg::PgConnection, Connection};
+//! Migrations of the database schema.
+//!
+//! Besides the versions diesel keeps, the checksum of each applied migration
+//! is stored, so a migration edited after it was released, or a database
+//! migrated by a newer release, is caught before the schema is used. The
+//! last `SUPPORTED_DOWNGRADES` migrations can be reverted, so a release can
+//! be rolled back without editing the database by hand; their down
+//! migrations are tested with the up ones.
+
+use diesel::{
+    pg::PgConnection, sql_query, sql_types::Text, Connection, QueryableByName,
+    RunQueryDsl,
+};
 use diesel_migrations::{
     embed_migrations, EmbeddedMigrations, MigrationHarness,
 };
-use snafu::{ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::collections::HashMap;
+
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
+/// Version and checksum of each migration of the build, in order
+const CHECKSUMS: &[(&str, &str)] =
+    include!(concat!(env!("OUT_DIR"), "/migration_checksums.rs"));
+
+/// How many of the latest migrations can be reverted
+pub const SUPPORTED_DOWNGRADES: usize = 3;
+
 #[derive(Debug, Snafu)]
 pub enum MigrationError {
     #[snafu(display("connection error"))]
@@ -17,19 +38,224 @@ pub enum MigrationError {
     RunMigrationError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[snafu(display("failed to query the migration checksums"))]
+    ChecksumQueryError { source: diesel::result::Error },
+
+    #[snafu(display(
+        "migration {} was changed after it was applied (checksum {}, expected {})",
+        version,
+        applied,
+        expected
+    ))]
+    ChecksumMismatch {
+        version: String,
+        applied: String,
+        expected: String,
+    },
+
+    #[snafu(display(
+        "migration {} is unknown to this release; revert it with the release that applied it",
+        version
+    ))]
+    UnknownMigration { version: String },
+
+    #[snafu(display(
+        "there are pending migrations, which aren't run automatically: {:?}",
+        versions
+    ))]
+    PendingMigrations { versions: Vec<String> },
+
+    #[snafu(display(
+        "can't downgrade to {}; only the last {} migrations can be reverted",
+        version,
+        SUPPORTED_DOWNGRADES
+    ))]
+    UnsupportedDowngrade { version: String },
+}
+
+// Lets the migrations run in a transaction, whose own queries may fail
+impl From<diesel::result::Error> for MigrationError {
+    fn from(source: diesel::result::Error) -> Self {
+        MigrationError::ChecksumQueryError { source }
+    }
+}
+
+#[derive(QueryableByName)]
+struct AppliedChecksum {
+    #[diesel(sql_type = Text)]
+    version: String,
+    #[diesel(sql_type = Text)]
+    checksum: String,
 }
 
+/// Run the pending migrations, after checking the applied ones. They run in
+/// a single transaction with the recording of their checksums, so a failure
+/// doesn't leave a migration applied without its checksum.
 pub fn run_migrations(postgres_endpoint: &str) -> Result<(), MigrationError> {
     tracing::trace!("running pending migrations");
 
+    let mut connection = connect(postgres_endpoint)?;
+    verify_applied(&mut connection)?;
+    connection.transaction(|connection| {
+        let versions: Vec<String> = connection
+            .run_pending_migrations(MIGRATIONS)
+            .context(RunMigrationSnafu)?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        for version in versions {
+            record_checksum(connection, &version)?;
+            tracing::trace!("runned migration {}", version);
+        }
+        Ok(())
+    })
+}
+
+/// Check that the schema is up to date without migrating it, for when the
+/// migrations are applied by the operator
+pub fn check_migrations(postgres_endpoint: &str) -> Result<(), MigrationError> {
+    tracing::trace!("checking migrations");
+
+    let mut connection = connect(postgres_endpoint)?;
+    verify_applied(&mut connection)?;
+    let versions: Vec<String> = connection
+        .pending_migrations(MIGRATIONS)
+        .context(RunMigrationSnafu)?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+    ensure!(versions.is_empty(), PendingMigrationsSnafu { versions });
+    Ok(())
+}
+
+/// Revert the migrations applied after `version`, returning the reverted
+/// versions, latest first
+pub fn revert_migrations(
+    postgres_endpoint: &str,
+    version: &str,
+) -> Result<Vec<String>, MigrationError> {
+    tracing::trace!(version, "reverting migrations");
+
+    let position = CHECKSUMS
+        .iter()
+        .position(|(known, _)| *known == version)
+        .filter(|position| {
+            position + SUPPORTED_DOWNGRADES + 1 >= CHECKSUMS.len()
+        })
+        .context(UnsupportedDowngradeSnafu { version })?;
+    let target = CHECKSUMS[position].0;
+
+    let mut connection = connect(postgres_endpoint)?;
+    verify_applied(&mut connection)?;
+    let mut reverted = vec![];
+    loop {
+        let applied =
+            connection.applied_migrations().context(RunMigrationSnafu)?;
+        let latest = applied.iter().map(ToString::to_string).max();
+        match latest {
+            Some(latest) if latest.as_str() > target => {
+                connection
+                    .revert_last_migration(MIGRATIONS)
+                    .context(RunMigrationSnafu)?;
+                sql_query(
+                    "DELETE FROM __rollups_migration_checksums WHERE version = $1",
+                )
+                .bind::<Text, _>(&latest)
+                .execute(&mut connection)
+                .context(ChecksumQuerySnafu)?;
+                tracing::info!("reverted migration {}", latest);
+                reverted.push(latest);
+            }
+            _ => return Ok(reverted),
+        }
+    }
+}
+
+fn connect(postgres_endpoint: &str) -> Result<PgConnection, MigrationError> {
     let mut connection =
         PgConnection::establish(postgres_endpoint).context(ConnectionSnafu)?;
-    let migrations = connection
-        .run_pending_migrations(MIGRATIONS)
-        .context(RunMigrationSnafu)?;
-    for migration in migrations.iter() {
-        tracing::trace!("runned migration {}", migration);
+    sql_query(
+        "CREATE TABLE IF NOT EXISTS __rollups_migration_checksums (
+            version VARCHAR(50) PRIMARY KEY,
+            checksum VARCHAR(16) NOT NULL
+        )",
+    )
+    .execute(&mut connection)
+    .context(ChecksumQuerySnafu)?;
+    Ok(connection)
+}
+
+/// Checks the applied migrations against the ones of the build. The
+/// migrations applied before their checksums were kept are trusted.
+fn verify_applied(connection: &mut PgConnection) -> Result<(), MigrationError> {
+    let checksums: HashMap<String, String> = sql_query(
+        "SELECT version, checksum FROM __rollups_migration_checksums",
+    )
+    .load::<AppliedChecksum>(connection)
+    .context(ChecksumQuerySnafu)?
+    .into_iter()
+    .map(|applied| (applied.version, applied.checksum))
+    .collect();
+
+    let applied = connection.applied_migrations().context(RunMigrationSnafu)?;
+    for version in applied.iter().map(ToString::to_string) {
+        let expected = checksum(&version)
+            .context(UnknownMigrationSnafu { version: &version })?;
+        match checksums.get(&version) {
+            Some(applied) => ensure!(
+                applied == expected,
+                ChecksumMismatchSnafu {
+                    version,
+                    applied,
+                    expected,
+                }
+            ),
+            None => record_checksum(connection, &version)?,
+        }
     }
+    Ok(())
+}
 
+fn record_checksum(
+    connection: &mut PgConnection,
+    version: &str,
+) -> Result<(), MigrationError> {
+    let checksum =
+        checksum(version).context(UnknownMigrationSnafu { version })?;
+    sql_query(
+        "INSERT INTO __rollups_migration_checksums (version, checksum)
+        VALUES ($1, $2)
+        ON CONFLICT (version) DO UPDATE SET checksum = EXCLUDED.checksum",
+    )
+    .bind::<Text, _>(version)
+    .bind::<Text, _>(checksum)
+    .execute(connection)
+    .context(ChecksumQuerySnafu)?;
     Ok(())
 }
+
+/// Versions of the migrations of the build, in order
+pub fn migration_versions() -> Vec<&'static str> {
+    CHECKSUMS.iter().map(|(version, _)| *version).collect()
+}
+
+fn checksum(version: &str) -> Option<&'static str> {
+    CHECKSUMS
+        .iter()
+        .find(|(known, _)| *known == version)
+        .map(|(_, checksum)| *checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_has_a_checksum_for_every_migration() {
+        assert_eq!(CHECKSUMS[0].0, "00000000000000");
+        let versions = migration_versions();
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(CHECKSUMS.iter().all(|(_, checksum)| checksum.len() == 16));
+    }
+}
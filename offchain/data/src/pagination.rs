@@ -2,14 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine as _};
-use snafu::ResultExt;
+use snafu::{OptionExt, ResultExt};
 use std::fmt::Debug;
 
 use super::error::{
     DecodeBase64CursorSnafu, DecodeUTF8CursorSnafu, Error,
-    MixedPaginationSnafu, PaginationCursorSnafu, PaginationLimitSnafu,
-    ParseCursorSnafu,
+    MalformedCursorSnafu, MixedPaginationSnafu, PaginationCursorSnafu,
+    PaginationLimitSnafu, ParseCursorSnafu,
 };
+use super::types::Consistency;
 
 const DEFAULT_PAGINATION_LIMIT: i32 = 1000;
 
@@ -139,6 +140,7 @@ impl Pagination {
             total_count: self.total_count,
             edges,
             page_info,
+            consistency: Consistency::Latest,
         }
     }
 }
@@ -167,23 +169,152 @@ impl Cursor {
     }
 }
 
+/// Pagination over the outputs of the DApp that seeks to the position of the
+/// cursors instead of skipping an offset, so the cost of a page doesn't grow
+/// with how far it is from the first output.
 #[derive(Debug, PartialEq)]
-pub struct Connection<N: Debug> {
+pub struct KeysetPagination {
+    after: Option<OutputCursor>,
+    before: Option<OutputCursor>,
+    limit: i32,
+    backward: bool,
+}
+
+impl KeysetPagination {
+    pub fn new(
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<Self, Error> {
+        let forward = first.is_some() || after.is_some();
+        let backward = last.is_some() || before.is_some();
+        snafu::ensure!(!forward || !backward, MixedPaginationSnafu);
+        let limit = if backward {
+            ensure_limit!(last)
+        } else {
+            ensure_limit!(first)
+        };
+        Ok(Self {
+            after: after.as_deref().map(OutputCursor::decode).transpose()?,
+            before: before.as_deref().map(OutputCursor::decode).transpose()?,
+            limit,
+            backward,
+        })
+    }
+
+    /// Only outputs after this position go in the page
+    pub fn after(&self) -> Option<OutputCursor> {
+        self.after
+    }
+
+    /// Only outputs before this position go in the page
+    pub fn before(&self) -> Option<OutputCursor> {
+        self.before
+    }
+
+    /// Whether the page is taken from the end, in which case the outputs
+    /// must be queried in descending order
+    pub fn is_backward(&self) -> bool {
+        self.backward
+    }
+
+    /// Number of outputs to query; one more than the page size, to tell
+    /// whether there are outputs past the page
+    pub fn query_limit(&self) -> i32 {
+        self.limit + 1
+    }
+
+    /// Creates the connection from the outputs queried in the order given by
+    /// `is_backward`, up to `query_limit`
+    pub fn create_connection<T: Debug>(
+        &self,
+        mut nodes: Vec<T>,
+        cursor: impl Fn(&T) -> OutputCursor,
+        total_count: i32,
+    ) -> Connection<T, OutputCursor> {
+        let has_more = nodes.len() > self.limit as usize;
+        nodes.truncate(self.limit as usize);
+        if self.backward {
+            nodes.reverse();
+        }
+        let edges: Vec<_> = nodes
+            .into_iter()
+            .map(|node| Edge {
+                cursor: cursor(&node),
+                node,
+            })
+            .collect();
+        // The output at the cursor comes before/after the page
+        let (has_next_page, has_previous_page) = if self.backward {
+            (self.before.is_some(), has_more)
+        } else {
+            (has_more, self.after.is_some())
+        };
+        let page_info = PageInfo {
+            start_cursor: edges.first().map(|edge| edge.cursor),
+            end_cursor: edges.last().map(|edge| edge.cursor),
+            has_next_page,
+            has_previous_page,
+        };
+        Connection {
+            total_count,
+            edges,
+            page_info,
+            consistency: Consistency::Latest,
+        }
+    }
+}
+
+/// Position of an output in the DApp, which stays valid as new outputs are
+/// added, unlike the offset of a `Cursor`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputCursor {
+    pub input_index: i32,
+    pub index: i32,
+}
+
+impl OutputCursor {
+    /// Encode cursor as base64
+    pub fn encode(&self) -> String {
+        base64_engine.encode(format!("{}:{}", self.input_index, self.index))
+    }
+
+    /// Decode cursor from base64 String
+    pub fn decode(value: &str) -> Result<OutputCursor, Error> {
+        let bytes = base64_engine
+            .decode(value)
+            .context(DecodeBase64CursorSnafu)?;
+        let (input_index, index) = std::str::from_utf8(&bytes)
+            .context(DecodeUTF8CursorSnafu)?
+            .split_once(':')
+            .context(MalformedCursorSnafu)?;
+        Ok(OutputCursor {
+            input_index: input_index.parse().context(ParseCursorSnafu)?,
+            index: index.parse().context(ParseCursorSnafu)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Connection<N: Debug, C = Cursor> {
     pub total_count: i32,
-    pub edges: Vec<Edge<N>>,
-    pub page_info: PageInfo,
+    pub edges: Vec<Edge<N, C>>,
+    pub page_info: PageInfo<C>,
+    /// Tier of the entries of the connection
+    pub consistency: Consistency,
 }
 
 #[derive(Debug, PartialEq)]
-pub struct Edge<N: Debug> {
+pub struct Edge<N: Debug, C = Cursor> {
     pub node: N,
-    pub cursor: Cursor,
+    pub cursor: C,
 }
 
 #[derive(Debug, PartialEq)]
-pub struct PageInfo {
-    pub start_cursor: Option<Cursor>,
-    pub end_cursor: Option<Cursor>,
+pub struct PageInfo<C = Cursor> {
+    pub start_cursor: Option<C>,
+    pub end_cursor: Option<C>,
     pub has_next_page: bool,
     pub has_previous_page: bool,
 }
@@ -488,7 +619,8 @@ mod tests {
                     end_cursor: None,
                     has_next_page: false,
                     has_previous_page: false,
-                }
+                },
+                consistency: Consistency::Latest,
             }
         );
     }
@@ -528,7 +660,8 @@ mod tests {
                     end_cursor: Some(Cursor { offset: 2 }),
                     has_next_page: false,
                     has_previous_page: false,
-                }
+                },
+                consistency: Consistency::Latest,
             }
         );
     }
@@ -561,7 +694,8 @@ mod tests {
                     end_cursor: Some(Cursor { offset: 1 }),
                     has_next_page: true,
                     has_previous_page: false,
-                }
+                },
+                consistency: Consistency::Latest,
             }
         );
     }
@@ -594,7 +728,8 @@ mod tests {
                     end_cursor: Some(Cursor { offset: 2 }),
                     has_next_page: false,
                     has_previous_page: true,
-                }
+                },
+                consistency: Consistency::Latest,
             }
         );
     }
@@ -621,8 +756,79 @@ mod tests {
                     end_cursor: Some(Cursor { offset: 1 }),
                     has_next_page: true,
                     has_previous_page: true,
-                }
+                },
+                consistency: Consistency::Latest,
             }
         );
     }
+
+    #[test]
+    fn it_encodes_and_decodes_output_cursor() {
+        let cursor = OutputCursor {
+            input_index: 12,
+            index: 3,
+        };
+        assert_eq!(cursor.encode(), "MTI6Mw==");
+        assert_eq!(OutputCursor::decode("MTI6Mw==").unwrap(), cursor);
+    }
+
+    #[test]
+    fn it_fails_to_decode_offset_cursor_as_output_cursor() {
+        let cursor = Cursor { offset: 12 }.encode();
+        assert!(matches!(
+            OutputCursor::decode(&cursor).unwrap_err(),
+            Error::MalformedCursorError { .. }
+        ));
+    }
+
+    fn output(input_index: i32, index: i32) -> OutputCursor {
+        OutputCursor { input_index, index }
+    }
+
+    #[test]
+    fn it_creates_keyset_connection_forward() {
+        let pagination =
+            KeysetPagination::new(Some(2), None, None, None).unwrap();
+        assert_eq!(pagination.query_limit(), 3);
+        let nodes = vec![output(0, 0), output(0, 1), output(2, 0)];
+        let connection = pagination.create_connection(nodes, |c| *c, 5);
+        assert_eq!(connection.edges.len(), 2);
+        assert_eq!(connection.page_info.start_cursor, Some(output(0, 0)));
+        assert_eq!(connection.page_info.end_cursor, Some(output(0, 1)));
+        assert!(connection.page_info.has_next_page);
+        assert!(!connection.page_info.has_previous_page);
+
+        let after = output(0, 1).encode();
+        let pagination =
+            KeysetPagination::new(Some(2), None, Some(after), None).unwrap();
+        assert_eq!(pagination.after(), Some(output(0, 1)));
+        let nodes = vec![output(2, 0)];
+        let connection = pagination.create_connection(nodes, |c| *c, 5);
+        assert_eq!(connection.edges.len(), 1);
+        assert!(!connection.page_info.has_next_page);
+        assert!(connection.page_info.has_previous_page);
+    }
+
+    #[test]
+    fn it_creates_keyset_connection_backward() {
+        let before = output(2, 0).encode();
+        let pagination =
+            KeysetPagination::new(None, Some(2), None, Some(before)).unwrap();
+        assert!(pagination.is_backward());
+        // Queried in descending order
+        let nodes = vec![output(0, 1), output(0, 0)];
+        let connection = pagination.create_connection(nodes, |c| *c, 5);
+        assert_eq!(connection.page_info.start_cursor, Some(output(0, 0)));
+        assert_eq!(connection.page_info.end_cursor, Some(output(0, 1)));
+        assert!(connection.page_info.has_next_page);
+        assert!(!connection.page_info.has_previous_page);
+    }
+
+    #[test]
+    fn it_fails_to_mix_keyset_pagination_directions() {
+        assert!(matches!(
+            KeysetPagination::new(Some(1), Some(1), None, None).unwrap_err(),
+            Error::MixedPaginationError { .. }
+        ));
+    }
 }
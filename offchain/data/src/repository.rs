@@ -4,21 +4,41 @@
 use backoff::ExponentialBackoff;
 use diesel::pg::{Pg, PgConnection};
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
-use diesel::{insert_into, prelude::*, update};
+use diesel::{
+    delete, insert_into, prelude::*, sql_function, sql_query, sql_types::Bytea,
+    update, Connection as _,
+};
 use snafu::ResultExt;
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use super::config::RepositoryConfig;
 use super::error::{DatabaseConnectionSnafu, DatabaseSnafu, Error};
-use super::pagination::{Connection, Pagination};
+use super::migrations::migration_versions;
+use super::pagination::{
+    Connection, KeysetPagination, OutputCursor, Pagination,
+};
 use super::schema;
 use super::types::{
-    CompletionStatus, Input, InputQueryFilter, Notice, NoticeQueryFilter,
-    OutputEnum, Proof, Report, ReportQueryFilter, Voucher, VoucherQueryFilter,
+    AsOf, CompletionStatus, Consistency, EpochCounts, IdempotencyKey, Input,
+    InputQueryFilter, InputRangeRows, Label, LedgerEntry, NewLedgerEntry,
+    Notice, NoticeQueryFilter, OutputEnum, OutputLineage, Proof, Report,
+    ReportQueryFilter, SearchField, SearchHit, SearchKind, Voucher,
+    VoucherEntry, VoucherQueryFilter,
 };
 
 pub const POOL_CONNECTION_SIZE: u32 = 3;
 
+/// Rows of each kind returned by a search
+pub const SEARCH_LIMIT: i64 = 100;
+
+sql_function! {
+    /// SHA-256 of a value, by which the payloads are indexed
+    fn sha256(value: Bytea) -> Bytea;
+}
+
 #[derive(Clone, Debug)]
 pub struct Repository {
     // Connection is not thread safe to share between threads, we use connection pool
@@ -54,6 +74,20 @@ impl Repository {
         })
         .context(DatabaseConnectionSnafu)
     }
+
+    /// Checks that the database is reachable, without retrying, for the
+    /// health checks
+    pub fn ping(&self) -> Result<(), Error> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .map_err(backoff::Error::permanent)
+            .context(DatabaseConnectionSnafu)?;
+        sql_query("SELECT 1")
+            .execute(&mut conn)
+            .context(DatabaseSnafu)?;
+        Ok(())
+    }
 }
 
 /// Basic queries that fetch by primary_key
@@ -141,6 +175,54 @@ impl Repository {
             .map(|mut proofs| proofs.pop())
             .context(DatabaseSnafu)
     }
+
+    /// Get every proof, sorted by input, output type and output
+    pub fn get_proofs(&self) -> Result<Vec<Proof>, Error> {
+        use schema::proofs::dsl;
+        let mut conn = self.conn()?;
+        dsl::proofs
+            .order((
+                dsl::input_index.asc(),
+                dsl::output_enum.asc(),
+                dsl::output_index.asc(),
+            ))
+            .load::<Proof>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+
+    /// Get the proofs of the outputs of the epoch whose first input is
+    /// `first_input_index`, sorted by input, output type and output. They
+    /// are only indexed once the epoch is finished.
+    pub fn get_epoch_proofs(
+        &self,
+        first_input_index: i32,
+    ) -> Result<Vec<Proof>, Error> {
+        use schema::proofs::dsl;
+        let mut conn = self.conn()?;
+        dsl::proofs
+            .filter(dsl::input_index.ge(first_input_index))
+            .filter(
+                (dsl::input_index - dsl::validity_input_index_within_epoch)
+                    .eq(first_input_index),
+            )
+            .order((
+                dsl::input_index.asc(),
+                dsl::output_enum.asc(),
+                dsl::output_index.asc(),
+            ))
+            .load::<Proof>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+
+    /// Get the DApp labels sorted by name
+    pub fn get_labels(&self) -> Result<Vec<Label>, Error> {
+        use schema::labels::dsl;
+        let mut conn = self.conn()?;
+        dsl::labels
+            .order(dsl::name.asc())
+            .load::<Label>(&mut conn)
+            .context(DatabaseSnafu)
+    }
 }
 
 /// Basic queries to insert rollups' outputs
@@ -240,6 +322,308 @@ impl Repository {
         tracing::trace!("Set {:?} status to input {}", status, input_index);
         Ok(())
     }
+
+    pub fn update_proof_context(
+        &self,
+        input_index: i32,
+        output_index: i32,
+        output_enum: OutputEnum,
+        context: Vec<u8>,
+    ) -> Result<(), Error> {
+        use schema::proofs;
+        let mut conn = self.conn()?;
+        update(proofs::table)
+            .filter(proofs::dsl::input_index.eq(input_index))
+            .filter(proofs::dsl::output_index.eq(output_index))
+            .filter(proofs::dsl::output_enum.eq(output_enum))
+            .set(proofs::context.eq(context))
+            .execute(&mut conn)
+            .context(DatabaseSnafu)?;
+        tracing::trace!(
+            "Set the context of the proof for {:?} {} of Input {}",
+            output_enum,
+            output_index,
+            input_index
+        );
+        Ok(())
+    }
+}
+
+/// Replace operations
+impl Repository {
+    /// Replace the DApp labels with `labels` in a single transaction
+    pub fn replace_labels(&self, labels: Vec<Label>) -> Result<(), Error> {
+        use schema::labels;
+        let mut conn = self.conn()?;
+        conn.transaction(|conn| {
+            delete(labels::table).execute(conn)?;
+            insert_into(labels::table).values(&labels).execute(conn)?;
+            Ok(())
+        })
+        .context(DatabaseSnafu)?;
+        tracing::trace!("Replaced the db labels with {:?}", labels);
+        Ok(())
+    }
+}
+
+/// Snapshot operations
+impl Repository {
+    /// Insert the rows of a snapshot in a single transaction, so a restore
+    /// that fails midway leaves the database as it was. Rows already in the
+    /// database are kept; only the statuses of the inputs are updated.
+    pub fn restore_snapshot(
+        &self,
+        inputs: &[Input],
+        vouchers: &[Voucher],
+        notices: &[Notice],
+        reports: &[Report],
+        proofs: &[Proof],
+    ) -> Result<(), Error> {
+        use schema::{inputs, notices, proofs, reports, vouchers};
+        let mut conn = self.conn()?;
+        conn.transaction(|conn| {
+            for input in inputs {
+                insert_into(inputs::table)
+                    .values(input)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                update(inputs::table)
+                    .filter(inputs::dsl::index.eq(input.index))
+                    .set(inputs::status.eq(input.status))
+                    .execute(conn)?;
+            }
+            for voucher in vouchers {
+                insert_into(vouchers::table)
+                    .values(voucher)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            for notice in notices {
+                insert_into(notices::table)
+                    .values(notice)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            for report in reports {
+                insert_into(reports::table)
+                    .values(report)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            for proof in proofs {
+                insert_into(proofs::table)
+                    .values(proof)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+        .context(DatabaseSnafu)?;
+        tracing::trace!(
+            "Restored a snapshot with {} inputs, {} vouchers, {} notices, {} reports and {} proofs",
+            inputs.len(),
+            vouchers.len(),
+            notices.len(),
+            reports.len(),
+            proofs.len()
+        );
+        Ok(())
+    }
+}
+
+/// Idempotency key operations
+impl Repository {
+    /// Claim `key` for `request`, holding it for `lease` while the request
+    /// runs. Returns the key as stored if it was already claimed, so the
+    /// caller can return its result instead of running the request again.
+    /// A key of the same request whose lease expired without a result, such
+    /// as when the process running it crashed, is claimed again.
+    pub fn claim_idempotency_key(
+        &self,
+        key: &str,
+        request: &str,
+        lease: Duration,
+    ) -> Result<Option<IdempotencyKey>, Error> {
+        use schema::idempotency_keys::dsl;
+        let mut conn = self.conn()?;
+        let now = SystemTime::now();
+        let claimed = IdempotencyKey {
+            key: key.to_owned(),
+            request: request.to_owned(),
+            result: None,
+            created_at: now,
+            lease_expires_at: Some(now + lease),
+        };
+        let stored = conn
+            .transaction(|conn| {
+                let inserted = insert_into(dsl::idempotency_keys)
+                    .values(&claimed)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                if inserted == 1 {
+                    return Ok(None);
+                }
+                let stored = dsl::idempotency_keys
+                    .find(key)
+                    .for_update()
+                    .first::<IdempotencyKey>(conn)?;
+                let expired = stored.result.is_none()
+                    && stored.request == request
+                    && stored.lease_expires_at.is_none_or(|t| t <= now);
+                if !expired {
+                    return Ok(Some(stored));
+                }
+                tracing::warn!(
+                    "Taking over idempotency key {}, whose lease expired",
+                    key
+                );
+                update(dsl::idempotency_keys.find(key))
+                    .set(dsl::lease_expires_at.eq(claimed.lease_expires_at))
+                    .execute(conn)?;
+                Ok(None)
+            })
+            .context(DatabaseSnafu)?;
+        tracing::trace!("Claimed idempotency key {}: {:?}", key, stored);
+        Ok(stored)
+    }
+
+    /// Store the result of the request of a claimed `key`
+    pub fn complete_idempotency_key(
+        &self,
+        key: &str,
+        result: String,
+    ) -> Result<(), Error> {
+        use schema::idempotency_keys::dsl;
+        let mut conn = self.conn()?;
+        update(dsl::idempotency_keys.find(key))
+            .set((
+                dsl::result.eq(result),
+                dsl::lease_expires_at.eq(None::<SystemTime>),
+            ))
+            .execute(&mut conn)
+            .context(DatabaseSnafu)?;
+        tracing::trace!("Completed idempotency key {}", key);
+        Ok(())
+    }
+
+    /// Release a claimed `key` whose request failed, so it can be retried.
+    /// Keys that already have a result are kept.
+    pub fn release_idempotency_key(&self, key: &str) -> Result<(), Error> {
+        use schema::idempotency_keys::dsl;
+        let mut conn = self.conn()?;
+        delete(dsl::idempotency_keys.find(key))
+            .filter(dsl::result.is_null())
+            .execute(&mut conn)
+            .context(DatabaseSnafu)?;
+        tracing::trace!("Released idempotency key {}", key);
+        Ok(())
+    }
+}
+
+/// Ledger operations
+impl Repository {
+    /// Insert the entries of a posting in a single transaction, so its sides
+    /// are never stored apart
+    pub fn insert_ledger_entries(
+        &self,
+        entries: Vec<NewLedgerEntry>,
+    ) -> Result<(), Error> {
+        use schema::ledger_entries;
+        let mut conn = self.conn()?;
+        conn.transaction(|conn| {
+            insert_into(ledger_entries::table)
+                .values(&entries)
+                .execute(conn)
+        })
+        .context(DatabaseSnafu)?;
+        tracing::trace!("Inserted {} ledger entries", entries.len());
+        Ok(())
+    }
+
+    /// Get the ledger entries in the order they were inserted
+    pub fn get_ledger_entries(&self) -> Result<Vec<LedgerEntry>, Error> {
+        use schema::ledger_entries::dsl;
+        let mut conn = self.conn()?;
+        dsl::ledger_entries
+            .order(dsl::id.asc())
+            .load::<LedgerEntry>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+}
+
+/// Delete operations
+impl Repository {
+    /// Delete every row of the DApp from the database, leaving the schema in
+    /// place. The ledger of the claimed epochs, which holds the books of the
+    /// validator rather than data of the DApp, is kept.
+    /// If `archive_schema` is given, the rows are copied into tables of that
+    /// Postgres schema first, which are created on the first run; both steps
+    /// happen in the same transaction. The archive tables are named after
+    /// the latest migration, such as `inputs_20240402000000`, so the rows
+    /// archived before a migration keep the columns they had.
+    pub fn delete_all(
+        &self,
+        archive_schema: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut conn = self.conn()?;
+        conn.transaction(|conn| {
+            if let Some(archive_schema) = archive_schema {
+                let archive_schema = quote_identifier(archive_schema);
+                let version = migration_versions()
+                    .pop()
+                    .expect("there should be at least one migration");
+                sql_query(format!(
+                    "CREATE SCHEMA IF NOT EXISTS {}",
+                    archive_schema
+                ))
+                .execute(conn)?;
+                for table in [
+                    "inputs",
+                    "vouchers",
+                    "notices",
+                    "reports",
+                    "proofs",
+                    "labels",
+                    "idempotency_keys",
+                ] {
+                    // Reruns append to the tables archived before
+                    sql_query(format!(
+                        "CREATE TABLE IF NOT EXISTS {}.\"{}_{}\" \
+                         (LIKE \"{}\")",
+                        archive_schema, table, version, table
+                    ))
+                    .execute(conn)?;
+                    sql_query(format!(
+                        "INSERT INTO {}.\"{}_{}\" SELECT * FROM \"{}\"",
+                        archive_schema, table, version, table
+                    ))
+                    .execute(conn)?;
+                }
+                tracing::trace!(
+                    "Archived tables to schema {} for version {}",
+                    archive_schema,
+                    version
+                );
+            }
+            // Outputs and proofs go first because they reference the inputs
+            delete(schema::proofs::table).execute(conn)?;
+            delete(schema::vouchers::table).execute(conn)?;
+            delete(schema::notices::table).execute(conn)?;
+            delete(schema::reports::table).execute(conn)?;
+            delete(schema::inputs::table).execute(conn)?;
+            delete(schema::labels::table).execute(conn)?;
+            delete(schema::idempotency_keys::table).execute(conn)?;
+            Ok(())
+        })
+        .context(DatabaseSnafu)?;
+        tracing::trace!("Deleted all rows from the db");
+        Ok(())
+    }
+}
+
+/// Quote a Postgres identifier so it can be safely interpolated in a query
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
 }
 
 /// Generate a boxed query from an input query filter
@@ -253,10 +637,67 @@ impl InputQueryFilter {
         if let Some(other) = self.index_lower_than {
             query = query.filter(dsl::index.lt(other));
         }
+        match self.as_of {
+            Some(AsOf::BlockNumber(block_number)) => {
+                query = query.filter(dsl::block_number.le(block_number));
+            }
+            Some(AsOf::Timestamp(timestamp)) => {
+                query = query.filter(dsl::timestamp.le(timestamp));
+            }
+            None => {}
+        }
+        match self.consistency {
+            Consistency::Latest => {}
+            Consistency::Finalized(block_number) => {
+                query = query.filter(dsl::block_number.le(block_number));
+            }
+            Consistency::MachineConfirmed(block_number) => {
+                query = query
+                    .filter(dsl::block_number.le(block_number))
+                    .filter(dsl::status.ne(CompletionStatus::Unprocessed));
+            }
+        }
         query
     }
 }
 
+/// Generate a query for the indices of the inputs recorded up to a point
+fn input_indices_as_of(
+    as_of: AsOf,
+) -> schema::inputs::BoxedQuery<'static, Pg, diesel::sql_types::Integer> {
+    use schema::inputs::dsl;
+    let query = dsl::inputs.select(dsl::index).into_boxed();
+    match as_of {
+        AsOf::BlockNumber(block_number) => {
+            query.filter(dsl::block_number.le(block_number))
+        }
+        AsOf::Timestamp(timestamp) => {
+            query.filter(dsl::timestamp.le(timestamp))
+        }
+    }
+}
+
+/// Generate a query for the indices of the inputs of a consistency tier, or
+/// none if the tier has every input
+fn input_indices_with(
+    consistency: Consistency,
+) -> Option<schema::inputs::BoxedQuery<'static, Pg, diesel::sql_types::Integer>>
+{
+    use schema::inputs::dsl;
+    let query = dsl::inputs.select(dsl::index).into_boxed();
+    match consistency {
+        Consistency::Latest => None,
+        Consistency::Finalized(block_number) => {
+            Some(query.filter(dsl::block_number.le(block_number)))
+        }
+        Consistency::MachineConfirmed(block_number) => Some(
+            query
+                .filter(dsl::block_number.le(block_number))
+                .filter(dsl::status.ne(CompletionStatus::Unprocessed)),
+        ),
+    }
+}
+
 /// Generate a boxed query from an output query filter
 macro_rules! impl_output_filter_to_query {
     ($filter: ty, $table: ident) => {
@@ -267,6 +708,14 @@ macro_rules! impl_output_filter_to_query {
                 if let Some(other) = self.input_index {
                     query = query.filter(dsl::input_index.eq(other));
                 }
+                if let Some(as_of) = self.as_of {
+                    query = query.filter(
+                        dsl::input_index.eq_any(input_indices_as_of(as_of)),
+                    );
+                }
+                if let Some(indices) = input_indices_with(self.consistency) {
+                    query = query.filter(dsl::input_index.eq_any(indices));
+                }
                 query
             }
         }
@@ -306,7 +755,10 @@ macro_rules! impl_paginated_query {
                 } else {
                     vec![]
                 };
-                Ok(pagination.create_connection(nodes))
+                Ok(Connection {
+                    consistency: filter.consistency,
+                    ..pagination.create_connection(nodes)
+                })
             }
         }
     };
@@ -316,3 +768,357 @@ impl_paginated_query!(get_inputs, inputs, Input, InputQueryFilter);
 impl_paginated_query!(get_vouchers, vouchers, Voucher, VoucherQueryFilter);
 impl_paginated_query!(get_notices, notices, Notice, NoticeQueryFilter);
 impl_paginated_query!(get_reports, reports, Report, ReportQueryFilter);
+
+impl Repository {
+    /// Lists the vouchers along with their epoch and input, paginated by
+    /// their position so clients can walk through all of them without
+    /// offset scans
+    pub fn get_voucher_entries(
+        &self,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+        filter: VoucherQueryFilter,
+    ) -> Result<Connection<VoucherEntry, OutputCursor>, Error> {
+        use schema::{proofs, vouchers};
+        let pagination = KeysetPagination::new(first, last, after, before)?;
+        let mut conn = self.conn()?;
+        let count = filter
+            .to_query()
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)?;
+
+        let mut query = vouchers::table
+            .left_join(
+                proofs::table.on(proofs::input_index
+                    .eq(vouchers::input_index)
+                    .and(proofs::output_index.eq(vouchers::index))
+                    .and(proofs::output_enum.eq(OutputEnum::Voucher))),
+            )
+            .select((
+                (proofs::input_index
+                    - proofs::validity_input_index_within_epoch)
+                    .nullable(),
+                vouchers::all_columns,
+            ))
+            .into_boxed();
+        if let Some(input_index) = filter.input_index {
+            query = query.filter(vouchers::input_index.eq(input_index));
+        }
+        if let Some(as_of) = filter.as_of {
+            query = query.filter(
+                vouchers::input_index.eq_any(input_indices_as_of(as_of)),
+            );
+        }
+        if let Some(indices) = input_indices_with(filter.consistency) {
+            query = query.filter(vouchers::input_index.eq_any(indices));
+        }
+        if let Some(after) = pagination.after() {
+            query = query.filter(
+                vouchers::input_index.gt(after.input_index).or(
+                    vouchers::input_index
+                        .eq(after.input_index)
+                        .and(vouchers::index.gt(after.index)),
+                ),
+            );
+        }
+        if let Some(before) = pagination.before() {
+            query = query.filter(
+                vouchers::input_index.lt(before.input_index).or(
+                    vouchers::input_index
+                        .eq(before.input_index)
+                        .and(vouchers::index.lt(before.index)),
+                ),
+            );
+        }
+        query = if pagination.is_backward() {
+            query.order((vouchers::input_index.desc(), vouchers::index.desc()))
+        } else {
+            query.order((vouchers::input_index.asc(), vouchers::index.asc()))
+        };
+        let nodes = query
+            .limit(pagination.query_limit().into())
+            .load::<(Option<i32>, Voucher)>(&mut conn)
+            .context(DatabaseSnafu)?
+            .into_iter()
+            .map(|(epoch_first_input_index, voucher)| VoucherEntry {
+                epoch_first_input_index,
+                voucher,
+            })
+            .collect();
+        let connection = pagination.create_connection(
+            nodes,
+            |entry| OutputCursor {
+                input_index: entry.voucher.input_index,
+                index: entry.voucher.index,
+            },
+            count as i32,
+        );
+        Ok(Connection {
+            consistency: filter.consistency,
+            ..connection
+        })
+    }
+}
+
+impl Repository {
+    /// Gets the input that produced an output along with the proof of the
+    /// output with a single query, so the lineage of the output can be
+    /// served without a query per field
+    pub fn get_output_lineage(
+        &self,
+        input_index: i32,
+        output_index: i32,
+        output_enum: OutputEnum,
+    ) -> Result<OutputLineage, Error> {
+        use schema::{inputs, proofs};
+        let mut conn = self.conn()?;
+        inputs::table
+            .left_join(
+                proofs::table.on(proofs::input_index
+                    .eq(inputs::index)
+                    .and(proofs::output_index.eq(output_index))
+                    .and(proofs::output_enum.eq(output_enum))),
+            )
+            .filter(inputs::index.eq(input_index))
+            .select((inputs::all_columns, proofs::all_columns.nullable()))
+            .load::<(Input, Option<Proof>)>(&mut conn)
+            .context(DatabaseSnafu)?
+            .pop()
+            .map(|(input, proof)| OutputLineage { input, proof })
+            .ok_or(Error::ItemNotFound {
+                item_type: "input".to_owned(),
+            })
+    }
+}
+
+impl Repository {
+    /// Counts the rows indexed for the inputs from `first_input_index` to
+    /// `last_input_index`, inclusive, so they can be reconciled with the
+    /// claim of their epoch
+    pub fn count_epoch_rows(
+        &self,
+        first_input_index: i32,
+        last_input_index: i32,
+    ) -> Result<EpochCounts, Error> {
+        use schema::{inputs, notices, proofs, vouchers};
+        let mut conn = self.conn()?;
+        let inputs = inputs::table
+            .filter(inputs::index.between(first_input_index, last_input_index))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let vouchers = vouchers::table
+            .filter(
+                vouchers::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let notices = notices::table
+            .filter(
+                notices::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let proofs = proofs::table
+            .filter(
+                proofs::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)?;
+        Ok(EpochCounts {
+            inputs,
+            vouchers,
+            notices,
+            proofs,
+        })
+    }
+
+    /// Gets the rows indexed for the inputs from `first_input_index` to
+    /// `last_input_index`, inclusive, sorted by their primary keys, so they
+    /// can be dumped in batches
+    pub fn get_input_range_rows(
+        &self,
+        first_input_index: i32,
+        last_input_index: i32,
+    ) -> Result<InputRangeRows, Error> {
+        use schema::{inputs, notices, proofs, reports, vouchers};
+        let mut conn = self.conn()?;
+        let inputs = inputs::table
+            .filter(inputs::index.between(first_input_index, last_input_index))
+            .order(inputs::table.primary_key())
+            .load::<Input>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let vouchers = vouchers::table
+            .filter(
+                vouchers::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .order(vouchers::table.primary_key())
+            .load::<Voucher>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let notices = notices::table
+            .filter(
+                notices::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .order(notices::table.primary_key())
+            .load::<Notice>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let reports = reports::table
+            .filter(
+                reports::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .order(reports::table.primary_key())
+            .load::<Report>(&mut conn)
+            .context(DatabaseSnafu)?;
+        let proofs = proofs::table
+            .filter(
+                proofs::input_index
+                    .between(first_input_index, last_input_index),
+            )
+            .order(proofs::table.primary_key())
+            .load::<Proof>(&mut conn)
+            .context(DatabaseSnafu)?;
+        Ok(InputRangeRows {
+            inputs,
+            vouchers,
+            notices,
+            reports,
+            proofs,
+        })
+    }
+
+    /// Gets the index of the last input indexed, if any
+    pub fn get_last_input_index(&self) -> Result<Option<i32>, Error> {
+        use schema::inputs::dsl;
+        let mut conn = self.conn()?;
+        dsl::inputs
+            .select(diesel::dsl::max(dsl::index))
+            .get_result::<Option<i32>>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+
+    /// Counts the inputs the node has processed, whose outputs are already
+    /// indexed
+    pub fn count_processed_inputs(&self) -> Result<i64, Error> {
+        use schema::inputs::dsl;
+        let mut conn = self.conn()?;
+        dsl::inputs
+            .filter(dsl::status.ne(CompletionStatus::Unprocessed))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+
+    /// Counts the proofs of the outputs, which are indexed once their epoch
+    /// is finished
+    pub fn count_proofs(&self) -> Result<i64, Error> {
+        use schema::proofs::dsl;
+        let mut conn = self.conn()?;
+        dsl::proofs
+            .count()
+            .get_result::<i64>(&mut conn)
+            .context(DatabaseSnafu)
+    }
+
+    /// Finds the rows a value refers to, at most `SEARCH_LIMIT` of each
+    /// kind and column. A 32-byte value is looked up as the hash of the
+    /// transaction of an input and as the SHA-256 of a payload; a 20-byte
+    /// one as the sender of an input and the destination of a voucher.
+    pub fn search(&self, value: &[u8]) -> Result<Vec<SearchHit>, Error> {
+        use schema::{inputs, notices, reports, vouchers};
+        let mut conn = self.conn()?;
+        let mut hits = vec![];
+        // Each query selects the input index and the index of its rows
+        macro_rules! search {
+            ($kind: ident, $matched_on: ident, $query: expr) => {
+                let rows = $query
+                    .limit(SEARCH_LIMIT)
+                    .load::<(i32, i32)>(&mut conn)
+                    .context(DatabaseSnafu)?;
+                hits.extend(rows.into_iter().map(|(input_index, index)| {
+                    SearchHit {
+                        kind: SearchKind::$kind,
+                        matched_on: SearchField::$matched_on,
+                        input_index,
+                        index,
+                    }
+                }));
+            };
+        }
+        match value.len() {
+            32 => {
+                search!(
+                    Input,
+                    TxHash,
+                    inputs::table
+                        .filter(inputs::tx_hash.eq(value))
+                        .select((inputs::index, inputs::index))
+                        .order(inputs::index)
+                );
+                search!(
+                    Input,
+                    PayloadHash,
+                    inputs::table
+                        .filter(sha256(inputs::payload).eq(value))
+                        .select((inputs::index, inputs::index))
+                        .order(inputs::index)
+                );
+                search!(
+                    Voucher,
+                    PayloadHash,
+                    vouchers::table
+                        .filter(sha256(vouchers::payload).eq(value))
+                        .select((vouchers::input_index, vouchers::index))
+                        .order(vouchers::table.primary_key())
+                );
+                search!(
+                    Notice,
+                    PayloadHash,
+                    notices::table
+                        .filter(sha256(notices::payload).eq(value))
+                        .select((notices::input_index, notices::index))
+                        .order(notices::table.primary_key())
+                );
+                search!(
+                    Report,
+                    PayloadHash,
+                    reports::table
+                        .filter(sha256(reports::payload).eq(value))
+                        .select((reports::input_index, reports::index))
+                        .order(reports::table.primary_key())
+                );
+            }
+            20 => {
+                search!(
+                    Input,
+                    MsgSender,
+                    inputs::table
+                        .filter(inputs::msg_sender.eq(value))
+                        .select((inputs::index, inputs::index))
+                        .order(inputs::index)
+                );
+                search!(
+                    Voucher,
+                    Destination,
+                    vouchers::table
+                        .filter(vouchers::destination.eq(value))
+                        .select((vouchers::input_index, vouchers::index))
+                        .order(vouchers::table.primary_key())
+                );
+            }
+            _ => {}
+        }
+        Ok(hits)
+    }
+}
@@ -10,6 +10,16 @@ pub mod sql_types {
     pub struct OutputEnum;
 }
 
+diesel::table! {
+    idempotency_keys (key) {
+        key -> Text,
+        request -> Text,
+        result -> Nullable<Text>,
+        created_at -> Timestamp,
+        lease_expires_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::CompletionStatus;
@@ -22,6 +32,30 @@ diesel::table! {
         timestamp -> Timestamp,
         payload -> Bytea,
         status -> CompletionStatus,
+        decoded_payload -> Nullable<Text>,
+        payload_codec -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    labels (name) {
+        name -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    ledger_entries (id) {
+        id -> Int4,
+        dapp_address -> Bytea,
+        epoch_index -> Int8,
+        account -> Text,
+        side -> Text,
+        amount -> Text,
+        tx_hash -> Bytea,
+        gas_used -> Nullable<Text>,
+        inputs -> Nullable<Int8>,
+        recorded_at -> Timestamp,
     }
 }
 
@@ -75,5 +109,12 @@ diesel::joinable!(reports -> inputs (input_index));
 diesel::joinable!(vouchers -> inputs (input_index));
 
 diesel::allow_tables_to_appear_in_same_query!(
-    inputs, notices, proofs, reports, vouchers,
+    idempotency_keys,
+    inputs,
+    labels,
+    ledger_entries,
+    notices,
+    proofs,
+    reports,
+    vouchers,
 );
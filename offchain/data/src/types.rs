@@ -8,7 +8,7 @@ use diesel::{AsExpression, Insertable, Queryable, QueryableByName};
 use std::io::Write;
 
 use super::schema::{
-    inputs, notices, proofs, reports,
+    idempotency_keys, inputs, labels, ledger_entries, notices, proofs, reports,
     sql_types::CompletionStatus as SQLCompletionStatus,
     sql_types::OutputEnum as SQLOutputEnum, vouchers,
 };
@@ -68,6 +68,10 @@ impl FromSql<SQLCompletionStatus, Pg> for CompletionStatus {
     }
 }
 
+impl diesel::query_builder::QueryId for SQLCompletionStatus {
+    type QueryId = SQLCompletionStatus;
+}
+
 #[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
 #[diesel(table_name = inputs)]
 pub struct Input {
@@ -78,6 +82,8 @@ pub struct Input {
     pub timestamp: std::time::SystemTime,
     pub payload: Vec<u8>,
     pub status: CompletionStatus,
+    pub decoded_payload: Option<String>,
+    pub payload_codec: Option<String>,
 }
 
 #[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
@@ -97,6 +103,86 @@ pub struct Voucher {
     pub payload: Vec<u8>,
 }
 
+/// Voucher along with the epoch and input it belongs to, as listed by
+/// `Repository::get_voucher_entries`
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoucherEntry {
+    /// Index of the first input of the epoch of the voucher. It is only
+    /// known once the epoch is finished and the proof of the voucher is
+    /// stored.
+    pub epoch_first_input_index: Option<i32>,
+    pub voucher: Voucher,
+}
+
+/// Input that produced an output, along with the proof of the output, as
+/// read by `Repository::get_output_lineage`
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputLineage {
+    pub input: Input,
+    /// Only known once the epoch of the input is finished
+    pub proof: Option<Proof>,
+}
+
+impl OutputLineage {
+    /// Index of the first input of the epoch of the output, which
+    /// identifies the epoch
+    pub fn epoch_first_input_index(&self) -> Option<i32> {
+        self.proof.as_ref().map(|proof| {
+            proof.input_index - proof.validity_input_index_within_epoch
+        })
+    }
+}
+
+/// Rows indexed for the inputs of an epoch, as counted by
+/// `Repository::count_epoch_rows`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EpochCounts {
+    pub inputs: i64,
+    pub vouchers: i64,
+    pub notices: i64,
+    pub proofs: i64,
+}
+
+/// Rows indexed for a range of inputs, as read by
+/// `Repository::get_input_range_rows`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputRangeRows {
+    pub inputs: Vec<Input>,
+    pub vouchers: Vec<Voucher>,
+    pub notices: Vec<Notice>,
+    pub reports: Vec<Report>,
+    pub proofs: Vec<Proof>,
+}
+
+/// Kind of row found by `Repository::search`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchKind {
+    Input,
+    Voucher,
+    Notice,
+    Report,
+}
+
+/// Column a search value was found in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchField {
+    TxHash,
+    MsgSender,
+    Destination,
+    /// SHA-256 of the payload
+    PayloadHash,
+}
+
+/// Row found by `Repository::search`. The index of an input is also its
+/// input index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub matched_on: SearchField,
+    pub input_index: i32,
+    pub index: i32,
+}
+
 #[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
 #[diesel(table_name = reports)]
 pub struct Report {
@@ -105,6 +191,61 @@ pub struct Report {
     pub payload: Vec<u8>,
 }
 
+/// Operator-defined annotation of the DApp
+#[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
+#[diesel(table_name = labels)]
+pub struct Label {
+    pub name: String,
+    pub value: String,
+}
+
+/// Key sent with a request so that retrying it doesn't run it again
+#[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
+#[diesel(table_name = idempotency_keys)]
+pub struct IdempotencyKey {
+    pub key: String,
+    /// Description of the request; the key can't be reused for another one
+    pub request: String,
+    /// Serialized result of the request, unset while it is running
+    pub result: Option<String>,
+    pub created_at: std::time::SystemTime,
+    /// Until when the request holds the key while it is running; a retry
+    /// takes the key over once it expires
+    pub lease_expires_at: Option<std::time::SystemTime>,
+}
+
+/// Side of a posting to the ledger of the claimed epochs, kept by the
+/// authority-claimer. The amounts are decimal strings, in wei.
+#[derive(Clone, Debug, PartialEq, Queryable, QueryableByName)]
+#[diesel(table_name = ledger_entries)]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub dapp_address: Vec<u8>,
+    pub epoch_index: i64,
+    pub account: String,
+    pub side: String,
+    pub amount: String,
+    pub tx_hash: Vec<u8>,
+    pub gas_used: Option<String>,
+    pub inputs: Option<i64>,
+    pub recorded_at: std::time::SystemTime,
+}
+
+/// Ledger entry to be inserted, which gets its id from the database
+#[derive(Clone, Debug, Insertable, PartialEq)]
+#[diesel(table_name = ledger_entries)]
+pub struct NewLedgerEntry {
+    pub dapp_address: Vec<u8>,
+    pub epoch_index: i64,
+    pub account: String,
+    pub side: String,
+    pub amount: String,
+    pub tx_hash: Vec<u8>,
+    pub gas_used: Option<String>,
+    pub inputs: Option<i64>,
+    pub recorded_at: std::time::SystemTime,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, FromSqlRow, AsExpression)]
 #[diesel(sql_type = SQLOutputEnum)]
 pub enum OutputEnum {
@@ -136,6 +277,13 @@ impl diesel::query_builder::QueryId for SQLOutputEnum {
     type QueryId = SQLOutputEnum;
 }
 
+// Diesel clones the ON clause of a join, which may compare the output type
+impl Clone for SQLOutputEnum {
+    fn clone(&self) -> Self {
+        SQLOutputEnum
+    }
+}
+
 #[derive(Clone, Debug, Insertable, PartialEq, Queryable, QueryableByName)]
 #[diesel(table_name = proofs)]
 pub struct Proof {
@@ -153,10 +301,34 @@ pub struct Proof {
     pub context: Vec<u8>,
 }
 
+/// Point in the history of the DApp. A query as of a point only sees the
+/// inputs recorded up to it, along with their outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsOf {
+    BlockNumber(i64),
+    Timestamp(std::time::SystemTime),
+}
+
+/// How settled the inputs a query sees, along with their outputs, must be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Consistency {
+    /// Every input recorded by the node
+    #[default]
+    Latest,
+    /// Only the inputs up to the given block, which is the finalized one of
+    /// the base layer
+    Finalized(i64),
+    /// Only the inputs up to the given finalized block that the machine has
+    /// also processed
+    MachineConfirmed(i64),
+}
+
 #[derive(Debug, Default)]
 pub struct InputQueryFilter {
     pub index_greater_than: Option<i32>,
     pub index_lower_than: Option<i32>,
+    pub as_of: Option<AsOf>,
+    pub consistency: Consistency,
 }
 
 macro_rules! decl_output_filter {
@@ -164,6 +336,8 @@ macro_rules! decl_output_filter {
         #[derive(Debug, Default)]
         pub struct $name {
             pub input_index: Option<i32>,
+            pub as_of: Option<AsOf>,
+            pub consistency: Consistency,
         }
     };
 }
@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use diesel::{pg::PgConnection, prelude::*, sql_query};
-use testcontainers::{clients::Cli, images::postgres::Postgres, RunnableImage};
+use rollups_data::{MigrationError, SUPPORTED_DOWNGRADES};
+use testcontainers::{
+    clients::Cli, images::postgres::Postgres, Container, RunnableImage,
+};
 
 const POSTGRES_PASSWORD: &'static str = "pw";
 
@@ -51,3 +54,84 @@ fn run_migrations() {
         assert!(tables.iter().find(|t| t.tablename == expected).is_some());
     }
 }
+
+fn setup_postgres(docker: &Cli) -> (Container<'_, Postgres>, String) {
+    tracing::info!("setting up Postgres container");
+    let image = RunnableImage::from(Postgres::default())
+        .with_tag("13")
+        .with_env_var(("POSTGRES_PASSWORD", POSTGRES_PASSWORD));
+    let postgres = docker.run(image);
+    let endpoint = postgres_endpoint(postgres.get_host_port_ipv4(5432));
+    (postgres, endpoint)
+}
+
+fn tables(endpoint: &str) -> Vec<String> {
+    let mut connection = PgConnection::establish(endpoint)
+        .expect("failed to establish connection");
+    sql_query("SELECT tablename FROM pg_tables;")
+        .load::<PgTable>(&mut connection)
+        .expect("failed to run query")
+        .into_iter()
+        .map(|table| table.tablename)
+        .collect()
+}
+
+#[test_log::test(test)]
+fn downgrade_and_upgrade_the_latest_migrations() {
+    let docker = Cli::default();
+    let (_postgres, endpoint) = setup_postgres(&docker);
+    rollups_data::run_migrations(&endpoint).expect("failed to run migrations");
+    let upgraded = tables(&endpoint);
+
+    // Each supported downgrade, followed by the upgrade of a newer release
+    let versions = rollups_data::migration_versions();
+    for steps in 1..=SUPPORTED_DOWNGRADES.min(versions.len() - 1) {
+        let target = versions[versions.len() - 1 - steps];
+        tracing::info!(target, "downgrading");
+        let reverted = rollups_data::revert_migrations(&endpoint, target)
+            .expect("failed to revert migrations");
+        assert_eq!(reverted.len(), steps);
+        assert!(matches!(
+            rollups_data::check_migrations(&endpoint),
+            Err(MigrationError::PendingMigrations { .. })
+        ));
+
+        rollups_data::run_migrations(&endpoint)
+            .expect("failed to run migrations");
+        rollups_data::check_migrations(&endpoint)
+            .expect("schema should be up to date");
+        let mut tables = tables(&endpoint);
+        tables.sort();
+        let mut expected = upgraded.clone();
+        expected.sort();
+        assert_eq!(tables, expected);
+    }
+
+    let oldest = versions[0];
+    assert!(matches!(
+        rollups_data::revert_migrations(&endpoint, oldest),
+        Err(MigrationError::UnsupportedDowngrade { .. })
+    ));
+}
+
+#[test_log::test(test)]
+fn reject_changed_migrations() {
+    let docker = Cli::default();
+    let (_postgres, endpoint) = setup_postgres(&docker);
+    rollups_data::run_migrations(&endpoint).expect("failed to run migrations");
+
+    let mut connection = PgConnection::establish(&endpoint)
+        .expect("failed to establish connection");
+    sql_query(
+        "UPDATE __rollups_migration_checksums SET checksum = '0000000000000000'
+        WHERE version = '20230110182039'",
+    )
+    .execute(&mut connection)
+    .expect("failed to run query");
+
+    assert!(matches!(
+        rollups_data::run_migrations(&endpoint),
+        Err(MigrationError::ChecksumMismatch { version, .. })
+            if version == "20230110182039"
+    ));
+}
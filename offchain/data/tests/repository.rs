@@ -8,12 +8,14 @@ use diesel::{
 };
 use rollups_data::Connection as PaginationConnection;
 use rollups_data::{
-    CompletionStatus, Cursor, Edge, Error, Input, InputQueryFilter, Notice,
-    PageInfo, Proof, RedactedUrl, Report, Repository, RepositoryConfig, Url,
-    Voucher,
+    AsOf, CompletionStatus, Consistency, Cursor, Edge, EpochCounts, Error,
+    Input, InputQueryFilter, Label, NewLedgerEntry, Notice, PageInfo, Proof,
+    RedactedUrl, Report, Repository, RepositoryConfig, SearchField, SearchHit,
+    SearchKind, Url, Voucher, VoucherQueryFilter,
 };
 use serial_test::serial;
 use std::io::Write;
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::time::{Duration, UNIX_EPOCH};
 use test_fixtures::DataFixture;
@@ -81,6 +83,8 @@ pub fn insert_test_input(repo: &Repository) {
         timestamp: UNIX_EPOCH + Duration::from_secs(1676489717),
         payload: "input-0".as_bytes().to_vec(),
         status: CompletionStatus::Accepted,
+        decoded_payload: None,
+        payload_codec: None,
     };
 
     repo.insert_input(input)
@@ -96,6 +100,8 @@ pub fn create_input() -> Input {
         timestamp: UNIX_EPOCH + Duration::from_secs(1676489717),
         payload: "input-0".as_bytes().to_vec(),
         status: CompletionStatus::Accepted,
+        decoded_payload: None,
+        payload_codec: None,
     }
 }
 
@@ -155,14 +161,17 @@ fn test_postgres_file_configuration() {
     {
         let mut pgpass = std::fs::File::create(&pgpass_path)
             .expect("failed to create pgpass");
-        // Set permission to 600
-        let metadata =
-            pgpass.metadata().expect("failed to get pgpass metadata");
-        let mut permissions = metadata.permissions();
-        permissions.set_mode(0o600);
-        pgpass
-            .set_permissions(permissions)
-            .expect("failed to set pgpass permissions");
+        // Set permission to 600; libpq only checks it on unix
+        #[cfg(unix)]
+        {
+            let metadata =
+                pgpass.metadata().expect("failed to get pgpass metadata");
+            let mut permissions = metadata.permissions();
+            permissions.set_mode(0o600);
+            pgpass
+                .set_permissions(permissions)
+                .expect("failed to set pgpass permissions");
+        }
         // Write pgpass contents
         write!(
             &mut pgpass,
@@ -623,6 +632,85 @@ fn test_get_proof() {
     assert_eq!(proof, get_proof);
 }
 
+#[test]
+#[serial]
+fn test_get_epoch_proofs() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    // Epochs start at inputs 0 and 2
+    let proof = |input_index, validity_input_index_within_epoch| Proof {
+        input_index,
+        output_index: 0,
+        output_enum: rollups_data::OutputEnum::Notice,
+        validity_input_index_within_epoch,
+        validity_output_index_within_input: 0,
+        validity_output_hashes_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_vouchers_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_notices_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_machine_state_hash: "<hash>".as_bytes().to_vec(),
+        validity_output_hash_in_output_hashes_siblings: vec![],
+        validity_output_hashes_in_epoch_siblings: vec![],
+        context: vec![],
+    };
+    for (input_index, index_within_epoch) in [(2, 0), (1, 1), (0, 0)] {
+        repo.insert_proof(proof(input_index, index_within_epoch))
+            .expect("Insert proof should succeed");
+    }
+
+    let proofs = repo.get_epoch_proofs(0).unwrap();
+    assert_eq!(proofs, vec![proof(0, 0), proof(1, 1)]);
+    assert_eq!(repo.get_epoch_proofs(2).unwrap(), vec![proof(2, 0)]);
+    assert!(repo.get_epoch_proofs(1).unwrap().is_empty());
+}
+
+#[test]
+#[serial]
+fn test_update_proof_context() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    let proof = Proof {
+        input_index: 0,
+        output_index: 0,
+        output_enum: rollups_data::OutputEnum::Notice,
+        validity_input_index_within_epoch: 0,
+        validity_output_index_within_input: 0,
+        validity_output_hashes_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_vouchers_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_notices_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_machine_state_hash: "<hash>".as_bytes().to_vec(),
+        validity_output_hash_in_output_hashes_siblings: vec![Some(
+            "<array>".as_bytes().to_vec(),
+        )],
+        validity_output_hashes_in_epoch_siblings: vec![Some(
+            "<array>".as_bytes().to_vec(),
+        )],
+        context: "<context>".as_bytes().to_vec(),
+    };
+    repo.insert_proof(proof.clone())
+        .expect("Insert proof should succeed");
+
+    repo.update_proof_context(
+        0,
+        0,
+        rollups_data::OutputEnum::Notice,
+        "<new context>".as_bytes().to_vec(),
+    )
+    .expect("Update proof context should succeed");
+
+    let proofs = repo.get_proofs().expect("Get proofs should succeed");
+    assert_eq!(
+        proofs,
+        vec![Proof {
+            context: "<new context>".as_bytes().to_vec(),
+            ..proof
+        }]
+    );
+}
+
 #[test]
 #[serial]
 fn test_get_proof_error() {
@@ -677,6 +765,8 @@ fn test_pagination_macro() {
         timestamp: UNIX_EPOCH + Duration::from_secs(1676489717),
         payload: "input-1".as_bytes().to_vec(),
         status: CompletionStatus::Accepted,
+        decoded_payload: None,
+        payload_codec: None,
     };
 
     repo.insert_input(input0.clone())
@@ -687,6 +777,7 @@ fn test_pagination_macro() {
     let query_filter = InputQueryFilter {
         index_greater_than: Some(-1),
         index_lower_than: Some(5),
+        ..Default::default()
     };
 
     let pagination_connection = repo
@@ -721,6 +812,738 @@ fn test_pagination_macro() {
                 has_next_page: false,
                 has_previous_page: false,
             },
+            consistency: Consistency::Latest,
         }
     );
 }
+
+#[test]
+#[serial]
+fn test_voucher_entries_keyset_pagination() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            ..create_input()
+        })
+        .expect("Insert input should succeed");
+    }
+    for (input_index, index) in [(0, 0), (0, 1), (2, 0)] {
+        repo.insert_voucher(Voucher {
+            input_index,
+            index,
+            destination: "destination".as_bytes().to_vec(),
+            payload: format!("voucher-{}-{}", input_index, index).into_bytes(),
+        })
+        .expect("Insert voucher should succeed");
+    }
+    // Only the epoch of input 2, which started at input 1, is finished
+    let proof = Proof {
+        input_index: 2,
+        output_index: 0,
+        output_enum: rollups_data::OutputEnum::Voucher,
+        validity_input_index_within_epoch: 1,
+        validity_output_index_within_input: 0,
+        validity_output_hashes_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_vouchers_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_notices_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_machine_state_hash: "<hash>".as_bytes().to_vec(),
+        validity_output_hash_in_output_hashes_siblings: vec![],
+        validity_output_hashes_in_epoch_siblings: vec![],
+        context: vec![],
+    };
+    repo.insert_proof(proof.clone())
+        .expect("Insert proof should succeed");
+    // The proof of a notice with the same indices as a voucher isn't joined
+    repo.insert_proof(Proof {
+        input_index: 0,
+        output_index: 0,
+        output_enum: rollups_data::OutputEnum::Notice,
+        validity_input_index_within_epoch: 0,
+        ..proof
+    })
+    .expect("Insert proof should succeed");
+
+    let page = repo
+        .get_voucher_entries(Some(2), None, None, None, Default::default())
+        .expect("Get voucher entries should succeed");
+    assert_eq!(page.total_count, 3);
+    let positions: Vec<_> = page
+        .edges
+        .iter()
+        .map(|edge| (edge.cursor.input_index, edge.cursor.index))
+        .collect();
+    assert_eq!(positions, [(0, 0), (0, 1)]);
+    assert!(page
+        .edges
+        .iter()
+        .all(|e| e.node.epoch_first_input_index.is_none()));
+    assert!(page.page_info.has_next_page);
+
+    let after = page.page_info.end_cursor.map(|cursor| cursor.encode());
+    let page = repo
+        .get_voucher_entries(Some(2), None, after, None, Default::default())
+        .expect("Get voucher entries should succeed");
+    assert_eq!(page.edges.len(), 1);
+    assert_eq!(page.edges[0].node.voucher.input_index, 2);
+    assert_eq!(page.edges[0].node.epoch_first_input_index, Some(1));
+    assert!(!page.page_info.has_next_page);
+    assert!(page.page_info.has_previous_page);
+
+    let page = repo
+        .get_voucher_entries(None, Some(1), None, None, Default::default())
+        .expect("Get voucher entries should succeed");
+    assert_eq!(page.edges.len(), 1);
+    assert_eq!(page.edges[0].node.voucher.input_index, 2);
+    assert!(page.page_info.has_previous_page);
+}
+
+#[test]
+#[serial]
+fn test_get_output_lineage() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            ..create_input()
+        })
+        .expect("Insert input should succeed");
+    }
+    let proof = Proof {
+        input_index: 2,
+        output_index: 1,
+        output_enum: rollups_data::OutputEnum::Notice,
+        validity_input_index_within_epoch: 1,
+        validity_output_index_within_input: 1,
+        validity_output_hashes_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_vouchers_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_notices_epoch_root_hash: "<hash>".as_bytes().to_vec(),
+        validity_machine_state_hash: "<hash>".as_bytes().to_vec(),
+        validity_output_hash_in_output_hashes_siblings: vec![],
+        validity_output_hashes_in_epoch_siblings: vec![],
+        context: vec![],
+    };
+    repo.insert_proof(proof.clone())
+        .expect("Insert proof should succeed");
+
+    let lineage = repo
+        .get_output_lineage(2, 1, rollups_data::OutputEnum::Notice)
+        .expect("Get output lineage should succeed");
+    assert_eq!(lineage.input.index, 2);
+    assert_eq!(lineage.proof, Some(proof.clone()));
+    assert_eq!(lineage.epoch_first_input_index(), Some(1));
+
+    // The proof of a voucher with the same indices isn't mixed up with it
+    let lineage = repo
+        .get_output_lineage(2, 1, rollups_data::OutputEnum::Voucher)
+        .expect("Get output lineage should succeed");
+    assert_eq!(lineage.input.index, 2);
+    assert_eq!(lineage.proof, None);
+    assert_eq!(lineage.epoch_first_input_index(), None);
+
+    // Each output gets its own proof once both have one
+    let voucher_proof = Proof {
+        output_enum: rollups_data::OutputEnum::Voucher,
+        ..proof.clone()
+    };
+    repo.insert_proof(voucher_proof.clone())
+        .expect("Insert proof should succeed");
+    let lineage = repo
+        .get_output_lineage(2, 1, rollups_data::OutputEnum::Voucher)
+        .expect("Get output lineage should succeed");
+    assert_eq!(lineage.proof, Some(voucher_proof));
+    let lineage = repo
+        .get_output_lineage(2, 1, rollups_data::OutputEnum::Notice)
+        .expect("Get output lineage should succeed");
+    assert_eq!(lineage.proof, Some(proof));
+
+    let err = repo
+        .get_output_lineage(3, 0, rollups_data::OutputEnum::Notice)
+        .expect_err("Get output lineage should fail");
+    assert!(matches!(
+        err,
+        Error::ItemNotFound { item_type } if item_type == "input"
+    ));
+}
+
+/// Query of the rows archived by the current schema version
+fn archived(table: &str) -> String {
+    let version = rollups_data::migration_versions().pop().unwrap();
+    format!("Select * from archive.{}_{}", table, version)
+}
+
+#[test]
+#[serial]
+fn test_delete_all_with_archive() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    let input = create_input();
+    repo.insert_input(input.clone())
+        .expect("Failed to insert input");
+    let notice = Notice {
+        input_index: 0,
+        index: 0,
+        payload: "notice-0-0".as_bytes().to_vec(),
+    };
+    repo.insert_notice(notice.clone())
+        .expect("Failed to insert notice");
+
+    repo.delete_all(Some("archive"))
+        .expect("Failed to delete all");
+
+    let input_error = repo.get_input(0).expect_err("Get input should fail");
+    assert!(matches!(
+        input_error,
+        Error::ItemNotFound { item_type } if item_type == "input"
+    ));
+
+    let archived_input: Input = test.get_from_sql(&archived("inputs"));
+    assert_eq!(archived_input, input);
+    let archived_notice: Notice = test.get_from_sql(&archived("notices"));
+    assert_eq!(archived_notice, notice);
+}
+
+#[test]
+#[serial]
+fn test_delete_all_with_archive_twice() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    repo.delete_all(Some("archive"))
+        .expect("Failed to delete all");
+
+    let input = create_input();
+    repo.insert_input(input.clone())
+        .expect("Failed to insert input");
+    repo.delete_all(Some("archive"))
+        .expect("Failed to delete all again");
+
+    let archived_input: Input = test.get_from_sql(&archived("inputs"));
+    assert_eq!(archived_input, input);
+}
+
+#[test]
+#[serial]
+fn test_delete_all_with_archive_of_an_older_schema() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    // Inputs archived by a version whose inputs had other columns
+    let mut conn = PgConnection::establish(&test.data.endpoint)
+        .expect("failed to connect to db");
+    sql_query("CREATE SCHEMA archive")
+        .execute(&mut conn)
+        .expect("failed to create the archive schema");
+    sql_query(
+        "CREATE TABLE archive.inputs_00000000000000 (index INTEGER NOT NULL)",
+    )
+    .execute(&mut conn)
+    .expect("failed to create the older archive table");
+
+    let input = create_input();
+    repo.insert_input(input.clone())
+        .expect("Failed to insert input");
+    repo.delete_all(Some("archive"))
+        .expect("Failed to delete all");
+
+    let archived_input: Input = test.get_from_sql(&archived("inputs"));
+    assert_eq!(archived_input, input);
+}
+
+#[test]
+#[serial]
+fn test_replace_labels() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    let label = |name: &str, value: &str| Label {
+        name: name.to_owned(),
+        value: value.to_owned(),
+    };
+    repo.replace_labels(vec![label("owner", "infra"), label("env", "dev")])
+        .expect("Failed to replace labels");
+    repo.replace_labels(vec![label("env", "prod"), label("contact", "a@b.c")])
+        .expect("Failed to replace labels");
+
+    let labels = repo.get_labels().expect("Failed to get labels");
+    assert_eq!(
+        labels,
+        vec![label("contact", "a@b.c"), label("env", "prod")]
+    );
+}
+
+#[test]
+#[serial]
+fn test_ledger_entries() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    let entry = |epoch_index: i64, side: &str, gas_used: Option<&str>| {
+        NewLedgerEntry {
+            dapp_address: vec![0xaa; 20],
+            epoch_index,
+            account: "claim_gas".to_owned(),
+            side: side.to_owned(),
+            // Wider than any integer type of Postgres
+            amount: "9".repeat(78),
+            tx_hash: vec![epoch_index as u8; 32],
+            gas_used: gas_used.map(str::to_owned),
+            inputs: gas_used.map(|_| 2),
+            recorded_at: UNIX_EPOCH + Duration::from_secs(1700000000),
+        }
+    };
+    assert!(repo.get_ledger_entries().unwrap().is_empty());
+    repo.insert_ledger_entries(vec![
+        entry(1, "debit", Some("21000")),
+        entry(1, "credit", None),
+    ])
+    .expect("Failed to insert ledger entries");
+    repo.insert_ledger_entries(vec![entry(0, "debit", Some("30000"))])
+        .expect("Failed to insert ledger entries");
+
+    let entries = repo.get_ledger_entries().expect("Failed to get entries");
+    let inserted = [
+        entry(1, "debit", Some("21000")),
+        entry(1, "credit", None),
+        entry(0, "debit", Some("30000")),
+    ];
+    assert_eq!(entries.len(), inserted.len());
+    for (entry, inserted) in entries.iter().zip(inserted) {
+        assert_eq!(entry.epoch_index, inserted.epoch_index);
+        assert_eq!(entry.side, inserted.side);
+        assert_eq!(entry.amount, inserted.amount);
+        assert_eq!(entry.gas_used, inserted.gas_used);
+        assert_eq!(entry.inputs, inserted.inputs);
+        assert_eq!(entry.recorded_at, inserted.recorded_at);
+    }
+
+    // The ledger isn't data of the DApp, so it survives deleting it
+    repo.delete_all(None).expect("Failed to delete all");
+    assert_eq!(repo.get_ledger_entries().unwrap().len(), 3);
+}
+
+#[test]
+#[serial]
+fn test_idempotency_keys() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+    const LEASE: Duration = Duration::from_secs(60);
+
+    let claimed = repo
+        .claim_idempotency_key("key-0", "request-0", LEASE)
+        .expect("Failed to claim key");
+    assert!(claimed.is_none());
+
+    // A retry while running sees the key without a result
+    let stored = repo
+        .claim_idempotency_key("key-0", "request-0", LEASE)
+        .expect("Failed to claim key")
+        .expect("Key should be stored");
+    assert_eq!(stored.request, "request-0");
+    assert_eq!(stored.result, None);
+    assert!(stored.lease_expires_at.is_some());
+
+    // Failed requests release the key
+    repo.release_idempotency_key("key-0")
+        .expect("Failed to release key");
+    let claimed = repo
+        .claim_idempotency_key("key-0", "request-0", LEASE)
+        .expect("Failed to claim key");
+    assert!(claimed.is_none());
+
+    // Completed keys keep their result
+    repo.complete_idempotency_key("key-0", "result-0".to_owned())
+        .expect("Failed to complete key");
+    repo.release_idempotency_key("key-0")
+        .expect("Failed to release key");
+    let stored = repo
+        .claim_idempotency_key("key-0", "request-1", LEASE)
+        .expect("Failed to claim key")
+        .expect("Key should be stored");
+    assert_eq!(stored.request, "request-0");
+    assert_eq!(stored.result, Some("result-0".to_owned()));
+    assert_eq!(stored.lease_expires_at, None);
+}
+
+#[test]
+#[serial]
+fn test_idempotency_key_leases() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    // The request that claimed the key crashed before completing it
+    let claimed = repo
+        .claim_idempotency_key("key-0", "request-0", Duration::ZERO)
+        .expect("Failed to claim key");
+    assert!(claimed.is_none());
+
+    // Another request can't take the key over
+    let stored = repo
+        .claim_idempotency_key("key-0", "request-1", Duration::ZERO)
+        .expect("Failed to claim key")
+        .expect("Key should be stored");
+    assert_eq!(stored.request, "request-0");
+
+    // A retry takes it over, and holds it until its own lease expires
+    let claimed = repo
+        .claim_idempotency_key("key-0", "request-0", Duration::from_secs(60))
+        .expect("Failed to claim key");
+    assert!(claimed.is_none());
+    let stored = repo
+        .claim_idempotency_key("key-0", "request-0", Duration::from_secs(60))
+        .expect("Failed to claim key")
+        .expect("Key should be stored");
+    assert_eq!(stored.result, None);
+
+    // Completed keys are never taken over
+    repo.claim_idempotency_key("key-1", "request-1", Duration::ZERO)
+        .expect("Failed to claim key");
+    repo.complete_idempotency_key("key-1", "result-1".to_owned())
+        .expect("Failed to complete key");
+    let stored = repo
+        .claim_idempotency_key("key-1", "request-1", Duration::ZERO)
+        .expect("Failed to claim key")
+        .expect("Key should be stored");
+    assert_eq!(stored.result, Some("result-1".to_owned()));
+}
+
+#[test]
+#[serial]
+fn test_count_epoch_rows() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+        repo.insert_voucher(Voucher {
+            input_index: index,
+            index: 0,
+            destination: "destination".as_bytes().to_vec(),
+            payload: "voucher".as_bytes().to_vec(),
+        })
+        .expect("Failed to insert voucher");
+    }
+    repo.insert_notice(Notice {
+        input_index: 1,
+        index: 0,
+        payload: "notice".as_bytes().to_vec(),
+    })
+    .expect("Failed to insert notice");
+
+    let counts = repo.count_epoch_rows(1, 2).expect("Failed to count rows");
+    assert_eq!(
+        counts,
+        EpochCounts {
+            inputs: 2,
+            vouchers: 2,
+            notices: 1,
+            proofs: 0,
+        }
+    );
+}
+
+#[test]
+#[serial]
+fn test_count_processed_inputs() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        let status = if index == 2 {
+            CompletionStatus::Unprocessed
+        } else {
+            CompletionStatus::Rejected
+        };
+        repo.insert_input(Input {
+            index,
+            status,
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+    }
+    assert_eq!(repo.count_processed_inputs().unwrap(), 2);
+    assert_eq!(repo.count_proofs().unwrap(), 0);
+
+    repo.update_input_status(2, CompletionStatus::Accepted)
+        .expect("Failed to update input status");
+    assert_eq!(repo.count_processed_inputs().unwrap(), 3);
+}
+
+#[test]
+#[serial]
+fn test_get_input_range_rows() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    assert_eq!(repo.get_last_input_index().unwrap(), None);
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+        repo.insert_report(Report {
+            input_index: index,
+            index: 0,
+            payload: "report".as_bytes().to_vec(),
+        })
+        .expect("Failed to insert report");
+    }
+    assert_eq!(repo.get_last_input_index().unwrap(), Some(2));
+
+    let rows = repo.get_input_range_rows(1, 2).expect("Failed to get rows");
+    let input_indices: Vec<_> =
+        rows.inputs.iter().map(|input| input.index).collect();
+    assert_eq!(input_indices, vec![1, 2]);
+    let report_indices: Vec<_> = rows
+        .reports
+        .iter()
+        .map(|report| report.input_index)
+        .collect();
+    assert_eq!(report_indices, vec![1, 2]);
+    assert!(rows.vouchers.is_empty());
+    assert!(rows.notices.is_empty());
+    assert!(rows.proofs.is_empty());
+}
+
+#[test]
+#[serial]
+fn test_queries_as_of() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            block_number: 10 * index as i64,
+            timestamp: UNIX_EPOCH + Duration::from_secs(1000 * index as u64),
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+        repo.insert_voucher(Voucher {
+            input_index: index,
+            index: 0,
+            destination: "destination".as_bytes().to_vec(),
+            payload: "voucher".as_bytes().to_vec(),
+        })
+        .expect("Failed to insert voucher");
+    }
+
+    let as_of_block = Some(AsOf::BlockNumber(15));
+    let inputs = repo
+        .get_inputs(
+            None,
+            None,
+            None,
+            None,
+            InputQueryFilter {
+                as_of: as_of_block,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get inputs");
+    assert_eq!(inputs.total_count, 2);
+    let vouchers = repo
+        .get_vouchers(
+            None,
+            None,
+            None,
+            None,
+            VoucherQueryFilter {
+                as_of: as_of_block,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get vouchers");
+    assert_eq!(vouchers.total_count, 2);
+
+    let as_of_timestamp =
+        Some(AsOf::Timestamp(UNIX_EPOCH + Duration::from_secs(999)));
+    let entries = repo
+        .get_voucher_entries(
+            None,
+            None,
+            None,
+            None,
+            VoucherQueryFilter {
+                as_of: as_of_timestamp,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get voucher entries");
+    assert_eq!(entries.total_count, 1);
+    assert_eq!(entries.edges.len(), 1);
+    assert_eq!(entries.edges[0].node.voucher.input_index, 0);
+}
+
+#[test]
+#[serial]
+fn test_queries_consistency() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    for index in 0..3 {
+        repo.insert_input(Input {
+            index,
+            block_number: 10 * index as i64,
+            status: CompletionStatus::Unprocessed,
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+        repo.insert_voucher(Voucher {
+            input_index: index,
+            index: 0,
+            destination: "destination".as_bytes().to_vec(),
+            payload: "voucher".as_bytes().to_vec(),
+        })
+        .expect("Failed to insert voucher");
+    }
+    repo.update_input_status(0, CompletionStatus::Accepted)
+        .expect("Failed to update input status");
+    repo.update_input_status(2, CompletionStatus::Rejected)
+        .expect("Failed to update input status");
+
+    let get_inputs = |consistency| {
+        repo.get_inputs(
+            None,
+            None,
+            None,
+            None,
+            InputQueryFilter {
+                consistency,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get inputs")
+    };
+    let get_vouchers = |consistency| {
+        repo.get_vouchers(
+            None,
+            None,
+            None,
+            None,
+            VoucherQueryFilter {
+                consistency,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get vouchers")
+    };
+
+    let inputs = get_inputs(Consistency::Latest);
+    assert_eq!(inputs.total_count, 3);
+    assert_eq!(inputs.consistency, Consistency::Latest);
+    let inputs = get_inputs(Consistency::Finalized(15));
+    assert_eq!(inputs.total_count, 2);
+    assert_eq!(inputs.consistency, Consistency::Finalized(15));
+    let inputs = get_inputs(Consistency::MachineConfirmed(15));
+    assert_eq!(inputs.total_count, 1);
+    assert_eq!(inputs.edges[0].node.index, 0);
+    assert_eq!(inputs.consistency, Consistency::MachineConfirmed(15));
+
+    assert_eq!(get_vouchers(Consistency::Latest).total_count, 3);
+    assert_eq!(get_vouchers(Consistency::Finalized(15)).total_count, 2);
+    let vouchers = get_vouchers(Consistency::MachineConfirmed(20));
+    assert_eq!(vouchers.total_count, 2);
+    assert_eq!(vouchers.edges[1].node.input_index, 2);
+
+    let entries = repo
+        .get_voucher_entries(
+            None,
+            None,
+            None,
+            None,
+            VoucherQueryFilter {
+                consistency: Consistency::MachineConfirmed(15),
+                ..Default::default()
+            },
+        )
+        .expect("Failed to get voucher entries");
+    assert_eq!(entries.total_count, 1);
+    assert_eq!(entries.edges[0].node.voucher.input_index, 0);
+    assert_eq!(entries.consistency, Consistency::MachineConfirmed(15));
+}
+
+#[test]
+#[serial]
+fn test_search() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker);
+    let repo = test.get_repository();
+
+    let tx_hash = vec![0xaa; 32];
+    let address = vec![0xbb; 20];
+    for index in 0..2 {
+        repo.insert_input(Input {
+            index,
+            msg_sender: address.clone(),
+            tx_hash: if index == 1 {
+                tx_hash.clone()
+            } else {
+                vec![0; 32]
+            },
+            ..create_input()
+        })
+        .expect("Failed to insert input");
+    }
+    repo.insert_voucher(Voucher {
+        input_index: 1,
+        index: 0,
+        destination: address.clone(),
+        payload: "voucher".as_bytes().to_vec(),
+    })
+    .expect("Failed to insert voucher");
+
+    let hit = |kind, matched_on, input_index, index| SearchHit {
+        kind,
+        matched_on,
+        input_index,
+        index,
+    };
+    assert_eq!(
+        repo.search(&tx_hash).expect("Failed to search"),
+        vec![hit(SearchKind::Input, SearchField::TxHash, 1, 1)]
+    );
+    assert_eq!(
+        repo.search(&address).expect("Failed to search"),
+        vec![
+            hit(SearchKind::Input, SearchField::MsgSender, 0, 0),
+            hit(SearchKind::Input, SearchField::MsgSender, 1, 1),
+            hit(SearchKind::Voucher, SearchField::Destination, 1, 0),
+        ]
+    );
+    // SHA-256 of `voucher`
+    let payload_hash = [
+        0x89, 0x23, 0xfe, 0xdf, 0xd1, 0x6a, 0x77, 0xb1, 0xfe, 0x21, 0x28, 0x0b,
+        0x25, 0x3f, 0xf5, 0x3d, 0x77, 0xb7, 0x5e, 0x9c, 0x71, 0xde, 0x51, 0x55,
+        0xe4, 0x07, 0x54, 0xad, 0x53, 0x84, 0x7f, 0xc2,
+    ];
+    assert_eq!(
+        repo.search(&payload_hash).expect("Failed to search"),
+        vec![hit(SearchKind::Voucher, SearchField::PayloadHash, 1, 0)]
+    );
+    assert!(repo.search(b"short").expect("Failed to search").is_empty());
+}
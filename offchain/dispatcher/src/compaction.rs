@@ -0,0 +1,84 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use rollups_events::{
+    Broker, BrokerConfig, BrokerError, BrokerStream, DAppMetadata,
+    RollupsClaimsStream, RollupsInputsStream, RollupsOutputsStream,
+};
+use scheduler::{JobConfig, Scheduler};
+use snafu::ResultExt;
+use std::time::Duration;
+
+use crate::error::{CompactionSnafu, DispatcherError};
+
+/// How the streams of the DApp are compacted
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+    pub interval: Duration,
+    /// Most events kept behind the newest one, even if unacknowledged
+    pub max_lag: Option<usize>,
+    /// Consumers that must register to each stream before it is trimmed
+    pub inputs_consumers: Vec<String>,
+    pub outputs_consumers: Vec<String>,
+    pub claims_consumers: Vec<String>,
+}
+
+/// Compacts the input, output and claim streams of the DApp periodically,
+/// trimming the events every registered consumer acknowledged once the
+/// expected consumers of the stream registered
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn start(
+    broker_config: BrokerConfig,
+    dapp_metadata: DAppMetadata,
+    config: CompactionConfig,
+    scheduler: Scheduler,
+) -> Result<(), DispatcherError> {
+    let broker = Broker::new(broker_config).await.context(CompactionSnafu)?;
+    let inputs_stream = RollupsInputsStream::new(&dapp_metadata);
+    let outputs_stream = RollupsOutputsStream::new(&dapp_metadata);
+    let claims_stream = RollupsClaimsStream::new(dapp_metadata.chain_id);
+    let config = &config;
+    let job = || {
+        let mut broker = broker.clone();
+        let inputs_stream = &inputs_stream;
+        let outputs_stream = &outputs_stream;
+        let claims_stream = &claims_stream;
+        async move {
+            let max_lag = config.max_lag;
+            let expected = &config.inputs_consumers;
+            compact(&mut broker, inputs_stream, expected, max_lag).await?;
+            let expected = &config.outputs_consumers;
+            compact(&mut broker, outputs_stream, expected, max_lag).await?;
+            let expected = &config.claims_consumers;
+            compact(&mut broker, claims_stream, expected, max_lag).await
+        }
+    };
+    scheduler
+        .run("broker_compaction", &JobConfig::every(config.interval), job)
+        .await;
+    Ok(())
+}
+
+async fn compact<S: BrokerStream>(
+    broker: &mut Broker,
+    stream: &S,
+    expected_consumers: &[String],
+    max_lag: Option<usize>,
+) -> Result<(), BrokerError> {
+    let report = broker.compact(stream, expected_consumers, max_lag).await?;
+    if !report.unregistered_consumers.is_empty() {
+        tracing::info!(
+            stream_key = stream.key(),
+            unregistered_consumers = ?report.unregistered_consumers,
+            "not compacting broker stream until its consumers register"
+        );
+    }
+    if report.trimmed > 0 {
+        tracing::info!(
+            stream_key = stream.key(),
+            trimmed = report.trimmed,
+            "compacted broker stream"
+        );
+    }
+    Ok(())
+}
@@ -5,14 +5,28 @@ use clap::Parser;
 use eth_state_client_lib::config::{
     Error as SCError, SCConfig, SCEnvCLIConfig,
 };
+use http_provider::HttpClientCLIConfig;
 use http_server::HttpServerConfig;
+use humane::ByteSize;
 use log::{LogConfig, LogEnvCliConfig};
-use snafu::{ResultExt, Snafu};
+use redacted::Url;
+use runtimes::{RuntimeCLIConfig, RuntimeConfig};
+use scheduler::{SchedulerCLIConfig, SchedulerConfig};
+use snafu::{ensure, ResultExt, Snafu};
+use std::{path::PathBuf, time::Duration};
 use types::blockchain_config::{
     BlockchainCLIConfig, BlockchainConfig, BlockchainConfigError,
 };
 
-use rollups_events::{BrokerCLIConfig, BrokerConfig};
+use rollups_events::{BrokerCLIConfig, BrokerConfig, Labels};
+
+use crate::{
+    compaction::CompactionConfig,
+    confirmations::ConfirmationsConfig,
+    epoch::EpochDurations,
+    finality::{Finality, FinalityConfig},
+    machine::spool::SpoolConfig,
+};
 
 #[derive(Parser)]
 #[command(name = "rd_config")]
@@ -30,13 +44,161 @@ pub struct DispatcherEnvCLIConfig {
     #[command(flatten)]
     pub blockchain_config: BlockchainCLIConfig,
 
-    /// Duration of rollups epoch in seconds, for which dispatcher will make claims.
-    #[arg(long, env, default_value = "604800")]
-    pub rd_epoch_duration: u64,
+    #[command(flatten)]
+    pub runtime_config: RuntimeCLIConfig,
+
+    #[command(flatten)]
+    pub http_client_config: HttpClientCLIConfig,
+
+    #[command(flatten)]
+    pub scheduler_config: SchedulerCLIConfig,
+
+    /// Duration of rollups epoch, such as `7d`, for which dispatcher will
+    /// make claims (a bare number is in seconds)
+    #[arg(
+        long,
+        env,
+        default_value = "7d",
+        value_parser = humane::parse_duration_or_secs
+    )]
+    pub rd_epoch_duration: Duration,
+
+    /// Comma-separated `<dapp address>=<duration>` epoch durations of the
+    /// DApps deployed with an epoch other than `rd_epoch_duration`
+    /// (e.g. `0x10dc...1ceE=1d`)
+    #[arg(long, env, default_value = "")]
+    pub rd_epoch_duration_overrides: EpochDurations,
+
+    /// Directory where the events are kept while the broker is unavailable,
+    /// to be published in order once it is back. Without it, the dispatcher
+    /// stops when it can't reach the broker
+    #[arg(long, env)]
+    pub rd_spool_dir: Option<PathBuf>,
+
+    /// Maximum size of the events kept in the spool, such as `64MiB`; once
+    /// it is full, the dispatcher stops
+    #[arg(
+        long,
+        env,
+        default_value = "64MiB",
+        value_parser = humane::parse_byte_size
+    )]
+    pub rd_spool_max_size: ByteSize,
+
+    /// Time to wait after failing to reach the broker before trying to
+    /// publish the spooled events again, such as `30s`
+    #[arg(
+        long,
+        env,
+        default_value = "30s",
+        value_parser = humane::parse_duration
+    )]
+    pub rd_spool_retry_interval: Duration,
+
+    /// Tune the confirmation depth of the blocks the dispatcher reads inputs
+    /// from to twice the deepest reorg observed recently, bounded by
+    /// `rd_min_confirmations` and `rd_max_confirmations`
+    #[arg(long, env, default_value_t = false)]
+    pub rd_adaptive_confirmations: bool,
+
+    /// Lowest confirmation depth the tuning may pick
+    #[arg(long, env, default_value_t = 1)]
+    pub rd_min_confirmations: usize,
+
+    /// Highest confirmation depth the tuning may pick
+    #[arg(long, env, default_value_t = 64)]
+    pub rd_max_confirmations: usize,
+
+    /// How long an observed reorg is taken into account by the tuning, such
+    /// as `7d`; until reorgs were tracked that long, the depth doesn't go
+    /// below the default confirmations of the state-client
+    #[arg(
+        long,
+        env,
+        default_value = "7d",
+        value_parser = humane::parse_duration
+    )]
+    pub rd_reorg_window: Duration,
+
+    /// Directory where the history of observed reorgs of each chain is kept
+    /// across restarts
+    #[arg(long, env)]
+    pub rd_reorg_history_dir: Option<PathBuf>,
+
+    /// Block the inputs are read up to: `confirmations` for the block at
+    /// the confirmation depth below the head, or `safe` or `finalized` for
+    /// the latest block with that tag on chains past the merge, unless the
+    /// confirmation depth is deeper
+    #[arg(long, env, value_enum, default_value_t = Finality::Confirmations)]
+    pub rd_finality: Finality,
+
+    /// HTTP endpoint of the base layer node the safe and finalized blocks
+    /// are read from
+    #[arg(
+        long,
+        env,
+        required_if_eq_any = [
+            ("rd_finality", "safe"),
+            ("rd_finality", "finalized"),
+        ]
+    )]
+    pub rd_finality_provider_http_endpoint: Option<Url>,
+
+    /// How often the input, output and claim streams are trimmed past the
+    /// events every registered consumer acknowledged, such as `10m`; without
+    /// it, the streams aren't trimmed
+    #[arg(long, env, value_parser = humane::parse_duration)]
+    pub rd_broker_compaction_interval: Option<Duration>,
+
+    /// Most events kept in a stream behind its newest one when compacting,
+    /// even if a consumer hasn't acknowledged them; consumers behind it lose
+    /// the trimmed events
+    #[arg(long, env)]
+    pub rd_broker_compaction_max_lag: Option<usize>,
+
+    /// Comma-separated consumers of the input stream, which must all have
+    /// registered before the stream is trimmed
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "advance-runner,indexer"
+    )]
+    pub rd_broker_compaction_inputs_consumers: Vec<String>,
+
+    /// Comma-separated consumers of the output stream, which must all have
+    /// registered before the stream is trimmed
+    #[arg(long, env, value_delimiter = ',', default_value = "indexer")]
+    pub rd_broker_compaction_outputs_consumers: Vec<String>,
+
+    /// Comma-separated consumers of the claim stream, which must all have
+    /// registered before the stream is trimmed
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "authority-claimer"
+    )]
+    pub rd_broker_compaction_claims_consumers: Vec<String>,
+
+    /// Longest time without a new block from the state-server, or without
+    /// folding one, before the dispatcher stops being live at `/healthz`
+    #[arg(
+        long,
+        env,
+        default_value = "5m",
+        value_parser = humane::parse_duration
+    )]
+    pub rd_max_block_age: Duration,
 
     /// Chain ID
     #[arg(long, env)]
     pub chain_id: u64,
+
+    /// Comma-separated `key=value` labels attached to this DApp's metrics
+    /// (e.g. `environment=production,owner_team=infra`)
+    #[arg(long, env, default_value = "")]
+    pub dapp_labels: Labels,
 }
 
 #[derive(Clone, Debug)]
@@ -45,9 +207,17 @@ pub struct DispatcherConfig {
     pub broker_config: BrokerConfig,
     pub log_config: LogConfig,
     pub blockchain_config: BlockchainConfig,
+    pub runtime_config: RuntimeConfig,
+    pub scheduler_config: SchedulerConfig,
 
-    pub epoch_duration: u64,
+    pub epoch_duration: Duration,
+    pub spool_config: Option<SpoolConfig>,
+    pub confirmations_config: ConfirmationsConfig,
+    pub finality_config: FinalityConfig,
+    pub compaction_config: Option<CompactionConfig>,
+    pub max_block_age: Duration,
     pub chain_id: u64,
+    pub dapp_labels: Labels,
 }
 
 #[derive(Debug, Snafu)]
@@ -57,6 +227,16 @@ pub enum Error {
 
     #[snafu(display("Blockchain configuration error"))]
     BlockchainError { source: BlockchainConfigError },
+
+    #[snafu(display(
+        "min confirmations ({}) must not exceed max confirmations ({})",
+        min,
+        max
+    ))]
+    ConfirmationsBoundsError { min: usize, max: usize },
+
+    #[snafu(display("epoch duration must not be zero"))]
+    ZeroEpochDurationError,
 }
 
 #[derive(Debug)]
@@ -81,13 +261,67 @@ impl Config {
 
         let broker_config = BrokerConfig::from(dispatcher_config.broker_config);
 
+        let confirmations_config = ConfirmationsConfig {
+            adaptive: dispatcher_config.rd_adaptive_confirmations,
+            min: dispatcher_config.rd_min_confirmations,
+            max: dispatcher_config.rd_max_confirmations,
+            window: dispatcher_config.rd_reorg_window,
+            history_dir: dispatcher_config.rd_reorg_history_dir,
+        };
+        ensure!(
+            confirmations_config.min <= confirmations_config.max,
+            ConfirmationsBoundsSnafu {
+                min: confirmations_config.min,
+                max: confirmations_config.max,
+            }
+        );
+
+        // The contracts don't keep the epoch duration, so it comes from the
+        // configuration of the DApp being served
+        let epoch_duration =
+            dispatcher_config.rd_epoch_duration_overrides.resolve(
+                &blockchain_config.dapp_address,
+                dispatcher_config.rd_epoch_duration,
+            );
+        ensure!(!epoch_duration.is_zero(), ZeroEpochDurationSnafu);
+
         let dispatcher_config = DispatcherConfig {
             sc_config,
             broker_config,
             log_config,
             blockchain_config,
-            epoch_duration: dispatcher_config.rd_epoch_duration,
+            runtime_config: dispatcher_config.runtime_config.into(),
+            scheduler_config: dispatcher_config.scheduler_config.into(),
+            epoch_duration,
+            spool_config: dispatcher_config.rd_spool_dir.map(|dir| {
+                SpoolConfig {
+                    dir,
+                    max_size: dispatcher_config.rd_spool_max_size.as_usize(),
+                    retry_interval: dispatcher_config.rd_spool_retry_interval,
+                }
+            }),
+            confirmations_config,
+            finality_config: FinalityConfig {
+                finality: dispatcher_config.rd_finality,
+                provider_http_endpoint: dispatcher_config
+                    .rd_finality_provider_http_endpoint,
+                http_client_config: dispatcher_config.http_client_config.into(),
+            },
+            compaction_config: dispatcher_config
+                .rd_broker_compaction_interval
+                .map(|interval| CompactionConfig {
+                    interval,
+                    max_lag: dispatcher_config.rd_broker_compaction_max_lag,
+                    inputs_consumers: dispatcher_config
+                        .rd_broker_compaction_inputs_consumers,
+                    outputs_consumers: dispatcher_config
+                        .rd_broker_compaction_outputs_consumers,
+                    claims_consumers: dispatcher_config
+                        .rd_broker_compaction_claims_consumers,
+                }),
+            max_block_age: dispatcher_config.rd_max_block_age,
             chain_id: dispatcher_config.chain_id,
+            dapp_labels: dispatcher_config.dapp_labels,
         };
 
         Ok(Config {
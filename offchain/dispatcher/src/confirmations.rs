@@ -0,0 +1,363 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use http_server::{CounterRef, GaugeRef};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Snafu)]
+pub enum ReorgHistoryError {
+    #[snafu(display("failed to create history directory `{}`", path.display()))]
+    CreateHistoryDir { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to read reorg history `{}`", path.display()))]
+    ReadHistory { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to write reorg history `{}`", path.display()))]
+    WriteHistory { path: PathBuf, source: io::Error },
+
+    #[snafu(display(
+        "line {} of reorg history `{}` is corrupted",
+        line,
+        path.display()
+    ))]
+    CorruptedHistory {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfirmationsConfig {
+    /// Whether the confirmation depth follows the observed reorgs, instead
+    /// of staying at the default of the state-client
+    pub adaptive: bool,
+    pub min: usize,
+    pub max: usize,
+    /// How long an observed reorg is taken into account
+    pub window: Duration,
+    /// Directory where the reorg history of each chain is kept, so it
+    /// survives restarts
+    pub history_dir: Option<PathBuf>,
+}
+
+/// Entry of the reorg history file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum HistoryEntry {
+    /// Reorgs are tracked since this time, in seconds since the Unix epoch
+    TrackingStarted {
+        at: u64,
+    },
+    Reorg(ObservedReorg),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObservedReorg {
+    /// Seconds since the Unix epoch
+    pub observed_at: u64,
+    /// Number of the new head after the reorg
+    pub block_number: u64,
+    /// Number of blocks replaced by the reorg
+    pub depth: usize,
+}
+
+/// Tracks the reorgs observed on the chain and picks the confirmation depth
+/// of the finalized view from them.
+///
+/// The tuned depth is twice the deepest reorg in the window, bounded by the
+/// configured min and max. It doesn't go below the default depth until the
+/// reorgs were tracked for a whole window, so a fresh node on a reorg-prone
+/// chain isn't trusting a short history.
+#[derive(Debug)]
+pub struct ConfirmationTuner {
+    config: ConfirmationsConfig,
+    default_confirmations: usize,
+    path: Option<PathBuf>,
+    tracking_started: u64,
+    reorgs: VecDeque<ObservedReorg>,
+    confirmations_gauge: GaugeRef,
+    max_reorg_depth_gauge: GaugeRef,
+    reorgs_counter: CounterRef,
+}
+
+impl ConfirmationTuner {
+    /// Opens the reorg history of the chain, starting a new one if there is
+    /// none
+    pub fn open(
+        config: &ConfirmationsConfig,
+        chain_id: u64,
+        default_confirmations: usize,
+        confirmations_gauge: GaugeRef,
+        max_reorg_depth_gauge: GaugeRef,
+        reorgs_counter: CounterRef,
+    ) -> Result<Self, ReorgHistoryError> {
+        let path = match &config.history_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .context(CreateHistoryDirSnafu { path: dir.clone() })?;
+                Some(dir.join(format!("reorgs-{}.jsonl", chain_id)))
+            }
+            None => None,
+        };
+        let mut tuner = Self {
+            config: config.clone(),
+            default_confirmations,
+            path,
+            tracking_started: unix_secs(SystemTime::now()),
+            reorgs: VecDeque::new(),
+            confirmations_gauge,
+            max_reorg_depth_gauge,
+            reorgs_counter,
+        };
+        tuner.load()?;
+        tuner.update_metrics(SystemTime::now());
+        Ok(tuner)
+    }
+
+    /// Records a reorg observed at the head of the chain
+    pub fn record(
+        &mut self,
+        block_number: u64,
+        depth: usize,
+    ) -> Result<(), ReorgHistoryError> {
+        self.record_at(SystemTime::now(), block_number, depth)
+    }
+
+    /// Depth of the deepest reorg in the window
+    pub fn max_reorg_depth(&self) -> usize {
+        self.max_reorg_depth_at(SystemTime::now())
+    }
+
+    /// Confirmation depth the finalized view should use
+    pub fn confirmations(&self) -> usize {
+        let now = SystemTime::now();
+        self.update_metrics(now);
+        self.confirmations_at(now)
+    }
+
+    fn record_at(
+        &mut self,
+        now: SystemTime,
+        block_number: u64,
+        depth: usize,
+    ) -> Result<(), ReorgHistoryError> {
+        let reorg = ObservedReorg {
+            observed_at: unix_secs(now),
+            block_number,
+            depth,
+        };
+        self.append(&HistoryEntry::Reorg(reorg.clone()))?;
+        self.reorgs.push_back(reorg);
+        self.reorgs_counter.inc();
+        self.update_metrics(now);
+        Ok(())
+    }
+
+    fn max_reorg_depth_at(&self, now: SystemTime) -> usize {
+        let since = unix_secs(now).saturating_sub(self.config.window.as_secs());
+        self.reorgs
+            .iter()
+            .filter(|reorg| reorg.observed_at >= since)
+            .map(|reorg| reorg.depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn confirmations_at(&self, now: SystemTime) -> usize {
+        if !self.config.adaptive {
+            return self.default_confirmations;
+        }
+        let tuned = (self.max_reorg_depth_at(now) * 2)
+            .clamp(self.config.min, self.config.max);
+        let tracked_for = unix_secs(now).saturating_sub(self.tracking_started);
+        if tracked_for < self.config.window.as_secs() {
+            tuned.max(self.default_confirmations.min(self.config.max))
+        } else {
+            tuned
+        }
+    }
+
+    fn update_metrics(&self, now: SystemTime) {
+        self.confirmations_gauge
+            .set(self.confirmations_at(now) as i64);
+        self.max_reorg_depth_gauge
+            .set(self.max_reorg_depth_at(now) as i64);
+    }
+
+    /// Loads the history, dropping the reorgs that left the window
+    fn load(&mut self) -> Result<(), ReorgHistoryError> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return self.save();
+            }
+            Err(e) => return Err(e).context(ReadHistorySnafu { path }),
+        };
+        let since = unix_secs(SystemTime::now())
+            .saturating_sub(self.config.window.as_secs());
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context(ReadHistorySnafu { path: path.clone() })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry =
+                serde_json::from_str(&line).context(CorruptedHistorySnafu {
+                    path: path.clone(),
+                    line: index + 1,
+                })?;
+            match entry {
+                HistoryEntry::TrackingStarted { at } => {
+                    self.tracking_started = at;
+                }
+                HistoryEntry::Reorg(reorg) if reorg.observed_at >= since => {
+                    self.reorgs.push_back(reorg);
+                }
+                HistoryEntry::Reorg(_) => {}
+            }
+        }
+        tracing::info!(
+            "Loaded {} reorgs from `{}`; the deepest has {} blocks",
+            self.reorgs.len(),
+            path.display(),
+            self.max_reorg_depth()
+        );
+        self.save()
+    }
+
+    /// Rewrites the history with the reorgs still in the window
+    fn save(&self) -> Result<(), ReorgHistoryError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let started = HistoryEntry::TrackingStarted {
+            at: self.tracking_started,
+        };
+        let contents: String = std::iter::once(started)
+            .chain(self.reorgs.iter().cloned().map(HistoryEntry::Reorg))
+            .map(|entry| to_line(&entry))
+            .collect();
+        let tmp_path = path.with_extension("jsonl.tmp");
+        let context = || WriteHistorySnafu { path: path.clone() };
+        let mut file = File::create(&tmp_path).with_context(|_| context())?;
+        file.write_all(contents.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|_| context())?;
+        fs::rename(&tmp_path, path).with_context(|_| context())
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> Result<(), ReorgHistoryError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let context = || WriteHistorySnafu { path: path.clone() };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|_| context())?;
+        file.write_all(to_line(entry).as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|_| context())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn to_line(entry: &HistoryEntry) -> String {
+    let mut line = serde_json::to_string(entry)
+        .expect("history entries should always serialize");
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn config(history_dir: Option<PathBuf>) -> ConfirmationsConfig {
+        ConfirmationsConfig {
+            adaptive: true,
+            min: 2,
+            max: 20,
+            window: 7 * DAY,
+            history_dir,
+        }
+    }
+
+    fn open(config: &ConfirmationsConfig) -> ConfirmationTuner {
+        ConfirmationTuner::open(
+            config,
+            1,
+            10,
+            GaugeRef::default(),
+            GaugeRef::default(),
+            CounterRef::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_keeps_the_default_until_a_window_is_tracked() {
+        let mut tuner = open(&config(None));
+        let now = SystemTime::now();
+        assert_eq!(tuner.confirmations_at(now), 10);
+        tuner.record_at(now, 100, 3).unwrap();
+        assert_eq!(tuner.confirmations_at(now), 10);
+
+        // After a window, the reorg is also gone
+        let later = now + 8 * DAY;
+        assert_eq!(tuner.confirmations_at(later), 2);
+        tuner.record_at(later, 200, 4).unwrap();
+        assert_eq!(tuner.confirmations_at(later), 8);
+        tuner.record_at(later, 300, 15).unwrap();
+        assert_eq!(tuner.confirmations_at(later), 20);
+        assert_eq!(tuner.reorgs_counter.get(), 3);
+    }
+
+    #[test]
+    fn it_uses_the_default_when_not_adaptive() {
+        let config = ConfirmationsConfig {
+            adaptive: false,
+            ..config(None)
+        };
+        let mut tuner = open(&config);
+        let later = SystemTime::now() + 8 * DAY;
+        tuner.record_at(later, 100, 8).unwrap();
+        assert_eq!(tuner.confirmations_at(later), 10);
+        assert_eq!(tuner.max_reorg_depth_at(later), 8);
+    }
+
+    #[test]
+    fn it_keeps_the_history_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(Some(dir.path().to_owned()));
+        let mut tuner = open(&config);
+        let tracking_started = tuner.tracking_started;
+        let now = SystemTime::now();
+        tuner.record_at(now - 30 * DAY, 100, 9).unwrap();
+        tuner.record_at(now, 200, 5).unwrap();
+
+        let tuner = open(&config);
+        assert_eq!(tuner.tracking_started, tracking_started);
+        assert_eq!(tuner.reorgs.len(), 1);
+        assert_eq!(tuner.max_reorg_depth(), 5);
+        assert!(dir.path().join("reorgs-1.jsonl").exists());
+    }
+}
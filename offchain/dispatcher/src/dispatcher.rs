@@ -1,29 +1,60 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use backoff::{future::retry_notify, ExponentialBackoffBuilder};
 use eth_state_client_lib::StateServer;
 use eth_state_fold_types::{Block, BlockStreamItem};
+use http_server::Health;
 use rollups_events::DAppMetadata;
-use std::sync::Arc;
+use std::{future::Future, sync::Arc, time::Duration};
 use tokio_stream::StreamExt;
-use tracing::{error, instrument, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 use types::foldables::{InputBox, InputBoxInitialState};
 
 use crate::{
     config::DispatcherConfig,
+    confirmations::ConfirmationTuner,
     drivers::{machine::MachineDriver, Context},
-    error::{BrokerSnafu, DispatcherError, StateServerSnafu},
-    machine::{rollups_broker::BrokerFacade, BrokerSend},
+    error::{
+        BrokerSnafu, DispatcherError, FinalitySnafu, ReorgHistorySnafu,
+        SpoolSnafu, StateServerSnafu,
+    },
+    finality::FoldTarget,
+    machine::{rollups_broker::BrokerFacade, spool::Spool, BrokerSend},
     metrics::DispatcherMetrics,
-    setup::{create_block_subscription, create_context, create_state_server},
+    setup::{
+        create_block_subscription, create_context, create_reorg_subscription,
+        create_state_server,
+    },
 };
 
 use snafu::{whatever, ResultExt};
 
+/// Subsystems of the dispatcher reported at `/healthz` and `/readyz`. The
+/// fold is only up once the state of the first block was synced, which may
+/// take long from genesis.
+const CHAIN_SUBSCRIPTION: &str = "chain_subscription";
+const FOLD: &str = "fold";
+const BROKER: &str = "broker";
+
+/// Backoff between the attempts to subscribe again to the state-server once
+/// a subscription closes, such as when it restarts
+const RESUBSCRIBE_INITIAL_INTERVAL: Duration = Duration::from_secs(1);
+const RESUBSCRIBE_MAX_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Adds the subsystems of the dispatcher, which keep it from being ready
+/// until they are up
+pub fn register_health(health: &Health, config: &DispatcherConfig) {
+    health.register(CHAIN_SUBSCRIPTION, Some(config.max_block_age));
+    health.register(FOLD, Some(config.max_block_age));
+    health.register(BROKER, None);
+}
+
 #[instrument(level = "trace", skip_all)]
 pub async fn start(
     config: DispatcherConfig,
     metrics: DispatcherMetrics,
+    health: Health,
 ) -> Result<(), DispatcherError> {
     trace!("Setting up dispatcher");
 
@@ -35,18 +66,54 @@ pub async fn start(
     trace!("Creating state-server connection");
     let state_server = create_state_server(&config.sc_config).await?;
 
-    trace!("Starting block subscription with confirmations");
-    let mut block_subscription = create_block_subscription(
-        &state_server,
+    trace!("Opening the reorg history");
+    let mut tuner = ConfirmationTuner::open(
+        &config.confirmations_config,
+        config.chain_id,
         config.sc_config.default_confirmations,
+        metrics.confirmations.get_or_create(&dapp_metadata).clone(),
+        metrics
+            .max_reorg_depth
+            .get_or_create(&dapp_metadata)
+            .clone(),
+        metrics
+            .reorgs_observed
+            .get_or_create(&dapp_metadata)
+            .clone(),
     )
-    .await?;
+    .context(ReorgHistorySnafu)?;
+    let mut confirmations = tuner.confirmations();
+    let mut fold_target =
+        FoldTarget::new(&config.finality_config).context(FinalitySnafu)?;
+
+    trace!(
+        "Starting block subscription with {} confirmations",
+        confirmations
+    );
+    let mut block_subscription =
+        create_block_subscription(&state_server, confirmations).await?;
+    let mut reorg_subscription =
+        create_reorg_subscription(&state_server).await?;
 
     trace!("Creating broker connection");
-    let broker =
+    let mut broker =
         BrokerFacade::new(config.broker_config.clone(), dapp_metadata.clone())
             .await
             .context(BrokerSnafu)?;
+    health.up(BROKER);
+    if let Some(spool_config) = &config.spool_config {
+        trace!("Opening the broker spool");
+        let spool = Spool::open(
+            spool_config,
+            metrics.spooled_events.get_or_create(&dapp_metadata).clone(),
+            metrics
+                .spool_size_bytes
+                .get_or_create(&dapp_metadata)
+                .clone(),
+        )
+        .context(SpoolSnafu)?;
+        broker = broker.with_spool(spool);
+    }
 
     trace!("Creating machine driver and blockchain driver");
     let mut machine_driver = MachineDriver::new(
@@ -89,52 +156,150 @@ pub async fn start(
 
     trace!("Starting dispatcher...");
     loop {
-        match block_subscription.next().await {
-            Some(Ok(BlockStreamItem::NewBlock(b))) => {
-                // Normal operation, react on newest block.
-                trace!(
-                    "Received block number {} and hash {:?}, parent: {:?}",
-                    b.number,
-                    b.hash,
-                    b.parent_hash
-                );
-                process_block(
-                    &b,
-                    &state_server,
-                    &initial_state,
-                    &mut context,
-                    &mut machine_driver,
-                    &broker,
-                )
-                .await?
-            }
+        tokio::select! {
+            item = block_subscription.next() => match item {
+                Some(Ok(BlockStreamItem::NewBlock(b))) => {
+                    // Normal operation, react on newest block.
+                    trace!(
+                        "Received block number {} and hash {:?}, parent: {:?}",
+                        b.number,
+                        b.hash,
+                        b.parent_hash
+                    );
+                    health.up(CHAIN_SUBSCRIPTION);
+                    broker.flush_spool().await.context(BrokerSnafu)?;
+                    // The fold goes stale while the target can't be read
+                    match fold_target.next(&b).await {
+                        Ok(target) => {
+                            if let Some(target) = target {
+                                process_block(
+                                    &target,
+                                    &state_server,
+                                    &initial_state,
+                                    &mut context,
+                                    &mut machine_driver,
+                                    &broker,
+                                )
+                                .await?;
+                            }
+                            health.up(FOLD);
+                        }
+                        Err(e) => warn!(
+                            "Failed to read the block to fold to `{}`; waiting for next block...",
+                            e
+                        ),
+                    }
+                }
 
-            Some(Ok(BlockStreamItem::Reorg(bs))) => {
-                error!(
-                    "Deep blockchain reorg of {} blocks; new latest has number {:?}, hash {:?}, and parent {:?}",
-                    bs.len(),
-                    bs.last().map(|b| b.number),
-                    bs.last().map(|b| b.hash),
-                    bs.last().map(|b| b.parent_hash)
-                );
-                error!("Bailing...");
-                whatever!("deep blockchain reorg");
-            }
+                Some(Ok(BlockStreamItem::Reorg(bs))) => {
+                    error!(
+                        "Deep blockchain reorg of {} blocks; new latest has number {:?}, hash {:?}, and parent {:?}",
+                        bs.len(),
+                        bs.last().map(|b| b.number),
+                        bs.last().map(|b| b.hash),
+                        bs.last().map(|b| b.parent_hash)
+                    );
+                    // Recorded so the next run uses a deeper confirmation
+                    // depth, if adaptive
+                    let head = bs.last().map_or(0, |b| b.number.as_u64());
+                    tuner
+                        .record(head, bs.len())
+                        .context(ReorgHistorySnafu)?;
+                    error!("Bailing...");
+                    whatever!("deep blockchain reorg");
+                }
 
-            Some(Err(e)) => {
-                warn!(
-                    "Subscription returned error `{}`; waiting for next block...",
-                    e
-                );
-            }
+                Some(Err(e)) => {
+                    warn!(
+                        "Subscription returned error `{}`; waiting for next block...",
+                        e
+                    );
+                    health.down(CHAIN_SUBSCRIPTION, e);
+                }
 
-            None => {
-                whatever!("subscription closed");
-            }
+                None => {
+                    warn!("Subscription closed; subscribing again...");
+                    health.down(CHAIN_SUBSCRIPTION, "the subscription closed");
+                    // The blocks missed meanwhile aren't skipped, since the
+                    // next block folds the inputs up to it
+                    block_subscription = resubscribe("block", || {
+                        create_block_subscription(&state_server, confirmations)
+                    })
+                    .await;
+                }
+            },
+
+            item = reorg_subscription.next() => match item {
+                Some(Ok(bs)) => {
+                    let head = bs.last().map_or(0, |b| b.number.as_u64());
+                    info!(
+                        "Observed reorg of {} blocks; new latest has number {}",
+                        bs.len(),
+                        head
+                    );
+                    tuner
+                        .record(head, bs.len())
+                        .context(ReorgHistorySnafu)?;
+                }
+
+                Some(Err(e)) => {
+                    warn!(
+                        "Reorg subscription returned error `{}`; waiting for next block...",
+                        e
+                    );
+                }
+
+                None => {
+                    warn!("Reorg subscription closed; subscribing again...");
+                    reorg_subscription = resubscribe("reorg", || {
+                        create_reorg_subscription(&state_server)
+                    })
+                    .await;
+                }
+            },
+        }
+
+        // Reorgs also leave the window as time passes
+        let tuned = tuner.confirmations();
+        if tuned != confirmations {
+            info!(
+                "Changing confirmation depth from {} to {} blocks",
+                confirmations, tuned
+            );
+            confirmations = tuned;
+            block_subscription =
+                create_block_subscription(&state_server, confirmations).await?;
         }
     }
 }
 
+/// Subscribes again to the state-server, backing off exponentially until it
+/// succeeds
+async fn resubscribe<S, F, Fut>(name: &str, subscribe: F) -> S
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<S, DispatcherError>>,
+{
+    let backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(RESUBSCRIBE_INITIAL_INTERVAL)
+        .with_max_interval(RESUBSCRIBE_MAX_INTERVAL)
+        .with_max_elapsed_time(None)
+        .build();
+    let subscription = retry_notify(
+        backoff,
+        || async { subscribe().await.map_err(backoff::Error::transient) },
+        |e, wait: Duration| {
+            warn!(
+                "Failed to subscribe again to the {} stream `{}`; retrying in {:?}",
+                name, e, wait
+            )
+        },
+    )
+    .await;
+    info!("Subscribed again to the {} stream", name);
+    subscription.expect("retries without a maximum elapsed time never fail")
+}
+
 #[instrument(level = "trace", skip_all)]
 #[allow(clippy::too_many_arguments)]
 async fn process_block(
@@ -157,6 +322,13 @@ async fn process_block(
         .await
         .context(StateServerSnafu)?;
 
+    let state_hash = state.state.state_hash();
+    debug!(
+        "Input box state hash at block {} is {:?}",
+        state.block.number, state_hash
+    );
+    context.record_state_hash(state.block.number.as_u64(), state_hash);
+
     // Drive machine
     trace!("Reacting to state with `machine_driver`");
     machine_driver
@@ -166,3 +338,101 @@ async fn process_block(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, BoxStream};
+    use std::sync::Mutex;
+
+    /// A state-server that drops the socket after a few blocks and then
+    /// refuses a few subscriptions, like one that restarts
+    #[derive(Clone)]
+    struct FlakyServer {
+        state: Arc<Mutex<FlakyState>>,
+    }
+
+    struct FlakyState {
+        blocks: u64,
+        next: u64,
+        blocks_per_socket: u64,
+        failures_per_drop: usize,
+        pending_failures: usize,
+        attempts: usize,
+    }
+
+    impl FlakyServer {
+        fn new(
+            blocks: u64,
+            blocks_per_socket: u64,
+            failures_per_drop: usize,
+        ) -> Self {
+            Self {
+                state: Arc::new(Mutex::new(FlakyState {
+                    blocks,
+                    next: 0,
+                    blocks_per_socket,
+                    failures_per_drop,
+                    pending_failures: 0,
+                    attempts: 0,
+                })),
+            }
+        }
+
+        fn attempts(&self) -> usize {
+            self.state.lock().unwrap().attempts
+        }
+
+        async fn subscribe(
+            &self,
+        ) -> Result<BoxStream<'static, u64>, DispatcherError> {
+            let mut state = self.state.lock().unwrap();
+            state.attempts += 1;
+            if state.pending_failures > 0 {
+                state.pending_failures -= 1;
+                return Err(DispatcherError::Whatever {
+                    message: "state-server is restarting".to_owned(),
+                    source: None,
+                });
+            }
+            let server = self.clone();
+            let socket = stream::unfold(0, move |sent| {
+                let server = server.clone();
+                async move {
+                    let mut state = server.state.lock().unwrap();
+                    if sent == state.blocks_per_socket {
+                        state.pending_failures = state.failures_per_drop;
+                        return None;
+                    }
+                    if state.next == state.blocks {
+                        return None;
+                    }
+                    let block = state.next;
+                    state.next += 1;
+                    Some((block, sent + 1))
+                }
+            });
+            Ok(Box::pin(socket))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_resumes_the_blocks_after_the_socket_drops() {
+        let server = FlakyServer::new(10, 3, 2);
+        let mut subscription = server.subscribe().await.unwrap();
+        let mut received = Vec::new();
+        while received.len() < 10 {
+            match subscription.next().await {
+                Some(block) => received.push(block),
+                None => {
+                    subscription =
+                        resubscribe("block", || server.subscribe()).await;
+                }
+            }
+        }
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+        // The socket dropped after blocks 2, 5 and 8, and each time two
+        // attempts failed before subscribing again
+        assert_eq!(server.attempts(), 1 + 3 * 3);
+    }
+}
@@ -6,6 +6,7 @@ use crate::{
     metrics::DispatcherMetrics,
 };
 
+use eth_state_fold_types::ethereum_types::H256;
 use rollups_events::DAppMetadata;
 use types::foldables::Input;
 
@@ -46,6 +47,21 @@ impl Context {
         self.inputs_sent_count
     }
 
+    /// Publishes the state hash of the block, so replicas can be compared
+    pub fn record_state_hash(&self, block_number: u64, state_hash: H256) {
+        let prefix: [u8; 8] = state_hash.as_bytes()[..8]
+            .try_into()
+            .expect("hash should have more than 8 bytes");
+        self.metrics
+            .state_hash_block
+            .get_or_create(&self.dapp_metadata)
+            .set(block_number as i64);
+        self.metrics
+            .state_hash_prefix
+            .get_or_create(&self.dapp_metadata)
+            .set(i64::from_be_bytes(prefix));
+    }
+
     pub async fn finish_epoch_if_needed(
         &mut self,
         event_timestamp: u64,
@@ -0,0 +1,154 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use rollups_events::Address;
+use snafu::{ensure, ResultExt, Snafu};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+#[derive(Debug, Snafu)]
+pub enum EpochDurationError {
+    #[snafu(display(
+        "epoch duration override `{}` is not in the `<dapp address>=<duration>` format",
+        entry
+    ))]
+    InvalidEntry { entry: String },
+
+    #[snafu(display("invalid DApp address `{}`", address))]
+    InvalidAddress {
+        address: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("invalid epoch duration `{}`", duration))]
+    InvalidDuration {
+        duration: String,
+        source: humane::HumaneError,
+    },
+
+    #[snafu(display("epoch duration of DApp `{}` must not be zero", address))]
+    ZeroDuration { address: String },
+
+    #[snafu(display(
+        "epoch duration of DApp `{}` is set more than once",
+        address
+    ))]
+    DuplicateDApp { address: String },
+}
+
+/// Epoch durations of the DApps deployed with a duration other than the
+/// default one.
+///
+/// They are parsed from a comma-separated list of
+/// `<dapp address>=<duration>` pairs, so the same setting can be shared by
+/// the nodes of several DApps.
+#[derive(Clone, Debug, Default)]
+pub struct EpochDurations(HashMap<Address, Duration>);
+
+impl EpochDurations {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Epoch duration of `dapp_address`, or `default` when it isn't
+    /// overridden
+    pub fn resolve(
+        &self,
+        dapp_address: &Address,
+        default: Duration,
+    ) -> Duration {
+        self.0.get(dapp_address).copied().unwrap_or(default)
+    }
+}
+
+impl FromStr for EpochDurations {
+    type Err = EpochDurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut durations = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (address, duration) = entry
+                .split_once('=')
+                .map(|(address, duration)| (address.trim(), duration.trim()))
+                .ok_or_else(|| EpochDurationError::InvalidEntry {
+                    entry: entry.to_owned(),
+                })?;
+            let dapp_address: Address = serde_json::from_value(
+                serde_json::Value::String(address.to_owned()),
+            )
+            .context(InvalidAddressSnafu { address })?;
+            let duration = humane::parse_duration_or_secs(duration)
+                .context(InvalidDurationSnafu { duration })?;
+            ensure!(!duration.is_zero(), ZeroDurationSnafu { address });
+            ensure!(
+                durations.insert(dapp_address, duration).is_none(),
+                DuplicateDAppSnafu { address }
+            );
+        }
+        Ok(Self(durations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAPP: &str = "0x10dc33852b996A4C8A391d6Ed224FD89A3aD1ceE";
+    const OTHER_DAPP: &str = "0xf3D8ce181a502B54512908a32780eaa9183Ef31a";
+
+    fn address(address: &str) -> Address {
+        serde_json::from_value(serde_json::Value::String(address.to_owned()))
+            .unwrap()
+    }
+
+    #[test]
+    fn it_resolves_the_overridden_durations() {
+        let durations: EpochDurations =
+            format!(" {}=1d, {} = 3600 ", DAPP, OTHER_DAPP)
+                .parse()
+                .unwrap();
+        let default = Duration::from_secs(7 * 86400);
+        assert_eq!(
+            durations.resolve(&address(DAPP), default),
+            Duration::from_secs(86400)
+        );
+        assert_eq!(
+            durations.resolve(&address(OTHER_DAPP), default),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(durations.resolve(&Address::default(), default), default);
+    }
+
+    #[test]
+    fn it_parses_empty_overrides() {
+        let durations: EpochDurations = "".parse().unwrap();
+        assert!(durations.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_invalid_overrides() {
+        assert!(matches!(
+            DAPP.parse::<EpochDurations>(),
+            Err(EpochDurationError::InvalidEntry { .. })
+        ));
+        assert!(matches!(
+            "=1d".parse::<EpochDurations>(),
+            Err(EpochDurationError::InvalidAddress { .. })
+        ));
+        assert!(matches!(
+            "0x1234=1d".parse::<EpochDurations>(),
+            Err(EpochDurationError::InvalidAddress { .. })
+        ));
+        assert!(matches!(
+            format!("{}=1 fortnight", DAPP).parse::<EpochDurations>(),
+            Err(EpochDurationError::InvalidDuration { .. })
+        ));
+        assert!(matches!(
+            format!("{}=0", DAPP).parse::<EpochDurations>(),
+            Err(EpochDurationError::ZeroDuration { .. })
+        ));
+        assert!(matches!(
+            format!("{}=1d,{}=2d", DAPP, DAPP).parse::<EpochDurations>(),
+            Err(EpochDurationError::DuplicateDApp { .. })
+        ));
+    }
+}
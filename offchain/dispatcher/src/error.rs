@@ -6,7 +6,7 @@ use snafu::Snafu;
 use std::net::AddrParseError;
 use tonic::{codegen::http::uri::InvalidUri, transport::Error as TonicError};
 
-use crate::machine;
+use crate::{confirmations, finality, machine};
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -14,6 +14,9 @@ pub enum DispatcherError {
     #[snafu(display("http server error"))]
     HttpServerError { source: std::io::Error },
 
+    #[snafu(display("runtime error"))]
+    RuntimeError { source: runtimes::RuntimeError },
+
     #[snafu(display("metrics address error"))]
     MetricsAddressError { source: AddrParseError },
 
@@ -22,12 +25,26 @@ pub enum DispatcherError {
         source: machine::rollups_broker::BrokerFacadeError,
     },
 
+    #[snafu(display("broker compaction error"))]
+    CompactionError { source: rollups_events::BrokerError },
+
     #[snafu(display("connection error"))]
     ChannelError { source: InvalidUri },
 
     #[snafu(display("connection error"))]
     ConnectError { source: TonicError },
 
+    #[snafu(display("broker spool error"))]
+    SpoolError { source: machine::spool::SpoolError },
+
+    #[snafu(display("reorg history error"))]
+    ReorgHistoryError {
+        source: confirmations::ReorgHistoryError,
+    },
+
+    #[snafu(display("finality error"))]
+    FinalityError { source: finality::FinalityError },
+
     #[snafu(display("state server error"))]
     StateServerError { source: StateServerError },
 
@@ -37,7 +54,7 @@ pub enum DispatcherError {
     #[snafu(whatever, display("{message}"))]
     Whatever {
         message: String,
-        #[snafu(source(from(Box<dyn std::error::Error>, Some)))]
-        source: Option<Box<dyn std::error::Error>>,
+        #[snafu(source(from(Box<dyn std::error::Error + Send + Sync>, Some)))]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
     },
 }
@@ -0,0 +1,176 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::ValueEnum;
+use eth_state_fold_types::Block;
+use ethers::{providers::Middleware, types::BlockNumber};
+use http_provider::{HttpClient, HttpClientConfig, HttpProvider};
+use redacted::Url;
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum FinalityError {
+    #[snafu(display("failed to create the HTTP client"))]
+    HttpClientError {
+        source: http_provider::HttpClientError,
+    },
+
+    #[snafu(display("failed to read the {} block", finality))]
+    ProviderError {
+        finality: Finality,
+        source: ethers::providers::ProviderError,
+    },
+}
+
+/// Which block the dispatcher folds the inputs up to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Finality {
+    /// The block at the confirmation depth below the head
+    #[default]
+    Confirmations,
+    /// The latest block the consensus layer deems safe from reorgs, on
+    /// chains past the merge
+    Safe,
+    /// The latest finalized block, on chains past the merge
+    Finalized,
+}
+
+impl Finality {
+    fn tag(self) -> Option<BlockNumber> {
+        match self {
+            Finality::Confirmations => None,
+            Finality::Safe => Some(BlockNumber::Safe),
+            Finality::Finalized => Some(BlockNumber::Finalized),
+        }
+    }
+}
+
+impl std::fmt::Display for Finality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finality::Confirmations => write!(f, "confirmed"),
+            Finality::Safe => write!(f, "safe"),
+            Finality::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FinalityConfig {
+    pub finality: Finality,
+    /// Base layer node the safe and finalized blocks are read from, which
+    /// is only set for those finalities
+    pub provider_http_endpoint: Option<Url>,
+    pub http_client_config: HttpClientConfig,
+}
+
+/// Picks the block to fold up to from the confirmed blocks streamed by the
+/// state-server.
+///
+/// With a tag, the target is the tagged block, unless the confirmed block is
+/// deeper, and it only moves forward, so the inputs of a block are never
+/// read before the block is safe or finalized.
+pub struct FoldTarget {
+    finality: Finality,
+    provider: Option<HttpProvider>,
+    last_number: Option<u64>,
+}
+
+impl FoldTarget {
+    pub fn new(config: &FinalityConfig) -> Result<Self, FinalityError> {
+        let provider = match &config.provider_http_endpoint {
+            Some(endpoint) if config.finality.tag().is_some() => {
+                let http_client = HttpClient::new(&config.http_client_config)
+                    .context(HttpClientSnafu)?;
+                Some(http_client.provider(endpoint.clone()))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            finality: config.finality,
+            provider,
+            last_number: None,
+        })
+    }
+
+    /// Block to fold up to once `confirmed` is streamed, or none if the
+    /// target didn't move forward
+    pub async fn next(
+        &mut self,
+        confirmed: &Block,
+    ) -> Result<Option<Block>, FinalityError> {
+        let (Some(tag), Some(provider)) = (self.finality.tag(), &self.provider)
+        else {
+            return Ok(Some(confirmed.clone()));
+        };
+        let tagged = provider
+            .get_block(tag)
+            .await
+            .context(ProviderSnafu {
+                finality: self.finality,
+            })?
+            .and_then(|block| {
+                Some(Block {
+                    hash: block.hash?,
+                    number: block.number?,
+                    parent_hash: block.parent_hash,
+                    timestamp: block.timestamp,
+                    logs_bloom: block.logs_bloom?,
+                })
+            });
+        // The chain may not have tagged a block yet
+        let Some(tagged) = tagged else {
+            return Ok(None);
+        };
+        let target = if tagged.number < confirmed.number {
+            tagged
+        } else {
+            confirmed.clone()
+        };
+        Ok(self.advance(target))
+    }
+
+    fn advance(&mut self, target: Block) -> Option<Block> {
+        let number = target.number.as_u64();
+        if self.last_number.is_some_and(|last| number <= last) {
+            return None;
+        }
+        self.last_number = Some(number);
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethereum_types::{Bloom, H256};
+
+    fn block(number: u64) -> Block {
+        Block {
+            hash: H256::random(),
+            number: number.into(),
+            parent_hash: H256::random(),
+            timestamp: 0.into(),
+            logs_bloom: Bloom::default(),
+        }
+    }
+
+    #[test]
+    fn it_only_moves_the_target_forward() {
+        let mut target = FoldTarget {
+            finality: Finality::Finalized,
+            provider: None,
+            last_number: None,
+        };
+        assert_eq!(
+            target.advance(block(10)).map(|b| b.number),
+            Some(10.into())
+        );
+        assert!(target.advance(block(10)).is_none());
+        assert!(target.advance(block(9)).is_none());
+        assert_eq!(
+            target.advance(block(12)).map(|b| b.number),
+            Some(12.into())
+        );
+    }
+}
@@ -1,27 +1,75 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+pub mod compaction;
 pub mod config;
 pub mod dispatcher;
+pub mod epoch;
 pub mod machine;
 
+mod confirmations;
 mod drivers;
 mod error;
+mod finality;
 mod metrics;
 mod setup;
 
 use config::Config;
 use error::DispatcherError;
+use http_server::{Health, Router};
 use metrics::DispatcherMetrics;
+use rollups_events::DAppMetadata;
+use runtimes::{RuntimeRole, Runtimes};
+use scheduler::Scheduler;
 use snafu::ResultExt;
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn run(config: Config) -> Result<(), DispatcherError> {
     let metrics = DispatcherMetrics::default();
-    let dispatcher_handle =
-        dispatcher::start(config.dispatcher_config, metrics.clone());
-    let http_server_handle =
-        http_server::start(config.http_server_config, metrics.into());
+    let mut registry = metrics
+        .clone()
+        .into_registry(&config.dispatcher_config.dapp_labels);
+    let runtimes =
+        Runtimes::new(&config.dispatcher_config.runtime_config, &mut registry)
+            .context(error::RuntimeSnafu)?;
+    let compaction_handle = config
+        .dispatcher_config
+        .compaction_config
+        .clone()
+        .map(|compaction| {
+            let dapp_metadata = DAppMetadata {
+                chain_id: config.dispatcher_config.chain_id,
+                dapp_address: config
+                    .dispatcher_config
+                    .blockchain_config
+                    .dapp_address
+                    .clone(),
+            };
+            runtimes.run(
+                RuntimeRole::Fold,
+                compaction::start(
+                    config.dispatcher_config.broker_config.clone(),
+                    dapp_metadata,
+                    compaction,
+                    Scheduler::new(&config.dispatcher_config.scheduler_config),
+                ),
+            )
+        });
+    let health = Health::default();
+    dispatcher::register_health(&health, &config.dispatcher_config);
+    let dispatcher_handle = runtimes.run(
+        RuntimeRole::Fold,
+        dispatcher::start(config.dispatcher_config, metrics, health.clone()),
+    );
+    let http_server_handle = runtimes.run(
+        RuntimeRole::Api,
+        http_server::start_with_health(
+            config.http_server_config,
+            registry,
+            health,
+            Router::new(),
+        ),
+    );
     tokio::select! {
         ret = http_server_handle => {
             ret.context(error::HttpServerSnafu)
@@ -29,5 +77,13 @@ pub async fn run(config: Config) -> Result<(), DispatcherError> {
         ret = dispatcher_handle => {
             ret
         }
+        ret = async {
+            match compaction_handle {
+                Some(handle) => handle.await,
+                None => std::future::pending().await,
+            }
+        } => {
+            ret
+        }
     }
 }
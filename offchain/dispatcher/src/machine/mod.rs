@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 pub mod rollups_broker;
+pub mod spool;
 
 use types::foldables::Input;
 
@@ -12,7 +12,10 @@ use rollups_events::{
 };
 use types::foldables::Input;
 
-use super::{BrokerSend, BrokerStatus, RollupStatus};
+use super::{
+    spool::{Spool, SpoolError, SpooledEvent},
+    BrokerSend, BrokerStatus, RollupStatus,
+};
 
 #[derive(Debug, Snafu)]
 pub enum BrokerFacadeError {
@@ -28,18 +31,39 @@ pub enum BrokerFacadeError {
     #[snafu(display("error producing finish-epoch event"))]
     ProduceFinishError { source: BrokerError },
 
+    #[snafu(display("error spooling event while the broker is unavailable"))]
+    SpoolError { source: SpoolError },
+
     #[snafu(whatever, display("{message}"))]
     Whatever {
         message: String,
-        #[snafu(source(from(Box<dyn std::error::Error>, Some)))]
-        source: Option<Box<dyn std::error::Error>>,
+        #[snafu(source(from(Box<dyn std::error::Error + Send + Sync>, Some)))]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
     },
 }
 
+impl BrokerFacadeError {
+    /// Whether the error comes from not reaching the broker, as opposed to
+    /// the broker rejecting the request
+    fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            BrokerFacadeError::PeekInputError {
+                source: BrokerError::ConnectionError { .. }
+            } | BrokerFacadeError::ProduceInputError {
+                source: BrokerError::ConnectionError { .. }
+            } | BrokerFacadeError::ProduceFinishError {
+                source: BrokerError::ConnectionError { .. }
+            }
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct BrokerFacade {
     broker: Mutex<Broker>,
     inputs_stream: RollupsInputsStream,
+    spool: Option<Mutex<SpoolState>>,
 }
 
 struct BrokerStreamStatus {
@@ -48,6 +72,75 @@ struct BrokerStreamStatus {
     status: RollupStatus,
 }
 
+/// Where the stream is, or will be once the spooled events are published
+#[derive(Clone, Copy, Debug)]
+struct StreamPosition {
+    epoch_number: u64,
+    status: RollupStatus,
+}
+
+impl From<&BrokerStreamStatus> for StreamPosition {
+    fn from(status: &BrokerStreamStatus) -> Self {
+        Self {
+            epoch_number: status.epoch_number,
+            status: status.status,
+        }
+    }
+}
+
+impl StreamPosition {
+    fn after(self, event: &SpooledEvent) -> Self {
+        match event {
+            SpooledEvent::Input { input_index, .. } => Self {
+                epoch_number: self.epoch_number,
+                status: RollupStatus {
+                    inputs_sent_count: input_index + 1,
+                    last_event_is_finish_epoch: false,
+                },
+            },
+            SpooledEvent::FinishEpoch {
+                epoch_number,
+                inputs_sent_count,
+            } => Self {
+                epoch_number: epoch_number + 1,
+                status: RollupStatus {
+                    inputs_sent_count: *inputs_sent_count,
+                    last_event_is_finish_epoch: true,
+                },
+            },
+        }
+    }
+}
+
+impl SpooledEvent {
+    /// Whether the event is already in the stream; a produce that failed
+    /// after reaching the broker leaves it there
+    fn is_published(&self, status: &BrokerStreamStatus) -> bool {
+        match self {
+            SpooledEvent::Input { input_index, .. } => {
+                status.status.inputs_sent_count > *input_index
+            }
+            SpooledEvent::FinishEpoch { epoch_number, .. } => {
+                status.epoch_number > *epoch_number
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SpoolState {
+    spool: Spool,
+    /// Last position read from the broker
+    published: Option<StreamPosition>,
+}
+
+impl SpoolState {
+    fn position(&self) -> Option<StreamPosition> {
+        let published = self.published?;
+        Some(self.spool.iter().fold(published, StreamPosition::after))
+    }
+}
+
 impl BrokerFacade {
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn new(
@@ -60,9 +153,186 @@ impl BrokerFacade {
                 Broker::new(config).await.context(BrokerConnectionSnafu)?,
             ),
             inputs_stream: RollupsInputsStream::new(&dapp_metadata),
+            spool: None,
         })
     }
 
+    /// Keeps the events in the spool while the broker is unavailable, and
+    /// publishes them in order once it is back, instead of failing
+    pub fn with_spool(mut self, spool: Spool) -> Self {
+        self.spool = Some(Mutex::new(SpoolState {
+            spool,
+            published: None,
+        }));
+        self
+    }
+
+    /// Publishes the spooled events, if the broker is back
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn flush_spool(&self) -> Result<(), BrokerFacadeError> {
+        let Some(state) = &self.spool else {
+            return Ok(());
+        };
+        let mut broker = self.broker.lock().await;
+        let mut state = state.lock().await;
+        if state.spool.is_empty() || !state.spool.should_retry() {
+            return Ok(());
+        }
+        let result = self.flush(&mut broker, &mut state).await;
+        match self.record_attempt(&mut state, result) {
+            Err(e) if e.is_unavailable() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Publishes the spooled events in order
+    async fn flush(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        state: &mut SpoolState,
+    ) -> Result<(), BrokerFacadeError> {
+        while let Some(event) = state.spool.front() {
+            let status = self.broker_status(broker).await?;
+            let status = if event.is_published(&status) {
+                tracing::trace!(?event, "skipping spooled event");
+                status
+            } else {
+                self.produce(broker, &status, event).await?
+            };
+            state.published = Some((&status).into());
+            state.spool.pop().context(SpoolSnafu)?;
+        }
+        Ok(())
+    }
+
+    /// Sends the event, or spools it if the broker is unavailable
+    async fn send_or_spool(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        state: &mut SpoolState,
+        event: SpooledEvent,
+    ) -> Result<(), BrokerFacadeError> {
+        if state.spool.should_retry() {
+            let result = async {
+                self.flush(broker, state).await?;
+                let status = self.broker_status(broker).await?;
+                let status = self.produce(broker, &status, &event).await?;
+                state.published = Some((&status).into());
+                Ok::<_, BrokerFacadeError>(())
+            }
+            .await;
+            match self.record_attempt(state, result) {
+                Ok(()) => return Ok(()),
+                Err(e) if !e.is_unavailable() => return Err(e),
+                Err(_) => {}
+            }
+        }
+        tracing::info!(?event, "spooling event");
+        state.spool.push(event).context(SpoolSnafu)
+    }
+
+    /// Records the outcome of an attempt to reach the broker, keeping only
+    /// the errors that aren't about it being unavailable
+    fn record_attempt(
+        &self,
+        state: &mut SpoolState,
+        result: Result<(), BrokerFacadeError>,
+    ) -> Result<(), BrokerFacadeError> {
+        match result {
+            Ok(()) => {
+                state.spool.record_success();
+                Ok(())
+            }
+            Err(e) if e.is_unavailable() => {
+                tracing::warn!(
+                    "Broker is unavailable, keeping {} events in the spool: {}",
+                    state.spool.len(),
+                    e
+                );
+                state.spool.record_failure();
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Position of the stream once the spool is published
+    async fn position(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        state: &mut SpoolState,
+    ) -> Result<StreamPosition, BrokerFacadeError> {
+        if state.published.is_none() {
+            let status = self.broker_status(broker).await?;
+            state.published = Some((&status).into());
+        }
+        Ok(state
+            .position()
+            .expect("published position should be known"))
+    }
+
+    async fn produce(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        status: &BrokerStreamStatus,
+        event: &SpooledEvent,
+    ) -> Result<BrokerStreamStatus, BrokerFacadeError> {
+        match event {
+            SpooledEvent::Input { input_index, input } => {
+                self.produce_input(broker, status, *input_index, input)
+                    .await
+            }
+            SpooledEvent::FinishEpoch {
+                inputs_sent_count, ..
+            } => {
+                self.produce_finish_epoch(broker, status, *inputs_sent_count)
+                    .await
+            }
+        }
+    }
+
+    async fn produce_input(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        status: &BrokerStreamStatus,
+        input_index: u64,
+        input: &Input,
+    ) -> Result<BrokerStreamStatus, BrokerFacadeError> {
+        let event = build_next_input(input, status);
+        tracing::info!(?event, "producing input event");
+
+        input_sanity_check!(event, input_index);
+
+        let id = broker
+            .produce(&self.inputs_stream, event.clone())
+            .await
+            .context(ProduceInputSnafu)?;
+        tracing::trace!(id, "produced event with id");
+
+        Ok(Event { id, payload: event }.into())
+    }
+
+    async fn produce_finish_epoch(
+        &self,
+        broker: &mut sync::MutexGuard<'_, Broker>,
+        status: &BrokerStreamStatus,
+        inputs_sent_count: u64,
+    ) -> Result<BrokerStreamStatus, BrokerFacadeError> {
+        let event = build_next_finish_epoch(status);
+        tracing::trace!(?event, "producing finish epoch event");
+
+        epoch_sanity_check!(event, inputs_sent_count);
+
+        let id = broker
+            .produce(&self.inputs_stream, event.clone())
+            .await
+            .context(ProduceFinishSnafu)?;
+
+        tracing::trace!(id, "produce event with id");
+
+        Ok(Event { id, payload: event }.into())
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     async fn broker_status(
         &self,
@@ -94,7 +364,16 @@ impl BrokerStatus for BrokerFacade {
     async fn status(&self) -> Result<RollupStatus, BrokerFacadeError> {
         tracing::trace!("querying broker status");
         let mut broker = self.broker.lock().await;
-        let status = self.broker_status(&mut broker).await?.status;
+        let status = self.broker_status(&mut broker).await?;
+        let status = match &self.spool {
+            Some(state) => {
+                // The spooled events count as sent
+                let mut state = state.lock().await;
+                state.published = Some((&status).into());
+                state.position().expect("position should be known").status
+            }
+            None => status.status,
+        };
         tracing::trace!(?status, "returning rollup status");
         Ok(status)
     }
@@ -144,20 +423,21 @@ impl BrokerSend for BrokerFacade {
         tracing::trace!(?input_index, ?input, "enqueueing input");
 
         let mut broker = self.broker.lock().await;
-        let status = self.broker_status(&mut broker).await?;
-
-        let event = build_next_input(input, &status);
-        tracing::info!(?event, "producing input event");
-
-        input_sanity_check!(event, input_index);
-
-        let id = broker
-            .produce(&self.inputs_stream, event)
-            .await
-            .context(ProduceInputSnafu)?;
-        tracing::trace!(id, "produced event with id");
+        let Some(state) = &self.spool else {
+            let status = self.broker_status(&mut broker).await?;
+            self.produce_input(&mut broker, &status, input_index, input)
+                .await?;
+            return Ok(());
+        };
 
-        Ok(())
+        let mut state = state.lock().await;
+        let position = self.position(&mut broker, &mut state).await?;
+        assert_eq!(position.status.inputs_sent_count, input_index);
+        let event = SpooledEvent::Input {
+            input_index,
+            input: input.clone(),
+        };
+        self.send_or_spool(&mut broker, &mut state, event).await
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -168,21 +448,21 @@ impl BrokerSend for BrokerFacade {
         tracing::info!(?inputs_sent_count, "finishing epoch");
 
         let mut broker = self.broker.lock().await;
-        let status = self.broker_status(&mut broker).await?;
-
-        let event = build_next_finish_epoch(&status);
-        tracing::trace!(?event, "producing finish epoch event");
-
-        epoch_sanity_check!(event, inputs_sent_count);
-
-        let id = broker
-            .produce(&self.inputs_stream, event)
-            .await
-            .context(ProduceFinishSnafu)?;
-
-        tracing::trace!(id, "produce event with id");
+        let Some(state) = &self.spool else {
+            let status = self.broker_status(&mut broker).await?;
+            self.produce_finish_epoch(&mut broker, &status, inputs_sent_count)
+                .await?;
+            return Ok(());
+        };
 
-        Ok(())
+        let mut state = state.lock().await;
+        let position = self.position(&mut broker, &mut state).await?;
+        assert_eq!(position.status.inputs_sent_count, inputs_sent_count);
+        let event = SpooledEvent::FinishEpoch {
+            epoch_number: position.epoch_number,
+            inputs_sent_count,
+        };
+        self.send_or_spool(&mut broker, &mut state, event).await
     }
 }
 
@@ -277,23 +557,32 @@ fn build_next_finish_epoch(status: &BrokerStreamStatus) -> RollupsInput {
 
 #[cfg(test)]
 mod broker_facade_tests {
-    use std::{sync::Arc, time::Duration};
+    use std::{
+        io,
+        net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+        sync::{Arc, Mutex as StdMutex},
+        time::Duration,
+    };
 
     use backoff::ExponentialBackoffBuilder;
     use eth_state_fold_types::{
         ethereum_types::{Bloom, H160, H256, U256, U64},
         Block,
     };
+    use http_server::GaugeRef;
     use rollups_events::{
         BrokerConfig, BrokerEndpoint, DAppMetadata, Hash, InputMetadata,
         Payload, RedactedUrl, RollupsAdvanceStateInput, RollupsData, Url,
     };
+    use tempfile::TempDir;
     use test_fixtures::broker::BrokerFixture;
     use testcontainers::clients::Cli;
     use types::foldables::Input;
 
     use crate::machine::{
-        rollups_broker::BrokerFacadeError, BrokerSend, BrokerStatus,
+        rollups_broker::BrokerFacadeError,
+        spool::{Spool, SpoolConfig, SpooledEvent},
+        BrokerSend, BrokerStatus,
     };
 
     use super::BrokerFacade;
@@ -456,6 +745,77 @@ mod broker_facade_tests {
 
     // NOTE: cannot test result error because the dependency is not injectable.
 
+    // --------------------------------------------------------------------------------------------
+    // spool
+    // --------------------------------------------------------------------------------------------
+
+    #[tokio::test]
+    async fn spool_keeps_events_while_broker_is_down() {
+        let docker = Cli::default();
+        let fixture = BrokerFixture::setup(&docker).await;
+        let proxy = Proxy::start(fixture.redis_endpoint());
+        let dir = tempfile::tempdir().unwrap();
+        let broker = spooled_setup(&fixture, proxy.endpoint(), &dir).await;
+
+        broker.enqueue_input(0, &new_enqueue_input()).await.unwrap();
+        proxy.cut();
+        broker.enqueue_input(1, &new_enqueue_input()).await.unwrap();
+        broker.enqueue_input(2, &new_enqueue_input()).await.unwrap();
+        broker.finish_epoch(3).await.unwrap();
+        broker.enqueue_input(3, &new_enqueue_input()).await.unwrap();
+        assert_eq!(spooled(&broker).await, 4);
+        assert_eq!(stream(&fixture).await, vec![(0, 1, false)]);
+
+        proxy.restore();
+        flush(&broker).await;
+        let status = broker.status().await.expect("'status' function failed");
+        assert_eq!(status.inputs_sent_count, 4);
+        assert!(!status.last_event_is_finish_epoch);
+        assert_eq!(
+            stream(&fixture).await,
+            vec![
+                (0, 1, false),
+                (0, 2, false),
+                (0, 3, false),
+                (0, 3, true),
+                (1, 4, false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn spool_replays_events_in_order_after_restart() {
+        let docker = Cli::default();
+        let fixture = BrokerFixture::setup(&docker).await;
+        let dir = tempfile::tempdir().unwrap();
+        // The first input reached the broker before the previous run stopped
+        produce_advance_state_inputs(&fixture, 1).await;
+        {
+            let mut spool = open_spool(&dir);
+            for event in [
+                spooled_input(0),
+                spooled_input(1),
+                SpooledEvent::FinishEpoch {
+                    epoch_number: 0,
+                    inputs_sent_count: 2,
+                },
+                spooled_input(2),
+            ] {
+                spool.push(event).unwrap();
+            }
+        }
+
+        let endpoint = fixture.redis_endpoint().clone();
+        let broker = spooled_setup(&fixture, endpoint, &dir).await;
+        assert_eq!(spooled(&broker).await, 4);
+        flush(&broker).await;
+        assert_eq!(
+            stream(&fixture).await,
+            vec![(0, 1, false), (0, 2, false), (0, 2, true), (1, 3, false)]
+        );
+        assert!(open_spool(&dir).is_empty());
+    }
+
     // --------------------------------------------------------------------------------------------
     // auxiliary
     // --------------------------------------------------------------------------------------------
@@ -474,7 +834,7 @@ mod broker_facade_tests {
         };
         let config = BrokerConfig {
             redis_endpoint,
-            consume_timeout: 300000,
+            consume_timeout: Duration::from_secs(300),
             backoff: ExponentialBackoffBuilder::new()
                 .with_initial_interval(Duration::from_millis(1000))
                 .with_max_elapsed_time(Some(Duration::from_millis(3000)))
@@ -492,6 +852,142 @@ mod broker_facade_tests {
         failable_setup(docker, false).await.unwrap()
     }
 
+    async fn spooled_setup(
+        fixture: &BrokerFixture<'_>,
+        redis_endpoint: BrokerEndpoint,
+        dir: &TempDir,
+    ) -> BrokerFacade {
+        let config = BrokerConfig {
+            redis_endpoint,
+            consume_timeout: Duration::from_secs(300),
+            backoff: ExponentialBackoffBuilder::new()
+                .with_initial_interval(Duration::from_millis(10))
+                .with_max_elapsed_time(Some(Duration::from_millis(500)))
+                .build(),
+        };
+        BrokerFacade::new(config, fixture.dapp_metadata())
+            .await
+            .unwrap()
+            .with_spool(open_spool(dir))
+    }
+
+    fn open_spool(dir: &TempDir) -> Spool {
+        let config = SpoolConfig {
+            dir: dir.path().to_owned(),
+            max_size: 1 << 20,
+            retry_interval: Duration::ZERO,
+        };
+        Spool::open(&config, GaugeRef::default(), GaugeRef::default()).unwrap()
+    }
+
+    fn spooled_input(input_index: u64) -> SpooledEvent {
+        SpooledEvent::Input {
+            input_index,
+            input: new_enqueue_input(),
+        }
+    }
+
+    async fn spooled(broker: &BrokerFacade) -> usize {
+        let state = broker.spool.as_ref().expect("facade without spool");
+        state.lock().await.spool.len()
+    }
+
+    /// Flushes the spool until it is empty, since the broker may take a few
+    /// attempts to reconnect
+    async fn flush(broker: &BrokerFacade) {
+        for _ in 0..50 {
+            broker.flush_spool().await.expect("failed to flush spool");
+            if spooled(broker).await == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("spool wasn't flushed");
+    }
+
+    /// Epoch index, inputs sent count and whether it finishes the epoch, for
+    /// each event of the inputs stream
+    async fn stream(fixture: &BrokerFixture<'_>) -> Vec<(u64, u64, bool)> {
+        fixture
+            .consume_all_inputs()
+            .await
+            .into_iter()
+            .map(|input| {
+                (
+                    input.epoch_index,
+                    input.inputs_sent_count,
+                    matches!(input.data, RollupsData::FinishEpoch {}),
+                )
+            })
+            .collect()
+    }
+
+    /// TCP proxy in front of the broker, which the tests cut to make the
+    /// broker unavailable
+    struct Proxy {
+        address: SocketAddr,
+        /// Open connections, or None while the proxy is cut
+        connections: Arc<StdMutex<Option<Vec<TcpStream>>>>,
+    }
+
+    impl Proxy {
+        fn start(redis_endpoint: &BrokerEndpoint) -> Self {
+            let BrokerEndpoint::Single(url) = redis_endpoint else {
+                panic!("fixture should have a single endpoint");
+            };
+            let target = url.inner().socket_addrs(|| None).unwrap()[0];
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let proxy = Self {
+                address: listener.local_addr().unwrap(),
+                connections: Arc::new(StdMutex::new(Some(vec![]))),
+            };
+            let connections = proxy.connections.clone();
+            std::thread::spawn(move || {
+                for client in listener.incoming() {
+                    let client = client.unwrap();
+                    let mut connections = connections.lock().unwrap();
+                    // Dropping the client closes its connection
+                    let Some(connections) = connections.as_mut() else {
+                        continue;
+                    };
+                    let server = TcpStream::connect(target).unwrap();
+                    connections.push(client.try_clone().unwrap());
+                    connections.push(server.try_clone().unwrap());
+                    pipe(
+                        client.try_clone().unwrap(),
+                        server.try_clone().unwrap(),
+                    );
+                    pipe(server, client);
+                }
+            });
+            proxy
+        }
+
+        fn endpoint(&self) -> BrokerEndpoint {
+            let url = format!("redis://{}", self.address);
+            BrokerEndpoint::Single(RedactedUrl::new(Url::parse(&url).unwrap()))
+        }
+
+        /// Closes the open connections and refuses the new ones
+        fn cut(&self) {
+            let connections = self.connections.lock().unwrap().take();
+            for connection in connections.into_iter().flatten() {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+
+        fn restore(&self) {
+            *self.connections.lock().unwrap() = Some(vec![]);
+        }
+    }
+
+    fn pipe(mut from: TcpStream, mut to: TcpStream) {
+        std::thread::spawn(move || {
+            let _ = io::copy(&mut from, &mut to);
+            let _ = to.shutdown(Shutdown::Both);
+        });
+    }
+
     fn new_enqueue_input() -> Input {
         Input {
             sender: Arc::new(H160::random()),
@@ -0,0 +1,346 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use http_server::GaugeRef;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt, Snafu};
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use types::foldables::Input;
+
+const SPOOL_FILE: &str = "broker-spool.jsonl";
+
+#[derive(Debug, Snafu)]
+pub enum SpoolError {
+    #[snafu(display("failed to create spool directory `{}`", path.display()))]
+    CreateDir { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to read spool `{}`", path.display()))]
+    ReadSpool { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to write spool `{}`", path.display()))]
+    WriteSpool { path: PathBuf, source: io::Error },
+
+    #[snafu(display(
+        "line {} of spool `{}` is corrupted",
+        line,
+        path.display()
+    ))]
+    Corrupted {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("spool is full ({} bytes)", max_size))]
+    Full { max_size: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    pub dir: PathBuf,
+    pub max_size: usize,
+    /// Time to wait after failing to reach the broker before trying again
+    pub retry_interval: Duration,
+}
+
+/// Event that couldn't be published because the broker was unavailable.
+///
+/// The events are rebuilt from the status of the broker when they are
+/// published, since the id of their parent is only known then.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SpooledEvent {
+    Input {
+        input_index: u64,
+        input: Input,
+    },
+    FinishEpoch {
+        epoch_number: u64,
+        inputs_sent_count: u64,
+    },
+}
+
+/// Events waiting for the broker, kept in order in a JSON-lines file so they
+/// survive restarts.
+///
+/// Events are appended and synced one by one; publishing one rewrites the
+/// file without it, which is fine since the spool is bounded.
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    events: VecDeque<(SpooledEvent, usize)>,
+    size: usize,
+    max_size: usize,
+    retry_interval: Duration,
+    failed_at: Option<Instant>,
+    events_gauge: GaugeRef,
+    size_gauge: GaugeRef,
+}
+
+impl Spool {
+    /// Opens the spool in the directory, loading the events left by a
+    /// previous run
+    pub fn open(
+        config: &SpoolConfig,
+        events_gauge: GaugeRef,
+        size_gauge: GaugeRef,
+    ) -> Result<Self, SpoolError> {
+        fs::create_dir_all(&config.dir).context(CreateDirSnafu {
+            path: config.dir.clone(),
+        })?;
+        let path = config.dir.join(SPOOL_FILE);
+        let mut spool = Self {
+            path,
+            events: VecDeque::new(),
+            size: 0,
+            max_size: config.max_size,
+            retry_interval: config.retry_interval,
+            failed_at: None,
+            events_gauge,
+            size_gauge,
+        };
+        spool.load()?;
+        if !spool.is_empty() {
+            tracing::warn!(
+                "Spool `{}` has {} events from a previous run",
+                spool.path.display(),
+                spool.len()
+            );
+        }
+        Ok(spool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn front(&self) -> Option<&SpooledEvent> {
+        self.events.front().map(|(event, _)| event)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SpooledEvent> {
+        self.events.iter().map(|(event, _)| event)
+    }
+
+    /// Appends the event to the spool, failing when it is full
+    pub fn push(&mut self, event: SpooledEvent) -> Result<(), SpoolError> {
+        let line = to_line(&event);
+        ensure!(
+            self.size + line.len() <= self.max_size,
+            FullSnafu {
+                max_size: self.max_size
+            }
+        );
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(WriteSpoolSnafu {
+                path: self.path.clone(),
+            })?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .context(WriteSpoolSnafu {
+                path: self.path.clone(),
+            })?;
+        self.size += line.len();
+        self.events.push_back((event, line.len()));
+        self.update_metrics();
+        Ok(())
+    }
+
+    /// Removes the first event, once it was published
+    pub fn pop(&mut self) -> Result<(), SpoolError> {
+        let Some((_, size)) = self.events.pop_front() else {
+            return Ok(());
+        };
+        self.size -= size;
+        self.update_metrics();
+        self.save()
+    }
+
+    /// Whether enough time passed since the broker last failed to try it
+    /// again
+    pub fn should_retry(&self) -> bool {
+        self.failed_at.map_or(true, |failed_at| {
+            failed_at.elapsed() >= self.retry_interval
+        })
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failed_at = Some(Instant::now());
+    }
+
+    pub fn record_success(&mut self) {
+        self.failed_at = None;
+    }
+
+    fn load(&mut self) -> Result<(), SpoolError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(e).context(ReadSpoolSnafu {
+                    path: self.path.clone(),
+                })
+            }
+        };
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context(ReadSpoolSnafu {
+                path: self.path.clone(),
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event =
+                serde_json::from_str(&line).context(CorruptedSnafu {
+                    path: self.path.clone(),
+                    line: index + 1,
+                })?;
+            // Counts the newline too, as in `push`
+            self.size += line.len() + 1;
+            self.events.push_back((event, line.len() + 1));
+        }
+        self.update_metrics();
+        Ok(())
+    }
+
+    /// Replaces the file atomically, so a crash while writing keeps the
+    /// previous events; those already published are skipped on the next run
+    fn save(&self) -> Result<(), SpoolError> {
+        let contents: String = self.iter().map(to_line).collect();
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let context = || WriteSpoolSnafu {
+            path: self.path.clone(),
+        };
+        let mut file = File::create(&tmp_path).with_context(|_| context())?;
+        file.write_all(contents.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|_| context())?;
+        fs::rename(&tmp_path, &self.path).with_context(|_| context())
+    }
+
+    fn update_metrics(&self) {
+        self.events_gauge.set(self.events.len() as i64);
+        self.size_gauge.set(self.size as i64);
+    }
+}
+
+fn to_line(event: &SpooledEvent) -> String {
+    let mut line = serde_json::to_string(event)
+        .expect("spooled events should always serialize");
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::{
+        ethereum_types::{Bloom, H160, H256, U256, U64},
+        Block,
+    };
+    use std::sync::Arc;
+
+    fn config(dir: &tempfile::TempDir, max_size: usize) -> SpoolConfig {
+        SpoolConfig {
+            dir: dir.path().to_owned(),
+            max_size,
+            retry_interval: Duration::from_secs(60),
+        }
+    }
+
+    fn open(config: &SpoolConfig) -> Spool {
+        Spool::open(config, GaugeRef::default(), GaugeRef::default()).unwrap()
+    }
+
+    fn input(input_index: u64) -> SpooledEvent {
+        let block = Block {
+            hash: H256::random(),
+            number: U64::from(input_index),
+            parent_hash: H256::random(),
+            timestamp: U256::from(input_index),
+            logs_bloom: Bloom::default(),
+        };
+        SpooledEvent::Input {
+            input_index,
+            input: Input {
+                sender: Arc::new(H160::random()),
+                payload: vec![input_index as u8],
+                block_added: Arc::new(block),
+                dapp: Arc::new(H160::random()),
+                tx_hash: Arc::new(H256::default()),
+            },
+        }
+    }
+
+    fn input_index(event: &SpooledEvent) -> u64 {
+        match event {
+            SpooledEvent::Input { input_index, .. } => *input_index,
+            SpooledEvent::FinishEpoch { .. } => panic!("not an input"),
+        }
+    }
+
+    #[test]
+    fn it_keeps_events_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 1 << 20);
+        let mut spool = open(&config);
+        for index in 0..3 {
+            spool.push(input(index)).unwrap();
+        }
+        spool
+            .push(SpooledEvent::FinishEpoch {
+                epoch_number: 0,
+                inputs_sent_count: 3,
+            })
+            .unwrap();
+        spool.pop().unwrap();
+        assert_eq!(spool.events_gauge.get(), 3);
+
+        let spool = open(&config);
+        assert_eq!(spool.len(), 3);
+        assert_eq!(input_index(spool.front().unwrap()), 1);
+        assert!(matches!(
+            spool.iter().last(),
+            Some(SpooledEvent::FinishEpoch {
+                inputs_sent_count: 3,
+                ..
+            })
+        ));
+        assert_eq!(spool.size_gauge.get(), spool.size as i64);
+    }
+
+    #[test]
+    fn it_bounds_the_size_of_the_spool() {
+        let dir = tempfile::tempdir().unwrap();
+        let size = to_line(&input(0)).len();
+        let mut spool = open(&config(&dir, size * 2));
+        spool.push(input(0)).unwrap();
+        spool.push(input(1)).unwrap();
+        assert!(matches!(spool.push(input(2)), Err(SpoolError::Full { .. })));
+        spool.pop().unwrap();
+        spool.push(input(2)).unwrap();
+        assert_eq!(spool.len(), 2);
+    }
+
+    #[test]
+    fn it_waits_before_retrying_the_broker() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = open(&config(&dir, 1024));
+        assert!(spool.should_retry());
+        spool.record_failure();
+        assert!(!spool.should_retry());
+        spool.record_success();
+        assert!(spool.should_retry());
+    }
+}
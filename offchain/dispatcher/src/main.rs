@@ -5,11 +5,15 @@
 // NOTE: doesn't support changing epoch_duration in the middle of things.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets::resolve_env().await?;
     let config = dispatcher::config::Config::initialize()?;
 
     log::configure(&config.dispatcher_config.log_config);
 
     log::log_service_start(&config, "Dispatcher");
 
-    dispatcher::run(config).await.map_err(|e| e.into())
+    tokio::select! {
+        ret = dispatcher::run(config) => ret.map_err(|e| e.into()),
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
@@ -1,8 +1,8 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use http_server::{CounterRef, FamilyRef, Registry};
-use rollups_events::DAppMetadata;
+use http_server::{CounterRef, FamilyRef, GaugeRef, Registry};
+use rollups_events::{DAppMetadata, Labels};
 
 const METRICS_PREFIX: &str = "cartesi_rollups_dispatcher";
 
@@ -15,25 +15,70 @@ pub struct DispatcherMetrics {
     pub claims_sent: FamilyRef<DAppMetadata, CounterRef>,
     pub advance_inputs_sent: FamilyRef<DAppMetadata, CounterRef>,
     pub finish_epochs_sent: FamilyRef<DAppMetadata, CounterRef>,
+    pub state_hash_block: FamilyRef<DAppMetadata, GaugeRef>,
+    pub state_hash_prefix: FamilyRef<DAppMetadata, GaugeRef>,
+    pub spooled_events: FamilyRef<DAppMetadata, GaugeRef>,
+    pub spool_size_bytes: FamilyRef<DAppMetadata, GaugeRef>,
+    pub confirmations: FamilyRef<DAppMetadata, GaugeRef>,
+    pub max_reorg_depth: FamilyRef<DAppMetadata, GaugeRef>,
+    pub reorgs_observed: FamilyRef<DAppMetadata, CounterRef>,
 }
 
-impl From<DispatcherMetrics> for Registry {
-    fn from(metrics: DispatcherMetrics) -> Self {
-        let mut registry = Registry::default();
+impl DispatcherMetrics {
+    /// Creates the registry, adding the operator-defined `labels` to every
+    /// metric.
+    pub fn into_registry(self, labels: &Labels) -> Registry {
+        let mut registry = Registry::with_labels(labels.metric_labels());
         registry.register(
             prefixed_metrics("claims_sent"),
             "Counts the number of claims sent",
-            metrics.claims_sent,
+            self.claims_sent,
         );
         registry.register(
             prefixed_metrics("advance_inputs_sent"),
             "Counts the number of <advance_input>s sent",
-            metrics.advance_inputs_sent,
+            self.advance_inputs_sent,
         );
         registry.register(
             prefixed_metrics("finish_epochs_sent"),
             "Counts the number of <finish_epoch>s sent",
-            metrics.finish_epochs_sent,
+            self.finish_epochs_sent,
+        );
+        registry.register(
+            prefixed_metrics("state_hash_block"),
+            "Number of the block of the last input box state hash",
+            self.state_hash_block,
+        );
+        registry.register(
+            prefixed_metrics("state_hash_prefix"),
+            "First 8 bytes of the last input box state hash, as a signed \
+            integer; replicas at the same block must report the same value",
+            self.state_hash_prefix,
+        );
+        registry.register(
+            prefixed_metrics("spooled_events"),
+            "Events waiting in the spool for the broker to be available",
+            self.spooled_events,
+        );
+        registry.register(
+            prefixed_metrics("spool_size_bytes"),
+            "Bytes of the events waiting in the spool",
+            self.spool_size_bytes,
+        );
+        registry.register(
+            prefixed_metrics("confirmations"),
+            "Confirmation depth of the blocks the dispatcher reads inputs from",
+            self.confirmations,
+        );
+        registry.register(
+            prefixed_metrics("max_reorg_depth"),
+            "Depth of the deepest reorg observed in the tracking window",
+            self.max_reorg_depth,
+        );
+        registry.register(
+            prefixed_metrics("reorgs_observed"),
+            "Counts the number of reorgs observed at the head of the chain",
+            self.reorgs_observed,
         );
         registry
     }
@@ -5,7 +5,7 @@ use eth_state_client_lib::{
     config::SCConfig, error::StateServerError, BlockServer,
     GrpcStateFoldClient, StateServer,
 };
-use eth_state_fold_types::{ethereum_types::U64, BlockStreamItem};
+use eth_state_fold_types::{ethereum_types::U64, Block, BlockStreamItem};
 use rollups_events::DAppMetadata;
 use snafu::{ensure, ResultExt};
 use tokio_stream::{Stream, StreamExt};
@@ -73,6 +73,24 @@ pub async fn create_block_subscription(
     Ok(s)
 }
 
+/// Subscribes to the head of the chain, yielding the new blocks of each
+/// reorg, including those shallower than the confirmation depth
+pub async fn create_reorg_subscription(
+    client: &impl BlockServer,
+) -> Result<
+    impl Stream<Item = Result<Vec<Block>, StateServerError>>
+        + Send
+        + std::marker::Unpin,
+    DispatcherError,
+> {
+    let s = client.subscribe_blocks(0).await.context(StateServerSnafu)?;
+    Ok(s.filter_map(|item| match item {
+        Ok(BlockStreamItem::Reorg(blocks)) => Some(Ok(blocks)),
+        Ok(BlockStreamItem::NewBlock(_)) => None,
+        Err(e) => Some(Err(e)),
+    }))
+}
+
 pub async fn create_context(
     config: &DispatcherConfig,
     block_server: &impl BlockServer,
@@ -88,7 +106,8 @@ pub async fn create_context(
         .context(StateServerSnafu)?
         .timestamp
         .as_u64();
-    let epoch_length = config.epoch_duration;
+    let epoch_length = config.epoch_duration.as_secs();
+    tracing::info!(epoch_length, "epoch length in seconds");
 
     let status = broker.status().await.context(BrokerSnafu)?;
 
@@ -0,0 +1,108 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Replays sequences of `NewClaimToHistory` events, with well-formed and
+//! malformed input ranges, through the insertion into the History, and
+//! checks the lookup of the claim of each input against the claims kept.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use eth_state_fold_types::{
+    ethers::types::{Address, Bloom, H256},
+    Block,
+};
+use im::HashMap;
+use libfuzzer_sys::fuzz_target;
+use std::sync::Arc;
+use types::foldables::{push_claim, Claim};
+
+#[derive(Arbitrary, Debug)]
+enum FirstIndex {
+    /// Right after the previous claim, as the History requires
+    Next,
+    /// Any value, as malformed logs may carry
+    Raw(u128),
+}
+
+#[derive(Arbitrary, Debug)]
+struct NewClaim {
+    dapp: u8,
+    first_index: FirstIndex,
+    /// Inputs in the claim past the first one
+    span: u128,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Replay {
+    claims: Vec<NewClaim>,
+    /// Inputs whose claims are looked up afterwards
+    probes: Vec<u128>,
+}
+
+fn claim(first_index: u128, last_index: u128) -> Arc<Claim> {
+    Arc::new(Claim {
+        epoch_hash: H256::repeat_byte(0x55),
+        first_index,
+        last_index,
+        block_added: Arc::new(Block {
+            hash: H256::repeat_byte(0x22),
+            number: 7.into(),
+            parent_hash: H256::repeat_byte(0x33),
+            timestamp: 1000.into(),
+            logs_bloom: Bloom::default(),
+        }),
+        tx_hash: Arc::new(H256::repeat_byte(0x44)),
+    })
+}
+
+fuzz_target!(|replay: Replay| {
+    let mut dapp_claims = HashMap::new();
+    let mut expected: std::collections::HashMap<Address, Vec<(u128, u128)>> =
+        Default::default();
+
+    for new_claim in replay.claims {
+        let dapp = Address::repeat_byte(new_claim.dapp % 4);
+        let previous = expected.get(&dapp).and_then(|claims| claims.last());
+        let first_index = match new_claim.first_index {
+            FirstIndex::Next => previous
+                .map_or(Some(0), |(_, last)| last.checked_add(1))
+                .unwrap_or(u128::MAX),
+            FirstIndex::Raw(first_index) => first_index,
+        };
+        let last_index = first_index.wrapping_add(new_claim.span);
+        let is_valid = first_index <= last_index
+            && previous.map_or(true, |(_, last)| {
+                last.checked_add(1) == Some(first_index)
+            });
+
+        let result = push_claim(
+            &mut dapp_claims,
+            Arc::new(dapp),
+            claim(first_index, last_index),
+        );
+        assert_eq!(result.is_ok(), is_valid, "{:?}", result);
+        if is_valid {
+            expected
+                .entry(dapp)
+                .or_default()
+                .push((first_index, last_index));
+        }
+    }
+
+    for (dapp, ranges) in expected {
+        let claims = &dapp_claims[&dapp];
+        assert_eq!(claims.claims.len(), ranges.len());
+        for probe in replay.probes.iter().copied() {
+            let found = claims.claim_for_input(probe).map(|(index, claim)| {
+                (index, claim.first_index, claim.last_index)
+            });
+            let wanted = ranges
+                .iter()
+                .enumerate()
+                .find(|(_, (first, last))| *first <= probe && probe <= *last)
+                .map(|(index, (first, last))| (index, *first, *last));
+            assert_eq!(found, wanted);
+        }
+    }
+});
@@ -0,0 +1,89 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Replays sequences of `InputAdded` events, with well-formed and malformed
+//! indices, through the insertion into the input boxes, and checks the
+//! result against a plain list of inputs per DApp.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use eth_state_fold_types::{
+    ethers::types::{Address, Bloom, H256, U256},
+    Block,
+};
+use im::HashMap;
+use libfuzzer_sys::fuzz_target;
+use std::sync::Arc;
+use types::foldables::{push_input, Input};
+
+#[derive(Arbitrary, Debug)]
+enum Index {
+    /// The index the InputBox would emit
+    Next,
+    /// Off from the index the InputBox would emit
+    Offset(i8),
+    /// Any 256-bit value, as malformed logs may carry
+    Raw([u8; 32]),
+}
+
+#[derive(Arbitrary, Debug)]
+struct InputAdded {
+    dapp: u8,
+    index: Index,
+    payload: Vec<u8>,
+}
+
+fn block() -> Arc<Block> {
+    Arc::new(Block {
+        hash: H256::repeat_byte(0x22),
+        number: 7.into(),
+        parent_hash: H256::repeat_byte(0x33),
+        timestamp: 1000.into(),
+        logs_bloom: Bloom::default(),
+    })
+}
+
+fuzz_target!(|events: Vec<InputAdded>| {
+    let block = block();
+    let mut input_boxes = HashMap::new();
+    let mut expected: std::collections::HashMap<Address, Vec<Vec<u8>>> =
+        Default::default();
+
+    for event in events {
+        // A few DApps, so their inputs interleave
+        let dapp = Address::repeat_byte(event.dapp % 4);
+        let count = expected.get(&dapp).map_or(0, Vec::len);
+        let index = match event.index {
+            Index::Next => U256::from(count),
+            Index::Offset(offset) => {
+                let index = count as i128 + i128::from(offset);
+                U256::from(index.max(0) as u128)
+            }
+            Index::Raw(bytes) => U256::from_big_endian(&bytes),
+        };
+        let input = Arc::new(Input {
+            sender: Arc::new(Address::repeat_byte(0x11)),
+            payload: event.payload.clone(),
+            block_added: block.clone(),
+            dapp: Arc::new(dapp),
+            tx_hash: Arc::new(H256::repeat_byte(0x44)),
+        });
+
+        let result = push_input(&mut input_boxes, index, input);
+        assert_eq!(result.is_ok(), index == U256::from(count), "{:?}", result);
+        if result.is_ok() {
+            expected.entry(dapp).or_default().push(event.payload);
+        }
+    }
+
+    assert_eq!(input_boxes.len(), expected.len());
+    for (dapp, payloads) in expected {
+        let inputs: Vec<_> = input_boxes[&dapp]
+            .inputs
+            .iter()
+            .map(|input| input.payload.clone())
+            .collect();
+        assert_eq!(inputs, payloads);
+    }
+});
@@ -0,0 +1,447 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use ethers::types::H256;
+use http_server::{CounterRef, GaugeRef, Registry};
+use redacted::RedactedUrl;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError};
+use rollups_data::Repository;
+use scheduler::{JobConfig, Scheduler};
+use sha3::{Digest, Keccak256};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+use crate::chain::ChainReader;
+use crate::explain::CacheOutcome;
+use crate::metrics::prefixed_metrics;
+
+const REDIS_KEY_PREFIX: &str = "graphql-cache";
+#[derive(Debug, Snafu)]
+pub enum QueryCacheError {
+    #[snafu(display("failed to connect to the redis cache tier"))]
+    RedisConnection { source: RedisError },
+}
+
+/// Settings of the query result cache
+#[derive(Debug, Clone)]
+pub struct QueryCacheConfig {
+    /// Bytes kept in memory; the cache is disabled when zero
+    pub max_size: usize,
+    pub ttl: Duration,
+    pub head_poll_interval: Duration,
+    pub redis_endpoint: Option<RedactedUrl>,
+}
+
+/// Cache key of a query, bound to the version of the data it was answered
+/// with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey(H256);
+
+/// What the result of a query depends on: the rows indexed by the node and,
+/// for the values read from the base layer, the head of the chain
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct DataVersion {
+    processed_inputs: i64,
+    proofs: i64,
+    /// Stays zero when the head isn't tracked
+    head: H256,
+}
+
+#[derive(Clone, Debug)]
+struct CacheMetrics {
+    memory_hits: CounterRef,
+    redis_hits: CounterRef,
+    misses: CounterRef,
+    size: GaugeRef,
+}
+
+impl CacheMetrics {
+    fn new(registry: &mut Registry) -> Self {
+        let metrics = Self {
+            memory_hits: CounterRef::default(),
+            redis_hits: CounterRef::default(),
+            misses: CounterRef::default(),
+            size: GaugeRef::default(),
+        };
+        registry.register(
+            prefixed_metrics("cache_memory_hits"),
+            "Queries answered from the in-memory cache",
+            metrics.memory_hits.clone(),
+        );
+        registry.register(
+            prefixed_metrics("cache_redis_hits"),
+            "Queries answered from the redis cache tier",
+            metrics.redis_hits.clone(),
+        );
+        registry.register(
+            prefixed_metrics("cache_misses"),
+            "Queries executed against the database",
+            metrics.misses.clone(),
+        );
+        registry.register(
+            prefixed_metrics("cache_size_bytes"),
+            "Bytes of query results held in memory",
+            metrics.size.clone(),
+        );
+        metrics
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Arc<String>,
+    expires_at: Instant,
+}
+
+/// Results held in memory, evicted in insertion order once over the limit
+#[derive(Debug)]
+struct MemoryTier {
+    entries: HashMap<CacheKey, Entry>,
+    order: VecDeque<CacheKey>,
+    size: usize,
+    max_size: usize,
+}
+
+impl MemoryTier {
+    fn new(max_size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    fn entry_size(value: &str) -> usize {
+        value.len() + std::mem::size_of::<CacheKey>()
+    }
+
+    fn get(&mut self, key: &CacheKey, now: Instant) -> Option<Arc<String>> {
+        let entry = self.entries.get(key)?;
+        if entry.expires_at > now {
+            return Some(entry.value.clone());
+        }
+        self.remove(key);
+        None
+    }
+
+    fn insert(
+        &mut self,
+        key: CacheKey,
+        value: Arc<String>,
+        expires_at: Instant,
+    ) {
+        let size = Self::entry_size(&value);
+        if size > self.max_size {
+            return;
+        }
+        match self.entries.insert(key, Entry { value, expires_at }) {
+            Some(previous) => self.size -= Self::entry_size(&previous.value),
+            None => self.order.push_back(key),
+        }
+        self.size += size;
+        while self.size > self.max_size {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.size -= Self::entry_size(&entry.value);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= Self::entry_size(&entry.value);
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+/// Cache of query results, so the explorers polling the same queries between
+/// blocks don't hit the database every time.
+///
+/// The key includes the number of processed inputs and of proofs, so every
+/// entry becomes unreachable once the node indexes new outputs, along with
+/// the hash of the latest block when there is a chain reader; the TTL bounds
+/// how stale a result can get between polls.
+#[derive(Clone)]
+pub struct QueryCache {
+    memory: Arc<Mutex<MemoryTier>>,
+    redis: Option<ConnectionManager>,
+    version: watch::Receiver<DataVersion>,
+    ttl: Duration,
+    metrics: CacheMetrics,
+}
+
+impl QueryCache {
+    /// Creates the cache, tracking the rows of the `repository` and, when
+    /// there is a chain reader, the head of the chain with the `scheduler`.
+    /// Returns `None` when the cache is disabled.
+    pub async fn new(
+        config: &QueryCacheConfig,
+        repository: Repository,
+        chain_reader: Option<ChainReader>,
+        scheduler: Scheduler,
+        registry: &mut Registry,
+    ) -> Result<Option<Self>, QueryCacheError> {
+        if config.max_size == 0 {
+            return Ok(None);
+        }
+        let redis = match &config.redis_endpoint {
+            Some(endpoint) => {
+                let client = Client::open(endpoint.inner().as_str())
+                    .context(RedisConnectionSnafu)?;
+                Some(
+                    ConnectionManager::new(client)
+                        .await
+                        .context(RedisConnectionSnafu)?,
+                )
+            }
+            None => None,
+        };
+        let (version_tx, version) = watch::channel(DataVersion::default());
+        let interval = config.head_poll_interval;
+        if let Some(chain_reader) = chain_reader {
+            tokio::spawn(track_head(
+                chain_reader,
+                scheduler.clone(),
+                interval,
+                version_tx.clone(),
+            ));
+        }
+        tokio::spawn(track_rows(repository, scheduler, interval, version_tx));
+        Ok(Some(Self {
+            memory: Arc::new(Mutex::new(MemoryTier::new(config.max_size))),
+            redis,
+            version,
+            ttl: config.ttl,
+            metrics: CacheMetrics::new(registry),
+        }))
+    }
+
+    /// Key of the query at the current version of the data
+    pub fn key(&self, query: &str) -> CacheKey {
+        let version = *self.version.borrow();
+        let mut hasher = Keccak256::new();
+        hasher.update(version.processed_inputs.to_be_bytes());
+        hasher.update(version.proofs.to_be_bytes());
+        hasher.update(version.head.as_bytes());
+        hasher.update(query.as_bytes());
+        CacheKey(H256::from_slice(&hasher.finalize()))
+    }
+
+    /// Cached result of the query, along with the tier that held it
+    pub async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Option<(Arc<String>, CacheOutcome)> {
+        let value = self.memory().get(key, Instant::now());
+        if let Some(value) = value {
+            self.metrics.memory_hits.inc();
+            return Some((value, CacheOutcome::MemoryHit));
+        }
+        if let Some(value) = self.get_redis(key).await {
+            self.metrics.redis_hits.inc();
+            let value = Arc::new(value);
+            self.insert_memory(*key, value.clone());
+            return Some((value, CacheOutcome::RedisHit));
+        }
+        self.metrics.misses.inc();
+        None
+    }
+
+    pub async fn insert(&self, key: CacheKey, value: String) {
+        self.set_redis(&key, &value).await;
+        self.insert_memory(key, Arc::new(value));
+    }
+
+    fn memory(&self) -> std::sync::MutexGuard<'_, MemoryTier> {
+        self.memory.lock().expect("Mutex should never be poisoned")
+    }
+
+    fn insert_memory(&self, key: CacheKey, value: Arc<String>) {
+        let mut memory = self.memory();
+        memory.insert(key, value, Instant::now() + self.ttl);
+        self.metrics.size.set(memory.size as i64);
+    }
+
+    async fn get_redis(&self, key: &CacheKey) -> Option<String> {
+        let mut connection = self.redis.clone()?;
+        connection
+            .get::<_, Option<String>>(redis_key(key))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("failed to read from the redis cache: {}", e);
+                None
+            })
+    }
+
+    async fn set_redis(&self, key: &CacheKey, value: &str) {
+        let Some(mut connection) = self.redis.clone() else {
+            return;
+        };
+        let ttl = self.ttl.as_secs().max(1);
+        if let Err(e) = connection
+            .set_ex::<_, _, ()>(redis_key(key), value, ttl)
+            .await
+        {
+            tracing::warn!("failed to write to the redis cache: {}", e);
+        }
+    }
+}
+
+fn redis_key(key: &CacheKey) -> String {
+    format!("{}:{}", REDIS_KEY_PREFIX, hex::encode(key.0))
+}
+
+async fn track_head(
+    chain_reader: ChainReader,
+    scheduler: Scheduler,
+    interval: Duration,
+    version_tx: watch::Sender<DataVersion>,
+) {
+    let job = || async {
+        let hash = chain_reader.latest_block_hash().await?;
+        version_tx.send_if_modified(|version| {
+            let modified = version.head != hash;
+            version.head = hash;
+            modified
+        });
+        Ok::<_, crate::ChainReaderError>(())
+    };
+    scheduler
+        .run("graphql_cache_head", &JobConfig::every(interval), job)
+        .await
+}
+
+async fn track_rows(
+    repository: Repository,
+    scheduler: Scheduler,
+    interval: Duration,
+    version_tx: watch::Sender<DataVersion>,
+) {
+    let job = || async {
+        let repository = repository.clone();
+        let (processed_inputs, proofs) =
+            tokio::task::spawn_blocking(move || {
+                Ok::<_, rollups_data::Error>((
+                    repository.count_processed_inputs()?,
+                    repository.count_proofs()?,
+                ))
+            })
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        version_tx.send_if_modified(|version| {
+            let modified = version.processed_inputs != processed_inputs
+                || version.proofs != proofs;
+            version.processed_inputs = processed_inputs;
+            version.proofs = proofs;
+            modified
+        });
+        Ok::<_, String>(())
+    };
+    scheduler
+        .run("graphql_cache_rows", &JobConfig::every(interval), job)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> CacheKey {
+        CacheKey(H256::repeat_byte(byte))
+    }
+
+    fn value(len: usize) -> Arc<String> {
+        Arc::new("x".repeat(len))
+    }
+
+    #[test]
+    fn it_expires_entries() {
+        let mut memory = MemoryTier::new(1024);
+        let now = Instant::now();
+        memory.insert(key(1), value(10), now + Duration::from_secs(1));
+        assert!(memory.get(&key(1), now).is_some());
+        assert!(memory.get(&key(1), now + Duration::from_secs(1)).is_none());
+        assert_eq!(memory.size, 0);
+        assert!(memory.order.is_empty());
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_entries_over_the_limit() {
+        let entry_size = MemoryTier::entry_size(&value(100));
+        let mut memory = MemoryTier::new(entry_size * 2);
+        let now = Instant::now();
+        let expires_at = now + Duration::from_secs(60);
+        memory.insert(key(1), value(100), expires_at);
+        memory.insert(key(2), value(100), expires_at);
+        memory.insert(key(3), value(100), expires_at);
+        assert!(memory.get(&key(1), now).is_none());
+        assert!(memory.get(&key(2), now).is_some());
+        assert!(memory.get(&key(3), now).is_some());
+        assert_eq!(memory.size, entry_size * 2);
+
+        // Values larger than the whole cache are not kept
+        memory.insert(key(4), value(1000), expires_at);
+        assert!(memory.get(&key(4), now).is_none());
+        assert_eq!(memory.size, entry_size * 2);
+    }
+
+    #[test]
+    fn it_replaces_entries() {
+        let mut memory = MemoryTier::new(1024);
+        let now = Instant::now();
+        let expires_at = now + Duration::from_secs(60);
+        memory.insert(key(1), value(10), expires_at);
+        memory.insert(key(1), value(20), expires_at);
+        assert_eq!(memory.get(&key(1), now).unwrap().len(), 20);
+        assert_eq!(memory.size, MemoryTier::entry_size(&value(20)));
+        assert_eq!(memory.order.len(), 1);
+    }
+
+    fn cache(version: watch::Receiver<DataVersion>) -> QueryCache {
+        QueryCache {
+            memory: Arc::new(Mutex::new(MemoryTier::new(1024))),
+            redis: None,
+            version,
+            ttl: Duration::from_secs(60),
+            metrics: CacheMetrics::new(&mut Registry::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_keys_queries_by_data_version() {
+        let (version_tx, version) = watch::channel(DataVersion::default());
+        let cache = cache(version);
+        let query = r#"{"query":"{ inputs { totalCount } }"}"#;
+        let key = cache.key(query);
+        assert!(cache.get(&key).await.is_none());
+        cache.insert(key, "result".to_owned()).await;
+        let (value, outcome) = cache.get(&key).await.unwrap();
+        assert_eq!(value.as_str(), "result");
+        assert_eq!(outcome, CacheOutcome::MemoryHit);
+        assert_eq!(cache.metrics.memory_hits.get(), 1);
+        assert_eq!(cache.metrics.misses.get(), 1);
+
+        // Newly processed inputs make the previous results unreachable, even
+        // when the head isn't tracked
+        version_tx.send_modify(|version| version.processed_inputs = 1);
+        let processed = cache.key(query);
+        assert_ne!(processed, key);
+
+        // And so do new proofs and a new head
+        version_tx.send_modify(|version| version.proofs = 1);
+        let proven = cache.key(query);
+        assert_ne!(proven, processed);
+        version_tx.send_modify(|version| version.head = H256::repeat_byte(1));
+        assert_ne!(cache.key(query), proven);
+    }
+}
@@ -0,0 +1,170 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use contracts::cartesi_dapp::CartesiDApp;
+use ethers::{
+    contract::ContractError,
+    providers::{Middleware, ProviderError},
+    types::{BlockNumber, H160, H256, U256},
+};
+use http_provider::{HttpClient, HttpProvider as ChainProvider};
+use snafu::{ResultExt, Snafu};
+use std::sync::Arc;
+use url::Url;
+
+#[derive(Debug, Snafu)]
+pub enum ChainReaderError {
+    #[snafu(display("failed to call contract"))]
+    ContractError {
+        source: ContractError<ChainProvider>,
+    },
+
+    #[snafu(display("failed to call provider"))]
+    ProviderError { source: ProviderError },
+}
+
+/// Value read directly from the base layer with `eth_call`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnChainValue<T> {
+    pub value: T,
+    /// Block at which the value was read, so clients can tell how fresh it is
+    pub block_number: u64,
+}
+
+/// Reads point values from the DApp contract, for the data the node doesn't
+/// hold or while its own state is still being rebuilt.
+#[derive(Clone, Debug)]
+pub struct ChainReader {
+    provider: Arc<ChainProvider>,
+    dapp: CartesiDApp<ChainProvider>,
+}
+
+impl ChainReader {
+    pub fn new(
+        http_client: &HttpClient,
+        http_endpoint: Url,
+        dapp_address: H160,
+    ) -> Self {
+        let provider = Arc::new(http_client.provider(http_endpoint));
+        let dapp = CartesiDApp::new(dapp_address, provider.clone());
+        Self { provider, dapp }
+    }
+
+    /// Checks whether the voucher was executed in the DApp contract
+    pub async fn was_voucher_executed(
+        &self,
+        input_index: u64,
+        output_index_within_input: u64,
+    ) -> Result<OnChainValue<bool>, ChainReaderError> {
+        let block_number = self
+            .provider
+            .get_block_number()
+            .await
+            .context(ProviderSnafu)?
+            .as_u64();
+        let value = self
+            .dapp
+            .was_voucher_executed(
+                U256::from(input_index),
+                U256::from(output_index_within_input),
+            )
+            .block(block_number)
+            .call()
+            .await
+            .context(ContractSnafu)?;
+        Ok(OnChainValue {
+            value,
+            block_number,
+        })
+    }
+
+    /// Number of the finalized block, up to which the base layer can no
+    /// longer reorg
+    pub async fn finalized_block_number(
+        &self,
+    ) -> Result<Option<u64>, ChainReaderError> {
+        let block = self
+            .provider
+            .get_block(BlockNumber::Finalized)
+            .await
+            .context(ProviderSnafu)?;
+        Ok(block
+            .and_then(|block| block.number)
+            .map(|number| number.as_u64()))
+    }
+
+    /// Hash of the latest block, which changes whenever the chain moves
+    pub async fn latest_block_hash(&self) -> Result<H256, ChainReaderError> {
+        let block = self
+            .provider
+            .get_block(BlockNumber::Latest)
+            .await
+            .context(ProviderSnafu)?;
+        Ok(block.and_then(|block| block.hash).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpServer};
+    use clap::Parser;
+    use http_provider::{HttpClientCLIConfig, HttpClientConfig};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Node that answers eth_blockNumber and eth_call, keeping the block of
+    /// each call
+    async fn node(
+        calls: web::Data<Mutex<Vec<Value>>>,
+        request: web::Json<Value>,
+    ) -> web::Json<Value> {
+        let request = request.into_inner();
+        let result = match request["method"].as_str() {
+            Some("eth_blockNumber") => json!("0x10"),
+            Some("eth_call") => {
+                calls.lock().unwrap().push(request["params"][1].clone());
+                json!(format!("0x{:064x}", 1))
+            }
+            _ => Value::Null,
+        };
+        web::Json(json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": result,
+        }))
+    }
+
+    #[actix_web::test]
+    async fn it_reads_the_voucher_execution_at_the_latest_block() {
+        let calls = web::Data::new(Mutex::new(vec![]));
+        let data = calls.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/", web::post().to(node))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let config: HttpClientConfig =
+            HttpClientCLIConfig::parse_from(["http_client_config"]).into();
+        let http_client = HttpClient::new(&config).unwrap();
+        let endpoint = Url::parse(&format!("http://{}", address)).unwrap();
+        let reader =
+            ChainReader::new(&http_client, endpoint, H160::repeat_byte(1));
+
+        let execution = reader.was_voucher_executed(2, 0).await.unwrap();
+        assert_eq!(
+            execution,
+            OnChainValue {
+                value: true,
+                block_number: 16,
+            }
+        );
+        assert_eq!(*calls.lock().unwrap(), vec![json!("0x10")]);
+    }
+}
@@ -2,8 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use clap::Parser;
+use ethers::types::H160;
+use http_provider::{HttpClientCLIConfig, HttpClientConfig};
+use humane::ByteSize;
 use log::{LogConfig, LogEnvCliConfig};
+use redacted::{PayloadPolicy, PayloadPolicyCLIConfig, Redacted, RedactedUrl};
 use rollups_data::{RepositoryCLIConfig, RepositoryConfig};
+use scheduler::{SchedulerCLIConfig, SchedulerConfig};
+use std::time::Duration;
+use url::Url;
+
+use crate::cache::QueryCacheConfig;
 
 #[derive(Debug)]
 pub struct GraphQLConfig {
@@ -12,6 +21,22 @@ pub struct GraphQLConfig {
     pub graphql_host: String,
     pub graphql_port: u16,
     pub healthcheck_port: u16,
+    pub chain_reader_config: Option<ChainReaderConfig>,
+    pub http_client_config: HttpClientConfig,
+    pub query_cache_config: QueryCacheConfig,
+    pub scheduler_config: SchedulerConfig,
+    pub json_rpc_port: Option<u16>,
+    pub dapp_address: Option<H160>,
+    pub search_peers: Vec<Url>,
+    pub payload_policy: PayloadPolicy,
+    pub operator_token: Option<Redacted<String>>,
+}
+
+/// Where to read the values that are queried directly from the base layer
+#[derive(Debug, Clone)]
+pub struct ChainReaderConfig {
+    pub http_endpoint: Url,
+    pub dapp_address: H160,
 }
 
 #[derive(Parser)]
@@ -24,15 +49,85 @@ pub struct CLIConfig {
     #[command(flatten)]
     pub log_config: LogEnvCliConfig,
 
+    #[command(flatten)]
+    pub http_client_config: HttpClientCLIConfig,
+
+    #[command(flatten)]
+    pub payload_policy_config: PayloadPolicyCLIConfig,
+
+    #[command(flatten)]
+    pub scheduler_config: SchedulerCLIConfig,
+
     #[arg(long, env, default_value = "127.0.0.1")]
     pub graphql_host: String,
 
     #[arg(long, env, default_value_t = 4000)]
     pub graphql_port: u16,
 
+    /// Port of the read-only JSON-RPC server, which exposes the outputs
+    /// with `rollups_*` methods; without it, the server is not started
+    #[arg(long, env)]
+    pub graphql_json_rpc_port: Option<u16>,
+
     /// Port of health check
     #[arg(long, env = "GRAPHQL_HEALTHCHECK_PORT", default_value_t = 8080)]
     pub healthcheck_port: u16,
+
+    /// HTTP endpoint of the base layer node. When set, point queries the
+    /// node can't answer from its database (such as whether a voucher was
+    /// executed) are read from the chain with `eth_call`
+    #[arg(long, env, requires = "graphql_dapp_address")]
+    pub graphql_chain_http_endpoint: Option<Url>,
+
+    /// Address of the DApp contract, read from the base layer when the
+    /// chain HTTP endpoint is set. It's also the `dappAddress` that keys
+    /// the inputs and outputs when the server is part of a federated graph
+    #[arg(long, env)]
+    pub graphql_dapp_address: Option<H160>,
+
+    /// Memory for the query result cache (e.g. `64MiB`); zero disables it
+    #[arg(
+        long,
+        env,
+        default_value = "0B",
+        value_parser = humane::parse_byte_size
+    )]
+    pub graphql_cache_max_size: ByteSize,
+
+    /// How long a cached query result may be served. Results are also
+    /// dropped when the node processes new inputs or proves their outputs,
+    /// and when a new block arrives, if the chain HTTP endpoint is set
+    #[arg(
+        long,
+        env,
+        default_value = "10s",
+        value_parser = humane::parse_duration
+    )]
+    pub graphql_cache_ttl: Duration,
+
+    /// How often the processed inputs and the latest block are polled to
+    /// invalidate the cache
+    #[arg(
+        long,
+        env,
+        default_value = "1s",
+        value_parser = humane::parse_duration
+    )]
+    pub graphql_cache_head_poll_interval: Duration,
+
+    /// Redis endpoint of a cache tier shared between the server replicas
+    #[arg(long, env)]
+    pub graphql_cache_redis_endpoint: Option<Url>,
+
+    /// Comma-separated GraphQL endpoints of the readers of other DApps and
+    /// chains, which the `search` query also looks up
+    #[arg(long, env, value_delimiter = ',')]
+    pub graphql_search_peers: Vec<Url>,
+
+    /// Token of the operators, who send it as a bearer token to be shown
+    /// the input payloads without redaction
+    #[arg(long, env)]
+    pub graphql_operator_token: Option<String>,
 }
 
 impl From<CLIConfig> for GraphQLConfig {
@@ -43,6 +138,30 @@ impl From<CLIConfig> for GraphQLConfig {
             graphql_host: cli_config.graphql_host,
             graphql_port: cli_config.graphql_port,
             healthcheck_port: cli_config.healthcheck_port,
+            chain_reader_config: cli_config
+                .graphql_chain_http_endpoint
+                .zip(cli_config.graphql_dapp_address)
+                .map(|(http_endpoint, dapp_address)| ChainReaderConfig {
+                    http_endpoint,
+                    dapp_address,
+                }),
+            http_client_config: cli_config.http_client_config.into(),
+            query_cache_config: QueryCacheConfig {
+                max_size: cli_config.graphql_cache_max_size.as_usize(),
+                ttl: cli_config.graphql_cache_ttl,
+                head_poll_interval: cli_config.graphql_cache_head_poll_interval,
+                redis_endpoint: cli_config
+                    .graphql_cache_redis_endpoint
+                    .map(RedactedUrl::new),
+            },
+            scheduler_config: cli_config.scheduler_config.into(),
+            json_rpc_port: cli_config.graphql_json_rpc_port,
+            dapp_address: cli_config.graphql_dapp_address,
+            search_peers: cli_config.graphql_search_peers,
+            payload_policy: cli_config.payload_policy_config.into(),
+            operator_token: cli_config
+                .graphql_operator_token
+                .map(Redacted::new),
         }
     }
 }
@@ -11,6 +11,21 @@ pub enum GraphQLServerError {
         source: http_health_check::HealthCheckError,
     },
 
+    #[snafu(display("failed to create the HTTP client"))]
+    HttpClientError {
+        source: http_provider::HttpClientError,
+    },
+
+    #[snafu(display("failed to create the query cache"))]
+    QueryCacheError { source: crate::QueryCacheError },
+
+    #[snafu(display("failed to bind the {} server to port {}", server, port))]
+    BindError {
+        server: &'static str,
+        port: u16,
+        source: std::io::Error,
+    },
+
     #[snafu(display("server error"))]
     ServerError { source: std::io::Error },
 }
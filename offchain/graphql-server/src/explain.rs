@@ -0,0 +1,221 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Explanation of how a query was answered, for debugging slow or stale
+//! responses.
+//!
+//! Queries opt in with `"extensions": {"explain": true}` in the request, and
+//! the explanation is returned in the `extensions` of the response: whether
+//! the cache answered it, the points in history and the finalized block the
+//! query was bound to, each read of the database, the base layer and the
+//! search peers, and how long each step took.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use rollups_data::AsOf;
+
+/// Whether the request opted in to the explain mode
+pub fn is_requested(request: &Value) -> bool {
+    request
+        .pointer("/extensions/explain")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// How the query cache took part in answering the query
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CacheOutcome {
+    #[default]
+    Disabled,
+    /// Operator queries skip the cache
+    Bypassed,
+    Miss,
+    MemoryHit,
+    RedisHit,
+}
+
+/// Point in history a query was bound to by its `asOf` argument
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Bound {
+    BlockNumber(i64),
+    Timestamp(u64),
+}
+
+impl From<AsOf> for Bound {
+    fn from(as_of: AsOf) -> Self {
+        match as_of {
+            AsOf::BlockNumber(block_number) => Self::BlockNumber(block_number),
+            AsOf::Timestamp(time) => Self::Timestamp(
+                time.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            ),
+        }
+    }
+}
+
+/// Read made while resolving the query
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Source {
+    Database {
+        tables: &'static [&'static str],
+        #[serde(rename = "elapsedMs", serialize_with = "millis")]
+        elapsed: Duration,
+    },
+    BaseLayer {
+        call: &'static str,
+        #[serde(rename = "elapsedMs", serialize_with = "millis")]
+        elapsed: Duration,
+    },
+    SearchPeers {
+        peers: usize,
+        #[serde(rename = "elapsedMs", serialize_with = "millis")]
+        elapsed: Duration,
+    },
+}
+
+/// Time taken by each step of the request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Timings {
+    #[serde(rename = "cacheMs", serialize_with = "millis")]
+    pub cache: Duration,
+    #[serde(rename = "executionMs", serialize_with = "millis")]
+    pub execution: Duration,
+    #[serde(rename = "totalMs", serialize_with = "millis")]
+    pub total: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Explanation {
+    cache: CacheOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finalized_block: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    as_of: Vec<Bound>,
+    sources: Vec<Source>,
+    timings: Timings,
+}
+
+/// Explanation of a query, shared by its resolvers
+#[derive(Clone, Debug, Default)]
+pub struct Explain(Arc<Mutex<Explanation>>);
+
+impl Explain {
+    fn explanation(&self) -> std::sync::MutexGuard<'_, Explanation> {
+        self.0.lock().expect("Mutex should never be poisoned")
+    }
+
+    pub fn cache(&self, outcome: CacheOutcome) {
+        self.explanation().cache = outcome;
+    }
+
+    pub fn finalized_block(&self, block_number: i64) {
+        self.explanation().finalized_block = Some(block_number);
+    }
+
+    pub fn as_of(&self, as_of: AsOf) {
+        let bound = Bound::from(as_of);
+        let mut explanation = self.explanation();
+        if !explanation.as_of.contains(&bound) {
+            explanation.as_of.push(bound);
+        }
+    }
+
+    pub fn source(&self, source: Source) {
+        self.explanation().sources.push(source);
+    }
+
+    /// Adds the explanation to the `extensions` of the GraphQL response
+    pub fn extend(&self, response: &mut Value, timings: Timings) {
+        let mut explanation = self.explanation().clone();
+        explanation.timings = timings;
+        let explanation = serde_json::to_value(explanation)
+            .expect("explanation should serialize");
+        if let Value::Object(response) = response {
+            let extensions = response
+                .entry("extensions")
+                .or_insert_with(|| Value::Object(Default::default()));
+            if let Value::Object(extensions) = extensions {
+                extensions.insert("explain".to_owned(), explanation);
+            }
+        }
+    }
+}
+
+/// Durations are shown in fractional milliseconds
+fn millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_nanos() as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_is_requested_through_the_extensions() {
+        assert!(is_requested(&json!({
+            "query": "{ inputs { totalCount } }",
+            "extensions": {"explain": true},
+        })));
+        assert!(!is_requested(
+            &json!({"query": "{ inputs { totalCount } }"})
+        ));
+        assert!(!is_requested(&json!({"extensions": {"explain": "yes"}})));
+    }
+
+    #[test]
+    fn it_extends_the_response_with_the_explanation() {
+        let explain = Explain::default();
+        explain.cache(CacheOutcome::Miss);
+        explain.finalized_block(120);
+        explain.as_of(AsOf::BlockNumber(100));
+        explain.as_of(AsOf::BlockNumber(100));
+        explain.source(Source::Database {
+            tables: &["inputs"],
+            elapsed: Duration::from_micros(1500),
+        });
+        let mut response = json!({"data": {"inputs": {"totalCount": 2}}});
+        explain.extend(
+            &mut response,
+            Timings {
+                cache: Duration::from_millis(1),
+                execution: Duration::from_millis(2),
+                total: Duration::from_millis(4),
+            },
+        );
+        assert_eq!(
+            response,
+            json!({
+                "data": {"inputs": {"totalCount": 2}},
+                "extensions": {"explain": {
+                    "cache": "miss",
+                    "finalizedBlock": 120,
+                    "asOf": [{"blockNumber": 100}],
+                    "sources": [{
+                        "kind": "database",
+                        "tables": ["inputs"],
+                        "elapsedMs": 1.5,
+                    }],
+                    "timings": {
+                        "cacheMs": 1.0,
+                        "executionMs": 2.0,
+                        "totalMs": 4.0,
+                    },
+                }},
+            })
+        );
+    }
+}
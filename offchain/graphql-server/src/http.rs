@@ -1,28 +1,70 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use crate::schema::{Context, Query, RollupsGraphQLScalarValue, Schema};
+use crate::cache::QueryCache;
+use crate::explain::{self, CacheOutcome, Explain, Timings};
+use crate::metrics::{count_rows, QueryMetrics, QueryOutcome};
+use crate::schema::{
+    encode_representations, Context, Query, RollupsGraphQLScalarValue, Schema,
+};
 use actix_cors::Cors;
 use actix_web::dev::Server;
 use actix_web::{
-    middleware::Logger, web, web::Data, App, HttpResponse, HttpServer,
-    Responder,
+    http::header, middleware::Logger, web, web::Data, App, HttpRequest,
+    HttpResponse, HttpServer, Responder,
 };
+use http_server::Registry;
 use juniper::http::playground::playground_source;
 use juniper::http::GraphQLRequest;
 use juniper::{EmptyMutation, EmptySubscription};
+use redacted::Redacted;
+use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 struct HttpContext {
     schema: Arc<Schema>,
     context: Context,
+    cache: Option<QueryCache>,
+    registry: Arc<Registry>,
+    metrics: QueryMetrics,
+    operator_token: Option<Redacted<String>>,
+}
+
+impl HttpContext {
+    /// Whether the request carries the operator token as a bearer token
+    fn is_operator(&self, request: &HttpRequest) -> bool {
+        let Some(token) = &self.operator_token else {
+            return false;
+        };
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|bearer| {
+                constant_time_eq(bearer.as_bytes(), token.inner().as_bytes())
+            })
+    }
+}
+
+/// Compares without stopping at the first difference, so the time taken
+/// doesn't tell how much of a guessed token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
 }
 
 pub fn start_service(
     host: &str,
     port: u16,
     context: Context,
+    cache: Option<QueryCache>,
+    registry: Registry,
+    metrics: QueryMetrics,
+    operator_token: Option<Redacted<String>>,
 ) -> std::io::Result<Server> {
+    let registry = Arc::new(registry);
     Ok(HttpServer::new(move || {
         let schema = std::sync::Arc::new(Schema::new_with_scalar_value(
             Query,
@@ -33,6 +75,10 @@ pub fn start_service(
         let http_context = HttpContext {
             schema: schema.clone(),
             context: context.clone(),
+            cache: cache.clone(),
+            registry: registry.clone(),
+            metrics: metrics.clone(),
+            operator_token: operator_token.clone(),
         };
 
         let cors = Cors::permissive();
@@ -43,6 +89,7 @@ pub fn start_service(
             .wrap(cors)
             .service(graphql)
             .service(juniper_playground)
+            .service(metrics)
     })
     .bind((host, port))?
     .run())
@@ -56,27 +103,140 @@ async fn juniper_playground() -> impl Responder {
         .body(html)
 }
 
+#[actix_web::get("/metrics")]
+async fn metrics(http_context: web::Data<HttpContext>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(http_server::encode_metrics(&http_context.registry))
+}
+
 #[actix_web::post("/graphql")]
 async fn graphql(
-    query: web::Json<GraphQLRequest<RollupsGraphQLScalarValue>>,
+    http_request: HttpRequest,
+    request: web::Json<serde_json::Value>,
     http_context: web::Data<HttpContext>,
 ) -> HttpResponse {
+    let received_at = Instant::now();
+    // Entity representations sent by a federation gateway are decoded by
+    // the `_Any` scalar, which only takes them as strings
+    let mut request = request.into_inner();
+    if let Some(variables) = request.get_mut("variables") {
+        encode_representations(variables);
+    }
+    let explain = explain::is_requested(&request).then(Explain::default);
+    let query = match serde_json::from_value::<
+        GraphQLRequest<RollupsGraphQLScalarValue>,
+    >(request)
+    {
+        Ok(query) => web::Json(query),
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .body(format!("invalid GraphQL request: {}", err))
+        }
+    };
+    let operation = query.operation_name().map(str::to_owned);
+    let metrics = http_context.metrics.clone();
+    // Operators are shown the full payloads, which are kept out of the
+    // cache shared with the public
+    let operator = http_context.is_operator(&http_request);
+    let cached = match &http_context.cache {
+        Some(_) if operator => {
+            if let Some(explain) = &explain {
+                explain.cache(CacheOutcome::Bypassed);
+            }
+            None
+        }
+        Some(cache) => match serde_json::to_string(&query.0) {
+            Ok(request) => Some((cache.clone(), cache.key(&request))),
+            Err(err) => {
+                tracing::warn!(
+                    "failed to serialize query for caching: {}",
+                    err
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some((cache, key)) = &cached {
+        if let Some((value, outcome)) = cache.get(key).await {
+            metrics.cache_hit(operation.as_deref());
+            let body = match &explain {
+                Some(explain) => {
+                    explain.cache(outcome);
+                    let timings = Timings {
+                        cache: received_at.elapsed(),
+                        execution: Duration::ZERO,
+                        total: received_at.elapsed(),
+                    };
+                    explained(&value, explain, timings)
+                }
+                None => value.to_string(),
+            };
+            return HttpResponse::Ok()
+                .content_type("application/json")
+                .body(body);
+        }
+        if let Some(explain) = &explain {
+            explain.cache(CacheOutcome::Miss);
+        }
+    }
+
     // Execute resolvers in blocking thread as there are lot of blocking diesel db operations
     let query = Arc::new(query);
+    let started_at = Instant::now();
+    let cache_elapsed = started_at - received_at;
+    let context_explain = explain.clone();
     let return_value: HttpResponse = match tokio::task::spawn_blocking(
         move || {
-            let res =
-                query.execute_sync(&http_context.schema, &http_context.context);
-            serde_json::to_string(&res)
+            let mut context = Cow::Borrowed(&http_context.context);
+            if operator {
+                context = Cow::Owned(context.for_operator());
+            }
+            if let Some(explain) = context_explain {
+                context = Cow::Owned(context.explaining(explain));
+            }
+            let res = query.execute_sync(&http_context.schema, &context);
+            serde_json::to_value(&res).map(|value| {
+                (value.to_string(), count_rows(&value), res.is_ok())
+            })
         },
     )
     .await
     {
         Ok(value) => match value {
-            Ok(value) => HttpResponse::Ok()
-                .content_type("application/json")
-                .body(value),
+            Ok((value, rows, is_ok)) => {
+                let execution = started_at.elapsed();
+                metrics.observe(
+                    operation.as_deref(),
+                    QueryOutcome {
+                        elapsed: execution,
+                        rows,
+                        bytes: value.len(),
+                        is_ok,
+                    },
+                );
+                // Errors may be transient, so only successful results are kept
+                if let (Some((cache, key)), true) = (cached, is_ok) {
+                    cache.insert(key, value.clone()).await;
+                }
+                let body = match &explain {
+                    Some(explain) => {
+                        let timings = Timings {
+                            cache: cache_elapsed,
+                            execution,
+                            total: received_at.elapsed(),
+                        };
+                        explained(&value, explain, timings)
+                    }
+                    None => value,
+                };
+                HttpResponse::Ok()
+                    .content_type("application/json")
+                    .body(body)
+            }
             Err(err) => {
+                metrics.failed(operation.as_deref());
                 let error_message = format!(
                             "unable to execute query, internal server error, details: {}", err
                         );
@@ -84,6 +244,7 @@ async fn graphql(
             }
         },
         Err(err) => {
+            metrics.failed(operation.as_deref());
             let error_message = format!(
                 "unable to execute query, internal server error, details: {}",
                 err
@@ -93,3 +254,12 @@ async fn graphql(
     };
     return_value
 }
+
+/// Response with the explanation of the query, which is left out of the
+/// cached results
+fn explained(response: &str, explain: &Explain, timings: Timings) -> String {
+    let mut response = serde_json::from_str(response)
+        .expect("the results should be valid JSON");
+    explain.extend(&mut response, timings);
+    response.to_string()
+}
@@ -1,28 +1,106 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use http_health_check::Health;
+use http_provider::HttpClient;
+use http_server::Registry;
+use scheduler::Scheduler;
 use snafu::ResultExt;
+use std::time::Duration;
 
-pub use config::{CLIConfig, GraphQLConfig};
+pub use cache::{QueryCache, QueryCacheConfig, QueryCacheError};
+pub use chain::{ChainReader, ChainReaderError, OnChainValue};
+pub use config::{CLIConfig, ChainReaderConfig, GraphQLConfig};
 pub use error::GraphQLServerError;
 pub use http::start_service;
+pub use metrics::QueryMetrics;
 pub use schema::Context;
+pub use search::{SearchPeerError, SearchPeers};
 
+mod cache;
+mod chain;
 pub mod config;
 mod error;
+mod explain;
 pub mod http;
+mod metrics;
+pub mod rpc;
 pub mod schema;
+mod search;
+
+/// Subsystem reported at `/healthz` and `/readyz`, checked to be reachable
+/// every `DATABASE_PROBE_INTERVAL`
+const DATABASE: &str = "database";
+const DATABASE_PROBE_INTERVAL: Duration = Duration::from_secs(10);
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn run(config: GraphQLConfig) -> Result<(), GraphQLServerError> {
     let repository = rollups_data::Repository::new(config.repository_config)
         .expect("failed to connect to database");
-    let context = Context::new(repository);
-    let service_handler =
-        start_service(&config.graphql_host, config.graphql_port, context)
-            .expect("failed to create server");
+    let health = Health::default();
+    health.register(DATABASE, None);
+    let probed = repository.clone();
+    tokio::spawn(health.clone().probe(
+        DATABASE,
+        DATABASE_PROBE_INTERVAL,
+        move || probed.ping(),
+    ));
+    let http_client = HttpClient::new(&config.http_client_config)
+        .context(error::HttpClientSnafu)?;
+    let chain_reader = config.chain_reader_config.map(|config| {
+        ChainReader::new(
+            &http_client,
+            config.http_endpoint,
+            config.dapp_address,
+        )
+    });
+    let mut registry = Registry::default();
+    let cache = QueryCache::new(
+        &config.query_cache_config,
+        repository.clone(),
+        chain_reader.clone(),
+        Scheduler::new(&config.scheduler_config),
+        &mut registry,
+    )
+    .await
+    .context(error::QueryCacheSnafu)?;
+    let metrics = QueryMetrics::new(&mut registry, config.dapp_address);
+    let json_rpc_handler = config
+        .json_rpc_port
+        .map(|port| {
+            rpc::start_service(
+                &config.graphql_host,
+                port,
+                repository.clone(),
+                config.payload_policy.clone(),
+                metrics.clone(),
+            )
+            .context(error::BindSnafu {
+                server: "JSON-RPC",
+                port,
+            })
+        })
+        .transpose()?;
+    let context = Context::new(repository, chain_reader)
+        .with_dapp_address(config.dapp_address)
+        .with_search_peers(SearchPeers::new(config.search_peers))
+        .with_payload_policy(config.payload_policy);
+    let service_handler = start_service(
+        &config.graphql_host,
+        config.graphql_port,
+        context,
+        cache,
+        registry,
+        metrics,
+        config.operator_token,
+    )
+    .context(error::BindSnafu {
+        server: "GraphQL",
+        port: config.graphql_port,
+    })?;
 
-    let health_handle = http_health_check::start(config.healthcheck_port);
+    let health_handle =
+        http_health_check::start_with_health(config.healthcheck_port, health);
 
     tokio::select! {
         ret = health_handle => {
@@ -31,5 +109,13 @@ pub async fn run(config: GraphQLConfig) -> Result<(), GraphQLServerError> {
         ret = service_handler => {
             ret.context(error::ServerSnafu)
         }
+        ret = async {
+            match json_rpc_handler {
+                Some(handler) => handler.await,
+                None => std::future::pending().await,
+            }
+        } => {
+            ret.context(error::ServerSnafu)
+        }
     }
 }
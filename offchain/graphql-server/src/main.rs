@@ -7,11 +7,15 @@ use graphql_server::{CLIConfig, GraphQLConfig};
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets::resolve_env().await?;
     let config: GraphQLConfig = CLIConfig::parse().into();
 
     log::configure(&config.log_config);
 
     log::log_service_start(&config, "GraphQL Server");
 
-    graphql_server::run(config).await.map_err(|e| e.into())
+    tokio::select! {
+        ret = graphql_server::run(config) => ret.map_err(|e| e.into()),
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
@@ -0,0 +1,222 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Metrics of the queries served, labeled by operation and DApp, so the
+//! queries that load the database can be told apart.
+//!
+//! GraphQL queries are labeled by their operation name and JSON-RPC calls
+//! by their method. Clients choose those names, so only the first
+//! `MAX_OPERATIONS` valid ones get their own label and the others are
+//! labeled `other`.
+
+use ethers::types::H160;
+use http_server::{
+    exponential_buckets, CounterRef, FamilyRef, HistogramRef, Registry,
+};
+use prometheus_client::encoding::EncodeLabelSet;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const METRICS_PREFIX: &str = "cartesi_rollups_graphql_server";
+
+/// Distinct operation names that get their own label
+const MAX_OPERATIONS: usize = 200;
+const MAX_OPERATION_LENGTH: usize = 64;
+
+const ANONYMOUS_OPERATION: &str = "anonymous";
+const OTHER_OPERATION: &str = "other";
+
+pub(crate) fn prefixed_metrics(name: &str) -> String {
+    format!("{}_{}", METRICS_PREFIX, name)
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueryLabels {
+    pub operation: String,
+    pub dapp_address: String,
+}
+
+type Histograms = FamilyRef<QueryLabels, HistogramRef, fn() -> HistogramRef>;
+
+/// Outcome of a query that was executed against the database
+#[derive(Clone, Copy, Debug)]
+pub struct QueryOutcome {
+    pub elapsed: Duration,
+    /// Rows returned by the connections in the result
+    pub rows: usize,
+    pub bytes: usize,
+    pub is_ok: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueryMetrics {
+    dapp_address: String,
+    operations: Arc<Mutex<HashSet<String>>>,
+    duration: Histograms,
+    rows: Histograms,
+    result_bytes: Histograms,
+    errors: FamilyRef<QueryLabels, CounterRef>,
+    cache_hits: FamilyRef<QueryLabels, CounterRef>,
+}
+
+impl QueryMetrics {
+    pub fn new(registry: &mut Registry, dapp_address: Option<H160>) -> Self {
+        let metrics = Self {
+            dapp_address: dapp_address
+                .map(|address| format!("{:?}", address))
+                .unwrap_or_default(),
+            operations: Default::default(),
+            duration: Histograms::new_with_constructor(|| {
+                HistogramRef::new(exponential_buckets(0.001, 2.0, 14))
+            }),
+            rows: Histograms::new_with_constructor(|| {
+                HistogramRef::new(exponential_buckets(1.0, 2.0, 12))
+            }),
+            result_bytes: Histograms::new_with_constructor(|| {
+                HistogramRef::new(exponential_buckets(256.0, 4.0, 10))
+            }),
+            errors: FamilyRef::default(),
+            cache_hits: FamilyRef::default(),
+        };
+        registry.register(
+            prefixed_metrics("query_duration_seconds"),
+            "Time taken to execute the queries against the database",
+            metrics.duration.clone(),
+        );
+        registry.register(
+            prefixed_metrics("query_rows"),
+            "Rows returned by the connections of the queries",
+            metrics.rows.clone(),
+        );
+        registry.register(
+            prefixed_metrics("query_result_bytes"),
+            "Size of the results of the queries",
+            metrics.result_bytes.clone(),
+        );
+        registry.register(
+            prefixed_metrics("query_errors"),
+            "Queries that failed or returned errors",
+            metrics.errors.clone(),
+        );
+        registry.register(
+            prefixed_metrics("query_cache_hits"),
+            "Queries answered from the cache",
+            metrics.cache_hits.clone(),
+        );
+        metrics
+    }
+
+    pub fn observe(&self, operation: Option<&str>, outcome: QueryOutcome) {
+        let labels = self.labels(operation);
+        self.duration
+            .get_or_create(&labels)
+            .observe(outcome.elapsed.as_secs_f64());
+        self.rows
+            .get_or_create(&labels)
+            .observe(outcome.rows as f64);
+        self.result_bytes
+            .get_or_create(&labels)
+            .observe(outcome.bytes as f64);
+        if !outcome.is_ok {
+            self.errors.get_or_create(&labels).inc();
+        }
+    }
+
+    /// Counts a query that failed before it was executed
+    pub fn failed(&self, operation: Option<&str>) {
+        self.errors.get_or_create(&self.labels(operation)).inc();
+    }
+
+    pub fn cache_hit(&self, operation: Option<&str>) {
+        self.cache_hits.get_or_create(&self.labels(operation)).inc();
+    }
+
+    fn labels(&self, operation: Option<&str>) -> QueryLabels {
+        QueryLabels {
+            operation: self.operation_label(operation),
+            dapp_address: self.dapp_address.clone(),
+        }
+    }
+
+    fn operation_label(&self, operation: Option<&str>) -> String {
+        let Some(operation) = operation else {
+            return ANONYMOUS_OPERATION.to_owned();
+        };
+        let valid = !operation.is_empty()
+            && operation.len() <= MAX_OPERATION_LENGTH
+            && operation
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return OTHER_OPERATION.to_owned();
+        }
+        let mut operations = self.operations.lock().unwrap();
+        if operations.contains(operation) {
+            return operation.to_owned();
+        }
+        if operations.len() < MAX_OPERATIONS {
+            operations.insert(operation.to_owned());
+            return operation.to_owned();
+        }
+        OTHER_OPERATION.to_owned()
+    }
+}
+
+/// Counts the nodes in the `edges` of the connections of a GraphQL result,
+/// including the nested ones
+pub fn count_rows(value: &Value) -> usize {
+    match value {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(name, value)| match (name.as_str(), value) {
+                ("edges", Value::Array(edges)) => {
+                    edges.len() + edges.iter().map(count_rows).sum::<usize>()
+                }
+                _ => count_rows(value),
+            })
+            .sum(),
+        Value::Array(values) => values.iter().map(count_rows).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_bounds_the_operation_labels() {
+        let metrics = QueryMetrics::new(&mut Registry::default(), None);
+        assert_eq!(metrics.operation_label(None), "anonymous");
+        assert_eq!(metrics.operation_label(Some("inputs")), "inputs");
+        assert_eq!(metrics.operation_label(Some("drop table")), "other");
+        for i in 1..MAX_OPERATIONS {
+            metrics.operation_label(Some(&format!("query_{}", i)));
+        }
+        assert_eq!(metrics.operation_label(Some("latest")), "other");
+        assert_eq!(metrics.operation_label(Some("inputs")), "inputs");
+    }
+
+    #[test]
+    fn it_counts_the_rows_of_the_connections() {
+        let result = json!({
+            "data": {
+                "inputs": {
+                    "totalCount": 10,
+                    "edges": [
+                        { "node": { "vouchers": { "edges": [{}, {}] } } },
+                        { "node": { "vouchers": { "edges": [] } } },
+                    ],
+                },
+                "input": { "index": 0 },
+            }
+        });
+        assert_eq!(count_rows(&result), 4);
+        assert_eq!(count_rows(&json!({ "data": null })), 0);
+    }
+}
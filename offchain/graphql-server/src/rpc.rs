@@ -0,0 +1,559 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Read-only JSON-RPC 2.0 facade over the reader API, for the tools built
+//! around generic JSON-RPC clients.
+//!
+//! Methods, with positional or named params:
+//! - `rollups_getInput(index)`
+//! - `rollups_getNotice(inputIndex, outputIndex)`
+//! - `rollups_getVoucher(inputIndex, outputIndex)`
+//! - `rollups_getReport(inputIndex, outputIndex)`
+//! - `rollups_getEpoch(firstInputIndex)`, known once the epoch is finished
+//!   and has outputs
+//!
+//! Indices are JSON numbers or hex quantities (`"0x1a"`), and binary data is
+//! returned in Ethereum hex format, as in the GraphQL API. The server has no
+//! operator access, so the input payloads are always redacted by the payload
+//! policy of the node.
+
+use actix_web::dev::Server;
+use actix_web::{
+    middleware::Logger, web, web::Data, App, HttpResponse, HttpServer,
+};
+use ethers::{types::U256, utils::keccak256};
+use redacted::PayloadPolicy;
+use rollups_data::{
+    Error as RepositoryError, Input, Notice, OutputEnum, Proof, Report,
+    Repository, Voucher,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::metrics::{QueryMetrics, QueryOutcome};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Server-defined error for the items that don't exist
+const NOT_FOUND: i64 = -32001;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<RepositoryError> for RpcError {
+    fn from(error: RepositoryError) -> Self {
+        match error {
+            RepositoryError::ItemNotFound { .. } => {
+                Self::new(NOT_FOUND, error.to_string())
+            }
+            _ => {
+                tracing::warn!("JSON-RPC call failed: {:?}", error);
+                Self::new(INTERNAL_ERROR, error.to_string())
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Requests without an id are notifications, which get no response
+    id: Option<Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl Response {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+pub fn start_service(
+    host: &str,
+    port: u16,
+    repository: Repository,
+    payload_policy: PayloadPolicy,
+    metrics: QueryMetrics,
+) -> std::io::Result<Server> {
+    let payload_policy = Data::new(payload_policy);
+    let metrics = Data::new(metrics);
+    Ok(HttpServer::new(move || {
+        App::new()
+            .app_data(Data::new(repository.clone()))
+            .app_data(payload_policy.clone())
+            .app_data(metrics.clone())
+            .wrap(Logger::default())
+            .service(json_rpc)
+    })
+    .bind((host, port))?
+    .run())
+}
+
+#[actix_web::post("/")]
+async fn json_rpc(
+    body: web::Bytes,
+    repository: web::Data<Repository>,
+    payload_policy: web::Data<PayloadPolicy>,
+    metrics: web::Data<QueryMetrics>,
+) -> HttpResponse {
+    // Calls run in a blocking thread, as the diesel db operations block
+    let response = tokio::task::spawn_blocking(move || {
+        handle(&body, |method, params| {
+            let started_at = Instant::now();
+            let result = call(&repository, &payload_policy, method, params);
+            metrics.observe(
+                Some(method),
+                QueryOutcome {
+                    elapsed: started_at.elapsed(),
+                    rows: result.is_ok().into(),
+                    bytes: result
+                        .as_ref()
+                        .map_or(0, |value| value.to_string().len()),
+                    is_ok: result.is_ok(),
+                },
+            );
+            result
+        })
+    })
+    .await;
+    match response {
+        Ok(Some(response)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(response.to_string()),
+        Ok(None) => HttpResponse::NoContent().finish(),
+        Err(err) => HttpResponse::InternalServerError().body(format!(
+            "unable to execute call, internal server error, details: {}",
+            err
+        )),
+    }
+}
+
+/// Handles a single or a batch request, returning `None` when there is
+/// nothing to respond (notifications only)
+fn handle(
+    body: &[u8],
+    call: impl Fn(&str, &Value) -> Result<Value, RpcError>,
+) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, e.to_string());
+            return Some(to_value(Response::new(Value::Null, Err(error))));
+        }
+    };
+    match request {
+        Value::Array(requests) if requests.is_empty() => {
+            let error = RpcError::new(INVALID_REQUEST, "empty batch");
+            Some(to_value(Response::new(Value::Null, Err(error))))
+        }
+        Value::Array(requests) => {
+            let responses: Vec<Value> = requests
+                .into_iter()
+                .filter_map(|request| handle_one(request, &call))
+                .map(to_value)
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        request => handle_one(request, &call).map(to_value),
+    }
+}
+
+fn handle_one(
+    request: Value,
+    call: &impl Fn(&str, &Value) -> Result<Value, RpcError>,
+) -> Option<Response> {
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(INVALID_REQUEST, e.to_string());
+            return Some(Response::new(Value::Null, Err(error)));
+        }
+    };
+    let id = request.id?;
+    if request.jsonrpc != "2.0" {
+        let error = RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+        return Some(Response::new(id, Err(error)));
+    }
+    Some(Response::new(id, call(&request.method, &request.params)))
+}
+
+fn to_value(response: Response) -> Value {
+    serde_json::to_value(response).expect("response should serialize")
+}
+
+fn call(
+    repository: &Repository,
+    payload_policy: &PayloadPolicy,
+    method: &str,
+    params: &Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "rollups_getInput" => {
+            let [index] = indices(params, ["index"])?;
+            let input = repository.get_input(index)?;
+            Ok(input_json(&input, payload_policy))
+        }
+        "rollups_getNotice" => {
+            let [input_index, index] =
+                indices(params, ["inputIndex", "outputIndex"])?;
+            let notice = repository.get_notice(index, input_index)?;
+            let proof =
+                repository.get_proof(input_index, index, OutputEnum::Notice)?;
+            Ok(notice_json(&notice, proof.as_ref()))
+        }
+        "rollups_getVoucher" => {
+            let [input_index, index] =
+                indices(params, ["inputIndex", "outputIndex"])?;
+            let voucher = repository.get_voucher(index, input_index)?;
+            let proof = repository.get_proof(
+                input_index,
+                index,
+                OutputEnum::Voucher,
+            )?;
+            Ok(voucher_json(&voucher, proof.as_ref()))
+        }
+        "rollups_getReport" => {
+            let [input_index, index] =
+                indices(params, ["inputIndex", "outputIndex"])?;
+            Ok(report_json(&repository.get_report(index, input_index)?))
+        }
+        "rollups_getEpoch" => {
+            let [first_input_index] = indices(params, ["firstInputIndex"])?;
+            let proofs = repository.get_epoch_proofs(first_input_index)?;
+            epoch_json(first_input_index, &proofs).ok_or_else(|| {
+                RpcError::new(
+                    NOT_FOUND,
+                    format!(
+                        "no finished epoch with outputs starts at input {}",
+                        first_input_index
+                    ),
+                )
+            })
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("method `{}` not found", method),
+        )),
+    }
+}
+
+/// Reads the index params, given by position or by name
+fn indices<const N: usize>(
+    params: &Value,
+    names: [&str; N],
+) -> Result<[i32; N], RpcError> {
+    let values: Vec<Option<&Value>> = match params {
+        Value::Array(values) if values.len() == N => {
+            values.iter().map(Some).collect()
+        }
+        Value::Array(values) => {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("expected {} params, got {}", N, values.len()),
+            ))
+        }
+        Value::Object(map) => names.iter().map(|name| map.get(*name)).collect(),
+        Value::Null if N == 0 => vec![],
+        _ => {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "params must be an array or an object",
+            ))
+        }
+    };
+    let mut indices = [0; N];
+    for ((index, value), name) in indices.iter_mut().zip(values).zip(names) {
+        *index = value.and_then(parse_index).ok_or_else(|| {
+            RpcError::new(
+                INVALID_PARAMS,
+                format!("`{}` must be a non-negative index", name),
+            )
+        })?;
+    }
+    Ok(indices)
+}
+
+fn parse_index(value: &Value) -> Option<i32> {
+    match value {
+        Value::Number(number) => number.as_u64()?.try_into().ok(),
+        Value::String(quantity) => {
+            let digits = quantity.strip_prefix("0x")?;
+            u32::from_str_radix(digits, 16).ok()?.try_into().ok()
+        }
+        _ => None,
+    }
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(data))
+}
+
+fn input_json(input: &Input, payload_policy: &PayloadPolicy) -> Value {
+    let timestamp = input
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    json!({
+        "index": input.index,
+        "status": format!("{:?}", input.status),
+        "msgSender": hex_encode(&input.msg_sender),
+        "timestamp": timestamp,
+        "blockNumber": input.block_number,
+        "transactionHash": hex_encode(&input.tx_hash),
+        "payload": hex_encode(&payload_policy.payload(&input.payload)),
+    })
+}
+
+fn proof_json(proof: &Proof) -> Value {
+    let siblings = |hashes: &Vec<Option<Vec<u8>>>| -> Vec<String> {
+        hashes
+            .iter()
+            .map(|hash| hex_encode(hash.as_deref().unwrap_or_default()))
+            .collect()
+    };
+    json!({
+        "validity": {
+            "inputIndexWithinEpoch": proof.validity_input_index_within_epoch,
+            "outputIndexWithinInput": proof.validity_output_index_within_input,
+            "outputHashesRootHash":
+                hex_encode(&proof.validity_output_hashes_root_hash),
+            "vouchersEpochRootHash":
+                hex_encode(&proof.validity_vouchers_epoch_root_hash),
+            "noticesEpochRootHash":
+                hex_encode(&proof.validity_notices_epoch_root_hash),
+            "machineStateHash": hex_encode(&proof.validity_machine_state_hash),
+            "outputHashInOutputHashesSiblings":
+                siblings(&proof.validity_output_hash_in_output_hashes_siblings),
+            "outputHashesInEpochSiblings":
+                siblings(&proof.validity_output_hashes_in_epoch_siblings),
+        },
+        "context": hex_encode(&proof.context),
+    })
+}
+
+fn notice_json(notice: &Notice, proof: Option<&Proof>) -> Value {
+    json!({
+        "inputIndex": notice.input_index,
+        "index": notice.index,
+        "payload": hex_encode(&notice.payload),
+        "proof": proof.map(proof_json),
+    })
+}
+
+fn voucher_json(voucher: &Voucher, proof: Option<&Proof>) -> Value {
+    json!({
+        "inputIndex": voucher.input_index,
+        "index": voucher.index,
+        "destination": hex_encode(&voucher.destination),
+        "payload": hex_encode(&voucher.payload),
+        "proof": proof.map(proof_json),
+    })
+}
+
+fn report_json(report: &Report) -> Value {
+    json!({
+        "inputIndex": report.input_index,
+        "index": report.index,
+        "payload": hex_encode(&report.payload),
+    })
+}
+
+/// Epoch that starts at `first_input_index`, as told by the proofs of its
+/// outputs, which all hold the same roots
+fn epoch_json(first_input_index: i32, proofs: &[Proof]) -> Option<Value> {
+    let proof = proofs.first()?;
+    let epoch_hash = keccak256(
+        [
+            proof.validity_vouchers_epoch_root_hash.as_slice(),
+            proof.validity_notices_epoch_root_hash.as_slice(),
+            proof.validity_machine_state_hash.as_slice(),
+        ]
+        .concat(),
+    );
+    let history_index = (proof.context.len() == 32)
+        .then(|| U256::from_big_endian(&proof.context))
+        .filter(|index| *index <= U256::from(i64::MAX))
+        .map(|index| index.as_u64());
+    let count = |output_enum| {
+        proofs
+            .iter()
+            .filter(|proof| proof.output_enum == output_enum)
+            .count()
+    };
+    Some(json!({
+        "firstInputIndex": first_input_index,
+        "epochHash": hex_encode(&epoch_hash),
+        "machineStateHash": hex_encode(&proof.validity_machine_state_hash),
+        "vouchersEpochRootHash":
+            hex_encode(&proof.validity_vouchers_epoch_root_hash),
+        "noticesEpochRootHash":
+            hex_encode(&proof.validity_notices_epoch_root_hash),
+        "historyIndex": history_index,
+        "lastInputIndexWithOutputs": proofs.last()?.input_index,
+        "vouchers": count(OutputEnum::Voucher),
+        "notices": count(OutputEnum::Notice),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "rollups_getReport" => {
+                let [input_index, index] =
+                    indices(params, ["inputIndex", "outputIndex"])?;
+                Ok(json!([input_index, index]))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "not found")),
+        }
+    }
+
+    fn handle_str(body: &str) -> Option<Value> {
+        handle(body.as_bytes(), echo)
+    }
+
+    #[test]
+    fn it_reads_positional_and_named_params() {
+        let response = handle_str(
+            r#"{"jsonrpc":"2.0","method":"rollups_getReport","params":[1,"0x2"],"id":7}"#,
+        );
+        assert_eq!(
+            response,
+            Some(json!({"jsonrpc": "2.0", "result": [1, 2], "id": 7}))
+        );
+        let response = handle_str(
+            r#"{"jsonrpc":"2.0","method":"rollups_getReport","params":{"inputIndex":3,"outputIndex":0},"id":"a"}"#,
+        );
+        assert_eq!(
+            response,
+            Some(json!({"jsonrpc": "2.0", "result": [3, 0], "id": "a"}))
+        );
+    }
+
+    #[test]
+    fn it_reports_errors() {
+        let error_code = |body: &str| {
+            handle_str(body).unwrap()["error"]["code"].as_i64().unwrap()
+        };
+        assert_eq!(error_code("{"), PARSE_ERROR);
+        assert_eq!(error_code("[]"), INVALID_REQUEST);
+        assert_eq!(error_code(r#"{"method":"x","id":1}"#), INVALID_REQUEST);
+        assert_eq!(
+            error_code(r#"{"jsonrpc":"1.0","method":"x","id":1}"#),
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            error_code(r#"{"jsonrpc":"2.0","method":"eth_call","id":1}"#),
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            error_code(
+                r#"{"jsonrpc":"2.0","method":"rollups_getReport","params":[1],"id":1}"#
+            ),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            error_code(
+                r#"{"jsonrpc":"2.0","method":"rollups_getReport","params":[1,-1],"id":1}"#
+            ),
+            INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn it_handles_batches_and_notifications() {
+        let response = handle_str(
+            r#"[
+                {"jsonrpc":"2.0","method":"rollups_getReport","params":[0,1],"id":1},
+                {"jsonrpc":"2.0","method":"rollups_getReport","params":[0,2]},
+                {"jsonrpc":"2.0","method":"rollups_getReport","params":[0,3],"id":3}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(response.as_array().unwrap().len(), 2);
+        assert_eq!(response[1]["result"], json!([0, 3]));
+
+        assert_eq!(
+            handle_str(
+                r#"{"jsonrpc":"2.0","method":"rollups_getReport","params":[0,2]}"#
+            ),
+            None
+        );
+    }
+
+    fn proof(input_index: i32, output_enum: OutputEnum) -> Proof {
+        Proof {
+            input_index,
+            output_index: 0,
+            output_enum,
+            validity_input_index_within_epoch: input_index - 4,
+            validity_output_index_within_input: 0,
+            validity_output_hashes_root_hash: vec![0; 32],
+            validity_vouchers_epoch_root_hash: vec![1; 32],
+            validity_notices_epoch_root_hash: vec![2; 32],
+            validity_machine_state_hash: vec![3; 32],
+            validity_output_hash_in_output_hashes_siblings: vec![],
+            validity_output_hashes_in_epoch_siblings: vec![],
+            // Index 7 in the history, big endian
+            context: [vec![0; 31], vec![7]].concat(),
+        }
+    }
+
+    #[test]
+    fn it_describes_epochs_by_their_proofs() {
+        assert_eq!(epoch_json(4, &[]), None);
+        let proofs = [
+            proof(4, OutputEnum::Voucher),
+            proof(4, OutputEnum::Notice),
+            proof(6, OutputEnum::Notice),
+        ];
+        let epoch = epoch_json(4, &proofs).unwrap();
+        let epoch_hash = keccak256([[1; 32], [2; 32], [3; 32]].concat());
+        assert_eq!(epoch["epochHash"], hex_encode(&epoch_hash));
+        assert_eq!(epoch["machineStateHash"], hex_encode(&[3; 32]));
+        assert_eq!(epoch["historyIndex"], 7);
+        assert_eq!(epoch["lastInputIndexWithOutputs"], 6);
+        assert_eq!(epoch["vouchers"], 1);
+        assert_eq!(epoch["notices"], 2);
+    }
+}
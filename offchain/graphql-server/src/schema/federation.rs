@@ -0,0 +1,237 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Support for serving the reader as a subgraph of a federated graph, so
+//! the readers of several DApps can be presented to front-ends as one.
+//!
+//! Inputs are keyed by the DApp address and their index; outputs by the
+//! DApp address, the index of their input and their own index.
+
+use juniper::parser::{ParseError, ScalarToken, Token};
+use juniper::{
+    graphql_scalar, EmptyMutation, EmptySubscription, GraphQLObject,
+    GraphQLUnion, ParseScalarResult, Value,
+};
+use rollups_data::{Input, Notice, Report, Voucher};
+use std::sync::OnceLock;
+
+use super::resolvers::{Context, Query};
+use super::scalar::RollupsGraphQLScalarValue;
+use super::Schema;
+
+/// Key of each entity type
+const ENTITY_KEYS: &[(&str, &str)] = &[
+    ("Input", "dappAddress index"),
+    ("Voucher", "dappAddress inputIndex index"),
+    ("Notice", "dappAddress inputIndex index"),
+    ("Report", "dappAddress inputIndex index"),
+];
+
+/// Reference to an entity, sent by the gateway of the federated graph to
+/// fetch it from this reader
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntityRepresentation {
+    pub typename: String,
+    pub dapp_address: Option<String>,
+    pub input_index: Option<i32>,
+    pub index: Option<i32>,
+}
+
+impl EntityRepresentation {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let int = |name| {
+            value
+                .get(name)
+                .and_then(serde_json::Value::as_i64)
+                .and_then(|value| i32::try_from(value).ok())
+        };
+        Some(Self {
+            typename: value.get("__typename")?.as_str()?.to_owned(),
+            dapp_address: value
+                .get("dappAddress")
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_owned),
+            input_index: int("inputIndex"),
+            index: int("index"),
+        })
+    }
+}
+
+#[graphql_scalar(
+    name = "_Any",
+    description = "Reference to an entity of the federated graph"
+)]
+impl GraphQLScalar for EntityRepresentation {
+    fn resolve(&self) -> Value {
+        Value::scalar(self.typename.clone())
+    }
+
+    fn from_input_value(
+        v: &juniper::InputValue,
+    ) -> Option<EntityRepresentation> {
+        // Representations given as variables come encoded as strings; see
+        // `encode_representations`
+        if let Some(json) = v.as_string_value() {
+            let value = serde_json::from_str(json).ok()?;
+            return EntityRepresentation::from_json(&value);
+        }
+        let object = v.to_object_value()?;
+        let string = |name| {
+            object
+                .get(name)
+                .and_then(|value| value.as_string_value())
+                .map(str::to_owned)
+        };
+        let int =
+            |name| object.get(name).and_then(|value| value.as_int_value());
+        Some(EntityRepresentation {
+            typename: string("__typename")?,
+            dapp_address: string("dappAddress"),
+            input_index: int("inputIndex"),
+            index: int("index"),
+        })
+    }
+
+    fn from_str<'a>(
+        value: ScalarToken<'a>,
+    ) -> ParseScalarResult<'a, RollupsGraphQLScalarValue> {
+        Err(ParseError::UnexpectedToken(Token::Scalar(value)))
+    }
+}
+
+/// Entity of the federated graph resolved by this reader
+#[derive(GraphQLUnion)]
+#[graphql(
+    name = "_Entity",
+    context = Context,
+    scalar = RollupsGraphQLScalarValue
+)]
+pub enum Entity {
+    Input(Input),
+    Voucher(Voucher),
+    Notice(Notice),
+    Report(Report),
+}
+
+#[derive(GraphQLObject)]
+#[graphql(name = "_Service", scalar = RollupsGraphQLScalarValue)]
+pub struct Service {
+    pub sdl: String,
+}
+
+/// Schema of the reader with the keys of its entities, as expected by the
+/// gateway of the federated graph. The fields and types added for the
+/// federation are left out, as the gateway adds its own.
+pub fn federated_sdl() -> &'static str {
+    static SDL: OnceLock<String> = OnceLock::new();
+    SDL.get_or_init(|| {
+        let schema = Schema::new_with_scalar_value(
+            Query,
+            EmptyMutation::new(),
+            EmptySubscription::new(),
+        );
+        add_entity_keys(&schema.as_schema_language())
+    })
+}
+
+fn add_entity_keys(sdl: &str) -> String {
+    let mut definitions = vec![];
+    for definition in sdl.split("\n\n") {
+        let is_federation_type = definition.lines().any(|line| {
+            line.starts_with("scalar _Any")
+                || line.starts_with("union _Entity")
+                || line.starts_with("type _Service")
+        });
+        if is_federation_type {
+            continue;
+        }
+        let definition: Vec<String> = definition
+            .lines()
+            .filter(|line| {
+                let line = line.trim_start();
+                !line.starts_with("_service:")
+                    && !line.starts_with("_entities(")
+            })
+            .map(|line| {
+                for (typename, fields) in ENTITY_KEYS {
+                    if line == format!("type {} {{", typename) {
+                        return format!(
+                            "type {} @key(fields: \"{}\") {{",
+                            typename, fields
+                        );
+                    }
+                }
+                line.to_owned()
+            })
+            .collect();
+        definitions.push(definition.join("\n"));
+    }
+    definitions.join("\n\n")
+}
+
+/// Encodes the entity representations in the variables of a request as JSON
+/// strings.
+///
+/// The gateway sends the representations as objects, but juniper rejects
+/// objects given as variables of scalar types. They are the only objects
+/// with a `__typename` field, since input fields can't start with `__`.
+pub fn encode_representations(variables: &mut serde_json::Value) {
+    if variables.get("__typename").is_some() {
+        *variables = serde_json::Value::String(variables.to_string());
+        return;
+    }
+    match variables {
+        serde_json::Value::Object(object) => {
+            object.values_mut().for_each(encode_representations)
+        }
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(encode_representations)
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_adds_the_keys_to_the_schema() {
+        let sdl = federated_sdl();
+        assert!(
+            sdl.contains("type Input @key(fields: \"dappAddress index\") {")
+        );
+        assert!(sdl.contains(
+            "type Voucher @key(fields: \"dappAddress inputIndex index\") {"
+        ));
+        assert!(sdl.contains("type Query {"));
+        assert!(!sdl.contains("_entities"));
+        assert!(!sdl.contains("_Service"));
+        assert!(!sdl.contains("_Any"));
+    }
+
+    #[test]
+    fn it_encodes_the_representations_in_the_variables() {
+        let mut variables = json!({
+            "representations": [
+                {"__typename": "Input", "dappAddress": "0xab", "index": 3},
+            ],
+            "filter": {"indexLowerThan": 2},
+        });
+        encode_representations(&mut variables);
+        let encoded = variables["representations"][0].as_str().unwrap();
+        assert_eq!(
+            EntityRepresentation::from_json(
+                &serde_json::from_str(encoded).unwrap()
+            ),
+            Some(EntityRepresentation {
+                typename: "Input".to_owned(),
+                dapp_address: Some("0xab".to_owned()),
+                input_index: None,
+                index: Some(3),
+            })
+        );
+        assert_eq!(variables["filter"], json!({"indexLowerThan": 2}));
+    }
+}
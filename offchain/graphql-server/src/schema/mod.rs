@@ -1,9 +1,11 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+mod federation;
 mod resolvers;
 mod scalar;
 
+pub use federation::encode_representations;
 pub use resolvers::{Context, Query};
 pub use scalar::RollupsGraphQLScalarValue;
 
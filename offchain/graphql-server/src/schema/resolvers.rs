@@ -1,30 +1,223 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use ethers::{
+    types::{H160, U256},
+    utils::keccak256,
+};
 use juniper::{
     graphql_object, DefaultScalarValue, FieldError, FieldResult, GraphQLEnum,
     GraphQLInputObject, GraphQLObject,
 };
-use std::time::UNIX_EPOCH;
+use redacted::PayloadPolicy;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use rollups_data::Repository;
 use rollups_data::{
-    CompletionStatus as DbCompletionStatus, Connection, Edge, Input,
-    InputQueryFilter, Notice, NoticeQueryFilter, OutputEnum,
-    PageInfo as DbPageInfo, Proof, Report, ReportQueryFilter, Voucher,
-    VoucherQueryFilter,
+    AsOf as DbAsOf, CompletionStatus as DbCompletionStatus, Connection,
+    Consistency as DbConsistency, Cursor, Edge, Input, InputQueryFilter, Label,
+    Notice, NoticeQueryFilter, OutputCursor, OutputEnum, OutputLineage,
+    PageInfo as DbPageInfo, Proof, Report, ReportQueryFilter,
+    SearchField as DbSearchField, SearchHit, SearchKind as DbSearchKind,
+    Voucher, VoucherEntry, VoucherQueryFilter,
 };
 
+use super::federation::{federated_sdl, Entity, EntityRepresentation, Service};
 use super::scalar::RollupsGraphQLScalarValue;
+use crate::chain::{ChainReader, ChainReaderError, OnChainValue};
+use crate::explain::{Explain, Source};
+use crate::search::SearchPeers;
 
 #[derive(Clone)]
 pub struct Context {
     repository: Repository,
+    chain_reader: Option<ChainReader>,
+    dapp_address: Option<H160>,
+    search_peers: SearchPeers,
+    payload_policy: Arc<PayloadPolicy>,
+    explain: Option<Explain>,
 }
 
 impl Context {
-    pub fn new(repository: Repository) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Repository,
+        chain_reader: Option<ChainReader>,
+    ) -> Self {
+        Self {
+            repository,
+            chain_reader,
+            dapp_address: None,
+            search_peers: SearchPeers::default(),
+            payload_policy: Arc::default(),
+            explain: None,
+        }
+    }
+
+    /// Sets the address of the DApp, which is part of the keys of the
+    /// entities of the federated graph
+    pub fn with_dapp_address(mut self, dapp_address: Option<H160>) -> Self {
+        self.dapp_address = dapp_address;
+        self
+    }
+
+    /// Sets the readers of other DApps and chains that are also searched
+    /// by the `search` query
+    pub fn with_search_peers(mut self, search_peers: SearchPeers) -> Self {
+        self.search_peers = search_peers;
+        self
+    }
+
+    /// Sets how the input payloads are redacted for the public
+    pub fn with_payload_policy(
+        mut self,
+        payload_policy: PayloadPolicy,
+    ) -> Self {
+        self.payload_policy = Arc::new(payload_policy);
+        self
+    }
+
+    /// Context of the operators, who are shown the full input payloads
+    pub fn for_operator(&self) -> Self {
+        Self {
+            payload_policy: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Context of a query in explain mode, whose reads are recorded in the
+    /// explanation
+    pub fn explaining(&self, explain: Explain) -> Self {
+        Self {
+            explain: Some(explain),
+            ..self.clone()
+        }
+    }
+
+    /// Reads from the tables of the database
+    fn read<T>(
+        &self,
+        tables: &'static [&'static str],
+        read: impl FnOnce(&Repository) -> Result<T, rollups_data::Error>,
+    ) -> Result<T, rollups_data::Error> {
+        let started_at = Instant::now();
+        let result = read(&self.repository);
+        if let Some(explain) = &self.explain {
+            explain.source(Source::Database {
+                tables,
+                elapsed: started_at.elapsed(),
+            });
+        }
+        result
+    }
+
+    /// Waits for a call to the base layer
+    fn call<T>(
+        &self,
+        call: &'static str,
+        future: impl std::future::Future<Output = T>,
+    ) -> T {
+        let started_at = Instant::now();
+        // Resolvers run in a blocking thread, so we can wait for the call
+        let output = tokio::runtime::Handle::current().block_on(future);
+        if let Some(explain) = &self.explain {
+            explain.source(Source::BaseLayer {
+                call,
+                elapsed: started_at.elapsed(),
+            });
+        }
+        output
+    }
+
+    /// Point in history of the `asOf` argument
+    fn as_of(&self, as_of: Option<AsOf>) -> FieldResult<Option<DbAsOf>> {
+        let as_of = as_of.map(DbAsOf::try_from).transpose()?;
+        if let (Some(explain), Some(as_of)) = (&self.explain, as_of) {
+            explain.as_of(as_of);
+        }
+        Ok(as_of)
+    }
+
+    /// Tier the data layer filters by, which is given the finalized block
+    /// read from the base layer for the tiers that need it
+    fn consistency(
+        &self,
+        tier: Option<Consistency>,
+    ) -> FieldResult<DbConsistency> {
+        let tier = tier.unwrap_or(Consistency::Latest);
+        if tier == Consistency::Latest {
+            return Ok(DbConsistency::Latest);
+        }
+        let chain_reader = self.chain_reader.as_ref().ok_or(
+            "the node is not configured to query the base layer for the finalized block",
+        )?;
+        let block_number = self
+            .call("finalizedBlock", chain_reader.finalized_block_number())
+            .map_err(convert_chain_error)?
+            .ok_or("the base layer has no finalized block")?
+            as i64;
+        if let Some(explain) = &self.explain {
+            explain.finalized_block(block_number);
+        }
+        Ok(match tier {
+            Consistency::Latest => DbConsistency::Latest,
+            Consistency::Finalized => DbConsistency::Finalized(block_number),
+            Consistency::MachineConfirmed => {
+                DbConsistency::MachineConfirmed(block_number)
+            }
+        })
+    }
+
+    fn dapp_address(&self) -> Option<String> {
+        self.dapp_address
+            .map(|dapp_address| hex_encode(dapp_address.as_bytes()))
+    }
+
+    /// Entity of this reader, or none if it belongs to another DApp or
+    /// doesn't exist
+    fn entity(
+        &self,
+        representation: &EntityRepresentation,
+    ) -> FieldResult<Option<Entity>> {
+        if let (Some(dapp_address), Some(own_address)) =
+            (&representation.dapp_address, self.dapp_address)
+        {
+            if dapp_address.parse::<H160>().ok() != Some(own_address) {
+                return Ok(None);
+            }
+        }
+        let (Some(index), input_index) =
+            (representation.index, representation.input_index)
+        else {
+            return Ok(None);
+        };
+        let entity = match (representation.typename.as_str(), input_index) {
+            ("Input", _) => self
+                .read(&["inputs"], |repository| repository.get_input(index))
+                .map(Entity::Input),
+            ("Voucher", Some(input_index)) => self
+                .read(&["vouchers"], |repository| {
+                    repository.get_voucher(index, input_index)
+                })
+                .map(Entity::Voucher),
+            ("Notice", Some(input_index)) => self
+                .read(&["notices"], |repository| {
+                    repository.get_notice(index, input_index)
+                })
+                .map(Entity::Notice),
+            ("Report", Some(input_index)) => self
+                .read(&["reports"], |repository| {
+                    repository.get_report(index, input_index)
+                })
+                .map(Entity::Report),
+            _ => return Ok(None),
+        };
+        match entity {
+            Ok(entity) => Ok(Some(entity)),
+            Err(rollups_data::Error::ItemNotFound { .. }) => Ok(None),
+            Err(e) => Err(convert_error(e)),
+        }
     }
 }
 
@@ -44,8 +237,7 @@ impl Query {
     ) -> FieldResult<Input> {
         executor
             .context()
-            .repository
-            .get_input(index)
+            .read(&["inputs"], |repository| repository.get_input(index))
             .map_err(convert_error)
     }
 
@@ -56,8 +248,9 @@ impl Query {
     ) -> FieldResult<Voucher> {
         executor
             .context()
-            .repository
-            .get_voucher(voucher_index, input_index)
+            .read(&["vouchers"], |repository| {
+                repository.get_voucher(voucher_index, input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -68,8 +261,9 @@ impl Query {
     ) -> FieldResult<Notice> {
         executor
             .context()
-            .repository
-            .get_notice(notice_index, input_index)
+            .read(&["notices"], |repository| {
+                repository.get_notice(notice_index, input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -80,8 +274,9 @@ impl Query {
     ) -> FieldResult<Report> {
         executor
             .context()
-            .repository
-            .get_report(report_index, input_index)
+            .read(&["reports"], |repository| {
+                repository.get_report(report_index, input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -106,12 +301,24 @@ impl Query {
         #[graphql(description = "Filter entries to retrieve")] r#where: Option<
             InputFilter,
         >,
+        #[graphql(
+            description = "Get entries as they were at a block or time of the base layer, seeing only the inputs recorded up to it and their outputs"
+        )]
+        as_of: Option<AsOf>,
+        #[graphql(
+            description = "Consistency tier of the entries, LATEST by default"
+        )]
+        consistency: Option<Consistency>,
     ) -> FieldResult<Connection<Input>> {
-        let filter = r#where.map(InputFilter::into).unwrap_or_default();
+        let mut filter: InputQueryFilter =
+            r#where.map(InputFilter::into).unwrap_or_default();
+        filter.as_of = executor.context().as_of(as_of)?;
+        filter.consistency = executor.context().consistency(consistency)?;
         executor
             .context()
-            .repository
-            .get_inputs(first, last, after, before, filter)
+            .read(&["inputs"], |repository| {
+                repository.get_inputs(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 
@@ -133,11 +340,68 @@ impl Query {
             description = "Get entries that come before the provided cursor (backward pagination)"
         )]
         before: Option<String>,
+        #[graphql(
+            description = "Get entries as they were at a block or time of the base layer, seeing only the inputs recorded up to it and their outputs"
+        )]
+        as_of: Option<AsOf>,
+        #[graphql(
+            description = "Consistency tier of the entries, LATEST by default"
+        )]
+        consistency: Option<Consistency>,
     ) -> FieldResult<Connection<Voucher>> {
+        let filter = VoucherQueryFilter {
+            as_of: executor.context().as_of(as_of)?,
+            consistency: executor.context().consistency(consistency)?,
+            ..Default::default()
+        };
+        executor
+            .context()
+            .read(&["vouchers", "inputs"], |repository| {
+                repository.get_vouchers(first, last, after, before, filter)
+            })
+            .map_err(convert_error)
+    }
+
+    #[graphql(
+        description = "Get vouchers along with their epoch and input, with support for pagination by cursors that remain valid as new vouchers are added"
+    )]
+    fn voucher_entries(
+        #[graphql(
+            description = "Get at most the first `n` entries (forward pagination)"
+        )]
+        first: Option<i32>,
+        #[graphql(
+            description = "Get at most the last `n` entries (backward pagination)"
+        )]
+        last: Option<i32>,
+        #[graphql(
+            description = "Get entries that come after the provided cursor (forward pagination)"
+        )]
+        after: Option<String>,
+        #[graphql(
+            description = "Get entries that come before the provided cursor (backward pagination)"
+        )]
+        before: Option<String>,
+        #[graphql(
+            description = "Get entries as they were at a block or time of the base layer, seeing only the inputs recorded up to it and their outputs"
+        )]
+        as_of: Option<AsOf>,
+        #[graphql(
+            description = "Consistency tier of the entries, LATEST by default"
+        )]
+        consistency: Option<Consistency>,
+    ) -> FieldResult<Connection<VoucherEntry, OutputCursor>> {
+        let filter = VoucherQueryFilter {
+            as_of: executor.context().as_of(as_of)?,
+            consistency: executor.context().consistency(consistency)?,
+            ..Default::default()
+        };
         executor
             .context()
-            .repository
-            .get_vouchers(first, last, after, before, Default::default())
+            .read(&["vouchers", "proofs", "inputs"], |repository| {
+                repository
+                    .get_voucher_entries(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 
@@ -159,11 +423,25 @@ impl Query {
             description = "Get entries that come before the provided cursor (backward pagination)"
         )]
         before: Option<String>,
+        #[graphql(
+            description = "Get entries as they were at a block or time of the base layer, seeing only the inputs recorded up to it and their outputs"
+        )]
+        as_of: Option<AsOf>,
+        #[graphql(
+            description = "Consistency tier of the entries, LATEST by default"
+        )]
+        consistency: Option<Consistency>,
     ) -> FieldResult<Connection<Notice>> {
+        let filter = NoticeQueryFilter {
+            as_of: executor.context().as_of(as_of)?,
+            consistency: executor.context().consistency(consistency)?,
+            ..Default::default()
+        };
         executor
             .context()
-            .repository
-            .get_notices(first, last, after, before, Default::default())
+            .read(&["notices", "inputs"], |repository| {
+                repository.get_notices(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 
@@ -185,13 +463,103 @@ impl Query {
             description = "Get entries that come before the provided cursor (backward pagination)"
         )]
         before: Option<String>,
+        #[graphql(
+            description = "Get entries as they were at a block or time of the base layer, seeing only the inputs recorded up to it and their outputs"
+        )]
+        as_of: Option<AsOf>,
+        #[graphql(
+            description = "Consistency tier of the entries, LATEST by default"
+        )]
+        consistency: Option<Consistency>,
     ) -> FieldResult<Connection<Report>> {
+        let filter = ReportQueryFilter {
+            as_of: executor.context().as_of(as_of)?,
+            consistency: executor.context().consistency(consistency)?,
+            ..Default::default()
+        };
         executor
             .context()
-            .repository
-            .get_reports(first, last, after, before, Default::default())
+            .read(&["reports", "inputs"], |repository| {
+                repository.get_reports(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
+
+    #[graphql(description = "Get the labels attached to the application")]
+    fn labels() -> FieldResult<Vec<Label>> {
+        executor
+            .context()
+            .read(&["labels"], |repository| repository.get_labels())
+            .map_err(convert_error)
+    }
+
+    #[graphql(
+        description = "Find the inputs and outputs that refer to a transaction hash, an address or the SHA-256 of a payload, in this node and in its search peers"
+    )]
+    fn search(
+        #[graphql(
+            description = "Transaction hash or payload SHA-256 (32 bytes), or address (20 bytes), in Ethereum hex binary format, starting with '0x'"
+        )]
+        term: String,
+        #[graphql(description = "Whether to leave out the search peers")]
+        local: Option<bool>,
+    ) -> FieldResult<Vec<SearchResult>> {
+        let context = executor.context();
+        let value = term
+            .strip_prefix("0x")
+            .and_then(|hex| hex::decode(hex).ok())
+            .filter(|value| value.len() == 20 || value.len() == 32)
+            .ok_or(
+                "search term must be a 20-byte address or a 32-byte hash, starting with '0x'",
+            )?;
+        let mut results: Vec<SearchResult> = context
+            .read(
+                &["inputs", "vouchers", "notices", "reports"],
+                |repository| repository.search(&value),
+            )
+            .map_err(convert_error)?
+            .into_iter()
+            .map(|hit| SearchResult::new(hit, context.dapp_address()))
+            .collect();
+        if !local.unwrap_or(false) && !context.search_peers.is_empty() {
+            // Resolvers run in a blocking thread, so we can wait for the peers
+            let started_at = Instant::now();
+            let found = tokio::runtime::Handle::current()
+                .block_on(context.search_peers.search::<SearchResult>(&term));
+            if let Some(explain) = &context.explain {
+                explain.source(Source::SearchPeers {
+                    peers: context.search_peers.len(),
+                    elapsed: started_at.elapsed(),
+                });
+            }
+            for (url, peer_results) in found {
+                results.extend(peer_results.into_iter().map(|result| {
+                    SearchResult {
+                        reader: Some(url.to_string()),
+                        ..result
+                    }
+                }));
+            }
+        }
+        Ok(results)
+    }
+
+    #[graphql(name = "_service")]
+    fn service() -> Service {
+        Service {
+            sdl: federated_sdl().to_owned(),
+        }
+    }
+
+    #[graphql(name = "_entities")]
+    fn entities(
+        representations: Vec<EntityRepresentation>,
+    ) -> FieldResult<Vec<Option<Entity>>> {
+        representations
+            .iter()
+            .map(|representation| executor.context().entity(representation))
+            .collect()
+    }
 }
 
 #[derive(GraphQLEnum)]
@@ -240,6 +608,13 @@ impl Input {
         self.index
     }
 
+    #[graphql(
+        description = "Address of the application, which identifies it in a federated graph; null if the node is not configured with it"
+    )]
+    fn dapp_address(&self) -> Option<String> {
+        executor.context().dapp_address()
+    }
+
     #[graphql(description = "Status of the input")]
     fn status(&self) -> CompletionStatus {
         self.status.into()
@@ -271,10 +646,27 @@ impl Input {
     }
 
     #[graphql(
-        description = "Input payload in Ethereum hex binary format, starting with '0x'"
+        description = "Input payload in Ethereum hex binary format, starting with '0x'; hashed or truncated if the node redacts the payloads of the DApp"
     )]
     fn payload(&self) -> String {
-        hex_encode(&self.payload)
+        hex_encode(&executor.context().payload_policy.payload(&self.payload))
+    }
+
+    #[graphql(
+        description = "Input payload decoded as a JSON document, if the node is configured with a codec that understands it; when the node redacts the payloads, it is only shown with its masked fields"
+    )]
+    fn decoded_payload(&self) -> Option<String> {
+        executor
+            .context()
+            .payload_policy
+            .decoded_payload(self.decoded_payload.as_deref())
+    }
+
+    #[graphql(
+        description = "Name of the codec used to decode the input payload, such as 'json', 'cbor' or 'abi'"
+    )]
+    fn payload_codec(&self) -> Option<String> {
+        self.payload_codec.clone()
     }
 
     #[graphql(
@@ -286,8 +678,9 @@ impl Input {
     ) -> FieldResult<Voucher> {
         executor
             .context()
-            .repository
-            .get_voucher(index, self.index)
+            .read(&["vouchers"], |repository| {
+                repository.get_voucher(index, self.index)
+            })
             .map_err(convert_error)
     }
 
@@ -300,8 +693,9 @@ impl Input {
     ) -> FieldResult<Notice> {
         executor
             .context()
-            .repository
-            .get_notice(index, self.index)
+            .read(&["notices"], |repository| {
+                repository.get_notice(index, self.index)
+            })
             .map_err(convert_error)
     }
 
@@ -314,8 +708,9 @@ impl Input {
     ) -> FieldResult<Report> {
         executor
             .context()
-            .repository
-            .get_report(index, self.index)
+            .read(&["reports"], |repository| {
+                repository.get_report(index, self.index)
+            })
             .map_err(convert_error)
     }
 
@@ -343,11 +738,13 @@ impl Input {
     ) -> FieldResult<Connection<Voucher>> {
         let filter = VoucherQueryFilter {
             input_index: Some(self.index),
+            ..Default::default()
         };
         executor
             .context()
-            .repository
-            .get_vouchers(first, last, after, before, filter)
+            .read(&["vouchers", "inputs"], |repository| {
+                repository.get_vouchers(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 
@@ -375,11 +772,13 @@ impl Input {
     ) -> FieldResult<Connection<Notice>> {
         let filter = NoticeQueryFilter {
             input_index: Some(self.index),
+            ..Default::default()
         };
         executor
             .context()
-            .repository
-            .get_notices(first, last, after, before, filter)
+            .read(&["notices", "inputs"], |repository| {
+                repository.get_notices(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 
@@ -407,11 +806,13 @@ impl Input {
     ) -> FieldResult<Connection<Report>> {
         let filter = ReportQueryFilter {
             input_index: Some(self.index),
+            ..Default::default()
         };
         executor
             .context()
-            .repository
-            .get_reports(first, last, after, before, filter)
+            .read(&["reports", "inputs"], |repository| {
+                repository.get_reports(first, last, after, before, filter)
+            })
             .map_err(convert_error)
     }
 }
@@ -429,12 +830,27 @@ impl Voucher {
         self.index
     }
 
+    #[graphql(
+        description = "Index of the input whose processing produced the voucher"
+    )]
+    fn input_index(&self) -> i32 {
+        self.input_index
+    }
+
+    #[graphql(
+        description = "Address of the application, which identifies it in a federated graph; null if the node is not configured with it"
+    )]
+    fn dapp_address(&self) -> Option<String> {
+        executor.context().dapp_address()
+    }
+
     #[graphql(description = "Input whose processing produced the voucher")]
     fn input(&self) -> FieldResult<Input> {
         executor
             .context()
-            .repository
-            .get_input(self.input_index)
+            .read(&["inputs"], |repository| {
+                repository.get_input(self.input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -458,10 +874,102 @@ impl Voucher {
     fn proof(&self) -> FieldResult<Option<Proof>> {
         executor
             .context()
-            .repository
-            .get_proof(self.input_index, self.index, OutputEnum::Voucher)
+            .read(&["proofs"], |repository| {
+                repository.get_proof(
+                    self.input_index,
+                    self.index,
+                    OutputEnum::Voucher,
+                )
+            })
+            .map_err(convert_error)
+    }
+
+    #[graphql(
+        description = "Input, epoch and claim of the voucher, read with a single query"
+    )]
+    fn lineage(&self) -> FieldResult<OutputLineage> {
+        executor
+            .context()
+            .read(&["inputs", "proofs"], |repository| {
+                repository.get_output_lineage(
+                    self.input_index,
+                    self.index,
+                    OutputEnum::Voucher,
+                )
+            })
             .map_err(convert_error)
     }
+
+    #[graphql(
+        description = "Whether the voucher was executed on the base layer blockchain, read directly from the application contract; null if the node is not configured to query the base layer"
+    )]
+    fn execution(&self) -> FieldResult<Option<VoucherExecution>> {
+        let context = executor.context();
+        let chain_reader = match &context.chain_reader {
+            Some(chain_reader) => chain_reader,
+            None => return Ok(None),
+        };
+        context
+            .call(
+                "wasVoucherExecuted",
+                chain_reader.was_voucher_executed(
+                    self.input_index as u64,
+                    self.index as u64,
+                ),
+            )
+            .map(|execution| Some(execution.into()))
+            .map_err(convert_chain_error)
+    }
+}
+
+#[graphql_object(
+    context = Context,
+    Scalar = RollupsGraphQLScalarValue,
+    description = "Voucher along with the epoch and input it belongs to"
+)]
+impl VoucherEntry {
+    #[graphql(
+        description = "Index of the first input of the epoch of the voucher, which identifies the epoch; null until the epoch is finished and the voucher proof is available"
+    )]
+    fn epoch_first_input_index(&self) -> Option<i32> {
+        self.epoch_first_input_index
+    }
+
+    #[graphql(description = "Index of the input that produced the voucher")]
+    fn input_index(&self) -> i32 {
+        self.voucher.input_index
+    }
+
+    #[graphql(description = "Voucher instance")]
+    fn voucher(&self) -> &Voucher {
+        &self.voucher
+    }
+}
+
+#[graphql_object(
+    context = Context,
+    Scalar = RollupsGraphQLScalarValue,
+    description = "Input, epoch and claim an output belongs to"
+)]
+impl OutputLineage {
+    #[graphql(description = "Input whose processing produced the output")]
+    fn input(&self) -> &Input {
+        &self.input
+    }
+
+    #[graphql(
+        description = "Index of the first input of the epoch of the output, which identifies the epoch; null until the epoch is finished and the output proof is available"
+    )]
+    fn epoch_first_input_index(&self) -> Option<i32> {
+        self.epoch_first_input_index()
+    }
+
+    #[graphql(
+        description = "Claim that committed the epoch of the output; null until the epoch is finished and the output proof is available"
+    )]
+    fn claim(&self) -> Option<OutputClaim> {
+        self.proof.as_ref().map(OutputClaim::from)
+    }
 }
 
 #[graphql_object(
@@ -477,12 +985,27 @@ impl Notice {
         self.index
     }
 
+    #[graphql(
+        description = "Index of the input whose processing produced the notice"
+    )]
+    fn input_index(&self) -> i32 {
+        self.input_index
+    }
+
+    #[graphql(
+        description = "Address of the application, which identifies it in a federated graph; null if the node is not configured with it"
+    )]
+    fn dapp_address(&self) -> Option<String> {
+        executor.context().dapp_address()
+    }
+
     #[graphql(description = "Input whose processing produced the notice")]
     fn input(&self) -> FieldResult<Input> {
         executor
             .context()
-            .repository
-            .get_input(self.input_index)
+            .read(&["inputs"], |repository| {
+                repository.get_input(self.input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -499,8 +1022,29 @@ impl Notice {
     fn proof(&self) -> FieldResult<Option<Proof>> {
         executor
             .context()
-            .repository
-            .get_proof(self.input_index, self.index, OutputEnum::Notice)
+            .read(&["proofs"], |repository| {
+                repository.get_proof(
+                    self.input_index,
+                    self.index,
+                    OutputEnum::Notice,
+                )
+            })
+            .map_err(convert_error)
+    }
+
+    #[graphql(
+        description = "Input, epoch and claim of the notice, read with a single query"
+    )]
+    fn lineage(&self) -> FieldResult<OutputLineage> {
+        executor
+            .context()
+            .read(&["inputs", "proofs"], |repository| {
+                repository.get_output_lineage(
+                    self.input_index,
+                    self.index,
+                    OutputEnum::Notice,
+                )
+            })
             .map_err(convert_error)
     }
 }
@@ -518,12 +1062,27 @@ impl Report {
         self.index
     }
 
+    #[graphql(
+        description = "Index of the input whose processing produced the report"
+    )]
+    fn input_index(&self) -> i32 {
+        self.input_index
+    }
+
+    #[graphql(
+        description = "Address of the application, which identifies it in a federated graph; null if the node is not configured with it"
+    )]
+    fn dapp_address(&self) -> Option<String> {
+        executor.context().dapp_address()
+    }
+
     #[graphql(description = "Input whose processing produced the report")]
     fn input(&self) -> FieldResult<Input> {
         executor
             .context()
-            .repository
-            .get_input(self.input_index)
+            .read(&["inputs"], |repository| {
+                repository.get_input(self.input_index)
+            })
             .map_err(convert_error)
     }
 
@@ -535,6 +1094,23 @@ impl Report {
     }
 }
 
+#[graphql_object(
+    context = Context,
+    Scalar = RollupsGraphQLScalarValue,
+    description = "Operator-defined annotation of the application, such as its environment or owner team"
+)]
+impl Label {
+    #[graphql(description = "Label name")]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[graphql(description = "Label value")]
+    fn value(&self) -> &str {
+        &self.value
+    }
+}
+
 #[graphql_object(
     context = Context,
     Scalar = RollupsGraphQLScalarValue,
@@ -577,6 +1153,175 @@ impl Proof {
     }
 }
 
+#[derive(GraphQLEnum, Deserialize)]
+#[graphql(description = "Kind of entity found by a search")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum SearchKind {
+    Input,
+    Voucher,
+    Notice,
+    Report,
+}
+
+impl From<DbSearchKind> for SearchKind {
+    fn from(kind: DbSearchKind) -> SearchKind {
+        match kind {
+            DbSearchKind::Input => SearchKind::Input,
+            DbSearchKind::Voucher => SearchKind::Voucher,
+            DbSearchKind::Notice => SearchKind::Notice,
+            DbSearchKind::Report => SearchKind::Report,
+        }
+    }
+}
+
+#[derive(GraphQLEnum, Deserialize)]
+#[graphql(description = "Field of an entity that matched a search")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum SearchField {
+    #[graphql(description = "Hash of the transaction that added the input")]
+    TxHash,
+    #[graphql(description = "Address that sent the input")]
+    MsgSender,
+    #[graphql(description = "Address the voucher is sent to")]
+    Destination,
+    #[graphql(description = "SHA-256 of the payload")]
+    PayloadHash,
+}
+
+impl From<DbSearchField> for SearchField {
+    fn from(field: DbSearchField) -> SearchField {
+        match field {
+            DbSearchField::TxHash => SearchField::TxHash,
+            DbSearchField::MsgSender => SearchField::MsgSender,
+            DbSearchField::Destination => SearchField::Destination,
+            DbSearchField::PayloadHash => SearchField::PayloadHash,
+        }
+    }
+}
+
+#[derive(GraphQLObject, Deserialize)]
+#[graphql(
+    description = "Entity found by a search, which can be fetched from the reader that found it"
+    scalar = RollupsGraphQLScalarValue,
+)]
+#[serde(rename_all = "camelCase")]
+struct SearchResult {
+    #[graphql(description = "Kind of the entity")]
+    kind: SearchKind,
+
+    #[graphql(description = "Field of the entity that matched the term")]
+    matched_on: SearchField,
+
+    #[graphql(
+        description = "Address of the application of the entity, if known by the reader that found it"
+    )]
+    dapp_address: Option<String>,
+
+    #[graphql(
+        description = "Index of the input, or of the input that produced the output"
+    )]
+    input_index: i32,
+
+    #[graphql(
+        description = "Index of the output within its input; the input index for inputs"
+    )]
+    index: i32,
+
+    #[graphql(
+        description = "URL of the search peer that found the entity; null if found by this node"
+    )]
+    #[serde(default)]
+    reader: Option<String>,
+}
+
+impl SearchResult {
+    fn new(hit: SearchHit, dapp_address: Option<String>) -> Self {
+        Self {
+            kind: hit.kind.into(),
+            matched_on: hit.matched_on.into(),
+            dapp_address,
+            input_index: hit.input_index,
+            index: hit.index,
+            reader: None,
+        }
+    }
+}
+
+#[derive(GraphQLEnum)]
+#[graphql(description = "Where a value returned by the API was read from")]
+enum DataSource {
+    #[graphql(
+        description = "Read from the base layer blockchain with eth_call"
+    )]
+    Chain,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(
+    description = "Execution status of a voucher"
+    scalar = RollupsGraphQLScalarValue,
+)]
+struct VoucherExecution {
+    #[graphql(description = "Whether the voucher was executed")]
+    executed: bool,
+
+    #[graphql(description = "Where the execution status was read from")]
+    source: DataSource,
+
+    #[graphql(
+        description = "Number of the base layer block at which the execution status was read"
+    )]
+    block_number: i64,
+}
+
+impl From<OnChainValue<bool>> for VoucherExecution {
+    fn from(value: OnChainValue<bool>) -> Self {
+        Self {
+            executed: value.value,
+            source: DataSource::Chain,
+            block_number: value.block_number as i64,
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(
+    description = "Claim that committed the epoch of an output"
+    scalar = RollupsGraphQLScalarValue,
+)]
+struct OutputClaim {
+    #[graphql(
+        description = "Hash of the epoch stored by the consensus, given in Ethereum hex binary format (32 bytes), starting with '0x'"
+    )]
+    epoch_hash: String,
+
+    #[graphql(
+        description = "Index of the claim in the history of the consensus, taken from the proof context; null if the context doesn't hold one"
+    )]
+    history_index: Option<i64>,
+}
+
+impl From<&Proof> for OutputClaim {
+    fn from(proof: &Proof) -> Self {
+        let epoch_hash = keccak256(
+            [
+                proof.validity_vouchers_epoch_root_hash.as_slice(),
+                proof.validity_notices_epoch_root_hash.as_slice(),
+                proof.validity_machine_state_hash.as_slice(),
+            ]
+            .concat(),
+        );
+        let history_index = (proof.context.len() == 32)
+            .then(|| U256::from_big_endian(&proof.context))
+            .filter(|index| *index <= U256::from(i64::MAX))
+            .map(|index| index.as_u64() as i64);
+        Self {
+            epoch_hash: hex_encode(&epoch_hash),
+            history_index,
+        }
+    }
+}
+
 #[derive(GraphQLObject, Debug, Clone)]
 #[graphql(
     description = "Validity proof for an output"
@@ -640,6 +1385,90 @@ impl From<InputFilter> for InputQueryFilter {
         InputQueryFilter {
             index_lower_than: filter.index_lower_than,
             index_greater_than: filter.index_greater_than,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, GraphQLInputObject)]
+#[graphql(scalar = RollupsGraphQLScalarValue)]
+/// Point in the history of the application, given by exactly one of its
+/// fields
+pub struct AsOf {
+    /// Number of the base layer block, inclusive
+    pub block_number: Option<i64>,
+
+    /// UNIX timestamp in seconds of the base layer block, inclusive
+    pub timestamp: Option<i64>,
+}
+
+impl TryFrom<AsOf> for DbAsOf {
+    type Error = FieldError<DefaultScalarValue>;
+
+    fn try_from(as_of: AsOf) -> Result<DbAsOf, Self::Error> {
+        match (as_of.block_number, as_of.timestamp) {
+            (Some(block_number), None) => Ok(DbAsOf::BlockNumber(block_number)),
+            (None, Some(timestamp)) => u64::try_from(timestamp)
+                .map(|secs| {
+                    DbAsOf::Timestamp(UNIX_EPOCH + Duration::from_secs(secs))
+                })
+                .map_err(|_| "asOf timestamp must not be negative".into()),
+            _ => Err(
+                "exactly one of blockNumber and timestamp must be set in asOf"
+                    .into(),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, GraphQLEnum)]
+#[graphql(description = "How settled the entries of a query must be")]
+enum Consistency {
+    #[graphql(
+        description = "Every input read by the node from the base layer, along with its outputs"
+    )]
+    Latest,
+
+    #[graphql(
+        description = "Only the inputs up to the finalized block of the base layer, along with their outputs"
+    )]
+    Finalized,
+
+    #[graphql(
+        description = "Only the finalized inputs that the machine has already processed, along with their outputs"
+    )]
+    MachineConfirmed,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(
+    description = "Consistency tier that served a query"
+    scalar = RollupsGraphQLScalarValue,
+)]
+struct ServedConsistency {
+    #[graphql(description = "Tier of the entries")]
+    tier: Consistency,
+
+    #[graphql(
+        description = "Finalized block of the base layer the entries were filtered by; null for the LATEST tier"
+    )]
+    finalized_block_number: Option<i64>,
+}
+
+impl From<DbConsistency> for ServedConsistency {
+    fn from(consistency: DbConsistency) -> Self {
+        let (tier, finalized_block_number) = match consistency {
+            DbConsistency::Latest => (Consistency::Latest, None),
+            DbConsistency::Finalized(block_number) => {
+                (Consistency::Finalized, Some(block_number))
+            }
+            DbConsistency::MachineConfirmed(block_number) => {
+                (Consistency::MachineConfirmed, Some(block_number))
+            }
+        };
+        Self {
+            tier,
+            finalized_block_number,
         }
     }
 }
@@ -660,8 +1489,25 @@ struct PageInfo {
     has_previous_page: bool,
 }
 
-impl From<&DbPageInfo> for PageInfo {
-    fn from(page_info: &DbPageInfo) -> PageInfo {
+/// Cursor of the data layer, encoded as given to clients
+trait EncodeCursor {
+    fn encode(&self) -> String;
+}
+
+impl EncodeCursor for Cursor {
+    fn encode(&self) -> String {
+        Cursor::encode(self)
+    }
+}
+
+impl EncodeCursor for OutputCursor {
+    fn encode(&self) -> String {
+        OutputCursor::encode(self)
+    }
+}
+
+impl<C: EncodeCursor> From<&DbPageInfo<C>> for PageInfo {
+    fn from(page_info: &DbPageInfo<C>) -> PageInfo {
         PageInfo {
             start_cursor: page_info
                 .start_cursor
@@ -680,13 +1526,16 @@ impl From<&DbPageInfo> for PageInfo {
 /// Implement the Connection and Edge objects
 macro_rules! impl_connection {
     ($connection_name: literal, $edge_name: literal, $node: ty) => {
+        impl_connection!($connection_name, $edge_name, $node, Cursor);
+    };
+    ($connection_name: literal, $edge_name: literal, $node: ty, $cursor: ty) => {
         #[graphql_object(
                                             name = $connection_name,
                                             context = Context,
                                             Scalar = RollupsGraphQLScalarValue,
                                             description = "Pagination result"
                                         )]
-        impl Connection<$node> {
+        impl Connection<$node, $cursor> {
             #[graphql(
                 description = "Total number of entries that match the query"
             )]
@@ -697,7 +1546,7 @@ macro_rules! impl_connection {
             #[graphql(
                 description = "Pagination entries returned for the current page"
             )]
-            fn edges(&self) -> &Vec<Edge<$node>> {
+            fn edges(&self) -> &Vec<Edge<$node, $cursor>> {
                 &self.edges
             }
 
@@ -705,6 +1554,13 @@ macro_rules! impl_connection {
             fn page_info(&self) -> PageInfo {
                 (&self.page_info).into()
             }
+
+            #[graphql(
+                description = "Consistency tier that served the entries"
+            )]
+            fn consistency(&self) -> ServedConsistency {
+                self.consistency.into()
+            }
         }
 
         #[graphql_object(
@@ -713,7 +1569,7 @@ macro_rules! impl_connection {
                                             Scalar = RollupsGraphQLScalarValue,
                                             description = "Pagination entry"
                                         )]
-        impl Edge<$node> {
+        impl Edge<$node, $cursor> {
             #[graphql(description = "Node instance")]
             fn node(&self) -> &$node {
                 &self.node
@@ -731,12 +1587,23 @@ impl_connection!("InputConnection", "InputEdge", Input);
 impl_connection!("VoucherConnection", "VoucherEdge", Voucher);
 impl_connection!("NoticeConnection", "NoticeEdge", Notice);
 impl_connection!("ReportConnection", "ReportEdge", Report);
+impl_connection!(
+    "VoucherEntryConnection",
+    "VoucherEntryEdge",
+    VoucherEntry,
+    OutputCursor
+);
 
 fn convert_error(e: rollups_data::Error) -> FieldError<DefaultScalarValue> {
     tracing::warn!("Got error during query: {:?}", e);
     e.into()
 }
 
+fn convert_chain_error(e: ChainReaderError) -> FieldError<DefaultScalarValue> {
+    tracing::warn!("Got error while reading from the chain: {:?}", e);
+    e.into()
+}
+
 pub fn hex_encode(data: &[u8]) -> String {
     format!("0x{}", hex::encode(data))
 }
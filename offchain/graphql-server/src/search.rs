@@ -0,0 +1,115 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use std::time::Duration;
+use url::Url;
+
+/// Search of the local database, as sent to each peer
+const PEER_QUERY: &str = "query($term: String!) { search(term: $term, local: true) { kind matchedOn dappAddress inputIndex index } }";
+
+/// How long a peer may take to answer, so a slow one doesn't hold the
+/// whole search
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Snafu)]
+pub enum SearchPeerError {
+    #[snafu(display("failed to query search peer {}", url))]
+    RequestError { url: Url, source: reqwest::Error },
+
+    #[snafu(display("search peer {} returned errors: {}", url, errors))]
+    ResponseError { url: Url, errors: serde_json::Value },
+}
+
+/// Reader nodes of other DApps and chains, whose databases are searched
+/// along with the local one
+#[derive(Clone, Debug, Default)]
+pub struct SearchPeers {
+    client: reqwest::Client,
+    urls: Vec<Url>,
+}
+
+#[derive(Deserialize)]
+struct PeerResponse<T> {
+    data: Option<PeerData<T>>,
+    errors: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct PeerData<T> {
+    search: Vec<T>,
+}
+
+impl SearchPeers {
+    /// The URLs are the GraphQL endpoints of the peers
+    pub fn new(urls: Vec<Url>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            urls,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    /// Searches each peer's own database for the term, returning what each
+    /// found along with its URL. Peers that fail are logged and left out,
+    /// so one of them being down doesn't fail the search.
+    pub async fn search<T: DeserializeOwned>(
+        &self,
+        term: &str,
+    ) -> Vec<(Url, Vec<T>)> {
+        let searches = self.urls.iter().map(|url| async move {
+            match self.search_peer(url, term).await {
+                Ok(hits) => Some((url.clone(), hits)),
+                Err(e) => {
+                    tracing::warn!("Got error while searching a peer: {:?}", e);
+                    None
+                }
+            }
+        });
+        futures::future::join_all(searches)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    async fn search_peer<T: DeserializeOwned>(
+        &self,
+        url: &Url,
+        term: &str,
+    ) -> Result<Vec<T>, SearchPeerError> {
+        let response: PeerResponse<T> = self
+            .client
+            .post(url.clone())
+            .timeout(PEER_TIMEOUT)
+            .json(&json!({
+                "query": PEER_QUERY,
+                "variables": { "term": term },
+            }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context(RequestSnafu { url: url.clone() })?
+            .json()
+            .await
+            .context(RequestSnafu { url: url.clone() })?;
+        match (response.data, response.errors) {
+            (Some(data), None) => Ok(data.search),
+            (_, errors) => ResponseSnafu {
+                url: url.clone(),
+                errors: errors.unwrap_or_default(),
+            }
+            .fail(),
+        }
+    }
+}
@@ -4,7 +4,8 @@
 use actix_web::dev::ServerHandle;
 use actix_web::rt::spawn;
 use awc::{Client, ClientRequest};
-use graphql_server::{http, schema::Context};
+use graphql_server::{http, schema::Context, QueryMetrics};
+use http_server::Registry;
 use rollups_data::{
     CompletionStatus, Input, Notice, Proof, Report, Repository, Voucher,
 };
@@ -44,6 +45,8 @@ impl TestState<'_> {
             timestamp: UNIX_EPOCH + Duration::from_secs(1676489717),
             payload: "input-0".as_bytes().to_vec(),
             status: CompletionStatus::Accepted,
+            decoded_payload: None,
+            payload_codec: None,
         };
 
         let notice = Notice {
@@ -135,6 +138,8 @@ impl TestState<'_> {
             timestamp: UNIX_EPOCH + Duration::from_secs(1676489717),
             payload: "input-0".as_bytes().to_vec(),
             status: CompletionStatus::Accepted,
+            decoded_payload: None,
+            payload_codec: None,
         };
 
         let notice0 = Notice {
@@ -196,13 +201,17 @@ pub struct GraphQLServerWrapper {
 
 impl GraphQLServerWrapper {
     async fn spawn_server(repository: Repository) -> Self {
-        let context = Context::new(repository);
+        let context = Context::new(repository, None);
+        let mut registry = Registry::default();
+        let metrics = QueryMetrics::new(&mut registry, None);
         let (tx, rx) = oneshot::channel();
 
         let join_handle = spawn(
             async {
-                let service_handler = http::start_service(HOST, PORT, context)
-                    .expect("failed to create server");
+                let service_handler = http::start_service(
+                    HOST, PORT, context, None, registry, metrics, None,
+                )
+                .expect("failed to create server");
                 tx.send(service_handler.handle())
                     .expect("failed to send server handle");
                 service_handler
@@ -335,6 +344,18 @@ async fn query_voucher_with_proof() {
     test.server.stop().await;
 }
 
+#[actix_web::test]
+#[serial_test::serial]
+async fn query_voucher_with_lineage() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker).await;
+    test.populate_database().await;
+
+    let body = post_query_request("voucher_with_lineage.json").await;
+    assert_from_body(body, "voucher_with_lineage.json");
+    test.server.stop().await;
+}
+
 #[actix_web::test]
 #[serial_test::serial]
 async fn query_proof_from_voucher() {
@@ -359,6 +380,42 @@ async fn query_vouchers() {
     test.server.stop().await;
 }
 
+#[actix_web::test]
+#[serial_test::serial]
+async fn query_vouchers_as_of() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker).await;
+    test.populate_database().await;
+
+    let body = post_query_request("vouchers_as_of.json").await;
+    assert_from_body(body, "vouchers_as_of.json");
+    test.server.stop().await;
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn query_consistency() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker).await;
+    test.populate_database().await;
+
+    let body = post_query_request("consistency.json").await;
+    assert_from_body(body, "consistency.json");
+    test.server.stop().await;
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn query_search() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker).await;
+    test.populate_database().await;
+
+    let body = post_query_request("search.json").await;
+    assert_from_body(body, "search.json");
+    test.server.stop().await;
+}
+
 #[actix_web::test]
 #[serial_test::serial]
 async fn query_report() {
@@ -551,6 +608,18 @@ async fn query_error_not_found() {
     test.server.stop().await;
 }
 
+#[actix_web::test]
+#[serial_test::serial]
+async fn query_error_consistency_without_chain() {
+    let docker = Cli::default();
+    let test = TestState::setup(&docker).await;
+    test.populate_database().await;
+
+    let body = post_query_request("error_consistency_without_chain.json").await;
+    assert_from_body(body, "error_consistency_without_chain.json");
+    test.server.stop().await;
+}
+
 #[actix_web::test]
 #[serial_test::serial]
 async fn query_error_unknown_field() {
@@ -8,11 +8,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             &[
                 "./grpc-interfaces/versioning.proto",
                 "./grpc-interfaces/server-manager.proto",
+                "./proto/epoch-hashes.proto",
+                "./proto/rollups-state.proto",
+                "./proto/state-diff.proto",
             ],
-            &["./grpc-interfaces"],
+            &["./grpc-interfaces", "./proto"],
         )?;
     println!("cargo:rerun-if-changed=./grpc-interfaces/versioning.proto");
     println!("cargo:rerun-if-changed=./grpc-interfaces/server-manager.proto");
+    println!("cargo:rerun-if-changed=./proto/epoch-hashes.proto");
+    println!("cargo:rerun-if-changed=./proto/rollups-state.proto");
+    println!("cargo:rerun-if-changed=./proto/state-diff.proto");
     println!("cargo:rerun-if-changed=build.rs");
     Ok(())
 }
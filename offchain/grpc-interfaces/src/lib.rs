@@ -12,3 +12,17 @@ pub mod cartesi_machine {
 pub mod cartesi_server_manager {
     tonic::include_proto!("cartesi_server_manager");
 }
+
+pub mod cartesi_epoch_hashes {
+    tonic::include_proto!("cartesi_epoch_hashes");
+}
+
+pub mod cartesi_rollups_state {
+    pub mod v1 {
+        tonic::include_proto!("cartesi_rollups_state.v1");
+    }
+}
+
+pub mod cartesi_state_diff {
+    tonic::include_proto!("cartesi_state_diff");
+}
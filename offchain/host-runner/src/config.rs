@@ -3,6 +3,7 @@
 
 use clap::Parser;
 use log::{LogConfig, LogEnvCliConfig};
+use std::time::Duration;
 
 const DEFAULT_ADDRESS: &str = "0.0.0.0";
 #[derive(Debug, Clone)]
@@ -14,7 +15,7 @@ pub struct Config {
     pub http_inspect_port: u16,
     pub http_rollup_server_address: String,
     pub http_rollup_server_port: u16,
-    pub finish_timeout: u64,
+    pub finish_timeout: Duration,
     pub healthcheck_port: u16,
 }
 
@@ -50,9 +51,15 @@ pub struct CLIConfig {
     #[arg(long, env, default_value = "5004")]
     pub http_rollup_server_port: u16,
 
-    /// Duration in ms for the finish request to timeout
-    #[arg(long, env, default_value = "10000")]
-    pub finish_timeout: u64,
+    /// Duration for the finish request to timeout, such as `10s` (a bare
+    /// number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "10s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    pub finish_timeout: Duration,
 
     /// Port of health check
     #[arg(long, env = "HOST_RUNNER_HEALTHCHECK_PORT", default_value_t = 8080)]
@@ -14,7 +14,6 @@ mod proofs;
 
 use futures_util::FutureExt;
 use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc};
-use std::time::Duration;
 use tokio::sync::oneshot;
 
 use clap::Parser;
@@ -37,8 +36,7 @@ async fn main() {
 
     log::log_service_start(&config, "Host Runner");
 
-    let controller =
-        Controller::new(Duration::from_millis(config.finish_timeout));
+    let controller = Controller::new(config.finish_timeout);
     let http_service_running = Arc::new(AtomicBool::new(true));
     let (grpc_shutdown_tx, grpc_shutdown_rx) = oneshot::channel::<()>();
     let grpc_service = {
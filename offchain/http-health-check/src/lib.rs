@@ -1,9 +1,16 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use axum::{routing::get, Router};
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
 use snafu::{ResultExt, Snafu};
-use std::net::SocketAddr;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 #[derive(Debug, Snafu)]
 pub enum HealthCheckError {
@@ -14,13 +21,208 @@ pub enum HealthCheckError {
     HttpServerError { source: std::io::Error },
 }
 
+/// State of a subsystem of the service
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    /// Not reported yet, such as while the initial sync runs
+    Starting,
+    Up,
+    Down,
+    /// Up, but not reported again within its maximum age
+    Stale,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SubsystemStatus {
+    pub name: &'static str,
+    pub state: SubsystemState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Status of the service, as served at `/healthz` and `/readyz`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HealthStatus {
+    /// No subsystem is down or stale
+    pub live: bool,
+    /// Every subsystem is up, so the service can take traffic
+    pub ready: bool,
+    pub subsystems: Vec<SubsystemStatus>,
+}
+
+#[derive(Clone, Debug)]
+struct Subsystem {
+    state: SubsystemState,
+    reason: Option<String>,
+    max_age: Option<Duration>,
+    reported_at: Instant,
+}
+
+/// Status of the subsystems of a service, which report themselves up or
+/// down. A service without subsystems is always live and ready.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    subsystems: Arc<Mutex<BTreeMap<&'static str, Subsystem>>>,
+}
+
+impl Health {
+    /// Adds a subsystem, which keeps the service from being ready until it
+    /// is reported up. Once up, it goes stale if not reported again within
+    /// `max_age`.
+    pub fn register(&self, name: &'static str, max_age: Option<Duration>) {
+        self.subsystems.lock().unwrap().insert(
+            name,
+            Subsystem {
+                state: SubsystemState::Starting,
+                reason: None,
+                max_age,
+                reported_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn up(&self, name: &'static str) {
+        self.report(name, SubsystemState::Up, None);
+    }
+
+    pub fn down(&self, name: &'static str, reason: impl Display) {
+        self.report(name, SubsystemState::Down, Some(reason.to_string()));
+    }
+
+    fn report(
+        &self,
+        name: &'static str,
+        state: SubsystemState,
+        reason: Option<String>,
+    ) {
+        let mut subsystems = self.subsystems.lock().unwrap();
+        let subsystem = subsystems.entry(name).or_insert(Subsystem {
+            state,
+            reason: None,
+            max_age: None,
+            reported_at: Instant::now(),
+        });
+        subsystem.state = state;
+        subsystem.reason = reason;
+        subsystem.reported_at = Instant::now();
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        self.status_at(Instant::now())
+    }
+
+    fn status_at(&self, now: Instant) -> HealthStatus {
+        let subsystems: Vec<_> = self
+            .subsystems
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&name, subsystem)| {
+                let stale = subsystem.max_age.is_some_and(|max_age| {
+                    now.saturating_duration_since(subsystem.reported_at)
+                        > max_age
+                });
+                let state = match subsystem.state {
+                    SubsystemState::Up if stale => SubsystemState::Stale,
+                    state => state,
+                };
+                SubsystemStatus {
+                    name,
+                    state,
+                    reason: subsystem.reason.clone(),
+                }
+            })
+            .collect();
+        HealthStatus {
+            live: subsystems.iter().all(|subsystem| {
+                !matches!(
+                    subsystem.state,
+                    SubsystemState::Down | SubsystemState::Stale
+                )
+            }),
+            ready: subsystems
+                .iter()
+                .all(|subsystem| subsystem.state == SubsystemState::Up),
+            subsystems,
+        }
+    }
+
+    /// Reports the subsystem with the outcome of `check` every `interval`.
+    /// The check runs in a blocking thread, so it may block, such as on a
+    /// query to a database, and the subsystem goes stale if it hangs.
+    pub async fn probe<F, E>(
+        self,
+        name: &'static str,
+        interval: Duration,
+        check: F,
+    ) where
+        F: Fn() -> Result<(), E> + Clone + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        self.register(name, Some(interval * 3));
+        loop {
+            match tokio::task::spawn_blocking(check.clone()).await {
+                Ok(Ok(())) => self.up(name),
+                Ok(Err(e)) => {
+                    tracing::warn!("{} is down: {}", name, e);
+                    self.down(name, e)
+                }
+                Err(e) => self.down(name, e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Routes that serve the status of the subsystems as JSON, with 503 when
+    /// the service isn't live (`/healthz`) or ready (`/readyz`)
+    pub fn routes(self) -> Router {
+        Router::new()
+            .route("/healthz", get(get_health))
+            .route("/readyz", get(get_readiness))
+            .with_state(self)
+    }
+}
+
+fn respond(ok: bool, status: HealthStatus) -> (StatusCode, Json<HealthStatus>) {
+    let code = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(status))
+}
+
+async fn get_health(
+    State(health): State<Health>,
+) -> (StatusCode, Json<HealthStatus>) {
+    let status = health.status();
+    respond(status.live, status)
+}
+
+async fn get_readiness(
+    State(health): State<Health>,
+) -> (StatusCode, Json<HealthStatus>) {
+    let status = health.status();
+    respond(status.ready, status)
+}
+
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn start(port: u16) -> Result<(), HealthCheckError> {
+    start_with_health(port, Health::default()).await
+}
+
+/// Same as `start`, serving the status of the subsystems of `health`
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn start_with_health(
+    port: u16,
+    health: Health,
+) -> Result<(), HealthCheckError> {
     tracing::trace!(?port, "starting health-check server on this port");
 
     let ip = "0.0.0.0".parse().context(ParseAddressSnafu)?;
     let addr = SocketAddr::new(ip, port);
-    let app = Router::new().route("/healthz", get(|| async { "" }));
+    let app = health.routes();
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .context(HttpServerSnafu)?;
@@ -29,3 +231,49 @@ pub async fn start(port: u16) -> Result<(), HealthCheckError> {
 
     axum::serve(listener, app).await.context(HttpServerSnafu)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_is_ready_once_every_subsystem_is_up() {
+        let health = Health::default();
+        assert!(health.status().ready);
+
+        health.register("fold", None);
+        health.register("broker", None);
+        let status = health.status();
+        assert!(status.live);
+        assert!(!status.ready);
+
+        health.up("fold");
+        health.up("broker");
+        assert!(health.status().ready);
+
+        health.down("broker", "connection refused");
+        let status = health.status();
+        assert!(!status.live);
+        assert!(!status.ready);
+        assert_eq!(
+            status.subsystems[0],
+            SubsystemStatus {
+                name: "broker",
+                state: SubsystemState::Down,
+                reason: Some("connection refused".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn it_goes_stale_when_not_reported_in_time() {
+        let health = Health::default();
+        health.register("chain_subscription", Some(Duration::from_secs(60)));
+        health.up("chain_subscription");
+
+        let later = Instant::now() + Duration::from_secs(61);
+        let status = health.status_at(later);
+        assert!(!status.live);
+        assert_eq!(status.subsystems[0].state, SubsystemState::Stale);
+    }
+}
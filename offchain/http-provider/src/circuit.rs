@@ -0,0 +1,369 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Circuit breaker that quarantines a provider returning bad data.
+//!
+//! Transport and JSON-RPC errors are left to the retries of the provider.
+//! A provider that answers with responses that don't deserialize, or with a
+//! chain id other than the one it first reported, is quarantined once it
+//! does so `failure_threshold` times in a row, so its data stops reaching
+//! the folds. Its requests then fail right away, except that the first one
+//! after each probe interval sends an `eth_chainId` probe first. The
+//! provider is re-admitted after `recovery_successes` good probes in a row.
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, ProviderError},
+    types::U256,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use snafu::{ResultExt, Snafu};
+use std::{fmt::Debug, sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Known-good query sent to a quarantined provider
+const PROBE_METHOD: &str = "eth_chainId";
+
+#[derive(Debug, Snafu)]
+pub enum CircuitBreakerError {
+    #[snafu(display("provider is quarantined after returning bad data"))]
+    Quarantined,
+
+    #[snafu(display(
+        "provider returned a malformed response to `{}`",
+        method
+    ))]
+    MalformedResponse {
+        method: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display(
+        "provider returned chain id {} instead of {}",
+        got,
+        expected
+    ))]
+    InconsistentChainId { expected: U256, got: U256 },
+
+    #[snafu(display("{}", source))]
+    Provider { source: ProviderError },
+}
+
+impl CircuitBreakerError {
+    fn is_bad_data(&self) -> bool {
+        matches!(
+            self,
+            CircuitBreakerError::MalformedResponse { .. }
+                | CircuitBreakerError::InconsistentChainId { .. }
+        )
+    }
+}
+
+impl From<CircuitBreakerError> for ProviderError {
+    fn from(error: CircuitBreakerError) -> Self {
+        match error {
+            // Errors of the provider itself go through as they were
+            CircuitBreakerError::Provider { source } => source,
+            error => ProviderError::JsonRpcClientError(Box::new(error)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerConfig {
+    /// Bad responses in a row that quarantine the provider, or zero to
+    /// never quarantine it
+    pub failure_threshold: u32,
+    /// Time between the probes of a quarantined provider
+    pub probe_interval: Duration,
+    /// Good probes in a row that re-admit a quarantined provider
+    pub recovery_successes: u32,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { next_probe: Instant, successes: u32 },
+}
+
+#[derive(Debug)]
+struct State {
+    circuit: Circuit,
+    chain_id: Option<U256>,
+}
+
+/// JSON-RPC client that quarantines its inner client while it returns bad
+/// data, probing it until it recovers
+#[derive(Debug)]
+pub struct CircuitBreaker<C> {
+    inner: C,
+    config: CircuitBreakerConfig,
+    state: Mutex<State>,
+}
+
+impl<C> CircuitBreaker<C> {
+    pub fn new(inner: C, config: CircuitBreakerConfig) -> Self {
+        Self {
+            inner,
+            config,
+            state: Mutex::new(State {
+                circuit: Circuit::Closed { failures: 0 },
+                chain_id: None,
+            }),
+        }
+    }
+
+    pub fn is_quarantined(&self) -> bool {
+        matches!(self.state().circuit, Circuit::Open { .. })
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("circuit breaker lock poisoned")
+    }
+
+    /// Whether a probe should go before the request, which is refused if
+    /// the provider is quarantined and the probe isn't due yet
+    fn start_probe(&self) -> Result<bool, CircuitBreakerError> {
+        let mut state = self.state();
+        match &mut state.circuit {
+            Circuit::Closed { .. } => Ok(false),
+            Circuit::Open { next_probe, .. } => {
+                let now = Instant::now();
+                if now < *next_probe {
+                    return QuarantinedSnafu.fail();
+                }
+                *next_probe = now + self.config.probe_interval;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Records the outcome of a probe, returning whether the provider is
+    /// admitted
+    fn finish_probe(&self, good: bool) -> bool {
+        let mut state = self.state();
+        let successes = match &mut state.circuit {
+            Circuit::Closed { .. } => return true,
+            Circuit::Open { successes, .. } => {
+                *successes = if good { *successes + 1 } else { 0 };
+                *successes
+            }
+        };
+        if successes < self.config.recovery_successes {
+            return false;
+        }
+        tracing::info!(successes, "provider recovered from quarantine");
+        state.circuit = Circuit::Closed { failures: 0 };
+        true
+    }
+
+    fn record<R>(&self, response: &Result<R, CircuitBreakerError>) {
+        let bad_data = match response {
+            Ok(_) => false,
+            Err(error) if error.is_bad_data() => true,
+            Err(_) => return,
+        };
+        let mut state = self.state();
+        let failures = match &mut state.circuit {
+            Circuit::Closed { failures } if bad_data => {
+                *failures += 1;
+                *failures
+            }
+            Circuit::Closed { failures } => {
+                *failures = 0;
+                return;
+            }
+            // Raced with the request that quarantined the provider
+            Circuit::Open { .. } => return,
+        };
+        if failures >= self.config.failure_threshold {
+            tracing::warn!(
+                failures,
+                "quarantining provider returning bad data"
+            );
+            state.circuit = Circuit::Open {
+                next_probe: Instant::now() + self.config.probe_interval,
+                successes: 0,
+            };
+        }
+    }
+
+    fn check_chain_id(&self, got: U256) -> Result<(), CircuitBreakerError> {
+        let mut state = self.state();
+        match state.chain_id {
+            Some(expected) if expected != got => {
+                InconsistentChainIdSnafu { expected, got }.fail()
+            }
+            Some(_) => Ok(()),
+            None => {
+                state.chain_id = Some(got);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<C: JsonRpcClient> CircuitBreaker<C> {
+    async fn send<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, CircuitBreakerError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let value: Value =
+            self.inner.request(method, params).await.map_err(|e| {
+                CircuitBreakerError::Provider { source: e.into() }
+            })?;
+        if method == PROBE_METHOD {
+            let chain_id = U256::deserialize(&value)
+                .context(MalformedResponseSnafu { method })?;
+            self.check_chain_id(chain_id)?;
+        }
+        R::deserialize(value).context(MalformedResponseSnafu { method })
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for CircuitBreaker<C> {
+    type Error = CircuitBreakerError;
+
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        if self.config.failure_threshold == 0 {
+            return self.send(method, params).await;
+        }
+        if self.start_probe()? {
+            let probe = self.send::<_, Value>(PROBE_METHOD, ()).await;
+            if !self.finish_probe(probe.is_ok()) {
+                return QuarantinedSnafu.fail();
+            }
+        }
+        let response = self.send(method, params).await;
+        self.record(&response);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{providers::MockProvider, types::U64};
+    use serde_json::json;
+
+    const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+    fn breaker(failure_threshold: u32) -> CircuitBreaker<MockProvider> {
+        CircuitBreaker::new(
+            MockProvider::new(),
+            CircuitBreakerConfig {
+                failure_threshold,
+                probe_interval: PROBE_INTERVAL,
+                recovery_successes: 2,
+            },
+        )
+    }
+
+    fn push_malformed(breaker: &CircuitBreaker<MockProvider>) {
+        breaker.inner.push(json!("not a block number")).unwrap();
+    }
+
+    async fn block_number(
+        breaker: &CircuitBreaker<MockProvider>,
+    ) -> Result<U64, CircuitBreakerError> {
+        breaker.request("eth_blockNumber", ()).await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_quarantines_a_provider_returning_malformed_responses() {
+        let breaker = breaker(2);
+        breaker.inner.push(U64::from(7)).unwrap();
+        assert_eq!(block_number(&breaker).await.unwrap(), U64::from(7));
+
+        for _ in 0..2 {
+            push_malformed(&breaker);
+            assert!(matches!(
+                block_number(&breaker).await,
+                Err(CircuitBreakerError::MalformedResponse { .. })
+            ));
+        }
+        assert!(breaker.is_quarantined());
+
+        // Refused without reaching the provider
+        breaker.inner.push(U64::from(8)).unwrap();
+        assert!(matches!(
+            block_number(&breaker).await,
+            Err(CircuitBreakerError::Quarantined)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_readmits_a_provider_after_consecutive_good_probes() {
+        let breaker = breaker(1);
+        push_malformed(&breaker);
+        assert!(block_number(&breaker).await.is_err());
+        assert!(breaker.is_quarantined());
+
+        // The mock answers the last response pushed first
+        tokio::time::advance(PROBE_INTERVAL).await;
+        breaker.inner.push(U256::one()).unwrap();
+        assert!(matches!(
+            block_number(&breaker).await,
+            Err(CircuitBreakerError::Quarantined)
+        ));
+        assert!(matches!(
+            block_number(&breaker).await,
+            Err(CircuitBreakerError::Quarantined)
+        ));
+
+        tokio::time::advance(PROBE_INTERVAL).await;
+        breaker.inner.push(U64::from(9)).unwrap();
+        breaker.inner.push(U256::one()).unwrap();
+        assert_eq!(block_number(&breaker).await.unwrap(), U64::from(9));
+        assert!(!breaker.is_quarantined());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_quarantines_a_provider_changing_chain_id() {
+        let breaker = breaker(1);
+        breaker.inner.push(U256::one()).unwrap();
+        let chain_id: U256 = breaker.request("eth_chainId", ()).await.unwrap();
+        assert_eq!(chain_id, U256::one());
+
+        breaker.inner.push(U256::from(5)).unwrap();
+        assert!(matches!(
+            breaker.request::<_, U256>("eth_chainId", ()).await,
+            Err(CircuitBreakerError::InconsistentChainId { .. })
+        ));
+        assert!(breaker.is_quarantined());
+
+        // A probe answering with the other chain id keeps it quarantined
+        tokio::time::advance(PROBE_INTERVAL).await;
+        breaker.inner.push(U256::from(5)).unwrap();
+        assert!(matches!(
+            block_number(&breaker).await,
+            Err(CircuitBreakerError::Quarantined)
+        ));
+        assert!(breaker.is_quarantined());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_never_quarantines_with_a_zero_threshold() {
+        let breaker = breaker(0);
+        for _ in 0..3 {
+            push_malformed(&breaker);
+            assert!(block_number(&breaker).await.is_err());
+        }
+        assert!(!breaker.is_quarantined());
+    }
+}
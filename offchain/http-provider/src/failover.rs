@@ -0,0 +1,212 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Failover across the JSON-RPC providers of the same chain.
+//!
+//! Each request goes to the healthiest provider first and, if it fails, to
+//! the next ones in turn, so a single flaky provider doesn't stall the folds.
+//! The health of a provider is a moving average of the outcomes of its last
+//! requests. A provider whose health drops below half is put on cooldown,
+//! during which it only gets the requests that every other provider failed,
+//! and the providers listed first are preferred while equally healthy.
+
+use async_trait::async_trait;
+use ethers::providers::JsonRpcClient;
+use redacted::RedactedUrl;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt::Debug, sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Weight of the outcome of each request on the health of the provider
+const OUTCOME_WEIGHT: f64 = 0.2;
+
+/// Health below which a provider is put on cooldown
+const COOLDOWN_HEALTH: f64 = 0.5;
+
+#[derive(Debug)]
+struct Health {
+    score: f64,
+    cooldown_until: Option<Instant>,
+}
+
+impl Health {
+    fn is_cooling_down(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| now < until)
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint<C> {
+    url: RedactedUrl,
+    client: C,
+}
+
+/// JSON-RPC client that fails over across the clients of several providers,
+/// ranked by their health
+#[derive(Debug)]
+pub struct Failover<C> {
+    endpoints: Vec<Endpoint<C>>,
+    health: Mutex<Vec<Health>>,
+    cooldown: Duration,
+}
+
+impl<C> Failover<C> {
+    /// Creates the client from the providers in order of preference
+    pub fn new(clients: Vec<(RedactedUrl, C)>, cooldown: Duration) -> Self {
+        assert!(!clients.is_empty(), "failover needs at least one provider");
+        let health = clients
+            .iter()
+            .map(|_| Health {
+                score: 1.0,
+                cooldown_until: None,
+            })
+            .collect();
+        Self {
+            endpoints: clients
+                .into_iter()
+                .map(|(url, client)| Endpoint { url, client })
+                .collect(),
+            health: Mutex::new(health),
+            cooldown,
+        }
+    }
+
+    fn health(&self) -> std::sync::MutexGuard<'_, Vec<Health>> {
+        self.health.lock().expect("failover lock poisoned")
+    }
+
+    /// Indexes of the providers in the order they are tried
+    fn ranking(&self) -> Vec<usize> {
+        let health = self.health();
+        let now = Instant::now();
+        let mut ranking: Vec<usize> = (0..health.len()).collect();
+        ranking.sort_by(|&a, &b| {
+            let (a, b) = (&health[a], &health[b]);
+            a.is_cooling_down(now)
+                .cmp(&b.is_cooling_down(now))
+                .then(b.score.total_cmp(&a.score))
+        });
+        ranking
+    }
+
+    fn record(&self, index: usize, success: bool) {
+        let mut health = self.health();
+        let health = &mut health[index];
+        let outcome = if success { 1.0 } else { 0.0 };
+        health.score =
+            health.score * (1.0 - OUTCOME_WEIGHT) + outcome * OUTCOME_WEIGHT;
+        if success {
+            health.cooldown_until = None;
+        } else if health.score < COOLDOWN_HEALTH
+            && !health.is_cooling_down(Instant::now())
+        {
+            tracing::warn!(
+                endpoint = ?self.endpoints[index].url,
+                score = health.score,
+                "provider is unhealthy; cooling it down"
+            );
+            health.cooldown_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[async_trait]
+impl<C> JsonRpcClient for Failover<C>
+where
+    C: JsonRpcClient,
+    C::Error: Send,
+{
+    type Error = C::Error;
+
+    async fn request<T, R>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        if self.endpoints.len() == 1 {
+            return self.endpoints[0].client.request(method, params).await;
+        }
+        // The params are serialized once, to be sent to every provider
+        let params = serde_json::to_value(params)
+            .expect("JSON-RPC params should serialize");
+        let mut last_error = None;
+        for index in self.ranking() {
+            let endpoint = &self.endpoints[index];
+            match endpoint.client.request(method, &params).await {
+                Ok(response) => {
+                    self.record(index, true);
+                    return Ok(response);
+                }
+                Err(e) => {
+                    tracing::debug!(
+                        endpoint = ?endpoint.url,
+                        method,
+                        "request failed; failing over: {}",
+                        e
+                    );
+                    self.record(index, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("failover should have tried a provider"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{providers::MockProvider, types::U64};
+    use redacted::Url;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    fn failover(count: usize) -> Failover<MockProvider> {
+        let clients = (0..count)
+            .map(|i| {
+                let url = Url::parse(&format!("http://node-{}", i)).unwrap();
+                (RedactedUrl::new(url), MockProvider::new())
+            })
+            .collect();
+        Failover::new(clients, COOLDOWN)
+    }
+
+    async fn block_number(failover: &Failover<MockProvider>) -> Option<U64> {
+        failover.request("eth_blockNumber", ()).await.ok()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_fails_over_to_the_next_provider() {
+        // The first provider has no responses, so its requests fail
+        let failover = failover(2);
+        failover.endpoints[1].client.push(U64::from(7)).unwrap();
+        assert_eq!(block_number(&failover).await, Some(U64::from(7)));
+
+        // Until the first one recovers, the second one is tried first
+        assert_eq!(failover.ranking(), vec![1, 0]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_ranks_the_providers_by_health() {
+        let failover = failover(2);
+        assert_eq!(failover.ranking(), vec![0, 1]);
+
+        for _ in 0..4 {
+            failover.record(0, false);
+        }
+        assert!(failover.health()[0].is_cooling_down(Instant::now()));
+        assert_eq!(failover.ranking(), vec![1, 0]);
+
+        // Back to the primary once it recovers
+        tokio::time::advance(COOLDOWN).await;
+        for _ in 0..4 {
+            failover.record(0, true);
+            failover.record(1, false);
+        }
+        assert_eq!(failover.ranking(), vec![0, 1]);
+    }
+}
@@ -0,0 +1,163 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Middleware that splits the `eth_getLogs` queries rejected for their size.
+//!
+//! Providers such as Infura reject queries over wide block ranges, or that
+//! return too many logs. A rejected query is bisected into two halves, which
+//! are queried concurrently and bisected again while rejected, down to single
+//! blocks, so the syncs of the foldables work against those providers no
+//! matter how far apart their blocks are. At most `max_parallelism` of these
+//! queries are in flight at a time.
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, ProviderError},
+    types::{BlockNumber, Filter, FilterBlockOption, Log},
+};
+use std::{future::Future, pin::Pin};
+use tokio::sync::Semaphore;
+
+/// Fragments of the errors of providers that reject a query for its size,
+/// matched case-insensitively. Timeouts aren't among them, since an
+/// unresponsive provider would have every query split down to single blocks.
+const RANGE_LIMIT_ERRORS: &[&str] = &[
+    "more than",
+    "too many",
+    "limited to",
+    "response size",
+    "block range",
+    "range is too",
+    "too large",
+];
+
+type LogsFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Log>, ProviderError>> + Send + 'a>>;
+
+#[derive(Debug)]
+pub struct LogRangeSplitter<M> {
+    inner: M,
+    permits: Semaphore,
+}
+
+impl<M> LogRangeSplitter<M>
+where
+    M: Middleware<Error = ProviderError>,
+{
+    pub fn new(inner: M, max_parallelism: usize) -> Self {
+        Self {
+            inner,
+            permits: Semaphore::new(max_parallelism.max(1)),
+        }
+    }
+
+    fn get_logs_in(
+        &self,
+        filter: Filter,
+        from: u64,
+        to: u64,
+    ) -> LogsFuture<'_> {
+        Box::pin(async move {
+            let query = filter.clone().from_block(from).to_block(to);
+            let logs = {
+                let _permit = self
+                    .permits
+                    .acquire()
+                    .await
+                    .expect("semaphore should never be closed");
+                self.inner.get_logs(&query).await
+            };
+            match logs {
+                Err(e) if is_range_limit(&e) => {
+                    let Some((lower, upper)) = bisect(from, to) else {
+                        return Err(e);
+                    };
+                    tracing::debug!(
+                        "splitting the logs query of blocks {} to {}: {}",
+                        from,
+                        to,
+                        e
+                    );
+                    let (lower, upper) = tokio::join!(
+                        self.get_logs_in(filter.clone(), lower.0, lower.1),
+                        self.get_logs_in(filter, upper.0, upper.1),
+                    );
+                    let mut logs = lower?;
+                    logs.extend(upper?);
+                    Ok(logs)
+                }
+                logs => logs,
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl<M> Middleware for LogRangeSplitter<M>
+where
+    M: Middleware<Error = ProviderError>,
+{
+    type Error = ProviderError;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        match filter.block_option {
+            FilterBlockOption::Range {
+                from_block: Some(BlockNumber::Number(from)),
+                to_block: Some(BlockNumber::Number(to)),
+            } if from <= to => {
+                self.get_logs_in(filter.clone(), from.as_u64(), to.as_u64())
+                    .await
+            }
+            // Queries by block hash or tag can't be split
+            _ => self.inner.get_logs(filter).await,
+        }
+    }
+}
+
+fn is_range_limit(error: &ProviderError) -> bool {
+    let message = error.to_string().to_lowercase();
+    RANGE_LIMIT_ERRORS
+        .iter()
+        .any(|fragment| message.contains(fragment))
+}
+
+/// Halves of the block range, or none if it is a single block
+fn bisect(from: u64, to: u64) -> Option<((u64, u64), (u64, u64))> {
+    if from >= to {
+        return None;
+    }
+    let middle = from + (to - from) / 2;
+    Some(((from, middle), (middle + 1, to)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_bisects_the_ranges_down_to_single_blocks() {
+        assert_eq!(bisect(10, 20), Some(((10, 15), (16, 20))));
+        assert_eq!(bisect(10, 11), Some(((10, 10), (11, 11))));
+        assert_eq!(bisect(10, 10), None);
+    }
+
+    #[test]
+    fn it_splits_only_on_the_errors_of_range_limits() {
+        let error = |message: &str| ProviderError::CustomError(message.into());
+        assert!(is_range_limit(&error(
+            "query returned more than 10000 results"
+        )));
+        assert!(is_range_limit(&error("Log response size exceeded.")));
+        assert!(is_range_limit(&error("block range is too wide")));
+        assert!(!is_range_limit(&error("connection refused")));
+        assert!(!is_range_limit(&error("invalid params")));
+        assert!(!is_range_limit(&error("request timed out")));
+        assert!(!is_range_limit(&error("gateway timeout")));
+    }
+}
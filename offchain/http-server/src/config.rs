@@ -8,6 +8,7 @@ use clap::{
 #[derive(Debug, Clone, Parser)]
 pub struct HttpServerConfig {
     pub(crate) port: u16,
+    pub(crate) admin_port: Option<u16>,
 }
 
 impl HttpServerConfig {
@@ -23,6 +24,7 @@ impl HttpServerConfig {
     ) -> (HttpServerConfig, C) {
         let command = <C as CommandFactory>::command();
         let command = add_port_arg(command, service);
+        let command = add_admin_port_arg(command, service);
 
         let matches = command.get_matches();
         let http_server_config: HttpServerConfig =
@@ -43,3 +45,40 @@ fn add_port_arg<S: ToString>(command: Command, service: S) -> Command {
             .default_value("8080"),
     )
 }
+
+fn add_admin_port_arg<S: ToString>(command: Command, service: S) -> Command {
+    let service = service.to_string().to_uppercase();
+    command.arg(
+        Arg::new("admin_port")
+            .long("http-server-admin-port")
+            .env(format!("{}_HTTP_SERVER_ADMIN_PORT", service))
+            .value_parser(value_parser!(u16))
+            .help(
+                "Port of the loopback interface serving /log-filter; \
+                 without it, the log filter can't be changed",
+            ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> HttpServerConfig {
+        let command = add_port_arg(Command::new("service"), "service");
+        let command = add_admin_port_arg(command, "service");
+        let matches = command
+            .try_get_matches_from(std::iter::once(&"service").chain(args))
+            .unwrap();
+        FromArgMatches::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn it_only_serves_the_admin_endpoints_when_configured() {
+        let config = parse(&[]);
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.admin_port, None);
+        let config = parse(&["--http-server-admin-port", "9090"]);
+        assert_eq!(config.admin_port, Some(9090));
+    }
+}
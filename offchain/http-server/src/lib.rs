@@ -11,42 +11,222 @@ pub use prometheus_client::registry::Registry;
 // Add any other metrics to re-export here.
 pub use prometheus_client::metrics::counter::Counter as CounterRef;
 pub use prometheus_client::metrics::family::Family as FamilyRef;
+pub use prometheus_client::metrics::gauge::Gauge as GaugeRef;
+pub use prometheus_client::metrics::histogram::{
+    exponential_buckets, Histogram as HistogramRef,
+};
 // End of metrics to re-export.
 
 // Re-exporting hyper error.
 pub use hyper::Error as HttpServerError;
 
-use axum::{routing::get, Router};
+pub use axum::Router;
+pub use http_health_check::Health;
+
+use axum::{http::StatusCode, routing::get};
 use prometheus_client::encoding::text::encode;
 use std::{
-    net::SocketAddr,
+    future::IntoFuture,
+    net::{Ipv4Addr, SocketAddr},
     sync::{Arc, Mutex},
 };
+use tokio::net::TcpListener;
 
-/// Starts a HTTP server with two endpoints: /healthz and /metrics.
+/// Starts a HTTP server with three endpoints: /healthz, /readyz and /metrics,
+/// and, when the admin port is configured, an admin server with the
+/// /log-filter endpoint listening only on the loopback interface.
 ///
 /// The `Registry` parameter is a `prometheus` type used for metric tracking.
+///
+/// A GET to /log-filter returns the current log filter, and a PUT replaces
+/// it with the directives in the body (e.g. `info,dispatcher=trace`).
 pub async fn start(
     config: HttpServerConfig,
     registry: Registry,
+) -> Result<(), std::io::Error> {
+    start_with_routes(config, registry, Router::new()).await
+}
+
+/// Same as `start`, also serving the service-specific `routes`.
+pub async fn start_with_routes(
+    config: HttpServerConfig,
+    registry: Registry,
+    routes: Router,
+) -> Result<(), std::io::Error> {
+    start_with_health(config, registry, Health::default(), routes).await
+}
+
+/// Same as `start_with_routes`, serving the status of the subsystems of
+/// `health` at /healthz and /readyz.
+pub async fn start_with_health(
+    config: HttpServerConfig,
+    registry: Registry,
+    health: Health,
+    routes: Router,
 ) -> Result<(), std::io::Error> {
     let ip = "0.0.0.0".parse().expect("could not parse host address");
-    let addr = SocketAddr::new(ip, config.port);
+    let admin_addr = config
+        .admin_port
+        .map(|port| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port));
+    serve(
+        SocketAddr::new(ip, config.port),
+        admin_addr,
+        registry,
+        health,
+        routes,
+    )
+    .await
+}
+
+/// Same as `start_with_health`, listening on `addr` instead of on every
+/// interface, and serving /log-filter at `admin_addr`, for the services whose
+/// other servers are also configured by address.
+pub async fn start_at(
+    addr: SocketAddr,
+    admin_addr: Option<SocketAddr>,
+    registry: Registry,
+    health: Health,
+) -> Result<(), std::io::Error> {
+    serve(addr, admin_addr, registry, health, Router::new()).await
+}
+
+async fn serve(
+    addr: SocketAddr,
+    admin_addr: Option<SocketAddr>,
+    registry: Registry,
+    health: Health,
+    routes: Router,
+) -> Result<(), std::io::Error> {
     tracing::info!("Starting HTTP server at {}", addr);
+    let listener = TcpListener::bind(&addr).await?;
+    let admin_listener = match admin_addr {
+        Some(admin_addr) => {
+            tracing::info!("Starting admin HTTP server at {}", admin_addr);
+            Some(TcpListener::bind(&admin_addr).await?)
+        }
+        None => None,
+    };
+    serve_on(listener, admin_listener, registry, health, routes).await
+}
 
+async fn serve_on(
+    listener: TcpListener,
+    admin_listener: Option<TcpListener>,
+    registry: Registry,
+    health: Health,
+    routes: Router,
+) -> Result<(), std::io::Error> {
     let registry = Arc::new(Mutex::new(registry));
     let router = Router::new()
-        .route("/healthz", get(|| async { "" }))
-        .route("/metrics", get(|| get_metrics(registry)));
+        .merge(health.routes())
+        .route("/metrics", get(|| get_metrics(registry)))
+        .merge(routes);
+    let admin_server = async {
+        match admin_listener {
+            Some(admin_listener) => {
+                axum::serve(admin_listener, admin_routes()).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        ret = axum::serve(listener, router).into_future() => ret,
+        ret = admin_server => ret,
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, router).await
+fn admin_routes() -> Router {
+    Router::new().route("/log-filter", get(get_log_filter).put(set_log_filter))
 }
 
 /// Returns the metrics as a specially encoded string.
 async fn get_metrics(registry: Arc<Mutex<Registry>>) -> String {
-    let registry = registry.lock().unwrap();
+    encode_metrics(&registry.lock().unwrap())
+}
+
+/// Encodes the metrics in the Prometheus text format, for the services that
+/// serve them from their own HTTP server.
+pub fn encode_metrics(registry: &Registry) -> String {
     let mut buffer = String::new();
-    encode(&mut buffer, &registry).unwrap();
+    encode(&mut buffer, registry).unwrap();
     buffer
 }
+
+async fn get_log_filter() -> Result<String, (StatusCode, String)> {
+    log::log_filter()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn set_log_filter(
+    directives: String,
+) -> Result<String, (StatusCode, String)> {
+    match log::set_log_filter(directives.trim()) {
+        Ok(()) => get_log_filter().await,
+        Err(e @ log::LogFilterError::InvalidFilter { .. }) => {
+            Err((StatusCode::BAD_REQUEST, e.to_string()))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    async fn start_servers() -> (String, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let admin_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let admin_url =
+            format!("http://{}", admin_listener.local_addr().unwrap());
+        tokio::spawn(serve_on(
+            listener,
+            Some(admin_listener),
+            Registry::default(),
+            Health::default(),
+            Router::new(),
+        ));
+        (url, admin_url)
+    }
+
+    #[tokio::test]
+    async fn it_serves_the_log_filter_only_on_the_admin_server() {
+        log::configure(&Default::default());
+        let (url, admin_url) = start_servers().await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .put(format!("{}/log-filter", url))
+            .body("trace")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client.get(format!("{}/metrics", url)).send().await;
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+
+        let response = client
+            .put(format!("{}/log-filter", admin_url))
+            .body("info,http_server=trace\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("http_server=trace"));
+
+        let response = client
+            .put(format!("{}/log-filter", admin_url))
+            .body("info,http_server=loud")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = client
+            .get(format!("{}/log-filter", admin_url))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.text().await.unwrap().contains("http_server=trace"));
+    }
+}
@@ -0,0 +1,272 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Parsers for humane config values, such as `30s`, `1h30m` or `2GiB`.
+//!
+//! The parsers are meant to be used as clap value parsers. The `*_or_*`
+//! variants also accept a bare number in the unit the field used before
+//! getting a humane format, so existing deployments keep working.
+
+use snafu::{ensure, OptionExt, Snafu};
+use std::{fmt, time::Duration};
+
+const DURATION_UNITS: &[(&str, u64)] = &[
+    ("ms", 1),
+    ("s", 1000),
+    ("m", 60 * 1000),
+    ("h", 60 * 60 * 1000),
+    ("d", 24 * 60 * 60 * 1000),
+];
+
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+];
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum HumaneError {
+    #[snafu(display("value is empty"))]
+    Empty,
+
+    #[snafu(display(
+        "`{}` is missing a unit (expected one of {})",
+        value,
+        expected
+    ))]
+    MissingUnit { value: String, expected: String },
+
+    #[snafu(display("`{}` is missing a number before `{}`", value, unit))]
+    MissingNumber { value: String, unit: String },
+
+    #[snafu(display(
+        "`{}` has unknown unit `{}` (expected one of {})",
+        value,
+        unit,
+        expected
+    ))]
+    UnknownUnit {
+        value: String,
+        unit: String,
+        expected: String,
+    },
+
+    #[snafu(display("`{}` is too large", value))]
+    Overflow { value: String },
+}
+
+/// Amount of bytes, such as a cache size or a message size limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(u64);
+
+impl ByteSize {
+    pub const fn from_bytes(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    pub fn as_usize(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0B");
+        }
+        let (unit, factor) = SIZE_UNITS
+            .iter()
+            .filter(|(_, factor)| self.0.is_multiple_of(*factor))
+            .max_by_key(|(_, factor)| *factor)
+            .expect("every size is a multiple of a byte");
+        write!(f, "{}{}", self.0 / factor, unit)
+    }
+}
+
+/// Parses a duration made of one or more `<number><unit>` parts, such as
+/// `500ms`, `30s` or `1h30m`. The units are `ms`, `s`, `m`, `h` and `d`.
+pub fn parse_duration(value: &str) -> Result<Duration, HumaneError> {
+    parse(value, DURATION_UNITS).map(Duration::from_millis)
+}
+
+/// Same as `parse_duration`, but a bare number is taken as seconds
+pub fn parse_duration_or_secs(value: &str) -> Result<Duration, HumaneError> {
+    match value.trim().parse::<u64>() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => parse_duration(value),
+    }
+}
+
+/// Same as `parse_duration`, but a bare number is taken as milliseconds
+pub fn parse_duration_or_millis(value: &str) -> Result<Duration, HumaneError> {
+    match value.trim().parse::<u64>() {
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(_) => parse_duration(value),
+    }
+}
+
+/// Parses a size such as `512KiB` or `2GB`; a bare number is taken as bytes.
+/// The units are `B`, the decimal `KB`, `MB` and `GB`, and the binary `KiB`,
+/// `MiB` and `GiB`.
+pub fn parse_byte_size(value: &str) -> Result<ByteSize, HumaneError> {
+    match value.trim().parse::<u64>() {
+        Ok(bytes) => Ok(ByteSize(bytes)),
+        Err(_) => parse(value, SIZE_UNITS).map(ByteSize),
+    }
+}
+
+/// Sums the `<number><unit>` parts of `value`, converting each one with the
+/// factor of its unit.
+fn parse(value: &str, units: &[(&str, u64)]) -> Result<u64, HumaneError> {
+    let trimmed = value.trim();
+    ensure!(!trimmed.is_empty(), EmptySnafu);
+
+    let expected = || {
+        units
+            .iter()
+            .map(|(unit, _)| *unit)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut total: u64 = 0;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_len);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let unit = unit.trim();
+
+        ensure!(
+            !unit.is_empty(),
+            MissingUnitSnafu {
+                value,
+                expected: expected(),
+            }
+        );
+        ensure!(!number.is_empty(), MissingNumberSnafu { value, unit });
+        let factor = units
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, factor)| *factor)
+            .context(UnknownUnitSnafu {
+                value,
+                unit,
+                expected: expected(),
+            })?;
+        // The number only has digits, so parsing can only fail on overflow
+        total = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(factor))
+            .and_then(|part| total.checked_add(part))
+            .context(OverflowSnafu { value })?;
+        rest = tail;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration(" 2d "), Ok(Duration::from_secs(172800)));
+    }
+
+    #[test]
+    fn parses_bare_numbers_in_the_legacy_unit() {
+        assert_eq!(
+            parse_duration_or_secs("86400"),
+            Ok(Duration::from_secs(86400))
+        );
+        assert_eq!(
+            parse_duration_or_secs("1d"),
+            Ok(Duration::from_secs(86400))
+        );
+        assert_eq!(
+            parse_duration_or_millis("120000"),
+            Ok(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_duration_or_millis("2m"),
+            Ok(Duration::from_secs(120))
+        );
+        assert_eq!(parse_byte_size("1024"), Ok(ByteSize(1024)));
+    }
+
+    #[test]
+    fn parses_byte_sizes() {
+        assert_eq!(parse_byte_size("512B"), Ok(ByteSize(512)));
+        assert_eq!(parse_byte_size("2KB"), Ok(ByteSize(2000)));
+        assert_eq!(parse_byte_size("100MiB"), Ok(ByteSize(100 << 20)));
+        assert_eq!(parse_byte_size("2GiB"), Ok(ByteSize(2 << 30)));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(parse_duration(""), Err(HumaneError::Empty));
+        assert_eq!(
+            parse_duration("30"),
+            Err(HumaneError::MissingUnit {
+                value: "30".to_owned(),
+                expected: "ms, s, m, h, d".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_duration("s"),
+            Err(HumaneError::MissingNumber {
+                value: "s".to_owned(),
+                unit: "s".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_duration("5y"),
+            Err(HumaneError::UnknownUnit {
+                value: "5y".to_owned(),
+                unit: "y".to_owned(),
+                expected: "ms, s, m, h, d".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_byte_size("2gib"),
+            Err(HumaneError::UnknownUnit {
+                value: "2gib".to_owned(),
+                unit: "gib".to_owned(),
+                expected: "B, KB, MB, GB, KiB, MiB, GiB".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_byte_size("99999999999GiB"),
+            Err(HumaneError::Overflow {
+                value: "99999999999GiB".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn formats_byte_sizes() {
+        assert_eq!(ByteSize(100 << 20).to_string(), "100MiB");
+        assert_eq!(ByteSize(2000).to_string(), "2KB");
+        assert_eq!(ByteSize(1001).to_string(), "1001B");
+        assert_eq!(ByteSize(0).to_string(), "0B");
+    }
+}
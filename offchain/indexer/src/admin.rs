@@ -0,0 +1,190 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Admin API of the indexer, which manages the labels of the DApp stored in
+//! the database and served by the reader API. It isn't authenticated, so it
+//! should only listen on a private interface.
+//!
+//! Mutations take an optional `Idempotency-Key` header; retrying one with
+//! the same key returns the result of the first request without applying
+//! it again.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use rollups_data::{Label, Repository};
+use rollups_events::Labels;
+use snafu::ResultExt;
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
+
+use crate::error::{AdminServerSnafu, IndexerError, RepositorySnafu};
+use crate::idempotency;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Time a mutation holds its idempotency key while it runs
+const IDEMPOTENCY_LEASE: Duration = Duration::from_secs(60);
+
+type Response<T> = Result<Json<T>, (StatusCode, String)>;
+
+/// Serves the admin API at `address`
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn serve(
+    address: SocketAddr,
+    repository: Repository,
+) -> Result<(), IndexerError> {
+    tracing::info!("starting admin API at {}", address);
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .context(AdminServerSnafu)?;
+    axum::serve(listener, routes(repository))
+        .await
+        .context(AdminServerSnafu)
+}
+
+/// A GET to /labels returns the labels of the DApp, and a PUT replaces them
+/// with the ones in the body, a JSON object of names to values.
+///
+/// The replaced labels are only served by the reader API. The dispatcher and
+/// the authority claimer add their own configured labels to their metrics
+/// when they start, so the metrics keep the old labels until those services
+/// are restarted with the new ones.
+pub fn routes(repository: Repository) -> Router {
+    Router::new()
+        .route("/labels", get(get_labels).put(put_labels))
+        .with_state(repository)
+}
+
+async fn get_labels(
+    State(repository): State<Repository>,
+) -> Response<BTreeMap<String, String>> {
+    let labels = tokio::task::spawn_blocking(move || repository.get_labels())
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    Ok(Json(
+        labels
+            .into_iter()
+            .map(|label| (label.name, label.value))
+            .collect(),
+    ))
+}
+
+async fn put_labels(
+    State(repository): State<Repository>,
+    headers: HeaderMap,
+    Json(labels): Json<BTreeMap<String, String>>,
+) -> Response<BTreeMap<String, String>> {
+    let labels = Labels::try_from(labels)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| key.to_str().map(str::to_owned))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    tracing::info!(?labels, ?idempotency_key, "replacing the DApp labels");
+    let rows = labels
+        .iter()
+        .map(|(name, value)| Label {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        })
+        .collect();
+    let replaced: BTreeMap<String, String> = labels
+        .iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    let request = format!(
+        "put-labels {}",
+        serde_json::to_string(&replaced).expect("labels should serialize")
+    );
+    let writer = repository.clone();
+    let replace = move || {
+        writer.replace_labels(rows).context(RepositorySnafu)?;
+        Ok(replaced)
+    };
+    let replaced = tokio::task::spawn_blocking(move || match idempotency_key {
+        Some(key) => idempotency::run_once(
+            &repository,
+            &key,
+            &request,
+            IDEMPOTENCY_LEASE,
+            replace,
+        ),
+        None => replace(),
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(mutation_error)?;
+    Ok(Json(replaced))
+}
+
+fn internal_error(error: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+}
+
+fn mutation_error(error: IndexerError) -> (StatusCode, String) {
+    let status = match error {
+        IndexerError::IdempotencyKeyReusedError { .. } => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        IndexerError::IdempotencyKeyInProgressError { .. } => {
+            StatusCode::CONFLICT
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use test_fixtures::RepositoryFixture;
+    use testcontainers::clients::Cli;
+
+    async fn put(
+        repository: &Repository,
+        idempotency_key: Option<&str>,
+        env: &str,
+    ) -> Response<BTreeMap<String, String>> {
+        let mut headers = HeaderMap::new();
+        if let Some(key) = idempotency_key {
+            headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        }
+        let labels = BTreeMap::from([("env".to_owned(), env.to_owned())]);
+        put_labels(State(repository.clone()), headers, Json(labels)).await
+    }
+
+    async fn env(repository: &Repository) -> String {
+        let Json(labels) = get_labels(State(repository.clone())).await.unwrap();
+        labels["env"].clone()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn it_replaces_the_labels_once_per_idempotency_key() {
+        let docker = Cli::default();
+        let fixture = RepositoryFixture::setup(&docker);
+        let repository = fixture.repository();
+
+        let Json(first) = put(repository, Some("key-0"), "prod").await.unwrap();
+        put(repository, None, "staging").await.unwrap();
+
+        // The retry returns the first result without replacing the labels
+        let Json(retry) = put(repository, Some("key-0"), "prod").await.unwrap();
+        assert_eq!(retry, first);
+        assert_eq!(env(repository).await, "staging");
+
+        let (status, _) =
+            put(repository, Some("key-0"), "dev").await.unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(env(repository).await, "staging");
+
+        put(repository, Some("key-1"), "dev").await.unwrap();
+        assert_eq!(env(repository).await, "dev");
+    }
+}
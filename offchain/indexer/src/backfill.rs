@@ -0,0 +1,279 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use contracts::history::{Claim, History};
+use ethers::{abi::AbiEncode, types::U256, utils::keccak256};
+use http_provider::HttpClient;
+use rollups_data::{Proof, Repository};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::sync::Arc;
+
+use crate::error::{
+    ContractSnafu, HttpClientSnafu, IndexerError, JoinSnafu, RepositorySnafu,
+};
+use crate::{idempotency, BackfillConfig};
+
+/// Outcome of re-anchoring the proofs
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillReport {
+    /// Proofs whose context now points to a claim of the new consensus
+    pub reanchored: usize,
+    /// Proofs that already pointed to the right claim
+    pub unchanged: usize,
+    /// Proofs of inputs the new consensus has not claimed yet
+    pub unclaimed: usize,
+    /// Proofs whose epoch differs from the claim of the new consensus, so they
+    /// have to be recomputed by the machine
+    pub stale: usize,
+}
+
+/// What to do with a single proof
+#[derive(Debug, PartialEq, Eq)]
+enum Reanchor {
+    Context(Vec<u8>),
+    Unclaimed,
+    Stale,
+}
+
+/// Re-anchor the proofs of the indexed outputs to the claims of the DApp's
+/// new consensus, after the DApp migrated to a new History contract.
+///
+/// The validity part of a proof only depends on the epoch, so it is kept as
+/// long as the new consensus claimed the same epoch, which is checked with
+/// the epoch hash derived from the proof. The context, which is the index of
+/// the claim in the History, is what gets updated, so the outputs stay
+/// executable against the new consensus. Proofs of epochs claimed with
+/// different boundaries are reported as stale and left untouched.
+///
+/// With an idempotency key, the report is stored in the database, and
+/// running the backfill again with the same key returns it without touching
+/// the proofs.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn backfill_proofs(
+    config: BackfillConfig,
+) -> Result<BackfillReport, IndexerError> {
+    let request = backfill_request(&config);
+    tracing::info!("reading the claims of the new consensus");
+    let http_client =
+        HttpClient::new(&config.http_client_config).context(HttpClientSnafu)?;
+    let provider =
+        Arc::new(http_client.provider(config.provider_http_endpoint));
+    let history = History::new(config.history_address, provider);
+    // The claims are indexed from the first one of the DApp in the History,
+    // so the ones made before `from_block` are counted as well
+    let first_claim_index = match config.from_block {
+        0 => 0,
+        from_block => history
+            .new_claim_to_history_filter()
+            .topic1(config.dapp_address)
+            .from_block(0)
+            .to_block(from_block - 1)
+            .query()
+            .await
+            .context(ContractSnafu)?
+            .into_iter()
+            .filter(|event| event.dapp == config.dapp_address)
+            .count(),
+    };
+    let claims: Vec<Claim> = history
+        .new_claim_to_history_filter()
+        .topic1(config.dapp_address)
+        .from_block(config.from_block)
+        .query()
+        .await
+        .context(ContractSnafu)?
+        .into_iter()
+        .filter(|event| event.dapp == config.dapp_address)
+        .map(|event| event.claim)
+        .collect();
+    tracing::info!(claims = claims.len(), first_claim_index, "read the claims");
+
+    tracing::info!("connecting to DB");
+    let repository = tokio::task::spawn_blocking(|| {
+        Repository::new(config.repository_config)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    let dry_run = config.dry_run;
+    let idempotency_key = config.idempotency_key;
+    let idempotency_lease = config.idempotency_lease;
+    tokio::task::spawn_blocking(move || {
+        let reanchor = || {
+            reanchor_proofs(&repository, &claims, first_claim_index, dry_run)
+        };
+        match idempotency_key {
+            Some(key) => idempotency::run_once(
+                &repository,
+                &key,
+                &request,
+                idempotency_lease,
+                reanchor,
+            ),
+            None => reanchor(),
+        }
+    })
+    .await
+    .context(JoinSnafu)?
+}
+
+/// Describes the backfill, so an idempotency key can't be reused with other
+/// parameters
+fn backfill_request(config: &BackfillConfig) -> String {
+    format!(
+        "proof-backfill history={:?} dapp={:?} from_block={} dry_run={}",
+        config.history_address,
+        config.dapp_address,
+        config.from_block,
+        config.dry_run
+    )
+}
+
+fn reanchor_proofs(
+    repository: &Repository,
+    claims: &[Claim],
+    first_claim_index: usize,
+    dry_run: bool,
+) -> Result<BackfillReport, IndexerError> {
+    let mut report = BackfillReport::default();
+    for proof in repository.get_proofs().context(RepositorySnafu)? {
+        match reanchor(&proof, claims, first_claim_index) {
+            Reanchor::Context(context) if context == proof.context => {
+                report.unchanged += 1;
+            }
+            Reanchor::Context(context) => {
+                if !dry_run {
+                    repository
+                        .update_proof_context(
+                            proof.input_index,
+                            proof.output_index,
+                            proof.output_enum,
+                            context,
+                        )
+                        .context(RepositorySnafu)?;
+                }
+                report.reanchored += 1;
+            }
+            Reanchor::Unclaimed => report.unclaimed += 1,
+            Reanchor::Stale => {
+                tracing::warn!(
+                    input_index = proof.input_index,
+                    output_index = proof.output_index,
+                    output_enum = ?proof.output_enum,
+                    "proof doesn't match the epoch claimed by the new consensus"
+                );
+                report.stale += 1;
+            }
+        }
+    }
+    tracing::info!(?report, dry_run, "finished re-anchoring the proofs");
+    Ok(report)
+}
+
+/// Finds the claim of the proof among `claims`, the first of which is the
+/// claim at `first_claim_index` in the History
+fn reanchor(
+    proof: &Proof,
+    claims: &[Claim],
+    first_claim_index: usize,
+) -> Reanchor {
+    let input_index = proof.input_index as u128;
+    let Some((claim_index, claim)) =
+        claims.iter().enumerate().find(|(_, claim)| {
+            claim.first_index <= input_index && input_index <= claim.last_index
+        })
+    else {
+        return Reanchor::Unclaimed;
+    };
+
+    let same_boundaries = claim.first_index
+        + proof.validity_input_index_within_epoch as u128
+        == input_index;
+    if !same_boundaries || claim.epoch_hash != epoch_hash(proof) {
+        return Reanchor::Stale;
+    }
+    Reanchor::Context(U256::from(first_claim_index + claim_index).encode())
+}
+
+/// Hash the consensus stores for the epoch of the proof
+fn epoch_hash(proof: &Proof) -> [u8; 32] {
+    keccak256(
+        [
+            proof.validity_vouchers_epoch_root_hash.as_slice(),
+            proof.validity_notices_epoch_root_hash.as_slice(),
+            proof.validity_machine_state_hash.as_slice(),
+        ]
+        .concat(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rollups_data::OutputEnum;
+
+    fn proof(input_index: i32, input_index_within_epoch: i32) -> Proof {
+        Proof {
+            input_index,
+            output_index: 0,
+            output_enum: OutputEnum::Voucher,
+            validity_input_index_within_epoch: input_index_within_epoch,
+            validity_output_index_within_input: 0,
+            validity_output_hashes_root_hash: vec![0; 32],
+            validity_vouchers_epoch_root_hash: vec![1; 32],
+            validity_notices_epoch_root_hash: vec![2; 32],
+            validity_machine_state_hash: vec![3; 32],
+            validity_output_hash_in_output_hashes_siblings: vec![],
+            validity_output_hashes_in_epoch_siblings: vec![],
+            context: U256::from(7).encode(),
+        }
+    }
+
+    fn claim(first_index: u128, last_index: u128) -> Claim {
+        Claim {
+            epoch_hash: epoch_hash(&proof(0, 0)),
+            first_index,
+            last_index,
+        }
+    }
+
+    #[test]
+    fn it_points_the_context_to_the_new_claim() {
+        let claims = [claim(0, 4), claim(5, 9)];
+        assert_eq!(
+            reanchor(&proof(6, 1), &claims, 0),
+            Reanchor::Context(U256::from(1).encode())
+        );
+    }
+
+    #[test]
+    fn it_reports_unclaimed_inputs() {
+        let claims = [claim(0, 4)];
+        assert_eq!(reanchor(&proof(6, 1), &claims, 0), Reanchor::Unclaimed);
+    }
+
+    #[test]
+    fn it_reports_stale_proofs() {
+        // The new consensus claimed inputs 5 and 6 in another epoch
+        let claims = [claim(0, 9)];
+        assert_eq!(reanchor(&proof(6, 1), &claims, 0), Reanchor::Stale);
+
+        let claims = [Claim {
+            epoch_hash: [0xff; 32],
+            ..claim(5, 9)
+        }];
+        assert_eq!(reanchor(&proof(6, 1), &claims, 0), Reanchor::Stale);
+    }
+
+    #[test]
+    fn it_counts_the_claims_before_the_first_block() {
+        // Three claims of the DApp were made before the queried blocks
+        let claims = [claim(15, 19), claim(20, 24)];
+        assert_eq!(
+            reanchor(&proof(21, 1), &claims, 3),
+            Reanchor::Context(U256::from(4).encode())
+        );
+    }
+}
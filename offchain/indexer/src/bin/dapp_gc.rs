@@ -0,0 +1,18 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+
+use indexer::{GcCLIConfig, GcConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    secrets::resolve_env().await?;
+    let config: GcConfig = GcCLIConfig::parse().into();
+
+    log::configure(&config.log_config);
+
+    log::log_service_start(&config, "DApp GC");
+
+    indexer::collect_garbage(config).await.map_err(|e| e.into())
+}
@@ -0,0 +1,21 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+
+use indexer::{DowngradeCLIConfig, DowngradeConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    secrets::resolve_env().await?;
+    let config: DowngradeConfig = DowngradeCLIConfig::parse().into();
+
+    log::configure(&config.log_config);
+
+    log::log_service_start(&config, "DB Downgrade");
+
+    indexer::downgrade_database(config)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,21 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+
+use indexer::{RebuildCLIConfig, RebuildConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    secrets::resolve_env().await?;
+    let config: RebuildConfig = RebuildCLIConfig::parse().into();
+
+    log::configure(&config.log_config);
+
+    log::log_service_start(&config, "DB Rebuild");
+
+    indexer::rebuild_database(config)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
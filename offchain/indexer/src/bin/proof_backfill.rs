@@ -0,0 +1,21 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+
+use indexer::{BackfillCLIConfig, BackfillConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    secrets::resolve_env().await?;
+    let config: BackfillConfig = BackfillCLIConfig::parse().into();
+
+    log::configure(&config.log_config);
+
+    log::log_service_start(&config, "Proof Backfill");
+
+    indexer::backfill_proofs(config)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,386 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Decoders for input payloads.
+//! The decoded form is stored as JSON next to the raw payload, so readers
+//! don't need to know the DApp's encoding to display its inputs.
+
+use ethabi::{param_type::Reader, ParamType, Token};
+use serde_json::{Map, Number, Value};
+use snafu::{ResultExt, Snafu};
+
+/// Decodes a payload into JSON
+pub trait PayloadCodec: Send + Sync {
+    /// Name stored along with the decoded payload
+    fn name(&self) -> &str;
+
+    /// Return None if the payload is not in the codec's format
+    fn decode(&self, payload: &[u8]) -> Option<Value>;
+}
+
+#[derive(Debug, Snafu)]
+pub enum CodecError {
+    #[snafu(display("unknown payload codec `{}`", name))]
+    UnknownCodec { name: String },
+
+    #[snafu(display("invalid ABI types `{}`", types))]
+    InvalidAbiTypes {
+        types: String,
+        source: ethabi::Error,
+    },
+}
+
+/// Which codec is used to decode the input payloads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecSelection {
+    /// Payloads are only stored raw
+    None,
+    /// Try the auto-detectable codecs in order, keeping the first match
+    Auto,
+    /// Use the codec with the given name
+    Named(String),
+}
+
+impl From<String> for CodecSelection {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "none" => Self::None,
+            "auto" => Self::Auto,
+            _ => Self::Named(name),
+        }
+    }
+}
+
+struct RegisteredCodec {
+    codec: Box<dyn PayloadCodec>,
+    auto_detect: bool,
+}
+
+/// Set of codecs available to the indexer
+pub struct CodecRegistry {
+    codecs: Vec<RegisteredCodec>,
+}
+
+impl Default for CodecRegistry {
+    /// Registry with the JSON and CBOR codecs, both auto-detectable
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(JsonCodec), true);
+        registry.register(Box::new(CborCodec), true);
+        registry
+    }
+}
+
+impl CodecRegistry {
+    pub fn empty() -> Self {
+        Self { codecs: vec![] }
+    }
+
+    /// Register a codec, replacing any codec with the same name.
+    /// Auto-detectable codecs are tried in registration order, so codecs
+    /// that accept almost any payload should not be auto-detectable.
+    pub fn register(
+        &mut self,
+        codec: Box<dyn PayloadCodec>,
+        auto_detect: bool,
+    ) {
+        self.codecs.retain(|c| c.codec.name() != codec.name());
+        self.codecs.push(RegisteredCodec { codec, auto_detect });
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn PayloadCodec> {
+        self.codecs
+            .iter()
+            .find(|c| c.codec.name() == name)
+            .map(|c| c.codec.as_ref())
+    }
+}
+
+/// Decodes payloads with the selected codec of a registry
+pub struct PayloadDecoder {
+    registry: CodecRegistry,
+    selection: CodecSelection,
+}
+
+impl PayloadDecoder {
+    /// Fail if the selected codec is not in the registry
+    pub fn new(
+        registry: CodecRegistry,
+        selection: CodecSelection,
+    ) -> Result<Self, CodecError> {
+        if let CodecSelection::Named(name) = &selection {
+            snafu::ensure!(
+                registry.get(name).is_some(),
+                UnknownCodecSnafu { name }
+            );
+        }
+        Ok(Self {
+            registry,
+            selection,
+        })
+    }
+
+    /// Return the codec name and the decoded payload as a JSON string
+    pub fn decode(&self, payload: &[u8]) -> Option<(String, String)> {
+        let decode = |codec: &dyn PayloadCodec| {
+            codec
+                .decode(payload)
+                .map(|value| (codec.name().to_owned(), value.to_string()))
+        };
+        match &self.selection {
+            CodecSelection::None => None,
+            CodecSelection::Auto => self
+                .registry
+                .codecs
+                .iter()
+                .filter(|c| c.auto_detect)
+                .find_map(|c| decode(c.codec.as_ref())),
+            CodecSelection::Named(name) => {
+                self.registry.get(name).and_then(decode)
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// JsonCodec
+// ------------------------------------------------------------------------------------------------
+
+/// Payloads that are UTF-8 encoded JSON documents
+pub struct JsonCodec;
+
+impl PayloadCodec for JsonCodec {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn decode(&self, payload: &[u8]) -> Option<Value> {
+        serde_json::from_slice(payload).ok()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// CborCodec
+// ------------------------------------------------------------------------------------------------
+
+/// Payloads that are a single CBOR (RFC 8949) data item.
+/// Byte strings become '0x' hex strings, tags are dropped, and map keys
+/// that are not text are converted to their JSON representation.
+pub struct CborCodec;
+
+const CBOR_MAX_DEPTH: usize = 64;
+
+impl PayloadCodec for CborCodec {
+    fn name(&self) -> &str {
+        "cbor"
+    }
+
+    fn decode(&self, payload: &[u8]) -> Option<Value> {
+        let mut data = payload;
+        let value: ciborium::value::Value =
+            ciborium::de::from_reader_with_recursion_limit(
+                &mut data,
+                CBOR_MAX_DEPTH,
+            )
+            .ok()?;
+        if !data.is_empty() {
+            return None;
+        }
+        cbor_value(value)
+    }
+}
+
+fn cbor_value(value: ciborium::value::Value) -> Option<Value> {
+    use ciborium::value::Value as Cbor;
+    Some(match value {
+        Cbor::Integer(integer) => {
+            let integer = i128::from(integer);
+            if let Ok(integer) = i64::try_from(integer) {
+                Value::from(integer)
+            } else if let Ok(integer) = u64::try_from(integer) {
+                Value::from(integer)
+            } else {
+                Value::String(integer.to_string())
+            }
+        }
+        Cbor::Bytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        // NaN and infinities have no JSON representation
+        Cbor::Float(float) => {
+            Number::from_f64(float).map_or(Value::Null, Value::Number)
+        }
+        Cbor::Text(text) => Value::String(text),
+        Cbor::Bool(bool) => Value::Bool(bool),
+        Cbor::Null => Value::Null,
+        Cbor::Tag(_, value) => cbor_value(*value)?,
+        Cbor::Array(items) => Value::Array(
+            items.into_iter().map(cbor_value).collect::<Option<_>>()?,
+        ),
+        Cbor::Map(entries) => {
+            let mut map = Map::new();
+            for (key, value) in entries {
+                let key = match cbor_value(key)? {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                map.insert(key, cbor_value(value)?);
+            }
+            Value::Object(map)
+        }
+        _ => return None,
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// AbiCodec
+// ------------------------------------------------------------------------------------------------
+
+/// Payloads that are the ABI encoding of a fixed list of types.
+/// Addresses, bytes and hashes become '0x' hex strings, and integers become
+/// decimal strings, since they may not fit a JSON number.
+pub struct AbiCodec {
+    types: Vec<ParamType>,
+}
+
+impl AbiCodec {
+    /// Create the codec from a comma-separated list of Solidity types,
+    /// such as `address,uint256,bytes`
+    pub fn new(types: &str) -> Result<Self, CodecError> {
+        let tuple = Reader::read(&format!("({})", types))
+            .context(InvalidAbiTypesSnafu { types })?;
+        match tuple {
+            ParamType::Tuple(types) => Ok(Self { types }),
+            _ => unreachable!("parenthesized types should be a tuple"),
+        }
+    }
+}
+
+impl PayloadCodec for AbiCodec {
+    fn name(&self) -> &str {
+        "abi"
+    }
+
+    fn decode(&self, payload: &[u8]) -> Option<Value> {
+        let tokens = ethabi::decode(&self.types, payload).ok()?;
+        Some(Value::Array(tokens.into_iter().map(abi_token).collect()))
+    }
+}
+
+fn abi_token(token: Token) -> Value {
+    match token {
+        Token::Address(address) => {
+            Value::String(format!("0x{}", hex::encode(address)))
+        }
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Int(int) => {
+            // Signed integers are encoded in two's complement
+            if int.bit(255) {
+                Value::String(format!(
+                    "-{}",
+                    (!int).overflowing_add(1.into()).0
+                ))
+            } else {
+                Value::String(int.to_string())
+            }
+        }
+        Token::Uint(uint) => Value::String(uint.to_string()),
+        Token::Bool(value) => Value::Bool(value),
+        Token::String(value) => Value::String(value),
+        Token::FixedArray(tokens)
+        | Token::Array(tokens)
+        | Token::Tuple(tokens) => {
+            Value::Array(tokens.into_iter().map(abi_token).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decoder(selection: &str) -> PayloadDecoder {
+        let mut registry = CodecRegistry::default();
+        registry
+            .register(Box::new(AbiCodec::new("uint256,bool").unwrap()), false);
+        PayloadDecoder::new(registry, selection.to_owned().into()).unwrap()
+    }
+
+    #[test]
+    fn test_it_decodes_json() {
+        let decoded = decoder("json").decode(br#"{"a": [1, "b"]}"#);
+        assert_eq!(
+            decoded,
+            Some(("json".to_owned(), r#"{"a":[1,"b"]}"#.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_it_decodes_cbor() {
+        // {"a": [1, -2, h'ff'], "b": true, "c": 1.5}
+        let payload = hex::decode("a3616183012141ff6162f56163f93e00");
+        let payload = payload.unwrap();
+        let decoded = CborCodec.decode(&payload);
+        assert_eq!(
+            decoded,
+            Some(json!({"a": [1, -2, "0xff"], "b": true, "c": 1.5}))
+        );
+    }
+
+    #[test]
+    fn test_it_decodes_indefinite_cbor() {
+        // [_ "ab", (_ "c", "d")]
+        let payload = hex::decode("9f626162 7f61636164ff ff".replace(' ', ""));
+        let decoded = CborCodec.decode(&payload.unwrap());
+        assert_eq!(decoded, Some(json!(["ab", "cd"])));
+    }
+
+    #[test]
+    fn test_it_rejects_trailing_cbor_bytes() {
+        assert_eq!(CborCodec.decode(&[0x01, 0x02]), None);
+    }
+
+    #[test]
+    fn test_it_decodes_abi() {
+        let payload =
+            ethabi::encode(&[Token::Uint(42.into()), Token::Bool(true)]);
+        let decoded = decoder("abi").decode(&payload);
+        assert_eq!(
+            decoded,
+            Some(("abi".to_owned(), r#"["42",true]"#.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_it_decodes_negative_abi_int() {
+        let codec = AbiCodec::new("int8").unwrap();
+        let payload = ethabi::encode(&[Token::Int(ethabi::Uint::MAX)]);
+        assert_eq!(codec.decode(&payload), Some(json!(["-1"])));
+    }
+
+    #[test]
+    fn test_it_auto_detects_codec() {
+        let decoder = decoder("auto");
+        assert_eq!(
+            decoder.decode(b"[1]"),
+            Some(("json".to_owned(), "[1]".to_owned()))
+        );
+        assert_eq!(
+            decoder.decode(&[0x81, 0x01]),
+            Some(("cbor".to_owned(), "[1]".to_owned()))
+        );
+        assert_eq!(decoder.decode(&[0xff]), None);
+    }
+
+    #[test]
+    fn test_it_rejects_unknown_codec() {
+        let result = PayloadDecoder::new(
+            CodecRegistry::default(),
+            CodecSelection::Named("xml".to_owned()),
+        );
+        assert!(matches!(result, Err(CodecError::UnknownCodec { .. })));
+    }
+}
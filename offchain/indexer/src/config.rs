@@ -2,12 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use clap::Parser;
+use redacted::{PayloadPolicy, PayloadPolicyCLIConfig, Redacted, RedactedUrl};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
+use crate::codecs::CodecSelection;
+use crate::reconcile::ReconcileConfig;
+use crate::snapshot::{SnapshotConfig, ValidationConfig};
+use ethers::types::H160;
+use http_provider::{HttpClientCLIConfig, HttpClientConfig};
 use log::{LogConfig, LogEnvCliConfig};
-pub use rollups_data::{RepositoryCLIConfig, RepositoryConfig};
+pub use rollups_data::{RepositoryCLIConfig, RepositoryConfig, Url};
 pub use rollups_events::{
-    BrokerCLIConfig, BrokerConfig, DAppMetadata, DAppMetadataCLIConfig,
+    BrokerCLIConfig, BrokerConfig, DAppMetadata, DAppMetadataCLIConfig, Labels,
 };
+use scheduler::{SchedulerCLIConfig, SchedulerConfig};
 
 #[derive(Debug)]
 pub struct IndexerConfig {
@@ -16,6 +24,15 @@ pub struct IndexerConfig {
     pub broker_config: BrokerConfig,
     pub log_config: LogConfig,
     pub healthcheck_port: u16,
+    pub payload_codec: CodecSelection,
+    pub payload_abi_types: Option<String>,
+    pub dapp_labels: Option<Labels>,
+    pub admin_address: Option<SocketAddr>,
+    pub skip_migrations: bool,
+    pub reconcile_config: Option<ReconcileConfig>,
+    pub snapshot_config: Option<SnapshotConfig>,
+    pub scheduler_config: SchedulerConfig,
+    pub payload_policy: PayloadPolicy,
 }
 
 #[derive(Parser)]
@@ -41,16 +58,494 @@ pub struct CLIConfig {
         default_value_t = 8080
     )]
     pub healthcheck_port: u16,
+
+    /// Codec used to decode the input payloads: `none`, `auto`, `json`, `cbor` or `abi`
+    #[arg(long, env, default_value = "none")]
+    pub input_payload_codec: String,
+
+    /// Comma-separated Solidity types of the payloads decoded with the `abi` codec,
+    /// such as `address,uint256,bytes`
+    #[arg(long, env)]
+    pub input_payload_abi_types: Option<String>,
+
+    #[command(flatten)]
+    payload_policy_config: PayloadPolicyCLIConfig,
+
+    #[command(flatten)]
+    scheduler_config: SchedulerCLIConfig,
+
+    /// Comma-separated `key=value` labels stored in the database and served by the API
+    /// (e.g. `environment=production,owner_team=infra`). If set, they replace the
+    /// stored labels on startup, including the ones set through the admin API
+    #[arg(long, env)]
+    pub dapp_labels: Option<Labels>,
+
+    /// Address of the admin API, which manages the DApp labels, such as
+    /// `127.0.0.1:8081`. It isn't authenticated, so it should only listen on a
+    /// private interface. If not set, it isn't served
+    #[arg(long, env)]
+    pub indexer_admin_address: Option<SocketAddr>,
+
+    /// Don't migrate the database on startup, only check that its schema is
+    /// up to date, for when the operator applies the migrations
+    #[arg(long, env, default_value_t = false)]
+    pub postgres_skip_migrations: bool,
+
+    #[command(flatten)]
+    http_client_config: HttpClientCLIConfig,
+
+    /// Interval between the checks of the indexed outputs against the epochs
+    /// claimed on chain, such as `10m`. If not set, they aren't checked
+    #[arg(
+        long,
+        env,
+        value_parser = humane::parse_duration,
+        requires_all = [
+            "reconcile_provider_http_endpoint",
+            "reconcile_history_address",
+        ]
+    )]
+    pub reconcile_interval: Option<Duration>,
+
+    /// HTTP endpoint of the base layer node the claims are read from
+    #[arg(long, env)]
+    pub reconcile_provider_http_endpoint: Option<Url>,
+
+    /// Address of the History contract the claims are read from
+    #[arg(long, env)]
+    pub reconcile_history_address: Option<H160>,
+
+    /// Block from which the claims are read, such as the block where the
+    /// History was deployed
+    #[arg(long, env, default_value_t = 0)]
+    pub reconcile_from_block: u64,
+
+    /// Read the events of a divergent epoch from the broker again and store
+    /// the ones missing from the database
+    #[arg(long, env, default_value_t = false)]
+    pub reconcile_reindex: bool,
+
+    /// Interval between the snapshots of the indexed data published for
+    /// mirror reader nodes, such as `6h`. If not set, none is published
+    #[arg(
+        long,
+        env,
+        value_parser = humane::parse_duration,
+        requires_all = ["snapshot_dir", "snapshot_signer_private_key"]
+    )]
+    pub snapshot_interval: Option<Duration>,
+
+    /// Directory where the snapshots are written
+    #[arg(long, env)]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Where the snapshots are uploaded to: an `s3://<bucket>/<prefix>` URL
+    /// or an HTTP URL that accepts `PUT` requests. If not set, they are only
+    /// written to the directory, which may be served as is
+    #[arg(long, env)]
+    pub snapshot_upload_url: Option<Url>,
+
+    /// Private key that signs the manifests of the snapshots; mirrors check
+    /// them against its address
+    #[arg(long, env, hide_env_values = true)]
+    pub snapshot_signer_private_key: Option<String>,
+
+    /// Snapshots kept in the directory
+    #[arg(long, env, default_value_t = 3)]
+    pub snapshot_keep: usize,
 }
 
 impl From<CLIConfig> for IndexerConfig {
     fn from(cli_config: CLIConfig) -> Self {
+        let http_client_config = cli_config.http_client_config.into();
+        let reconcile_config =
+            cli_config
+                .reconcile_interval
+                .map(|interval| ReconcileConfig {
+                    interval,
+                    http_client_config,
+                    provider_http_endpoint: cli_config
+                        .reconcile_provider_http_endpoint
+                        .expect("required by the reconcile interval"),
+                    history_address: cli_config
+                        .reconcile_history_address
+                        .expect("required by the reconcile interval"),
+                    from_block: cli_config.reconcile_from_block,
+                    reindex: cli_config.reconcile_reindex,
+                });
+        let snapshot_config =
+            cli_config.snapshot_interval.map(|interval| SnapshotConfig {
+                interval,
+                dir: cli_config
+                    .snapshot_dir
+                    .expect("required by the snapshot interval"),
+                upload_url: cli_config
+                    .snapshot_upload_url
+                    .map(RedactedUrl::new),
+                signer_private_key: Redacted::new(
+                    cli_config
+                        .snapshot_signer_private_key
+                        .expect("required by the snapshot interval"),
+                ),
+                keep: cli_config.snapshot_keep,
+            });
         Self {
             repository_config: cli_config.repository_config.into(),
             dapp_metadata: cli_config.dapp_metadata_config.into(),
             broker_config: cli_config.broker_config.into(),
             log_config: cli_config.log_config.into(),
             healthcheck_port: cli_config.healthcheck_port,
+            payload_codec: cli_config.input_payload_codec.into(),
+            payload_abi_types: cli_config.input_payload_abi_types,
+            dapp_labels: cli_config.dapp_labels,
+            admin_address: cli_config.indexer_admin_address,
+            skip_migrations: cli_config.postgres_skip_migrations,
+            reconcile_config,
+            snapshot_config,
+            scheduler_config: cli_config.scheduler_config.into(),
+            payload_policy: cli_config.payload_policy_config.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GcConfig {
+    pub repository_config: RepositoryConfig,
+    pub dapp_metadata: DAppMetadata,
+    pub broker_config: BrokerConfig,
+    pub log_config: LogConfig,
+    pub archive_schema: Option<String>,
+}
+
+#[derive(Parser)]
+#[command(name = "dapp_gc_config")]
+#[command(about = "Configuration for removing the data of a deregistered DApp")]
+pub struct GcCLIConfig {
+    #[command(flatten)]
+    repository_config: RepositoryCLIConfig,
+
+    #[command(flatten)]
+    dapp_metadata_config: DAppMetadataCLIConfig,
+
+    #[command(flatten)]
+    broker_config: BrokerCLIConfig,
+
+    #[command(flatten)]
+    pub log_config: LogEnvCliConfig,
+
+    /// Postgres schema that receives a copy of the DApp tables before they are emptied,
+    /// in tables named after the schema version, such as `inputs_20240402000000`.
+    /// If not set, the rows are discarded
+    #[arg(long, env)]
+    pub gc_archive_schema: Option<String>,
+}
+
+impl From<GcCLIConfig> for GcConfig {
+    fn from(cli_config: GcCLIConfig) -> Self {
+        Self {
+            repository_config: cli_config.repository_config.into(),
+            dapp_metadata: cli_config.dapp_metadata_config.into(),
+            broker_config: cli_config.broker_config.into(),
+            log_config: cli_config.log_config.into(),
+            archive_schema: cli_config.gc_archive_schema,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RebuildConfig {
+    pub repository_config: RepositoryConfig,
+    pub dapp_metadata: DAppMetadata,
+    pub broker_config: BrokerConfig,
+    pub log_config: LogConfig,
+    pub payload_codec: CodecSelection,
+    pub payload_abi_types: Option<String>,
+    pub archive_schema: Option<String>,
+    pub skip_migrations: bool,
+}
+
+#[derive(Parser)]
+#[command(name = "db_rebuild_config")]
+#[command(
+    about = "Configuration for rebuilding the database of the indexer from the streams in the broker"
+)]
+pub struct RebuildCLIConfig {
+    #[command(flatten)]
+    repository_config: RepositoryCLIConfig,
+
+    #[command(flatten)]
+    dapp_metadata_config: DAppMetadataCLIConfig,
+
+    #[command(flatten)]
+    broker_config: BrokerCLIConfig,
+
+    #[command(flatten)]
+    pub log_config: LogEnvCliConfig,
+
+    /// Codec used to decode the input payloads: `none`, `auto`, `json`, `cbor` or `abi`
+    #[arg(long, env, default_value = "none")]
+    pub input_payload_codec: String,
+
+    /// Comma-separated Solidity types of the payloads decoded with the `abi` codec,
+    /// such as `address,uint256,bytes`
+    #[arg(long, env)]
+    pub input_payload_abi_types: Option<String>,
+
+    /// Postgres schema that receives a copy of the tables before they are emptied,
+    /// in tables named after the schema version, such as `inputs_20240402000000`.
+    /// If not set, the rows are discarded
+    #[arg(long, env)]
+    pub rebuild_archive_schema: Option<String>,
+
+    /// Don't migrate the database, only check that its schema is up to date
+    #[arg(long, env, default_value_t = false)]
+    pub postgres_skip_migrations: bool,
+}
+
+impl From<RebuildCLIConfig> for RebuildConfig {
+    fn from(cli_config: RebuildCLIConfig) -> Self {
+        Self {
+            repository_config: cli_config.repository_config.into(),
+            dapp_metadata: cli_config.dapp_metadata_config.into(),
+            broker_config: cli_config.broker_config.into(),
+            log_config: cli_config.log_config.into(),
+            payload_codec: cli_config.input_payload_codec.into(),
+            payload_abi_types: cli_config.input_payload_abi_types,
+            archive_schema: cli_config.rebuild_archive_schema,
+            skip_migrations: cli_config.postgres_skip_migrations,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BackfillConfig {
+    pub repository_config: RepositoryConfig,
+    pub log_config: LogConfig,
+    pub http_client_config: HttpClientConfig,
+    pub provider_http_endpoint: Url,
+    pub history_address: H160,
+    pub dapp_address: H160,
+    pub from_block: u64,
+    pub dry_run: bool,
+    pub idempotency_key: Option<String>,
+    pub idempotency_lease: Duration,
+}
+
+#[derive(Parser)]
+#[command(name = "proof_backfill_config")]
+#[command(
+    about = "Configuration for re-anchoring the proofs to a new consensus"
+)]
+pub struct BackfillCLIConfig {
+    #[command(flatten)]
+    repository_config: RepositoryCLIConfig,
+
+    #[command(flatten)]
+    pub log_config: LogEnvCliConfig,
+
+    #[command(flatten)]
+    http_client_config: HttpClientCLIConfig,
+
+    /// HTTP endpoint of the base layer node
+    #[arg(long, env)]
+    pub backfill_provider_http_endpoint: Url,
+
+    /// Address of the History contract of the new consensus
+    #[arg(long, env)]
+    pub backfill_history_address: H160,
+
+    /// Address of the DApp contract
+    #[arg(long, env)]
+    pub backfill_dapp_address: H160,
+
+    /// Block from which the claims of the new consensus are read, such as the
+    /// block where its History was deployed
+    #[arg(long, env, default_value_t = 0)]
+    pub backfill_from_block: u64,
+
+    /// Report what would change without updating the database
+    #[arg(long, env, default_value_t = false)]
+    pub backfill_dry_run: bool,
+
+    /// Key that identifies this backfill request. Running the backfill again
+    /// with the same key returns the report of the first run instead of
+    /// re-anchoring the proofs again, so automation can retry safely
+    #[arg(long, env)]
+    pub backfill_idempotency_key: Option<String>,
+
+    /// Time the backfill holds its idempotency key, such as `1h`; a retry
+    /// after that runs the backfill again if the first run didn't finish,
+    /// so it should be longer than a backfill takes
+    #[arg(
+        long,
+        env,
+        default_value = "1h",
+        value_parser = humane::parse_duration
+    )]
+    pub backfill_idempotency_lease: Duration,
+}
+
+impl From<BackfillCLIConfig> for BackfillConfig {
+    fn from(cli_config: BackfillCLIConfig) -> Self {
+        Self {
+            repository_config: cli_config.repository_config.into(),
+            log_config: cli_config.log_config.into(),
+            http_client_config: cli_config.http_client_config.into(),
+            provider_http_endpoint: cli_config.backfill_provider_http_endpoint,
+            history_address: cli_config.backfill_history_address,
+            dapp_address: cli_config.backfill_dapp_address,
+            from_block: cli_config.backfill_from_block,
+            dry_run: cli_config.backfill_dry_run,
+            idempotency_key: cli_config.backfill_idempotency_key,
+            idempotency_lease: cli_config.backfill_idempotency_lease,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DowngradeConfig {
+    pub repository_config: RepositoryConfig,
+    pub log_config: LogConfig,
+    pub version: String,
+}
+
+#[derive(Parser)]
+#[command(name = "db_downgrade_config")]
+#[command(
+    about = "Configuration for reverting the database to the schema of a previous release"
+)]
+pub struct DowngradeCLIConfig {
+    #[command(flatten)]
+    repository_config: RepositoryCLIConfig,
+
+    #[command(flatten)]
+    pub log_config: LogEnvCliConfig,
+
+    /// Version of the last migration kept, such as `20240402000000`. Only
+    /// the latest migrations can be reverted
+    #[arg(long, env)]
+    pub downgrade_to_version: String,
+}
+
+impl From<DowngradeCLIConfig> for DowngradeConfig {
+    fn from(cli_config: DowngradeCLIConfig) -> Self {
+        Self {
+            repository_config: cli_config.repository_config.into(),
+            log_config: cli_config.log_config.into(),
+            version: cli_config.downgrade_to_version,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BootstrapConfig {
+    pub repository_config: RepositoryConfig,
+    pub dapp_metadata: DAppMetadata,
+    pub log_config: LogConfig,
+    pub from_url: Url,
+    pub trusted_signer: H160,
+    pub skip_migrations: bool,
+    pub validation: Option<ValidationConfig>,
+}
+
+#[derive(Parser)]
+#[command(name = "bootstrap_config")]
+#[command(
+    about = "Configuration for filling the database of a mirror reader node from a published snapshot"
+)]
+pub struct BootstrapCLIConfig {
+    #[command(flatten)]
+    repository_config: RepositoryCLIConfig,
+
+    #[command(flatten)]
+    dapp_metadata_config: DAppMetadataCLIConfig,
+
+    #[command(flatten)]
+    pub log_config: LogEnvCliConfig,
+
+    /// URL of the manifest of the snapshot, such as
+    /// `https://snapshots.example.com/dapp/latest.json`
+    #[arg(long = "from-url", env = "BOOTSTRAP_FROM_URL")]
+    pub bootstrap_from_url: Url,
+
+    /// Address of the publisher of the snapshot, which must have signed its
+    /// manifest
+    #[arg(long = "trusted-signer", env = "BOOTSTRAP_TRUSTED_SIGNER")]
+    pub bootstrap_trusted_signer: H160,
+
+    /// Don't migrate the database, only check that its schema is up to date
+    #[arg(long, env, default_value_t = false)]
+    pub postgres_skip_migrations: bool,
+
+    #[command(flatten)]
+    http_client_config: HttpClientCLIConfig,
+
+    /// HTTP endpoint of the base layer node the snapshot is checked against
+    /// before it is restored. If not set, only the signature and the files
+    /// are checked
+    #[arg(
+        long = "validate-provider-http-endpoint",
+        env = "BOOTSTRAP_VALIDATE_PROVIDER_HTTP_ENDPOINT",
+        requires = "bootstrap_validate_input_box_address"
+    )]
+    pub bootstrap_validate_provider_http_endpoint: Option<Url>,
+
+    /// Address of the InputBox the inputs of the snapshot were added to
+    #[arg(
+        long = "validate-input-box-address",
+        env = "BOOTSTRAP_VALIDATE_INPUT_BOX_ADDRESS"
+    )]
+    pub bootstrap_validate_input_box_address: Option<H160>,
+
+    /// Address of the CartesiDAppFactory that created the DApp. If not set,
+    /// the creation of the DApp isn't checked, only that it has code
+    #[arg(
+        long = "validate-dapp-factory-address",
+        env = "BOOTSTRAP_VALIDATE_DAPP_FACTORY_ADDRESS"
+    )]
+    pub bootstrap_validate_dapp_factory_address: Option<H160>,
+
+    /// Inputs of the snapshot checked against the receipts of their
+    /// transactions
+    #[arg(
+        long = "validate-samples",
+        env = "BOOTSTRAP_VALIDATE_SAMPLES",
+        default_value_t = 32
+    )]
+    pub bootstrap_validate_samples: usize,
+
+    /// Blocks up to the last input of the snapshot whose inputs are read
+    /// from the chain again and compared with the snapshot
+    #[arg(
+        long = "validate-window-blocks",
+        env = "BOOTSTRAP_VALIDATE_WINDOW_BLOCKS",
+        default_value_t = 10000
+    )]
+    pub bootstrap_validate_window_blocks: u64,
+}
+
+impl From<BootstrapCLIConfig> for BootstrapConfig {
+    fn from(cli_config: BootstrapCLIConfig) -> Self {
+        Self {
+            repository_config: cli_config.repository_config.into(),
+            dapp_metadata: cli_config.dapp_metadata_config.into(),
+            log_config: cli_config.log_config.into(),
+            from_url: cli_config.bootstrap_from_url,
+            trusted_signer: cli_config.bootstrap_trusted_signer,
+            skip_migrations: cli_config.postgres_skip_migrations,
+            validation: cli_config
+                .bootstrap_validate_provider_http_endpoint
+                .map(|provider_http_endpoint| ValidationConfig {
+                    http_client_config: cli_config.http_client_config.into(),
+                    provider_http_endpoint,
+                    input_box_address: cli_config
+                        .bootstrap_validate_input_box_address
+                        .expect("required by the provider endpoint"),
+                    dapp_factory_address: cli_config
+                        .bootstrap_validate_dapp_factory_address,
+                    samples: cli_config.bootstrap_validate_samples,
+                    window_blocks: cli_config.bootstrap_validate_window_blocks,
+                }),
         }
     }
 }
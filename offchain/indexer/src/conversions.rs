@@ -45,6 +45,8 @@ pub fn convert_input(input: RollupsAdvanceStateInput) -> Input {
         timestamp,
         payload: input.payload.into_inner(),
         status: CompletionStatus::Unprocessed,
+        decoded_payload: None,
+        payload_codec: None,
     }
 }
 
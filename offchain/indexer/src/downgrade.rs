@@ -0,0 +1,30 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use snafu::ResultExt;
+
+use crate::error::{IndexerError, JoinSnafu, MigrationsSnafu};
+use crate::DowngradeConfig;
+
+/// Revert the database to the schema of a previous release, before rolling
+/// the node back to it.
+///
+/// The indexer must be stopped before running this, and started again with
+/// the previous release, since this release would migrate the database back
+/// up on startup.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn downgrade_database(
+    config: DowngradeConfig,
+) -> Result<Vec<String>, IndexerError> {
+    let endpoint = config.repository_config.endpoint();
+    let version = config.version;
+    tracing::info!(%version, "reverting migrations");
+    let reverted = tokio::task::spawn_blocking(move || {
+        rollups_data::revert_migrations(&endpoint, &version)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(MigrationsSnafu)?;
+    tracing::info!(?reverted, "reverted migrations");
+    Ok(reverted)
+}
@@ -11,6 +11,9 @@ pub enum IndexerError {
         source: http_health_check::HealthCheckError,
     },
 
+    #[snafu(display("admin API error"))]
+    AdminServerError { source: std::io::Error },
+
     #[snafu(display("broker error"))]
     BrokerError { source: rollups_events::BrokerError },
 
@@ -22,6 +25,129 @@ pub enum IndexerError {
     #[snafu(display("repository error"))]
     RepositoryError { source: rollups_data::Error },
 
+    #[snafu(display("payload codec error"))]
+    CodecError { source: crate::codecs::CodecError },
+
+    #[snafu(display("HTTP client error"))]
+    HttpClientError {
+        source: http_provider::HttpClientError,
+    },
+
+    #[snafu(display("failed to call provider"))]
+    ProviderError {
+        source: ethers::providers::ProviderError,
+    },
+
+    #[snafu(display("failed to call contract"))]
+    ContractError {
+        source: ethers::contract::ContractError<http_provider::HttpProvider>,
+    },
+
+    #[snafu(display(
+        "idempotency key `{}` was already used for another request",
+        key
+    ))]
+    IdempotencyKeyReusedError { key: String },
+
+    #[snafu(display(
+        "request with idempotency key `{}` is still running",
+        key
+    ))]
+    IdempotencyKeyInProgressError { key: String },
+
+    #[snafu(display("failed to read the stored result of the request"))]
+    IdempotencyResultError { source: serde_json::Error },
+
+    #[snafu(display("failed to access snapshot file `{}`", path.display()))]
+    SnapshotIoError {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("invalid row {} of snapshot file `{}`", line, path))]
+    SnapshotRowError {
+        path: String,
+        line: u64,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("invalid private key of the snapshot signer"))]
+    InvalidSnapshotSignerError,
+
+    #[snafu(display("failed to sign the snapshot manifest"))]
+    SnapshotSignError {
+        source: ethers::signers::WalletError,
+    },
+
+    #[snafu(display("invalid snapshot URL `{}`", url))]
+    SnapshotUrlError {
+        url: String,
+        source: redacted::url::ParseError,
+    },
+
+    #[snafu(display("failed to upload snapshot file `{}`", path))]
+    SnapshotHttpUploadError {
+        path: String,
+        source: reqwest::Error,
+    },
+
+    #[snafu(display("failed to upload snapshot file `{}` to S3", path))]
+    SnapshotS3UploadError {
+        path: String,
+        source: rusoto_core::RusotoError<rusoto_s3::PutObjectError>,
+    },
+
+    #[snafu(display("failed to download `{}`", url))]
+    SnapshotDownloadError { url: String, source: reqwest::Error },
+
+    #[snafu(display("invalid snapshot signature: {}", reason))]
+    InvalidSnapshotSignatureError { reason: String },
+
+    #[snafu(display("invalid snapshot manifest"))]
+    InvalidSnapshotManifestError { source: serde_json::Error },
+
+    #[snafu(display("unsupported snapshot version {}", version))]
+    UnsupportedSnapshotVersionError { version: u32 },
+
+    #[snafu(display("snapshot has no file for the `{}` table", table))]
+    MissingSnapshotTableError { table: String },
+
+    #[snafu(display("snapshot file `{}` is corrupted: {}", path, reason))]
+    SnapshotFileMismatchError { path: String, reason: String },
+
+    #[snafu(display(
+        "snapshot is of DApp {} on chain {}, not of DApp {} on chain {}",
+        dapp_address,
+        chain_id,
+        expected_dapp_address,
+        expected_chain_id
+    ))]
+    SnapshotMirrorMismatchError {
+        chain_id: u64,
+        dapp_address: String,
+        expected_chain_id: u64,
+        expected_dapp_address: String,
+    },
+
+    #[snafu(display("snapshot doesn't match the chain:\n{}", report))]
+    SnapshotRejectedError {
+        report: crate::snapshot::ValidationReport,
+    },
+
+    #[snafu(display(
+        "can't rebuild the database from the broker: {}",
+        reason
+    ))]
+    RebuildUnavailableError { reason: String },
+
+    #[snafu(display(
+        "rebuilt database doesn't match the broker:\n{}",
+        report
+    ))]
+    RebuildMismatchError {
+        report: crate::rebuild::RebuildReport,
+    },
+
     #[snafu(display("join error"))]
     JoinError { source: tokio::task::JoinError },
 }
@@ -0,0 +1,65 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use rollups_data::Repository;
+use rollups_events::{Broker, RollupsInputsStream, RollupsOutputsStream};
+use snafu::ResultExt;
+
+use crate::error::{BrokerSnafu, IndexerError, JoinSnafu, RepositorySnafu};
+use crate::GcConfig;
+
+/// Remove the data of a deregistered DApp: the indexed rows (optionally
+/// archived first) and its inputs and outputs streams in the broker.
+///
+/// The DApp services must be stopped before running this, otherwise the
+/// indexer would write the rows back. The claims stream is shared by every
+/// DApp of the chain, so it is left untouched. Metrics series are kept in the
+/// memory of the DApp services, so they are dropped when those stop.
+///
+/// The files the DApp services keep on their own disks are out of scope:
+/// the checkpoints of the state server and the spool of the dispatcher are
+/// removed with the volumes of those services, such as by deleting their
+/// `--state-server-checkpoint-dir` and `--rd-spool-dir`.
+///
+/// The rows are removed before the streams so that, if anything fails, the
+/// operation can be retried without losing the only copy of the data.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn collect_garbage(config: GcConfig) -> Result<(), IndexerError> {
+    tracing::info!("connecting to DB");
+    let repository = tokio::task::spawn_blocking(|| {
+        Repository::new(config.repository_config)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    tracing::info!(?config.archive_schema, "deleting indexed rows");
+    let archive_schema = config.archive_schema;
+    tokio::task::spawn_blocking(move || {
+        repository.delete_all(archive_schema.as_deref())
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    tracing::info!("connecting to broker");
+    let mut broker = Broker::new(config.broker_config)
+        .await
+        .context(BrokerSnafu)?;
+
+    let inputs_stream = RollupsInputsStream::new(&config.dapp_metadata);
+    let deleted = broker
+        .delete_stream(&inputs_stream)
+        .await
+        .context(BrokerSnafu)?;
+    tracing::info!(deleted, "deleted inputs stream");
+
+    let outputs_stream = RollupsOutputsStream::new(&config.dapp_metadata);
+    let deleted = broker
+        .delete_stream(&outputs_stream)
+        .await
+        .context(BrokerSnafu)?;
+    tracing::info!(deleted, "deleted outputs stream");
+
+    Ok(())
+}
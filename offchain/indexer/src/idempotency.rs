@@ -0,0 +1,59 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Mutations run once per idempotency key, so that automation retrying a
+//! request doesn't run it twice. The result of the first run is stored in
+//! the database and returned to the retries.
+
+use rollups_data::Repository;
+use serde::{de::DeserializeOwned, Serialize};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::time::Duration;
+
+use crate::error::{
+    IdempotencyKeyInProgressSnafu, IdempotencyKeyReusedSnafu,
+    IdempotencyResultSnafu, IndexerError, RepositorySnafu,
+};
+
+/// Runs `mutation` unless it already ran with `key`, in which case the
+/// stored result is returned.
+///
+/// `request` describes the mutation, so the key can't be reused for another
+/// one. The key is held for `lease` while the mutation runs; a retry after
+/// that, such as after a crash, runs it again. A failed mutation releases
+/// the key.
+pub fn run_once<T: Serialize + DeserializeOwned>(
+    repository: &Repository,
+    key: &str,
+    request: &str,
+    lease: Duration,
+    mutation: impl FnOnce() -> Result<T, IndexerError>,
+) -> Result<T, IndexerError> {
+    let stored = repository
+        .claim_idempotency_key(key, request, lease)
+        .context(RepositorySnafu)?;
+    if let Some(stored) = stored {
+        ensure!(stored.request == request, IdempotencyKeyReusedSnafu { key });
+        let result = stored
+            .result
+            .context(IdempotencyKeyInProgressSnafu { key })?;
+        tracing::info!(%key, "request already ran with this idempotency key");
+        return serde_json::from_str(&result).context(IdempotencyResultSnafu);
+    }
+    match mutation() {
+        Ok(value) => {
+            let result = serde_json::to_string(&value)
+                .expect("idempotent results should always serialize");
+            repository
+                .complete_idempotency_key(key, result)
+                .context(RepositorySnafu)?;
+            Ok(value)
+        }
+        Err(e) => {
+            repository
+                .release_idempotency_key(key)
+                .context(RepositorySnafu)?;
+            Err(e)
+        }
+    }
+}
@@ -1,31 +1,68 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use rollups_data::Repository;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_health_check::Health;
+
+use redacted::PayloadPolicy;
+use rollups_data::{Label, Repository};
 use rollups_events::indexer::{IndexerEvent, IndexerState};
 use rollups_events::{
-    Broker, BrokerError, RollupsData, RollupsInput, RollupsOutput,
+    Broker, BrokerError, Payload, RollupsData, RollupsInput, RollupsOutput,
 };
+use scheduler::Scheduler;
 use snafu::ResultExt;
 
+use crate::codecs::{AbiCodec, CodecRegistry, PayloadDecoder};
 use crate::conversions::*;
 use crate::error::{
-    BrokerSnafu, IndexerError, JoinSnafu, MigrationsSnafu, RepositorySnafu,
+    BrokerSnafu, CodecSnafu, IndexerError, JoinSnafu, MigrationsSnafu,
+    RepositorySnafu,
 };
 use crate::IndexerConfig;
+use crate::{admin, reconcile, snapshot};
+
+/// Subsystems of the indexer reported at `/healthz` and `/readyz`
+pub const DATABASE: &str = "database";
+pub const BROKER: &str = "broker";
+
+/// Every how long the database is checked to be reachable
+const DATABASE_PROBE_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct Indexer {
     repository: Repository,
     broker: Broker,
     state: IndexerState,
+    decoder: Arc<PayloadDecoder>,
+    payload_policy: PayloadPolicy,
 }
 
 impl Indexer {
     #[tracing::instrument(level = "trace", skip_all)]
-    pub async fn start(config: IndexerConfig) -> Result<(), IndexerError> {
-        tracing::info!("running database migrations");
+    pub async fn start(
+        config: IndexerConfig,
+        mut registry: CodecRegistry,
+        health: Health,
+    ) -> Result<(), IndexerError> {
+        if let Some(types) = &config.payload_abi_types {
+            let codec = AbiCodec::new(types).context(CodecSnafu)?;
+            registry.register(Box::new(codec), false);
+        }
+        let decoder = PayloadDecoder::new(registry, config.payload_codec)
+            .context(CodecSnafu)?;
+
         let endpoint = config.repository_config.endpoint();
-        rollups_data::run_migrations(&endpoint).context(MigrationsSnafu)?;
+        if config.skip_migrations {
+            tracing::info!("checking database migrations");
+            rollups_data::check_migrations(&endpoint)
+                .context(MigrationsSnafu)?;
+        } else {
+            tracing::info!("running database migrations");
+            rollups_data::run_migrations(&endpoint).context(MigrationsSnafu)?;
+        }
 
         tracing::info!("runned migrations; connecting to DB");
         let repository = tokio::task::spawn_blocking(|| {
@@ -34,26 +71,100 @@ impl Indexer {
         .await
         .context(JoinSnafu)?
         .context(RepositorySnafu)?;
+        let probed = repository.clone();
+        tokio::spawn(health.clone().probe(
+            DATABASE,
+            DATABASE_PROBE_INTERVAL,
+            move || probed.ping(),
+        ));
+
+        if let Some(dapp_labels) = config.dapp_labels {
+            tracing::info!("connected to database; storing the DApp labels");
+            let labels = dapp_labels
+                .iter()
+                .map(|(name, value)| Label {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                })
+                .collect();
+            let repository_clone = repository.clone();
+            tokio::task::spawn_blocking(move || {
+                repository_clone.replace_labels(labels)
+            })
+            .await
+            .context(JoinSnafu)?
+            .context(RepositorySnafu)?;
+        }
+        if let Some(admin_address) = config.admin_address {
+            let admin = admin::serve(admin_address, repository.clone());
+            tokio::spawn(async move {
+                if let Err(e) = admin.await {
+                    tracing::error!("stopped serving the admin API: {}", e);
+                }
+            });
+        }
 
-        tracing::info!("connected to database; connecting to broker");
-        let broker = Broker::new(config.broker_config)
+        let decoder = Arc::new(decoder);
+        let scheduler = Scheduler::new(&config.scheduler_config);
+        if let Some(reconcile_config) = config.reconcile_config {
+            let reconcile = reconcile::start(
+                reconcile_config,
+                repository.clone(),
+                decoder.clone(),
+                config.broker_config.clone(),
+                config.dapp_metadata.clone(),
+                scheduler.clone(),
+            );
+            tokio::spawn(async move {
+                if let Err(e) = reconcile.await {
+                    tracing::error!(
+                        "stopped reconciling the indexed outputs: {}",
+                        e
+                    );
+                }
+            });
+        }
+        if let Some(snapshot_config) = config.snapshot_config {
+            let publish = snapshot::start(
+                snapshot_config,
+                repository.clone(),
+                config.dapp_metadata.clone(),
+                scheduler,
+            );
+            tokio::spawn(async move {
+                if let Err(e) = publish.await {
+                    tracing::error!("stopped publishing snapshots: {}", e);
+                }
+            });
+        }
+
+        tracing::info!("connecting to broker");
+        let mut broker = Broker::new(config.broker_config)
             .await
             .context(BrokerSnafu)?;
 
-        let state = IndexerState::new(&config.dapp_metadata);
+        let mut state = IndexerState::new(&config.dapp_metadata);
+        broker
+            .indexer_register(&mut state)
+            .await
+            .context(BrokerSnafu)?;
+        health.up(BROKER);
         let mut indexer = Indexer {
             repository,
             broker,
             state,
+            decoder,
+            payload_policy: config.payload_policy,
         };
 
         tracing::info!("connected to broker; starting main loop");
         loop {
             let event = indexer.consume_event().await?;
             let repository = indexer.repository.clone();
+            let decoder = indexer.decoder.clone();
             tokio::task::spawn_blocking(move || match event {
                 IndexerEvent::Input(input) => {
-                    store_input(&repository, input.payload)
+                    store_input(&repository, &decoder, input.payload)
                 }
                 IndexerEvent::Output(output) => {
                     store_output(&repository, output.payload)
@@ -62,6 +173,11 @@ impl Indexer {
             .await
             .context(JoinSnafu)?
             .context(RepositorySnafu)?;
+            indexer
+                .broker
+                .indexer_acknowledge(&indexer.state)
+                .await
+                .context(BrokerSnafu)?;
         }
     }
 
@@ -71,7 +187,8 @@ impl Indexer {
         loop {
             match self.broker.indexer_consume(&mut self.state).await {
                 Ok(event) => {
-                    tracing::info!(?event, "received event");
+                    let logged = redact_event(&event, &self.payload_policy);
+                    tracing::info!(event = ?logged, "received event");
                     return Ok(event);
                 }
                 Err(source) => match source {
@@ -88,14 +205,38 @@ impl Indexer {
     }
 }
 
+/// Event as logged, with the input payload redacted by the policy
+fn redact_event<'a>(
+    event: &'a IndexerEvent,
+    policy: &PayloadPolicy,
+) -> Cow<'a, IndexerEvent> {
+    if !policy.is_redacting() {
+        return Cow::Borrowed(event);
+    }
+    let mut event = event.clone();
+    if let IndexerEvent::Input(input) = &mut event {
+        if let RollupsData::AdvanceStateInput(input) = &mut input.payload.data {
+            let payload = policy.payload(input.payload.inner()).into_owned();
+            input.payload = Payload::new(payload);
+        }
+    }
+    Cow::Owned(event)
+}
+
 #[tracing::instrument(level = "trace", skip_all)]
-fn store_input(
+pub(crate) fn store_input(
     repository: &Repository,
+    decoder: &PayloadDecoder,
     input: RollupsInput,
 ) -> Result<(), rollups_data::Error> {
     match input.data {
         RollupsData::AdvanceStateInput(input) => {
-            repository.insert_input(convert_input(input))
+            let mut input = convert_input(input);
+            if let Some((codec, decoded)) = decoder.decode(&input.payload) {
+                input.payload_codec = Some(codec);
+                input.decoded_payload = Some(decoded);
+            }
+            repository.insert_input(input)
         }
         RollupsData::FinishEpoch {} => {
             tracing::trace!("ignoring finish epoch");
@@ -105,7 +246,7 @@ fn store_input(
 }
 
 #[tracing::instrument(level = "trace", skip_all)]
-fn store_output(
+pub(crate) fn store_output(
     repository: &Repository,
     output: RollupsOutput,
 ) -> Result<(), rollups_data::Error> {
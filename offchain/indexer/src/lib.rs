@@ -1,20 +1,62 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use http_health_check::Health;
 use snafu::ResultExt;
 
-pub use config::{CLIConfig, IndexerConfig};
+pub use backfill::{backfill_proofs, BackfillReport};
+pub use codecs::{
+    AbiCodec, CborCodec, CodecError, CodecRegistry, CodecSelection, JsonCodec,
+    PayloadCodec,
+};
+pub use config::{
+    BackfillCLIConfig, BackfillConfig, BootstrapCLIConfig, BootstrapConfig,
+    CLIConfig, DowngradeCLIConfig, DowngradeConfig, GcCLIConfig, GcConfig,
+    IndexerConfig, RebuildCLIConfig, RebuildConfig,
+};
+pub use downgrade::downgrade_database;
 pub use error::IndexerError;
+pub use gc::collect_garbage;
+pub use rebuild::{rebuild_database, RebuildReport};
+pub use reconcile::ReconcileConfig;
+pub use snapshot::{
+    bootstrap_snapshot, Manifest, ManifestFile, SnapshotConfig,
+    ValidationCheck, ValidationConfig, ValidationReport,
+};
 
+mod admin;
+mod backfill;
+mod codecs;
 pub mod config;
 mod conversions;
+mod downgrade;
 mod error;
+mod gc;
+mod idempotency;
 mod indexer;
+mod rebuild;
+mod reconcile;
+mod snapshot;
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn run(config: IndexerConfig) -> Result<(), IndexerError> {
-    let health_handle = http_health_check::start(config.healthcheck_port);
-    let indexer_handle = indexer::Indexer::start(config);
+    run_with_codecs(config, CodecRegistry::default()).await
+}
+
+/// Run the indexer with a custom set of payload codecs
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn run_with_codecs(
+    config: IndexerConfig,
+    registry: CodecRegistry,
+) -> Result<(), IndexerError> {
+    let health = Health::default();
+    health.register(indexer::DATABASE, None);
+    health.register(indexer::BROKER, None);
+    let health_handle = http_health_check::start_with_health(
+        config.healthcheck_port,
+        health.clone(),
+    );
+    let indexer_handle = indexer::Indexer::start(config, registry, health);
     tokio::select! {
         ret = health_handle => {
             ret.context(error::HealthCheckSnafu)
@@ -7,11 +7,15 @@ use indexer::{CLIConfig, IndexerConfig};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets::resolve_env().await?;
     let config: IndexerConfig = CLIConfig::parse().into();
 
     log::configure(&config.log_config);
 
     log::log_service_start(&config, "Indexer");
 
-    indexer::run(config).await.map_err(|e| e.into())
+    tokio::select! {
+        ret = indexer::run(config) => ret.map_err(|e| e.into()),
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
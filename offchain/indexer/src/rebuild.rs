@@ -0,0 +1,389 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use rollups_data::{EpochCounts, Repository};
+use rollups_events::{
+    Broker, RollupsData, RollupsInput, RollupsInputsStream, RollupsOutput,
+    RollupsOutputEnum, RollupsOutputsStream, INITIAL_ID,
+};
+use snafu::{ensure, ResultExt};
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+use crate::codecs::{AbiCodec, CodecRegistry, PayloadDecoder};
+use crate::error::{
+    BrokerSnafu, CodecSnafu, IndexerError, JoinSnafu, MigrationsSnafu,
+    RebuildMismatchSnafu, RebuildUnavailableSnafu, RepositorySnafu,
+};
+use crate::indexer::{store_input, store_output};
+use crate::RebuildConfig;
+
+/// Events stored between the progress reports
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// Rows the events of the streams add up to. An event may be in a stream
+/// more than once, such as after the machine is restarted, and is only
+/// stored once, so the rows are told apart by their keys.
+#[derive(Debug, Default)]
+struct Tally {
+    events: usize,
+    inputs: BTreeSet<u64>,
+    vouchers: BTreeSet<(u64, u64)>,
+    notices: BTreeSet<(u64, u64)>,
+    proofs: BTreeSet<(u64, u64, &'static str)>,
+}
+
+impl Tally {
+    fn add_input(&mut self, input: &RollupsInput) {
+        self.events += 1;
+        if let RollupsData::AdvanceStateInput(input) = &input.data {
+            self.inputs.insert(input.metadata.input_index);
+        }
+    }
+
+    fn add_output(&mut self, output: &RollupsOutput) {
+        self.events += 1;
+        match output {
+            RollupsOutput::Voucher(voucher) => {
+                self.vouchers.insert((voucher.input_index, voucher.index));
+            }
+            RollupsOutput::Notice(notice) => {
+                self.notices.insert((notice.input_index, notice.index));
+            }
+            RollupsOutput::Proof(proof) => {
+                let output_enum = match proof.output_enum {
+                    RollupsOutputEnum::Voucher => "voucher",
+                    RollupsOutputEnum::Notice => "notice",
+                };
+                self.proofs.insert((
+                    proof.input_index,
+                    proof.output_index,
+                    output_enum,
+                ));
+            }
+            RollupsOutput::AdvanceResult(_) | RollupsOutput::Report(_) => {}
+        }
+    }
+
+    /// Number of inputs missing before the last one, and the first of them.
+    /// The streams only miss inputs once they are compacted.
+    fn missing_inputs(&self) -> Option<(u64, u64)> {
+        let last = *self.inputs.last()?;
+        let missing = last + 1 - self.inputs.len() as u64;
+        let first = self
+            .inputs
+            .iter()
+            .zip(0..)
+            .find(|(index, expected)| **index != *expected)
+            .map(|(_, expected)| expected)?;
+        Some((missing, first))
+    }
+
+    fn rows(&self) -> EpochCounts {
+        EpochCounts {
+            inputs: self.inputs.len() as i64,
+            vouchers: self.vouchers.len() as i64,
+            notices: self.notices.len() as i64,
+            proofs: self.proofs.len() as i64,
+        }
+    }
+}
+
+/// Outcome of a rebuild: the rows the events of the broker add up to, and
+/// the ones found in the database once they were stored
+#[derive(Debug, PartialEq, Eq)]
+pub struct RebuildReport {
+    pub events: usize,
+    pub expected: EpochCounts,
+    pub indexed: EpochCounts,
+}
+
+impl RebuildReport {
+    pub fn passed(&self) -> bool {
+        self.expected == self.indexed
+    }
+}
+
+impl fmt::Display for RebuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = [
+            ("inputs", self.expected.inputs, self.indexed.inputs),
+            ("vouchers", self.expected.vouchers, self.indexed.vouchers),
+            ("notices", self.expected.notices, self.indexed.notices),
+            ("proofs", self.expected.proofs, self.indexed.proofs),
+        ];
+        for (table, expected, indexed) in rows {
+            if expected == indexed {
+                writeln!(f, "{}: {} ok", table, indexed)?;
+            } else {
+                writeln!(
+                    f,
+                    "{}: {} indexed, {} expected",
+                    table, indexed, expected
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drop the indexed rows of the DApp and store them again from its inputs
+/// and outputs streams in the broker, without reading the chain, such as
+/// to recover from a schema change.
+///
+/// The indexer must be stopped before running this. The streams are read
+/// once to check they still hold every input from the first one, and
+/// nothing is deleted if they were compacted. The rows are then deleted,
+/// optionally archived first, and the events read in the first pass are
+/// stored again, with the progress logged as it goes. At the end, the rows
+/// in the database are checked against the ones the events add up to.
+///
+/// The labels are stored by the indexer on startup, which then resumes from
+/// the last events it acknowledged, since the rebuild doesn't acknowledge.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn rebuild_database(
+    config: RebuildConfig,
+) -> Result<RebuildReport, IndexerError> {
+    let mut registry = CodecRegistry::default();
+    if let Some(types) = &config.payload_abi_types {
+        let codec = AbiCodec::new(types).context(CodecSnafu)?;
+        registry.register(Box::new(codec), false);
+    }
+    let decoder = Arc::new(
+        PayloadDecoder::new(registry, config.payload_codec)
+            .context(CodecSnafu)?,
+    );
+
+    tracing::info!("connecting to broker");
+    let mut broker = Broker::new(config.broker_config)
+        .await
+        .context(BrokerSnafu)?;
+    let inputs_stream = RollupsInputsStream::new(&config.dapp_metadata);
+    let outputs_stream = RollupsOutputsStream::new(&config.dapp_metadata);
+
+    tracing::info!("reading the streams of the DApp");
+    let mut tally = Tally::default();
+    let mut inputs_last_id = INITIAL_ID.to_owned();
+    while let Some(event) = broker
+        .consume_nonblocking(&inputs_stream, &inputs_last_id)
+        .await
+        .context(BrokerSnafu)?
+    {
+        tally.add_input(&event.payload);
+        inputs_last_id = event.id;
+    }
+    let mut outputs_last_id = INITIAL_ID.to_owned();
+    while let Some(event) = broker
+        .consume_nonblocking(&outputs_stream, &outputs_last_id)
+        .await
+        .context(BrokerSnafu)?
+    {
+        tally.add_output(&event.payload);
+        outputs_last_id = event.id;
+    }
+    ensure!(
+        !tally.inputs.is_empty(),
+        RebuildUnavailableSnafu {
+            reason: "the inputs stream has no inputs",
+        }
+    );
+    if let Some((missing, first)) = tally.missing_inputs() {
+        return RebuildUnavailableSnafu {
+            reason: format!(
+                "the inputs stream is missing {} inputs, from input {}, so \
+                 it was compacted",
+                missing, first
+            ),
+        }
+        .fail();
+    }
+    tracing::info!(
+        events = tally.events,
+        rows = ?tally.rows(),
+        "read the streams"
+    );
+
+    let endpoint = config.repository_config.endpoint();
+    if config.skip_migrations {
+        tracing::info!("checking database migrations");
+        rollups_data::check_migrations(&endpoint).context(MigrationsSnafu)?;
+    } else {
+        tracing::info!("running database migrations");
+        rollups_data::run_migrations(&endpoint).context(MigrationsSnafu)?;
+    }
+    let repository = tokio::task::spawn_blocking(|| {
+        Repository::new(config.repository_config)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    tracing::info!(?config.archive_schema, "deleting indexed rows");
+    let archive_schema = config.archive_schema;
+    let repository_clone = repository.clone();
+    tokio::task::spawn_blocking(move || {
+        repository_clone.delete_all(archive_schema.as_deref())
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    // The inputs go first, since the outputs refer to them, and only up to
+    // the events of the first pass, which the tally is made of
+    let mut stored = 0;
+    let mut last_id = INITIAL_ID.to_owned();
+    while last_id != inputs_last_id {
+        let Some(event) = broker
+            .consume_nonblocking(&inputs_stream, &last_id)
+            .await
+            .context(BrokerSnafu)?
+        else {
+            break;
+        };
+        last_id = event.id;
+        let repository = repository.clone();
+        let decoder = decoder.clone();
+        tokio::task::spawn_blocking(move || {
+            store_input(&repository, &decoder, event.payload)
+        })
+        .await
+        .context(JoinSnafu)?
+        .context(RepositorySnafu)?;
+        stored += 1;
+        report_progress(stored, tally.events);
+    }
+    let mut last_id = INITIAL_ID.to_owned();
+    while last_id != outputs_last_id {
+        let Some(event) = broker
+            .consume_nonblocking(&outputs_stream, &last_id)
+            .await
+            .context(BrokerSnafu)?
+        else {
+            break;
+        };
+        last_id = event.id;
+        let repository = repository.clone();
+        tokio::task::spawn_blocking(move || {
+            store_output(&repository, event.payload)
+        })
+        .await
+        .context(JoinSnafu)?
+        .context(RepositorySnafu)?;
+        stored += 1;
+        report_progress(stored, tally.events);
+    }
+
+    tracing::info!("checking the rebuilt rows");
+    let last_input_index = *tally.inputs.last().expect("checked above");
+    let indexed = tokio::task::spawn_blocking(move || {
+        repository.count_epoch_rows(0, last_input_index as i32)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+    let report = RebuildReport {
+        events: stored,
+        expected: tally.rows(),
+        indexed,
+    };
+    ensure!(report.passed(), RebuildMismatchSnafu { report });
+    tracing::info!(%report, "rebuilt the database");
+    Ok(report)
+}
+
+fn report_progress(stored: usize, total: usize) {
+    if stored % PROGRESS_INTERVAL == 0 || stored == total {
+        let percent = stored as f64 * 100.0 / total.max(1) as f64;
+        tracing::info!(stored, total, "stored {:.1}% of the events", percent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rollups_events::{
+        InputMetadata, RollupsAdvanceStateInput, RollupsNotice, RollupsProof,
+    };
+
+    fn input(input_index: u64) -> RollupsInput {
+        RollupsInput {
+            parent_id: INITIAL_ID.to_owned(),
+            epoch_index: 0,
+            inputs_sent_count: input_index + 1,
+            data: RollupsData::AdvanceStateInput(RollupsAdvanceStateInput {
+                metadata: InputMetadata {
+                    input_index,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn it_tallies_the_distinct_rows() {
+        let mut tally = Tally::default();
+        for index in [0, 1, 1, 2] {
+            tally.add_input(&input(index));
+        }
+        let notice = RollupsOutput::Notice(RollupsNotice {
+            input_index: 1,
+            ..Default::default()
+        });
+        tally.add_output(&notice);
+        tally.add_output(&notice);
+        tally.add_output(&RollupsOutput::Proof(RollupsProof {
+            input_index: 1,
+            output_enum: RollupsOutputEnum::Notice,
+            ..Default::default()
+        }));
+        tally.add_output(&RollupsOutput::Proof(RollupsProof {
+            input_index: 1,
+            output_enum: RollupsOutputEnum::Voucher,
+            ..Default::default()
+        }));
+
+        assert_eq!(tally.events, 8);
+        assert_eq!(
+            tally.rows(),
+            EpochCounts {
+                inputs: 3,
+                vouchers: 0,
+                notices: 1,
+                proofs: 2,
+            }
+        );
+        assert_eq!(tally.missing_inputs(), None);
+    }
+
+    #[test]
+    fn it_finds_the_inputs_trimmed_by_compaction() {
+        let mut tally = Tally::default();
+        for index in [3, 4, 6] {
+            tally.add_input(&input(index));
+        }
+        assert_eq!(tally.missing_inputs(), Some((4, 0)));
+        assert_eq!(Tally::default().missing_inputs(), None);
+    }
+
+    #[test]
+    fn it_reports_the_tables_that_dont_add_up() {
+        let expected = EpochCounts {
+            inputs: 3,
+            vouchers: 1,
+            notices: 2,
+            proofs: 3,
+        };
+        let report = RebuildReport {
+            events: 10,
+            expected: expected.clone(),
+            indexed: EpochCounts {
+                notices: 1,
+                ..expected
+            },
+        };
+        assert!(!report.passed());
+        let report = report.to_string();
+        assert!(report.contains("inputs: 3 ok"));
+        assert!(report.contains("notices: 1 indexed, 2 expected"));
+    }
+}
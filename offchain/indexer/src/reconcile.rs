@@ -0,0 +1,374 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use contracts::history::{Claim, History};
+use ethers::{providers::Middleware, types::H160};
+use http_provider::{HttpClient, HttpClientConfig, HttpProvider};
+use rollups_data::{EpochCounts, Repository, Url};
+use rollups_events::{
+    Broker, BrokerConfig, DAppMetadata, RollupsData, RollupsInputsStream,
+    RollupsOutput, RollupsOutputsStream, INITIAL_ID,
+};
+use scheduler::{JobConfig, Scheduler};
+use snafu::ResultExt;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+use crate::codecs::PayloadDecoder;
+use crate::error::{
+    BrokerSnafu, ContractSnafu, HttpClientSnafu, IndexerError, JoinSnafu,
+    ProviderSnafu, RepositorySnafu,
+};
+use crate::indexer::{store_input, store_output};
+
+/// How the indexed outputs are reconciled with the claims on chain
+#[derive(Debug)]
+pub struct ReconcileConfig {
+    pub interval: Duration,
+    pub http_client_config: HttpClientConfig,
+    pub provider_http_endpoint: Url,
+    pub history_address: H160,
+    pub from_block: u64,
+    pub reindex: bool,
+}
+
+/// Rows of a claimed epoch that don't add up
+#[derive(Debug, PartialEq, Eq)]
+struct Divergence {
+    claimed_inputs: i64,
+    indexed_inputs: i64,
+    indexed_outputs: i64,
+    indexed_proofs: i64,
+}
+
+/// Claims read so far, kept across the runs of the job
+struct ReconcileState {
+    next_block: u64,
+    epochs: Vec<ClaimedEpoch>,
+}
+
+/// An epoch claimed on chain that wasn't found consistent yet
+struct ClaimedEpoch {
+    first_index: i32,
+    last_index: i32,
+    /// Whether the last check found it divergent
+    divergent: bool,
+}
+
+/// Periodically checks the rows indexed for each epoch claimed on chain,
+/// catching the data lost silently by the indexing pipeline.
+///
+/// The inputs of an epoch are checked against the range of its claim, and
+/// its vouchers and notices against their proofs: the machine proves every
+/// output of the epoch it commits to, so a missing output or proof means a
+/// row was lost. Since the indexer may lag behind the chain, an epoch is
+/// only reported after being found divergent in two consecutive runs. With
+/// `reindex`, the events of a divergent epoch are then read from the broker
+/// again and stored; rows already indexed are left as they are.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn start(
+    config: ReconcileConfig,
+    repository: Repository,
+    decoder: Arc<PayloadDecoder>,
+    broker_config: BrokerConfig,
+    dapp_metadata: DAppMetadata,
+    scheduler: Scheduler,
+) -> Result<(), IndexerError> {
+    let http_client =
+        HttpClient::new(&config.http_client_config).context(HttpClientSnafu)?;
+    let provider =
+        Arc::new(http_client.provider(config.provider_http_endpoint));
+    let history = History::new(config.history_address, provider.clone());
+    let dapp_address = H160(dapp_metadata.dapp_address.inner().to_owned());
+    let broker = Broker::new(broker_config).await.context(BrokerSnafu)?;
+
+    let state = Mutex::new(ReconcileState {
+        next_block: config.from_block,
+        epochs: vec![],
+    });
+    let reindex_divergent = config.reindex;
+    let job = || {
+        let provider = provider.clone();
+        let history = &history;
+        let repository = &repository;
+        let decoder = &decoder;
+        let dapp_metadata = &dapp_metadata;
+        let mut broker = broker.clone();
+        let state = &state;
+        async move {
+            let mut state = state.lock().await;
+            let latest = provider
+                .get_block_number()
+                .await
+                .context(ProviderSnafu)?
+                .as_u64();
+            if state.next_block <= latest {
+                let claims = history
+                    .new_claim_to_history_filter()
+                    .from_block(state.next_block)
+                    .to_block(latest)
+                    .query()
+                    .await
+                    .context(ContractSnafu)?;
+                state.epochs.extend(
+                    claims
+                        .into_iter()
+                        .filter(|event| event.dapp == dapp_address)
+                        .map(|event| claimed_epoch(&event.claim)),
+                );
+                state.next_block = latest + 1;
+            }
+
+            let mut index = 0;
+            while index < state.epochs.len() {
+                let epoch = &mut state.epochs[index];
+                let reconciled = reconcile_epoch(
+                    epoch,
+                    repository,
+                    reindex_divergent.then_some((
+                        &mut broker,
+                        dapp_metadata,
+                        decoder,
+                    )),
+                )
+                .await?;
+                if reconciled {
+                    state.epochs.remove(index);
+                } else {
+                    index += 1;
+                }
+            }
+            Ok::<_, IndexerError>(())
+        }
+    };
+    scheduler
+        .run(
+            "output_reconciliation",
+            &JobConfig::every(config.interval),
+            job,
+        )
+        .await;
+    Ok(())
+}
+
+/// Checks the epoch, re-indexing it if asked to and it was already found
+/// divergent. Returns whether the epoch is consistent.
+async fn reconcile_epoch(
+    epoch: &mut ClaimedEpoch,
+    repository: &Repository,
+    reindex_with: Option<(&mut Broker, &DAppMetadata, &Arc<PayloadDecoder>)>,
+) -> Result<bool, IndexerError> {
+    let Some(divergence) = check(repository, epoch).await? else {
+        return Ok(true);
+    };
+    if !epoch.divergent {
+        tracing::debug!(
+            first_index = epoch.first_index,
+            last_index = epoch.last_index,
+            ?divergence,
+            "epoch diverges from its claim; checking it again on the next run"
+        );
+        epoch.divergent = true;
+        return Ok(false);
+    }
+    tracing::error!(
+        first_index = epoch.first_index,
+        last_index = epoch.last_index,
+        ?divergence,
+        "indexed outputs diverge from the claimed epoch"
+    );
+    let Some((broker, dapp_metadata, decoder)) = reindex_with else {
+        return Ok(false);
+    };
+    let restored =
+        reindex(broker, dapp_metadata, repository, decoder, epoch).await?;
+    tracing::info!(
+        first_index = epoch.first_index,
+        last_index = epoch.last_index,
+        restored,
+        "re-indexed the divergent epoch"
+    );
+    if check(repository, epoch).await?.is_none() {
+        return Ok(true);
+    }
+    tracing::error!(
+        first_index = epoch.first_index,
+        last_index = epoch.last_index,
+        "epoch still diverges after re-indexing; its events may have been trimmed from the broker"
+    );
+    Ok(false)
+}
+
+fn claimed_epoch(claim: &Claim) -> ClaimedEpoch {
+    ClaimedEpoch {
+        first_index: saturating_i32(claim.first_index),
+        last_index: saturating_i32(claim.last_index),
+        divergent: false,
+    }
+}
+
+/// The database keeps the input indices as `integer`
+fn saturating_i32(index: u128) -> i32 {
+    i32::try_from(index).unwrap_or(i32::MAX)
+}
+
+async fn check(
+    repository: &Repository,
+    epoch: &ClaimedEpoch,
+) -> Result<Option<Divergence>, IndexerError> {
+    let repository = repository.clone();
+    let (first_index, last_index) = (epoch.first_index, epoch.last_index);
+    let counts = tokio::task::spawn_blocking(move || {
+        repository.count_epoch_rows(first_index, last_index)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+    Ok(divergence(epoch, &counts))
+}
+
+fn divergence(
+    epoch: &ClaimedEpoch,
+    counts: &EpochCounts,
+) -> Option<Divergence> {
+    let claimed_inputs =
+        i64::from(epoch.last_index) - i64::from(epoch.first_index) + 1;
+    let indexed_outputs = counts.vouchers + counts.notices;
+    if counts.inputs == claimed_inputs && indexed_outputs == counts.proofs {
+        return None;
+    }
+    Some(Divergence {
+        claimed_inputs,
+        indexed_inputs: counts.inputs,
+        indexed_outputs,
+        indexed_proofs: counts.proofs,
+    })
+}
+
+/// Stores the events of the inputs of the epoch again, returning how many
+/// were read from the broker
+async fn reindex(
+    broker: &mut Broker,
+    dapp_metadata: &DAppMetadata,
+    repository: &Repository,
+    decoder: &Arc<PayloadDecoder>,
+    epoch: &ClaimedEpoch,
+) -> Result<usize, IndexerError> {
+    let in_epoch = |input_index: u64| {
+        i64::from(epoch.first_index) <= input_index as i64
+            && input_index as i64 <= i64::from(epoch.last_index)
+    };
+    let mut restored = 0;
+
+    let inputs_stream = RollupsInputsStream::new(dapp_metadata);
+    let mut last_id = INITIAL_ID.to_owned();
+    while let Some(event) = broker
+        .consume_nonblocking(&inputs_stream, &last_id)
+        .await
+        .context(BrokerSnafu)?
+    {
+        last_id = event.id;
+        let RollupsData::AdvanceStateInput(input) = &event.payload.data else {
+            continue;
+        };
+        if in_epoch(input.metadata.input_index) {
+            let repository = repository.clone();
+            let decoder = decoder.clone();
+            tokio::task::spawn_blocking(move || {
+                store_input(&repository, &decoder, event.payload)
+            })
+            .await
+            .context(JoinSnafu)?
+            .context(RepositorySnafu)?;
+            restored += 1;
+        }
+    }
+
+    let outputs_stream = RollupsOutputsStream::new(dapp_metadata);
+    let mut last_id = INITIAL_ID.to_owned();
+    while let Some(event) = broker
+        .consume_nonblocking(&outputs_stream, &last_id)
+        .await
+        .context(BrokerSnafu)?
+    {
+        last_id = event.id;
+        if in_epoch(output_input_index(&event.payload)) {
+            let repository = repository.clone();
+            tokio::task::spawn_blocking(move || {
+                store_output(&repository, event.payload)
+            })
+            .await
+            .context(JoinSnafu)?
+            .context(RepositorySnafu)?;
+            restored += 1;
+        }
+    }
+    Ok(restored)
+}
+
+fn output_input_index(output: &RollupsOutput) -> u64 {
+    match output {
+        RollupsOutput::AdvanceResult(result) => result.input_index,
+        RollupsOutput::Voucher(voucher) => voucher.input_index,
+        RollupsOutput::Notice(notice) => notice.input_index,
+        RollupsOutput::Report(report) => report.input_index,
+        RollupsOutput::Proof(proof) => proof.input_index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epoch(first_index: i32, last_index: i32) -> ClaimedEpoch {
+        ClaimedEpoch {
+            first_index,
+            last_index,
+            divergent: false,
+        }
+    }
+
+    fn counts(inputs: i64, outputs: i64, proofs: i64) -> EpochCounts {
+        EpochCounts {
+            inputs,
+            vouchers: outputs,
+            notices: 0,
+            proofs,
+        }
+    }
+
+    #[test]
+    fn it_accepts_consistent_epochs() {
+        assert_eq!(divergence(&epoch(5, 9), &counts(5, 3, 3)), None);
+        assert_eq!(divergence(&epoch(0, 0), &counts(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn it_reports_missing_inputs() {
+        assert_eq!(
+            divergence(&epoch(5, 9), &counts(4, 3, 3)),
+            Some(Divergence {
+                claimed_inputs: 5,
+                indexed_inputs: 4,
+                indexed_outputs: 3,
+                indexed_proofs: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn it_reports_missing_outputs_and_proofs() {
+        assert!(divergence(&epoch(5, 9), &counts(5, 2, 3)).is_some());
+        assert!(divergence(&epoch(5, 9), &counts(5, 3, 2)).is_some());
+    }
+
+    #[test]
+    fn it_saturates_indices_beyond_the_database_range() {
+        let claim = Claim {
+            epoch_hash: [0; 32],
+            first_index: 0,
+            last_index: u128::MAX,
+        };
+        assert_eq!(claimed_epoch(&claim).last_index, i32::MAX);
+    }
+}
@@ -0,0 +1,270 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use redacted::Url;
+use rollups_data::{Input, Notice, Proof, Report, Repository, Voucher};
+use rollups_events::DAppMetadata;
+use serde::de::DeserializeOwned;
+use snafu::{ensure, OptionExt, ResultExt};
+
+use super::rows::{InputRow, NoticeRow, ProofRow, ReportRow, VoucherRow};
+use super::validate::validate_snapshot;
+use super::{verify_file, verify_manifest, Manifest, ManifestFile, Table};
+use crate::error::{
+    IndexerError, JoinSnafu, MigrationsSnafu, MissingSnapshotTableSnafu,
+    RepositorySnafu, SnapshotDownloadSnafu, SnapshotMirrorMismatchSnafu,
+    SnapshotRejectedSnafu, SnapshotRowSnafu, SnapshotUrlSnafu,
+};
+use crate::BootstrapConfig;
+
+/// Fill the database of a mirror reader node from a published snapshot.
+///
+/// The manifest is only read once its signature is checked against the
+/// trusted signer, and must be of the chain and DApp of the mirror. Each file
+/// is checked against the manifest and every row is parsed. With a
+/// validation config, the inputs are also checked against the chain, so
+/// a snapshot the trusted signer got wrong is refused as well. Nothing is
+/// stored until every check passes, and then every row is stored in a single
+/// transaction. Rows already in the database are kept, so a mirror can be
+/// brought up to date by bootstrapping again from a newer snapshot; only the
+/// statuses of the inputs are updated.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn bootstrap_snapshot(
+    config: BootstrapConfig,
+) -> Result<Manifest, IndexerError> {
+    let client = reqwest::Client::new();
+    tracing::info!(url = %config.from_url, "downloading snapshot manifest");
+    let manifest = download(&client, &config.from_url).await?;
+    let signature = download(&client, &signature_url(&config.from_url)).await?;
+    let manifest =
+        verify_manifest(&manifest, &signature, config.trusted_signer)?;
+    tracing::info!(
+        chain_id = manifest.chain_id,
+        dapp_address = manifest.dapp_address,
+        last_input_index = manifest.last_input_index,
+        "verified the signature of the snapshot"
+    );
+    check_mirror(&manifest, &config.dapp_metadata)?;
+
+    let mut rows = Rows::default();
+    for table in Table::ALL {
+        let file = manifest
+            .files
+            .iter()
+            .find(|file| file.table == table)
+            .context(MissingSnapshotTableSnafu {
+                table: table.name(),
+            })?;
+        let url = config
+            .from_url
+            .join(&file.path)
+            .context(SnapshotUrlSnafu { url: &file.path })?;
+        let contents = download(&client, &url).await?;
+        verify_file(file, &contents)?;
+        rows.parse(file, &contents)?;
+    }
+
+    if let Some(validation) = &config.validation {
+        tracing::info!(
+            inputs = rows.inputs.len(),
+            "validating the snapshot against the chain"
+        );
+        let report =
+            validate_snapshot(validation, &manifest, &rows.inputs).await?;
+        ensure!(report.passed(), SnapshotRejectedSnafu { report });
+    }
+
+    let endpoint = config.repository_config.endpoint();
+    if config.skip_migrations {
+        tracing::info!("checking database migrations");
+        rollups_data::check_migrations(&endpoint).context(MigrationsSnafu)?;
+    } else {
+        tracing::info!("running database migrations");
+        rollups_data::run_migrations(&endpoint).context(MigrationsSnafu)?;
+    }
+    let repository = tokio::task::spawn_blocking(|| {
+        Repository::new(config.repository_config)
+    })
+    .await
+    .context(JoinSnafu)?
+    .context(RepositorySnafu)?;
+
+    tracing::info!(
+        inputs = rows.inputs.len(),
+        vouchers = rows.vouchers.len(),
+        notices = rows.notices.len(),
+        reports = rows.reports.len(),
+        proofs = rows.proofs.len(),
+        "restoring the snapshot"
+    );
+    tokio::task::spawn_blocking(move || rows.restore(&repository))
+        .await
+        .context(JoinSnafu)??;
+    tracing::info!("restored the snapshot");
+    Ok(manifest)
+}
+
+/// Checks that the snapshot is of the chain and DApp of the mirror, since
+/// the trusted signer may publish the snapshots of several DApps
+fn check_mirror(
+    manifest: &Manifest,
+    dapp_metadata: &DAppMetadata,
+) -> Result<(), IndexerError> {
+    let expected_dapp_address =
+        format!("0x{}", hex::encode(dapp_metadata.dapp_address.inner()));
+    ensure!(
+        manifest.chain_id == dapp_metadata.chain_id
+            && manifest
+                .dapp_address
+                .eq_ignore_ascii_case(&expected_dapp_address),
+        SnapshotMirrorMismatchSnafu {
+            chain_id: manifest.chain_id,
+            dapp_address: &manifest.dapp_address,
+            expected_chain_id: dapp_metadata.chain_id,
+            expected_dapp_address,
+        }
+    );
+    Ok(())
+}
+
+/// The signature is stored next to the manifest
+fn signature_url(manifest_url: &Url) -> Url {
+    let mut url = manifest_url.clone();
+    url.set_path(&format!("{}.sig", manifest_url.path()));
+    url
+}
+
+async fn download(
+    client: &reqwest::Client,
+    url: &Url,
+) -> Result<Vec<u8>, IndexerError> {
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context(SnapshotDownloadSnafu { url: url.as_str() })?;
+    let bytes = response
+        .bytes()
+        .await
+        .context(SnapshotDownloadSnafu { url: url.as_str() })?;
+    Ok(bytes.to_vec())
+}
+
+/// Rows of a snapshot, parsed before any of them is stored
+#[derive(Default)]
+struct Rows {
+    inputs: Vec<Input>,
+    vouchers: Vec<Voucher>,
+    notices: Vec<Notice>,
+    reports: Vec<Report>,
+    proofs: Vec<Proof>,
+}
+
+impl Rows {
+    fn parse(
+        &mut self,
+        file: &ManifestFile,
+        contents: &[u8],
+    ) -> Result<(), IndexerError> {
+        let lines = contents
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty());
+        for (index, line) in lines.enumerate() {
+            let line_number = index as u64 + 1;
+            match file.table {
+                Table::Inputs => {
+                    let InputRow(input) = parse(file, line_number, line)?;
+                    self.inputs.push(input);
+                }
+                Table::Vouchers => {
+                    let VoucherRow(voucher) = parse(file, line_number, line)?;
+                    self.vouchers.push(voucher);
+                }
+                Table::Notices => {
+                    let NoticeRow(notice) = parse(file, line_number, line)?;
+                    self.notices.push(notice);
+                }
+                Table::Reports => {
+                    let ReportRow(report) = parse(file, line_number, line)?;
+                    self.reports.push(report);
+                }
+                Table::Proofs => {
+                    let ProofRow(proof) = parse(file, line_number, line)?;
+                    self.proofs.push(proof);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(&self, repository: &Repository) -> Result<(), IndexerError> {
+        repository
+            .restore_snapshot(
+                &self.inputs,
+                &self.vouchers,
+                &self.notices,
+                &self.reports,
+                &self.proofs,
+            )
+            .context(RepositorySnafu)
+    }
+}
+
+fn parse<T: DeserializeOwned>(
+    file: &ManifestFile,
+    line_number: u64,
+    line: &[u8],
+) -> Result<T, IndexerError> {
+    serde_json::from_slice(line).context(SnapshotRowSnafu {
+        path: &file.path,
+        line: line_number,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::MANIFEST_VERSION;
+
+    #[test]
+    fn it_finds_the_signature_next_to_the_manifest() {
+        let url =
+            Url::parse("https://mirror.example/dapp/latest.json").unwrap();
+        assert_eq!(
+            signature_url(&url).as_str(),
+            "https://mirror.example/dapp/latest.json.sig"
+        );
+        assert_eq!(
+            url.join("snapshot-1700000000/inputs.jsonl")
+                .unwrap()
+                .as_str(),
+            "https://mirror.example/dapp/snapshot-1700000000/inputs.jsonl"
+        );
+    }
+
+    #[test]
+    fn it_only_restores_snapshots_of_the_mirror() {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            chain_id: 31337,
+            dapp_address: format!("0x{}", hex::encode_upper([0xfa; 20])),
+            created_at: 1700000000,
+            last_input_index: None,
+            files: vec![],
+        };
+        let dapp_metadata = |chain_id, byte| DAppMetadata {
+            chain_id,
+            dapp_address: rollups_events::Address::new([byte; 20]),
+        };
+        assert!(check_mirror(&manifest, &dapp_metadata(31337, 0xfa)).is_ok());
+        assert!(matches!(
+            check_mirror(&manifest, &dapp_metadata(1, 0xfa)),
+            Err(IndexerError::SnapshotMirrorMismatchError { .. })
+        ));
+        assert!(matches!(
+            check_mirror(&manifest, &dapp_metadata(31337, 0xfb)),
+            Err(IndexerError::SnapshotMirrorMismatchError { .. })
+        ));
+    }
+}
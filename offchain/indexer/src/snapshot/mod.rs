@@ -0,0 +1,231 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Read-only snapshots of the indexed data, published so community members
+//! can bootstrap mirror reader nodes without access to the broker.
+//!
+//! A snapshot is one JSON-lines file per table, described by a manifest with
+//! the size and keccak256 of each file. The publisher signs the bytes of the
+//! manifest with an Ethereum key (EIP-191) and stores the signature next to
+//! it, so mirrors only have to trust the address of the publisher:
+//!
+//! ```text
+//! latest.json                 manifest of the latest snapshot
+//! latest.json.sig             `0x`-prefixed signature of the manifest
+//! snapshot-<time>.json        manifest of each snapshot
+//! snapshot-<time>.json.sig
+//! snapshot-<time>/inputs.jsonl
+//! snapshot-<time>/vouchers.jsonl
+//! ...
+//! ```
+//!
+//! The paths in a manifest are relative to it, so the snapshots can be
+//! served by any HTTP server, public bucket or IPFS gateway.
+
+use ethers::types::{Signature, H160};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use snafu::{ensure, ResultExt};
+
+use crate::error::{
+    IndexerError, InvalidSnapshotManifestSnafu, UnsupportedSnapshotVersionSnafu,
+};
+
+mod bootstrap;
+mod publish;
+mod rows;
+mod store;
+mod validate;
+
+pub use bootstrap::bootstrap_snapshot;
+pub(crate) use publish::start;
+pub use publish::SnapshotConfig;
+pub use validate::{ValidationCheck, ValidationConfig, ValidationReport};
+
+/// Version of the layout of the snapshots
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Describes a snapshot; its bytes are what the publisher signs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub chain_id: u64,
+    pub dapp_address: String,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Last input in the snapshot, unset if the DApp received none
+    pub last_input_index: Option<i32>,
+    pub files: Vec<ManifestFile>,
+}
+
+/// File of a snapshot with the rows of a table
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub table: Table,
+    /// Relative to the manifest
+    pub path: String,
+    pub rows: u64,
+    pub size: u64,
+    /// `0x`-prefixed keccak256 of the file
+    pub keccak256: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Table {
+    Inputs,
+    Vouchers,
+    Notices,
+    Reports,
+    Proofs,
+}
+
+impl Table {
+    /// Every table, in the order they are restored, since the outputs
+    /// reference the inputs
+    pub const ALL: [Table; 5] = [
+        Table::Inputs,
+        Table::Vouchers,
+        Table::Notices,
+        Table::Reports,
+        Table::Proofs,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Table::Inputs => "inputs",
+            Table::Vouchers => "vouchers",
+            Table::Notices => "notices",
+            Table::Reports => "reports",
+            Table::Proofs => "proofs",
+        }
+    }
+}
+
+fn keccak256_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(Keccak256::digest(bytes)))
+}
+
+/// Checks that the manifest was signed by the trusted signer before
+/// reading it
+fn verify_manifest(
+    manifest: &[u8],
+    signature: &[u8],
+    trusted_signer: H160,
+) -> Result<Manifest, IndexerError> {
+    let invalid =
+        |reason: String| IndexerError::InvalidSnapshotSignatureError { reason };
+    let signature = String::from_utf8_lossy(signature)
+        .trim()
+        .parse::<Signature>()
+        .map_err(|e| invalid(e.to_string()))?;
+    signature
+        .verify(manifest, trusted_signer)
+        .map_err(|e| invalid(e.to_string()))?;
+    let manifest: Manifest = serde_json::from_slice(manifest)
+        .context(InvalidSnapshotManifestSnafu)?;
+    ensure!(
+        manifest.version == MANIFEST_VERSION,
+        UnsupportedSnapshotVersionSnafu {
+            version: manifest.version
+        }
+    );
+    Ok(manifest)
+}
+
+/// Checks that the downloaded file is the one described by the manifest
+fn verify_file(
+    file: &ManifestFile,
+    contents: &[u8],
+) -> Result<(), IndexerError> {
+    let mismatch = |reason: &str| IndexerError::SnapshotFileMismatchError {
+        path: file.path.clone(),
+        reason: reason.to_owned(),
+    };
+    if contents.len() as u64 != file.size {
+        return Err(mismatch("size differs from the manifest"));
+    }
+    if keccak256_hex(contents) != file.keccak256 {
+        return Err(mismatch("keccak256 differs from the manifest"));
+    }
+    let rows = contents.iter().filter(|&&byte| byte == b'\n').count();
+    if rows as u64 != file.rows {
+        return Err(mismatch("row count differs from the manifest"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    fn manifest() -> Manifest {
+        let contents = b"{}\n{}\n";
+        Manifest {
+            version: MANIFEST_VERSION,
+            chain_id: 31337,
+            dapp_address: format!("0x{}", hex::encode([0xfa; 20])),
+            created_at: 1700000000,
+            last_input_index: Some(1),
+            files: vec![ManifestFile {
+                table: Table::Inputs,
+                path: "snapshot-1700000000/inputs.jsonl".to_owned(),
+                rows: 2,
+                size: contents.len() as u64,
+                keccak256: keccak256_hex(contents),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn it_accepts_manifests_of_the_trusted_signer() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let json = serde_json::to_vec_pretty(&manifest()).unwrap();
+        let signature = wallet.sign_message(&json).await.unwrap();
+        let signature = format!("0x{}\n", signature);
+        assert_eq!(
+            verify_manifest(&json, signature.as_bytes(), wallet.address())
+                .unwrap(),
+            manifest()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_tampered_or_untrusted_manifests() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let json = serde_json::to_vec_pretty(&manifest()).unwrap();
+        let signature = wallet.sign_message(&json).await.unwrap().to_string();
+
+        let other = LocalWallet::new(&mut rand::thread_rng());
+        assert!(matches!(
+            verify_manifest(&json, signature.as_bytes(), other.address()),
+            Err(IndexerError::InvalidSnapshotSignatureError { .. })
+        ));
+
+        let tampered = serde_json::to_vec_pretty(&Manifest {
+            last_input_index: Some(2),
+            ..manifest()
+        })
+        .unwrap();
+        assert!(matches!(
+            verify_manifest(&tampered, signature.as_bytes(), wallet.address()),
+            Err(IndexerError::InvalidSnapshotSignatureError { .. })
+        ));
+    }
+
+    #[test]
+    fn it_checks_the_files_against_the_manifest() {
+        let manifest = manifest();
+        let file = &manifest.files[0];
+        assert!(verify_file(file, b"{}\n{}\n").is_ok());
+        assert!(matches!(
+            verify_file(file, b"{}\n{ }\n"),
+            Err(IndexerError::SnapshotFileMismatchError { .. })
+        ));
+        assert!(matches!(
+            verify_file(file, b"{}\n{}\n\n"),
+            Err(IndexerError::SnapshotFileMismatchError { .. })
+        ));
+    }
+}
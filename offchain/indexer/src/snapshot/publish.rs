@@ -0,0 +1,373 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use ethers::signers::{LocalWallet, Signer};
+use redacted::{Redacted, RedactedUrl};
+use rollups_data::Repository;
+use rollups_events::DAppMetadata;
+use scheduler::{JobConfig, Scheduler};
+use serde::Serialize;
+use sha3::{Digest, Keccak256};
+use snafu::ResultExt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::rows::{InputRow, NoticeRow, ProofRow, ReportRow, VoucherRow};
+use super::store::UploadTarget;
+use super::{Manifest, ManifestFile, Table, MANIFEST_VERSION};
+use crate::error::{
+    IndexerError, JoinSnafu, RepositorySnafu, SnapshotIoSnafu,
+    SnapshotRowSnafu, SnapshotSignSnafu,
+};
+
+/// Manifest of the latest snapshot, which mirrors bootstrap from
+const LATEST_MANIFEST: &str = "latest.json";
+
+/// Prefix of the name of each snapshot
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+
+/// Inputs whose rows are read from the database at a time
+const BATCH_SIZE: i32 = 1000;
+
+/// How the snapshots are published
+#[derive(Debug)]
+pub struct SnapshotConfig {
+    pub interval: Duration,
+    /// Directory where the snapshots are written before being uploaded
+    pub dir: PathBuf,
+    pub upload_url: Option<RedactedUrl>,
+    pub signer_private_key: Redacted<String>,
+    /// Snapshots kept in the directory; uploaded ones are left to the
+    /// retention rules of the bucket
+    pub keep: usize,
+}
+
+/// Periodically dumps the indexed data into a signed snapshot, uploading it
+/// if there's a target.
+///
+/// Each snapshot has the inputs indexed when it starts, along with the
+/// outputs and proofs stored for them by then; outputs that arrive later go
+/// in the next snapshot.
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn start(
+    config: SnapshotConfig,
+    repository: Repository,
+    dapp_metadata: DAppMetadata,
+    scheduler: Scheduler,
+) -> Result<(), IndexerError> {
+    let wallet: LocalWallet = config
+        .signer_private_key
+        .inner()
+        .trim()
+        .parse()
+        .map_err(|_| IndexerError::InvalidSnapshotSignerError)?;
+    let target = config.upload_url.as_ref().map(UploadTarget::new);
+    tracing::info!(
+        signer = ?wallet.address(),
+        dir = %config.dir.display(),
+        "publishing signed snapshots"
+    );
+
+    let job = || {
+        let config = &config;
+        let repository = &repository;
+        let dapp_metadata = &dapp_metadata;
+        let wallet = &wallet;
+        let target = &target;
+        async move {
+            let (name, manifest) =
+                publish(&config.dir, repository, dapp_metadata, wallet).await?;
+            tracing::info!(
+                name,
+                last_input_index = manifest.last_input_index,
+                "wrote snapshot"
+            );
+            if let Some(target) = target {
+                upload(target, &config.dir, &name, &manifest).await?;
+                tracing::info!(name, "uploaded snapshot");
+            }
+            prune(&config.dir, config.keep)
+        }
+    };
+    scheduler
+        .run(
+            "snapshot_publishing",
+            &JobConfig::every(config.interval),
+            job,
+        )
+        .await;
+    Ok(())
+}
+
+/// Writes a snapshot of the database to the directory, returning its name
+/// and manifest
+async fn publish(
+    dir: &Path,
+    repository: &Repository,
+    dapp_metadata: &DAppMetadata,
+    wallet: &LocalWallet,
+) -> Result<(String, Manifest), IndexerError> {
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = format!("{}{}", SNAPSHOT_PREFIX, created_at);
+
+    let (last_input_index, files) = {
+        let repository = repository.clone();
+        let dir = dir.to_owned();
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || {
+            let last_input_index =
+                repository.get_last_input_index().context(RepositorySnafu)?;
+            let files = dump(&repository, &dir, &name, last_input_index)?;
+            Ok::<_, IndexerError>((last_input_index, files))
+        })
+        .await
+        .context(JoinSnafu)??
+    };
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        chain_id: dapp_metadata.chain_id,
+        dapp_address: format!(
+            "0x{}",
+            hex::encode(dapp_metadata.dapp_address.inner())
+        ),
+        created_at,
+        last_input_index,
+        files,
+    };
+
+    let json = serde_json::to_vec_pretty(&manifest)
+        .expect("manifests should always serialize");
+    let signature = wallet
+        .sign_message(&json)
+        .await
+        .context(SnapshotSignSnafu)?;
+    let signature = format!("0x{}\n", signature);
+    // The latest manifest goes last, so it never points to missing files
+    for manifest_name in [format!("{}.json", name), LATEST_MANIFEST.to_owned()]
+    {
+        write_atomically(&dir.join(&manifest_name), &json)?;
+        write_atomically(
+            &dir.join(format!("{}.sig", manifest_name)),
+            signature.as_bytes(),
+        )?;
+    }
+    Ok((name, manifest))
+}
+
+fn dump(
+    repository: &Repository,
+    dir: &Path,
+    name: &str,
+    last_input_index: Option<i32>,
+) -> Result<Vec<ManifestFile>, IndexerError> {
+    let snapshot_dir = dir.join(name);
+    fs::create_dir_all(&snapshot_dir).context(SnapshotIoSnafu {
+        path: &snapshot_dir,
+    })?;
+    let mut files = Table::ALL
+        .into_iter()
+        .map(|table| DumpFile::create(&snapshot_dir, name, table))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [inputs, vouchers, notices, reports, proofs] = files.as_mut_slice()
+    else {
+        unreachable!("there's a file for each table");
+    };
+
+    if let Some(last_input_index) = last_input_index {
+        for first in (0..=last_input_index).step_by(BATCH_SIZE as usize) {
+            let last =
+                first.saturating_add(BATCH_SIZE - 1).min(last_input_index);
+            let rows = repository
+                .get_input_range_rows(first, last)
+                .context(RepositorySnafu)?;
+            for input in rows.inputs {
+                inputs.write(&InputRow(input))?;
+            }
+            for voucher in rows.vouchers {
+                vouchers.write(&VoucherRow(voucher))?;
+            }
+            for notice in rows.notices {
+                notices.write(&NoticeRow(notice))?;
+            }
+            for report in rows.reports {
+                reports.write(&ReportRow(report))?;
+            }
+            for proof in rows.proofs {
+                proofs.write(&ProofRow(proof))?;
+            }
+        }
+    }
+
+    files.into_iter().map(DumpFile::finish).collect()
+}
+
+/// JSON-lines file with the rows of a table, hashed as it is written
+struct DumpFile {
+    table: Table,
+    path: PathBuf,
+    /// Relative to the manifest
+    manifest_path: String,
+    writer: BufWriter<File>,
+    hasher: Keccak256,
+    rows: u64,
+    size: u64,
+}
+
+impl DumpFile {
+    fn create(
+        dir: &Path,
+        name: &str,
+        table: Table,
+    ) -> Result<Self, IndexerError> {
+        let file_name = format!("{}.jsonl", table.name());
+        let path = dir.join(&file_name);
+        let file =
+            File::create(&path).context(SnapshotIoSnafu { path: &path })?;
+        Ok(Self {
+            table,
+            path,
+            manifest_path: format!("{}/{}", name, file_name),
+            writer: BufWriter::new(file),
+            hasher: Keccak256::new(),
+            rows: 0,
+            size: 0,
+        })
+    }
+
+    fn write<T: Serialize>(&mut self, row: &T) -> Result<(), IndexerError> {
+        let mut line = serde_json::to_vec(row).context(SnapshotRowSnafu {
+            path: &self.manifest_path,
+            line: self.rows + 1,
+        })?;
+        line.push(b'\n');
+        self.hasher.update(&line);
+        self.writer
+            .write_all(&line)
+            .context(SnapshotIoSnafu { path: &self.path })?;
+        self.rows += 1;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<ManifestFile, IndexerError> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_all())
+            .context(SnapshotIoSnafu { path: &self.path })?;
+        Ok(ManifestFile {
+            table: self.table,
+            path: self.manifest_path,
+            rows: self.rows,
+            size: self.size,
+            keccak256: format!("0x{}", hex::encode(self.hasher.finalize())),
+        })
+    }
+}
+
+/// Writes the file through a temporary one, so readers of the directory
+/// never see it half written
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), IndexerError> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, path))
+        .context(SnapshotIoSnafu { path })
+}
+
+/// Uploads the files of the snapshot, then its manifests
+async fn upload(
+    target: &UploadTarget,
+    dir: &Path,
+    name: &str,
+    manifest: &Manifest,
+) -> Result<(), IndexerError> {
+    let mut paths: Vec<String> = manifest
+        .files
+        .iter()
+        .map(|file| file.path.clone())
+        .collect();
+    for manifest_name in [format!("{}.json", name), LATEST_MANIFEST.to_owned()]
+    {
+        paths.push(format!("{}.sig", manifest_name));
+        paths.push(manifest_name);
+    }
+    for path in paths {
+        let local_path = dir.join(&path);
+        let body = tokio::fs::read(&local_path)
+            .await
+            .context(SnapshotIoSnafu { path: local_path })?;
+        target.put(&path, body).await?;
+    }
+    Ok(())
+}
+
+/// Removes the oldest snapshots from the directory, keeping the latest
+fn prune(dir: &Path, keep: usize) -> Result<(), IndexerError> {
+    let entries = fs::read_dir(dir).context(SnapshotIoSnafu { path: dir })?;
+    let mut snapshots: Vec<(u64, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let created_at =
+                name.strip_prefix(SNAPSHOT_PREFIX)?.parse().ok()?;
+            Some((created_at, name))
+        })
+        .collect();
+    snapshots.sort();
+    let stale = snapshots.len().saturating_sub(keep.max(1));
+    for (_, name) in snapshots.into_iter().take(stale) {
+        let path = dir.join(&name);
+        fs::remove_dir_all(&path).context(SnapshotIoSnafu { path })?;
+        for manifest_name in
+            [format!("{}.json", name), format!("{}.json.sig", name)]
+        {
+            let path = dir.join(manifest_name);
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).context(SnapshotIoSnafu { path });
+                }
+                _ => {}
+            }
+        }
+        tracing::info!(name, "removed old snapshot");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_the_latest_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        for created_at in [900, 1000, 1100] {
+            let name = format!("{}{}", SNAPSHOT_PREFIX, created_at);
+            fs::create_dir(dir.path().join(&name)).unwrap();
+            fs::write(dir.path().join(format!("{}.json", name)), "{}").unwrap();
+        }
+        fs::write(dir.path().join(LATEST_MANIFEST), "{}").unwrap();
+
+        prune(dir.path(), 2).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "latest.json",
+                "snapshot-1000",
+                "snapshot-1000.json",
+                "snapshot-1100",
+                "snapshot-1100.json",
+            ]
+        );
+    }
+}
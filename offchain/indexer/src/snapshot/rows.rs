@@ -0,0 +1,251 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Rows of the snapshots, one JSON object per line, with the bytes encoded as
+//! `0x`-prefixed hex and the timestamps as Unix seconds, so the dumps can be
+//! read without this crate.
+
+use rollups_data::{
+    CompletionStatus, Input, Notice, OutputEnum, Proof, Report, Voucher,
+};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+#[derive(Serialize, Deserialize)]
+pub struct InputRow(#[serde(with = "InputDef")] pub Input);
+
+#[derive(Serialize, Deserialize)]
+pub struct VoucherRow(#[serde(with = "VoucherDef")] pub Voucher);
+
+#[derive(Serialize, Deserialize)]
+pub struct NoticeRow(#[serde(with = "NoticeDef")] pub Notice);
+
+#[derive(Serialize, Deserialize)]
+pub struct ReportRow(#[serde(with = "ReportDef")] pub Report);
+
+#[derive(Serialize, Deserialize)]
+pub struct ProofRow(#[serde(with = "ProofDef")] pub Proof);
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CompletionStatus")]
+enum CompletionStatusDef {
+    Unprocessed,
+    Accepted,
+    Rejected,
+    Exception,
+    MachineHalted,
+    CycleLimitExceeded,
+    TimeLimitExceeded,
+    PayloadLengthLimitExceeded,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OutputEnum", rename_all = "lowercase")]
+enum OutputEnumDef {
+    Voucher,
+    Notice,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Input")]
+struct InputDef {
+    index: i32,
+    #[serde(with = "hex_bytes")]
+    msg_sender: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    tx_hash: Vec<u8>,
+    block_number: i64,
+    #[serde(with = "unix_seconds")]
+    timestamp: SystemTime,
+    #[serde(with = "hex_bytes")]
+    payload: Vec<u8>,
+    #[serde(with = "CompletionStatusDef")]
+    status: CompletionStatus,
+    decoded_payload: Option<String>,
+    payload_codec: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Voucher")]
+struct VoucherDef {
+    input_index: i32,
+    index: i32,
+    #[serde(with = "hex_bytes")]
+    destination: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Notice")]
+struct NoticeDef {
+    input_index: i32,
+    index: i32,
+    #[serde(with = "hex_bytes")]
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Report")]
+struct ReportDef {
+    input_index: i32,
+    index: i32,
+    #[serde(with = "hex_bytes")]
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Proof")]
+struct ProofDef {
+    input_index: i32,
+    output_index: i32,
+    #[serde(with = "OutputEnumDef")]
+    output_enum: OutputEnum,
+    validity_input_index_within_epoch: i32,
+    validity_output_index_within_input: i32,
+    #[serde(with = "hex_bytes")]
+    validity_output_hashes_root_hash: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    validity_vouchers_epoch_root_hash: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    validity_notices_epoch_root_hash: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    validity_machine_state_hash: Vec<u8>,
+    #[serde(with = "hex_siblings")]
+    validity_output_hash_in_output_hashes_siblings: Vec<Option<Vec<u8>>>,
+    #[serde(with = "hex_siblings")]
+    validity_output_hashes_in_epoch_siblings: Vec<Option<Vec<u8>>>,
+    #[serde(with = "hex_bytes")]
+    context: Vec<u8>,
+}
+
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        decode(&hex).map_err(D::Error::custom)
+    }
+
+    pub fn decode(hex: &str) -> Result<Vec<u8>, hex::FromHexError> {
+        hex::decode(hex.strip_prefix("0x").unwrap_or(hex))
+    }
+}
+
+mod hex_siblings {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        siblings: &[Option<Vec<u8>>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(siblings.iter().map(|sibling| {
+            sibling
+                .as_ref()
+                .map(|bytes| format!("0x{}", hex::encode(bytes)))
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Option<Vec<u8>>>, D::Error> {
+        Vec::<Option<String>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|sibling| sibling.map(|hex| super::hex_bytes::decode(&hex)))
+            .map(Option::transpose)
+            .collect::<Result<_, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+mod unix_seconds {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &SystemTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let seconds = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_err(serde::ser::Error::custom)?
+            .as_secs();
+        serializer.serialize_u64(seconds)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SystemTime, D::Error> {
+        let seconds = u64::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(Duration::from_secs(seconds))
+            .ok_or_else(|| D::Error::custom("timestamp out of range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn it_encodes_the_bytes_as_hex() {
+        let row = ReportRow(Report {
+            input_index: 1,
+            index: 2,
+            payload: vec![0xca, 0xfe],
+        });
+        assert_eq!(
+            serde_json::to_string(&row).unwrap(),
+            r#"{"input_index":1,"index":2,"payload":"0xcafe"}"#
+        );
+    }
+
+    #[test]
+    fn it_reads_back_the_rows() {
+        let input = Input {
+            index: 3,
+            msg_sender: vec![1; 20],
+            tx_hash: vec![2; 32],
+            block_number: 10,
+            timestamp: UNIX_EPOCH + Duration::from_secs(1700000000),
+            payload: b"hello".to_vec(),
+            status: CompletionStatus::Accepted,
+            decoded_payload: Some("\"hello\"".to_owned()),
+            payload_codec: Some("json".to_owned()),
+        };
+        let json = serde_json::to_string(&InputRow(input.clone())).unwrap();
+        let row: InputRow = serde_json::from_str(&json).unwrap();
+        assert_eq!(row.0, input);
+
+        let proof = Proof {
+            input_index: 3,
+            output_index: 0,
+            output_enum: OutputEnum::Notice,
+            validity_input_index_within_epoch: 1,
+            validity_output_index_within_input: 0,
+            validity_output_hashes_root_hash: vec![1; 32],
+            validity_vouchers_epoch_root_hash: vec![2; 32],
+            validity_notices_epoch_root_hash: vec![3; 32],
+            validity_machine_state_hash: vec![4; 32],
+            validity_output_hash_in_output_hashes_siblings: vec![
+                Some(vec![5; 32]),
+                None,
+            ],
+            validity_output_hashes_in_epoch_siblings: vec![Some(vec![6; 32])],
+            context: vec![],
+        };
+        let json = serde_json::to_string(&ProofRow(proof.clone())).unwrap();
+        let row: ProofRow = serde_json::from_str(&json).unwrap();
+        assert_eq!(row.0, proof);
+    }
+}
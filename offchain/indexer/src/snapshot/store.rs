@@ -0,0 +1,118 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use redacted::{RedactedUrl, Url};
+use rusoto_core::Region;
+use rusoto_s3::{PutObjectRequest, S3Client, S3};
+use snafu::ResultExt;
+
+use crate::error::{
+    IndexerError, SnapshotHttpUploadSnafu, SnapshotS3UploadSnafu,
+    SnapshotUrlSnafu,
+};
+
+/// Where the published snapshots are uploaded to
+pub enum UploadTarget {
+    /// Any server that accepts `PUT` requests, such as a WebDAV server or
+    /// a bucket behind an authenticating proxy. Credentials in the URL are
+    /// sent with basic authentication
+    Http {
+        client: reqwest::Client,
+        base_url: RedactedUrl,
+    },
+    /// An AWS S3 bucket, or one compatible with it, with the credentials
+    /// and region taken from the usual AWS variables
+    S3 {
+        client: S3Client,
+        bucket: String,
+        prefix: String,
+    },
+}
+
+impl UploadTarget {
+    pub fn new(url: &RedactedUrl) -> Self {
+        let url = url.inner();
+        if url.scheme() == "s3" {
+            let bucket = url.host_str().unwrap_or_default().to_owned();
+            let prefix = url.path().trim_matches('/').to_owned();
+            return UploadTarget::S3 {
+                client: S3Client::new(Region::default()),
+                bucket,
+                prefix,
+            };
+        }
+        // Joined paths replace the last segment unless it ends with a slash
+        let mut base_url = url.clone();
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        UploadTarget::Http {
+            client: reqwest::Client::new(),
+            base_url: RedactedUrl::new(base_url),
+        }
+    }
+
+    /// Uploads the file to the path, relative to the base of the target
+    pub async fn put(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+    ) -> Result<(), IndexerError> {
+        match self {
+            UploadTarget::Http { client, base_url } => {
+                let base_url = base_url.inner();
+                let url = base_url.join(path).context(SnapshotUrlSnafu {
+                    url: path.to_owned(),
+                })?;
+                let mut request = client.put(without_credentials(url));
+                if !base_url.username().is_empty() {
+                    request = request
+                        .basic_auth(base_url.username(), base_url.password());
+                }
+                request
+                    .body(body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .context(SnapshotHttpUploadSnafu { path })?;
+            }
+            UploadTarget::S3 {
+                client,
+                bucket,
+                prefix,
+            } => {
+                let key = match prefix.as_str() {
+                    "" => path.to_owned(),
+                    prefix => format!("{}/{}", prefix, path),
+                };
+                client
+                    .put_object(PutObjectRequest {
+                        bucket: bucket.clone(),
+                        key,
+                        body: Some(body.into()),
+                        content_type: Some(content_type(path).to_owned()),
+                        ..Default::default()
+                    })
+                    .await
+                    .context(SnapshotS3UploadSnafu { path })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn without_credentials(mut url: Url) -> Url {
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url
+}
+
+fn content_type(path: &str) -> &'static str {
+    if path.ends_with(".json") {
+        "application/json"
+    } else if path.ends_with(".jsonl") {
+        "application/jsonl"
+    } else {
+        "text/plain"
+    }
+}
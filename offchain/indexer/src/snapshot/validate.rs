@@ -0,0 +1,526 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use contracts::cartesi_dapp::CartesiDApp;
+use contracts::cartesi_dapp_factory::CartesiDAppFactory;
+use contracts::input_box::{InputAddedFilter, InputBox};
+use ethers::abi::RawLog;
+use ethers::contract::EthEvent;
+use ethers::providers::Middleware;
+use ethers::types::{Log, H160, H256, U256};
+use http_provider::{HttpClient, HttpClientConfig, HttpProvider};
+use rollups_data::{Input, Url};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::Manifest;
+use crate::error::{
+    ContractSnafu, HttpClientSnafu, IndexerError, ProviderSnafu,
+};
+
+/// How a snapshot is checked against the chain before it is restored
+#[derive(Debug)]
+pub struct ValidationConfig {
+    pub http_client_config: HttpClientConfig,
+    pub provider_http_endpoint: Url,
+    pub input_box_address: H160,
+    /// Factory that deployed the DApp; without it, only the code of the DApp
+    /// is checked
+    pub dapp_factory_address: Option<H160>,
+    /// Inputs checked against the receipts of their transactions
+    pub samples: usize,
+    /// Blocks up to the last input of the snapshot whose inputs are read
+    /// from the chain again and compared with the snapshot
+    pub window_blocks: u64,
+}
+
+/// Outcome of each check of a snapshot
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub checks: Vec<ValidationCheck>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ValidationCheck {
+    pub name: &'static str,
+    /// Empty if the check passed
+    pub failures: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.failures.is_empty())
+    }
+
+    fn push(&mut self, name: &'static str, failures: Vec<String>) {
+        if failures.is_empty() {
+            tracing::info!(check = name, "snapshot passed check");
+        } else {
+            tracing::warn!(
+                check = name,
+                failures = failures.len(),
+                "snapshot failed check"
+            );
+        }
+        self.checks.push(ValidationCheck { name, failures });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            if check.failures.is_empty() {
+                writeln!(f, "{}: ok", check.name)?;
+            } else {
+                writeln!(
+                    f,
+                    "{}: {} failure(s)",
+                    check.name,
+                    check.failures.len()
+                )?;
+                for failure in &check.failures {
+                    writeln!(f, "  - {}", failure)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Input as read from the chain
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ChainInput {
+    index: U256,
+    msg_sender: H160,
+    tx_hash: H256,
+    block_number: u64,
+    payload: Vec<u8>,
+}
+
+/// Checks the inputs of a snapshot against the chain, so a snapshot that
+/// was corrupted or forged by its publisher is refused before anything is
+/// stored:
+///
+/// - the chain is the one of the manifest;
+/// - the DApp was created by the factory, with the template hash it has,
+///   before its first input; or, without the factory, it has code;
+/// - the indices of the inputs are contiguous up to the last one;
+/// - sampled inputs match the `InputAdded` events in the receipts of their
+///   transactions;
+/// - the inputs of the last blocks of the snapshot match the ones folded
+///   from the events of those blocks.
+///
+/// The snapshot is still only as complete as the last of those blocks:
+/// inputs older than the window are only checked by sampling.
+pub async fn validate_snapshot(
+    config: &ValidationConfig,
+    manifest: &Manifest,
+    inputs: &[Input],
+) -> Result<ValidationReport, IndexerError> {
+    let http_client =
+        HttpClient::new(&config.http_client_config).context(HttpClientSnafu)?;
+    let provider =
+        Arc::new(http_client.provider(config.provider_http_endpoint.clone()));
+    let dapp_address = manifest.dapp_address.parse::<H160>().ok();
+    let mut report = ValidationReport::default();
+
+    let chain_id = provider.get_chainid().await.context(ProviderSnafu)?;
+    let mut failures = vec![];
+    if chain_id != manifest.chain_id.into() {
+        failures.push(format!(
+            "snapshot is of chain {} but the provider is on chain {}",
+            manifest.chain_id, chain_id
+        ));
+    }
+    report.push("chain", failures);
+
+    let Some(dapp_address) = dapp_address else {
+        report.push(
+            "dapp creation",
+            vec![format!("invalid DApp address `{}`", manifest.dapp_address)],
+        );
+        return Ok(report);
+    };
+    let failures =
+        check_creation(config, &provider, dapp_address, inputs).await?;
+    report.push("dapp creation", failures);
+
+    report.push("input indices", check_indices(manifest, inputs));
+
+    let mut failures = vec![];
+    for position in sample_positions(inputs.len(), config.samples) {
+        let input = &inputs[position];
+        if input.tx_hash.len() != H256::len_bytes() {
+            failures.push(format!(
+                "input {} has an invalid transaction hash",
+                input.index
+            ));
+            continue;
+        }
+        let tx_hash = H256::from_slice(&input.tx_hash);
+        let receipt = provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .context(ProviderSnafu)?;
+        let chain_input = receipt.and_then(|receipt| {
+            let block_number = receipt.block_number?.as_u64();
+            receipt.logs.iter().find_map(|log| {
+                decode_input(
+                    log,
+                    config.input_box_address,
+                    dapp_address,
+                    input.index,
+                    block_number,
+                )
+            })
+        });
+        match chain_input {
+            Some(chain_input) => {
+                failures.extend(input_mismatches(input, &chain_input))
+            }
+            None => failures.push(format!(
+                "input {} wasn't added by transaction {:?}",
+                input.index, tx_hash
+            )),
+        }
+    }
+    report.push("sampled inputs", failures);
+
+    let failures = match inputs.iter().map(|input| input.block_number).max() {
+        Some(last_block) => {
+            let last_block = last_block as u64;
+            let first_block = last_block
+                .saturating_sub(config.window_blocks.saturating_sub(1));
+            let events = InputBox::new(config.input_box_address, provider)
+                .input_added_filter()
+                .topic1(dapp_address)
+                .from_block(first_block)
+                .to_block(last_block)
+                .query_with_meta()
+                .await
+                .context(ContractSnafu)?;
+            let chain_inputs: Vec<ChainInput> = events
+                .into_iter()
+                .map(|(event, meta)| ChainInput {
+                    index: event.input_index,
+                    msg_sender: event.sender,
+                    tx_hash: meta.transaction_hash,
+                    block_number: meta.block_number.as_u64(),
+                    payload: event.input.to_vec(),
+                })
+                .collect();
+            let window_inputs: Vec<&Input> = inputs
+                .iter()
+                .filter(|input| input.block_number as u64 >= first_block)
+                .collect();
+            window_mismatches(&window_inputs, &chain_inputs)
+        }
+        None => vec![],
+    };
+    report.push("recent inputs", failures);
+
+    Ok(report)
+}
+
+async fn check_creation(
+    config: &ValidationConfig,
+    provider: &Arc<HttpProvider>,
+    dapp_address: H160,
+    inputs: &[Input],
+) -> Result<Vec<String>, IndexerError> {
+    let Some(factory_address) = config.dapp_factory_address else {
+        let code = provider
+            .get_code(dapp_address, None)
+            .await
+            .context(ProviderSnafu)?;
+        return Ok(if code.is_empty() {
+            vec![format!("there's no contract at {:?}", dapp_address)]
+        } else {
+            vec![]
+        });
+    };
+    let created = CartesiDAppFactory::new(factory_address, provider.clone())
+        .application_created_filter()
+        .query_with_meta()
+        .await
+        .context(ContractSnafu)?
+        .into_iter()
+        .find(|(event, _)| event.application == dapp_address);
+    let Some((event, meta)) = created else {
+        return Ok(vec![format!(
+            "factory {:?} didn't create the DApp",
+            factory_address
+        )]);
+    };
+    let mut failures = vec![];
+    let template_hash = CartesiDApp::new(dapp_address, provider.clone())
+        .get_template_hash()
+        .call()
+        .await
+        .context(ContractSnafu)?;
+    if template_hash != event.template_hash {
+        failures.push(format!(
+            "DApp has template hash {:?} but was created with {:?}",
+            H256(template_hash),
+            H256(event.template_hash)
+        ));
+    }
+    let created_at = meta.block_number.as_u64();
+    if let Some(first) = inputs.iter().min_by_key(|input| input.index) {
+        if (first.block_number as u64) < created_at {
+            failures.push(format!(
+                "input {} was added at block {}, before the DApp was created at block {}",
+                first.index, first.block_number, created_at
+            ));
+        }
+    }
+    Ok(failures)
+}
+
+fn check_indices(manifest: &Manifest, inputs: &[Input]) -> Vec<String> {
+    let mut failures = vec![];
+    let mut indices: Vec<i32> =
+        inputs.iter().map(|input| input.index).collect();
+    indices.sort_unstable();
+    for (expected, index) in indices.iter().enumerate() {
+        if *index != expected as i32 {
+            failures.push(format!(
+                "input {} is missing or repeated; found {} instead",
+                expected, index
+            ));
+            break;
+        }
+    }
+    let last = indices.last().copied();
+    if last != manifest.last_input_index {
+        failures.push(format!(
+            "last input is {:?} but the manifest says {:?}",
+            last, manifest.last_input_index
+        ));
+    }
+    failures
+}
+
+/// Positions of the inputs checked against their receipts, spread over the
+/// snapshot and always including the last one
+fn sample_positions(len: usize, samples: usize) -> Vec<usize> {
+    if len == 0 || samples == 0 {
+        return vec![];
+    }
+    let samples = samples.min(len);
+    let mut positions: Vec<usize> = (0..samples)
+        .map(|sample| (sample * len) / samples)
+        .collect();
+    positions.push(len - 1);
+    positions.dedup();
+    positions
+}
+
+/// The input added to the DApp at the index by the log, if it's that one
+fn decode_input(
+    log: &Log,
+    input_box_address: H160,
+    dapp_address: H160,
+    index: i32,
+    block_number: u64,
+) -> Option<ChainInput> {
+    if log.address != input_box_address
+        || log.removed == Some(true)
+        || log.topics.first() != Some(&InputAddedFilter::signature())
+    {
+        return None;
+    }
+    let event =
+        InputAddedFilter::decode_log(&RawLog::from(log.clone())).ok()?;
+    if event.dapp != dapp_address || event.input_index != U256::from(index) {
+        return None;
+    }
+    Some(ChainInput {
+        index: event.input_index,
+        msg_sender: event.sender,
+        tx_hash: log.transaction_hash.unwrap_or_default(),
+        block_number,
+        payload: event.input.to_vec(),
+    })
+}
+
+/// Fields of the input of the snapshot that differ from the chain
+fn input_mismatches(input: &Input, chain_input: &ChainInput) -> Vec<String> {
+    let mut failures = vec![];
+    let mut differs = |field: &str, equal: bool| {
+        if !equal {
+            failures.push(format!(
+                "{} of input {} differs from the chain",
+                field, input.index
+            ));
+        }
+    };
+    differs(
+        "sender",
+        input.msg_sender == chain_input.msg_sender.as_bytes(),
+    );
+    differs(
+        "transaction hash",
+        input.tx_hash == chain_input.tx_hash.as_bytes(),
+    );
+    differs(
+        "block number",
+        input.block_number as u64 == chain_input.block_number,
+    );
+    differs("payload", input.payload == chain_input.payload);
+    failures
+}
+
+/// Differences between the inputs of the snapshot in a window of blocks and
+/// the ones read from the chain for it
+fn window_mismatches(
+    inputs: &[&Input],
+    chain_inputs: &[ChainInput],
+) -> Vec<String> {
+    let snapshot: BTreeMap<U256, &Input> = inputs
+        .iter()
+        .map(|input| (U256::from(input.index), *input))
+        .collect();
+    let chain: BTreeMap<U256, &ChainInput> = chain_inputs
+        .iter()
+        .map(|chain_input| (chain_input.index, chain_input))
+        .collect();
+    let mut failures = vec![];
+    for (index, chain_input) in &chain {
+        match snapshot.get(index) {
+            Some(input) => {
+                failures.extend(input_mismatches(input, chain_input))
+            }
+            None => failures.push(format!(
+                "input {} added at block {} is missing from the snapshot",
+                index, chain_input.block_number
+            )),
+        }
+    }
+    for (index, input) in &snapshot {
+        if !chain.contains_key(index) {
+            failures.push(format!(
+                "input {} at block {} isn't on the chain",
+                index, input.block_number
+            ));
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rollups_data::CompletionStatus;
+    use std::time::UNIX_EPOCH;
+
+    fn input(index: i32, block_number: i64, payload: &[u8]) -> Input {
+        Input {
+            index,
+            msg_sender: H160::repeat_byte(0x11).as_bytes().to_vec(),
+            tx_hash: H256::repeat_byte(index as u8).as_bytes().to_vec(),
+            block_number,
+            timestamp: UNIX_EPOCH,
+            payload: payload.to_vec(),
+            status: CompletionStatus::Accepted,
+            decoded_payload: None,
+            payload_codec: None,
+        }
+    }
+
+    fn chain_input(
+        index: i32,
+        block_number: u64,
+        payload: &[u8],
+    ) -> ChainInput {
+        ChainInput {
+            index: index.into(),
+            msg_sender: H160::repeat_byte(0x11),
+            tx_hash: H256::repeat_byte(index as u8),
+            block_number,
+            payload: payload.to_vec(),
+        }
+    }
+
+    fn manifest(last_input_index: Option<i32>) -> Manifest {
+        Manifest {
+            version: super::super::MANIFEST_VERSION,
+            chain_id: 31337,
+            dapp_address: format!("{:?}", H160::repeat_byte(0xfa)),
+            created_at: 1700000000,
+            last_input_index,
+            files: vec![],
+        }
+    }
+
+    #[test]
+    fn it_samples_across_the_snapshot() {
+        assert_eq!(sample_positions(0, 4), Vec::<usize>::new());
+        assert_eq!(sample_positions(10, 0), Vec::<usize>::new());
+        assert_eq!(sample_positions(3, 8), vec![0, 1, 2]);
+        assert_eq!(sample_positions(100, 4), vec![0, 25, 50, 75, 99]);
+    }
+
+    #[test]
+    fn it_checks_the_indices_against_the_manifest() {
+        let inputs = [input(1, 10, b""), input(0, 10, b"")];
+        assert!(check_indices(&manifest(Some(1)), &inputs).is_empty());
+        assert!(check_indices(&manifest(None), &[]).is_empty());
+        assert_eq!(check_indices(&manifest(Some(2)), &inputs).len(), 1);
+
+        let inputs = [input(0, 10, b""), input(2, 10, b"")];
+        assert_eq!(
+            check_indices(&manifest(Some(2)), &inputs),
+            vec!["input 1 is missing or repeated; found 2 instead"]
+        );
+    }
+
+    #[test]
+    fn it_compares_the_window_with_the_chain() {
+        let snapshot = [
+            input(3, 100, b"a"),
+            input(4, 101, b"tampered"),
+            input(6, 103, b"forged"),
+        ];
+        let snapshot: Vec<&Input> = snapshot.iter().collect();
+        let chain = [
+            chain_input(3, 100, b"a"),
+            chain_input(4, 101, b"b"),
+            chain_input(5, 102, b"c"),
+        ];
+        assert_eq!(
+            window_mismatches(&snapshot, &chain),
+            vec![
+                "payload of input 4 differs from the chain",
+                "input 5 added at block 102 is missing from the snapshot",
+                "input 6 at block 103 isn't on the chain",
+            ]
+        );
+        assert!(window_mismatches(&snapshot[..1], &chain[..1]).is_empty());
+    }
+
+    #[test]
+    fn it_reports_each_check() {
+        let report = ValidationReport {
+            checks: vec![
+                ValidationCheck {
+                    name: "chain",
+                    failures: vec![],
+                },
+                ValidationCheck {
+                    name: "sampled inputs",
+                    failures: vec![
+                        "payload of input 4 differs from the chain".to_owned()
+                    ],
+                },
+            ],
+        };
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "chain: ok\nsampled inputs: 1 failure(s)\n  - payload of input 4 differs from the chain\n"
+        );
+    }
+}
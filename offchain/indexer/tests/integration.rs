@@ -14,12 +14,12 @@ use rollups_events::{
     RollupsVoucher,
 };
 use serial_test::serial;
-use std::time::UNIX_EPOCH;
+use std::time::{Duration, UNIX_EPOCH};
 use test_fixtures::{BrokerFixture, RepositoryFixture};
 use testcontainers::clients::Cli;
 use tokio::task::JoinHandle;
 
-const BROKER_CONSUME_TIMEOUT: usize = 100;
+const BROKER_CONSUME_TIMEOUT: Duration = Duration::from_millis(100);
 
 /// Starts one container with the broker, one container with the database,
 /// and the indexer in a background thread.
@@ -180,10 +180,7 @@ async fn indexer_inserts_input_after_broker_timeout() {
     let state = TestState::setup(&docker).await;
 
     tracing::info!("sleeping so the broker consume times out in indexer");
-    tokio::time::sleep(std::time::Duration::from_millis(
-        2 * BROKER_CONSUME_TIMEOUT as u64,
-    ))
-    .await;
+    tokio::time::sleep(2 * BROKER_CONSUME_TIMEOUT).await;
 
     let input_sent = state.produce_input_in_broker(0).await;
     let input_read = state.get_input_from_database(&input_sent).await;
@@ -435,6 +432,15 @@ async fn spawn_indexer(
         broker_config,
         healthcheck_port: 0,
         log_config: LogConfig::default(),
+        payload_codec: indexer::CodecSelection::None,
+        payload_abi_types: None,
+        dapp_labels: None,
+        admin_address: None,
+        skip_migrations: false,
+        reconcile_config: None,
+        snapshot_config: None,
+        scheduler_config: Default::default(),
+        payload_policy: Default::default(),
     };
     tokio::spawn(async move {
         indexer::run(indexer_config).await.map_err(|e| {
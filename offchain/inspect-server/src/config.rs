@@ -7,8 +7,10 @@
 /// take precedence over same parameter from file configuration.
 use clap::Parser;
 use log::{LogConfig, LogEnvCliConfig};
+use scheduler::{SchedulerCLIConfig, SchedulerConfig};
 use serde::Deserialize;
 use snafu::{ResultExt, Snafu};
+use std::{fmt, time::Duration};
 
 #[derive(Debug, Snafu)]
 pub enum ConfigError {
@@ -32,7 +34,13 @@ pub struct InspectServerConfig {
     pub server_manager_address: String,
     pub session_id: String,
     pub queue_size: usize,
+    pub queue_timeout: Duration,
+    pub client_id_header: Option<String>,
+    pub client_api_keys: ApiKeys,
     pub healthcheck_port: u16,
+    pub replica_addresses: Vec<String>,
+    pub replica_health_check_interval: Duration,
+    pub scheduler_config: SchedulerConfig,
 }
 
 #[derive(Parser)]
@@ -42,6 +50,9 @@ pub struct CLIConfig {
     #[command(flatten)]
     pub log_config: LogEnvCliConfig,
 
+    #[command(flatten)]
+    pub scheduler_config: SchedulerCLIConfig,
+
     /// HTTP address for the inspect server
     #[arg(long, env)]
     inspect_server_address: Option<String>,
@@ -58,11 +69,49 @@ pub struct CLIConfig {
     #[arg(long, env)]
     queue_size: Option<usize>,
 
+    /// Longest time an inspect request waits in the queue, such as `10s`;
+    /// the requests that wait longer are answered with an error instead of
+    /// being sent to the server manager
+    #[arg(
+        long,
+        env,
+        default_value = "10s",
+        value_parser = humane::parse_duration
+    )]
+    inspect_queue_timeout: Duration,
+
+    /// Header with the API key of the client of an inspect request, such as
+    /// `x-api-key`, whose requests are queued apart from the others so
+    /// a single client can't starve them. Without it, or when a request
+    /// doesn't have one of the API keys, the client is identified by its IP
+    #[arg(long, env, requires = "inspect_client_api_keys")]
+    inspect_client_id_header: Option<String>,
+
+    /// Comma-separated API keys accepted in the client header
+    #[arg(long, env, value_delimiter = ',')]
+    inspect_client_api_keys: Vec<String>,
+
+    /// Comma-separated gRPC addresses of server managers running read
+    /// replicas of the machine. When set, inspect requests are balanced
+    /// across the healthy replicas instead of going to the server manager
+    /// that advances the state
+    #[arg(long, env, value_delimiter = ',')]
+    inspect_replica_addresses: Option<Vec<String>>,
+
+    /// Interval between the health checks of each replica
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration
+    )]
+    inspect_replica_health_check_interval: Duration,
+
     /// Path to the config file
     #[arg(long, env)]
     pub config_path: Option<String>,
 
-    /// Port of health check
+    /// Port of health check, which also serves the metrics at `/metrics`
     #[arg(
         long,
         env = "INSPECT_SERVER_HEALTHCHECK_PORT",
@@ -96,23 +145,58 @@ impl From<CLIConfig> for InspectServerConfig {
             .or(file_config.queue_size)
             .unwrap_or(100);
 
+        let replica_addresses: Vec<String> = cli_config
+            .inspect_replica_addresses
+            .or(file_config.inspect_replica_addresses)
+            .unwrap_or_default();
+
         Self {
             log_config: cli_config.log_config.into(),
             inspect_server_address,
             server_manager_address,
             session_id,
             queue_size,
+            queue_timeout: cli_config.inspect_queue_timeout,
+            client_id_header: cli_config.inspect_client_id_header,
+            client_api_keys: ApiKeys::new(cli_config.inspect_client_api_keys),
             healthcheck_port: cli_config.healthcheck_port,
+            replica_addresses,
+            replica_health_check_interval: cli_config
+                .inspect_replica_health_check_interval,
+            scheduler_config: cli_config.scheduler_config.into(),
         }
     }
 }
 
+/// API keys that identify the clients of the inspect requests, which are
+/// kept out of the logs
+#[derive(Clone, Default)]
+pub struct ApiKeys(Vec<String>);
+
+impl ApiKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self(keys)
+    }
+
+    /// Index of the key, if it is one of them
+    pub fn position(&self, key: &str) -> Option<usize> {
+        self.0.iter().position(|known| known == key)
+    }
+}
+
+impl fmt::Debug for ApiKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKeys({} keys)", self.0.len())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Default)]
 struct FileConfig {
     inspect_server_address: Option<String>,
     server_manager_address: Option<String>,
     session_id: Option<String>,
     queue_size: Option<usize>,
+    inspect_replica_addresses: Option<Vec<String>>,
 }
 
 fn load_config_file<T: Default + serde::de::DeserializeOwned>(
@@ -7,9 +7,7 @@ use snafu::Snafu;
 #[snafu(visibility(pub(crate)))]
 pub enum InspectError {
     #[snafu(display("health check error"))]
-    HealthCheckError {
-        source: http_health_check::HealthCheckError,
-    },
+    HealthCheckError { source: std::io::Error },
 
     #[snafu(display("server error"))]
     ServerError { source: std::io::Error },
@@ -19,4 +17,7 @@ pub enum InspectError {
 
     #[snafu(display("Failed to inspect state: {}", message))]
     InspectFailed { message: String },
+
+    #[snafu(display("Inspect request shed: {}", message))]
+    RequestShed { message: String },
 }
@@ -1,63 +1,122 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use tokio::sync::{mpsc, oneshot};
+use http_server::Registry;
+use scheduler::{JobConfig, Scheduler};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::sync::{oneshot, watch};
 use tonic::Request;
 use uuid::Uuid;
 
 use crate::config::InspectServerConfig;
 use crate::error::InspectError;
+use crate::queue::InspectQueue;
 
 use grpc_interfaces::cartesi_server_manager::{
-    server_manager_client::ServerManagerClient, InspectStateRequest,
+    server_manager_client::ServerManagerClient, GetSessionStatusRequest,
+    InspectStateRequest,
 };
 pub use grpc_interfaces::cartesi_server_manager::{
     CompletionStatus, InspectStateResponse, Report,
 };
 
+/// Timeout of the health check of a replica
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct InspectClient {
-    inspect_tx: mpsc::Sender<InspectRequest>,
+    queue: Arc<InspectQueue>,
+    /// Health of each replica; empty when inspecting the main server manager
+    replicas_health: Vec<watch::Receiver<bool>>,
 }
 
 /// The inspect client is a wrapper that just sends the inspect requests to another thread and
 /// waits for the result. The actual request to the server manager is done by the handle_inspect
 /// function.
+///
+/// The requests wait in a queue that takes the requests of each client in turns, so a client
+/// flooding the server doesn't starve the others; see `InspectQueue`.
+///
+/// When read replicas are configured, the requests go to them instead of the server manager that
+/// advances the state. Each healthy replica takes the next queued request as soon as it is idle,
+/// which spreads the load across them.
 impl InspectClient {
-    pub fn new(config: &InspectServerConfig) -> Self {
-        let (inspect_tx, inspect_rx) = mpsc::channel(config.queue_size);
-        let address = config.server_manager_address.clone();
+    pub fn new(config: &InspectServerConfig, registry: &mut Registry) -> Self {
+        let queue = Arc::new(InspectQueue::new(
+            config.queue_size,
+            config.queue_timeout,
+            registry,
+        ));
         let session_id = config.session_id.clone();
-        tokio::spawn(handle_inspect(address, session_id, inspect_rx));
-        Self { inspect_tx }
+        if config.replica_addresses.is_empty() {
+            let address = config.server_manager_address.clone();
+            tokio::spawn(handle_inspect(address, session_id, queue.clone()));
+            return Self {
+                queue,
+                replicas_health: vec![],
+            };
+        }
+
+        let scheduler = Scheduler::new(&config.scheduler_config);
+        let replicas_health = config
+            .replica_addresses
+            .iter()
+            .map(|address| {
+                let (health_tx, health_rx) = watch::channel(false);
+                tokio::spawn(check_replica_health(
+                    address.clone(),
+                    session_id.clone(),
+                    scheduler.clone(),
+                    config.replica_health_check_interval,
+                    health_tx,
+                ));
+                tokio::spawn(handle_replica_inspect(
+                    address.clone(),
+                    session_id.clone(),
+                    health_rx.clone(),
+                    queue.clone(),
+                ));
+                health_rx
+            })
+            .collect();
+        Self {
+            queue,
+            replicas_health,
+        }
     }
 
+    /// Inspects the state with `payload`, queued with the other requests of
+    /// `client_id`
     pub async fn inspect(
         &self,
+        client_id: String,
         payload: Vec<u8>,
     ) -> Result<InspectStateResponse, InspectError> {
+        if !self.replicas_health.is_empty()
+            && !self.replicas_health.iter().any(|health| *health.borrow())
+        {
+            return Err(InspectError::FailedToConnect {
+                message: "no healthy inspect replica".to_owned(),
+            });
+        }
         let (response_tx, response_rx) = oneshot::channel();
         let request = InspectRequest {
             payload,
             response_tx,
         };
-        if let Err(e) = self.inspect_tx.try_send(request) {
-            return Err(InspectError::InspectFailed {
-                message: e.to_string(),
-            });
-        } else {
-            tracing::debug!("inspect request added to the queue");
-        }
+        self.queue.push(client_id, request)?;
+        tracing::debug!("inspect request added to the queue");
         response_rx.await.expect("handle_inspect never fails")
     }
 }
 
-struct InspectRequest {
+pub(crate) struct InspectRequest {
     payload: Vec<u8>,
-    response_tx: oneshot::Sender<Result<InspectStateResponse, InspectError>>,
+    pub response_tx:
+        oneshot::Sender<Result<InspectStateResponse, InspectError>>,
 }
 
-fn respond(
+pub(crate) fn respond(
     response_tx: oneshot::Sender<Result<InspectStateResponse, InspectError>>,
     response: Result<InspectStateResponse, InspectError>,
 ) {
@@ -66,50 +125,128 @@ fn respond(
     }
 }
 
-/// Loop that answers requests comming from inspect_rx.
+/// Loop that answers requests comming from the queue.
 async fn handle_inspect(
     address: String,
     session_id: String,
-    mut inspect_rx: mpsc::Receiver<InspectRequest>,
+    queue: Arc<InspectQueue>,
+) {
+    let endpoint = format!("http://{}", address);
+    loop {
+        let request = queue.pop().await;
+        let response =
+            inspect_state(&endpoint, &session_id, request.payload).await;
+        respond(request.response_tx, response);
+    }
+}
+
+/// Loop that answers requests comming from the shared queue with a
+/// replica, taking requests only while the replica is healthy.
+async fn handle_replica_inspect(
+    address: String,
+    session_id: String,
+    mut health_rx: watch::Receiver<bool>,
+    queue: Arc<InspectQueue>,
 ) {
     let endpoint = format!("http://{}", address);
-    while let Some(request) = inspect_rx.recv().await {
-        match ServerManagerClient::connect(endpoint.clone()).await {
-            Err(e) => {
-                respond(
-                    request.response_tx,
-                    Err(InspectError::FailedToConnect {
-                        message: e.to_string(),
-                    }),
-                );
-            }
-            Ok(mut client) => {
-                let request_id = Uuid::new_v4().to_string();
-                let grpc_request = InspectStateRequest {
-                    session_id: session_id.clone(),
-                    query_payload: request.payload,
-                };
-
-                tracing::debug!(
-                    "calling grpc inspect_state request={:?} request_id={}",
-                    grpc_request,
-                    request_id
-                );
-                let mut grpc_request = Request::new(grpc_request);
-                grpc_request
-                    .metadata_mut()
-                    .insert("request-id", request_id.parse().unwrap());
-                let grpc_response = client.inspect_state(grpc_request).await;
-
-                tracing::debug!("got grpc response from inspect_state response={:?} request_id={}", grpc_response, request_id);
-
-                let response = grpc_response
-                    .map(|result| result.into_inner())
-                    .map_err(|e| InspectError::InspectFailed {
-                        message: e.message().to_string(),
-                    });
-                respond(request.response_tx, response);
-            }
+    loop {
+        if health_rx.wait_for(|healthy| *healthy).await.is_err() {
+            return;
         }
+        // Nothing is locked while waiting for a request, so the replica stops
+        // waiting as soon as it turns unhealthy and leaves the next request
+        // to the healthy ones
+        let request = tokio::select! {
+            request = queue.pop() => request,
+            _ = health_rx.wait_for(|healthy| !*healthy) => continue,
+        };
+        tracing::debug!("replica {} took an inspect request", address);
+        let response =
+            inspect_state(&endpoint, &session_id, request.payload).await;
+        respond(request.response_tx, response);
     }
 }
+
+/// Periodically checks whether the replica has the session
+async fn check_replica_health(
+    address: String,
+    session_id: String,
+    scheduler: Scheduler,
+    interval: Duration,
+    health_tx: watch::Sender<bool>,
+) {
+    let endpoint = format!("http://{}", address);
+    let job = || async {
+        let check = async {
+            let mut client =
+                ServerManagerClient::connect(endpoint.clone()).await.ok()?;
+            let request = GetSessionStatusRequest {
+                session_id: session_id.clone(),
+            };
+            client.get_session_status(request).await.ok()
+        };
+        let healthy = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, check)
+            .await
+            .ok()
+            .flatten()
+            .is_some();
+        if *health_tx.borrow() != healthy {
+            tracing::info!(
+                "inspect replica {} is {}",
+                address,
+                if healthy { "healthy" } else { "unhealthy" }
+            );
+        }
+        health_tx.send_replace(healthy);
+        Ok::<_, Infallible>(())
+    };
+    scheduler
+        .run(
+            &format!("inspect_replica_health_{}", address),
+            &JobConfig::every(interval),
+            job,
+        )
+        .await
+}
+
+/// Sends the inspect request to the server manager at endpoint.
+async fn inspect_state(
+    endpoint: &str,
+    session_id: &str,
+    payload: Vec<u8>,
+) -> Result<InspectStateResponse, InspectError> {
+    let mut client = ServerManagerClient::connect(endpoint.to_owned())
+        .await
+        .map_err(|e| InspectError::FailedToConnect {
+            message: e.to_string(),
+        })?;
+
+    let request_id = Uuid::new_v4().to_string();
+    let grpc_request = InspectStateRequest {
+        session_id: session_id.to_owned(),
+        query_payload: payload,
+    };
+
+    tracing::debug!(
+        "calling grpc inspect_state request={:?} request_id={}",
+        grpc_request,
+        request_id
+    );
+    let mut grpc_request = Request::new(grpc_request);
+    grpc_request
+        .metadata_mut()
+        .insert("request-id", request_id.parse().unwrap());
+    let grpc_response = client.inspect_state(grpc_request).await;
+
+    tracing::debug!(
+        "got grpc response from inspect_state response={:?} request_id={}",
+        grpc_response,
+        request_id
+    );
+
+    grpc_response
+        .map(|result| result.into_inner())
+        .map_err(|e| InspectError::InspectFailed {
+            message: e.message().to_string(),
+        })
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use error::InspectError;
+use http_server::Registry;
 use snafu::ResultExt;
 
 pub use config::InspectServerConfig;
@@ -10,16 +11,19 @@ pub use inspect::InspectClient;
 pub mod config;
 mod error;
 pub mod inspect;
+mod queue;
 pub mod server;
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn run(config: InspectServerConfig) -> Result<(), InspectError> {
-    let health_handle = http_health_check::start(config.healthcheck_port);
-    let inspect_client = InspectClient::new(&config);
+    let mut registry = Registry::default();
+    let inspect_client = InspectClient::new(&config, &mut registry);
+    let internal_server =
+        server::serve_internal(config.healthcheck_port, registry);
     let inspect_server =
         server::create(&config, inspect_client).context(error::ServerSnafu)?;
     tokio::select! {
-        ret = health_handle => {
+        ret = internal_server => {
             ret.context(error::HealthCheckSnafu)
         }
         ret = inspect_server => {
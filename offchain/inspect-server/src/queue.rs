@@ -0,0 +1,289 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use http_server::{CounterRef, GaugeRef, Registry};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+use crate::error::InspectError;
+use crate::inspect::{respond, InspectRequest};
+
+const METRICS_PREFIX: &str = "cartesi_rollups_inspect_server";
+
+fn prefixed_metrics(name: &str) -> String {
+    format!("{}_{}", METRICS_PREFIX, name)
+}
+
+#[derive(Clone, Debug, Default)]
+struct QueueMetrics {
+    depth: GaugeRef,
+    rejected: CounterRef,
+    evicted: CounterRef,
+    timed_out: CounterRef,
+}
+
+impl QueueMetrics {
+    fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+        registry.register(
+            prefixed_metrics("queue_depth"),
+            "Inspect requests waiting for a server manager",
+            metrics.depth.clone(),
+        );
+        registry.register(
+            prefixed_metrics("requests_rejected"),
+            "Inspect requests rejected because the queue was full",
+            metrics.rejected.clone(),
+        );
+        registry.register(
+            prefixed_metrics("requests_evicted"),
+            "Queued inspect requests shed to make room for a client with \
+            fewer queued requests",
+            metrics.evicted.clone(),
+        );
+        registry.register(
+            prefixed_metrics("requests_timed_out"),
+            "Queued inspect requests shed after waiting longer than the \
+            queue timeout",
+            metrics.timed_out.clone(),
+        );
+        metrics
+    }
+}
+
+/// Queue of the inspect requests waiting for a server manager, shared by
+/// the tasks that send them.
+///
+/// Requests are taken in turns, one of each client, so a client flooding
+/// the server doesn't make the others wait behind all of its requests. When
+/// the queue is full, the newest request of the client with the most queued
+/// requests is shed to make room for a client with fewer. Requests that
+/// waited longer than the timeout are shed instead of being sent, since
+/// their clients have likely given up on them.
+pub(crate) struct InspectQueue {
+    queue: Mutex<FairQueue<InspectRequest>>,
+    available: Notify,
+    timeout: Duration,
+    metrics: QueueMetrics,
+}
+
+impl InspectQueue {
+    pub fn new(
+        capacity: usize,
+        timeout: Duration,
+        registry: &mut Registry,
+    ) -> Self {
+        Self {
+            queue: Mutex::new(FairQueue::new(capacity)),
+            available: Notify::new(),
+            timeout,
+            metrics: QueueMetrics::new(registry),
+        }
+    }
+
+    pub fn push(
+        &self,
+        client_id: String,
+        request: InspectRequest,
+    ) -> Result<(), InspectError> {
+        let pushed = {
+            let mut queue = self.queue.lock().unwrap();
+            let pushed = queue.push(client_id, request, Instant::now());
+            self.metrics.depth.set(queue.len() as i64);
+            pushed
+        };
+        match pushed {
+            Ok(None) => {}
+            Ok(Some(evicted)) => {
+                tracing::debug!("shed an inspect request of a busier client");
+                self.metrics.evicted.inc();
+                respond(
+                    evicted.response_tx,
+                    Err(InspectError::RequestShed {
+                        message: "queue is full".to_owned(),
+                    }),
+                );
+            }
+            Err(_) => {
+                self.metrics.rejected.inc();
+                return Err(InspectError::InspectFailed {
+                    message: "no available capacity".to_owned(),
+                });
+            }
+        }
+        self.available.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next request to be sent, shedding the ones that
+    /// waited too long. It holds no lock while waiting, and cancelling it
+    /// leaves the queued requests to the other tasks.
+    pub async fn pop(&self) -> InspectRequest {
+        loop {
+            let available = self.available.notified();
+            while let Some((request, queued_at)) = self.try_pop() {
+                if request.response_tx.is_closed() {
+                    tracing::debug!("dropped inspect request (client left)");
+                } else if queued_at.elapsed() > self.timeout {
+                    self.metrics.timed_out.inc();
+                    respond(
+                        request.response_tx,
+                        Err(InspectError::RequestShed {
+                            message: "timed out in the queue".to_owned(),
+                        }),
+                    );
+                } else {
+                    return request;
+                }
+            }
+            available.await;
+        }
+    }
+
+    fn try_pop(&self) -> Option<(InspectRequest, Instant)> {
+        let mut queue = self.queue.lock().unwrap();
+        let popped = queue.pop();
+        self.metrics.depth.set(queue.len() as i64);
+        popped
+    }
+}
+
+/// Bounded queue that takes the items of each client in turns
+struct FairQueue<T> {
+    capacity: usize,
+    len: usize,
+    clients: HashMap<String, VecDeque<(T, Instant)>>,
+    /// Clients with queued items, in the order of their next turn
+    turns: VecDeque<String>,
+}
+
+impl<T> FairQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            len: 0,
+            clients: HashMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Queues the item, returning the item shed to make room for it. When
+    /// no client has at least two more queued items than `client_id`, so
+    /// shedding one of them would make `client_id` the busiest one, the item
+    /// itself is returned as the error.
+    fn push(
+        &mut self,
+        client_id: String,
+        item: T,
+        now: Instant,
+    ) -> Result<Option<T>, T> {
+        let mut evicted = None;
+        if self.len >= self.capacity {
+            let queued = self.clients.get(&client_id).map_or(0, VecDeque::len);
+            let busiest = self
+                .clients
+                .iter()
+                .max_by_key(|(_, items)| items.len())
+                .filter(|(_, items)| items.len() > queued + 1)
+                .map(|(busiest, _)| busiest.clone());
+            match busiest {
+                Some(busiest) => evicted = self.pop_newest(&busiest),
+                None => return Err(item),
+            }
+        }
+        let items = self.clients.entry(client_id.clone()).or_default();
+        if items.is_empty() {
+            self.turns.push_back(client_id);
+        }
+        items.push_back((item, now));
+        self.len += 1;
+        Ok(evicted)
+    }
+
+    /// Takes the oldest item of the client whose turn it is
+    fn pop(&mut self) -> Option<(T, Instant)> {
+        let client_id = self.turns.pop_front()?;
+        let items = self.clients.get_mut(&client_id)?;
+        let item = items.pop_front();
+        if items.is_empty() {
+            self.clients.remove(&client_id);
+        } else {
+            self.turns.push_back(client_id);
+        }
+        self.len -= 1;
+        item
+    }
+
+    fn pop_newest(&mut self, client_id: &str) -> Option<T> {
+        let items = self.clients.get_mut(client_id)?;
+        let (item, _) = items.pop_back()?;
+        if items.is_empty() {
+            self.clients.remove(client_id);
+            self.turns.retain(|turn| turn != client_id);
+        }
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(
+        queue: &mut FairQueue<u32>,
+        client_id: &str,
+        item: u32,
+    ) -> Result<Option<u32>, u32> {
+        queue.push(client_id.to_owned(), item, Instant::now())
+    }
+
+    fn pop_all(queue: &mut FairQueue<u32>) -> Vec<u32> {
+        std::iter::from_fn(|| queue.pop().map(|(item, _)| item)).collect()
+    }
+
+    #[test]
+    fn it_takes_the_clients_in_turns() {
+        let mut queue = FairQueue::new(10);
+        for item in 1..=3 {
+            assert_eq!(push(&mut queue, "a", item), Ok(None));
+        }
+        assert_eq!(push(&mut queue, "b", 4), Ok(None));
+        assert_eq!(push(&mut queue, "c", 5), Ok(None));
+        assert_eq!(queue.len(), 5);
+        assert_eq!(pop_all(&mut queue), vec![1, 4, 5, 2, 3]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn it_sheds_the_newest_item_of_the_busiest_client() {
+        let mut queue = FairQueue::new(3);
+        for item in 1..=3 {
+            assert_eq!(push(&mut queue, "a", item), Ok(None));
+        }
+        assert_eq!(push(&mut queue, "b", 4), Ok(Some(3)));
+        assert_eq!(push(&mut queue, "c", 5), Ok(Some(2)));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(pop_all(&mut queue), vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn it_rejects_items_when_full_of_evenly_queued_clients() {
+        let mut queue = FairQueue::new(2);
+        assert_eq!(push(&mut queue, "a", 1), Ok(None));
+        assert_eq!(push(&mut queue, "a", 2), Ok(None));
+        assert_eq!(push(&mut queue, "a", 3), Err(3));
+        assert_eq!(push(&mut queue, "b", 4), Ok(Some(2)));
+        assert_eq!(push(&mut queue, "b", 5), Err(5));
+        assert_eq!(push(&mut queue, "c", 6), Err(6));
+        assert_eq!(pop_all(&mut queue), vec![1, 4]);
+    }
+}
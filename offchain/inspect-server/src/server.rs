@@ -6,10 +6,13 @@ use actix_web::{
     dev::Server, error, web, App, HttpRequest, HttpResponse, HttpServer,
     Responder,
 };
+use http_health_check::Health;
+use http_server::Registry;
 use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
 use tracing_actix_web::TracingLogger;
 
-use crate::config::InspectServerConfig;
+use crate::config::{ApiKeys, InspectServerConfig};
 use crate::error::InspectError;
 use crate::inspect::{
     CompletionStatus, InspectClient, InspectStateResponse, Report,
@@ -22,10 +25,15 @@ pub fn create(
     config: &InspectServerConfig,
     inspect_client: InspectClient,
 ) -> std::io::Result<Server> {
+    let client_identifier = ClientIdentifier {
+        header: config.client_id_header.clone(),
+        api_keys: config.client_api_keys.clone(),
+    };
     let server = HttpServer::new(move || {
         let cors = Cors::permissive();
         App::new()
             .app_data(web::Data::new(inspect_client.clone()))
+            .app_data(web::Data::new(client_identifier.clone()))
             .app_data(web::PayloadConfig::new(CARTESI_MACHINE_RX_BUFFER_LIMIT))
             .wrap(TracingLogger::default())
             .wrap(cors)
@@ -37,28 +45,73 @@ pub fn create(
     Ok(server)
 }
 
+/// Serves `/healthz`, `/readyz` and `/metrics` on the healthcheck port,
+/// apart from the inspect API, so the metrics aren't exposed with it
+pub async fn serve_internal(
+    healthcheck_port: u16,
+    registry: Registry,
+) -> std::io::Result<()> {
+    let address =
+        SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), healthcheck_port);
+    http_server::start_at(address, None, registry, Health::default()).await
+}
+
+/// Identifies the client of a request, whose requests are queued apart from
+/// the others
+#[derive(Clone)]
+struct ClientIdentifier {
+    header: Option<String>,
+    api_keys: ApiKeys,
+}
+
+impl ClientIdentifier {
+    /// The API key in the header, if it is one of the configured ones, or
+    /// else the IP of the peer. Neither unknown keys nor forwarding headers
+    /// are trusted, since any client can set them.
+    fn identify(&self, request: &HttpRequest) -> String {
+        let key = self
+            .header
+            .as_ref()
+            .and_then(|header| request.headers().get(header))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| self.api_keys.position(value));
+        match key {
+            Some(key) => format!("key:{}", key),
+            None => request
+                .peer_addr()
+                .map(|addr| format!("ip:{}", addr.ip()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
 #[actix_web::get("/inspect/{payload:.*}")]
 async fn inspect_get(
     request: HttpRequest,
     payload: web::Path<String>,
     inspect_client: web::Data<InspectClient>,
+    client_identifier: web::Data<ClientIdentifier>,
 ) -> actix_web::error::Result<impl Responder> {
     let mut payload = payload.into_inner();
     if let Some(query) = request.uri().query() {
         payload = payload + "?" + query;
     }
     let payload = payload.as_bytes().to_vec();
-    let response = inspect_client.inspect(payload).await?;
+    let client_id = client_identifier.identify(&request);
+    let response = inspect_client.inspect(client_id, payload).await?;
     let http_response = HttpInspectResponse::from(response);
     Ok(HttpResponse::Ok().json(http_response))
 }
 
 #[actix_web::post("/inspect")]
 async fn inspect_post(
+    request: HttpRequest,
     payload: web::Bytes,
     inspect_client: web::Data<InspectClient>,
+    client_identifier: web::Data<ClientIdentifier>,
 ) -> actix_web::error::Result<impl Responder> {
-    let response = inspect_client.inspect(payload.to_vec()).await?;
+    let client_id = client_identifier.identify(&request);
+    let response = inspect_client.inspect(client_id, payload.to_vec()).await?;
     let http_response = HttpInspectResponse::from(response);
     Ok(HttpResponse::Ok().json(http_response))
 }
@@ -137,7 +190,44 @@ impl From<InspectError> for error::Error {
             InspectError::InspectFailed { .. } => {
                 error::ErrorBadRequest(e.to_string())
             }
+            InspectError::RequestShed { .. } => {
+                error::ErrorServiceUnavailable(e.to_string())
+            }
             _ => error::ErrorBadGateway(e.to_string()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn identify(key: Option<&str>) -> String {
+        let identifier = ClientIdentifier {
+            header: Some("x-api-key".to_owned()),
+            api_keys: ApiKeys::new(vec![
+                "first".to_owned(),
+                "second".to_owned(),
+            ]),
+        };
+        let mut request =
+            TestRequest::default().peer_addr("10.0.0.1:4000".parse().unwrap());
+        if let Some(key) = key {
+            request = request.insert_header(("x-api-key", key));
+        }
+        identifier.identify(&request.to_http_request())
+    }
+
+    #[test]
+    fn it_identifies_the_clients_by_their_api_keys() {
+        assert_eq!(identify(Some("first")), "key:0");
+        assert_eq!(identify(Some("second")), "key:1");
+    }
+
+    #[test]
+    fn it_identifies_the_clients_without_a_known_api_key_by_their_ip() {
+        assert_eq!(identify(Some("forged")), "ip:10.0.0.1");
+        assert_eq!(identify(None), "ip:10.0.0.1");
+    }
+}
@@ -4,10 +4,12 @@
 #![allow(dead_code)]
 
 use actix_web::dev::ServerHandle;
-use inspect_server::config::InspectServerConfig;
+use http_server::Registry;
+use inspect_server::config::{ApiKeys, InspectServerConfig};
 use log::LogConfig;
 pub use reqwest::StatusCode;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{oneshot, Notify};
 use tokio::task::JoinHandle;
 use tonic::{transport::Server, Request, Response, Status};
@@ -29,10 +31,16 @@ use inspect_server::server::HttpInspectResponse;
 
 pub const SERVER_MANAGER_ADDRESS: &'static str = "127.0.0.1:50001";
 pub const INSPECT_SERVER_ADDRESS: &'static str = "127.0.0.1:50002";
+pub const REPLICA_ADDRESS: &'static str = "127.0.0.1:50003";
+pub const HEALTHCHECK_PORT: u16 = 50004;
 pub const SESSION_ID: &'static str = "default session";
 pub const ACTIVE_EPOCH_INDEX: u64 = 123;
 pub const PROCESSED_INPUT_COUNT: u64 = 456;
 pub const QUEUE_SIZE: usize = 3;
+pub const QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const CLIENT_ID_HEADER: &'static str = "x-api-key";
+pub const CLIENT_API_KEYS: [&'static str; 2] = ["flood", "other"];
+pub const REPLICA_HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct TestState {
     server_manager: MockServerManagerWrapper,
@@ -73,22 +81,45 @@ pub trait MockInspect: Send + Sync + 'static {
 pub struct InspectServerWrapper {
     server_handle: ServerHandle,
     join_handle: JoinHandle<()>,
+    internal_handle: JoinHandle<()>,
 }
 
 impl InspectServerWrapper {
     /// Start the inspect server in another thread.
     /// This function blocks until the server is ready.
     pub async fn start() -> Self {
+        Self::start_with_replicas(vec![]).await
+    }
+
+    /// Start the inspect server sending the requests to read replicas.
+    pub async fn start_with_replicas(replica_addresses: Vec<String>) -> Self {
         let inspect_server_config = InspectServerConfig {
             inspect_server_address: INSPECT_SERVER_ADDRESS.to_string(),
             server_manager_address: SERVER_MANAGER_ADDRESS.to_string(),
             session_id: SESSION_ID.to_string(),
             queue_size: QUEUE_SIZE,
-            healthcheck_port: 0,
+            queue_timeout: QUEUE_TIMEOUT,
+            client_id_header: Some(CLIENT_ID_HEADER.to_string()),
+            client_api_keys: ApiKeys::new(
+                CLIENT_API_KEYS.iter().map(|key| key.to_string()).collect(),
+            ),
+            healthcheck_port: HEALTHCHECK_PORT,
             log_config: LogConfig::default(),
+            replica_addresses,
+            replica_health_check_interval: REPLICA_HEALTH_CHECK_INTERVAL,
+            scheduler_config: Default::default(),
         };
 
-        let inspect_client = InspectClient::new(&inspect_server_config);
+        let mut registry = Registry::default();
+        let inspect_client =
+            InspectClient::new(&inspect_server_config, &mut registry);
+        let internal_server =
+            inspect_server::server::serve_internal(HEALTHCHECK_PORT, registry);
+        let internal_handle = tokio::spawn(async move {
+            internal_server
+                .await
+                .expect("internal server execution failed");
+        });
         let (handle_tx, handle_rx) = oneshot::channel();
         let join_handle = tokio::spawn(async move {
             let server = inspect_server::server::create(
@@ -106,6 +137,7 @@ impl InspectServerWrapper {
         Self {
             server_handle,
             join_handle,
+            internal_handle,
         }
     }
 
@@ -116,6 +148,8 @@ impl InspectServerWrapper {
         self.join_handle
             .await
             .expect("failed to stop inspect server");
+        self.internal_handle.abort();
+        let _ = self.internal_handle.await;
     }
 }
 
@@ -128,8 +162,13 @@ impl MockServerManagerWrapper {
     /// Start the server manager in another thread.
     /// This function blocks until the server is ready.
     pub async fn start(mock: impl MockInspect) -> Self {
+        Self::start_at(mock, SERVER_MANAGER_ADDRESS).await
+    }
+
+    /// Start the server manager at the given address in another thread.
+    pub async fn start_at(mock: impl MockInspect, address: &str) -> Self {
         let service = MockServerManager { mock };
-        let address = SERVER_MANAGER_ADDRESS.parse().expect("invalid address");
+        let address = address.parse().expect("invalid address");
         let ready = Arc::new(Notify::new());
         let shutdown = Arc::new(Notify::new());
         let join_handle = {
@@ -231,7 +270,10 @@ impl<T: MockInspect> ServerManager for MockServerManager<T> {
         &self,
         _: Request<GetSessionStatusRequest>,
     ) -> Result<Response<GetSessionStatusResponse>, Status> {
-        unimplemented!()
+        Ok(Response::new(GetSessionStatusResponse {
+            session_id: SESSION_ID.to_string(),
+            ..Default::default()
+        }))
     }
 
     async fn get_epoch_status(
@@ -259,6 +301,38 @@ pub async fn send_get_request(
     let response = reqwest::get(url)
         .await
         .expect("failed to send inspect via GET");
+    into_result(response).await
+}
+
+/// Same as `send_get_request`, identifying the client with `client_id`.
+pub async fn send_get_request_as(
+    client_id: &str,
+    payload: &str,
+) -> Result<HttpInspectResponse, (StatusCode, String)> {
+    let url = format!("http://{}/inspect/{}", INSPECT_SERVER_ADDRESS, payload);
+    let response = reqwest::Client::new()
+        .get(url)
+        .header(CLIENT_ID_HEADER, client_id)
+        .send()
+        .await
+        .expect("failed to send inspect via GET");
+    into_result(response).await
+}
+
+/// Get the metrics served at the healthcheck port of the inspect server.
+pub async fn get_metrics() -> String {
+    let url = format!("http://127.0.0.1:{}/metrics", HEALTHCHECK_PORT);
+    reqwest::get(url)
+        .await
+        .expect("failed to get metrics")
+        .text()
+        .await
+        .expect("failed to obtain metrics")
+}
+
+async fn into_result(
+    response: reqwest::Response,
+) -> Result<HttpInspectResponse, (StatusCode, String)> {
     let status = response.status();
     if status == 200 {
         let response = response
@@ -289,20 +363,7 @@ pub async fn send_post_request(
         .send()
         .await
         .expect("failed to send inspect via POST");
-    let status = response.status();
-    if status == 200 {
-        let response = response
-            .json::<HttpInspectResponse>()
-            .await
-            .expect("failed to decode json response");
-        Ok(response)
-    } else {
-        let message = response
-            .text()
-            .await
-            .expect("failed to obtain response body");
-        Err((status, message))
-    }
+    into_result(response).await
 }
 
 /// Convert binary value to the hex format
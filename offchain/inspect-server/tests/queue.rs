@@ -240,3 +240,50 @@ async fn test_post_it_returns_error_when_queue_is_full() {
     }
     state.teardown().await;
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_it_sheds_requests_of_flooding_client() {
+    let (mock, response_tx) = SyncInspect::setup();
+    let state = TestState::setup(mock).await;
+    // Fill the queue with the requests of a single client; the first one is
+    // consumed right away
+    let mut flood_handlers = FuturesUnordered::new();
+    for _ in 0..(QUEUE_SIZE + 1) {
+        flood_handlers
+            .push(tokio::spawn(send_get_request_as("flood", "hello")));
+    }
+    // Wait until the requests arrive in the inspect-server
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    // Another client still gets a place in the queue
+    let other_handler = tokio::spawn(send_get_request_as("other", "hello"));
+    let (status, message) = flood_handlers
+        .next()
+        .await
+        .expect("failed to poll")
+        .expect("failed to join handler")
+        .expect_err("failed to receive error");
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(message, "Inspect request shed: queue is full");
+    // Add the responses to the queue
+    for _ in 0..(QUEUE_SIZE + 1) {
+        response_tx
+            .send(MockInspectResponse::default())
+            .await
+            .expect("failed to send response");
+    }
+    other_handler
+        .await
+        .expect("failed to join handler")
+        .expect("failed to obtain response");
+    while let Some(handler) = flood_handlers.next().await {
+        handler
+            .expect("failed to join handler")
+            .expect("failed to obtain response");
+    }
+    let metrics = get_metrics().await;
+    assert!(metrics
+        .contains("cartesi_rollups_inspect_server_requests_evicted_total 1"));
+    assert!(metrics.contains("cartesi_rollups_inspect_server_queue_depth 0"));
+    state.teardown().await;
+}
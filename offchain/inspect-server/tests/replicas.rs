@@ -0,0 +1,83 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+mod common;
+use crate::common::*;
+
+struct DefaultInspect;
+
+#[tonic::async_trait]
+impl MockInspect for DefaultInspect {
+    async fn inspect_state(&self, _: Vec<u8>) -> MockInspectResponse {
+        MockInspectResponse::default()
+    }
+}
+
+/// Wait for a few rounds of replica health checks
+async fn wait_for_health_checks() {
+    tokio::time::sleep(REPLICA_HEALTH_CHECK_INTERVAL * 3).await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_get_is_answered_by_replica() {
+    let replica =
+        MockServerManagerWrapper::start_at(DefaultInspect, REPLICA_ADDRESS)
+            .await;
+    let inspect_server = InspectServerWrapper::start_with_replicas(vec![
+        REPLICA_ADDRESS.to_string(),
+    ])
+    .await;
+    wait_for_health_checks().await;
+    // The main server manager is down, so the replica answered
+    send_get_request("hello")
+        .await
+        .expect("failed to obtain response");
+    inspect_server.stop().await;
+    replica.stop().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_get_error_when_no_replica_is_healthy() {
+    let server_manager = MockServerManagerWrapper::start(DefaultInspect).await;
+    let inspect_server = InspectServerWrapper::start_with_replicas(vec![
+        REPLICA_ADDRESS.to_string(),
+    ])
+    .await;
+    wait_for_health_checks().await;
+    // The main server manager is up, but it is reserved for advancing
+    let (status, message) = send_get_request("hello")
+        .await
+        .expect_err("failed to obtain response");
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        &message,
+        "Failed to connect to server manager: no healthy inspect replica"
+    );
+    inspect_server.stop().await;
+    server_manager.stop().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_get_succeeds_after_replica_starts() {
+    let inspect_server = InspectServerWrapper::start_with_replicas(vec![
+        REPLICA_ADDRESS.to_string(),
+    ])
+    .await;
+    wait_for_health_checks().await;
+    let (status, _) = send_get_request("hello")
+        .await
+        .expect_err("failed to obtain response");
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let replica =
+        MockServerManagerWrapper::start_at(DefaultInspect, REPLICA_ADDRESS)
+            .await;
+    wait_for_health_checks().await;
+    send_get_request("hello")
+        .await
+        .expect("failed to obtain response");
+    inspect_server.stop().await;
+    replica.stop().await;
+}
@@ -0,0 +1,184 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+use reqwest::Url;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "cartesi-rollups-loadgen")]
+#[command(about = "Generates load on the reader and inspect APIs of a node")]
+pub struct CLIConfig {
+    /// URL of the reader API, such as `http://localhost:4000/graphql`
+    #[arg(long, env = "LOADGEN_GRAPHQL_URL")]
+    graphql_url: Option<Url>,
+
+    /// Base URL of the inspect API, such as `http://localhost:5005/inspect`
+    #[arg(long, env = "LOADGEN_INSPECT_URL")]
+    inspect_url: Option<Url>,
+
+    /// Number of clients sending operations at the same time
+    #[arg(long, env = "LOADGEN_CONCURRENCY", default_value_t = 8)]
+    concurrency: usize,
+
+    /// How long the load is generated for, such as `5m`
+    #[arg(
+        long,
+        env = "LOADGEN_DURATION",
+        default_value = "1m",
+        value_parser = humane::parse_duration
+    )]
+    duration: Duration,
+
+    /// Timeout of each request
+    #[arg(
+        long,
+        env = "LOADGEN_REQUEST_TIMEOUT",
+        default_value = "30s",
+        value_parser = humane::parse_duration
+    )]
+    request_timeout: Duration,
+
+    /// Relative weight of the walks through the pages of the inputs
+    #[arg(long, env = "LOADGEN_PAGINATION_WEIGHT", default_value_t = 5)]
+    pagination_weight: u32,
+
+    /// Relative weight of the lookups of an input with its outputs
+    #[arg(long, env = "LOADGEN_LOOKUP_WEIGHT", default_value_t = 4)]
+    lookup_weight: u32,
+
+    /// Relative weight of the bursts of inspect requests
+    #[arg(long, env = "LOADGEN_INSPECT_WEIGHT", default_value_t = 1)]
+    inspect_weight: u32,
+
+    /// Number of items of each page of a walk
+    #[arg(long, env = "LOADGEN_PAGE_SIZE", default_value_t = 20)]
+    page_size: u32,
+
+    /// Most pages read by a walk before it starts over
+    #[arg(long, env = "LOADGEN_MAX_PAGES", default_value_t = 10)]
+    max_pages: u32,
+
+    /// Number of inspect requests sent at once by a burst
+    #[arg(long, env = "LOADGEN_INSPECT_BURST", default_value_t = 10)]
+    inspect_burst: usize,
+
+    /// Payload of the inspect requests, in hex
+    #[arg(
+        long,
+        env = "LOADGEN_INSPECT_PAYLOAD",
+        default_value = "0x",
+        value_parser = parse_hex
+    )]
+    // Qualified, so clap parses it as one value rather than many
+    inspect_payload: ::std::vec::Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadgenConfig {
+    pub graphql_url: Option<Url>,
+    pub inspect_url: Option<Url>,
+    pub concurrency: usize,
+    pub duration: Duration,
+    pub request_timeout: Duration,
+    pub mix: Mix,
+    pub page_size: u32,
+    pub max_pages: u32,
+    pub inspect_burst: usize,
+    pub inspect_payload: Vec<u8>,
+}
+
+/// Relative weights of the operations; the ones without a target get none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub pagination: u32,
+    pub lookup: u32,
+    pub inspect: u32,
+}
+
+impl From<CLIConfig> for LoadgenConfig {
+    fn from(cli_config: CLIConfig) -> Self {
+        let weight = |target: &Option<Url>, weight: u32| {
+            if target.is_some() {
+                weight
+            } else {
+                0
+            }
+        };
+        let mix = Mix {
+            pagination: weight(
+                &cli_config.graphql_url,
+                cli_config.pagination_weight,
+            ),
+            lookup: weight(&cli_config.graphql_url, cli_config.lookup_weight),
+            inspect: weight(&cli_config.inspect_url, cli_config.inspect_weight),
+        };
+        Self {
+            graphql_url: cli_config.graphql_url,
+            inspect_url: cli_config.inspect_url,
+            concurrency: cli_config.concurrency.max(1),
+            duration: cli_config.duration,
+            request_timeout: cli_config.request_timeout,
+            mix,
+            page_size: cli_config.page_size.max(1),
+            max_pages: cli_config.max_pages.max(1),
+            inspect_burst: cli_config.inspect_burst.max(1),
+            inspect_payload: cli_config.inspect_payload,
+        }
+    }
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    hex::decode(value.strip_prefix("0x").unwrap_or(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> LoadgenConfig {
+        let mut argv = vec!["cartesi-rollups-loadgen"];
+        argv.extend_from_slice(args);
+        CLIConfig::parse_from(argv).into()
+    }
+
+    #[test]
+    fn it_only_mixes_the_operations_with_a_target() {
+        let inspect_only = config(&["--inspect-url", "http://node/inspect"]);
+        assert_eq!(
+            inspect_only.mix,
+            Mix {
+                pagination: 0,
+                lookup: 0,
+                inspect: 1
+            }
+        );
+
+        let graphql_only = config(&[
+            "--graphql-url",
+            "http://node/graphql",
+            "--lookup-weight",
+            "2",
+        ]);
+        assert_eq!(
+            graphql_only.mix,
+            Mix {
+                pagination: 5,
+                lookup: 2,
+                inspect: 0
+            }
+        );
+    }
+
+    #[test]
+    fn it_parses_the_inspect_payload() {
+        let config = config(&["--inspect-payload", "0xcafe"]);
+        assert_eq!(config.inspect_payload, vec![0xca, 0xfe]);
+        assert!(CLIConfig::try_parse_from([
+            "cartesi-rollups-loadgen",
+            "--inspect-payload",
+            "0xcaf"
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,76 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Load generator for the reader and inspect APIs of a node.
+//!
+//! Each client keeps sending operations picked from a weighted mix, which
+//! walks through pages of inputs, looks up inputs with their outputs and
+//! sends bursts of inspect requests, until the run is over. Every request
+//! is recorded with its latency and, if it failed, why.
+
+use rand::{rngs::StdRng, SeedableRng};
+use snafu::{ensure, ResultExt, Snafu};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+pub mod config;
+mod operations;
+mod stats;
+
+pub use config::{CLIConfig, LoadgenConfig, Mix};
+pub use operations::{Operation, RequestError};
+pub use stats::{Latencies, OperationStats, Report};
+
+use operations::Client;
+
+#[derive(Debug, Snafu)]
+pub enum LoadgenError {
+    #[snafu(display("no operation to send; set a GraphQL or inspect URL"))]
+    NoOperation,
+
+    #[snafu(display("failed to build HTTP client"))]
+    ClientBuilder { source: reqwest::Error },
+}
+
+/// Sends the mix of operations for the duration of the run
+pub async fn run(config: LoadgenConfig) -> Result<Report, LoadgenError> {
+    let mix = config.mix;
+    ensure!(
+        mix.pagination + mix.lookup + mix.inspect > 0,
+        NoOperationSnafu
+    );
+
+    let http = reqwest::Client::builder()
+        .timeout(config.request_timeout)
+        .build()
+        .context(ClientBuilderSnafu)?;
+    let report = Arc::new(Mutex::new(Report::default()));
+    let config = Arc::new(config);
+    let client = Arc::new(Client::new(http, config.clone(), report.clone()));
+
+    let start = Instant::now();
+    let deadline = start + config.duration;
+    let clients = (0..config.concurrency).map(|_| {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let mut rng = StdRng::from_entropy();
+            while Instant::now() < deadline {
+                match Operation::pick(&mix, &mut rng) {
+                    Some(operation) => client.run(operation, &mut rng).await,
+                    None => return,
+                }
+            }
+        })
+    });
+    for client in futures::future::join_all(clients).await {
+        client.expect("load generator client should not panic");
+    }
+
+    let mut report = std::mem::take(
+        &mut *report.lock().expect("Mutex should never be poisoned"),
+    );
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
@@ -0,0 +1,14 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::Parser;
+
+use loadgen::{CLIConfig, LoadgenConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config: LoadgenConfig = CLIConfig::parse().into();
+    let report = loadgen::run(config).await?;
+    print!("{}", report);
+    Ok(())
+}
@@ -0,0 +1,342 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use futures::future::join_all;
+use rand::Rng;
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use crate::config::{LoadgenConfig, Mix};
+use crate::stats::Report;
+
+pub const INPUTS_PAGE: &str = "inputs page";
+pub const INPUT_LOOKUP: &str = "input lookup";
+pub const INSPECT: &str = "inspect";
+
+const INPUTS_PAGE_QUERY: &str = r#"
+query inputsPage($first: Int, $after: String) {
+  inputs(first: $first, after: $after) {
+    totalCount
+    pageInfo { endCursor hasNextPage }
+    edges { node { index msgSender timestamp payload } }
+  }
+}"#;
+
+const INPUT_COUNT_QUERY: &str = r#"
+query inputCount {
+  inputs(first: 0) { totalCount }
+}"#;
+
+const INPUT_LOOKUP_QUERY: &str = r#"
+query inputLookup($index: Int!) {
+  input(index: $index) {
+    index
+    status
+    payload
+    vouchers(first: 5) { totalCount edges { node { index destination payload } } }
+    notices(first: 5) { totalCount edges { node { index payload } } }
+    reports(first: 5) { totalCount edges { node { index payload } } }
+  }
+}"#;
+
+/// Operation of the mix sent by a client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Walks through the pages of the inputs, as a front-end listing them
+    PaginationWalk,
+    /// Looks up a random input with its first outputs
+    Lookup,
+    /// Sends a burst of inspect requests at once
+    InspectBurst,
+}
+
+impl Operation {
+    /// Operation picked with the weights of the mix, if any has a weight
+    pub fn pick(mix: &Mix, rng: &mut impl Rng) -> Option<Self> {
+        let weights = [
+            (Operation::PaginationWalk, mix.pagination),
+            (Operation::Lookup, mix.lookup),
+            (Operation::InspectBurst, mix.inspect),
+        ];
+        let total: u32 = weights.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.gen_range(0..total);
+        for (operation, weight) in weights {
+            if pick < weight {
+                return Some(operation);
+            }
+            pick -= weight;
+        }
+        unreachable!("the pick is below the total weight")
+    }
+}
+
+/// Why a request failed, as broken down in the report
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    Timeout,
+    Connect,
+    Status(StatusCode),
+    GraphQL(String),
+    Inspect(String),
+    Malformed,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Timeout => write!(f, "timeout"),
+            RequestError::Connect => write!(f, "connection failed"),
+            RequestError::Status(status) => write!(f, "HTTP {}", status),
+            RequestError::GraphQL(message) => {
+                write!(f, "GraphQL error: {}", message)
+            }
+            RequestError::Inspect(status) => {
+                write!(f, "inspect status {}", status)
+            }
+            RequestError::Malformed => write!(f, "malformed response"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            RequestError::Timeout
+        } else if let Some(status) = error.status() {
+            RequestError::Status(status)
+        } else if error.is_decode() {
+            RequestError::Malformed
+        } else {
+            RequestError::Connect
+        }
+    }
+}
+
+/// Data of a GraphQL response, or its first error
+pub fn graphql_data(response: Value) -> Result<Value, RequestError> {
+    if let Some(error) = response["errors"].as_array().and_then(|e| e.first()) {
+        let message = error["message"].as_str().unwrap_or("unknown");
+        return Err(RequestError::GraphQL(message.to_owned()));
+    }
+    match response.get("data") {
+        Some(data) if !data.is_null() => Ok(data.clone()),
+        _ => Err(RequestError::Malformed),
+    }
+}
+
+/// Sends the operations of the clients, recording each of their requests
+pub struct Client {
+    http: reqwest::Client,
+    config: Arc<LoadgenConfig>,
+    report: Arc<Mutex<Report>>,
+    /// Number of inputs last seen, to pick the ones looked up
+    input_count: AtomicU64,
+}
+
+impl Client {
+    pub fn new(
+        http: reqwest::Client,
+        config: Arc<LoadgenConfig>,
+        report: Arc<Mutex<Report>>,
+    ) -> Self {
+        Self {
+            http,
+            config,
+            report,
+            input_count: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn run(&self, operation: Operation, rng: &mut impl Rng) {
+        match operation {
+            Operation::PaginationWalk => self.walk_pages().await,
+            Operation::Lookup => self.look_up(rng).await,
+            Operation::InspectBurst => self.inspect_burst().await,
+        }
+    }
+
+    async fn walk_pages(&self) {
+        let mut after = Value::Null;
+        for _ in 0..self.config.max_pages {
+            let variables = json!({
+                "first": self.config.page_size,
+                "after": after,
+            });
+            let Some(data) =
+                self.query(INPUTS_PAGE, INPUTS_PAGE_QUERY, variables).await
+            else {
+                return;
+            };
+            let inputs = &data["inputs"];
+            self.see_input_count(inputs);
+            let page_info = &inputs["pageInfo"];
+            if page_info["hasNextPage"] != Value::Bool(true) {
+                return;
+            }
+            after = page_info["endCursor"].clone();
+        }
+    }
+
+    async fn look_up(&self, rng: &mut impl Rng) {
+        let mut count = self.input_count.load(Ordering::Relaxed);
+        if count == 0 {
+            let data =
+                self.query(INPUT_LOOKUP, INPUT_COUNT_QUERY, json!({})).await;
+            count = match data {
+                Some(data) => self.see_input_count(&data["inputs"]),
+                None => return,
+            };
+            if count == 0 {
+                return;
+            }
+        }
+        let index = rng.gen_range(0..count);
+        let variables = json!({ "index": index });
+        self.query(INPUT_LOOKUP, INPUT_LOOKUP_QUERY, variables)
+            .await;
+    }
+
+    async fn inspect_burst(&self) {
+        let Some(url) = &self.config.inspect_url else {
+            return;
+        };
+        let requests = (0..self.config.inspect_burst).map(|_| async {
+            let start = Instant::now();
+            let result = self.inspect(url).await;
+            self.record(INSPECT, start, result.err());
+        });
+        join_all(requests).await;
+    }
+
+    async fn inspect(&self, url: &Url) -> Result<(), RequestError> {
+        let response = self
+            .http
+            .post(url.clone())
+            .body(self.config.inspect_payload.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let response: Value = response.json().await?;
+        match response["status"].as_str() {
+            Some("Accepted") => Ok(()),
+            Some(status) => Err(RequestError::Inspect(status.to_owned())),
+            None => Err(RequestError::Malformed),
+        }
+    }
+
+    /// Sends a GraphQL query, returning its data if it succeeded
+    async fn query(
+        &self,
+        operation: &'static str,
+        query: &str,
+        variables: Value,
+    ) -> Option<Value> {
+        let url = self.config.graphql_url.as_ref()?;
+        let start = Instant::now();
+        let result = self.send_query(url, query, variables).await;
+        match result {
+            Ok(data) => {
+                self.record(operation, start, None);
+                Some(data)
+            }
+            Err(error) => {
+                self.record(operation, start, Some(error));
+                None
+            }
+        }
+    }
+
+    async fn send_query(
+        &self,
+        url: &Url,
+        query: &str,
+        variables: Value,
+    ) -> Result<Value, RequestError> {
+        let body = json!({ "query": query, "variables": variables });
+        let response = self
+            .http
+            .post(url.clone())
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        graphql_data(response.json().await?)
+    }
+
+    fn see_input_count(&self, inputs: &Value) -> u64 {
+        let count = inputs["totalCount"].as_u64().unwrap_or(0);
+        self.input_count.store(count, Ordering::Relaxed);
+        count
+    }
+
+    fn record(
+        &self,
+        operation: &'static str,
+        start: Instant,
+        error: Option<RequestError>,
+    ) {
+        self.report
+            .lock()
+            .expect("Mutex should never be poisoned")
+            .record(operation, start.elapsed(), error.map(|e| e.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn it_picks_only_the_operations_with_a_weight() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mix = Mix {
+            pagination: 0,
+            lookup: 3,
+            inspect: 1,
+        };
+        let picks: Vec<_> = (0..100)
+            .map(|_| Operation::pick(&mix, &mut rng).unwrap())
+            .collect();
+        assert!(!picks.contains(&Operation::PaginationWalk));
+        assert!(picks.contains(&Operation::Lookup));
+        assert!(picks.contains(&Operation::InspectBurst));
+
+        let none = Mix {
+            pagination: 0,
+            lookup: 0,
+            inspect: 0,
+        };
+        assert_eq!(Operation::pick(&none, &mut rng), None);
+    }
+
+    #[test]
+    fn it_reads_the_data_or_the_first_error() {
+        let data = json!({"data": {"inputs": {"totalCount": 3}}});
+        assert_eq!(
+            graphql_data(data),
+            Ok(json!({"inputs": {"totalCount": 3}}))
+        );
+
+        let errors = json!({
+            "data": null,
+            "errors": [{"message": "Input not found"}, {"message": "other"}],
+        });
+        assert_eq!(
+            graphql_data(errors),
+            Err(RequestError::GraphQL("Input not found".to_owned()))
+        );
+        assert_eq!(graphql_data(json!({})), Err(RequestError::Malformed));
+    }
+}
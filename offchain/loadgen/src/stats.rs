@@ -0,0 +1,222 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+/// Histogram buckets are up to 2^16 ms, after which they all go to the
+/// last one
+const MAX_LOG2_MILLIS: usize = 16;
+
+/// Widest bar of a histogram bucket
+const BAR_WIDTH: u64 = 40;
+
+/// Latencies of the requests of an operation
+#[derive(Clone, Debug, Default)]
+pub struct Latencies {
+    samples: Vec<Duration>,
+    sorted: bool,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+        self.sorted = false;
+    }
+
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.samples.iter().sum::<Duration>() / count as u32,
+        }
+    }
+
+    /// Latency under which the fraction `quantile` of the requests finished
+    pub fn percentile(&mut self, quantile: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        if !self.sorted {
+            self.samples.sort_unstable();
+            self.sorted = true;
+        }
+        let rank = (quantile * self.count() as f64).ceil() as usize;
+        self.samples[rank.clamp(1, self.count()) - 1]
+    }
+
+    /// Number of requests that took under each power of two milliseconds
+    pub fn histogram(&self) -> Vec<(Duration, u64)> {
+        let mut counts = [0; MAX_LOG2_MILLIS + 1];
+        for latency in &self.samples {
+            let millis = latency.as_millis();
+            let bucket = (u128::BITS - millis.leading_zeros()) as usize;
+            counts[bucket.min(MAX_LOG2_MILLIS)] += 1;
+        }
+        let first = counts.iter().position(|&count| count != 0);
+        let last = counts.iter().rposition(|&count| count != 0);
+        match (first, last) {
+            (Some(first), Some(last)) => (first..=last)
+                .map(|bucket| {
+                    (Duration::from_millis(1 << bucket), counts[bucket])
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Latencies and errors of the requests of an operation
+#[derive(Clone, Debug, Default)]
+pub struct OperationStats {
+    pub latencies: Latencies,
+    /// Number of errors of each kind
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl OperationStats {
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+/// Results of a run, by operation
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub elapsed: Duration,
+    pub operations: BTreeMap<&'static str, OperationStats>,
+}
+
+impl Report {
+    pub fn record(
+        &mut self,
+        operation: &'static str,
+        latency: Duration,
+        error: Option<String>,
+    ) {
+        let stats = self.operations.entry(operation).or_default();
+        stats.latencies.record(latency);
+        if let Some(error) = error {
+            *stats.errors.entry(error).or_default() += 1;
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Ran for {:.1}s", self.elapsed.as_secs_f64())?;
+        writeln!(
+            f,
+            "\n{:<14} {:>9} {:>7} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "operation",
+            "requests",
+            "errors",
+            "req/s",
+            "mean",
+            "p50",
+            "p90",
+            "p99",
+            "max"
+        )?;
+        let elapsed = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        for (operation, stats) in &self.operations {
+            let mut latencies = stats.latencies.clone();
+            writeln!(
+                f,
+                "{:<14} {:>9} {:>7} {:>8.1} {:>9} {:>9} {:>9} {:>9} {:>9}",
+                operation,
+                latencies.count(),
+                stats.error_count(),
+                latencies.count() as f64 / elapsed,
+                millis(latencies.mean()),
+                millis(latencies.percentile(0.5)),
+                millis(latencies.percentile(0.9)),
+                millis(latencies.percentile(0.99)),
+                millis(latencies.percentile(1.0)),
+            )?;
+        }
+
+        for (operation, stats) in &self.operations {
+            writeln!(f, "\n{} latencies", operation)?;
+            let histogram = stats.latencies.histogram();
+            let widest = histogram.iter().map(|(_, count)| *count).max();
+            for (bound, count) in &histogram {
+                let bar = count * BAR_WIDTH / widest.unwrap_or(1).max(1);
+                writeln!(
+                    f,
+                    "  < {:>8} {:>9} {}",
+                    millis(*bound),
+                    count,
+                    "#".repeat(bar as usize)
+                )?;
+            }
+            if !stats.errors.is_empty() {
+                writeln!(f, "{} errors", operation)?;
+                for (error, count) in &stats.errors {
+                    writeln!(f, "  {:>9} {}", count, error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latencies(millis: &[u64]) -> Latencies {
+        let mut latencies = Latencies::default();
+        for millis in millis {
+            latencies.record(Duration::from_millis(*millis));
+        }
+        latencies
+    }
+
+    #[test]
+    fn it_computes_the_percentiles() {
+        let mut latencies =
+            latencies(&[40, 10, 30, 20, 50, 60, 70, 80, 90, 100]);
+        assert_eq!(latencies.percentile(0.5), Duration::from_millis(50));
+        assert_eq!(latencies.percentile(0.9), Duration::from_millis(90));
+        assert_eq!(latencies.percentile(1.0), Duration::from_millis(100));
+        assert_eq!(latencies.percentile(0.0), Duration::from_millis(10));
+        assert_eq!(latencies.mean(), Duration::from_millis(55));
+        assert_eq!(Latencies::default().percentile(0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn it_buckets_the_latencies_by_powers_of_two() {
+        let latencies = latencies(&[3, 5, 6, 7, 200_000]);
+        let histogram = latencies.histogram();
+        assert_eq!(histogram.first(), Some(&(Duration::from_millis(4), 1)));
+        assert_eq!(histogram[1], (Duration::from_millis(8), 3));
+        assert_eq!(
+            histogram.last(),
+            Some(&(Duration::from_millis(1 << MAX_LOG2_MILLIS), 1))
+        );
+        assert!(Latencies::default().histogram().is_empty());
+    }
+
+    #[test]
+    fn it_breaks_down_the_errors() {
+        let mut report = Report::default();
+        let latency = Duration::from_millis(1);
+        report.record("inspect", latency, None);
+        report.record("inspect", latency, Some("HTTP 503".to_owned()));
+        report.record("inspect", latency, Some("HTTP 503".to_owned()));
+        report.record("inspect", latency, Some("timeout".to_owned()));
+
+        let stats = &report.operations["inspect"];
+        assert_eq!(stats.latencies.count(), 4);
+        assert_eq!(stats.error_count(), 3);
+        assert_eq!(stats.errors["HTTP 503"], 2);
+        assert!(report.to_string().contains("inspect errors"));
+    }
+}
@@ -1,10 +1,18 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::OnceLock};
 
-use clap::Parser;
-use tracing::info;
-use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use clap::{Parser, ValueEnum};
+use snafu::{OptionExt, ResultExt, Snafu};
+use tracing::{info, Subscriber};
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter, ParseError},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    Layer, Registry,
+};
 
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -18,14 +26,50 @@ pub struct LogEnvCliConfig {
 
     #[arg(long, env, default_value = "false")]
     pub log_enable_color: bool,
+
+    /// Format of the log lines
+    #[arg(long, env, value_enum, default_value_t = LogFormat::Compact)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One line per event
+    #[default]
+    Compact,
+    /// Multiple lines per event, for humans reading the output directly
+    Pretty,
+    /// One JSON object per event, for log collectors
+    Json,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct LogConfig {
     pub enable_timestamp: bool,
     pub enable_color: bool,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Snafu)]
+pub enum LogFilterError {
+    #[snafu(display("logging was not configured"))]
+    NotConfigured,
+
+    #[snafu(display("invalid log filter `{}`: {}", directives, source))]
+    InvalidFilter {
+        directives: String,
+        source: ParseError,
+    },
+
+    #[snafu(display("failed to swap the log filter"))]
+    Reload { source: reload::Error },
 }
 
+/// Handle to the filter installed by `configure`, used to change the log
+/// levels while the service is running
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> =
+    OnceLock::new();
+
 impl LogConfig {
     pub fn initialize(env_cli_config: LogEnvCliConfig) -> Self {
         let enable_timestamp = env_cli_config.log_enable_timestamp;
@@ -35,6 +79,7 @@ impl LogConfig {
         LogConfig {
             enable_timestamp,
             enable_color,
+            format: env_cli_config.log_format,
         }
     }
 }
@@ -49,19 +94,51 @@ pub fn configure(config: &LogConfig) {
     let filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = FILTER_HANDLE.set(handle);
 
-    let subscribe_builder = tracing_subscriber::fmt()
-        .compact()
-        .with_env_filter(filter)
-        .with_ansi(config.enable_color);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(config))
+        .init();
+}
 
-    if !config.enable_timestamp {
-        subscribe_builder.without_time().init();
-    } else {
-        subscribe_builder.init();
+fn fmt_layer<S>(config: &LogConfig) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer().with_ansi(config.enable_color);
+    match (config.format, config.enable_timestamp) {
+        (LogFormat::Compact, true) => layer.compact().boxed(),
+        (LogFormat::Compact, false) => layer.compact().without_time().boxed(),
+        (LogFormat::Pretty, true) => layer.pretty().boxed(),
+        (LogFormat::Pretty, false) => layer.pretty().without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().boxed(),
+        (LogFormat::Json, false) => layer.json().without_time().boxed(),
     }
 }
 
+/// Returns the directives of the current log filter
+pub fn log_filter() -> Result<String, LogFilterError> {
+    let handle = FILTER_HANDLE.get().context(NotConfiguredSnafu)?;
+    handle
+        .with_current(|filter| filter.to_string())
+        .context(ReloadSnafu)
+}
+
+/// Replaces the log filter with `directives`, such as
+/// `info,authority_claimer::checker=trace`, without restarting the service
+pub fn set_log_filter(directives: &str) -> Result<(), LogFilterError> {
+    let handle = FILTER_HANDLE.get().context(NotConfiguredSnafu)?;
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse(directives)
+        .context(InvalidFilterSnafu { directives })?;
+    handle.reload(filter).context(ReloadSnafu)?;
+    info!("Log filter set to `{}`", directives);
+    Ok(())
+}
+
 pub fn log_service_start<C: Debug>(config: &C, service_name: &str) {
     let git_ref = built_info::GIT_HEAD_REF.unwrap_or("N/A");
     let git_hash = built_info::GIT_COMMIT_HASH.unwrap_or("N/A");
@@ -1,9 +1,13 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+mod payload;
+
 use std::fmt;
 pub use url::{self, Url};
 
+pub use payload::{PayloadPolicy, PayloadPolicyCLIConfig, PayloadRedaction};
+
 /// Wrapper that redacts the entire field
 #[derive(Clone)]
 pub struct Redacted<T: Clone>(T);
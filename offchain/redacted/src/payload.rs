@@ -0,0 +1,186 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Redaction of the input payloads of privacy-sensitive DApps, which is
+//! applied wherever the node shows them to the public: the reader APIs and
+//! the logs.
+
+use clap::Parser;
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+use std::{borrow::Cow, fmt, str::FromStr};
+
+/// Value that replaces the masked fields of the decoded payloads
+const MASK: &str = "[REDACTED]";
+
+/// How the raw input payloads are shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadRedaction {
+    /// As they are
+    #[default]
+    None,
+    /// As their Keccak-256 hash
+    Hash,
+    /// As their first bytes
+    Truncate(usize),
+}
+
+impl FromStr for PayloadRedaction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "hash" => Ok(Self::Hash),
+            _ => value
+                .strip_prefix("truncate:")
+                .and_then(|length| length.parse().ok())
+                .map(Self::Truncate)
+                .ok_or_else(|| {
+                    format!(
+                        "expected `none`, `hash` or `truncate:<bytes>`, got `{}`",
+                        value
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for PayloadRedaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Hash => write!(f, "hash"),
+            Self::Truncate(length) => write!(f, "truncate:{}", length),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "payload_policy_config")]
+pub struct PayloadPolicyCLIConfig {
+    /// How the input payloads are shown to the public: `none`, `hash` for
+    /// their Keccak-256 hash, or `truncate:<bytes>` for their first bytes.
+    /// Decoded payloads are hidden along with them, unless masks are set
+    #[arg(long, env, default_value = "none")]
+    pub payload_redaction: PayloadRedaction,
+
+    /// Comma-separated JSON pointers of the fields of the decoded payloads
+    /// that are masked, such as `/user/email`, so the other fields are still
+    /// shown while the payloads are redacted
+    #[arg(long, env, value_delimiter = ',')]
+    pub payload_redaction_masks: Vec<String>,
+}
+
+/// Redaction of the input payloads shown to the public. Operators with
+/// access to the full data get `PayloadPolicy::default()`, which shows the
+/// payloads as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PayloadPolicy {
+    pub redaction: PayloadRedaction,
+    /// JSON pointers of the fields masked in the decoded payloads, which are
+    /// only shown when there are some
+    pub masks: Vec<String>,
+}
+
+impl From<PayloadPolicyCLIConfig> for PayloadPolicy {
+    fn from(cli_config: PayloadPolicyCLIConfig) -> Self {
+        Self {
+            redaction: cli_config.payload_redaction,
+            masks: cli_config.payload_redaction_masks,
+        }
+    }
+}
+
+impl PayloadPolicy {
+    pub fn is_redacting(&self) -> bool {
+        self.redaction != PayloadRedaction::None
+    }
+
+    /// Payload as shown to the public
+    pub fn payload<'a>(&self, payload: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.redaction {
+            PayloadRedaction::None => Cow::Borrowed(payload),
+            PayloadRedaction::Hash => {
+                Cow::Owned(Keccak256::digest(payload).to_vec())
+            }
+            PayloadRedaction::Truncate(length) => {
+                Cow::Borrowed(&payload[..length.min(payload.len())])
+            }
+        }
+    }
+
+    /// Decoded payload as shown to the public, which is hidden when the
+    /// payloads are redacted unless it is a JSON document with masks
+    pub fn decoded_payload(&self, decoded: Option<&str>) -> Option<String> {
+        let decoded = decoded?;
+        if !self.is_redacting() {
+            return Some(decoded.to_owned());
+        }
+        if self.masks.is_empty() {
+            return None;
+        }
+        let mut document: Value = serde_json::from_str(decoded).ok()?;
+        for mask in &self.masks {
+            if let Some(field) = document.pointer_mut(mask) {
+                *field = Value::String(MASK.to_owned());
+            }
+        }
+        Some(document.to_string())
+    }
+}
+
+#[test]
+fn parses_the_redactions() {
+    for redaction in ["none", "hash", "truncate:4"] {
+        let parsed: PayloadRedaction = redaction.parse().unwrap();
+        assert_eq!(parsed.to_string(), redaction);
+    }
+    assert!("truncate:".parse::<PayloadRedaction>().is_err());
+    assert!("mask".parse::<PayloadRedaction>().is_err());
+}
+
+#[test]
+fn redacts_the_payloads() {
+    let payload = b"hello world";
+    assert_eq!(&*PayloadPolicy::default().payload(payload), payload);
+
+    let truncate = PayloadPolicy {
+        redaction: PayloadRedaction::Truncate(5),
+        masks: vec![],
+    };
+    assert_eq!(&*truncate.payload(payload), b"hello");
+    assert_eq!(&*truncate.payload(b"hi"), b"hi");
+
+    let hash = PayloadPolicy {
+        redaction: PayloadRedaction::Hash,
+        masks: vec![],
+    };
+    assert_eq!(hash.payload(payload).len(), 32);
+    assert_ne!(hash.payload(payload), hash.payload(b"hello"));
+}
+
+#[test]
+fn masks_the_fields_of_the_decoded_payloads() {
+    let decoded = r#"{"user":{"email":"a@b.c","name":"A"},"amount":1}"#;
+    assert_eq!(
+        PayloadPolicy::default().decoded_payload(Some(decoded)),
+        Some(decoded.to_owned())
+    );
+
+    let mut policy = PayloadPolicy {
+        redaction: PayloadRedaction::Hash,
+        masks: vec![],
+    };
+    assert_eq!(policy.decoded_payload(Some(decoded)), None);
+
+    policy.masks = vec!["/user/email".to_owned(), "/missing".to_owned()];
+    let masked: Value =
+        serde_json::from_str(&policy.decoded_payload(Some(decoded)).unwrap())
+            .unwrap();
+    assert_eq!(masked["user"]["email"], MASK);
+    assert_eq!(masked["user"]["name"], "A");
+    assert_eq!(masked["amount"], 1);
+    assert_eq!(policy.decoded_payload(Some("not json")), None);
+    assert_eq!(policy.decoded_payload(None), None);
+}
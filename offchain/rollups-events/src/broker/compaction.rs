@@ -0,0 +1,280 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Consumer progress and compaction of the streams
+//!
+//! The consumers of a stream register under a name and acknowledge the id
+//! of the last event they won't need again, even after a restart. They are
+//! kept in a hash next to the stream, in the same hash slot, and a stream is
+//! only trimmed up to the slowest of them. A consumer that replays the
+//! stream from the start on every run registers without acknowledging, so
+//! the stream is kept whole for it. The consumers expected to read a stream
+//! are declared up front, and the stream isn't trimmed until all of them
+//! registered, so a consumer that starts after the producers doesn't miss
+//! the events trimmed before it registered.
+//!
+//! A maximum lag bounds how many events are kept regardless: the consumers
+//! behind it lose the trimmed events and are reported as lagging. The newest
+//! event is never trimmed, since the producers read their position from it.
+use backoff::future::retry;
+use redis::streams::StreamRangeReply;
+use redis::AsyncCommands;
+use snafu::ResultExt;
+use std::collections::HashMap;
+
+use super::ConnectionSnafu;
+use crate::{Broker, BrokerError, BrokerStream, INITIAL_ID};
+
+/// Outcome of compacting a stream
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CompactionReport {
+    /// Number of events trimmed
+    pub trimmed: usize,
+    /// Consumers that lost unacknowledged events to the maximum lag
+    pub lagging_consumers: Vec<String>,
+    /// Expected consumers that haven't registered yet, which keep the
+    /// stream from being trimmed
+    pub unregistered_consumers: Vec<String>,
+}
+
+/// Key of the hash with the progress of the consumers of the stream
+fn consumers_key<S: BrokerStream>(stream: &S) -> String {
+    format!("{}:consumers", stream.key())
+}
+
+/// Parses a stream id, such as `1700000000000-3`, into comparable parts
+fn parse_id(id: &str) -> Option<(u64, u64)> {
+    match id.split_once('-') {
+        Some((ms, seq)) => Some((ms.parse().ok()?, seq.parse().ok()?)),
+        None => Some((id.parse().ok()?, 0)),
+    }
+}
+
+/// Lowest id of the events after the given one
+fn next_id((ms, seq): (u64, u64)) -> (u64, u64) {
+    match seq.checked_add(1) {
+        Some(seq) => (ms, seq),
+        None => (ms + 1, 0),
+    }
+}
+
+/// Lowest id that is kept when compacting a stream whose consumers are at
+/// `acknowledged`, given the id of the newest event that fits in the maximum
+/// lag, if any is set and exceeded, and the id of the newest event
+fn min_kept_id(
+    acknowledged: &[(u64, u64)],
+    lag_limit: Option<(u64, u64)>,
+    newest: (u64, u64),
+) -> Option<(u64, u64)> {
+    let by_consumers = acknowledged.iter().min().copied().map(next_id);
+    let min_id = match (by_consumers, lag_limit) {
+        (Some(by_consumers), Some(lag_limit)) => by_consumers.max(lag_limit),
+        (by_consumers, lag_limit) => by_consumers.or(lag_limit)?,
+    };
+    Some(min_id.min(newest))
+}
+
+impl Broker {
+    /// Register a consumer of the stream, if it isn't yet, and return the id
+    /// of the last event it acknowledged, from where it should resume
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn register_consumer<S: BrokerStream>(
+        &mut self,
+        stream: &S,
+        consumer: &str,
+    ) -> Result<String, BrokerError> {
+        let key = consumers_key(stream);
+        let last_id = retry(self.backoff.clone(), || async {
+            tracing::trace!(%key, consumer, "registering consumer");
+            let mut connection = self.connection.clone();
+            let _: bool =
+                connection.hset_nx(&key, consumer, INITIAL_ID).await?;
+            let last_id: String = connection.hget(&key, consumer).await?;
+            Ok(last_id)
+        })
+        .await
+        .context(ConnectionSnafu)?;
+
+        tracing::trace!(last_id, "returning last acknowledged id");
+        Ok(last_id)
+    }
+
+    /// Acknowledge that the consumer won't need the events of the stream up
+    /// to the given id again
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn acknowledge<S: BrokerStream>(
+        &mut self,
+        stream: &S,
+        consumer: &str,
+        id: &str,
+    ) -> Result<(), BrokerError> {
+        let key = consumers_key(stream);
+        retry(self.backoff.clone(), || async {
+            tracing::trace!(%key, consumer, id, "acknowledging event");
+            let _: () =
+                self.connection.clone().hset(&key, consumer, id).await?;
+            Ok(())
+        })
+        .await
+        .context(ConnectionSnafu)
+    }
+
+    /// Trim the events of the stream that every registered consumer
+    /// acknowledged, and the ones beyond `max_lag` of the newest event.
+    /// Nothing is trimmed until every one of `expected_consumers` registered.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn compact<S: BrokerStream>(
+        &mut self,
+        stream: &S,
+        expected_consumers: &[String],
+        max_lag: Option<usize>,
+    ) -> Result<CompactionReport, BrokerError> {
+        let key = consumers_key(stream);
+        let consumers: HashMap<String, String> =
+            retry(self.backoff.clone(), || async {
+                tracing::trace!(%key, "reading consumer progress");
+                Ok(self.connection.clone().hgetall(&key).await?)
+            })
+            .await
+            .context(ConnectionSnafu)?;
+        let unregistered_consumers: Vec<String> = expected_consumers
+            .iter()
+            .filter(|consumer| !consumers.contains_key(*consumer))
+            .cloned()
+            .collect();
+        if !unregistered_consumers.is_empty() {
+            tracing::trace!(
+                ?unregistered_consumers,
+                "expected consumers not registered; nothing to compact"
+            );
+            return Ok(CompactionReport {
+                unregistered_consumers,
+                ..Default::default()
+            });
+        }
+        let consumers: Vec<(String, (u64, u64))> = consumers
+            .into_iter()
+            .filter_map(|(consumer, id)| match parse_id(&id) {
+                Some(id) => Some((consumer, id)),
+                None => {
+                    tracing::warn!(
+                        stream_key = stream.key(),
+                        %consumer,
+                        %id,
+                        "ignoring consumer with invalid acknowledged id"
+                    );
+                    None
+                }
+            })
+            .collect();
+        if consumers.is_empty() && max_lag.is_none() {
+            tracing::trace!("no registered consumers; nothing to compact");
+            return Ok(CompactionReport::default());
+        }
+
+        // The newest event and, past it, the newest one beyond the lag
+        let count = max_lag.map_or(1, |max_lag| max_lag.max(1) + 1);
+        let reply: StreamRangeReply = retry(self.backoff.clone(), || async {
+            tracing::trace!(stream_key = stream.key(), "reading newest events");
+            Ok(self
+                .connection
+                .clone()
+                .xrevrange_count(stream.key(), "+", "-", count)
+                .await?)
+        })
+        .await
+        .context(ConnectionSnafu)?;
+        let ids: Vec<(u64, u64)> =
+            reply.ids.iter().filter_map(|id| parse_id(&id.id)).collect();
+        let Some(&newest) = ids.first() else {
+            tracing::trace!("stream is empty; nothing to compact");
+            return Ok(CompactionReport::default());
+        };
+        let lag_limit = (ids.len() == count && max_lag.is_some())
+            .then(|| next_id(ids[count - 1]));
+
+        let acknowledged: Vec<_> =
+            consumers.iter().map(|(_, id)| *id).collect();
+        let Some(min_id) = min_kept_id(&acknowledged, lag_limit, newest) else {
+            return Ok(CompactionReport::default());
+        };
+        let lagging_consumers: Vec<String> = consumers
+            .into_iter()
+            .filter(|(_, id)| next_id(*id) < min_id)
+            .map(|(consumer, _)| consumer)
+            .collect();
+
+        let min_id = format!("{}-{}", min_id.0, min_id.1);
+        let trimmed: usize = retry(self.backoff.clone(), || async {
+            tracing::trace!(stream_key = stream.key(), %min_id, "trimming");
+            let trimmed = redis::cmd("XTRIM")
+                .arg(stream.key())
+                .arg("MINID")
+                .arg(&min_id)
+                .query_async(&mut self.connection.clone())
+                .await?;
+            Ok(trimmed)
+        })
+        .await
+        .context(ConnectionSnafu)?;
+
+        for consumer in lagging_consumers.iter() {
+            tracing::warn!(
+                stream_key = stream.key(),
+                %consumer,
+                %min_id,
+                "consumer is beyond the maximum lag and lost events"
+            );
+        }
+        tracing::trace!(trimmed, "compacted stream");
+        Ok(CompactionReport {
+            trimmed,
+            lagging_consumers,
+            unregistered_consumers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_stream_ids() {
+        assert_eq!(parse_id(INITIAL_ID), Some((0, 0)));
+        assert_eq!(parse_id("1700000000000-3"), Some((1700000000000, 3)));
+        assert_eq!(parse_id("1700000000000-x"), None);
+        assert_eq!(next_id((5, u64::MAX)), (6, 0));
+    }
+
+    #[test]
+    fn it_keeps_the_events_of_the_slowest_consumer() {
+        let newest = (30, 0);
+        assert_eq!(
+            min_kept_id(&[(20, 1), (10, 0)], None, newest),
+            Some((10, 1))
+        );
+        // A consumer that never acknowledged keeps the whole stream
+        assert_eq!(min_kept_id(&[(20, 1), (0, 0)], None, newest), Some((0, 1)));
+        assert_eq!(min_kept_id(&[], None, newest), None);
+    }
+
+    #[test]
+    fn it_applies_the_maximum_lag() {
+        let newest = (30, 0);
+        assert_eq!(
+            min_kept_id(&[(10, 0)], Some((25, 0)), newest),
+            Some((25, 0))
+        );
+        assert_eq!(
+            min_kept_id(&[(28, 0)], Some((25, 0)), newest),
+            Some((28, 1))
+        );
+        assert_eq!(min_kept_id(&[], Some((25, 0)), newest), Some((25, 0)));
+    }
+
+    #[test]
+    fn it_never_trims_the_newest_event() {
+        assert_eq!(min_kept_id(&[(30, 0)], None, (30, 0)), Some((30, 0)));
+    }
+}
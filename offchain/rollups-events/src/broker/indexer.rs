@@ -43,7 +43,42 @@ impl IndexerState {
     }
 }
 
+/// Name under which the indexer acknowledges the events it stored
+const CONSUMER: &str = "indexer";
+
 impl Broker {
+    /// Register the indexer as a consumer of the input and output streams
+    /// and resume from the last events it acknowledged
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn indexer_register(
+        &mut self,
+        state: &mut IndexerState,
+    ) -> Result<(), BrokerError> {
+        state.inputs_last_id = self
+            .register_consumer(&state.inputs_stream, CONSUMER)
+            .await?;
+        state.outputs_last_id = self
+            .register_consumer(&state.outputs_stream, CONSUMER)
+            .await?;
+        Ok(())
+    }
+
+    /// Acknowledge the events consumed so far, once they are stored
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn indexer_acknowledge(
+        &mut self,
+        state: &IndexerState,
+    ) -> Result<(), BrokerError> {
+        self.acknowledge(&state.inputs_stream, CONSUMER, &state.inputs_last_id)
+            .await?;
+        self.acknowledge(
+            &state.outputs_stream,
+            CONSUMER,
+            &state.outputs_last_id,
+        )
+        .await
+    }
+
     /// Consume an event from the Input stream and if there is none,
     /// consume from the Output stream. This is a blocking operation.
     /// Return IndexerEvent::Input if present or IndexerEvent::Output otherwise
@@ -65,7 +100,7 @@ impl Broker {
             );
             let opts = StreamReadOptions::default()
                 .count(1)
-                .block(self.consume_timeout);
+                .block(self.consume_timeout.as_millis() as usize);
             let reply: StreamReadReply = self
                 .connection
                 .clone()
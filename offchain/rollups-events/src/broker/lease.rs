@@ -0,0 +1,88 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Leases with fencing tokens
+//!
+//! A lease is held by one holder at a time, such as the one node of a
+//! failover group that sends claims, for a time to live that the holder
+//! keeps renewing. Each time it is acquired, it gets a fencing token higher
+//! than the previous ones. A holder that sees its token change lost the
+//! lease in between, even if it holds it again, and must stop acting on it.
+//!
+//! The lease and its token counter share a hash tag, so the script that
+//! updates them runs on a single node of a Redis Cluster too.
+use redis::Script;
+use snafu::ResultExt;
+use std::time::Duration;
+
+use super::ConnectionSnafu;
+use crate::{Broker, BrokerError};
+
+/// Renews the lease if its holder is `ARGV[1]`, or acquires it with a new
+/// token if it is free, and returns the token; returns nil if another
+/// holder has it. The lease is kept as `<holder>:<token>`.
+const ACQUIRE_SCRIPT: &str = r"
+local current = redis.call('GET', KEYS[1])
+if current then
+    local holder, token = string.match(current, '^(.*):(%d+)$')
+    if holder ~= ARGV[1] then
+        return false
+    end
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return tonumber(token)
+end
+local token = redis.call('INCR', KEYS[2])
+redis.call('SET', KEYS[1], ARGV[1] .. ':' .. token, 'PX', ARGV[2])
+return token
+";
+
+/// Keys of the lease and of its token counter
+fn lease_keys(lease: &str) -> (String, String) {
+    (
+        format!("{{{}}}:lease", lease),
+        format!("{{{}}}:fencing-token", lease),
+    )
+}
+
+impl Broker {
+    /// Acquire the lease for the holder, or renew it if the holder already
+    /// has it, for the given time to live, and return its fencing token.
+    /// Return `None` while another holder has it.
+    ///
+    /// Unlike the other operations, this one isn't retried, since the lease
+    /// may expire before a retry succeeds; the holder decides when to try
+    /// again.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn acquire_lease(
+        &mut self,
+        lease: &str,
+        holder: &str,
+        ttl: Duration,
+    ) -> Result<Option<u64>, BrokerError> {
+        let (lease_key, token_key) = lease_keys(lease);
+        tracing::trace!(%lease_key, holder, ?ttl, "acquiring lease");
+        let token = Script::new(ACQUIRE_SCRIPT)
+            .key(&lease_key)
+            .key(&token_key)
+            .arg(holder)
+            .arg(ttl.as_millis().max(1) as u64)
+            .invoke_async(&mut self.connection)
+            .await
+            .context(ConnectionSnafu)?;
+
+        tracing::trace!(?token, "returning fencing token");
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_the_lease_and_its_token_in_the_same_slot() {
+        let (lease_key, token_key) = lease_keys("claims");
+        assert_eq!(lease_key, "{claims}:lease");
+        assert_eq!(token_key, "{claims}:fencing-token");
+    }
+}
@@ -19,10 +19,19 @@ use std::time::Duration;
 
 pub use redacted::{RedactedUrl, Url};
 
+pub mod compaction;
 pub mod indexer;
+mod lease;
 
 pub const INITIAL_ID: &str = "0";
 
+/// Version of the schema of the event payloads, stored next to the payload
+/// of each event. Fields added with a serde default keep the version, so
+/// only incompatible changes bump it, and consumers refuse the events of a
+/// newer version instead of misreading them. Events without a version
+/// predate it and are taken as the first one.
+pub const SCHEMA_VERSION: u32 = 1;
+
 /// The `BrokerConnection` enum implements the `ConnectionLike` trait
 /// to satisfy the `AsyncCommands` trait bounds.
 /// As `AsyncCommands` requires its implementors to be `Sized`, we couldn't
@@ -77,7 +86,7 @@ impl ConnectionLike for BrokerConnection {
 pub struct Broker {
     connection: BrokerConnection,
     backoff: ExponentialBackoff,
-    consume_timeout: usize,
+    consume_timeout: Duration,
 }
 
 impl Broker {
@@ -134,6 +143,7 @@ impl Broker {
         let payload =
             serde_json::to_string(&payload).context(InvalidPayloadSnafu)?;
 
+        let version = SCHEMA_VERSION.to_string();
         let event_id = retry(self.backoff.clone(), || async {
             tracing::trace!(
                 stream_key = stream.key(),
@@ -143,7 +153,11 @@ impl Broker {
             let event_id = self
                 .connection
                 .clone()
-                .xadd(stream.key(), "*", &[("payload", &payload)])
+                .xadd(
+                    stream.key(),
+                    "*",
+                    &[("payload", &payload), ("version", &version)],
+                )
                 .await?;
 
             Ok(event_id)
@@ -198,7 +212,7 @@ impl Broker {
             );
             let opts = StreamReadOptions::default()
                 .count(1)
-                .block(self.consume_timeout);
+                .block(self.consume_timeout.as_millis() as usize);
             let reply: StreamReadReply = self
                 .connection
                 .clone()
@@ -280,6 +294,25 @@ impl Broker {
             Ok(None)
         }
     }
+
+    /// Delete the stream and all its events
+    /// Return whether the stream existed.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn delete_stream<S: BrokerStream>(
+        &mut self,
+        stream: &S,
+    ) -> Result<bool, BrokerError> {
+        let deleted: usize = retry(self.backoff.clone(), || async {
+            tracing::trace!(stream_key = stream.key(), "deleting stream");
+            let deleted = self.connection.clone().del(stream.key()).await?;
+            Ok(deleted)
+        })
+        .await
+        .context(ConnectionSnafu)?;
+
+        tracing::trace!(deleted, "returning whether stream was deleted");
+        Ok(deleted > 0)
+    }
 }
 
 /// Custom implementation of Debug because ConnectionManager doesn't implement debug
@@ -315,9 +348,19 @@ impl<P: Serialize + DeserializeOwned + Clone + Eq + PartialEq> TryFrom<StreamId>
         let payload = stream_id
             .get::<String>("payload")
             .ok_or(BrokerError::InvalidEvent)?;
+        let version = match stream_id.get::<String>("version") {
+            Some(version) => {
+                version.parse().map_err(|_| BrokerError::InvalidEvent)?
+            }
+            None => 1,
+        };
+        snafu::ensure!(
+            version <= SCHEMA_VERSION,
+            UnsupportedVersionSnafu { version }
+        );
         let id = stream_id.id;
 
-        tracing::trace!(id, payload, "received event");
+        tracing::trace!(id, payload, version, "received event");
 
         tracing::trace!("parsing JSON payload");
         let payload =
@@ -344,6 +387,13 @@ pub enum BrokerError {
 
     #[snafu(display("error parsing event payload"))]
     InvalidPayload { source: serde_json::Error },
+
+    #[snafu(display(
+        "event payload has schema version {}, but only up to {} is supported",
+        version,
+        SCHEMA_VERSION
+    ))]
+    UnsupportedVersion { version: u32 },
 }
 
 #[derive(Debug, Parser)]
@@ -360,13 +410,24 @@ pub struct BrokerCLIConfig {
     #[arg(long, env, num_args = 1.., value_delimiter = ',')]
     redis_cluster_endpoints: Option<Vec<String>>,
 
-    /// Timeout when consuming input events (in millis)
-    #[arg(long, env, default_value = "5000")]
-    broker_consume_timeout: usize,
-
-    /// The max elapsed time for backoff in ms
-    #[arg(long, env, default_value = "120000")]
-    broker_backoff_max_elapsed_duration: u64,
+    /// Timeout when consuming input events, such as `5s` (a bare number is
+    /// in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "5s",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    broker_consume_timeout: Duration,
+
+    /// The max elapsed time for backoff, such as `2m` (a bare number is in ms)
+    #[arg(
+        long,
+        env,
+        default_value = "2m",
+        value_parser = humane::parse_duration_or_millis
+    )]
+    broker_backoff_max_elapsed_duration: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -378,17 +439,16 @@ pub enum BrokerEndpoint {
 #[derive(Debug, Clone)]
 pub struct BrokerConfig {
     pub redis_endpoint: BrokerEndpoint,
-    pub consume_timeout: usize,
+    pub consume_timeout: Duration,
     pub backoff: ExponentialBackoff,
 }
 
 impl From<BrokerCLIConfig> for BrokerConfig {
     fn from(cli_config: BrokerCLIConfig) -> BrokerConfig {
-        let max_elapsed_time = Duration::from_millis(
-            cli_config.broker_backoff_max_elapsed_duration,
-        );
         let backoff = ExponentialBackoffBuilder::new()
-            .with_max_elapsed_time(Some(max_elapsed_time))
+            .with_max_elapsed_time(Some(
+                cli_config.broker_backoff_max_elapsed_duration,
+            ))
             .build();
         let redis_endpoint =
             if let Some(endpoints) = cli_config.redis_cluster_endpoints {
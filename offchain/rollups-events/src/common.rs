@@ -54,12 +54,10 @@ impl<'de, const N: usize> Deserialize<'de> for HexArray<N> {
     where
         D: Deserializer<'de>,
     {
-        let mut string_data = String::deserialize(deserializer)?;
+        let string_data = String::deserialize(deserializer)?;
         // The hex crate doesn't decode '0x' at the start, so we treat the value before decoding
-        if string_data[..2].eq("0x") {
-            string_data.drain(..2);
-        }
-        let vec_data = hex::decode(string_data).map_err(|e| {
+        let hex_data = string_data.strip_prefix("0x").unwrap_or(&string_data);
+        let vec_data = hex::decode(hex_data).map_err(|e| {
             serde::de::Error::custom(format!("fail to decode hex ({})", e))
         })?;
         let data = vec_data
@@ -0,0 +1,187 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use snafu::{ensure, Snafu};
+use std::{borrow::Cow, collections::BTreeMap, str::FromStr};
+
+/// Label names used by the node itself, which operators can't override
+const RESERVED_LABELS: &[&str] = &["chain_id", "dapp_address"];
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum LabelsError {
+    #[snafu(display("label `{}` is not in the `key=value` format", label))]
+    MissingValue { label: String },
+
+    #[snafu(display("invalid label name `{}`", name))]
+    InvalidName { name: String },
+
+    #[snafu(display("label name `{}` is reserved", name))]
+    ReservedName { name: String },
+
+    #[snafu(display("label `{}` is set more than once", name))]
+    DuplicateName { name: String },
+}
+
+/// Operator-defined annotations (e.g. environment, owner team, contact)
+/// attached to a DApp or a validator.
+///
+/// Labels are parsed from a comma-separated list of `key=value` pairs.
+/// Names follow the Prometheus label name rules, so they can be added to
+/// the metrics as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Iterates over the labels sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the labels in the format expected by
+    /// `prometheus_client::registry::Registry::with_labels`
+    pub fn metric_labels(
+        &self,
+    ) -> impl Iterator<Item = (Cow<'static, str>, Cow<'static, str>)> + '_ {
+        self.0
+            .iter()
+            .map(|(k, v)| (Cow::Owned(k.clone()), Cow::Owned(v.clone())))
+    }
+}
+
+impl FromStr for Labels {
+    type Err = LabelsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut labels = BTreeMap::new();
+        for label in s.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+            let (name, value) = label.split_once('=').ok_or_else(|| {
+                LabelsError::MissingValue {
+                    label: label.to_owned(),
+                }
+            })?;
+            let name = name.trim();
+            check_name(name)?;
+            ensure!(
+                labels
+                    .insert(name.to_owned(), value.trim().to_owned())
+                    .is_none(),
+                DuplicateNameSnafu { name }
+            );
+        }
+        Ok(Self(labels))
+    }
+}
+
+/// Labels set through an API, such as the admin API of the indexer
+impl TryFrom<BTreeMap<String, String>> for Labels {
+    type Error = LabelsError;
+
+    fn try_from(labels: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        for name in labels.keys() {
+            check_name(name)?;
+        }
+        Ok(Self(labels))
+    }
+}
+
+fn check_name(name: &str) -> Result<(), LabelsError> {
+    ensure!(is_valid_name(name), InvalidNameSnafu { name });
+    ensure!(!RESERVED_LABELS.contains(&name), ReservedNameSnafu { name });
+    Ok(())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first_is_valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    first_is_valid
+        && !name.starts_with("__")
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_labels() {
+        let labels: Labels =
+            " environment=production, owner_team = infra ,contact=a@b.c"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![
+                ("contact", "a@b.c"),
+                ("environment", "production"),
+                ("owner_team", "infra"),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_checks_the_names_of_labels_set_through_an_api() {
+        let labels: BTreeMap<_, _> =
+            [("environment".to_owned(), "staging".to_owned())].into();
+        let labels = Labels::try_from(labels).unwrap();
+        assert_eq!(labels.get("environment"), Some("staging"));
+
+        let labels: BTreeMap<_, _> =
+            [("dapp_address".to_owned(), "0x".to_owned())].into();
+        assert_eq!(
+            Labels::try_from(labels),
+            Err(LabelsError::ReservedName {
+                name: "dapp_address".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_empty_labels() {
+        let labels: Labels = "".parse().unwrap();
+        assert!(labels.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_invalid_labels() {
+        assert_eq!(
+            "environment".parse::<Labels>(),
+            Err(LabelsError::MissingValue {
+                label: "environment".to_owned()
+            })
+        );
+        assert_eq!(
+            "owner-team=infra".parse::<Labels>(),
+            Err(LabelsError::InvalidName {
+                name: "owner-team".to_owned()
+            })
+        );
+        assert_eq!(
+            "__name__=x".parse::<Labels>(),
+            Err(LabelsError::InvalidName {
+                name: "__name__".to_owned()
+            })
+        );
+        assert_eq!(
+            "chain_id=1".parse::<Labels>(),
+            Err(LabelsError::ReservedName {
+                name: "chain_id".to_owned()
+            })
+        );
+        assert_eq!(
+            "env=a,env=b".parse::<Labels>(),
+            Err(LabelsError::DuplicateName {
+                name: "env".to_owned()
+            })
+        );
+    }
+}
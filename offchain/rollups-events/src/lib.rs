@@ -3,16 +3,19 @@
 
 mod broker;
 mod common;
+mod labels;
 mod rollups_claims;
 mod rollups_inputs;
 mod rollups_outputs;
 mod rollups_stream;
 
 pub use broker::{
-    indexer, Broker, BrokerCLIConfig, BrokerConfig, BrokerEndpoint,
+    compaction, indexer, Broker, BrokerCLIConfig, BrokerConfig, BrokerEndpoint,
     BrokerError, BrokerStream, Event, RedactedUrl, Url, INITIAL_ID,
+    SCHEMA_VERSION,
 };
 pub use common::{Address, Hash, Payload, ADDRESS_SIZE, HASH_SIZE};
+pub use labels::{Labels, LabelsError};
 pub use rollups_claims::{RollupsClaim, RollupsClaimsStream};
 pub use rollups_inputs::{
     InputMetadata, RollupsAdvanceStateInput, RollupsData, RollupsInput,
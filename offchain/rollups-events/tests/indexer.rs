@@ -9,11 +9,12 @@ use rollups_events::{
     RollupsData, RollupsInput, RollupsInputsStream, RollupsOutput,
     RollupsOutputsStream, Url,
 };
+use std::time::Duration;
 use testcontainers::{
     clients::Cli, core::WaitFor, images::generic::GenericImage, Container,
 };
 
-pub const CONSUME_TIMEOUT: usize = 10;
+pub const CONSUME_TIMEOUT: Duration = Duration::from_millis(10);
 pub const CHAIN_ID: u64 = 99;
 pub const DAPP_ADDRESS: Address = Address::new([0xfa; 20]);
 
@@ -23,7 +24,7 @@ pub struct TestState<'d> {
 }
 
 impl TestState<'_> {
-    pub async fn setup(docker: &Cli) -> TestState {
+    pub async fn setup(docker: &Cli) -> TestState<'_> {
         let image = GenericImage::new("redis", "6.2").with_wait_for(
             WaitFor::message_on_stdout("Ready to accept connections"),
         );
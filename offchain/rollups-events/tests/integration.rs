@@ -6,17 +6,18 @@ use redis::aio::ConnectionManager;
 use redis::streams::StreamRangeReply;
 use redis::{AsyncCommands, Client};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use testcontainers::{
     clients::Cli, core::WaitFor, images::generic::GenericImage, Container,
 };
 
 use rollups_events::{
     Broker, BrokerConfig, BrokerEndpoint, BrokerError, BrokerStream,
-    RedactedUrl, Url, INITIAL_ID,
+    RedactedUrl, Url, INITIAL_ID, SCHEMA_VERSION,
 };
 
-const STREAM_KEY: &'static str = "test-stream";
-const CONSUME_TIMEOUT: usize = 10;
+const STREAM_KEY: &str = "test-stream";
+const CONSUME_TIMEOUT: Duration = Duration::from_millis(10);
 
 struct TestState<'d> {
     _node: Container<'d, GenericImage>,
@@ -26,7 +27,7 @@ struct TestState<'d> {
 }
 
 impl TestState<'_> {
-    async fn setup(docker: &Cli) -> TestState {
+    async fn setup(docker: &Cli) -> TestState<'_> {
         let image = GenericImage::new("redis", "6.2").with_wait_for(
             WaitFor::message_on_stdout("Ready to accept connections"),
         );
@@ -103,10 +104,11 @@ async fn test_it_produces_events() {
         .await
         .expect("failed to read");
     assert_eq!(reply.ids.len(), 3);
-    for i in 0..N {
+    for (i, (entry, id)) in reply.ids.iter().zip(&ids).enumerate() {
         let expected = format!(r#"{{"data":"{}"}}"#, i);
-        assert_eq!(reply.ids[i].id, ids[i]);
-        assert_eq!(reply.ids[i].get::<String>("payload").unwrap(), expected);
+        assert_eq!(&entry.id, id);
+        assert_eq!(entry.get::<String>("payload").unwrap(), expected);
+        assert_eq!(entry.get::<u32>("version").unwrap(), SCHEMA_VERSION);
     }
 }
 
@@ -119,7 +121,7 @@ async fn test_it_peeks_in_stream_with_no_events() {
         .peek_latest(&MockStream {})
         .await
         .expect("failed to peek");
-    assert!(matches!(event, None));
+    assert!(event.is_none());
 }
 
 #[test_log::test(tokio::test)]
@@ -189,6 +191,34 @@ async fn test_it_fails_to_peek_event_with_invalid_data_encoding() {
     assert!(matches!(err, BrokerError::InvalidPayload { .. }));
 }
 
+#[test_log::test(tokio::test)]
+async fn test_it_fails_to_peek_event_with_newer_schema_version() {
+    let docker = Cli::default();
+    let mut state = TestState::setup(&docker).await;
+    // Produce event directly in Redis
+    let version = (SCHEMA_VERSION + 1).to_string();
+    let _: String = state
+        .conn
+        .xadd(
+            STREAM_KEY,
+            "1-0",
+            &[("payload", r#"{"data":"0"}"#), ("version", &version)],
+        )
+        .await
+        .expect("failed to add events");
+    // Peek the event using the Broker struct
+    let mut broker = state.create_broker().await;
+    let err = broker
+        .peek_latest(&MockStream {})
+        .await
+        .expect_err("failed to get error");
+    assert!(matches!(
+        err,
+        BrokerError::UnsupportedVersion { version }
+            if version == SCHEMA_VERSION + 1
+    ));
+}
+
 #[test_log::test(tokio::test)]
 async fn test_it_consumes_events() {
     let docker = Cli::default();
@@ -284,5 +314,186 @@ async fn test_it_does_not_block_when_consuming_empty_stream() {
         .consume_nonblocking(&MockStream {}, INITIAL_ID)
         .await
         .expect("failed to peek");
-    assert!(matches!(event, None));
+    assert!(event.is_none());
+}
+
+#[test_log::test(tokio::test)]
+async fn test_it_deletes_stream() {
+    let docker = Cli::default();
+    let mut state = TestState::setup(&docker).await;
+    let mut broker = state.create_broker().await;
+    let data = MockPayload {
+        data: "0".to_owned(),
+    };
+    broker
+        .produce(&MockStream {}, data)
+        .await
+        .expect("failed to produce");
+    let deleted = broker
+        .delete_stream(&MockStream {})
+        .await
+        .expect("failed to delete");
+    assert!(deleted);
+    let exists: bool = state
+        .conn
+        .exists(STREAM_KEY)
+        .await
+        .expect("failed to check key");
+    assert!(!exists);
+    let deleted = broker
+        .delete_stream(&MockStream {})
+        .await
+        .expect("failed to delete");
+    assert!(!deleted);
+}
+
+#[test_log::test(tokio::test)]
+async fn test_it_compacts_stream_past_slowest_consumer() {
+    let docker = Cli::default();
+    let mut state = TestState::setup(&docker).await;
+    let mut broker = state.create_broker().await;
+    let mut ids = vec![];
+    for i in 0..5 {
+        let data = MockPayload {
+            data: i.to_string(),
+        };
+        let id = broker
+            .produce(&MockStream {}, data)
+            .await
+            .expect("failed to produce");
+        ids.push(id);
+    }
+    for consumer in ["fast", "slow"] {
+        let last_id = broker
+            .register_consumer(&MockStream {}, consumer)
+            .await
+            .expect("failed to register");
+        assert_eq!(last_id, INITIAL_ID);
+    }
+    let report = broker
+        .compact(&MockStream {}, &[], None)
+        .await
+        .expect("failed to compact");
+    assert_eq!(report.trimmed, 0);
+
+    broker
+        .acknowledge(&MockStream {}, "fast", &ids[3])
+        .await
+        .expect("failed to acknowledge");
+    broker
+        .acknowledge(&MockStream {}, "slow", &ids[1])
+        .await
+        .expect("failed to acknowledge");
+    let last_id = broker
+        .register_consumer(&MockStream {}, "slow")
+        .await
+        .expect("failed to register");
+    assert_eq!(last_id, ids[1]);
+    let report = broker
+        .compact(&MockStream {}, &[], None)
+        .await
+        .expect("failed to compact");
+    assert_eq!(report.trimmed, 2);
+    assert!(report.lagging_consumers.is_empty());
+
+    // The slow consumer is beyond a lag of one event
+    let report = broker
+        .compact(&MockStream {}, &[], Some(1))
+        .await
+        .expect("failed to compact");
+    assert_eq!(report.trimmed, 2);
+    assert_eq!(report.lagging_consumers, vec!["slow".to_owned()]);
+    let reply: StreamRangeReply = state
+        .conn
+        .xrange(STREAM_KEY, "-", "+")
+        .await
+        .expect("failed to read");
+    assert_eq!(reply.ids.len(), 1);
+    assert_eq!(reply.ids[0].id, ids[4]);
+}
+
+#[test_log::test(tokio::test)]
+async fn test_it_waits_for_the_expected_consumers_before_compacting() {
+    let docker = Cli::default();
+    let state = TestState::setup(&docker).await;
+    let mut broker = state.create_broker().await;
+    let mut ids = vec![];
+    for i in 0..3 {
+        let data = MockPayload {
+            data: i.to_string(),
+        };
+        let id = broker
+            .produce(&MockStream {}, data)
+            .await
+            .expect("failed to produce");
+        ids.push(id);
+    }
+    broker
+        .register_consumer(&MockStream {}, "fast")
+        .await
+        .expect("failed to register");
+    broker
+        .acknowledge(&MockStream {}, "fast", &ids[1])
+        .await
+        .expect("failed to acknowledge");
+    let expected = vec!["fast".to_owned(), "late".to_owned()];
+
+    // Not even the maximum lag trims the stream before every consumer
+    let report = broker
+        .compact(&MockStream {}, &expected, Some(0))
+        .await
+        .expect("failed to compact");
+    assert_eq!(report.trimmed, 0);
+    assert_eq!(report.unregistered_consumers, vec!["late".to_owned()]);
+
+    broker
+        .register_consumer(&MockStream {}, "late")
+        .await
+        .expect("failed to register");
+    broker
+        .acknowledge(&MockStream {}, "late", &ids[1])
+        .await
+        .expect("failed to acknowledge");
+    let report = broker
+        .compact(&MockStream {}, &expected, None)
+        .await
+        .expect("failed to compact");
+    assert_eq!(report.trimmed, 2);
+    assert!(report.unregistered_consumers.is_empty());
+}
+
+#[test_log::test(tokio::test)]
+async fn test_it_fences_lease_holders() {
+    let docker = Cli::default();
+    let state = TestState::setup(&docker).await;
+    let mut broker = state.create_broker().await;
+    let ttl = Duration::from_secs(60);
+    let token = broker
+        .acquire_lease("claims", "us-east", ttl)
+        .await
+        .expect("failed to acquire lease");
+    assert_eq!(token, Some(1));
+    let token = broker
+        .acquire_lease("claims", "eu-west", ttl)
+        .await
+        .expect("failed to acquire lease");
+    assert_eq!(token, None);
+    let token = broker
+        .acquire_lease("claims", "us-east", Duration::from_millis(50))
+        .await
+        .expect("failed to renew lease");
+    assert_eq!(token, Some(1));
+
+    // Once it expires, the lease is acquired again with a new token
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let token = broker
+        .acquire_lease("claims", "eu-west", ttl)
+        .await
+        .expect("failed to acquire lease");
+    assert_eq!(token, Some(2));
+    let token = broker
+        .acquire_lease("claims", "us-east", ttl)
+        .await
+        .expect("failed to acquire lease");
+    assert_eq!(token, None);
 }
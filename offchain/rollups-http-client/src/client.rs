@@ -6,7 +6,6 @@ use crate::rollup::{
     RollupRequest, RollupResponse, Voucher,
 };
 use serde::{Deserialize, Serialize};
-use std::io::ErrorKind;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "request_type")]
@@ -196,7 +195,7 @@ pub async fn send_finish_request(
                     )
                     .expect("failed to decode message");
 
-                    Err(std::io::Error::new(ErrorKind::Other, finish_error))
+                    Err(std::io::Error::other(finish_error))
                 }
             }
             Err(e) => {
@@ -205,7 +204,7 @@ pub async fn send_finish_request(
                     status,
                     e
                 );
-                Err(std::io::Error::new(ErrorKind::Other, e.to_string()))
+                Err(std::io::Error::other(e.to_string()))
             }
         }
     }
@@ -0,0 +1,505 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Isolation of the work of a service in separate tokio runtimes.
+//!
+//! The fold pipelines, the API servers and the transaction services of a
+//! service can each get a dedicated runtime, optionally pinned to a set of
+//! cores, so a burst of CPU-heavy folding can't starve the API or delay the
+//! handling of transaction deadlines. The roles without a dedicated runtime
+//! run in the main runtime of the service, as before.
+
+use clap::Parser;
+use http_server::{CounterRef, FamilyRef, GaugeRef, Registry};
+use prometheus_client::encoding::EncodeLabelSet;
+use snafu::{ensure, ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    task::AbortHandle,
+};
+
+const METRICS_PREFIX: &str = "cartesi_rollups_runtime";
+const MAIN_RUNTIME: &str = "main";
+
+fn prefixed_metrics(name: &str) -> String {
+    format!("{}_{}", METRICS_PREFIX, name)
+}
+
+/// Kind of work that can be isolated in its own runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RuntimeRole {
+    /// Fold pipelines and the processing of new blocks
+    Fold,
+    /// HTTP, GraphQL and gRPC servers
+    Api,
+    /// Services that send transactions and watch their deadlines
+    Tx,
+}
+
+impl RuntimeRole {
+    pub const ALL: [RuntimeRole; 3] =
+        [RuntimeRole::Fold, RuntimeRole::Api, RuntimeRole::Tx];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuntimeRole::Fold => "fold",
+            RuntimeRole::Api => "api",
+            RuntimeRole::Tx => "tx",
+        }
+    }
+}
+
+impl fmt::Display for RuntimeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum CoreSetError {
+    #[snafu(display("core set is empty"))]
+    EmptyCoreSet,
+
+    #[snafu(display("`{}` is not a core number", value))]
+    InvalidCore { value: String },
+
+    #[snafu(display("core range `{}` ends before it starts", value))]
+    ReversedRange { value: String },
+}
+
+/// Set of cores, written as a comma-separated list of cores and ranges of
+/// cores, such as `0-3,6`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoreSet(Vec<usize>);
+
+impl CoreSet {
+    pub fn cores(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl FromStr for CoreSet {
+    type Err = CoreSetError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse_core = |core: &str| {
+            core.trim().parse::<usize>().map_err(|_| {
+                CoreSetError::InvalidCore {
+                    value: core.trim().to_owned(),
+                }
+            })
+        };
+        let mut cores = vec![];
+        for part in value.split(',').filter(|part| !part.trim().is_empty()) {
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_core(start)?, parse_core(end)?);
+                    ensure!(
+                        start <= end,
+                        ReversedRangeSnafu { value: part.trim() }
+                    );
+                    cores.extend(start..=end);
+                }
+                None => cores.push(parse_core(part)?),
+            }
+        }
+        ensure!(!cores.is_empty(), EmptyCoreSetSnafu);
+        cores.sort_unstable();
+        cores.dedup();
+        Ok(CoreSet(cores))
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "runtime_config")]
+pub struct RuntimeCLIConfig {
+    /// Worker threads of a dedicated runtime for the fold pipelines. If
+    /// neither this nor the cores are set, they run in the main runtime
+    #[arg(long, env)]
+    pub fold_runtime_threads: Option<usize>,
+
+    /// Cores the threads of the fold runtime are pinned to, such as `0-3,6`.
+    /// Without threads, the runtime gets one thread per core
+    #[arg(long, env)]
+    pub fold_runtime_cores: Option<CoreSet>,
+
+    /// Worker threads of a dedicated runtime for the API servers. If neither
+    /// this nor the cores are set, they run in the main runtime
+    #[arg(long, env)]
+    pub api_runtime_threads: Option<usize>,
+
+    /// Cores the threads of the API runtime are pinned to, such as `4-5`.
+    /// Without threads, the runtime gets one thread per core
+    #[arg(long, env)]
+    pub api_runtime_cores: Option<CoreSet>,
+
+    /// Worker threads of a dedicated runtime for the transaction services.
+    /// If neither this nor the cores are set, they run in the main runtime
+    #[arg(long, env)]
+    pub tx_runtime_threads: Option<usize>,
+
+    /// Cores the threads of the transaction runtime are pinned to, such as
+    /// `7`. Without threads, the runtime gets one thread per core
+    #[arg(long, env)]
+    pub tx_runtime_cores: Option<CoreSet>,
+
+    /// Interval of the probe that measures how late each runtime wakes up
+    /// its tasks, such as `1s`
+    #[arg(
+        long,
+        env,
+        default_value = "1s",
+        value_parser = humane::parse_duration
+    )]
+    pub runtime_probe_interval: Duration,
+}
+
+/// Threads and cores of the runtime of a role
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoleConfig {
+    pub threads: Option<usize>,
+    pub cores: Option<CoreSet>,
+}
+
+impl RoleConfig {
+    /// Threads of the dedicated runtime, if the role has one
+    fn worker_threads(&self) -> Option<usize> {
+        self.threads
+            .or_else(|| self.cores.as_ref().map(|cores| cores.0.len()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub fold: RoleConfig,
+    pub api: RoleConfig,
+    pub tx: RoleConfig,
+    pub probe_interval: Duration,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            fold: RoleConfig::default(),
+            api: RoleConfig::default(),
+            tx: RoleConfig::default(),
+            probe_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn role(&self, role: RuntimeRole) -> &RoleConfig {
+        match role {
+            RuntimeRole::Fold => &self.fold,
+            RuntimeRole::Api => &self.api,
+            RuntimeRole::Tx => &self.tx,
+        }
+    }
+}
+
+impl From<RuntimeCLIConfig> for RuntimeConfig {
+    fn from(cli_config: RuntimeCLIConfig) -> Self {
+        Self {
+            fold: RoleConfig {
+                threads: cli_config.fold_runtime_threads,
+                cores: cli_config.fold_runtime_cores,
+            },
+            api: RoleConfig {
+                threads: cli_config.api_runtime_threads,
+                cores: cli_config.api_runtime_cores,
+            },
+            tx: RoleConfig {
+                threads: cli_config.tx_runtime_threads,
+                cores: cli_config.tx_runtime_cores,
+            },
+            probe_interval: cli_config.runtime_probe_interval,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum RuntimeError {
+    #[snafu(display("the {} runtime must have at least one thread", role))]
+    NoThreads { role: RuntimeRole },
+
+    #[snafu(display("failed to build the {} runtime", role))]
+    Build {
+        role: RuntimeRole,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RuntimeLabels {
+    runtime: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct RuntimeMetrics {
+    workers: FamilyRef<RuntimeLabels, GaugeRef>,
+    tasks_spawned: FamilyRef<RuntimeLabels, CounterRef>,
+    wake_delay: FamilyRef<RuntimeLabels, GaugeRef>,
+}
+
+impl RuntimeMetrics {
+    fn register(&self, registry: &mut Registry) {
+        registry.register(
+            prefixed_metrics("workers"),
+            "Worker threads of each dedicated runtime of the service",
+            self.workers.clone(),
+        );
+        registry.register(
+            prefixed_metrics("tasks_spawned"),
+            "Counts the long-running tasks spawned in each runtime",
+            self.tasks_spawned.clone(),
+        );
+        registry.register(
+            prefixed_metrics("wake_delay_microseconds"),
+            "How late the last probe of each runtime woke up; a growing \
+            value means the runtime is starved",
+            self.wake_delay.clone(),
+        );
+    }
+}
+
+/// Runtimes of a service: the main one, and the dedicated ones of the roles
+/// that were configured with threads or cores.
+///
+/// The dedicated runtimes are shut down in the background when this is
+/// dropped, so it is fine to drop it from async code.
+#[derive(Debug)]
+pub struct Runtimes {
+    main: Handle,
+    dedicated: HashMap<RuntimeRole, Runtime>,
+    metrics: RuntimeMetrics,
+}
+
+impl Runtimes {
+    /// Builds the dedicated runtimes and registers their metrics. Must be
+    /// called from within the main runtime.
+    pub fn new(
+        config: &RuntimeConfig,
+        registry: &mut Registry,
+    ) -> Result<Self, RuntimeError> {
+        let metrics = RuntimeMetrics::default();
+        metrics.register(registry);
+
+        let main = Handle::current();
+        main.spawn(probe(metrics.clone(), MAIN_RUNTIME, config.probe_interval));
+
+        // Built before the dedicated runtimes, so the ones already built are
+        // shut down by its drop if building another fails.
+        let mut runtimes = Self {
+            main,
+            dedicated: HashMap::new(),
+            metrics,
+        };
+        for role in RuntimeRole::ALL {
+            let role_config = config.role(role);
+            let Some(threads) = role_config.worker_threads() else {
+                continue;
+            };
+            let runtime = build_runtime(role, threads, role_config)?;
+            tracing::info!(
+                "Running the {} tasks in a dedicated runtime with {} \
+                threads",
+                role,
+                threads
+            );
+            let metrics = &runtimes.metrics;
+            metrics
+                .workers
+                .get_or_create(&labels(role.name()))
+                .set(threads as i64);
+            runtime.spawn(probe(
+                metrics.clone(),
+                role.name(),
+                config.probe_interval,
+            ));
+            runtimes.dedicated.insert(role, runtime);
+        }
+        Ok(runtimes)
+    }
+
+    /// Handle of the runtime where the tasks of the role run
+    pub fn handle(&self, role: RuntimeRole) -> &Handle {
+        self.dedicated
+            .get(&role)
+            .map(Runtime::handle)
+            .unwrap_or(&self.main)
+    }
+
+    /// Runs the future in the runtime of the role, returning its output.
+    ///
+    /// The task is aborted when the returned future is dropped, and panics
+    /// of the task are resumed in the caller.
+    pub async fn run<F>(&self, role: RuntimeRole, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let runtime = if self.dedicated.contains_key(&role) {
+            role.name()
+        } else {
+            MAIN_RUNTIME
+        };
+        self.metrics
+            .tasks_spawned
+            .get_or_create(&labels(runtime))
+            .inc();
+
+        let task = self.handle(role).spawn(future);
+        let _guard = AbortOnDrop(task.abort_handle());
+        match task.await {
+            Ok(output) => output,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("{} task was cancelled: {}", role, e),
+        }
+    }
+}
+
+impl Drop for Runtimes {
+    fn drop(&mut self) {
+        for (_, runtime) in self.dedicated.drain() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn labels(runtime: &str) -> RuntimeLabels {
+    RuntimeLabels {
+        runtime: runtime.to_owned(),
+    }
+}
+
+fn build_runtime(
+    role: RuntimeRole,
+    threads: usize,
+    config: &RoleConfig,
+) -> Result<Runtime, RuntimeError> {
+    ensure!(threads > 0, NoThreadsSnafu { role });
+    let mut builder = Builder::new_multi_thread();
+    builder
+        .worker_threads(threads)
+        .thread_name(format!("{}-runtime", role))
+        .enable_all();
+    if let Some(cores) = &config.cores {
+        // The blocking threads of the runtime are pinned as well, so the
+        // blocking work of the role stays on its cores.
+        let cores = cores.0.clone();
+        let next = AtomicUsize::new(0);
+        builder.on_thread_start(move || {
+            let id = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+            if !core_affinity::set_for_current(core_affinity::CoreId { id }) {
+                tracing::warn!(
+                    "failed to pin a {} thread to core {}",
+                    role,
+                    id
+                );
+            }
+        });
+    }
+    builder.build().context(BuildSnafu { role })
+}
+
+/// Sleeps for the interval over and over, recording how late it wakes up.
+/// A busy runtime polls its timers late, so this measures the latency its
+/// tasks get.
+async fn probe(
+    metrics: RuntimeMetrics,
+    runtime: &'static str,
+    interval: Duration,
+) {
+    let wake_delay = metrics.wake_delay.get_or_create(&labels(runtime)).clone();
+    loop {
+        let started_at = Instant::now();
+        tokio::time::sleep(interval).await;
+        let delay = started_at.elapsed().saturating_sub(interval);
+        wake_delay.set(delay.as_micros() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_core_sets() {
+        let cores: CoreSet = "4-6, 0,5".parse().unwrap();
+        assert_eq!(cores.cores(), [0, 4, 5, 6]);
+        assert_eq!(
+            "".parse::<CoreSet>().unwrap_err(),
+            CoreSetError::EmptyCoreSet
+        );
+        assert_eq!(
+            "1,x".parse::<CoreSet>().unwrap_err(),
+            CoreSetError::InvalidCore {
+                value: "x".to_owned()
+            }
+        );
+        assert_eq!(
+            "3-1".parse::<CoreSet>().unwrap_err(),
+            CoreSetError::ReversedRange {
+                value: "3-1".to_owned()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_runs_roles_in_their_runtimes() {
+        let config = RuntimeConfig {
+            fold: RoleConfig {
+                threads: Some(1),
+                cores: None,
+            },
+            ..Default::default()
+        };
+        let mut registry = Registry::default();
+        let runtimes = Runtimes::new(&config, &mut registry).unwrap();
+
+        let thread_name =
+            || async { std::thread::current().name().map(str::to_owned) };
+        let fold_thread = runtimes.run(RuntimeRole::Fold, thread_name()).await;
+        assert_eq!(fold_thread.as_deref(), Some("fold-runtime"));
+        let api_thread = runtimes.run(RuntimeRole::Api, thread_name()).await;
+        assert_ne!(api_thread.as_deref(), Some("fold-runtime"));
+
+        let metrics = http_server::encode_metrics(&registry);
+        assert!(metrics
+            .contains("cartesi_rollups_runtime_workers{runtime=\"fold\"} 1"));
+        assert!(metrics.contains(
+            "cartesi_rollups_runtime_tasks_spawned_total{runtime=\"main\"} 1"
+        ));
+        drop(runtimes);
+    }
+
+    #[tokio::test]
+    async fn it_rejects_runtimes_without_threads() {
+        let config = RuntimeConfig {
+            tx: RoleConfig {
+                threads: Some(0),
+                cores: None,
+            },
+            ..Default::default()
+        };
+        let err = Runtimes::new(&config, &mut Registry::default()).unwrap_err();
+        assert!(matches!(err, RuntimeError::NoThreads { .. }));
+    }
+}
@@ -0,0 +1,337 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Scheduler for the periodic background jobs of the services.
+//!
+//! A job runs at a fixed interval or at the times of a cron expression, with
+//! an optional random jitter so replicas of a service don't run it at the
+//! same instant. When the scheduler has a state directory, the time of the
+//! last run of each job is persisted there, so a restart neither runs a job
+//! ahead of its schedule nor forgets the runs missed while the service was
+//! down; what happens to those is decided by the job's `MissedRunPolicy`.
+//!
+//! A job either runs on its own with `Scheduler::run`, or, in the loops that
+//! do other work between its runs, with the ticks of `Scheduler::ticker`.
+
+use clap::{Parser, ValueEnum};
+use rand::Rng;
+use std::{
+    fmt::Display,
+    future::Future,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+pub use schedule::{
+    seconds_since_epoch, CronSchedule, Schedule, ScheduleError,
+};
+pub use store::{StateError, StateStore};
+pub use window::{format_utc_time, parse_utc_time, Window};
+
+mod schedule;
+mod store;
+mod window;
+
+/// What to do with the runs that were due while the service was down or while
+/// the previous run was still going
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MissedRunPolicy {
+    /// Wait for the next scheduled time
+    #[default]
+    Skip,
+    /// Run once right away, then follow the schedule
+    RunOnce,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "scheduler_config")]
+pub struct SchedulerCLIConfig {
+    /// Directory where the time of the last run of each periodic job is kept,
+    /// so the schedules survive restarts. If not set, the jobs are scheduled
+    /// as if they never ran before
+    #[arg(long, env)]
+    pub scheduler_state_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerConfig {
+    pub state_dir: Option<PathBuf>,
+}
+
+impl From<SchedulerCLIConfig> for SchedulerConfig {
+    fn from(cli_config: SchedulerCLIConfig) -> Self {
+        Self {
+            state_dir: cli_config.scheduler_state_dir,
+        }
+    }
+}
+
+/// When and how a job runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobConfig {
+    pub schedule: Schedule,
+    /// Upper bound of the random delay added to each run
+    pub jitter: Duration,
+    pub missed_runs: MissedRunPolicy,
+}
+
+impl JobConfig {
+    /// Runs the job right away and then at every interval, without jitter
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            schedule: Schedule::Every(interval),
+            jitter: Duration::ZERO,
+            missed_runs: MissedRunPolicy::Skip,
+        }
+    }
+
+    /// Time of the next run, given the time of the last one
+    fn next_run(
+        &self,
+        last_run: Option<SystemTime>,
+        now: SystemTime,
+    ) -> SystemTime {
+        let Some(last_run) = last_run else {
+            return match self.schedule {
+                Schedule::Every(_) => now,
+                Schedule::Cron(_) => self.schedule.next_after(now),
+            };
+        };
+        let due = self.schedule.next_after(last_run);
+        if due >= now {
+            return due;
+        }
+        match self.missed_runs {
+            MissedRunPolicy::Skip => self.schedule.next_after(now),
+            MissedRunPolicy::RunOnce => now,
+        }
+    }
+
+    fn jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            rand::thread_rng().gen_range(Duration::ZERO..self.jitter)
+        }
+    }
+}
+
+/// Runs the periodic jobs of a service. Cloning it is cheap and keeps using
+/// the same state directory.
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    store: Option<StateStore>,
+}
+
+impl Scheduler {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        Self {
+            store: config.state_dir.clone().map(StateStore::new),
+        }
+    }
+
+    /// Runs the job forever, following its config. The name identifies the
+    /// job in the logs and in the state directory, so it must be unique
+    /// within the service and stable across restarts.
+    ///
+    /// A failed run is logged and counts as a run; the job is tried again at
+    /// the next scheduled time.
+    #[tracing::instrument(level = "trace", skip_all, fields(job = name))]
+    pub async fn run<F, Fut, E>(
+        &self,
+        name: &str,
+        config: &JobConfig,
+        mut job: F,
+    ) where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut last_run = self.load_last_run(name);
+        loop {
+            let next_run = config.next_run(last_run, SystemTime::now());
+            let delay = next_run
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+                + config.jitter();
+            tracing::trace!(?delay, "waiting for the next run");
+            tokio::time::sleep(delay).await;
+
+            let started_at = SystemTime::now();
+            if let Err(e) = job().await {
+                tracing::warn!("periodic job {} failed: {}", name, e);
+            }
+            last_run = Some(started_at);
+            self.save_last_run(name, started_at);
+        }
+    }
+
+    /// Ticks of the job, for the loops that run it along with other work,
+    /// such as in a `tokio::select!`. The name is used as in `run`.
+    pub fn ticker(&self, name: &str, config: &JobConfig) -> Ticker {
+        Ticker {
+            scheduler: self.clone(),
+            name: name.to_owned(),
+            config: config.clone(),
+            last_run: self.load_last_run(name),
+        }
+    }
+
+    fn load_last_run(&self, name: &str) -> Option<SystemTime> {
+        let store = self.store.as_ref()?;
+        match store.last_run(name) {
+            Ok(last_run) => last_run,
+            Err(e) => {
+                tracing::warn!(
+                    "failed to load the state of periodic job {}: {}",
+                    name,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    fn save_last_run(&self, name: &str, time: SystemTime) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.record_run(name, time) {
+            tracing::warn!(
+                "failed to save the state of periodic job {}: {}",
+                name,
+                e
+            );
+        }
+    }
+}
+
+/// Scheduled times of a job, created by `Scheduler::ticker`
+#[derive(Debug)]
+pub struct Ticker {
+    scheduler: Scheduler,
+    name: String,
+    config: JobConfig,
+    last_run: Option<SystemTime>,
+}
+
+impl Ticker {
+    /// Waits until the next run is due and records it as run. It is cancel
+    /// safe: a tick that is cancelled doesn't count as a run.
+    pub async fn tick(&mut self) {
+        let next_run = self.config.next_run(self.last_run, SystemTime::now());
+        let delay = next_run
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            + self.config.jitter();
+        tracing::trace!(job = self.name, ?delay, "waiting for the next tick");
+        tokio::time::sleep(delay).await;
+
+        let now = SystemTime::now();
+        self.last_run = Some(now);
+        self.scheduler.save_last_run(&self.name, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::UNIX_EPOCH;
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn job_config(schedule: &str, missed_runs: MissedRunPolicy) -> JobConfig {
+        JobConfig {
+            schedule: schedule.parse().unwrap(),
+            jitter: Duration::ZERO,
+            missed_runs,
+        }
+    }
+
+    #[test]
+    fn it_schedules_jobs_that_never_ran() {
+        let config = job_config("@every 1m", MissedRunPolicy::Skip);
+        assert_eq!(config.next_run(None, time(1000)), time(1000));
+
+        let config = job_config("@hourly", MissedRunPolicy::Skip);
+        assert_eq!(config.next_run(None, time(1000)), time(3600));
+    }
+
+    #[test]
+    fn it_follows_the_schedule_after_a_run() {
+        let config = job_config("@every 1m", MissedRunPolicy::RunOnce);
+        assert_eq!(config.next_run(Some(time(1000)), time(1010)), time(1060));
+
+        let config = job_config("@hourly", MissedRunPolicy::RunOnce);
+        assert_eq!(config.next_run(Some(time(3600)), time(3610)), time(7200));
+    }
+
+    #[test]
+    fn it_applies_the_missed_run_policy() {
+        let skip = job_config("@hourly", MissedRunPolicy::Skip);
+        assert_eq!(skip.next_run(Some(time(0)), time(10000)), time(10800));
+
+        let run_once = job_config("@hourly", MissedRunPolicy::RunOnce);
+        assert_eq!(run_once.next_run(Some(time(0)), time(10000)), time(10000));
+    }
+
+    #[tokio::test]
+    async fn it_runs_and_persists_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = Scheduler::new(&SchedulerConfig {
+            state_dir: Some(dir.path().to_owned()),
+        });
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job = || {
+            let runs = runs.clone();
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Err("failing runs count as runs")
+            }
+        };
+        let config = JobConfig::every(Duration::from_millis(20));
+        let started_at = SystemTime::now();
+        let _ = tokio::time::timeout(
+            Duration::from_millis(110),
+            scheduler.run("test", &config, job),
+        )
+        .await;
+
+        assert!(runs.load(Ordering::SeqCst) >= 3);
+        let last_run = StateStore::new(dir.path()).last_run("test").unwrap();
+        assert!(last_run.unwrap() > started_at);
+    }
+
+    #[tokio::test]
+    async fn it_ticks_and_persists_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = Scheduler::new(&SchedulerConfig {
+            state_dir: Some(dir.path().to_owned()),
+        });
+        let config = JobConfig::every(Duration::from_millis(50));
+        let mut ticker = scheduler.ticker("test", &config);
+        let started_at = SystemTime::now();
+        ticker.tick().await;
+        assert!(started_at.elapsed().unwrap() < Duration::from_millis(50));
+
+        // A cancelled tick doesn't count as a run
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(10), ticker.tick());
+        assert!(cancelled.await.is_err());
+        ticker.tick().await;
+        assert!(started_at.elapsed().unwrap() >= Duration::from_millis(50));
+
+        // A restarted ticker waits for the interval since the last run
+        let last_run = StateStore::new(dir.path()).last_run("test").unwrap();
+        let mut ticker = scheduler.ticker("test", &config);
+        ticker.tick().await;
+        let elapsed = last_run.unwrap().elapsed().unwrap();
+        assert!(elapsed >= Duration::from_millis(50));
+    }
+}
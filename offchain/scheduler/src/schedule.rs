@@ -0,0 +1,383 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use humane::HumaneError;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+/// How far ahead the next occurrence of a cron expression is searched. A
+/// Gregorian leap day repeats at most every 8 years, so an expression that
+/// doesn't happen within this window never happens.
+const MAX_SEARCH_DAYS: u64 = 28 * 366;
+
+/// Shorthands for common cron expressions
+const ALIASES: &[(&str, &str)] = &[
+    ("@hourly", "0 * * * *"),
+    ("@daily", "0 0 * * *"),
+    ("@midnight", "0 0 * * *"),
+    ("@weekly", "0 0 * * 0"),
+    ("@monthly", "0 0 1 * *"),
+    ("@yearly", "0 0 1 1 *"),
+    ("@annually", "0 0 1 1 *"),
+];
+
+#[derive(Debug, Snafu, PartialEq)]
+#[snafu(visibility(pub(crate)))]
+pub enum ScheduleError {
+    #[snafu(display(
+        "`{}` should have 5 fields (minute, hour, day of month, month and day of week)",
+        value
+    ))]
+    FieldCount { value: String },
+
+    #[snafu(display("invalid {} `{}` in `{}`", field, part, value))]
+    InvalidField {
+        field: &'static str,
+        part: String,
+        value: String,
+    },
+
+    #[snafu(display("`{}` never happens", value))]
+    NeverHappens { value: String },
+
+    #[snafu(display("invalid interval in `{}`", value))]
+    InvalidInterval { value: String, source: HumaneError },
+
+    #[snafu(display("interval in `{}` must be greater than zero", value))]
+    ZeroInterval { value: String },
+
+    #[snafu(display("`{}` should be `<start> for <duration>`", value))]
+    InvalidWindow { value: String },
+
+    #[snafu(display("invalid duration in `{}`", value))]
+    InvalidWindowDuration { value: String, source: HumaneError },
+
+    #[snafu(display("invalid UTC time `{}` in `{}`", time, value))]
+    InvalidTime { time: String, value: String },
+
+    #[snafu(display("window `{}` should start at a time or cron expression, not at an interval", value))]
+    IntervalWindow { value: String },
+}
+
+/// When a job runs, either at a fixed interval (`@every 30s`) or at the times
+/// matched by a cron expression (`30 2 * * *`, `@daily`), in UTC.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// First time the job is due strictly after `time`
+    pub fn next_after(&self, time: SystemTime) -> SystemTime {
+        match self {
+            Schedule::Every(interval) => time + *interval,
+            Schedule::Cron(cron) => {
+                let secs = seconds_since_epoch(time);
+                let next = cron
+                    .next_after(secs)
+                    .expect("cron expression was checked to happen");
+                UNIX_EPOCH + Duration::from_secs(next)
+            }
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let trimmed = value.trim();
+        if let Some(interval) = trimmed.strip_prefix("@every") {
+            let interval = humane::parse_duration(interval)
+                .context(InvalidIntervalSnafu { value })?;
+            ensure!(!interval.is_zero(), ZeroIntervalSnafu { value });
+            return Ok(Schedule::Every(interval));
+        }
+        let expression = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == trimmed)
+            .map(|(_, expression)| *expression)
+            .unwrap_or(trimmed);
+        CronSchedule::parse(expression, value).map(Schedule::Cron)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Every(interval) => write!(f, "@every {:?}", interval),
+            Schedule::Cron(cron) => write!(f, "{}", cron.expression),
+        }
+    }
+}
+
+/// The five fields of a cron expression, each one as the set of the values
+/// it matches
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month or the day of week was restricted, since a
+    /// day matches when either restricted field matches
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronSchedule {
+    fn parse(expression: &str, value: &str) -> Result<Self, ScheduleError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return FieldCountSnafu { value }.fail();
+        };
+        let mut weekdays_set =
+            parse_field(weekdays, 0, 7, "day of week", value)?;
+        // Both 0 and 7 are Sunday
+        if weekdays_set & (1 << 7) != 0 {
+            weekdays_set = (weekdays_set | 1) & !(1 << 7);
+        }
+        let cron = Self {
+            expression: expression.to_owned(),
+            minutes: parse_field(minutes, 0, 59, "minute", value)?,
+            hours: parse_field(hours, 0, 23, "hour", value)?,
+            days: parse_field(days, 1, 31, "day of month", value)?,
+            months: parse_field(months, 1, 12, "month", value)?,
+            weekdays: weekdays_set,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        };
+        ensure!(cron.next_after(0).is_some(), NeverHappensSnafu { value });
+        Ok(cron)
+    }
+
+    /// First minute matched by the expression strictly after `secs`, in
+    /// seconds since the Unix epoch
+    fn next_after(&self, secs: u64) -> Option<u64> {
+        let mut minute = secs / 60 + 1;
+        let limit = minute + MAX_SEARCH_DAYS * MINUTES_PER_DAY;
+        while minute < limit {
+            let days = minute / MINUTES_PER_DAY;
+            let (year, month, day) = civil_from_days(days);
+            if !contains(self.months, month) {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                minute = days_from_civil(year, month, 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            if !self.matches_day(day, (days + 4) % 7) {
+                minute = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            if !contains(self.hours, minute / 60 % 24) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if !contains(self.minutes, minute % 60) {
+                minute += 1;
+                continue;
+            }
+            return Some(minute * 60);
+        }
+        None
+    }
+
+    fn matches_day(&self, day: u64, weekday: u64) -> bool {
+        let day_matches = contains(self.days, day);
+        let weekday_matches = contains(self.weekdays, weekday);
+        if self.days_restricted && self.weekdays_restricted {
+            day_matches || weekday_matches
+        } else {
+            day_matches && weekday_matches
+        }
+    }
+}
+
+fn contains(set: u64, value: u64) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a comma-separated list of `*`, `N` or `N-M`, each one optionally
+/// followed by a `/step`, into the set of values it matches
+fn parse_field(
+    field: &str,
+    min: u64,
+    max: u64,
+    name: &'static str,
+    value: &str,
+) -> Result<u64, ScheduleError> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let invalid = || InvalidFieldSnafu {
+            field: name,
+            part,
+            value,
+        };
+        let number = |s: &str| s.parse::<u64>().ok().context(invalid());
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(number(step)?)),
+            None => (part, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (number(first)?, number(last)?),
+            // `N/step` goes from N up to the maximum
+            None if step.is_some() => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        let step = step.unwrap_or(1);
+        ensure!(
+            min <= first && first <= last && last <= max && step > 0,
+            invalid()
+        );
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Whole seconds from the Unix epoch to `time`, or zero before it
+pub fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date, with the
+/// algorithm from http://howardhinnant.github.io/date_algorithms.html
+pub(crate) fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of `civil_from_days`
+pub(crate) fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seconds since the Unix epoch of a UTC date and time
+    fn at(year: u64, month: u64, day: u64, hour: u64, minute: u64) -> u64 {
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60
+    }
+
+    fn cron(value: &str) -> CronSchedule {
+        match value.parse() {
+            Ok(Schedule::Cron(cron)) => cron,
+            other => panic!("`{}` parsed to {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn converts_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(19783), (2024, 3, 1));
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(
+            "@every 1h30m".parse(),
+            Ok(Schedule::Every(Duration::from_secs(5400)))
+        );
+        assert!(matches!(
+            "@every 0s".parse::<Schedule>(),
+            Err(ScheduleError::ZeroInterval { .. })
+        ));
+        assert!(matches!(
+            "@every often".parse::<Schedule>(),
+            Err(ScheduleError::InvalidInterval { .. })
+        ));
+    }
+
+    #[test]
+    fn finds_the_next_occurrence() {
+        let now = at(2024, 2, 28, 10, 15);
+        assert_eq!(
+            cron("*/20 * * * *").next_after(now),
+            Some(at(2024, 2, 28, 10, 20))
+        );
+        assert_eq!(
+            cron("30 2 * * *").next_after(now),
+            Some(at(2024, 2, 29, 2, 30))
+        );
+        assert_eq!(
+            cron("@monthly").next_after(now),
+            Some(at(2024, 3, 1, 0, 0))
+        );
+        // 2024-03-04 is a Monday
+        assert_eq!(
+            cron("0 9 * * 1-5").next_after(at(2024, 3, 1, 9, 0)),
+            Some(at(2024, 3, 4, 9, 0))
+        );
+        assert_eq!(
+            cron("0 0 29 2 *").next_after(at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+        // Either the 15th or a Sunday
+        assert_eq!(
+            cron("0 0 15 * 7").next_after(at(2024, 3, 11, 0, 0)),
+            Some(at(2024, 3, 15, 0, 0))
+        );
+        assert_eq!(
+            cron("0 0 15 * 7").next_after(at(2024, 3, 15, 0, 0)),
+            Some(at(2024, 3, 17, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(matches!(
+            "* * * *".parse::<Schedule>(),
+            Err(ScheduleError::FieldCount { .. })
+        ));
+        assert_eq!(
+            "60 * * * *".parse::<Schedule>(),
+            Err(ScheduleError::InvalidField {
+                field: "minute",
+                part: "60".to_owned(),
+                value: "60 * * * *".to_owned(),
+            })
+        );
+        assert!(matches!(
+            "*/0 * * * *".parse::<Schedule>(),
+            Err(ScheduleError::InvalidField { .. })
+        ));
+        assert!(matches!(
+            "0 0 30 2 *".parse::<Schedule>(),
+            Err(ScheduleError::NeverHappens { .. })
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Snafu)]
+pub enum StateError {
+    #[snafu(display("failed to create state directory `{}`", path.display()))]
+    CreateDir { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to read job state `{}`", path.display()))]
+    ReadFile { path: PathBuf, source: io::Error },
+
+    #[snafu(display("failed to write job state `{}`", path.display()))]
+    WriteFile { path: PathBuf, source: io::Error },
+
+    #[snafu(display("job state `{}` is corrupted", path.display()))]
+    Corrupted {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JobState {
+    /// Milliseconds since the Unix epoch
+    last_run: u64,
+}
+
+/// Keeps the time of the last run of each job as a JSON file, so the
+/// schedules survive restarts
+#[derive(Clone, Debug)]
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, job: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", job))
+    }
+
+    /// Time of the last run of the job, if it ever ran
+    pub fn last_run(
+        &self,
+        job: &str,
+    ) -> Result<Option<SystemTime>, StateError> {
+        let path = self.path(job);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(ReadFileSnafu { path }),
+        };
+        let state: JobState = serde_json::from_slice(&contents)
+            .context(CorruptedSnafu { path })?;
+        Ok(Some(UNIX_EPOCH + Duration::from_millis(state.last_run)))
+    }
+
+    /// Records a run of the job. The file is replaced atomically, so a crash
+    /// while writing keeps the previous state.
+    pub fn record_run(
+        &self,
+        job: &str,
+        time: SystemTime,
+    ) -> Result<(), StateError> {
+        fs::create_dir_all(&self.dir).context(CreateDirSnafu {
+            path: self.dir.clone(),
+        })?;
+        let last_run = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let contents = serde_json::to_vec(&JobState { last_run })
+            .expect("job state should serialize to JSON");
+        let path = self.path(job);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents).context(WriteFileSnafu {
+            path: tmp_path.clone(),
+        })?;
+        fs::rename(&tmp_path, &path).context(WriteFileSnafu { path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_records_the_last_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("jobs"));
+        assert_eq!(store.last_run("prune").unwrap(), None);
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        store.record_run("prune", time).unwrap();
+        assert_eq!(store.last_run("prune").unwrap(), Some(time));
+        assert_eq!(store.last_run("report").unwrap(), None);
+
+        // A new store over the same directory sees the same state
+        let store = StateStore::new(dir.path().join("jobs"));
+        assert_eq!(store.last_run("prune").unwrap(), Some(time));
+    }
+
+    #[test]
+    fn it_reports_corrupted_state() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("prune.json"), "not json").unwrap();
+        let store = StateStore::new(dir.path());
+        assert!(matches!(
+            store.last_run("prune"),
+            Err(StateError::Corrupted { .. })
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use snafu::{ensure, OptionExt, ResultExt};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::schedule::{
+    civil_from_days, days_from_civil, IntervalWindowSnafu, InvalidTimeSnafu,
+    InvalidWindowDurationSnafu, InvalidWindowSnafu, Schedule, ScheduleError,
+    ZeroIntervalSnafu,
+};
+
+/// A span of time, either once from a UTC timestamp
+/// (`2024-03-01T02:00Z for 2h`) or from each time matched by a cron
+/// expression (`0 2 * * 0 for 90m`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    start: WindowStart,
+    duration: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum WindowStart {
+    Once(SystemTime),
+    Cron(Schedule),
+}
+
+impl Window {
+    /// End of the window if `time` is inside it
+    pub fn end_if_active(&self, time: SystemTime) -> Option<SystemTime> {
+        match &self.start {
+            WindowStart::Once(start) => {
+                let end = *start + self.duration;
+                (*start <= time && time < end).then_some(end)
+            }
+            WindowStart::Cron(schedule) => {
+                let since =
+                    time.checked_sub(self.duration).unwrap_or(UNIX_EPOCH);
+                let mut start = schedule.next_after(since);
+                if start > time {
+                    return None;
+                }
+                // Overlapping occurrences extend the window
+                loop {
+                    let next = schedule.next_after(start);
+                    if next > time {
+                        return Some(start + self.duration);
+                    }
+                    start = next;
+                }
+            }
+        }
+    }
+
+    /// Start and end of each occurrence of the window that overlaps the
+    /// span from `from` until `until`, in order
+    pub fn occurrences(
+        &self,
+        from: SystemTime,
+        until: SystemTime,
+    ) -> Vec<(SystemTime, SystemTime)> {
+        match &self.start {
+            WindowStart::Once(start) => {
+                let end = *start + self.duration;
+                if *start < until && from < end {
+                    vec![(*start, end)]
+                } else {
+                    vec![]
+                }
+            }
+            WindowStart::Cron(schedule) => {
+                let since =
+                    from.checked_sub(self.duration).unwrap_or(UNIX_EPOCH);
+                let mut occurrences = vec![];
+                let mut start = schedule.next_after(since);
+                while start < until {
+                    occurrences.push((start, start + self.duration));
+                    start = schedule.next_after(start);
+                }
+                occurrences
+            }
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = ScheduleError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (start, duration) = value
+            .rsplit_once(" for ")
+            .context(InvalidWindowSnafu { value })?;
+        let duration = humane::parse_duration(duration)
+            .context(InvalidWindowDurationSnafu { value })?;
+        ensure!(!duration.is_zero(), ZeroIntervalSnafu { value });
+        let start = start.trim();
+        let start = if start.starts_with(|c: char| c.is_ascii_digit())
+            && start.ends_with(['Z', 'z'])
+        {
+            WindowStart::Once(parse_utc_time_in(start, value)?)
+        } else {
+            match start.parse()? {
+                Schedule::Every(_) => {
+                    return IntervalWindowSnafu { value }.fail()
+                }
+                schedule => WindowStart::Cron(schedule),
+            }
+        };
+        Ok(Self { start, duration })
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.start {
+            WindowStart::Once(start) => {
+                write!(f, "{}", format_utc_time(*start))?;
+            }
+            WindowStart::Cron(schedule) => write!(f, "{}", schedule)?,
+        }
+        write!(f, " for {:?}", self.duration)
+    }
+}
+
+/// Formats the time as a UTC timestamp such as `2024-03-01T02:00:30Z`
+pub fn format_utc_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days(secs / 86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses a UTC timestamp such as `2024-03-01T02:00Z` or
+/// `2024-03-01T02:00:30Z`
+pub fn parse_utc_time(time: &str) -> Result<SystemTime, ScheduleError> {
+    let time = time.trim();
+    ensure!(
+        time.ends_with(['Z', 'z']),
+        InvalidTimeSnafu { time, value: time }
+    );
+    parse_utc_time_in(time, time)
+}
+
+/// Parses the UTC timestamp `time`, which ends with `Z`, from `value`
+fn parse_utc_time_in(
+    time: &str,
+    value: &str,
+) -> Result<SystemTime, ScheduleError> {
+    let invalid = || InvalidTimeSnafu { time, value };
+    let (date, clock) = time[..time.len() - 1]
+        .split_once(['T', 't', ' '])
+        .context(invalid())?;
+    let number = |s: &str| s.parse::<u64>().ok().context(invalid());
+    let date: Vec<&str> = date.split('-').collect();
+    let clock: Vec<&str> = clock.split(':').collect();
+    let ([year, month, day], [hour, minute, rest @ ..]) =
+        (&date[..], &clock[..])
+    else {
+        return invalid().fail();
+    };
+    let (year, month, day) = (number(year)?, number(month)?, number(day)?);
+    let (hour, minute) = (number(hour)?, number(minute)?);
+    let second = match rest {
+        [] => 0,
+        [second] => number(second)?,
+        _ => return invalid().fail(),
+    };
+    ensure!(
+        year >= 1970
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60,
+        invalid()
+    );
+    let days = days_from_civil(year, month, day);
+    // Rejects days past the end of the month, such as February 30th
+    ensure!(civil_from_days(days) == (year, month, day), invalid());
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(
+        year: u64,
+        month: u64,
+        day: u64,
+        hour: u64,
+        minute: u64,
+    ) -> SystemTime {
+        let secs = days_from_civil(year, month, day) * 86400
+            + hour * 3600
+            + minute * 60;
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn window(value: &str) -> Window {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(
+            window("2024-03-01T02:00Z for 2h"),
+            Window {
+                start: WindowStart::Once(at(2024, 3, 1, 2, 0)),
+                duration: Duration::from_secs(7200),
+            }
+        );
+        assert_eq!(
+            window("2024-03-01T02:00:30Z for 2h").to_string(),
+            "2024-03-01T02:00:30Z for 7200s"
+        );
+        assert_eq!(
+            window("0 2 * * 0 for 90m").to_string(),
+            "0 2 * * 0 for 5400s"
+        );
+        assert!(matches!(
+            "@daily".parse::<Window>(),
+            Err(ScheduleError::InvalidWindow { .. })
+        ));
+        assert!(matches!(
+            "@every 1h for 5m".parse::<Window>(),
+            Err(ScheduleError::IntervalWindow { .. })
+        ));
+        assert!(matches!(
+            "2024-02-30T02:00Z for 1h".parse::<Window>(),
+            Err(ScheduleError::InvalidTime { .. })
+        ));
+        assert!(matches!(
+            "@daily for 0s".parse::<Window>(),
+            Err(ScheduleError::ZeroInterval { .. })
+        ));
+    }
+
+    #[test]
+    fn finds_the_end_of_one_off_windows() {
+        let window = window("2024-03-01T02:00Z for 2h");
+        assert_eq!(window.end_if_active(at(2024, 3, 1, 1, 59)), None);
+        assert_eq!(
+            window.end_if_active(at(2024, 3, 1, 2, 0)),
+            Some(at(2024, 3, 1, 4, 0))
+        );
+        assert_eq!(window.end_if_active(at(2024, 3, 1, 4, 0)), None);
+    }
+
+    #[test]
+    fn finds_the_end_of_recurring_windows() {
+        // 2024-03-03 is a Sunday
+        let window = window("0 23 * * 6 for 3h");
+        assert_eq!(window.end_if_active(at(2024, 3, 2, 22, 59)), None);
+        assert_eq!(
+            window.end_if_active(at(2024, 3, 2, 23, 0)),
+            Some(at(2024, 3, 3, 2, 0))
+        );
+        assert_eq!(
+            window.end_if_active(at(2024, 3, 3, 1, 30)),
+            Some(at(2024, 3, 3, 2, 0))
+        );
+        assert_eq!(window.end_if_active(at(2024, 3, 3, 2, 0)), None);
+
+        let overlapping: Window = "*/10 * * * * for 15m".parse().unwrap();
+        assert_eq!(
+            overlapping.end_if_active(at(2024, 3, 3, 2, 12)),
+            Some(at(2024, 3, 3, 2, 25))
+        );
+    }
+
+    #[test]
+    fn lists_the_occurrences_in_a_span() {
+        let once = window("2024-03-01T02:00Z for 2h");
+        assert_eq!(
+            once.occurrences(at(2024, 3, 1, 3, 0), at(2024, 3, 2, 0, 0)),
+            vec![(at(2024, 3, 1, 2, 0), at(2024, 3, 1, 4, 0))]
+        );
+        assert!(once
+            .occurrences(at(2024, 3, 1, 4, 0), at(2024, 3, 2, 0, 0))
+            .is_empty());
+
+        let daily = window("0 23 * * * for 3h");
+        assert_eq!(
+            daily.occurrences(at(2024, 3, 2, 1, 0), at(2024, 3, 3, 23, 0)),
+            vec![
+                (at(2024, 3, 1, 23, 0), at(2024, 3, 2, 2, 0)),
+                (at(2024, 3, 2, 23, 0), at(2024, 3, 3, 2, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_and_parses_utc_times() {
+        assert_eq!(
+            format_utc_time(at(2024, 3, 1, 2, 30)),
+            "2024-03-01T02:30:00Z"
+        );
+        assert_eq!(
+            parse_utc_time("2024-03-01T02:30Z"),
+            Ok(at(2024, 3, 1, 2, 30))
+        );
+        assert!(matches!(
+            parse_utc_time("2024-03-01T02:30"),
+            Err(ScheduleError::InvalidTime { .. })
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use redacted::Redacted;
+use rusoto_core::Region;
+use rusoto_secretsmanager::{
+    GetSecretValueRequest, SecretsManager, SecretsManagerClient,
+};
+use serde_json::Value;
+use snafu::{OptionExt, ResultExt};
+use std::{env, fs};
+
+use crate::{
+    AwsBinarySecretSnafu, AwsRequestSnafu, InvalidJsonSnafu, MissingFieldSnafu,
+    MissingVarSnafu, ReadFileSnafu, SecretError, SecretRef, VaultRequestSnafu,
+};
+
+const VAULT_ADDR_VAR: &str = "VAULT_ADDR";
+const VAULT_TOKEN_VAR: &str = "VAULT_TOKEN";
+const VAULT_NAMESPACE_VAR: &str = "VAULT_NAMESPACE";
+
+/// Clients of the secret stores, created when a secret first needs them
+#[derive(Default)]
+pub(crate) struct Backends {
+    vault: Option<VaultClient>,
+    aws: Option<SecretsManagerClient>,
+}
+
+impl Backends {
+    pub async fn fetch(
+        &mut self,
+        reference: &SecretRef,
+    ) -> Result<String, SecretError> {
+        match reference {
+            SecretRef::Env { var } => {
+                env::var(var).ok().context(MissingVarSnafu { var })
+            }
+            SecretRef::File { path } => Ok(fs::read_to_string(path)
+                .context(ReadFileSnafu { path })?
+                .trim()
+                .to_owned()),
+            SecretRef::Vault { path, field } => {
+                if self.vault.is_none() {
+                    self.vault = Some(VaultClient::from_env()?);
+                }
+                let vault = self.vault.as_ref().expect("vault client is set");
+                let secret = vault.read(path).await?;
+                field_of(&secret, field, reference)
+            }
+            SecretRef::AwsSecretsManager { secret_id, field } => {
+                let aws = self.aws.get_or_insert_with(|| {
+                    SecretsManagerClient::new(Region::default())
+                });
+                let request = GetSecretValueRequest {
+                    secret_id: secret_id.to_owned(),
+                    ..Default::default()
+                };
+                let value = aws
+                    .get_secret_value(request)
+                    .await
+                    .context(AwsRequestSnafu { secret_id })?
+                    .secret_string
+                    .context(AwsBinarySecretSnafu { secret_id })?;
+                match field {
+                    None => Ok(value),
+                    Some(field) => {
+                        let secret: Value = serde_json::from_str(&value)
+                            .context(InvalidJsonSnafu {
+                                reference: reference.to_string(),
+                            })?;
+                        field_of(&secret, field, reference)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn field_of(
+    secret: &Value,
+    field: &str,
+    reference: &SecretRef,
+) -> Result<String, SecretError> {
+    secret
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .context(MissingFieldSnafu {
+            reference: reference.to_string(),
+            field,
+        })
+}
+
+struct VaultClient {
+    http: reqwest::Client,
+    addr: String,
+    token: Redacted<String>,
+    namespace: Option<String>,
+}
+
+impl VaultClient {
+    fn from_env() -> Result<Self, SecretError> {
+        let var = |var: &str| env::var(var).ok().filter(|v| !v.is_empty());
+        Ok(Self {
+            http: reqwest::Client::new(),
+            addr: var(VAULT_ADDR_VAR).context(MissingVarSnafu {
+                var: VAULT_ADDR_VAR,
+            })?,
+            token: Redacted::new(var(VAULT_TOKEN_VAR).context(
+                MissingVarSnafu {
+                    var: VAULT_TOKEN_VAR,
+                },
+            )?),
+            namespace: var(VAULT_NAMESPACE_VAR),
+        })
+    }
+
+    /// Reads the data of a secret, from both the version 1 and version 2 of
+    /// the key-value engine
+    async fn read(&self, path: &str) -> Result<Value, SecretError> {
+        let url = format!("{}/v1/{}", self.addr.trim_end_matches('/'), path);
+        let mut request = self
+            .http
+            .get(url)
+            .header("X-Vault-Token", self.token.inner());
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let mut body: Value = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context(VaultRequestSnafu { path })?
+            .json()
+            .await
+            .context(VaultRequestSnafu { path })?;
+        let mut data = body["data"].take();
+        if data["data"].is_object() {
+            data = data["data"].take();
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_reads_the_fields_of_a_secret() {
+        let reference = SecretRef::AwsSecretsManager {
+            secret_id: "node".to_owned(),
+            field: Some("rpc".to_owned()),
+        };
+        let secret = json!({ "rpc": "https://eth.example.com", "port": 1 });
+        assert_eq!(
+            field_of(&secret, "rpc", &reference).unwrap(),
+            "https://eth.example.com"
+        );
+        for field in ["port", "missing"] {
+            assert!(matches!(
+                field_of(&secret, field, &reference),
+                Err(SecretError::MissingField { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Resolution of the secrets referenced by the environment of a service.
+//!
+//! Any environment variable may hold a reference to a secret instead of its
+//! value, such as `POSTGRES_ENDPOINT=vault://secret/data/node#postgres`. The
+//! references are resolved once at startup, before the configuration is
+//! parsed, so every option of every service can be kept in a secret store
+//! without the services knowing about it:
+//!
+//! - `env://<var>` reads another environment variable;
+//! - `file://<path>` reads a file, such as a mounted Kubernetes secret;
+//! - `vault://<path>#<field>` reads a field of a HashiCorp Vault secret,
+//!   with the server and token taken from `VAULT_ADDR` and `VAULT_TOKEN`;
+//! - `awssm://<secret id>[#<field>]` reads an AWS Secrets Manager secret,
+//!   or a field of it when it holds a JSON object.
+//!
+//! With `SECRETS_REFRESH_INTERVAL` set, the secrets are fetched again
+//! periodically. Since the services read their configuration once, a
+//! rotated secret stops the service, so it's restarted with the new value.
+
+use redacted::Redacted;
+use rusoto_core::RusotoError;
+use rusoto_secretsmanager::GetSecretValueError;
+use snafu::{ResultExt, Snafu};
+use std::{env, fmt, path::PathBuf, time::Duration};
+
+mod backends;
+
+use backends::Backends;
+
+/// Variable with the interval between the refreshes of the secrets
+pub const REFRESH_INTERVAL_VAR: &str = "SECRETS_REFRESH_INTERVAL";
+
+#[derive(Debug, Snafu)]
+pub enum SecretError {
+    #[snafu(display("invalid secret reference `{}`: {}", reference, reason))]
+    InvalidReference { reference: String, reason: String },
+
+    #[snafu(display("environment variable `{}` is not set", var))]
+    MissingVar { var: String },
+
+    #[snafu(display("failed to read secret file `{}`", path.display()))]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to fetch Vault secret `{}`", path))]
+    VaultRequest {
+        path: String,
+        source: reqwest::Error,
+    },
+
+    #[snafu(display("failed to fetch AWS secret `{}`", secret_id))]
+    AwsRequest {
+        secret_id: String,
+        #[snafu(source(from(RusotoError<GetSecretValueError>, Box::new)))]
+        source: Box<RusotoError<GetSecretValueError>>,
+    },
+
+    #[snafu(display("AWS secret `{}` has no string value", secret_id))]
+    AwsBinarySecret { secret_id: String },
+
+    #[snafu(display("secret `{}` is not a JSON object", reference))]
+    InvalidJson {
+        reference: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display(
+        "secret `{}` has no string field `{}`",
+        reference,
+        field
+    ))]
+    MissingField { reference: String, field: String },
+
+    #[snafu(display("failed to resolve the secret of `{}`", var))]
+    Resolve {
+        var: String,
+        #[snafu(source(from(SecretError, Box::new)))]
+        source: Box<SecretError>,
+    },
+
+    #[snafu(display("invalid `{}`", REFRESH_INTERVAL_VAR))]
+    InvalidRefreshInterval { source: humane::HumaneError },
+
+    #[snafu(display(
+        "secret of `{}` was rotated; restarting to load it",
+        var
+    ))]
+    Rotated { var: String },
+}
+
+/// Where the value of a secret is kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretRef {
+    Env {
+        var: String,
+    },
+    File {
+        path: PathBuf,
+    },
+    Vault {
+        path: String,
+        field: String,
+    },
+    AwsSecretsManager {
+        secret_id: String,
+        field: Option<String>,
+    },
+}
+
+impl SecretRef {
+    /// Parses a secret reference, returning `None` when the value has no
+    /// secret scheme and is the secret itself
+    pub fn parse(value: &str) -> Result<Option<Self>, SecretError> {
+        let Some((scheme, location)) = value.split_once("://") else {
+            return Ok(None);
+        };
+        let invalid = |reason: &str| SecretError::InvalidReference {
+            reference: value.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let (location, field) = match location.split_once('#') {
+            Some((location, field)) => (location, Some(field.to_owned())),
+            None => (location, None),
+        };
+        if location.is_empty() {
+            return Err(invalid("missing location"));
+        }
+        let reference = match scheme {
+            "env" => SecretRef::Env {
+                var: location.to_owned(),
+            },
+            "file" => SecretRef::File {
+                path: PathBuf::from(location),
+            },
+            "vault" => SecretRef::Vault {
+                path: location.trim_start_matches('/').to_owned(),
+                field: field.ok_or_else(|| invalid("missing `#<field>`"))?,
+            },
+            "awssm" => SecretRef::AwsSecretsManager {
+                secret_id: location.to_owned(),
+                field,
+            },
+            _ => return Ok(None),
+        };
+        match reference {
+            SecretRef::Env { .. } | SecretRef::File { .. }
+                if value.contains('#') =>
+            {
+                Err(invalid("fields are only supported by vault and awssm"))
+            }
+            reference => Ok(Some(reference)),
+        }
+    }
+
+    /// Whether the secret is kept outside the node and fetched over the
+    /// network
+    fn is_remote(&self) -> bool {
+        matches!(
+            self,
+            SecretRef::Vault { .. } | SecretRef::AwsSecretsManager { .. }
+        )
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretRef::Env { var } => write!(f, "env://{}", var),
+            SecretRef::File { path } => write!(f, "file://{}", path.display()),
+            SecretRef::Vault { path, field } => {
+                write!(f, "vault://{}#{}", path, field)
+            }
+            SecretRef::AwsSecretsManager { secret_id, field } => {
+                write!(f, "awssm://{}", secret_id)?;
+                match field {
+                    Some(field) => write!(f, "#{}", field),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Secrets resolved from the environment, which may be refreshed
+pub struct Secrets {
+    resolved: Vec<ResolvedSecret>,
+    refresh_interval: Option<Duration>,
+    backends: Backends,
+}
+
+struct ResolvedSecret {
+    var: String,
+    reference: SecretRef,
+    value: Redacted<String>,
+}
+
+/// Replaces the environment variables that hold secret references with the
+/// values of the secrets.
+///
+/// The local references are resolved before the remote ones, so the token
+/// of Vault can itself be read from a file. Must be called before the
+/// configuration is parsed and before the service spawns tasks that read
+/// the environment.
+pub async fn resolve_env() -> Result<Secrets, SecretError> {
+    let refresh_interval = match env::var(REFRESH_INTERVAL_VAR) {
+        Ok(interval) if !interval.is_empty() => Some(
+            humane::parse_duration(&interval)
+                .context(InvalidRefreshIntervalSnafu)?,
+        ),
+        _ => None,
+    };
+
+    let mut references = vec![];
+    for (var, value) in env::vars_os() {
+        let (Some(var), Some(value)) = (var.to_str(), value.to_str()) else {
+            continue;
+        };
+        if let Some(reference) =
+            SecretRef::parse(value).context(ResolveSnafu { var })?
+        {
+            references.push((var.to_owned(), reference));
+        }
+    }
+    references.sort_by_key(|(_, reference)| reference.is_remote());
+
+    let mut backends = Backends::default();
+    let mut resolved = vec![];
+    for (var, reference) in references {
+        let value = backends
+            .fetch(&reference)
+            .await
+            .context(ResolveSnafu { var: &var })?;
+        env::set_var(&var, &value);
+        resolved.push(ResolvedSecret {
+            var,
+            reference,
+            value: Redacted::new(value),
+        });
+    }
+    Ok(Secrets {
+        resolved,
+        refresh_interval,
+        backends,
+    })
+}
+
+impl Secrets {
+    /// Names of the variables whose secrets were resolved
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.resolved.iter().map(|secret| secret.var.as_str())
+    }
+
+    /// Fetches the secrets periodically, returning once one of them
+    /// changes. A secret that can't be fetched keeps its value until the
+    /// next refresh. Never returns when the refresh is disabled.
+    pub async fn watch(mut self) -> SecretError {
+        let Some(interval) = self.refresh_interval else {
+            return std::future::pending().await;
+        };
+        tracing::info!(
+            vars = ?self.vars().collect::<Vec<_>>(),
+            ?interval,
+            "refreshing the secrets periodically"
+        );
+        loop {
+            tokio::time::sleep(interval).await;
+            for secret in &self.resolved {
+                match self.backends.fetch(&secret.reference).await {
+                    Ok(value) if value != *secret.value.inner() => {
+                        return SecretError::Rotated {
+                            var: secret.var.clone(),
+                        };
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!(
+                        var = secret.var,
+                        "failed to refresh secret: {}",
+                        e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_the_references() {
+        assert_eq!(
+            SecretRef::parse("env://DB_URL").unwrap(),
+            Some(SecretRef::Env {
+                var: "DB_URL".to_owned()
+            })
+        );
+        assert_eq!(
+            SecretRef::parse("file:///run/secrets/db").unwrap(),
+            Some(SecretRef::File {
+                path: PathBuf::from("/run/secrets/db")
+            })
+        );
+        assert_eq!(
+            SecretRef::parse("vault://secret/data/node#postgres").unwrap(),
+            Some(SecretRef::Vault {
+                path: "secret/data/node".to_owned(),
+                field: "postgres".to_owned(),
+            })
+        );
+        assert_eq!(
+            SecretRef::parse("awssm://prod/node#rpc").unwrap(),
+            Some(SecretRef::AwsSecretsManager {
+                secret_id: "prod/node".to_owned(),
+                field: Some("rpc".to_owned()),
+            })
+        );
+        assert_eq!(
+            SecretRef::parse("awssm://prod/signer").unwrap(),
+            Some(SecretRef::AwsSecretsManager {
+                secret_id: "prod/signer".to_owned(),
+                field: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_leaves_plain_values_alone() {
+        for value in [
+            "",
+            "0x1234",
+            "postgres://user:pw@localhost:5432/db",
+            "https://eth.example.com/v1/key#fragment",
+        ] {
+            assert_eq!(SecretRef::parse(value).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn it_rejects_invalid_references() {
+        for value in
+            ["vault://secret/data/node", "file://", "env://DB_URL#field"]
+        {
+            assert!(matches!(
+                SecretRef::parse(value),
+                Err(SecretError::InvalidReference { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_resolves_local_references() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"s3cr3t\n").unwrap();
+        let mut backends = Backends::default();
+        let reference = SecretRef::File {
+            path: file.path().to_owned(),
+        };
+        assert_eq!(backends.fetch(&reference).await.unwrap(), "s3cr3t");
+        assert!(matches!(
+            backends
+                .fetch(&SecretRef::Env {
+                    var: "SECRETS_TEST_UNSET_VAR".to_owned()
+                })
+                .await,
+            Err(SecretError::MissingVar { .. })
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Input box states built from public archives of the chain, such as the
+//! logs exported by cryo or from the BigQuery datasets, instead of querying
+//! every event since the deployment of the DApp from a provider.
+//!
+//! The archive is a JSON array or JSON lines of logs, with the topics either
+//! as `topics` or as `topic0` to `topic3`. Only the blocks of the inputs are
+//! fetched from the provider, to check the archive against the chain; the
+//! state is then checkpointed at the last block of the archive, so the
+//! state-server only syncs the blocks after it.
+
+use contracts::input_box::InputAddedFilter;
+use eth_state_fold_types::{
+    ethers::{
+        abi::RawLog,
+        contract::EthEvent,
+        providers::{Middleware, ProviderError},
+        types::{Address, Bytes, H256},
+    },
+    Block,
+};
+use serde::{Deserialize, Deserializer};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{collections::BTreeMap, sync::Arc};
+use types::foldables::{push_input, Input, InputBox, InputBoxInitialState};
+
+#[derive(Debug, Snafu)]
+pub enum BackfillError {
+    #[snafu(display("invalid log at line {} of the archive", line))]
+    ParseLog {
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("invalid InputAdded log in transaction {:?}", tx_hash))]
+    DecodeLog {
+        tx_hash: H256,
+        source: eth_state_fold_types::ethers::abi::Error,
+    },
+
+    #[snafu(display(
+        "archive has a log of block {}, after the last block {}",
+        block_number,
+        to_block
+    ))]
+    LogAfterLastBlock { block_number: u64, to_block: u64 },
+
+    #[snafu(display("failed to fetch block {} from the provider", number))]
+    FetchBlock { number: u64, source: ProviderError },
+
+    #[snafu(display("block {} isn't in the chain yet", number))]
+    MissingBlock { number: u64 },
+
+    #[snafu(display(
+        "block {} of the archive was reorged out of the chain",
+        number
+    ))]
+    ReorgedBlock { number: u64 },
+
+    #[snafu(display("inputs of the archive don't add up to an input box"))]
+    InputIndex {
+        #[snafu(source(from(anyhow::Error, Into::into)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+/// Log exported from an archive of the chain
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArchiveLog {
+    #[serde(deserialize_with = "number")]
+    pub block_number: u64,
+    /// Missing from the cryo exports unless included with
+    /// `--include-columns block_hash`
+    #[serde(default)]
+    pub block_hash: Option<H256>,
+    pub transaction_hash: H256,
+    pub address: Address,
+    #[serde(default)]
+    topics: Vec<H256>,
+    #[serde(default)]
+    topic0: Option<H256>,
+    #[serde(default)]
+    topic1: Option<H256>,
+    #[serde(default)]
+    topic2: Option<H256>,
+    #[serde(default)]
+    topic3: Option<H256>,
+    pub data: Bytes,
+}
+
+impl ArchiveLog {
+    pub fn topics(&self) -> Vec<H256> {
+        if !self.topics.is_empty() {
+            return self.topics.clone();
+        }
+        [self.topic0, self.topic1, self.topic2, self.topic3]
+            .into_iter()
+            .map_while(std::convert::identity)
+            .collect()
+    }
+}
+
+/// Block numbers are either JSON numbers or, as BigQuery exports them,
+/// strings
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Number(u64),
+        String(String),
+    }
+
+    match Number::deserialize(deserializer)? {
+        Number::Number(number) => Ok(number),
+        Number::String(number) => match number.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => number.parse(),
+        }
+        .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Reads the logs of the archive, as a JSON array or as JSON lines
+pub fn read_logs(archive: &str) -> Result<Vec<ArchiveLog>, BackfillError> {
+    if archive.trim_start().starts_with('[') {
+        return serde_json::from_str(archive)
+            .context(ParseLogSnafu { line: 1 });
+    }
+    archive
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .context(ParseLogSnafu { line: index + 1 })
+        })
+        .collect()
+}
+
+/// InputAdded logs of the DApp in the archive, by input index
+pub fn input_logs(
+    logs: Vec<ArchiveLog>,
+    initial_state: &InputBoxInitialState,
+) -> Result<Vec<(ArchiveLog, InputAddedFilter)>, BackfillError> {
+    let dapp = H256::from(*initial_state.dapp_address);
+    let mut inputs = vec![];
+    for log in logs {
+        let topics = log.topics();
+        if log.address != *initial_state.input_box_address
+            || topics.first() != Some(&InputAddedFilter::signature())
+            || topics.get(1) != Some(&dapp)
+        {
+            continue;
+        }
+        let event = InputAddedFilter::decode_log(&RawLog {
+            topics,
+            data: log.data.to_vec(),
+        })
+        .context(DecodeLogSnafu {
+            tx_hash: log.transaction_hash,
+        })?;
+        inputs.push((log, event));
+    }
+    inputs.sort_by_key(|(_, event)| event.input_index);
+    Ok(inputs)
+}
+
+/// Input box of the DApp with the inputs of the archive, which ends at
+/// `to_block`, along with that block
+pub async fn backfill<M: Middleware<Error = ProviderError>>(
+    provider: &M,
+    initial_state: &InputBoxInitialState,
+    logs: Vec<ArchiveLog>,
+    to_block: u64,
+) -> Result<(InputBox, Block), BackfillError> {
+    let mut blocks: BTreeMap<u64, Arc<Block>> = BTreeMap::new();
+    let mut input_boxes = Default::default();
+    for (log, event) in input_logs(logs, initial_state)? {
+        ensure!(
+            log.block_number <= to_block,
+            LogAfterLastBlockSnafu {
+                block_number: log.block_number,
+                to_block
+            }
+        );
+        let block = match blocks.get(&log.block_number) {
+            Some(block) => block.clone(),
+            None => {
+                let block =
+                    Arc::new(fetch_block(provider, log.block_number).await?);
+                blocks.insert(log.block_number, block.clone());
+                block
+            }
+        };
+        ensure!(
+            log.block_hash.iter().all(|hash| *hash == block.hash),
+            ReorgedBlockSnafu {
+                number: log.block_number
+            }
+        );
+        let input = Input {
+            sender: Arc::new(event.sender),
+            payload: event.input.to_vec(),
+            block_added: block,
+            dapp: Arc::new(event.dapp),
+            tx_hash: Arc::new(log.transaction_hash),
+        };
+        push_input(&mut input_boxes, event.input_index, Arc::new(input))
+            .context(InputIndexSnafu)?;
+    }
+
+    let block = fetch_block(provider, to_block).await?;
+    let input_box = InputBox {
+        dapp_address: initial_state.dapp_address.clone(),
+        input_box_address: initial_state.input_box_address.clone(),
+        dapp_input_boxes: Arc::new(input_boxes),
+    };
+    Ok((input_box, block))
+}
+
+async fn fetch_block<M: Middleware<Error = ProviderError>>(
+    provider: &M,
+    number: u64,
+) -> Result<Block, BackfillError> {
+    let block = provider
+        .get_block(number)
+        .await
+        .context(FetchBlockSnafu { number })?
+        .context(MissingBlockSnafu { number })?;
+    Ok(Block {
+        hash: block.hash.context(MissingBlockSnafu { number })?,
+        number: block.number.context(MissingBlockSnafu { number })?,
+        parent_hash: block.parent_hash,
+        timestamp: block.timestamp,
+        logs_bloom: block.logs_bloom.context(MissingBlockSnafu { number })?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethers::abi::{encode, Token};
+    use serde_json::json;
+
+    fn dapp() -> Address {
+        Address::repeat_byte(0xda)
+    }
+
+    fn input_box() -> Address {
+        Address::repeat_byte(0xbb)
+    }
+
+    fn initial_state() -> InputBoxInitialState {
+        InputBoxInitialState {
+            dapp_address: Arc::new(dapp()),
+            input_box_address: Arc::new(input_box()),
+        }
+    }
+
+    fn input_added(dapp: Address, index: u64, payload: &[u8]) -> ArchiveLog {
+        let data = encode(&[
+            Token::Address(Address::repeat_byte(0x5e)),
+            Token::Bytes(payload.to_vec()),
+        ]);
+        serde_json::from_value(json!({
+            "block_number": 100 + index,
+            "transaction_hash": H256::repeat_byte(index as u8),
+            "address": input_box(),
+            "topics": [
+                InputAddedFilter::signature(),
+                H256::from(dapp),
+                H256::from_low_u64_be(index),
+            ],
+            "data": Bytes::from(data),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_reads_the_logs_of_cryo_and_bigquery_exports() {
+        let cryo = json!([{
+            "block_number": 17034870,
+            "block_hash": H256::repeat_byte(1),
+            "transaction_hash": H256::repeat_byte(2),
+            "address": input_box(),
+            "topic0": H256::repeat_byte(3),
+            "topic1": H256::repeat_byte(4),
+            "topic2": null,
+            "topic3": null,
+            "data": "0x",
+        }]);
+        let logs = read_logs(&cryo.to_string()).unwrap();
+        assert_eq!(logs[0].block_number, 17034870);
+        assert_eq!(
+            logs[0].topics(),
+            vec![H256::repeat_byte(3), H256::repeat_byte(4)]
+        );
+
+        let bigquery = json!({
+            "block_number": "17034870",
+            "transaction_hash": H256::repeat_byte(2),
+            "address": input_box(),
+            "topics": [H256::repeat_byte(3)],
+            "data": "0x01",
+        });
+        let lines = format!("{}\n\n{}\n", bigquery, bigquery);
+        let logs = read_logs(&lines).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[1].block_number, 17034870);
+        assert_eq!(logs[1].block_hash, None);
+        assert_eq!(logs[1].topics(), vec![H256::repeat_byte(3)]);
+
+        let error = read_logs(&format!("{}\n{{", bigquery)).unwrap_err();
+        assert!(matches!(error, BackfillError::ParseLog { line: 2, .. }));
+    }
+
+    #[test]
+    fn it_keeps_the_inputs_of_the_dapp_in_order() {
+        let mut other_contract = input_added(dapp(), 0, b"other");
+        other_contract.address = Address::repeat_byte(0xcc);
+        let logs = vec![
+            input_added(dapp(), 1, b"second"),
+            input_added(Address::repeat_byte(0xdb), 0, b"other dapp"),
+            other_contract,
+            input_added(dapp(), 0, b"first"),
+        ];
+        let inputs = input_logs(logs, &initial_state()).unwrap();
+        let payloads: Vec<_> = inputs
+            .iter()
+            .map(|(_, event)| event.input.to_vec())
+            .collect();
+        assert_eq!(payloads, vec![b"first".to_vec(), b"second".to_vec()]);
+        assert_eq!(inputs[0].0.block_number, 100);
+    }
+}
@@ -1,11 +1,17 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use eth_state_fold_types::ethers::types::{H160, H256};
 use eth_state_server_lib::config::{
     Result, StateServerConfig, StateServerEnvCLIConfig,
 };
+use http_provider::{HttpClientCLIConfig, HttpClientConfig};
 use log::{LogConfig, LogEnvCliConfig};
+use redacted::{Redacted, RedactedUrl, Url};
+use state_server::factory::PipelinesConfig;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use types::foldables::DAppFactoryInitialState;
 
 #[derive(Parser)]
 #[command(name = "state_server_config")]
@@ -16,12 +22,121 @@ pub struct EnvCLIConfig {
 
     #[command(flatten)]
     pub log_config: LogEnvCliConfig,
+
+    #[command(flatten)]
+    pub http_client_config: HttpClientCLIConfig,
+
+    /// State served to the clients
+    #[arg(long, env, value_enum, default_value_t = FoldableKind::InputBox)]
+    pub state_server_foldable: FoldableKind,
+
+    /// Every how many blocks the inputs folded from a block are checked
+    /// against the receipts of the block, reporting and folding again the
+    /// blocks where they differ. Only applies to the input box; zero turns
+    /// the checks off
+    #[arg(long, env, default_value_t = 0)]
+    pub state_server_gap_check_interval: u64,
+
+    /// Directory where the states are checkpointed, so the syncs on start
+    /// and after deep reorgs only query the events after the last
+    /// checkpoint; without it, every sync starts from genesis
+    #[arg(long, env)]
+    pub state_server_checkpoint_dir: Option<PathBuf>,
+
+    /// Every how many blocks the states are checkpointed
+    #[arg(long, env, default_value_t = 1000)]
+    pub state_server_checkpoint_interval: u64,
+
+    /// How many of the last checkpoints of each state are kept. A sync
+    /// resumes from the newest one still in the chain, so the states roll
+    /// back past the checkpoints of reorged out blocks instead of starting
+    /// from genesis
+    #[arg(long, env, default_value_t = 3)]
+    pub state_server_checkpoint_history: usize,
+
+    /// Private key of the operator, in hex, that signs the manifests of the
+    /// checkpoints; with it, only the checkpoints in a manifest it signed are
+    /// resumed from
+    #[arg(long, env)]
+    pub state_server_checkpoint_signing_key: Option<String>,
+
+    /// Address of the gRPC server that streams only the entries of the
+    /// states that changed on each block; without it, the changes are not
+    /// served
+    #[arg(long, env)]
+    pub state_server_diff_address: Option<SocketAddr>,
+
+    /// Address of the HTTP server of the metrics of the syncs, folds and
+    /// event queries, at `/metrics`; without it, they are not served
+    #[arg(long, env)]
+    pub state_server_metrics_address: Option<SocketAddr>,
+
+    /// Address of the HTTP server that changes the log filter at
+    /// `/log-filter`, which should be on the loopback interface; it is only
+    /// served along with the metrics
+    #[arg(long, env)]
+    pub state_server_admin_address: Option<SocketAddr>,
+
+    /// Maximum number of log queries in flight while splitting the ones the
+    /// provider rejects for their size into smaller block ranges
+    #[arg(long, env, default_value_t = 4)]
+    pub state_server_logs_max_parallelism: usize,
+
+    /// Comma-separated HTTP endpoints of other nodes of the same chain,
+    /// which the syncs and folds fail over to, in order, when the main
+    /// endpoint fails
+    #[arg(long, env, value_delimiter = ',')]
+    pub state_server_fallback_http_endpoints: Vec<Url>,
+
+    /// Address of a CartesiDAppFactory whose DApps get their inputs folded
+    /// from the block they are deployed, so their nodes start without a cold
+    /// sync. Only applies to the input box
+    #[arg(long, env, requires = "state_server_input_box_address")]
+    pub state_server_factory_address: Option<H160>,
+
+    /// Only the DApps of this owner get their inputs folded, if set
+    #[arg(long, env, requires = "state_server_factory_address")]
+    pub state_server_factory_dapp_owner: Option<H160>,
+
+    /// Only the DApps of this template hash get their inputs folded, if set
+    #[arg(long, env, requires = "state_server_factory_address")]
+    pub state_server_factory_template_hash: Option<H256>,
+
+    /// Address of the InputBox the inputs of the DApps deployed by the
+    /// factory are added to
+    #[arg(long, env, requires = "state_server_factory_address")]
+    pub state_server_input_box_address: Option<H160>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FoldableKind {
+    /// Inputs of the DApps, read by the dispatcher
+    InputBox,
+    /// Claims of the v1 History contract, read by proof generation and the
+    /// reader API of InputBox-era DApps
+    History,
+    /// DApps deployed by a CartesiDAppFactory, read by hosted nodes to start
+    /// the pipelines of new DApps
+    DAppFactory,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub state_server_config: StateServerConfig,
     pub log_config: LogConfig,
+    pub http_client_config: HttpClientConfig,
+    pub foldable: FoldableKind,
+    pub gap_check_interval: u64,
+    pub checkpoint_dir: Option<PathBuf>,
+    pub checkpoint_interval: u64,
+    pub checkpoint_history: usize,
+    pub checkpoint_signing_key: Option<Redacted<String>>,
+    pub diff_address: Option<SocketAddr>,
+    pub metrics_address: Option<SocketAddr>,
+    pub admin_address: Option<SocketAddr>,
+    pub logs_max_parallelism: usize,
+    pub fallback_http_endpoints: Vec<RedactedUrl>,
+    pub pipelines: Option<PipelinesConfig>,
 }
 
 impl Config {
@@ -29,10 +144,49 @@ impl Config {
         let state_server_config =
             StateServerConfig::initialize(env_cli_config.state_server_config);
         let log_config = LogConfig::initialize(env_cli_config.log_config);
+        let http_client_config = env_cli_config.http_client_config.into();
+        let pipelines = env_cli_config.state_server_factory_address.map(
+            |factory_address| PipelinesConfig {
+                factory: DAppFactoryInitialState {
+                    factory_address: Arc::new(factory_address),
+                    dapp_owner: env_cli_config
+                        .state_server_factory_dapp_owner
+                        .map(Arc::new),
+                    template_hash: env_cli_config
+                        .state_server_factory_template_hash,
+                },
+                input_box_address: Arc::new(
+                    env_cli_config
+                        .state_server_input_box_address
+                        .expect("clap requires the input box address"),
+                ),
+            },
+        );
 
         Ok(Self {
             state_server_config: state_server_config?,
             log_config,
+            http_client_config,
+            foldable: env_cli_config.state_server_foldable,
+            gap_check_interval: env_cli_config.state_server_gap_check_interval,
+            checkpoint_dir: env_cli_config.state_server_checkpoint_dir,
+            checkpoint_interval: env_cli_config
+                .state_server_checkpoint_interval,
+            checkpoint_history: env_cli_config.state_server_checkpoint_history,
+            checkpoint_signing_key: env_cli_config
+                .state_server_checkpoint_signing_key
+                .map(Redacted::new),
+            diff_address: env_cli_config.state_server_diff_address,
+            metrics_address: env_cli_config.state_server_metrics_address,
+            admin_address: env_cli_config.state_server_admin_address,
+            logs_max_parallelism: env_cli_config
+                .state_server_logs_max_parallelism,
+            fallback_http_endpoints: env_cli_config
+                .state_server_fallback_http_endpoints
+                .into_iter()
+                .map(RedactedUrl::new)
+                .collect(),
+            pipelines,
         })
     }
 
@@ -0,0 +1,156 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Entries that changed between two states, compared through their JSON
+
+use serde_json::Value;
+
+/// Entry of a state that was added, replaced or removed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedEntry {
+    /// JSON pointer (RFC 6901) to the entry
+    pub path: String,
+    /// New value of the entry, or none if it was removed
+    pub value: Option<Value>,
+}
+
+/// Entries to change in `previous` to get to `current`, as deep as both
+/// have objects or arrays at the same path. Removed array elements come
+/// last first, so the changes apply in order.
+pub fn changed_entries(previous: &Value, current: &Value) -> Vec<ChangedEntry> {
+    let mut entries = vec![];
+    push_changes(String::new(), previous, current, &mut entries);
+    entries
+}
+
+fn push_changes(
+    path: String,
+    previous: &Value,
+    current: &Value,
+    entries: &mut Vec<ChangedEntry>,
+) {
+    match (previous, current) {
+        (Value::Object(previous), Value::Object(current)) => {
+            for (key, value) in current {
+                let path = format!("{}/{}", path, escape(key));
+                match previous.get(key) {
+                    Some(previous) => {
+                        push_changes(path, previous, value, entries)
+                    }
+                    None => entries.push(ChangedEntry {
+                        path,
+                        value: Some(value.clone()),
+                    }),
+                }
+            }
+            for key in previous.keys() {
+                if !current.contains_key(key) {
+                    entries.push(ChangedEntry {
+                        path: format!("{}/{}", path, escape(key)),
+                        value: None,
+                    });
+                }
+            }
+        }
+        (Value::Array(previous), Value::Array(current)) => {
+            for (index, value) in current.iter().enumerate() {
+                let path = format!("{}/{}", path, index);
+                match previous.get(index) {
+                    Some(previous) => {
+                        push_changes(path, previous, value, entries)
+                    }
+                    None => entries.push(ChangedEntry {
+                        path,
+                        value: Some(value.clone()),
+                    }),
+                }
+            }
+            for index in (current.len()..previous.len()).rev() {
+                entries.push(ChangedEntry {
+                    path: format!("{}/{}", path, index),
+                    value: None,
+                });
+            }
+        }
+        _ if previous == current => {}
+        _ => entries.push(ChangedEntry {
+            path,
+            value: Some(current.clone()),
+        }),
+    }
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn changed(path: &str, value: Value) -> ChangedEntry {
+        ChangedEntry {
+            path: path.to_owned(),
+            value: Some(value),
+        }
+    }
+
+    fn removed(path: &str) -> ChangedEntry {
+        ChangedEntry {
+            path: path.to_owned(),
+            value: None,
+        }
+    }
+
+    #[test]
+    fn it_finds_no_changes_in_equal_states() {
+        let state = json!({"inputs": [{"index": 0}], "address": "0xaa"});
+        assert!(changed_entries(&state, &state).is_empty());
+    }
+
+    #[test]
+    fn it_only_lists_the_changed_entries() {
+        let previous = json!({
+            "dapp_input_boxes": {"0xaa": {"inputs": [1, 2]}, "0xbb": {}},
+            "address": "0xcc",
+        });
+        let current = json!({
+            "dapp_input_boxes": {"0xaa": {"inputs": [1, 3, 4]}, "0xdd": {}},
+            "address": "0xcc",
+        });
+        assert_eq!(
+            changed_entries(&previous, &current),
+            vec![
+                changed("/dapp_input_boxes/0xaa/inputs/1", json!(3)),
+                changed("/dapp_input_boxes/0xaa/inputs/2", json!(4)),
+                changed("/dapp_input_boxes/0xdd", json!({})),
+                removed("/dapp_input_boxes/0xbb"),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_removes_array_elements_from_the_end() {
+        let previous = json!([1, 2, 3, 4]);
+        let current = json!([1, 5]);
+        assert_eq!(
+            changed_entries(&previous, &current),
+            vec![changed("/1", json!(5)), removed("/3"), removed("/2")]
+        );
+    }
+
+    #[test]
+    fn it_replaces_entries_that_change_type() {
+        let previous = json!({"a/b~c": [1]});
+        let current = json!({"a/b~c": {"0": 1}});
+        assert_eq!(
+            changed_entries(&previous, &current),
+            vec![changed("/a~1b~0c", json!({"0": 1}))]
+        );
+        assert_eq!(
+            changed_entries(&json!(1), &json!(null)),
+            vec![changed("", json!(null))]
+        );
+    }
+}
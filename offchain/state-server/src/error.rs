@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use eth_block_history::BlockArchiveError;
-use eth_state_fold_types::ethers::providers::{Http, RetryClient};
+use http_provider::{HttpClientError as ClientError, HttpProvider};
 use snafu::Snafu;
 use tonic::transport::Error as TonicError;
 use url::ParseError;
@@ -17,12 +17,11 @@ pub enum StateServerError {
     #[snafu(display("parser error"))]
     ParserError { source: ParseError },
 
+    #[snafu(display("HTTP client error"))]
+    HttpClientError { source: ClientError },
+
     #[snafu(display("block archive error"))]
     BlockArchiveError {
-        source: BlockArchiveError<
-            eth_state_fold_types::ethers::providers::Provider<
-                RetryClient<Http>,
-            >,
-        >,
+        source: BlockArchiveError<HttpProvider>,
     },
 }
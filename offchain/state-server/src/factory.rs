@@ -0,0 +1,238 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Pipelines of the DApps deployed by a CartesiDAppFactory. The factory is
+//! folded on every new block, and the inputs of each DApp it deployed are
+//! folded from then on, so hosted nodes onboard new DApps without changing
+//! the configuration of the state-server.
+
+use eth_state_fold::{Foldable, StateFoldEnvironment};
+use eth_state_fold_types::{ethers::types::Address, Block};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use types::foldables::{
+    DAppFactory, DAppFactoryInitialState, DeployedDApp, InputBoxInitialState,
+};
+use types::UserData;
+
+use crate::ServerProvider;
+
+/// Interval between the checks for a new block
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct PipelinesConfig {
+    /// Factory and the owner and template hash of the DApps registered
+    pub factory: DAppFactoryInitialState,
+    /// InputBox the inputs of the DApps are added to
+    pub input_box_address: Arc<Address>,
+}
+
+/// Registers a pipeline for each DApp the factory deploys and keeps the
+/// states of `F` of the registered DApps folded up to the latest block
+pub struct FactoryDelegate<F: Foldable> {
+    config: PipelinesConfig,
+    pipelines: HashMap<Arc<Address>, F::InitialState>,
+}
+
+impl<F> FactoryDelegate<F>
+where
+    F: Foldable<UserData = Mutex<UserData>> + 'static,
+    <F as Foldable>::InitialState: serde::de::DeserializeOwned,
+{
+    pub fn new(config: PipelinesConfig) -> Self {
+        Self {
+            config,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Registers the pipelines of the DApps not registered yet, returning
+    /// their addresses. The factory fold only keeps the DApps that match the
+    /// configured owner and template hash.
+    pub fn register<'a>(
+        &mut self,
+        dapps: impl IntoIterator<Item = &'a Arc<DeployedDApp>>,
+    ) -> Result<Vec<Arc<Address>>, serde_json::Error> {
+        let mut registered = vec![];
+        for dapp in dapps {
+            if self.pipelines.contains_key(&dapp.dapp_address) {
+                continue;
+            }
+            let initial_state = self.initial_state(dapp)?;
+            tracing::info!(
+                dapp_address = ?dapp.dapp_address,
+                dapp_owner = ?dapp.dapp_owner,
+                "registered the pipeline of a DApp deployed by the factory"
+            );
+            self.pipelines
+                .insert(Arc::clone(&dapp.dapp_address), initial_state);
+            registered.push(Arc::clone(&dapp.dapp_address));
+        }
+        Ok(registered)
+    }
+
+    /// Addresses of the DApps with a registered pipeline
+    pub fn dapps(&self) -> impl Iterator<Item = &Arc<Address>> {
+        self.pipelines.keys()
+    }
+
+    /// Initial state of the pipeline of the DApp. The served foldable is one
+    /// of the input box ones, whose initial states all serialize as the one
+    /// of the input box.
+    fn initial_state(
+        &self,
+        dapp: &DeployedDApp,
+    ) -> Result<F::InitialState, serde_json::Error> {
+        let initial_state = InputBoxInitialState {
+            dapp_address: Arc::clone(&dapp.dapp_address),
+            input_box_address: Arc::clone(&self.config.input_box_address),
+        };
+        serde_json::to_value(initial_state).and_then(serde_json::from_value)
+    }
+
+    /// Folds the factory and the registered pipelines on every new block.
+    /// Failed folds are retried on the next block.
+    pub async fn run(
+        mut self,
+        block_archive: Arc<eth_block_history::BlockArchive<ServerProvider>>,
+        env: Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
+    ) {
+        tracing::info!(
+            factory_address = ?self.config.factory.factory_address,
+            "registering the pipelines of the DApps deployed by the factory"
+        );
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        let mut last_hash = None;
+        loop {
+            interval.tick().await;
+            let block = block_archive.latest_block().await;
+            if last_hash == Some(block.hash) {
+                continue;
+            }
+            last_hash = Some(block.hash);
+            self.fold(block, &env).await;
+        }
+    }
+
+    async fn fold(
+        &mut self,
+        block: Arc<Block>,
+        env: &StateFoldEnvironment<ServerProvider, Mutex<UserData>>,
+    ) {
+        match DAppFactory::get_state_for_block(
+            &self.config.factory,
+            Arc::clone(&block),
+            env,
+        )
+        .await
+        {
+            Ok(factory) => {
+                if let Err(e) = self.register(factory.state.dapps.iter()) {
+                    tracing::error!(
+                        "failed to register the pipeline of a DApp: {}",
+                        e
+                    );
+                }
+            }
+            Err(e) => tracing::warn!(
+                block = ?block.hash,
+                "failed to fold the DApps deployed by the factory: {}",
+                e
+            ),
+        }
+        for (dapp_address, initial_state) in self.pipelines.iter() {
+            if let Err(e) =
+                F::get_state_for_block(initial_state, Arc::clone(&block), env)
+                    .await
+            {
+                tracing::warn!(
+                    ?dapp_address,
+                    block = ?block.hash,
+                    "failed to fold the pipeline of the DApp: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethers::types::{H256, U256, U64};
+    use types::foldables::{Audited, History, InputBox};
+
+    fn config() -> PipelinesConfig {
+        PipelinesConfig {
+            factory: DAppFactoryInitialState {
+                factory_address: Arc::new(Address::repeat_byte(0xfa)),
+                dapp_owner: None,
+                template_hash: None,
+            },
+            input_box_address: Arc::new(Address::repeat_byte(0x1b)),
+        }
+    }
+
+    fn deployed(dapp: u8) -> Arc<DeployedDApp> {
+        Arc::new(DeployedDApp {
+            dapp_address: Arc::new(Address::repeat_byte(dapp)),
+            dapp_owner: Arc::new(Address::repeat_byte(0x0e)),
+            consensus_address: Arc::new(Address::repeat_byte(0xc0)),
+            template_hash: H256::repeat_byte(0x7e),
+            block_added: Arc::new(Block {
+                hash: H256::repeat_byte(dapp),
+                number: U64::from(dapp),
+                parent_hash: H256::zero(),
+                timestamp: U256::zero(),
+                logs_bloom: Default::default(),
+            }),
+            tx_hash: Arc::new(H256::repeat_byte(dapp)),
+        })
+    }
+
+    #[test]
+    fn it_registers_each_dapp_once() {
+        let mut delegate = FactoryDelegate::<InputBox>::new(config());
+
+        let registered = delegate.register(&[deployed(1), deployed(2)]);
+        assert_eq!(
+            registered.unwrap(),
+            vec![
+                Arc::new(Address::repeat_byte(1)),
+                Arc::new(Address::repeat_byte(2))
+            ]
+        );
+
+        let registered =
+            delegate.register(&[deployed(1), deployed(2), deployed(3)]);
+        assert_eq!(
+            registered.unwrap(),
+            vec![Arc::new(Address::repeat_byte(3))]
+        );
+        assert_eq!(delegate.dapps().count(), 3);
+
+        let initial_state = &delegate.pipelines[&Address::repeat_byte(3)];
+        assert_eq!(*initial_state.dapp_address, Address::repeat_byte(3));
+        assert_eq!(
+            *initial_state.input_box_address,
+            Address::repeat_byte(0x1b)
+        );
+    }
+
+    #[test]
+    fn it_registers_the_pipelines_of_the_wrapped_input_box() {
+        let mut delegate = FactoryDelegate::<Audited<InputBox>>::new(config());
+        assert!(delegate.register(&[deployed(1)]).is_ok());
+    }
+
+    #[test]
+    fn it_fails_to_register_the_pipelines_of_other_foldables() {
+        let mut delegate = FactoryDelegate::<History>::new(config());
+        assert!(delegate.register(&[deployed(1)]).is_err());
+        assert_eq!(delegate.dapps().count(), 0);
+    }
+}
@@ -0,0 +1,226 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use clap::{Args, Parser, Subcommand};
+use eth_state_client_lib::{
+    config::{SCConfig, SCEnvCLIConfig},
+    BlockServer, GrpcStateFoldClient, StateServer,
+};
+use eth_state_fold_types::{
+    ethereum_types::{H256, U64},
+    ethers::signers::LocalWallet,
+    QueryBlock,
+};
+use http_provider::{HttpClient, HttpClientCLIConfig, HttpClientConfig};
+use serde_json::Value;
+use state_server::{backfill, query::Filter};
+use std::path::PathBuf;
+use tonic::transport::Channel;
+use types::foldables::{seed_checkpoint, InputBoxInitialState};
+use url::Url;
+
+#[derive(Parser)]
+#[command(name = "cartesi-rollups-state")]
+#[command(about = "Reads and seeds the states of the state-server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the fragments of a state selected by a jq-style filter, such
+    /// as `.dapp_input_boxes[] | .inputs | length`
+    Inspect(InspectArgs),
+
+    /// Checkpoints the input box state built from an archive of the logs of
+    /// the chain, so the state-server only syncs the blocks after it
+    Backfill(BackfillArgs),
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    #[command(flatten)]
+    sc_config: SCEnvCLIConfig,
+
+    /// Initial state of the foldable served by the state-server, as JSON
+    /// (e.g. `{"dapp_address":"0x...","input_box_address":"0x..."}`)
+    #[arg(long, env = "STATE_INSPECT_INITIAL_STATE", value_parser = parse_json)]
+    initial_state: Value,
+
+    /// Block to read the state at: `latest`, a block number or a block hash
+    #[arg(long, default_value = "latest", value_parser = parse_block)]
+    block: BlockSelector,
+
+    /// Print each fragment on a single line
+    #[arg(long)]
+    compact: bool,
+
+    /// Filter applied to the state; `.` prints all of it
+    #[arg(default_value = ".")]
+    filter: Filter,
+}
+
+#[derive(Args)]
+struct BackfillArgs {
+    #[command(flatten)]
+    http_client_config: HttpClientCLIConfig,
+
+    /// HTTP endpoint of the node the blocks of the inputs are fetched from
+    #[arg(long, env)]
+    bh_http_endpoint: Url,
+
+    /// Initial state of the input box, as JSON
+    /// (e.g. `{"dapp_address":"0x...","input_box_address":"0x..."}`)
+    #[arg(
+        long,
+        env = "STATE_BACKFILL_INITIAL_STATE",
+        value_parser = parse_initial_state
+    )]
+    initial_state: InputBoxInitialState,
+
+    /// Last block the archive covers, where the state is checkpointed. It
+    /// should be finalized, since a checkpoint of a block reorged out of the
+    /// chain isn't resumed from
+    #[arg(long)]
+    to_block: u64,
+
+    /// Directory of the checkpoints of the state-server
+    #[arg(long, env)]
+    state_server_checkpoint_dir: PathBuf,
+
+    /// How many of the last checkpoints of each state are kept
+    #[arg(long, env, default_value_t = 3)]
+    state_server_checkpoint_history: usize,
+
+    /// Private key of the operator, in hex, that signs the manifests of the
+    /// checkpoints
+    #[arg(long, env)]
+    state_server_checkpoint_signing_key: Option<String>,
+
+    /// Files of the logs exported from the archive, each a JSON array or
+    /// JSON lines
+    #[arg(required = true)]
+    archive: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BlockSelector {
+    Latest,
+    Number(u64),
+    Hash(H256),
+}
+
+impl From<BlockSelector> for QueryBlock {
+    fn from(block: BlockSelector) -> Self {
+        match block {
+            BlockSelector::Latest => QueryBlock::Latest,
+            BlockSelector::Number(number) => {
+                QueryBlock::BlockNumber(U64::from(number))
+            }
+            BlockSelector::Hash(hash) => QueryBlock::BlockHash(hash),
+        }
+    }
+}
+
+fn parse_block(value: &str) -> Result<BlockSelector, String> {
+    if value == "latest" {
+        Ok(BlockSelector::Latest)
+    } else if value.starts_with("0x") {
+        value
+            .parse()
+            .map(BlockSelector::Hash)
+            .map_err(|e| format!("invalid block hash: {}", e))
+    } else {
+        value
+            .parse()
+            .map(BlockSelector::Number)
+            .map_err(|e| format!("invalid block number: {}", e))
+    }
+}
+
+fn parse_json(value: &str) -> Result<Value, String> {
+    serde_json::from_str(value).map_err(|e| format!("invalid JSON: {}", e))
+}
+
+fn parse_initial_state(value: &str) -> Result<InputBoxInitialState, String> {
+    serde_json::from_str(value)
+        .map_err(|e| format!("invalid initial state: {}", e))
+}
+
+async fn inspect(args: InspectArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sc_config = SCConfig::initialize(args.sc_config)?;
+    let channel = Channel::from_shared(sc_config.grpc_endpoint.to_owned())?
+        .connect()
+        .await?;
+    let client: GrpcStateFoldClient<Value, Value> =
+        GrpcStateFoldClient::new_from_channel(channel, &sc_config);
+
+    let block = client.query_block(QueryBlock::from(args.block)).await?;
+    let state = client.query_state(&args.initial_state, block.hash).await?;
+    eprintln!("State at block {} ({:?})", block.number, block.hash);
+
+    for fragment in args.filter.apply(&state.state)? {
+        let fragment = if args.compact {
+            serde_json::to_string(&fragment)?
+        } else {
+            serde_json::to_string_pretty(&fragment)?
+        };
+        println!("{}", fragment);
+    }
+    Ok(())
+}
+
+async fn backfill(
+    args: BackfillArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = match &args.state_server_checkpoint_signing_key {
+        Some(key) => Some(key.trim().parse::<LocalWallet>()?),
+        None => None,
+    };
+    let mut logs = vec![];
+    for path in &args.archive {
+        let archive = std::fs::read_to_string(path)?;
+        logs.extend(backfill::read_logs(&archive)?);
+    }
+    eprintln!("Read {} logs from the archive", logs.len());
+
+    let http_client =
+        HttpClient::new(&HttpClientConfig::from(args.http_client_config))?;
+    let provider = http_client.provider(args.bh_http_endpoint);
+    let (state, block) =
+        backfill::backfill(&provider, &args.initial_state, logs, args.to_block)
+            .await?;
+
+    let path = seed_checkpoint(
+        &args.state_server_checkpoint_dir,
+        &args.initial_state,
+        args.state_server_checkpoint_history,
+        &block,
+        &state,
+        signer.as_ref(),
+    )
+    .await
+    .map_err(|e| format!("failed to write the checkpoint: {:?}", e))?;
+    let inputs: usize = state
+        .dapp_input_boxes
+        .values()
+        .map(|input_box| input_box.inputs.len())
+        .sum();
+    eprintln!(
+        "Checkpointed {} inputs at block {} ({:?}) in {}",
+        inputs,
+        block.number,
+        block.hash,
+        path.display()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Cli::parse().command {
+        Command::Inspect(args) => inspect(args).await,
+        Command::Backfill(args) => backfill(args).await,
+    }
+}
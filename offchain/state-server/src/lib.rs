@@ -1,83 +1,279 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use backoff::{future::retry_notify, ExponentialBackoffBuilder};
 use eth_state_fold::{Foldable, StateFoldEnvironment};
-use eth_state_fold_types::ethers::providers::{
-    Http, HttpRateLimitRetryPolicy, Provider, RetryClient,
-};
+use eth_state_fold_types::BlockStreamItem;
 use eth_state_server_lib::{
     config,
     grpc_server::StateServer,
     utils::{start_server, wait_for_signal},
 };
+use grpc_interfaces::cartesi_state_diff::state_diff_server::StateDiffServer;
+use http_provider::{
+    FailoverProvider, HttpClient, HttpClientConfig, LogRangeSplitter,
+};
+use http_server::Health;
+use redacted::RedactedUrl;
 use snafu::ResultExt;
-use std::sync::{Arc, Mutex};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+use tonic::transport::Server;
 use types::UserData;
 use url::Url;
 
 use crate::error::{
-    BlockArchiveSnafu, ParserSnafu, StateServerError, TonicSnafu,
+    BlockArchiveSnafu, HttpClientSnafu, ParserSnafu, StateServerError,
+    TonicSnafu,
 };
 
+pub mod backfill;
+pub mod diff;
 mod error;
+pub mod factory;
+pub mod query;
+mod state_diff;
+
+use factory::{FactoryDelegate, PipelinesConfig};
+use state_diff::StateDiffService;
 
-const MAX_RETRIES: u32 = 10;
-const INITIAL_BACKOFF: u64 = 1000;
+/// Subsystem reported at `/healthz` and `/readyz`, which goes stale when no
+/// block arrives within the block timeout
+const CHAIN_SUBSCRIPTION: &str = "chain_subscription";
 
+/// Backoff between the attempts to subscribe again to the chain once the
+/// subscription fails or ends
+const RESUBSCRIBE_INITIAL_INTERVAL: Duration = Duration::from_secs(1);
+const RESUBSCRIBE_MAX_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Adds the subsystems of the state-server, which keep it from being ready
+/// until they are up
+pub fn register_health(health: &Health, config: &config::StateServerConfig) {
+    health
+        .register(CHAIN_SUBSCRIPTION, Some(config.block_history.block_timeout));
+}
+
+/// Serves the states of `F` over the state-fold gRPC API and, if given
+/// `diff_address`, their changes over the state diff one. If given
+/// `pipelines`, the states of the DApps deployed by the factory are kept
+/// folded as well.
 #[tracing::instrument(level = "trace")]
 pub async fn run_server<F: Foldable<UserData = Mutex<UserData>> + 'static>(
     config: config::StateServerConfig,
+    http_client_config: &HttpClientConfig,
+    logs_max_parallelism: usize,
+    fallback_http_endpoints: Vec<RedactedUrl>,
+    user_data: UserData,
+    diff_address: Option<SocketAddr>,
+    pipelines: Option<PipelinesConfig>,
+    health: Health,
 ) -> Result<(), StateServerError>
 where
     <F as Foldable>::InitialState: serde::de::DeserializeOwned,
     F: serde::ser::Serialize,
 {
-    let provider = create_provider(&config)?;
+    // Logs collisions between the tracked event signatures up front
+    types::foldables::tracked_events();
+
+    let provider = create_provider(
+        &config,
+        http_client_config,
+        logs_max_parallelism,
+        fallback_http_endpoints,
+    )?;
     let block_subscriber =
         create_block_subscriber(&config, Arc::clone(&provider)).await?;
     let env = create_env(
         &config,
         Arc::clone(&provider),
         Arc::clone(&block_subscriber.block_archive),
+        user_data,
     )?;
 
-    let server = StateServer::<_, _, F>::new(block_subscriber, env);
+    let server = StateServer::<_, _, F>::new(
+        Arc::clone(&block_subscriber),
+        Arc::clone(&env),
+    );
+    let diff_service = StateDiffService::<F>::new(
+        Arc::clone(&block_subscriber),
+        Arc::clone(&env),
+    );
 
+    let (signal_tx, signal_rx) = oneshot::channel();
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
-    tokio::spawn(async { wait_for_signal(shutdown_tx).await });
+    tokio::spawn(async { wait_for_signal(signal_tx).await });
+    let shutdown_env = Arc::clone(&env);
+    tokio::spawn(async move {
+        // The server only stops once the folds in flight finish
+        if signal_rx.await.is_ok() {
+            cancel_in_flight(&shutdown_env, "shutdown");
+            let _ = shutdown_tx.send(());
+        }
+    });
+    if let Some(pipelines) = pipelines {
+        tokio::spawn(FactoryDelegate::<F>::new(pipelines).run(
+            Arc::clone(&block_subscriber.block_archive),
+            Arc::clone(&env),
+        ));
+    }
+    tokio::spawn(cancel_on_reorg(block_subscriber, env, health));
 
-    start_server(&config, server, shutdown_rx)
-        .await
-        .context(TonicSnafu)
+    // The state diff server stops along with the main one
+    tokio::select! {
+        ret = start_server(&config, server, shutdown_rx) => {
+            ret.context(TonicSnafu)
+        }
+        ret = serve_diffs(diff_address, diff_service) => {
+            ret.context(TonicSnafu)
+        }
+    }
+}
+
+async fn serve_diffs<F>(
+    address: Option<SocketAddr>,
+    service: StateDiffService<F>,
+) -> Result<(), tonic::transport::Error>
+where
+    F: Foldable<UserData = Mutex<UserData>> + serde::ser::Serialize + 'static,
+    <F as Foldable>::InitialState: serde::de::DeserializeOwned,
+{
+    match address {
+        Some(address) => {
+            tracing::info!("serving state diffs on {}", address);
+            Server::builder()
+                .add_service(StateDiffServer::new(service))
+                .serve(address)
+                .await
+        }
+        None => std::future::pending().await,
+    }
 }
 
-type ServerProvider = Provider<RetryClient<Http>>;
+type ServerProvider = LogRangeSplitter<FailoverProvider>;
 
-fn create_provider(
-    config: &config::StateServerConfig,
-) -> Result<Arc<ServerProvider>, StateServerError> {
-    let http = Http::new(
-        Url::parse(&config.block_history.http_endpoint).context(ParserSnafu)?,
-    );
+fn cancel_in_flight(
+    env: &StateFoldEnvironment<ServerProvider, Mutex<UserData>>,
+    reason: &str,
+) {
+    tracing::info!("cancelling the syncs and folds in flight on {}", reason);
+    env.user_data()
+        .lock()
+        .expect("Mutex should never be poisoned")
+        .cancel_in_flight();
+}
 
-    let retry_client = RetryClient::new(
-        http,
-        Box::new(HttpRateLimitRetryPolicy),
-        MAX_RETRIES,
-        INITIAL_BACKOFF,
+fn cancel_reorged(
+    env: &StateFoldEnvironment<ServerProvider, Mutex<UserData>>,
+    block_number: u64,
+) {
+    let cancelled = env
+        .user_data()
+        .lock()
+        .expect("Mutex should never be poisoned")
+        .cancel_in_flight_from(block_number);
+    tracing::info!(
+        block_number,
+        cancelled,
+        "cancelled the syncs and folds of the blocks reorged out"
     );
+}
+
+/// Cancels the syncs and folds in flight up to the blocks reorged out when
+/// the chain reorgs, letting the ones up to earlier blocks finish, and
+/// reports whether the subscription to the chain is alive. The subscription
+/// is renewed, backing off exponentially, whenever it fails or ends.
+async fn cancel_on_reorg(
+    block_subscriber: Arc<eth_block_history::BlockSubscriber<ServerProvider>>,
+    env: Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
+    health: Health,
+) {
+    let backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(RESUBSCRIBE_INITIAL_INTERVAL)
+        .with_max_interval(RESUBSCRIBE_MAX_INTERVAL)
+        .with_max_elapsed_time(None)
+        .build();
+    loop {
+        let blocks = retry_notify(
+            backoff.clone(),
+            || async {
+                block_subscriber
+                    .subscribe_new_blocks_at_depth(0)
+                    .await
+                    .map_err(backoff::Error::transient)
+            },
+            |e, wait: Duration| {
+                tracing::warn!(
+                    "failed to subscribe to reorgs; retrying in {:?}: {}",
+                    wait,
+                    e
+                );
+                health.down(CHAIN_SUBSCRIPTION, e);
+            },
+        )
+        .await
+        .expect("retries without a maximum elapsed time never fail");
+        tokio::pin!(blocks);
+        let reason = loop {
+            match blocks.next().await {
+                Some(Ok(BlockStreamItem::Reorg(blocks))) => {
+                    health.up(CHAIN_SUBSCRIPTION);
+                    if let Some(reorged) =
+                        blocks.iter().map(|block| block.number).min()
+                    {
+                        cancel_reorged(&env, reorged.as_u64())
+                    }
+                }
+                Some(Ok(BlockStreamItem::NewBlock(_))) => {
+                    health.up(CHAIN_SUBSCRIPTION)
+                }
+                Some(Err(e)) => break e.to_string(),
+                None => break "the block subscription ended".to_owned(),
+            }
+        };
+        tracing::warn!(
+            "reorg subscription stopped; subscribing again: {}",
+            reason
+        );
+        health.down(CHAIN_SUBSCRIPTION, reason);
+        tokio::time::sleep(RESUBSCRIBE_INITIAL_INTERVAL).await;
+    }
+}
 
-    let provider = Provider::new(retry_client);
+fn create_provider(
+    config: &config::StateServerConfig,
+    http_client_config: &HttpClientConfig,
+    logs_max_parallelism: usize,
+    fallback_http_endpoints: Vec<RedactedUrl>,
+) -> Result<Arc<ServerProvider>, StateServerError> {
+    let http_client =
+        HttpClient::new(http_client_config).context(HttpClientSnafu)?;
+    let endpoint =
+        Url::parse(&config.block_history.http_endpoint).context(ParserSnafu)?;
+    let endpoints = std::iter::once(endpoint)
+        .chain(
+            fallback_http_endpoints
+                .into_iter()
+                .map(RedactedUrl::into_inner),
+        )
+        .collect();
 
-    Ok(Arc::new(provider))
+    Ok(Arc::new(LogRangeSplitter::new(
+        http_client.failover_provider(endpoints),
+        logs_max_parallelism,
+    )))
 }
 
 fn create_env(
     config: &config::StateServerConfig,
     provider: Arc<ServerProvider>,
     block_archive: Arc<eth_block_history::BlockArchive<ServerProvider>>,
+    user_data: UserData,
 ) -> Result<
     Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
     StateServerError,
@@ -90,7 +286,7 @@ fn create_env(
         config.state_fold.query_limit_error_codes.clone(),
         config.state_fold.concurrent_events_fetch,
         10000,
-        Mutex::new(UserData::default()),
+        Mutex::new(user_data),
     );
 
     Ok(Arc::new(env))
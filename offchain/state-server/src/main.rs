@@ -1,18 +1,176 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 mod config;
-use config::Config;
-use types::foldables::InputBox;
+use config::{Config, FoldableKind};
+use eth_state_fold_types::ethers::signers::{LocalWallet, Signer};
+use http_server::{Health, Registry};
+use types::foldables::{Audited, Checkpointed, DAppFactory, History, InputBox};
+use types::metrics::FoldMetrics;
+use types::UserData;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets::resolve_env().await?;
     let config: Config = Config::initialize_from_args()?;
 
     log::configure(&config.log_config);
 
     log::log_service_start(&config, "State Server");
 
-    state_server::run_server::<InputBox>(config.state_server_config)
-        .await
-        .map_err(|e| e.into())
+    let metrics = FoldMetrics::default();
+    let mut user_data = UserData::default()
+        .with_gap_check_interval(config.gap_check_interval)
+        .with_metrics(metrics.clone());
+    if let Some(dir) = config.checkpoint_dir.clone() {
+        user_data = user_data.with_checkpoints(
+            dir,
+            config.checkpoint_interval,
+            config.checkpoint_history,
+        );
+    }
+    if let Some(key) = &config.checkpoint_signing_key {
+        let wallet: LocalWallet = key.inner().trim().parse()?;
+        tracing::info!(signer = ?wallet.address(), "signing the checkpoints");
+        user_data = user_data.with_checkpoint_signer(wallet);
+    }
+    let audited = config.gap_check_interval > 0;
+    let checkpointed = config.checkpoint_dir.is_some();
+    let metrics_address = config.metrics_address;
+    let admin_address = config.admin_address;
+    let health = Health::default();
+    state_server::register_health(&health, &config.state_server_config);
+
+    if config.pipelines.is_some() && config.foldable != FoldableKind::InputBox {
+        return Err("the factory pipelines need the input-box foldable".into());
+    }
+
+    let server = async {
+        match config.foldable {
+            FoldableKind::InputBox if audited && checkpointed => {
+                state_server::run_server::<Checkpointed<Audited<InputBox>>>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::InputBox if audited => {
+                state_server::run_server::<Audited<InputBox>>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::InputBox if checkpointed => {
+                state_server::run_server::<Checkpointed<InputBox>>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::InputBox => {
+                state_server::run_server::<InputBox>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::History if checkpointed => {
+                state_server::run_server::<Checkpointed<History>>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::History => {
+                state_server::run_server::<History>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::DAppFactory if checkpointed => {
+                state_server::run_server::<Checkpointed<DAppFactory>>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+            FoldableKind::DAppFactory => {
+                state_server::run_server::<DAppFactory>(
+                    config.state_server_config,
+                    &config.http_client_config,
+                    config.logs_max_parallelism,
+                    config.fallback_http_endpoints,
+                    user_data,
+                    config.diff_address,
+                    config.pipelines,
+                    health.clone(),
+                )
+                .await
+            }
+        }
+    };
+    let metrics_server = async {
+        match metrics_address {
+            Some(address) => {
+                let mut registry = Registry::default();
+                metrics.register(&mut registry);
+                http_server::start_at(
+                    address,
+                    admin_address,
+                    registry,
+                    health.clone(),
+                )
+                .await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        ret = server => ret.map_err(|e| e.into()),
+        ret = metrics_server => ret.map_err(|e| e.into()),
+        rotated = secrets.watch() => Err(rotated.into()),
+    }
 }
@@ -0,0 +1,435 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Path queries over the JSON of a state, in a small subset of the jq
+//! language: `.`, `.field`, `."quoted field"`, `[index]`, `["key"]`,
+//! `[start:end]`, `[]`, the `keys`, `length` and `type` builtins, and `|`.
+
+use serde_json::Value;
+use snafu::{OptionExt, Snafu};
+use std::{iter::Peekable, str::CharIndices};
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum QueryError {
+    #[snafu(display("invalid filter at position {}: {}", position, reason))]
+    Parse { position: usize, reason: String },
+
+    #[snafu(display("cannot {} {}", action, kind))]
+    Eval { action: String, kind: &'static str },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Field(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Iterate,
+    Keys,
+    Length,
+    Type,
+}
+
+/// Parsed filter, applied to a value with `Filter::apply`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    steps: Vec<Step>,
+}
+
+impl std::str::FromStr for Filter {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).parse()
+    }
+}
+
+impl Filter {
+    /// Values selected by the filter; `[]` and `keys | .[]` may select many
+    pub fn apply(&self, value: &Value) -> Result<Vec<Value>, QueryError> {
+        let mut values = vec![value.clone()];
+        for step in &self.steps {
+            let mut next = Vec::with_capacity(values.len());
+            for value in &values {
+                apply_step(step, value, &mut next)?;
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn eval_error(action: String, value: &Value) -> QueryError {
+    QueryError::Eval {
+        action,
+        kind: kind(value),
+    }
+}
+
+/// Position of `index` in a sequence of `len` items, counting from the end
+/// when negative
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn slice_bounds(
+    start: Option<i64>,
+    end: Option<i64>,
+    len: usize,
+) -> (usize, usize) {
+    let clamp = |bound: i64| {
+        let bound = if bound < 0 { len as i64 + bound } else { bound };
+        bound.clamp(0, len as i64) as usize
+    };
+    let start = start.map(clamp).unwrap_or(0);
+    let end = end.map(clamp).unwrap_or(len);
+    (start, end.max(start))
+}
+
+fn apply_step(
+    step: &Step,
+    value: &Value,
+    out: &mut Vec<Value>,
+) -> Result<(), QueryError> {
+    match (step, value) {
+        (Step::Field(_) | Step::Index(_) | Step::Slice(..), Value::Null) => {
+            out.push(Value::Null)
+        }
+        (Step::Field(field), Value::Object(map)) => {
+            out.push(map.get(field).cloned().unwrap_or(Value::Null))
+        }
+        (Step::Field(field), _) => {
+            return Err(eval_error(
+                format!("index field `{}` of", field),
+                value,
+            ))
+        }
+        (Step::Index(index), Value::Array(items)) => out.push(
+            resolve_index(*index, items.len())
+                .map(|i| items[i].clone())
+                .unwrap_or(Value::Null),
+        ),
+        (Step::Index(index), _) => {
+            return Err(eval_error(format!("index {} of", index), value))
+        }
+        (Step::Slice(start, end), Value::Array(items)) => {
+            let (start, end) = slice_bounds(*start, *end, items.len());
+            out.push(Value::Array(items[start..end].to_vec()))
+        }
+        (Step::Slice(start, end), Value::String(s)) => {
+            let chars: Vec<char> = s.chars().collect();
+            let (start, end) = slice_bounds(*start, *end, chars.len());
+            out.push(Value::String(chars[start..end].iter().collect()))
+        }
+        (Step::Slice(..), _) => {
+            return Err(eval_error("slice".to_owned(), value))
+        }
+        (Step::Iterate, Value::Array(items)) => {
+            out.extend(items.iter().cloned())
+        }
+        (Step::Iterate, Value::Object(map)) => {
+            out.extend(map.values().cloned())
+        }
+        (Step::Iterate, _) => {
+            return Err(eval_error("iterate over".to_owned(), value))
+        }
+        (Step::Keys, Value::Object(map)) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push(keys.into_iter().cloned().map(Value::String).collect())
+        }
+        (Step::Keys, Value::Array(items)) => {
+            out.push((0..items.len()).map(Value::from).collect())
+        }
+        (Step::Keys, _) => {
+            return Err(eval_error("get the keys of".to_owned(), value))
+        }
+        (Step::Length, _) => out.push(match value {
+            Value::Null => Value::from(0),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(map) => Value::from(map.len()),
+            Value::String(s) => Value::from(s.chars().count()),
+            _ => return Err(eval_error("get the length of".to_owned(), value)),
+        }),
+        (Step::Type, _) => out.push(Value::from(kind(value))),
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+        }
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or(self.input.len())
+    }
+
+    fn error<T>(&mut self, reason: &str) -> Result<T, QueryError> {
+        ParseSnafu {
+            position: self.position(),
+            reason,
+        }
+        .fail()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), QueryError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", expected))
+        }
+    }
+
+    fn parse(mut self) -> Result<Filter, QueryError> {
+        let mut steps = Vec::new();
+        loop {
+            self.skip_whitespace();
+            self.parse_term(&mut steps)?;
+            self.skip_whitespace();
+            if self.chars.peek().is_none() {
+                return Ok(Filter { steps });
+            }
+            self.expect('|')?;
+        }
+    }
+
+    /// A builtin or a path starting with `.`
+    fn parse_term(&mut self, steps: &mut Vec<Step>) -> Result<(), QueryError> {
+        if self.chars.peek().map_or(false, |(_, c)| c.is_alphabetic()) {
+            let position = self.position();
+            let name = self.identifier();
+            let step = match name.as_str() {
+                "keys" => Step::Keys,
+                "length" => Step::Length,
+                "type" => Step::Type,
+                _ => {
+                    return ParseSnafu {
+                        position,
+                        reason: format!("unknown builtin `{}`", name),
+                    }
+                    .fail()
+                }
+            };
+            steps.push(step);
+            return Ok(());
+        }
+
+        self.expect('.')?;
+        match self.chars.peek() {
+            Some((_, '"')) => steps.push(Step::Field(self.string()?)),
+            Some((_, c)) if is_identifier_start(*c) => {
+                steps.push(Step::Field(self.identifier()))
+            }
+            _ => {}
+        }
+        loop {
+            if self.eat('[') {
+                steps.push(self.bracket()?);
+            } else if self.eat('.') {
+                match self.chars.peek() {
+                    Some((_, '"')) => steps.push(Step::Field(self.string()?)),
+                    Some((_, c)) if is_identifier_start(*c) => {
+                        steps.push(Step::Field(self.identifier()))
+                    }
+                    Some((_, '[')) => {}
+                    _ => return self.error("expected a field name"),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut name = String::new();
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| is_identifier(*c))
+        {
+            name.push(c);
+        }
+        name
+    }
+
+    fn string(&mut self) -> Result<String, QueryError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => s.push(c),
+                    _ => return self.error("invalid escape"),
+                },
+                Some((_, c)) => s.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Option<i64>, QueryError> {
+        self.skip_whitespace();
+        let start = self.position();
+        let mut digits = String::new();
+        if self.eat('-') {
+            digits.push('-');
+        }
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| c.is_ascii_digit())
+        {
+            digits.push(c);
+        }
+        self.skip_whitespace();
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        let parsed = digits.parse().ok();
+        parsed.map(Some).context(ParseSnafu {
+            position: start,
+            reason: format!("invalid index `{}`", digits),
+        })
+    }
+
+    /// Contents of `[...]`, after the opening bracket
+    fn bracket(&mut self) -> Result<Step, QueryError> {
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Step::Iterate);
+        }
+        if let Some((_, '"')) = self.chars.peek() {
+            let field = self.string()?;
+            self.skip_whitespace();
+            self.expect(']')?;
+            return Ok(Step::Field(field));
+        }
+        let start = self.integer()?;
+        let step = if self.eat(':') {
+            Step::Slice(start, self.integer()?)
+        } else {
+            let Some(index) = start else {
+                return self.error("expected an index, a key or a slice");
+            };
+            Step::Index(index)
+        };
+        self.expect(']')?;
+        Ok(step)
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(filter: &str, value: &Value) -> Vec<Value> {
+        filter.parse::<Filter>().unwrap().apply(value).unwrap()
+    }
+
+    fn state() -> Value {
+        json!({
+            "vouchers": [[1, 2], [3, {"a": 1, "b": 2}]],
+            "dapp_input_boxes": {"0xab": {"inputs": [10, 11, 12]}},
+            "name with spaces": "x",
+        })
+    }
+
+    #[test]
+    fn it_selects_paths() {
+        let state = state();
+        assert_eq!(query(".", &state), vec![state.clone()]);
+        assert_eq!(query(".vouchers[1][0]", &state), vec![json!(3)]);
+        assert_eq!(query(".vouchers[-1][1].b", &state), vec![json!(2)]);
+        assert_eq!(query(".vouchers[7]", &state), vec![Value::Null]);
+        assert_eq!(query(".missing.field[3]", &state), vec![Value::Null]);
+        assert_eq!(
+            query(r#".dapp_input_boxes["0xab"].inputs[1:]"#, &state),
+            vec![json!([11, 12])]
+        );
+        assert_eq!(query(r#"."name with spaces""#, &state), vec![json!("x")]);
+    }
+
+    #[test]
+    fn it_pipes_builtins() {
+        let state = state();
+        assert_eq!(
+            query(".vouchers[1][1] | keys", &state),
+            vec![json!(["a", "b"])]
+        );
+        assert_eq!(query(".vouchers | length", &state), vec![json!(2)]);
+        assert_eq!(
+            query(".vouchers[] | length", &state),
+            vec![json!(2), json!(2)]
+        );
+        assert_eq!(
+            query(".dapp_input_boxes[] | .inputs | type", &state),
+            vec![json!("array")]
+        );
+        assert_eq!(
+            query("keys | .[0]", &state),
+            vec![json!("dapp_input_boxes")]
+        );
+    }
+
+    #[test]
+    fn it_reports_invalid_filters() {
+        assert!(matches!(
+            "vouchers".parse::<Filter>(),
+            Err(QueryError::Parse { position: 0, .. })
+        ));
+        assert!(matches!(
+            ".vouchers[1".parse::<Filter>(),
+            Err(QueryError::Parse { position: 11, .. })
+        ));
+        assert!(".a | ".parse::<Filter>().is_err());
+        assert!(".a..b".parse::<Filter>().is_err());
+        assert!(" ".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn it_reports_type_errors() {
+        let filter: Filter = ".vouchers.a".parse().unwrap();
+        assert_eq!(
+            filter.apply(&state()),
+            Err(QueryError::Eval {
+                action: "index field `a` of".to_owned(),
+                kind: "array",
+            })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_block_history::BlockSubscriber;
+use eth_state_fold::{Foldable, StateFoldEnvironment};
+use eth_state_fold_types::{BlockStreamItem, QueryBlock};
+use grpc_interfaces::cartesi_state_diff::{
+    state_diff_server::StateDiff, ChangedEntry as GrpcChangedEntry,
+    StateDiffUpdate, SubscribeStateDiffRequest,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{Request, Response, Status};
+use types::UserData;
+
+use crate::diff::{changed_entries, ChangedEntry};
+use crate::ServerProvider;
+
+/// Updates buffered for a subscriber before the folds wait for it
+const UPDATE_BUFFER: usize = 16;
+
+/// Streams the changes of the states of `F`, sharing the folds and the
+/// block subscription of the state-server
+pub struct StateDiffService<F> {
+    block_subscriber: Arc<BlockSubscriber<ServerProvider>>,
+    env: Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
+    foldable: PhantomData<fn() -> F>,
+}
+
+impl<F> StateDiffService<F> {
+    pub fn new(
+        block_subscriber: Arc<BlockSubscriber<ServerProvider>>,
+        env: Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
+    ) -> Self {
+        Self {
+            block_subscriber,
+            env,
+            foldable: PhantomData,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<F> StateDiff for StateDiffService<F>
+where
+    F: Foldable<UserData = Mutex<UserData>> + Serialize + 'static,
+    F::InitialState: DeserializeOwned,
+{
+    type SubscribeStateDiffStream =
+        ReceiverStream<Result<StateDiffUpdate, Status>>;
+
+    async fn subscribe_state_diff(
+        &self,
+        request: Request<SubscribeStateDiffRequest>,
+    ) -> Result<Response<Self::SubscribeStateDiffStream>, Status> {
+        let request = request.into_inner();
+        let initial_state: F::InitialState =
+            serde_json::from_str(&request.initial_state_json).map_err(|e| {
+                Status::invalid_argument(format!(
+                    "invalid initial state: {}",
+                    e
+                ))
+            })?;
+        tracing::info!(
+            confirmations = request.confirmations,
+            "received subscribe_state_diff"
+        );
+
+        let (tx, rx) = mpsc::channel(UPDATE_BUFFER);
+        let block_subscriber = Arc::clone(&self.block_subscriber);
+        let env = Arc::clone(&self.env);
+        tokio::spawn(async move {
+            let streamed = stream_diffs::<F>(
+                block_subscriber,
+                env,
+                initial_state,
+                request.confirmations as usize,
+                &tx,
+            )
+            .await;
+            if let Err(status) = streamed {
+                tracing::warn!("stopped streaming state diffs: {}", status);
+                let _ = tx.send(Err(status)).await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Sends an update for each block that changes the state, until the
+/// subscriber goes away
+async fn stream_diffs<F>(
+    block_subscriber: Arc<BlockSubscriber<ServerProvider>>,
+    env: Arc<StateFoldEnvironment<ServerProvider, Mutex<UserData>>>,
+    initial_state: F::InitialState,
+    depth: usize,
+    tx: &mpsc::Sender<Result<StateDiffUpdate, Status>>,
+) -> Result<(), Status>
+where
+    F: Foldable<UserData = Mutex<UserData>> + Serialize + 'static,
+{
+    let blocks = block_subscriber
+        .subscribe_new_blocks_at_depth(depth)
+        .await
+        .map_err(|e| {
+            Status::unavailable(format!("failed to subscribe to blocks: {}", e))
+        })?;
+    tokio::pin!(blocks);
+
+    let mut previous: Option<Value> = None;
+    while let Some(item) = blocks.next().await {
+        let block_hash = match item {
+            Ok(BlockStreamItem::NewBlock(block)) => block.hash,
+            // The new head is diffed against the last state sent, which
+            // undoes the changes of the blocks reorged out
+            Ok(BlockStreamItem::Reorg(blocks)) => match blocks.last() {
+                Some(block) => block.hash,
+                None => continue,
+            },
+            Err(e) => {
+                return Err(Status::unavailable(format!(
+                    "block subscription failed: {}",
+                    e
+                )))
+            }
+        };
+        let state = env
+            .get_state_for_block::<F, _>(
+                &initial_state,
+                QueryBlock::BlockHash(block_hash),
+            )
+            .await
+            .map_err(|e| {
+                Status::internal(format!("failed to fold the state: {}", e))
+            })?;
+        let current = serde_json::to_value(&*state.state)
+            .expect("states should always serialize");
+
+        let update = StateDiffUpdate {
+            block_number: state.block.number.as_u64(),
+            block_hash: state.block.hash.as_bytes().to_vec(),
+            state_json: None,
+            changes: vec![],
+        };
+        let Some(update) = diff_update(&mut previous, current, update) else {
+            continue;
+        };
+        if tx.send(Ok(update)).await.is_err() {
+            tracing::info!("state diff subscriber went away");
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Fills the update of the block with the whole state, if it's the first
+/// one, or with the changes from the previous state, keeping the current one
+/// as the previous. Returns `None` if the state didn't change.
+fn diff_update(
+    previous: &mut Option<Value>,
+    current: Value,
+    mut update: StateDiffUpdate,
+) -> Option<StateDiffUpdate> {
+    match previous {
+        Some(previous) => {
+            update.changes = changed_entries(previous, &current)
+                .into_iter()
+                .map(GrpcChangedEntry::from)
+                .collect();
+            if update.changes.is_empty() {
+                return None;
+            }
+        }
+        None => update.state_json = Some(current.to_string()),
+    }
+    *previous = Some(current);
+    Some(update)
+}
+
+impl From<ChangedEntry> for GrpcChangedEntry {
+    fn from(entry: ChangedEntry) -> Self {
+        Self {
+            path: entry.path,
+            value_json: entry.value.map(|value| value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(block_number: u64) -> StateDiffUpdate {
+        StateDiffUpdate {
+            block_number,
+            block_hash: vec![block_number as u8; 32],
+            state_json: None,
+            changes: vec![],
+        }
+    }
+
+    fn entry(path: &str, value_json: Option<&str>) -> GrpcChangedEntry {
+        GrpcChangedEntry {
+            path: path.to_owned(),
+            value_json: value_json.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn it_sends_the_whole_state_first() {
+        let mut previous = None;
+        let state = json!({"inputs": [], "address": "0xaa"});
+
+        let first = diff_update(&mut previous, state.clone(), update(1))
+            .expect("the first state should be sent");
+        assert_eq!(first.block_number, 1);
+        assert_eq!(first.state_json, Some(state.to_string()));
+        assert!(first.changes.is_empty());
+        assert_eq!(previous, Some(state));
+    }
+
+    #[test]
+    fn it_sends_the_added_removed_and_changed_fields() {
+        let mut previous = Some(json!({
+            "inputs": [{"index": 0}],
+            "address": "0xaa",
+            "removed": true,
+        }));
+        let current = json!({
+            "inputs": [{"index": 0}, {"index": 1}],
+            "address": "0xbb",
+            "added": 1,
+        });
+
+        let changes = diff_update(&mut previous, current.clone(), update(2))
+            .expect("the changes should be sent");
+        assert_eq!(changes.block_number, 2);
+        assert_eq!(changes.state_json, None);
+        assert_eq!(
+            changes.changes,
+            vec![
+                entry("/added", Some("1")),
+                entry("/address", Some("\"0xbb\"")),
+                entry("/inputs/1", Some("{\"index\":1}")),
+                entry("/removed", None),
+            ]
+        );
+        assert_eq!(previous, Some(current));
+    }
+
+    #[test]
+    fn it_skips_the_blocks_that_dont_change_the_state() {
+        let state = json!({"inputs": [{"index": 0}]});
+        let mut previous = Some(state.clone());
+        assert_eq!(diff_update(&mut previous, state.clone(), update(3)), None);
+        assert_eq!(previous, Some(state));
+    }
+}
@@ -8,6 +8,7 @@ use rollups_events::{
     RollupsInputsStream, RollupsOutput, RollupsOutputsStream, Url,
     ADDRESS_SIZE, INITIAL_ID,
 };
+use std::time::Duration;
 use testcontainers::{
     clients::Cli, core::WaitFor, images::generic::GenericImage, Container,
 };
@@ -15,7 +16,7 @@ use tokio::sync::Mutex;
 
 const CHAIN_ID: u64 = 0;
 const DAPP_ADDRESS: Address = Address::new([0xfa; ADDRESS_SIZE]);
-const CONSUME_TIMEOUT: usize = 10_000; // ms
+const CONSUME_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct BrokerFixture<'d> {
     _node: Container<'d, GenericImage>,
@@ -164,6 +165,26 @@ impl BrokerFixture<'_> {
             .expect("failed to produce event")
     }
 
+    /// Obtain all produced input events
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn consume_all_inputs(&self) -> Vec<RollupsInput> {
+        tracing::trace!("consuming all rollups-inputs events");
+        let mut inputs = vec![];
+        let mut last_id = INITIAL_ID.to_owned();
+        while let Some(event) = self
+            .client
+            .lock()
+            .await
+            .consume_nonblocking(&self.inputs_stream, &last_id)
+            .await
+            .expect("failed to consume input")
+        {
+            inputs.push(event.payload);
+            last_id = event.id;
+        }
+        inputs
+    }
+
     /// Produce the claim given the hash
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn produce_rollups_claim(&self, rollups_claim: RollupsClaim) {
@@ -234,6 +255,26 @@ impl BrokerFixture<'_> {
         claims
     }
 
+    /// Obtain all produced outputs
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn consume_all_outputs(&self) -> Vec<RollupsOutput> {
+        tracing::trace!("consuming all rollups-outputs events");
+        let mut outputs = vec![];
+        let mut last_id = INITIAL_ID.to_owned();
+        while let Some(event) = self
+            .client
+            .lock()
+            .await
+            .consume_nonblocking(&self.outputs_stream, &last_id)
+            .await
+            .expect("failed to consume output")
+        {
+            outputs.push(event.payload);
+            last_id = event.id;
+        }
+        outputs
+    }
+
     /// Produce an output event
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn produce_output(&self, output: RollupsOutput) {
@@ -6,7 +6,7 @@ use anyhow::{anyhow, Context};
 use backoff::{future::retry, ExponentialBackoff, ExponentialBackoffBuilder};
 use grpc_interfaces::cartesi_server_manager::{
     processed_input::ProcessedInputOneOf,
-    server_manager_client::ServerManagerClient, EpochState,
+    server_manager_client::ServerManagerClient, EndSessionRequest, EpochState,
     GetEpochStatusRequest, GetEpochStatusResponse, GetSessionStatusRequest,
 };
 use rollups_events::Payload;
@@ -24,6 +24,33 @@ const DOCKER_TAG: &str = "cartesi/test-server-manager-nonroot";
 const SESSION_ID: &str = "default-session-id";
 const RETRY_MAX_ELAPSED_TIME: u64 = 120;
 
+/// Returns the user and group that own the files written by the container,
+/// so the snapshot volume stays accessible from the host.
+#[cfg(unix)]
+fn current_user() -> (String, String, String, String) {
+    let user = users::get_current_username().unwrap();
+    let group = users::get_current_groupname().unwrap();
+    (
+        user.to_str().unwrap().to_owned(),
+        users::get_current_uid().to_string(),
+        group.to_str().unwrap().to_owned(),
+        users::get_current_gid().to_string(),
+    )
+}
+
+/// Docker Desktop maps volume ownership on its own outside unix hosts, so any
+/// unprivileged user works.
+#[cfg(not(unix))]
+fn current_user() -> (String, String, String, String) {
+    let name = String::from("cartesi");
+    (
+        name.clone(),
+        String::from("1000"),
+        name,
+        String::from("1000"),
+    )
+}
+
 macro_rules! grpc_call {
     ($self: ident, $method: ident, $request: expr) => {
         $self
@@ -54,14 +81,11 @@ impl ServerManagerFixture<'_> {
         tracing::info!("setting up server-manager fixture");
 
         tracing::trace!("generating {} docker image", DOCKER_TAG);
-        let user = users::get_current_username().unwrap();
-        let uid = users::get_current_uid().to_string();
-        let group = users::get_current_groupname().unwrap();
-        let gid = users::get_current_gid().to_string();
+        let (user, uid, group, gid) = current_user();
         let build_args = vec![
-            ("user", user.to_str().unwrap()),
+            ("user", user.as_str()),
             ("uid", &uid),
-            ("group", group.to_str().unwrap()),
+            ("group", group.as_str()),
             ("gid", &gid),
         ];
         docker_cli::build(DOCKERFILE, DOCKER_TAG, &build_args);
@@ -121,6 +145,16 @@ impl ServerManagerFixture<'_> {
         .expect("failed to wait for session");
     }
 
+    /// End the session, which the server-manager loses as when it restarts
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn end_session(&self) {
+        tracing::trace!("ending session");
+        let request = EndSessionRequest {
+            session_id: self.session_id.clone(),
+        };
+        grpc_call!(self, end_session, request).expect("failed to end session");
+    }
+
     /// Wait until there is the required amount of processed inputs
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn assert_epoch_status(
@@ -0,0 +1,26 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Stable API of the aggregated state of a DApp, for the crates that embed
+//! the node as a library.
+//!
+//! The foldables are internal and change shape whenever the way they are
+//! folded does; the types here are built from them and follow semver
+//! instead. Within a version module:
+//!
+//! - the structs are `#[non_exhaustive]`, so new fields are not breaking;
+//! - new fields deserialize with a default, so documents written before
+//!   them still read;
+//! - a field that is replaced is kept, marked `#[deprecated]` and still
+//!   filled, until the next version module.
+//!
+//! The JSON of each version is pinned by the compatibility tests in
+//! `tests/api.rs`, and its protobuf, behind the `proto` feature, by
+//! `rollups-state.proto` in `grpc-interfaces`.
+
+#[cfg(feature = "proto")]
+mod proto;
+pub mod v1;
+
+/// Latest version of the API
+pub use v1::RollupsState;
@@ -0,0 +1,44 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Conversions of the API to its protobuf messages
+
+use grpc_interfaces::cartesi_rollups_state::v1 as proto;
+
+use super::v1;
+
+impl From<v1::RollupsState> for proto::RollupsState {
+    fn from(state: v1::RollupsState) -> Self {
+        Self {
+            dapp_address: state.dapp_address.as_bytes().to_vec(),
+            input_box_address: state.input_box_address.as_bytes().to_vec(),
+            history_address: state.history_address.as_bytes().to_vec(),
+            inputs: state.inputs.into_iter().map(Into::into).collect(),
+            claims: state.claims.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<v1::Input> for proto::Input {
+    fn from(input: v1::Input) -> Self {
+        Self {
+            index: input.index,
+            sender: input.sender.as_bytes().to_vec(),
+            payload: input.payload.to_vec(),
+            block_number: input.block_number,
+            tx_hash: input.tx_hash.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<v1::Claim> for proto::Claim {
+    fn from(claim: v1::Claim) -> Self {
+        Self {
+            epoch_hash: claim.epoch_hash.as_bytes().to_vec(),
+            first_index: claim.first_index,
+            last_index: claim.last_index,
+            block_number: claim.block_number,
+            tx_hash: claim.tx_hash.as_bytes().to_vec(),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold_types::ethers::types::{Address, Bytes, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::foldables::{self, History, InputBox};
+
+/// Inputs of a DApp and the claims of its epochs
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RollupsState {
+    pub dapp_address: Address,
+    pub input_box_address: Address,
+    pub history_address: Address,
+    /// Inputs in the order they were added
+    pub inputs: Vec<Input>,
+    /// Claims in the order they were submitted
+    pub claims: Vec<Claim>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Input {
+    pub index: u64,
+    pub sender: Address,
+    pub payload: Bytes,
+    pub block_number: u64,
+    pub tx_hash: H256,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Claim {
+    pub epoch_hash: H256,
+    pub first_index: u64,
+    pub last_index: u64,
+    pub block_number: u64,
+    pub tx_hash: H256,
+}
+
+impl RollupsState {
+    /// State of the DApp the input box and the history were folded for
+    pub fn new(input_box: &InputBox, history: &History) -> Self {
+        let dapp_address = *input_box.dapp_address;
+        let inputs = input_box
+            .dapp_input_boxes
+            .get(&dapp_address)
+            .map(|input_box| {
+                input_box
+                    .inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, input)| Input::new(index as u64, input))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let claims = history
+            .claims(&dapp_address)
+            .claims
+            .iter()
+            .map(|claim| Claim::new(claim))
+            .collect();
+        Self {
+            dapp_address,
+            input_box_address: *input_box.input_box_address,
+            history_address: *history.history_address,
+            inputs,
+            claims,
+        }
+    }
+}
+
+impl Input {
+    fn new(index: u64, input: &foldables::Input) -> Self {
+        Self {
+            index,
+            sender: *input.sender,
+            payload: input.payload.clone().into(),
+            block_number: input.block_added.number.as_u64(),
+            tx_hash: *input.tx_hash,
+        }
+    }
+}
+
+impl Claim {
+    fn new(claim: &foldables::Claim) -> Self {
+        Self {
+            epoch_hash: claim.epoch_hash,
+            first_index: saturating_u64(claim.first_index),
+            last_index: saturating_u64(claim.last_index),
+            block_number: claim.block_added.number.as_u64(),
+            tx_hash: *claim.tx_hash,
+        }
+    }
+}
+
+/// The History stores input indices as `uint128`, though no DApp gets
+/// near `u64::MAX` inputs
+fn saturating_u64(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,171 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Canonical encodings of the states of the foldables, which are the same
+//! bytes for the same state on every node and release, so snapshots and
+//! responses can be diffed by external tools.
+//!
+//! Canonical JSON is compact, with the keys of every object sorted by their
+//! UTF-8 bytes. Canonical CBOR, behind the `cbor` feature, follows the core
+//! deterministic encoding of RFC 8949: shortest integers and map keys sorted
+//! by length, then by their bytes.
+
+use im::HashMap;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::{hash::Hash, sync::Arc};
+
+/// Canonical JSON of the value
+pub fn to_canonical_json<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut json = String::new();
+    write_json(&value, &mut json);
+    Ok(json)
+}
+
+fn write_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Array(items) => {
+            json.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_json(item, json);
+            }
+            json.push(']');
+        }
+        Value::Object(map) => {
+            // Sorted here, since `serde_json` keeps the insertion order when
+            // any crate in the build enables its `preserve_order` feature
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            json.push('{');
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                json.push_str(&Value::String(key.clone()).to_string());
+                json.push(':');
+                write_json(value, json);
+            }
+            json.push('}');
+        }
+        _ => json.push_str(&value.to_string()),
+    }
+}
+
+/// Canonical CBOR of the value, built from its JSON form
+#[cfg(feature = "cbor")]
+pub fn to_canonical_cbor<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<Vec<u8>, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut cbor = vec![];
+    ciborium::ser::into_writer(&to_cbor_value(value), &mut cbor)
+        .expect("CBOR values should always encode into a buffer");
+    Ok(cbor)
+}
+
+#[cfg(feature = "cbor")]
+fn to_cbor_value(value: Value) -> ciborium::value::Value {
+    use ciborium::value::Value as Cbor;
+    match value {
+        Value::Null => Cbor::Null,
+        Value::Bool(value) => Cbor::Bool(value),
+        Value::Number(number) => {
+            if let Some(number) = number.as_u64() {
+                Cbor::Integer(number.into())
+            } else if let Some(number) = number.as_i64() {
+                Cbor::Integer(number.into())
+            } else {
+                Cbor::Float(number.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Value::String(value) => Cbor::Text(value),
+        Value::Array(items) => {
+            Cbor::Array(items.into_iter().map(to_cbor_value).collect())
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| {
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            });
+            Cbor::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Cbor::Text(key), to_cbor_value(value)))
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Serializes a map of a state with its keys in order, instead of in the
+/// order of their hashes, which changes between runs
+pub(crate) fn sorted_map<K, V, S>(
+    map: &Arc<HashMap<K, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: Hash + Eq + Ord + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    serializer.collect_map(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_sorts_the_keys_of_the_json() {
+        let value = json!({"b": [{"z": 1, "a": null}], "a": "\"quoted\""});
+        assert_eq!(
+            to_canonical_json(&value).unwrap(),
+            r#"{"a":"\"quoted\"","b":[{"a":null,"z":1}]}"#
+        );
+    }
+
+    #[test]
+    fn it_serializes_maps_in_key_order() {
+        #[derive(Serialize)]
+        struct State {
+            #[serde(serialize_with = "sorted_map")]
+            map: Arc<HashMap<u32, &'static str>>,
+        }
+        let forward: HashMap<_, _> = (0..64).map(|i| (i, "x")).collect();
+        let backward: HashMap<_, _> = (0..64).rev().map(|i| (i, "x")).collect();
+        let forward = serde_json::to_string(&State {
+            map: Arc::new(forward),
+        })
+        .unwrap();
+        let backward = serde_json::to_string(&State {
+            map: Arc::new(backward),
+        })
+        .unwrap();
+        assert_eq!(forward, backward);
+        assert!(forward.starts_with(r#"{"map":{"0":"x","1":"x","2":"x""#));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn it_sorts_the_cbor_keys_by_length_first() {
+        let value = json!({"bb": 1, "a": 2, "c": [300]});
+        assert_eq!(
+            to_canonical_cbor(&value).unwrap(),
+            [
+                0xa3, // map(3)
+                0x61, b'a', 0x02, // "a": 2
+                0x61, b'c', 0x81, 0x19, 0x01, 0x2c, // "c": [300]
+                0x62, b'b', b'b', 0x01, // "bb": 1
+            ]
+        );
+    }
+}
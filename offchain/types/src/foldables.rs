@@ -1,7 +1,11 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use crate::{FoldableError, UserData};
+use crate::{
+    metrics::{Delegate, FoldMetrics, FoldPath},
+    user_data::InFlightKey,
+    FoldableError, UserData,
+};
 
 use eth_state_fold::{
     utils as fold_utils, FoldMiddleware, Foldable, StateFoldEnvironment,
@@ -10,18 +14,39 @@ use eth_state_fold::{
 use eth_state_fold_types::{
     ethers::{
         contract::LogMeta,
-        prelude::EthEvent,
         providers::Middleware,
-        types::{Address, TxHash},
+        types::{Address, TxHash, H256, U256},
     },
     Block,
 };
 
-use anyhow::{ensure, Context};
+use anyhow::{anyhow, ensure, Context};
 use async_trait::async_trait;
 use im::{HashMap, Vector};
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::future::Future;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod checkpoint;
+mod events;
+mod factory;
+mod gaps;
+mod history;
+mod shadow;
+pub use checkpoint::{
+    seed_checkpoint, Checkpointed, QueryRange, ResumableSync,
+};
+pub use events::{
+    tracked_events, EventRegistry, EventSignature, TopicCollision,
+};
+pub use factory::{DAppFactory, DAppFactoryInitialState, DeployedDApp};
+pub use gaps::{Audited, BlockEvent, EventGap, ReceiptEvents};
+pub use history::{
+    push_claim, Claim, DAppClaims, History, HistoryInitialState,
+};
+pub use shadow::Shadow;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct InputBoxInitialState {
@@ -47,9 +72,49 @@ pub struct DAppInputBox {
 pub struct InputBox {
     pub dapp_address: Arc<Address>,
     pub input_box_address: Arc<Address>,
+    #[serde(serialize_with = "crate::canonical::sorted_map")]
     pub dapp_input_boxes: Arc<HashMap<Arc<Address>, Arc<DAppInputBox>>>,
 }
 
+impl InputBox {
+    /// Canonical Keccak-256 hash of the state, which is the same on every
+    /// node that folded the same blocks, regardless of how the state was
+    /// built or serialized.
+    ///
+    /// The hash covers the addresses of the DApp and of the input box, then
+    /// each DApp input box in address order, with every integer in big-endian
+    /// and every payload prefixed by its length.
+    pub fn state_hash(&self) -> H256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(self.dapp_address.as_bytes());
+        hasher.update(self.input_box_address.as_bytes());
+
+        let mut dapps: Vec<_> = self.dapp_input_boxes.iter().collect();
+        dapps.sort_by_key(|(dapp, _)| ***dapp);
+        hasher.update((dapps.len() as u64).to_be_bytes());
+        for (dapp, input_box) in dapps {
+            hasher.update(dapp.as_bytes());
+            hasher.update((input_box.inputs.len() as u64).to_be_bytes());
+            for input in input_box.inputs.iter() {
+                input.hash_into(&mut hasher);
+            }
+        }
+        H256(hasher.finalize().into())
+    }
+}
+
+impl Input {
+    fn hash_into(&self, hasher: &mut Keccak256) {
+        hasher.update(self.sender.as_bytes());
+        hasher.update(self.dapp.as_bytes());
+        hasher.update(self.block_added.number.as_u64().to_be_bytes());
+        hasher.update(self.block_added.hash.as_bytes());
+        hasher.update(self.tx_hash.as_bytes());
+        hasher.update((self.payload.len() as u64).to_be_bytes());
+        hasher.update(&self.payload);
+    }
+}
+
 #[async_trait]
 impl Foldable for InputBox {
     type InitialState = InputBoxInitialState;
@@ -58,7 +123,7 @@ impl Foldable for InputBox {
 
     async fn sync<M: Middleware + 'static>(
         initial_state: &Self::InitialState,
-        _block: &Block,
+        block: &Block,
         env: &StateFoldEnvironment<M, Self::UserData>,
         access: Arc<SyncMiddleware<M>>,
     ) -> Result<Self, Self::Error> {
@@ -66,13 +131,20 @@ impl Foldable for InputBox {
         let input_box_address = Arc::clone(&initial_state.input_box_address);
 
         Ok(Self {
-            dapp_input_boxes: updated_inputs(
-                None,
-                access,
+            dapp_input_boxes: cancellable(
                 env,
-                &input_box_address,
-                &dapp_address,
-                None,
+                Delegate::InputBox,
+                FoldPath::Sync,
+                block.number.as_u64(),
+                updated_inputs(
+                    None,
+                    access,
+                    env,
+                    &input_box_address,
+                    &dapp_address,
+                    None,
+                    None,
+                ),
             )
             .await?,
             dapp_address,
@@ -90,24 +162,65 @@ impl Foldable for InputBox {
         let dapp_address = Arc::clone(&previous_state.dapp_address);
         let input_box_address = Arc::clone(&previous_state.input_box_address);
 
-        if !fold_utils::contains_address(&block.logs_bloom, &input_box_address)
-            || !fold_utils::contains_topic(&block.logs_bloom, &*dapp_address)
-            || !fold_utils::contains_topic(
-                &block.logs_bloom,
-                &contracts::input_box::InputAddedFilter::signature(),
-            )
+        if !tracked_events().bloom_may_contain(
+            &block.logs_bloom,
+            events::INPUT_BOX,
+            &input_box_address,
+        ) || !fold_utils::contains_topic(&block.logs_bloom, &*dapp_address)
         {
+            skipped(env, Delegate::InputBox);
             return Ok(previous_state.clone());
         }
 
         Ok(Self {
-            dapp_input_boxes: updated_inputs(
-                Some(&previous_state.dapp_input_boxes),
-                access,
+            dapp_input_boxes: cancellable(
+                env,
+                Delegate::InputBox,
+                FoldPath::Fold,
+                block.number.as_u64(),
+                updated_inputs(
+                    Some(&previous_state.dapp_input_boxes),
+                    access,
+                    env,
+                    &input_box_address,
+                    &dapp_address,
+                    None,
+                    None,
+                ),
+            )
+            .await?,
+            dapp_address,
+            input_box_address,
+        })
+    }
+}
+
+#[async_trait]
+impl ResumableSync for InputBox {
+    async fn sync_range<M: Middleware + 'static>(
+        previous_state: &Self,
+        range: QueryRange,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let dapp_address = Arc::clone(&previous_state.dapp_address);
+        let input_box_address = Arc::clone(&previous_state.input_box_address);
+
+        Ok(Self {
+            dapp_input_boxes: cancellable(
                 env,
-                &input_box_address,
-                &dapp_address,
-                None,
+                Delegate::InputBox,
+                FoldPath::Resume,
+                range.to_block,
+                updated_inputs(
+                    Some(&previous_state.dapp_input_boxes),
+                    access,
+                    env,
+                    &input_box_address,
+                    &dapp_address,
+                    None,
+                    Some(range),
+                ),
             )
             .await?,
             dapp_address,
@@ -123,6 +236,7 @@ async fn updated_inputs<M1: Middleware + 'static, M2: Middleware + 'static>(
     contract_address: &Address,
     dapp_address: &Address,
     block_opt: Option<Block>, // TODO: Option<Arc<Block>>,
+    range: Option<QueryRange>,
 ) -> Result<Arc<HashMap<Arc<Address>, Arc<DAppInputBox>>>, FoldableError> {
     let mut input_boxes =
         previous_input_boxes.cloned().unwrap_or(HashMap::new());
@@ -133,30 +247,60 @@ async fn updated_inputs<M1: Middleware + 'static, M2: Middleware + 'static>(
         contract_address,
         dapp_address,
         block_opt,
+        range,
     )
     .await?;
 
-    for input in new_inputs {
-        let dapp = input.dapp.clone();
-        let input = Arc::new(input);
-
-        input_boxes
-            .entry(dapp)
-            .and_modify(|i| {
-                let mut new_input_box = (**i).clone();
-                new_input_box.inputs.push_back(input.clone());
-                *i = Arc::new(new_input_box);
-            })
-            .or_insert_with(|| {
-                Arc::new(DAppInputBox {
-                    inputs: im::vector![input],
-                })
-            });
+    for (input_index, input) in new_inputs {
+        push_input(&mut input_boxes, input_index, Arc::new(input))?;
     }
 
     Ok(Arc::new(input_boxes))
 }
 
+/// Appends an input to the input box of its DApp.
+///
+/// The index emitted by the InputBox must be the position of the input in
+/// that box. An index that doesn't fit in 64 bits, or that skips or repeats
+/// one, means the logs are malformed or incomplete, so the fold fails
+/// instead of storing the input at the wrong position.
+pub fn push_input(
+    input_boxes: &mut HashMap<Arc<Address>, Arc<DAppInputBox>>,
+    input_index: U256,
+    input: Arc<Input>,
+) -> Result<(), anyhow::Error> {
+    ensure!(
+        input_index <= U256::from(u64::MAX),
+        "Index {} of input of DApp `{:?}` doesn't fit in 64 bits",
+        input_index,
+        input.dapp
+    );
+    let expected = input_boxes
+        .get(&input.dapp)
+        .map_or(0, |input_box| input_box.inputs.len());
+    ensure!(
+        input_index == U256::from(expected),
+        "Index {} of input of DApp `{:?}` should be {}",
+        input_index,
+        input.dapp,
+        expected
+    );
+
+    input_boxes
+        .entry(input.dapp.clone())
+        .and_modify(|i| {
+            let mut new_input_box = (**i).clone();
+            new_input_box.inputs.push_back(input.clone());
+            *i = Arc::new(new_input_box);
+        })
+        .or_insert_with(|| {
+            Arc::new(DAppInputBox {
+                inputs: im::vector![input],
+            })
+        });
+    Ok(())
+}
+
 async fn fetch_all_new_inputs<
     M1: Middleware + 'static,
     M2: Middleware + 'static,
@@ -166,21 +310,26 @@ async fn fetch_all_new_inputs<
     contract_address: &Address,
     dapp_address: &Address,
     block_opt: Option<Block>, // TODO: Option<Arc<Block>>,
-) -> Result<Vec<Input>, FoldableError> {
+    range: Option<QueryRange>,
+) -> Result<Vec<(U256, Input)>, FoldableError> {
     use contracts::input_box::*;
     let contract = InputBox::new(*contract_address, Arc::clone(&provider));
 
     // Retrieve `InputAdded` events
-    let input_events = contract
-        .input_added_filter()
-        .topic1(*dapp_address)
-        .query_with_meta()
-        .await
-        .context("Error querying for input added events")?;
+    let mut filter = contract.input_added_filter().topic1(*dapp_address);
+    if let Some(range) = range {
+        filter = filter.from_block(range.from_block).to_block(range.to_block);
+    }
+    let input_events =
+        timed_query(env, Delegate::InputBox, filter.query_with_meta())
+            .await
+            .context("Error querying for input added events")?;
 
     let mut inputs = Vec::with_capacity(input_events.len());
     for (event, meta) in input_events {
-        inputs.push(Input::build_input(env, event, meta, &block_opt).await?);
+        let input_index = event.input_index;
+        let input = Input::build_input(env, event, meta, &block_opt).await?;
+        inputs.push((input_index, input));
     }
 
     Ok(inputs)
@@ -222,6 +371,85 @@ impl Input {
     }
 }
 
+/// Runs a sync or fold step of the delegate up to `block_number`, which is
+/// aborted once the steps in flight up to it are cancelled, recording it in
+/// the fold metrics
+async fn cancellable<M: Middleware + 'static, T>(
+    env: &StateFoldEnvironment<M, Mutex<UserData>>,
+    delegate: Delegate,
+    path: FoldPath,
+    block_number: u64,
+    step: impl Future<Output = Result<T, FoldableError>>,
+) -> Result<T, FoldableError> {
+    let (key, token, metrics) = {
+        let mut user_data = env
+            .user_data()
+            .lock()
+            .expect("Mutex should never be poisoned");
+        let (key, token) = user_data.start_in_flight(block_number);
+        (key, token, user_data.metrics().clone())
+    };
+    let _in_flight = InFlight {
+        user_data: env.user_data(),
+        key,
+    };
+    let started_at = Instant::now();
+    let result = tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(anyhow!("Sync or fold cancelled").into()),
+        result = step => result,
+    };
+    if result.is_ok() {
+        metrics.step(delegate, path, started_at.elapsed());
+    }
+    result
+}
+
+/// Stops tracking a step in flight once it finishes or is dropped
+struct InFlight<'a> {
+    user_data: &'a Mutex<UserData>,
+    key: InFlightKey,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut user_data) = self.user_data.lock() {
+            user_data.finish_in_flight(self.key);
+        }
+    }
+}
+
+fn fold_metrics<M: Middleware + 'static>(
+    env: &StateFoldEnvironment<M, Mutex<UserData>>,
+) -> FoldMetrics {
+    env.user_data()
+        .lock()
+        .expect("Mutex should never be poisoned")
+        .metrics()
+        .clone()
+}
+
+/// Records a fold of a block whose bloom filter rules out the events of the
+/// delegate
+fn skipped<M: Middleware + 'static>(
+    env: &StateFoldEnvironment<M, Mutex<UserData>>,
+    delegate: Delegate,
+) {
+    fold_metrics(env).step(delegate, FoldPath::Skip, Duration::ZERO);
+}
+
+/// Runs an event query of the delegate, recording how long it took
+async fn timed_query<M: Middleware + 'static, T>(
+    env: &StateFoldEnvironment<M, Mutex<UserData>>,
+    delegate: Delegate,
+    query: impl Future<Output = T>,
+) -> T {
+    let started_at = Instant::now();
+    let result = query.await;
+    fold_metrics(env).event_query(delegate, started_at.elapsed());
+    result
+}
+
 fn meta_consistent_with_block(
     meta: &LogMeta,
     block: &Block,
@@ -238,3 +466,79 @@ fn meta_consistent_with_block(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethers::types::Bloom;
+
+    fn input(dapp: Address, payload: &[u8]) -> Arc<Input> {
+        Arc::new(Input {
+            sender: Arc::new(Address::repeat_byte(0x11)),
+            payload: payload.to_vec(),
+            block_added: Arc::new(Block {
+                hash: H256::repeat_byte(0x22),
+                number: 7.into(),
+                parent_hash: H256::repeat_byte(0x33),
+                timestamp: 1000.into(),
+                logs_bloom: Bloom::default(),
+            }),
+            dapp: Arc::new(dapp),
+            tx_hash: Arc::new(H256::repeat_byte(0x44)),
+        })
+    }
+
+    fn input_box(dapps: &[(Address, &[&[u8]])]) -> InputBox {
+        let mut dapp_input_boxes = HashMap::new();
+        for (dapp, payloads) in dapps {
+            let inputs = payloads
+                .iter()
+                .map(|payload| input(*dapp, payload))
+                .collect();
+            dapp_input_boxes
+                .insert(Arc::new(*dapp), Arc::new(DAppInputBox { inputs }));
+        }
+        InputBox {
+            dapp_address: Arc::new(Address::repeat_byte(0xaa)),
+            input_box_address: Arc::new(Address::repeat_byte(0xbb)),
+            dapp_input_boxes: Arc::new(dapp_input_boxes),
+        }
+    }
+
+    #[test]
+    fn state_hash_does_not_depend_on_insertion_order() {
+        let a = Address::repeat_byte(1);
+        let b = Address::repeat_byte(2);
+        let first = input_box(&[(a, &[b"x"]), (b, &[b"y", b"z"])]);
+        let second = input_box(&[(b, &[b"y", b"z"]), (a, &[b"x"])]);
+        assert_eq!(first.state_hash(), second.state_hash());
+    }
+
+    #[test]
+    fn state_hash_changes_with_the_inputs() {
+        let a = Address::repeat_byte(1);
+        let reference = input_box(&[(a, &[b"xy"])]).state_hash();
+        assert_ne!(reference, input_box(&[(a, &[b"x", b"y"])]).state_hash());
+        assert_ne!(reference, input_box(&[(a, &[b"xz"])]).state_hash());
+        assert_ne!(reference, input_box(&[]).state_hash());
+    }
+
+    #[test]
+    fn push_input_checks_the_index() {
+        let a = Address::repeat_byte(1);
+        let b = Address::repeat_byte(2);
+        let mut input_boxes = HashMap::new();
+        push_input(&mut input_boxes, 0.into(), input(a, b"x")).unwrap();
+        push_input(&mut input_boxes, 0.into(), input(b, b"y")).unwrap();
+        push_input(&mut input_boxes, 1.into(), input(a, b"z")).unwrap();
+
+        // Repeated, skipped and truncated indices
+        assert!(push_input(&mut input_boxes, 1.into(), input(a, b"")).is_err());
+        assert!(push_input(&mut input_boxes, 3.into(), input(a, b"")).is_err());
+        let index = (U256::one() << 64) + 2;
+        assert!(push_input(&mut input_boxes, index, input(a, b"")).is_err());
+
+        assert_eq!(input_boxes[&a].inputs.len(), 2);
+        assert_eq!(input_boxes[&b].inputs.len(), 1);
+    }
+}
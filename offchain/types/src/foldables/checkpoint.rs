@@ -0,0 +1,675 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold::{
+    FoldMiddleware, Foldable, StateFoldEnvironment, SyncMiddleware,
+};
+use eth_state_fold_types::{
+    ethers::{
+        providers::Middleware,
+        signers::{LocalWallet, Signer},
+        types::{Address, Signature, H256},
+    },
+    Block,
+};
+
+use anyhow::{bail, ensure, Context};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use sha3::{Digest, Keccak256};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::UserData;
+
+/// Blocks whose events a resumed sync queries, both inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryRange {
+    pub from_block: u64,
+    pub to_block: u64,
+}
+
+impl QueryRange {
+    /// Blocks after the one a state was synced up to, or none if it is
+    /// already synced up to `to_block`
+    pub fn after(synced_block: u64, to_block: u64) -> Option<Self> {
+        let from_block = synced_block.checked_add(1)?;
+        (from_block <= to_block).then_some(Self {
+            from_block,
+            to_block,
+        })
+    }
+}
+
+/// Foldables that can bring a state synced up to a block to a later one by
+/// querying only the events of the blocks in between
+#[async_trait]
+pub trait ResumableSync: Foldable {
+    /// State with the events of the range added to `previous_state`, which
+    /// was synced up to the block before it
+    async fn sync_range<M: Middleware + 'static>(
+        previous_state: &Self,
+        range: QueryRange,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error>;
+}
+
+/// State saved with the block it was folded up to
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<F> {
+    block_number: u64,
+    block_hash: H256,
+    state: F,
+}
+
+/// Checkpoints of a state, from the oldest to the newest, each linked to the
+/// one before it, so a checkpoint that was altered or corrupted at rest isn't
+/// resumed from. It is signed by the operator, if the checkpoints are signed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    block_number: u64,
+    block_hash: H256,
+    /// keccak256 of the file of the checkpoint
+    checkpoint_hash: H256,
+    /// Link of the previous entry, which may have been dropped from the
+    /// manifest along with its checkpoint
+    previous: H256,
+    /// keccak256 of the fields above, which the next entry links to
+    link: H256,
+}
+
+impl ManifestEntry {
+    fn new(
+        block_number: u64,
+        block_hash: H256,
+        checkpoint_hash: H256,
+        previous: H256,
+    ) -> Self {
+        let mut entry = Self {
+            block_number,
+            block_hash,
+            checkpoint_hash,
+            previous,
+            link: H256::zero(),
+        };
+        entry.link = entry.compute_link();
+        entry
+    }
+
+    fn compute_link(&self) -> H256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(self.block_number.to_be_bytes());
+        hasher.update(self.block_hash);
+        hasher.update(self.checkpoint_hash);
+        hasher.update(self.previous);
+        H256(hasher.finalize().into())
+    }
+}
+
+impl Manifest {
+    /// Checks that each entry is intact and links to the one before it
+    fn verify_chain(&self) -> Result<(), anyhow::Error> {
+        for (index, entry) in self.entries.iter().enumerate() {
+            ensure!(
+                entry.link == entry.compute_link(),
+                "entry of block {} was altered",
+                entry.block_number
+            );
+            if let Some(previous) = index.checked_sub(1) {
+                ensure!(
+                    entry.previous == self.entries[previous].link,
+                    "entry of block {} doesn't link to the one before it",
+                    entry.block_number
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the checkpoint is one of the entries
+    fn contains<F>(
+        &self,
+        checkpoint: &Checkpoint<F>,
+        checkpoint_hash: H256,
+    ) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.block_number == checkpoint.block_number
+                && entry.block_hash == checkpoint.block_hash
+                && entry.checkpoint_hash == checkpoint_hash
+        })
+    }
+
+    /// Adds the checkpoint, keeping only the last `history` entries
+    fn push(&mut self, history: usize, block: &Block, checkpoint_hash: H256) {
+        let previous = self
+            .entries
+            .last()
+            .map(|entry| entry.link)
+            .unwrap_or_default();
+        self.entries.push(ManifestEntry::new(
+            block.number.as_u64(),
+            block.hash,
+            checkpoint_hash,
+            previous,
+        ));
+        let excess = self.entries.len().saturating_sub(history);
+        self.entries.drain(..excess);
+    }
+}
+
+/// Saves the folded state every few blocks, so a sync, such as the one on
+/// start, resumes from the last checkpoint instead of querying the events
+/// from genesis, such as with `run_server::<Checkpointed<InputBox>>`.
+///
+/// The checkpoints are written to the directory and at the interval set
+/// with `UserData::with_checkpoints`, which also sets how many of the last
+/// ones are kept for each initial state. A checkpoint whose block is no
+/// longer in the chain was reorged out, along with a part of its state that
+/// can't be told apart, so the sync rolls back to the newest older one still
+/// in the chain and replays the blocks after it. Only when none is left, or
+/// none can be read, does the sync start from genesis again.
+///
+/// Next to the checkpoints is their manifest, signed with the key set with
+/// `UserData::with_checkpoint_signer`, if any. A checkpoint is only resumed
+/// from if it is in the manifest, and the manifest is intact and, with a
+/// key, signed with it; otherwise the sync starts from genesis, as if the
+/// checkpoint wasn't there.
+#[derive(Clone, Debug)]
+pub struct Checkpointed<F> {
+    pub state: F,
+    /// File of the checkpoints of the state, if they are on
+    path: Option<Arc<PathBuf>>,
+}
+
+impl<F: Serialize> Serialize for Checkpointed<F> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.state.serialize(serializer)
+    }
+}
+
+#[async_trait]
+impl<F> Foldable for Checkpointed<F>
+where
+    F: Foldable<UserData = Mutex<UserData>>
+        + ResumableSync
+        + Serialize
+        + DeserializeOwned
+        + 'static,
+    F::InitialState: Serialize,
+{
+    type InitialState = F::InitialState;
+    type Error = F::Error;
+    type UserData = F::UserData;
+
+    async fn sync<M: Middleware + 'static>(
+        initial_state: &Self::InitialState,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let (dir, history, signer) = {
+            let user_data = env
+                .user_data()
+                .lock()
+                .expect("Mutex should never be poisoned");
+            (
+                user_data.checkpoint_dir(),
+                user_data.checkpoint_history(),
+                user_data.checkpoint_signer().map(|wallet| wallet.address()),
+            )
+        };
+        let path =
+            dir.map(|dir| Arc::new(checkpoint_path(&dir, initial_state)));
+        let block_number = block.number.as_u64();
+
+        let manifest = match &path {
+            Some(path) => match load_manifest(path, signer).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    tracing::error!(
+                        path = %path.display(),
+                        "checkpoints failed the integrity check; not resuming from them: {:?}",
+                        e
+                    );
+                    Manifest::default()
+                }
+            },
+            None => Manifest::default(),
+        };
+
+        // From the newest checkpoint to the oldest
+        let paths = path.iter().flat_map(|path| {
+            (0..history).map(|index| history_path(path, index))
+        });
+        for file in paths {
+            let (checkpoint, checkpoint_hash) = match load::<F>(&file).await {
+                Ok(Some(checkpoint)) => checkpoint,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::warn!(
+                        path = %file.display(),
+                        "failed to read the checkpoint: {:?}",
+                        e
+                    );
+                    continue;
+                }
+            };
+            if !manifest.contains(&checkpoint, checkpoint_hash) {
+                tracing::error!(
+                    path = %file.display(),
+                    checkpoint_block_number = checkpoint.block_number,
+                    "checkpoint isn't in its manifest; skipping it"
+                );
+            } else if checkpoint.block_number > block_number {
+                tracing::info!(
+                    block_number,
+                    checkpoint_block_number = checkpoint.block_number,
+                    "checkpoint is ahead of the block"
+                );
+            } else if is_canonical(&access, &checkpoint).await {
+                tracing::info!(
+                    block_number,
+                    checkpoint_block_number = checkpoint.block_number,
+                    "resuming the sync from the checkpoint"
+                );
+                let state = match QueryRange::after(
+                    checkpoint.block_number,
+                    block_number,
+                ) {
+                    Some(range) => {
+                        F::sync_range(&checkpoint.state, range, env, access)
+                            .await?
+                    }
+                    None => checkpoint.state,
+                };
+                return Ok(Self { state, path });
+            } else {
+                tracing::warn!(
+                    checkpoint_block_number = checkpoint.block_number,
+                    checkpoint_block_hash = ?checkpoint.block_hash,
+                    "checkpoint was reorged out; rolling back past it"
+                );
+            }
+        }
+
+        if path.is_some() {
+            tracing::info!(block_number, "no checkpoint to resume from");
+        }
+        let state = F::sync(initial_state, block, env, access).await?;
+        Ok(Self { state, path })
+    }
+
+    async fn fold<M: Middleware + 'static>(
+        previous_state: &Self,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<FoldMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let state = F::fold(&previous_state.state, block, env, access).await?;
+        let path = previous_state.path.clone();
+
+        let block_number = block.number.as_u64();
+        let (checkpoints, history, signer) = {
+            let user_data = env
+                .user_data()
+                .lock()
+                .expect("Mutex should never be poisoned");
+            (
+                user_data.checkpoints_at(block_number),
+                user_data.checkpoint_history(),
+                user_data.checkpoint_signer().cloned(),
+            )
+        };
+        if let (true, Some(path)) = (checkpoints, &path) {
+            if let Err(e) =
+                save(path, history, block, &state, signer.as_ref()).await
+            {
+                tracing::warn!(
+                    block_number,
+                    path = %path.display(),
+                    "failed to write the checkpoint: {:?}",
+                    e
+                );
+            }
+        }
+        Ok(Self { state, path })
+    }
+}
+
+/// Writes a checkpoint of a state built outside of the folds, such as from
+/// an archive of the chain, so the next sync of the state with the initial
+/// state resumes from it and only queries the events after the block.
+/// Returns the file of the checkpoint
+pub async fn seed_checkpoint<I: Serialize, F: Serialize>(
+    dir: &Path,
+    initial_state: &I,
+    history: usize,
+    block: &Block,
+    state: &F,
+    signer: Option<&LocalWallet>,
+) -> Result<PathBuf, anyhow::Error> {
+    let path = checkpoint_path(dir, initial_state);
+    save(&path, history, block, state, signer).await?;
+    Ok(path)
+}
+
+/// Whether the block of the checkpoint is still in the chain
+async fn is_canonical<M: Middleware + 'static, F>(
+    access: &SyncMiddleware<M>,
+    checkpoint: &Checkpoint<F>,
+) -> bool {
+    match access.get_block(checkpoint.block_number).await {
+        Ok(Some(block)) => block.hash == Some(checkpoint.block_hash),
+        Ok(None) => false,
+        Err(e) => {
+            tracing::warn!(
+                checkpoint_block_number = checkpoint.block_number,
+                "failed to fetch the block of the checkpoint: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// File of the checkpoints of the states with the initial state, which is
+/// shared by the foldables that wrap the same delegate
+fn checkpoint_path<I: Serialize>(dir: &Path, initial_state: &I) -> PathBuf {
+    let initial_state = serde_json::to_vec(initial_state)
+        .expect("initial states should always serialize");
+    let key = H256(Keccak256::digest(initial_state).into());
+    dir.join(format!("{:x}.json", key))
+}
+
+/// File of the checkpoint kept `index` checkpoints before the last one,
+/// which is in the file of the checkpoints itself
+fn history_path(path: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => path.to_path_buf(),
+        index => path.with_extension(format!("{}.json", index)),
+    }
+}
+
+/// Manifest of the checkpoints in the file, and its signature
+fn manifest_path(path: &Path) -> PathBuf {
+    path.with_extension("manifest.json")
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    path.with_extension("manifest.json.sig")
+}
+
+/// Reads the checkpoint along with the keccak256 of its file
+async fn load<F: DeserializeOwned>(
+    path: &Path,
+) -> Result<Option<(Checkpoint<F>, H256)>, anyhow::Error> {
+    let Some(json) = read(path).await? else {
+        return Ok(None);
+    };
+    let checkpoint =
+        serde_json::from_slice(&json).context("invalid checkpoint")?;
+    Ok(Some((checkpoint, H256(Keccak256::digest(&json).into()))))
+}
+
+/// Reads the manifest of the checkpoints, checking that it is intact and,
+/// given a `signer`, that it signed it
+async fn load_manifest(
+    path: &Path,
+    signer: Option<Address>,
+) -> Result<Manifest, anyhow::Error> {
+    let Some(json) = read(&manifest_path(path)).await? else {
+        return Ok(Manifest::default());
+    };
+    if let Some(signer) = signer {
+        let Some(signature) = read(&signature_path(path)).await? else {
+            bail!("the manifest isn't signed");
+        };
+        String::from_utf8_lossy(&signature)
+            .trim()
+            .parse::<Signature>()
+            .context("invalid manifest signature")?
+            .verify(json.as_slice(), signer)
+            .context("manifest isn't signed by the checkpoint signer")?;
+    }
+    let manifest: Manifest =
+        serde_json::from_slice(&json).context("invalid manifest")?;
+    manifest.verify_chain()?;
+    Ok(manifest)
+}
+
+async fn read(path: &Path) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the file through a temporary one, so a crash never leaves it half
+/// written
+async fn write(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    let temporary = path.with_extension("tmp");
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, path).await?;
+    Ok(())
+}
+
+/// Writes the checkpoint, after moving the last `history - 1` ones back a
+/// file, and then adds it to the manifest, signing it with the `signer`
+async fn save<F: Serialize>(
+    path: &Path,
+    history: usize,
+    block: &Block,
+    state: &F,
+    signer: Option<&LocalWallet>,
+) -> Result<(), anyhow::Error> {
+    let json = serde_json::to_vec(&Checkpoint {
+        block_number: block.number.as_u64(),
+        block_hash: block.hash,
+        state,
+    })?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    for index in (1..history).rev() {
+        let from = history_path(path, index - 1);
+        match tokio::fs::rename(&from, history_path(path, index)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    write(path, &json).await?;
+
+    // A manifest that can't be trusted is started over, since it would
+    // reject the new checkpoint along with the old ones
+    let address = signer.map(|wallet| wallet.address());
+    let mut manifest = load_manifest(path, address).await.unwrap_or_default();
+    manifest.push(history, block, H256(Keccak256::digest(&json).into()));
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    if let Some(signer) = signer {
+        let signature = signer.sign_message(&manifest).await?;
+        write(&signature_path(path), format!("0x{}", signature).as_bytes())
+            .await?;
+    }
+    write(&manifest_path(path), &manifest).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foldables::InputBoxInitialState;
+    use eth_state_fold_types::ethers::types::{Address, Bloom};
+    use serde_json::{json, Value};
+
+    fn block(number: u64) -> Block {
+        Block {
+            hash: H256::repeat_byte(number as u8),
+            number: number.into(),
+            parent_hash: H256::repeat_byte(0),
+            timestamp: 1000.into(),
+            logs_bloom: Bloom::default(),
+        }
+    }
+
+    fn initial_state(dapp: u8) -> InputBoxInitialState {
+        InputBoxInitialState {
+            dapp_address: Arc::new(Address::repeat_byte(dapp)),
+            input_box_address: Arc::new(Address::repeat_byte(0xbb)),
+        }
+    }
+
+    #[test]
+    fn it_queries_the_blocks_after_the_checkpoint() {
+        assert_eq!(
+            QueryRange::after(10, 20),
+            Some(QueryRange {
+                from_block: 11,
+                to_block: 20
+            })
+        );
+        assert_eq!(
+            QueryRange::after(10, 11),
+            Some(QueryRange {
+                from_block: 11,
+                to_block: 11
+            })
+        );
+        assert_eq!(QueryRange::after(10, 10), None);
+        assert_eq!(QueryRange::after(u64::MAX, u64::MAX), None);
+    }
+
+    #[test]
+    fn it_keeps_a_file_per_initial_state() {
+        let dir = Path::new("checkpoints");
+        let path = checkpoint_path(dir, &initial_state(0xaa));
+        assert_eq!(path, checkpoint_path(dir, &initial_state(0xaa)));
+        assert_ne!(path, checkpoint_path(dir, &initial_state(0xab)));
+        assert_eq!(path.parent(), Some(dir));
+    }
+
+    #[tokio::test]
+    async fn it_loads_the_saved_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = checkpoint_path(dir.path(), &initial_state(0xaa));
+        assert!(load::<Value>(&path).await.unwrap().is_none());
+
+        let state = json!({"dapp_input_boxes": {"0xaa": {"inputs": [1]}}});
+        save(&path, 1, &block(7), &state, None).await.unwrap();
+        save(&path, 1, &block(9), &state, None).await.unwrap();
+        let (checkpoint, _) = load::<Value>(&path).await.unwrap().unwrap();
+        assert_eq!(checkpoint.block_number, 9);
+        assert_eq!(checkpoint.block_hash, H256::repeat_byte(9));
+        assert_eq!(checkpoint.state, state);
+
+        tokio::fs::write(&path, "{").await.unwrap();
+        assert!(load::<Value>(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_resumes_from_a_seeded_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let state = json!({"dapp_input_boxes": {"0xaa": {"inputs": [1]}}});
+        let path = seed_checkpoint(
+            dir.path(),
+            &initial_state(0xaa),
+            3,
+            &block(7),
+            &state,
+            Some(&wallet),
+        )
+        .await
+        .unwrap();
+        assert_eq!(path, checkpoint_path(dir.path(), &initial_state(0xaa)));
+
+        let manifest =
+            load_manifest(&path, Some(wallet.address())).await.unwrap();
+        let (checkpoint, hash) = load::<Value>(&path).await.unwrap().unwrap();
+        assert!(manifest.contains(&checkpoint, hash));
+        assert_eq!(checkpoint.block_number, 7);
+        assert_eq!(checkpoint.state, state);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_last_checkpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = checkpoint_path(dir.path(), &initial_state(0xaa));
+        for number in 7..=9 {
+            save(&path, 2, &block(number), &json!(number), None)
+                .await
+                .unwrap();
+        }
+
+        let (newest, _) = load::<Value>(&path).await.unwrap().unwrap();
+        assert_eq!(newest.block_number, 9);
+        let older = history_path(&path, 1);
+        let (older, _) = load::<Value>(&older).await.unwrap().unwrap();
+        assert_eq!(older.block_number, 8);
+        assert_eq!(older.state, json!(8));
+        let oldest = history_path(&path, 2);
+        assert!(load::<Value>(&oldest).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn it_only_trusts_the_checkpoints_in_the_signed_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = checkpoint_path(dir.path(), &initial_state(0xaa));
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        for number in 7..=9 {
+            save(&path, 2, &block(number), &json!(number), Some(&wallet))
+                .await
+                .unwrap();
+        }
+
+        let manifest =
+            load_manifest(&path, Some(wallet.address())).await.unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        let (checkpoint, hash) = load::<Value>(&path).await.unwrap().unwrap();
+        assert!(manifest.contains(&checkpoint, hash));
+
+        // A checkpoint altered at rest is no longer in the manifest
+        let altered = json!({
+            "block_number": 9,
+            "block_hash": block(9).hash,
+            "state": 10,
+        });
+        tokio::fs::write(&path, altered.to_string()).await.unwrap();
+        let (checkpoint, hash) = load::<Value>(&path).await.unwrap().unwrap();
+        assert!(!manifest.contains(&checkpoint, hash));
+
+        // Nor is a manifest that another key signed
+        let other = LocalWallet::new(&mut rand::thread_rng());
+        assert!(load_manifest(&path, Some(other.address())).await.is_err());
+    }
+
+    #[test]
+    fn it_detects_broken_links_in_the_manifest() {
+        let mut manifest = Manifest::default();
+        for number in 7..=9 {
+            manifest.push(3, &block(number), H256::repeat_byte(1));
+        }
+        assert!(manifest.verify_chain().is_ok());
+
+        manifest.entries[1].checkpoint_hash = H256::repeat_byte(2);
+        assert!(manifest.verify_chain().is_err());
+        manifest.entries[1] = ManifestEntry::new(
+            8,
+            block(8).hash,
+            H256::repeat_byte(2),
+            manifest.entries[0].link,
+        );
+        assert!(manifest.verify_chain().is_err());
+    }
+}
@@ -0,0 +1,262 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use contracts::cartesi_dapp_factory::ApplicationCreatedFilter;
+use eth_state_fold::utils as fold_utils;
+use eth_state_fold_types::ethers::{
+    abi::{Abi, Event},
+    prelude::EthEvent,
+    types::{Address, Bloom, BloomInput, H256},
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+pub const INPUT_BOX: &str = "InputBox";
+pub const HISTORY: &str = "History";
+pub const AUTHORITY: &str = "Authority";
+pub const CARTESI_DAPP: &str = "CartesiDApp";
+pub const CARTESI_DAPP_FACTORY: &str = "CartesiDAppFactory";
+
+/// Event declared in the ABI of a contract, with what is needed to tell it
+/// apart from other events with the same topic0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventSignature {
+    pub contract: &'static str,
+    /// Canonical signature, e.g. `InputAdded(address,uint256,address,bytes)`
+    pub signature: String,
+    /// Which of the params are indexed, and so go to the topics
+    pub indexed: Vec<bool>,
+    pub topic0: H256,
+}
+
+impl EventSignature {
+    fn new(contract: &'static str, event: &Event) -> Self {
+        let params: Vec<String> = event
+            .inputs
+            .iter()
+            .map(|input| input.kind.to_string())
+            .collect();
+        Self {
+            contract,
+            signature: format!("{}({})", event.name, params.join(",")),
+            indexed: event.inputs.iter().map(|input| input.indexed).collect(),
+            topic0: event.signature(),
+        }
+    }
+}
+
+/// Events of different contracts that share a topic0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicCollision {
+    pub topic0: H256,
+    pub events: Vec<EventSignature>,
+    /// Whether the events index different params, so a log of one can't be
+    /// decoded as the other; logs must then be told apart by address
+    pub ambiguous: bool,
+}
+
+/// Registry of the event signatures of the contracts the foldables read,
+/// and of the events each foldable tracks.
+///
+/// Bloom checks go through the registry, so a foldable skips a block only
+/// when none of the events it tracks can be in it.
+#[derive(Clone, Debug, Default)]
+pub struct EventRegistry {
+    events: BTreeMap<H256, Vec<EventSignature>>,
+    tracked: HashMap<&'static str, Vec<H256>>,
+}
+
+impl EventRegistry {
+    /// Registers the events of the ABI of a contract, returning the
+    /// collisions it introduces
+    pub fn register_abi(
+        &mut self,
+        contract: &'static str,
+        abi: &Abi,
+    ) -> Vec<TopicCollision> {
+        let mut new_collisions = vec![];
+        for event in abi.events().filter(|event| !event.anonymous) {
+            let event = EventSignature::new(contract, event);
+            let topic0 = event.topic0;
+            let events = self.events.entry(topic0).or_default();
+            if events.contains(&event) {
+                continue;
+            }
+            let collides = events.iter().any(|e| e.contract != contract);
+            events.push(event);
+            if collides {
+                new_collisions.extend(self.collision(&topic0));
+            }
+        }
+        new_collisions
+    }
+
+    /// Tracks an event of a contract, so its topic is consulted by the bloom
+    /// checks of the contract
+    pub fn track<E: EthEvent>(&mut self, contract: &'static str) {
+        let topics = self.tracked.entry(contract).or_default();
+        let topic0 = E::signature();
+        if !topics.contains(&topic0) {
+            topics.push(topic0);
+        }
+    }
+
+    /// Topics tracked for the contract
+    pub fn tracked_topics(&self, contract: &str) -> &[H256] {
+        self.tracked
+            .get(contract)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Whether the block may hold a log of a tracked event of the contract
+    pub fn bloom_may_contain(
+        &self,
+        bloom: &Bloom,
+        contract: &str,
+        address: &Address,
+    ) -> bool {
+        fold_utils::contains_address(bloom, address)
+            && self
+                .tracked_topics(contract)
+                .iter()
+                .any(|topic| fold_utils::contains_topic(bloom, topic))
+    }
+
+    fn collision(&self, topic0: &H256) -> Option<TopicCollision> {
+        let events = self.events.get(topic0)?;
+        let first = events.first()?;
+        if events.iter().all(|e| e.contract == first.contract) {
+            return None;
+        }
+        Some(TopicCollision {
+            topic0: *topic0,
+            events: events.clone(),
+            ambiguous: events.iter().any(|e| e.indexed != first.indexed),
+        })
+    }
+
+    /// Every topic0 shared by events of different contracts
+    pub fn collisions(&self) -> Vec<TopicCollision> {
+        self.events
+            .keys()
+            .filter_map(|topic0| self.collision(topic0))
+            .collect()
+    }
+}
+
+/// Registry of the contracts and events read by the foldables of this
+/// crate. Collisions are logged when it is first built, so a new release of
+/// the contracts that introduces ambiguous signatures is noticed.
+pub fn tracked_events() -> &'static EventRegistry {
+    static REGISTRY: OnceLock<EventRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = EventRegistry::default();
+        let abis: [(&'static str, &Abi); 5] = [
+            (INPUT_BOX, &*contracts::input_box::INPUTBOX_ABI),
+            (HISTORY, &*contracts::history::HISTORY_ABI),
+            (AUTHORITY, &*contracts::authority::AUTHORITY_ABI),
+            (CARTESI_DAPP, &*contracts::cartesi_dapp::CARTESIDAPP_ABI),
+            (
+                CARTESI_DAPP_FACTORY,
+                &*contracts::cartesi_dapp_factory::CARTESIDAPPFACTORY_ABI,
+            ),
+        ];
+        for (contract, abi) in abis {
+            for collision in registry.register_abi(contract, abi) {
+                if collision.ambiguous {
+                    tracing::warn!(
+                        "Ambiguous event signatures share topic0 {:?}: {:?}",
+                        collision.topic0,
+                        collision.events
+                    );
+                } else {
+                    tracing::debug!(
+                        "Events of different contracts share topic0 {:?}: {:?}",
+                        collision.topic0,
+                        collision.events
+                    );
+                }
+            }
+        }
+        registry.track::<contracts::input_box::InputAddedFilter>(INPUT_BOX);
+        registry.track::<contracts::history::NewClaimToHistoryFilter>(HISTORY);
+        registry.track::<ApplicationCreatedFilter>(CARTESI_DAPP_FACTORY);
+        registry
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abi(json: &str) -> Abi {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn transfer_abi(indexed: bool) -> Abi {
+        abi(&format!(
+            r#"[{{"type":"event","name":"Transfer","anonymous":false,"inputs":[
+                {{"name":"from","type":"address","indexed":true}},
+                {{"name":"to","type":"address","indexed":{}}},
+                {{"name":"value","type":"uint256","indexed":false}}
+            ]}}]"#,
+            indexed
+        ))
+    }
+
+    #[test]
+    fn it_detects_collisions_between_contracts() {
+        let mut registry = EventRegistry::default();
+        assert!(registry.register_abi("A", &transfer_abi(true)).is_empty());
+        // The same ABI again is not a collision
+        assert!(registry.register_abi("A", &transfer_abi(true)).is_empty());
+
+        let collisions = registry.register_abi("B", &transfer_abi(true));
+        assert_eq!(collisions.len(), 1);
+        assert!(!collisions[0].ambiguous);
+        assert_eq!(
+            collisions[0].events[0].signature,
+            "Transfer(address,address,uint256)"
+        );
+
+        let collisions = registry.register_abi("C", &transfer_abi(false));
+        assert_eq!(collisions.len(), 1);
+        assert!(collisions[0].ambiguous);
+        assert_eq!(collisions[0].events.len(), 3);
+        assert_eq!(registry.collisions(), collisions);
+    }
+
+    #[test]
+    fn it_tracks_the_events_of_the_foldables() {
+        let registry = tracked_events();
+        assert_eq!(
+            registry.tracked_topics(INPUT_BOX),
+            [contracts::input_box::InputAddedFilter::signature()]
+        );
+        assert_eq!(
+            registry.tracked_topics(HISTORY),
+            [contracts::history::NewClaimToHistoryFilter::signature()]
+        );
+        assert_eq!(
+            registry.tracked_topics(CARTESI_DAPP_FACTORY),
+            [ApplicationCreatedFilter::signature()]
+        );
+        assert!(registry.tracked_topics(AUTHORITY).is_empty());
+        // A new release of the contracts must not make the logs ambiguous
+        assert!(registry.collisions().iter().all(|c| !c.ambiguous));
+    }
+
+    #[test]
+    fn it_checks_blooms_against_the_tracked_topics() {
+        let address = Address::repeat_byte(0xaa);
+        let topic = contracts::input_box::InputAddedFilter::signature();
+        let mut bloom = Bloom::default();
+        bloom.accrue(BloomInput::Raw(address.as_bytes()));
+        let registry = tracked_events();
+        assert!(!registry.bloom_may_contain(&bloom, INPUT_BOX, &address));
+        bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        assert!(registry.bloom_may_contain(&bloom, INPUT_BOX, &address));
+        assert!(!registry.bloom_may_contain(&bloom, HISTORY, &address));
+    }
+}
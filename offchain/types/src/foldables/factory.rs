@@ -0,0 +1,292 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use crate::{
+    metrics::{Delegate, FoldPath},
+    FoldableError, UserData,
+};
+
+use eth_state_fold::{
+    FoldMiddleware, Foldable, StateFoldEnvironment, SyncMiddleware,
+};
+use eth_state_fold_types::{
+    ethers::{
+        contract::LogMeta,
+        providers::Middleware,
+        types::{Address, TxHash, H256},
+    },
+    Block,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use im::Vector;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    cancellable, events, meta_consistent_with_block, skipped, timed_query,
+    tracked_events, QueryRange, ResumableSync,
+};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct DAppFactoryInitialState {
+    pub factory_address: Arc<Address>,
+    /// Only the DApps of this owner are tracked, if set
+    pub dapp_owner: Option<Arc<Address>>,
+    /// Only the DApps of this template hash are tracked, if set
+    pub template_hash: Option<H256>,
+}
+
+impl DAppFactoryInitialState {
+    /// Whether a DApp deployed by the factory is tracked
+    pub fn matches(&self, dapp_owner: &Address, template_hash: &H256) -> bool {
+        self.dapp_owner
+            .as_ref()
+            .map_or(true, |owner| **owner == *dapp_owner)
+            && self
+                .template_hash
+                .as_ref()
+                .map_or(true, |hash| hash == template_hash)
+    }
+}
+
+/// DApp deployed by the factory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeployedDApp {
+    pub dapp_address: Arc<Address>,
+    pub dapp_owner: Arc<Address>,
+    pub consensus_address: Arc<Address>,
+    pub template_hash: H256,
+    pub block_added: Arc<Block>,
+    pub tx_hash: Arc<TxHash>,
+}
+
+/// DApps deployed by a `CartesiDAppFactory` that match the criteria of the
+/// initial state, in deployment order.
+///
+/// Hosted nodes follow this state to start the pipelines of the DApps of
+/// their customers as they are deployed, instead of being configured with
+/// each DApp address. The owner and the template hash aren't indexed by the
+/// `ApplicationCreated` event, so every deployment of the factory is fetched
+/// and the criteria are checked here.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DAppFactory {
+    pub initial_state: Arc<DAppFactoryInitialState>,
+    pub dapps: Vector<Arc<DeployedDApp>>,
+}
+
+#[async_trait]
+impl Foldable for DAppFactory {
+    type InitialState = DAppFactoryInitialState;
+    type Error = FoldableError;
+    type UserData = Mutex<UserData>;
+
+    async fn sync<M: Middleware + 'static>(
+        initial_state: &Self::InitialState,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let initial_state = Arc::new(initial_state.clone());
+        let dapps = cancellable(
+            env,
+            Delegate::DAppFactory,
+            FoldPath::Sync,
+            block.number.as_u64(),
+            fetch_new_dapps(access, env, &initial_state, Vector::new(), None),
+        )
+        .await?;
+
+        Ok(Self {
+            initial_state,
+            dapps,
+        })
+    }
+
+    async fn fold<M: Middleware + 'static>(
+        previous_state: &Self,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<FoldMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let initial_state = Arc::clone(&previous_state.initial_state);
+
+        if !tracked_events().bloom_may_contain(
+            &block.logs_bloom,
+            events::CARTESI_DAPP_FACTORY,
+            &initial_state.factory_address,
+        ) {
+            skipped(env, Delegate::DAppFactory);
+            return Ok(previous_state.clone());
+        }
+
+        let dapps = cancellable(
+            env,
+            Delegate::DAppFactory,
+            FoldPath::Fold,
+            block.number.as_u64(),
+            fetch_new_dapps(
+                access,
+                env,
+                &initial_state,
+                previous_state.dapps.clone(),
+                None,
+            ),
+        )
+        .await?;
+
+        Ok(Self {
+            initial_state,
+            dapps,
+        })
+    }
+}
+
+#[async_trait]
+impl ResumableSync for DAppFactory {
+    async fn sync_range<M: Middleware + 'static>(
+        previous_state: &Self,
+        range: QueryRange,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let initial_state = Arc::clone(&previous_state.initial_state);
+        let dapps = cancellable(
+            env,
+            Delegate::DAppFactory,
+            FoldPath::Resume,
+            range.to_block,
+            fetch_new_dapps(
+                access,
+                env,
+                &initial_state,
+                previous_state.dapps.clone(),
+                Some(range),
+            ),
+        )
+        .await?;
+
+        Ok(Self {
+            initial_state,
+            dapps,
+        })
+    }
+}
+
+async fn fetch_new_dapps<M1: Middleware + 'static, M2: Middleware + 'static>(
+    provider: Arc<M1>,
+    env: &StateFoldEnvironment<M2, <DAppFactory as Foldable>::UserData>,
+    initial_state: &DAppFactoryInitialState,
+    mut dapps: Vector<Arc<DeployedDApp>>,
+    range: Option<QueryRange>,
+) -> Result<Vector<Arc<DeployedDApp>>, FoldableError> {
+    use contracts::cartesi_dapp_factory::CartesiDAppFactory;
+    let contract = CartesiDAppFactory::new(
+        *initial_state.factory_address,
+        Arc::clone(&provider),
+    );
+
+    // Retrieve `ApplicationCreated` events
+    let mut filter = contract.application_created_filter();
+    if let Some(range) = range {
+        filter = filter.from_block(range.from_block).to_block(range.to_block);
+    }
+    let created_events =
+        timed_query(env, Delegate::DAppFactory, filter.query_with_meta())
+            .await
+            .context("Error querying for application created events")?;
+
+    for (event, meta) in created_events {
+        let template_hash = H256(event.template_hash);
+        if !initial_state.matches(&event.dapp_owner, &template_hash) {
+            continue;
+        }
+        let dapp = DeployedDApp::build_dapp(env, event, meta).await?;
+        tracing::info!(
+            dapp_address = ?dapp.dapp_address,
+            dapp_owner = ?dapp.dapp_owner,
+            "Found new DApp deployed by the factory"
+        );
+        dapps.push_back(Arc::new(dapp));
+    }
+
+    Ok(dapps)
+}
+
+impl DeployedDApp {
+    async fn build_dapp<M: Middleware + 'static>(
+        env: &StateFoldEnvironment<M, <DAppFactory as Foldable>::UserData>,
+        event: contracts::cartesi_dapp_factory::ApplicationCreatedFilter,
+        meta: LogMeta,
+    ) -> Result<Self, FoldableError> {
+        let block =
+            env.block_with_hash(&meta.block_hash)
+                .await
+                .context(format!(
+                    "Could not query block `{:?}`",
+                    meta.block_hash
+                ))?;
+
+        meta_consistent_with_block(&meta, &block)?;
+
+        let mut user_data = env
+            .user_data()
+            .lock()
+            .expect("Mutex should never be poisoned");
+
+        Ok(Self {
+            dapp_address: user_data.get(event.application),
+            dapp_owner: user_data.get(event.dapp_owner),
+            consensus_address: user_data.get(event.consensus),
+            template_hash: H256(event.template_hash),
+            block_added: block,
+            tx_hash: Arc::new(meta.transaction_hash),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn initial_state(
+        dapp_owner: Option<Address>,
+        template_hash: Option<H256>,
+    ) -> DAppFactoryInitialState {
+        DAppFactoryInitialState {
+            factory_address: Arc::new(Address::repeat_byte(0xff)),
+            dapp_owner: dapp_owner.map(Arc::new),
+            template_hash,
+        }
+    }
+
+    #[test]
+    fn it_matches_the_deployments_against_the_criteria() {
+        let owner = Address::repeat_byte(1);
+        let template_hash = H256::repeat_byte(2);
+        let other_owner = Address::repeat_byte(3);
+        let other_hash = H256::repeat_byte(4);
+
+        let any = initial_state(None, None);
+        assert!(any.matches(&other_owner, &other_hash));
+
+        let by_owner = initial_state(Some(owner), None);
+        assert!(by_owner.matches(&owner, &other_hash));
+        assert!(!by_owner.matches(&other_owner, &template_hash));
+
+        let both = initial_state(Some(owner), Some(template_hash));
+        assert!(both.matches(&owner, &template_hash));
+        assert!(!both.matches(&owner, &other_hash));
+        assert!(!both.matches(&other_owner, &template_hash));
+    }
+
+    #[test]
+    fn it_reads_partial_criteria_from_json() {
+        let initial_state: DAppFactoryInitialState = serde_json::from_str(
+            r#"{"factory_address":"0xffffffffffffffffffffffffffffffffffffffff"}"#,
+        )
+        .unwrap();
+        assert_eq!(initial_state, self::initial_state(None, None));
+    }
+}
@@ -0,0 +1,374 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold::{
+    FoldMiddleware, Foldable, StateFoldEnvironment, SyncMiddleware,
+};
+use eth_state_fold_types::{
+    ethers::{
+        contract::EthEvent,
+        providers::Middleware,
+        types::{TransactionReceipt, H256, U256},
+    },
+    Block,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::BTreeSet, sync::Arc, sync::Mutex};
+
+use super::{InputBox, QueryRange, ResumableSync};
+use crate::UserData;
+
+/// An event of a block, as the transaction that emitted it and what it
+/// means to the delegate
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlockEvent {
+    pub tx_hash: H256,
+    pub description: String,
+}
+
+/// Foldables whose events in a block can be listed both from their states
+/// and from the receipts of the block
+pub trait ReceiptEvents {
+    /// Events the fold of a block added to the `previous` state
+    fn folded_events(previous: &Self, current: &Self) -> BTreeSet<BlockEvent>;
+
+    /// Events in the receipts of a block that the fold should have added to
+    /// the `previous` state
+    fn receipt_events(
+        previous: &Self,
+        receipts: &[TransactionReceipt],
+    ) -> BTreeSet<BlockEvent>;
+}
+
+/// Difference between the events folded from a block and the ones in its
+/// receipts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventGap {
+    /// In the receipts but not in the state
+    pub missing: Vec<BlockEvent>,
+    /// In the state but not in the receipts
+    pub unexpected: Vec<BlockEvent>,
+}
+
+impl EventGap {
+    pub fn between(
+        folded: &BTreeSet<BlockEvent>,
+        receipts: &BTreeSet<BlockEvent>,
+    ) -> Self {
+        Self {
+            missing: receipts.difference(folded).cloned().collect(),
+            unexpected: folded.difference(receipts).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+
+    fn describe(events: &[BlockEvent]) -> String {
+        events
+            .iter()
+            .map(|event| {
+                format!("{} in {:?}", event.description, event.tx_hash)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Checks the events folded from sampled blocks against the receipts of
+/// those blocks, which are fetched with `eth_getBlockReceipts` rather than
+/// with the log filters of the delegate, so decoder or filter bugs that
+/// silently skip events show up, such as with
+/// `run_server::<Audited<InputBox>>`.
+///
+/// The blocks are sampled at the interval set with
+/// `UserData::with_gap_check_interval`. A mismatch is logged with the
+/// events missing from the state and the ones not in the receipts, then the
+/// block is folded again, which recovers from providers that answered a log
+/// query with an incomplete response. The state is served as it, whether or
+/// not the gap persists.
+#[derive(Clone, Debug)]
+pub struct Audited<F> {
+    pub state: F,
+}
+
+impl<F: Serialize> Serialize for Audited<F> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.state.serialize(serializer)
+    }
+}
+
+impl<'de, F: Deserialize<'de>> Deserialize<'de> for Audited<F> {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        F::deserialize(deserializer).map(|state| Self { state })
+    }
+}
+
+#[async_trait]
+impl<F> Foldable for Audited<F>
+where
+    F: Foldable<UserData = Mutex<UserData>> + ReceiptEvents + 'static,
+{
+    type InitialState = F::InitialState;
+    type Error = F::Error;
+    type UserData = F::UserData;
+
+    async fn sync<M: Middleware + 'static>(
+        initial_state: &Self::InitialState,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let state = F::sync(initial_state, block, env, access).await?;
+        Ok(Self { state })
+    }
+
+    async fn fold<M: Middleware + 'static>(
+        previous_state: &Self,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<FoldMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let previous = &previous_state.state;
+        let state = F::fold(previous, block, env, Arc::clone(&access)).await?;
+
+        let block_number = block.number.as_u64();
+        let sampled = env
+            .user_data()
+            .lock()
+            .expect("Mutex should never be poisoned")
+            .samples_for_gaps(block_number);
+        if !sampled {
+            return Ok(Self { state });
+        }
+        let receipts = match access.get_block_receipts(block.number).await {
+            Ok(receipts) => receipts,
+            Err(e) => {
+                tracing::warn!(
+                    block_number,
+                    block_hash = ?block.hash,
+                    "failed to fetch the receipts to check for event gaps: {}",
+                    e
+                );
+                return Ok(Self { state });
+            }
+        };
+        let expected = F::receipt_events(previous, &receipts);
+        let gap =
+            EventGap::between(&F::folded_events(previous, &state), &expected);
+        if gap.is_empty() {
+            return Ok(Self { state });
+        }
+        tracing::warn!(
+            block_number,
+            block_hash = ?block.hash,
+            missing = EventGap::describe(&gap.missing),
+            unexpected = EventGap::describe(&gap.unexpected),
+            "folded events differ from the block receipts; folding the block again"
+        );
+
+        let state = F::fold(previous, block, env, access).await?;
+        let gap =
+            EventGap::between(&F::folded_events(previous, &state), &expected);
+        if gap.is_empty() {
+            tracing::info!(
+                block_number,
+                block_hash = ?block.hash,
+                "folding the block again filled the event gap"
+            );
+        } else {
+            tracing::error!(
+                block_number,
+                block_hash = ?block.hash,
+                missing = EventGap::describe(&gap.missing),
+                unexpected = EventGap::describe(&gap.unexpected),
+                "folded events still differ from the block receipts"
+            );
+        }
+        Ok(Self { state })
+    }
+}
+
+/// Only the folds are audited, so resumed syncs aren't checked either
+#[async_trait]
+impl<F> ResumableSync for Audited<F>
+where
+    F: Foldable<UserData = Mutex<UserData>>
+        + ReceiptEvents
+        + ResumableSync
+        + 'static,
+{
+    async fn sync_range<M: Middleware + 'static>(
+        previous_state: &Self,
+        range: QueryRange,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let state =
+            F::sync_range(&previous_state.state, range, env, access).await?;
+        Ok(Self { state })
+    }
+}
+
+impl ReceiptEvents for InputBox {
+    fn folded_events(previous: &Self, current: &Self) -> BTreeSet<BlockEvent> {
+        let mut events = BTreeSet::new();
+        for (dapp, input_box) in current.dapp_input_boxes.iter() {
+            let known = previous
+                .dapp_input_boxes
+                .get(dapp)
+                .map_or(0, |input_box| input_box.inputs.len());
+            for (index, input) in
+                input_box.inputs.iter().enumerate().skip(known)
+            {
+                events.insert(BlockEvent {
+                    tx_hash: *input.tx_hash,
+                    description: input_added(&H256::from(**dapp), index.into()),
+                });
+            }
+        }
+        events
+    }
+
+    fn receipt_events(
+        previous: &Self,
+        receipts: &[TransactionReceipt],
+    ) -> BTreeSet<BlockEvent> {
+        let signature = contracts::input_box::InputAddedFilter::signature();
+        let dapp = H256::from(*previous.dapp_address);
+        receipts
+            .iter()
+            .flat_map(|receipt| &receipt.logs)
+            .filter(|log| {
+                log.address == *previous.input_box_address
+                    && log.removed != Some(true)
+                    && log.topics.len() == 3
+                    && log.topics[0] == signature
+                    && log.topics[1] == dapp
+            })
+            .map(|log| BlockEvent {
+                tx_hash: log.transaction_hash.unwrap_or_default(),
+                description: input_added(
+                    &log.topics[1],
+                    U256::from_big_endian(log.topics[2].as_bytes()),
+                ),
+            })
+            .collect()
+    }
+}
+
+fn input_added(dapp: &H256, index: U256) -> String {
+    format!("InputAdded({:?}, {})", dapp, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foldables::{push_input, Input};
+    use eth_state_fold_types::ethers::types::{Address, Bloom, Log};
+    use im::HashMap;
+
+    fn dapp() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    fn input_box_address() -> Address {
+        Address::repeat_byte(0xbb)
+    }
+
+    fn input_box(tx_hashes: &[u8]) -> InputBox {
+        let mut dapp_input_boxes = HashMap::new();
+        for (index, tx_hash) in tx_hashes.iter().enumerate() {
+            let input = Input {
+                sender: Arc::new(Address::repeat_byte(0x11)),
+                payload: vec![],
+                block_added: Arc::new(Block {
+                    hash: H256::repeat_byte(0x22),
+                    number: 7.into(),
+                    parent_hash: H256::repeat_byte(0x33),
+                    timestamp: 1000.into(),
+                    logs_bloom: Bloom::default(),
+                }),
+                dapp: Arc::new(dapp()),
+                tx_hash: Arc::new(H256::repeat_byte(*tx_hash)),
+            };
+            push_input(&mut dapp_input_boxes, index.into(), Arc::new(input))
+                .unwrap();
+        }
+        InputBox {
+            dapp_address: Arc::new(dapp()),
+            input_box_address: Arc::new(input_box_address()),
+            dapp_input_boxes: Arc::new(dapp_input_boxes),
+        }
+    }
+
+    fn receipt(
+        tx_hash: u8,
+        address: Address,
+        index: u64,
+    ) -> TransactionReceipt {
+        let tx_hash = H256::repeat_byte(tx_hash);
+        let mut index_topic = H256::zero();
+        U256::from(index).to_big_endian(index_topic.as_bytes_mut());
+        TransactionReceipt {
+            transaction_hash: tx_hash,
+            logs: vec![Log {
+                address,
+                topics: vec![
+                    contracts::input_box::InputAddedFilter::signature(),
+                    H256::from(dapp()),
+                    index_topic,
+                ],
+                transaction_hash: Some(tx_hash),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_matches_the_folded_inputs_with_the_receipts() {
+        let previous = input_box(&[1]);
+        let current = input_box(&[1, 2, 3]);
+        let receipts = [
+            receipt(2, input_box_address(), 1),
+            receipt(3, input_box_address(), 2),
+            // Emitted by another contract
+            receipt(4, Address::repeat_byte(0xcc), 3),
+        ];
+        let folded = InputBox::folded_events(&previous, &current);
+        let expected = InputBox::receipt_events(&previous, &receipts);
+        assert_eq!(folded.len(), 2);
+        assert!(EventGap::between(&folded, &expected).is_empty());
+    }
+
+    #[test]
+    fn it_reports_the_skipped_events() {
+        let previous = input_box(&[1]);
+        let current = input_box(&[1, 2]);
+        let receipts = [
+            receipt(2, input_box_address(), 1),
+            receipt(3, input_box_address(), 2),
+        ];
+        let gap = EventGap::between(
+            &InputBox::folded_events(&previous, &current),
+            &InputBox::receipt_events(&previous, &receipts),
+        );
+        assert!(gap.unexpected.is_empty());
+        assert_eq!(
+            gap.missing,
+            vec![BlockEvent {
+                tx_hash: H256::repeat_byte(3),
+                description: input_added(&H256::from(dapp()), 2.into()),
+            }]
+        );
+    }
+}
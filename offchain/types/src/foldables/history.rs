@@ -0,0 +1,395 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use crate::{
+    metrics::{Delegate, FoldPath},
+    FoldableError, UserData,
+};
+
+use eth_state_fold::{
+    utils as fold_utils, FoldMiddleware, Foldable, StateFoldEnvironment,
+    SyncMiddleware,
+};
+use eth_state_fold_types::{
+    ethers::{
+        contract::LogMeta,
+        providers::Middleware,
+        types::{Address, TxHash, H256},
+    },
+    Block,
+};
+
+use anyhow::{ensure, Context};
+use async_trait::async_trait;
+use im::{HashMap, Vector};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    cancellable, events, meta_consistent_with_block, skipped, timed_query,
+    tracked_events, QueryRange, ResumableSync,
+};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct HistoryInitialState {
+    pub dapp_address: Arc<Address>,
+    pub history_address: Arc<Address>,
+}
+
+/// Claim of an epoch submitted to the History
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claim {
+    pub epoch_hash: H256,
+    pub first_index: u128,
+    pub last_index: u128,
+    pub block_added: Arc<Block>,
+    pub tx_hash: Arc<TxHash>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DAppClaims {
+    /// Claims in submission order; the position of a claim is its index in
+    /// the History, which is the context of the proofs of its outputs
+    pub claims: Vector<Arc<Claim>>,
+}
+
+impl DAppClaims {
+    /// Index and claim of the epoch that contains the input
+    pub fn claim_for_input(
+        &self,
+        input_index: u128,
+    ) -> Option<(usize, &Arc<Claim>)> {
+        self.claims.iter().enumerate().find(|(_, claim)| {
+            claim.first_index <= input_index && input_index <= claim.last_index
+        })
+    }
+}
+
+/// Claims of the v1 History contract, for the DApps whose epochs are claimed
+/// on an InputBox-era consensus.
+///
+/// Only the claims of `dapp_address` are fetched; the filter goes to the node
+/// as a topic, so other DApps sharing the History cost nothing. Reorgs need
+/// no special handling: the state is kept per block, so a claim removed by a
+/// reorg is absent from the state of the blocks of the new chain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct History {
+    pub dapp_address: Arc<Address>,
+    pub history_address: Arc<Address>,
+    #[serde(serialize_with = "crate::canonical::sorted_map")]
+    pub dapp_claims: Arc<HashMap<Arc<Address>, Arc<DAppClaims>>>,
+}
+
+impl History {
+    /// Claims of the DApp, empty if it was never claimed
+    pub fn claims(&self, dapp: &Address) -> Arc<DAppClaims> {
+        self.dapp_claims.get(dapp).cloned().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Foldable for History {
+    type InitialState = HistoryInitialState;
+    type Error = FoldableError;
+    type UserData = Mutex<UserData>;
+
+    async fn sync<M: Middleware + 'static>(
+        initial_state: &Self::InitialState,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let dapp_address = Arc::clone(&initial_state.dapp_address);
+        let history_address = Arc::clone(&initial_state.history_address);
+
+        Ok(Self {
+            dapp_claims: cancellable(
+                env,
+                Delegate::History,
+                FoldPath::Sync,
+                block.number.as_u64(),
+                updated_claims(
+                    None,
+                    access,
+                    env,
+                    &history_address,
+                    &dapp_address,
+                    None,
+                ),
+            )
+            .await?,
+            dapp_address,
+            history_address,
+        })
+    }
+
+    async fn fold<M: Middleware + 'static>(
+        previous_state: &Self,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<FoldMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let dapp_address = Arc::clone(&previous_state.dapp_address);
+        let history_address = Arc::clone(&previous_state.history_address);
+
+        if !tracked_events().bloom_may_contain(
+            &block.logs_bloom,
+            events::HISTORY,
+            &history_address,
+        ) || !fold_utils::contains_topic(&block.logs_bloom, &*dapp_address)
+        {
+            skipped(env, Delegate::History);
+            return Ok(previous_state.clone());
+        }
+
+        Ok(Self {
+            dapp_claims: cancellable(
+                env,
+                Delegate::History,
+                FoldPath::Fold,
+                block.number.as_u64(),
+                updated_claims(
+                    Some(&previous_state.dapp_claims),
+                    access,
+                    env,
+                    &history_address,
+                    &dapp_address,
+                    None,
+                ),
+            )
+            .await?,
+            dapp_address,
+            history_address,
+        })
+    }
+}
+
+#[async_trait]
+impl ResumableSync for History {
+    async fn sync_range<M: Middleware + 'static>(
+        previous_state: &Self,
+        range: QueryRange,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let dapp_address = Arc::clone(&previous_state.dapp_address);
+        let history_address = Arc::clone(&previous_state.history_address);
+
+        Ok(Self {
+            dapp_claims: cancellable(
+                env,
+                Delegate::History,
+                FoldPath::Resume,
+                range.to_block,
+                updated_claims(
+                    Some(&previous_state.dapp_claims),
+                    access,
+                    env,
+                    &history_address,
+                    &dapp_address,
+                    Some(range),
+                ),
+            )
+            .await?,
+            dapp_address,
+            history_address,
+        })
+    }
+}
+
+async fn updated_claims<M1: Middleware + 'static, M2: Middleware + 'static>(
+    previous_claims: Option<&HashMap<Arc<Address>, Arc<DAppClaims>>>,
+    provider: Arc<M1>,
+    env: &StateFoldEnvironment<M2, <History as Foldable>::UserData>,
+    contract_address: &Address,
+    dapp_address: &Address,
+    range: Option<QueryRange>,
+) -> Result<Arc<HashMap<Arc<Address>, Arc<DAppClaims>>>, FoldableError> {
+    let mut dapp_claims = previous_claims.cloned().unwrap_or_default();
+
+    let new_claims = fetch_all_new_claims(
+        provider,
+        env,
+        contract_address,
+        dapp_address,
+        range,
+    )
+    .await?;
+
+    for (dapp, claim) in new_claims {
+        push_claim(&mut dapp_claims, dapp, Arc::new(claim))?;
+    }
+
+    Ok(Arc::new(dapp_claims))
+}
+
+/// Appends a claim to the claims of its DApp.
+///
+/// The History only accepts a claim that doesn't end before it starts and
+/// that starts right after the previous one of the DApp, so a claim that
+/// breaks this means the logs are malformed or incomplete, and the fold
+/// fails instead of storing it.
+pub fn push_claim(
+    dapp_claims: &mut HashMap<Arc<Address>, Arc<DAppClaims>>,
+    dapp: Arc<Address>,
+    claim: Arc<Claim>,
+) -> Result<(), anyhow::Error> {
+    let mut claims = dapp_claims
+        .get(&dapp)
+        .map(|claims| (**claims).clone())
+        .unwrap_or_default();
+    ensure!(
+        claim.first_index <= claim.last_index,
+        "Claim of inputs [{}, {}] of DApp `{:?}` ends before it starts",
+        claim.first_index,
+        claim.last_index,
+        dapp
+    );
+    if let Some(last) = claims.claims.last() {
+        let expected = last.last_index.checked_add(1);
+        ensure!(
+            Some(claim.first_index) == expected,
+            "Claim of inputs [{}, {}] of DApp `{:?}` should start at {:?}",
+            claim.first_index,
+            claim.last_index,
+            dapp,
+            expected
+        );
+    }
+    claims.claims.push_back(claim);
+    dapp_claims.insert(dapp, Arc::new(claims));
+    Ok(())
+}
+
+async fn fetch_all_new_claims<
+    M1: Middleware + 'static,
+    M2: Middleware + 'static,
+>(
+    provider: Arc<M1>,
+    env: &StateFoldEnvironment<M2, <History as Foldable>::UserData>,
+    contract_address: &Address,
+    dapp_address: &Address,
+    range: Option<QueryRange>,
+) -> Result<Vec<(Arc<Address>, Claim)>, FoldableError> {
+    use contracts::history::History as HistoryContract;
+    let contract =
+        HistoryContract::new(*contract_address, Arc::clone(&provider));
+
+    // Retrieve `NewClaimToHistory` events
+    let mut filter =
+        contract.new_claim_to_history_filter().topic1(*dapp_address);
+    if let Some(range) = range {
+        filter = filter.from_block(range.from_block).to_block(range.to_block);
+    }
+    let claim_events =
+        timed_query(env, Delegate::History, filter.query_with_meta())
+            .await
+            .context("Error querying for new claim events")?;
+
+    let mut claims = Vec::with_capacity(claim_events.len());
+    for (event, meta) in claim_events {
+        claims.push(Claim::build_claim(env, event, meta).await?);
+    }
+
+    Ok(claims)
+}
+
+impl Claim {
+    async fn build_claim<M: Middleware + 'static>(
+        env: &StateFoldEnvironment<M, <History as Foldable>::UserData>,
+        event: contracts::history::NewClaimToHistoryFilter,
+        meta: LogMeta,
+    ) -> Result<(Arc<Address>, Self), FoldableError> {
+        let block =
+            env.block_with_hash(&meta.block_hash)
+                .await
+                .context(format!(
+                    "Could not query block `{:?}`",
+                    meta.block_hash
+                ))?;
+
+        meta_consistent_with_block(&meta, &block)?;
+
+        let dapp = env
+            .user_data()
+            .lock()
+            .expect("Mutex should never be poisoned")
+            .get(event.dapp);
+
+        Ok((
+            dapp,
+            Self {
+                epoch_hash: H256(event.claim.epoch_hash),
+                first_index: event.claim.first_index,
+                last_index: event.claim.last_index,
+                block_added: block,
+                tx_hash: Arc::new(meta.transaction_hash),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethers::types::Bloom;
+
+    fn claim(first_index: u128, last_index: u128) -> Arc<Claim> {
+        Arc::new(Claim {
+            epoch_hash: H256::repeat_byte(first_index as u8),
+            first_index,
+            last_index,
+            block_added: Arc::new(Block {
+                hash: H256::repeat_byte(0x22),
+                number: 7.into(),
+                parent_hash: H256::repeat_byte(0x33),
+                timestamp: 1000.into(),
+                logs_bloom: Bloom::default(),
+            }),
+            tx_hash: Arc::new(H256::repeat_byte(0x44)),
+        })
+    }
+
+    #[test]
+    fn it_maps_inputs_to_claims() {
+        let dapp = Address::repeat_byte(0xaa);
+        let claims = DAppClaims {
+            claims: im::vector![claim(0, 4), claim(5, 5), claim(6, 9)],
+        };
+        let history = History {
+            dapp_address: Arc::new(dapp),
+            history_address: Arc::new(Address::repeat_byte(0xbb)),
+            dapp_claims: Arc::new(HashMap::unit(
+                Arc::new(dapp),
+                Arc::new(claims),
+            )),
+        };
+
+        let claims = history.claims(&dapp);
+        let (index, found) = claims.claim_for_input(5).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(found.epoch_hash, H256::repeat_byte(5));
+        assert_eq!(claims.claim_for_input(9).unwrap().0, 2);
+        assert!(claims.claim_for_input(10).is_none());
+        assert!(history.claims(&Address::zero()).claims.is_empty());
+    }
+
+    #[test]
+    fn it_only_pushes_contiguous_claims() {
+        let dapp = Arc::new(Address::repeat_byte(0xaa));
+        let mut dapp_claims = HashMap::new();
+        push_claim(&mut dapp_claims, dapp.clone(), claim(0, 4)).unwrap();
+        push_claim(&mut dapp_claims, dapp.clone(), claim(5, 5)).unwrap();
+
+        assert!(
+            push_claim(&mut dapp_claims, dapp.clone(), claim(5, 7)).is_err()
+        );
+        assert!(
+            push_claim(&mut dapp_claims, dapp.clone(), claim(7, 9)).is_err()
+        );
+        assert!(
+            push_claim(&mut dapp_claims, dapp.clone(), claim(6, 5)).is_err()
+        );
+        assert_eq!(dapp_claims[&dapp].claims.len(), 2);
+    }
+}
@@ -0,0 +1,317 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold::{
+    FoldMiddleware, Foldable, StateFoldEnvironment, SyncMiddleware,
+};
+use eth_state_fold_types::{ethers::providers::Middleware, Block};
+
+use async_trait::async_trait;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::{fmt::Display, sync::Arc};
+
+/// Folds a secondary implementation of a delegate next to the primary one
+/// and reports where their states diverge, so a refactored delegate can be
+/// checked against live blocks before it replaces the original, such as
+/// with `run_server::<Shadow<InputBox, RefactoredInputBox>>`.
+///
+/// Only the primary state is served: `Shadow` serializes as it, and the
+/// errors of the secondary are logged instead of returned. A failed
+/// secondary stays off until the state is synced again.
+///
+/// The states are compared through their JSON form, which is what the
+/// clients receive; a divergence is logged when it first shows up and when
+/// the states match again, rather than on every block.
+#[derive(Clone, Debug)]
+pub struct Shadow<P, S> {
+    pub primary: P,
+    pub secondary: Option<Arc<S>>,
+    /// First difference between the states, as of the last block
+    pub divergence: Option<String>,
+}
+
+impl<P: Serialize, S> Serialize for Shadow<P, S> {
+    fn serialize<Z: Serializer>(
+        &self,
+        serializer: Z,
+    ) -> Result<Z::Ok, Z::Error> {
+        self.primary.serialize(serializer)
+    }
+}
+
+#[async_trait]
+impl<P, S> Foldable for Shadow<P, S>
+where
+    P: Foldable + Serialize + 'static,
+    S: Foldable<InitialState = P::InitialState, UserData = P::UserData>
+        + Serialize
+        + 'static,
+{
+    type InitialState = P::InitialState;
+    type Error = P::Error;
+    type UserData = P::UserData;
+
+    async fn sync<M: Middleware + 'static>(
+        initial_state: &Self::InitialState,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<SyncMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let primary =
+            P::sync(initial_state, block, env, Arc::clone(&access)).await?;
+        let secondary = S::sync(initial_state, block, env, access).await;
+        Ok(Self::compare(primary, Some(secondary), None, block))
+    }
+
+    async fn fold<M: Middleware + 'static>(
+        previous_state: &Self,
+        block: &Block,
+        env: &StateFoldEnvironment<M, Self::UserData>,
+        access: Arc<FoldMiddleware<M>>,
+    ) -> Result<Self, Self::Error> {
+        let primary =
+            P::fold(&previous_state.primary, block, env, Arc::clone(&access))
+                .await?;
+        let secondary = match &previous_state.secondary {
+            Some(secondary) => {
+                Some(S::fold(secondary, block, env, access).await)
+            }
+            None => None,
+        };
+        Ok(Self::compare(
+            primary,
+            secondary,
+            previous_state.divergence.as_deref(),
+            block,
+        ))
+    }
+}
+
+impl<P: Serialize, S: Serialize> Shadow<P, S> {
+    fn compare<E: Display>(
+        primary: P,
+        secondary: Option<Result<S, E>>,
+        previous_divergence: Option<&str>,
+        block: &Block,
+    ) -> Self {
+        let block_number = block.number.as_u64();
+        let secondary = match secondary {
+            Some(Ok(secondary)) => Some(Arc::new(secondary)),
+            Some(Err(e)) => {
+                tracing::error!(
+                    block_number,
+                    block_hash = ?block.hash,
+                    "shadow delegate failed and is off until the next sync: {}",
+                    e
+                );
+                None
+            }
+            None => None,
+        };
+        let divergence = secondary
+            .as_ref()
+            .and_then(|secondary| divergence(&primary, secondary.as_ref()));
+        match (&divergence, previous_divergence) {
+            (Some(divergence), previous)
+                if previous != Some(divergence.as_str()) =>
+            {
+                tracing::warn!(
+                    block_number,
+                    block_hash = ?block.hash,
+                    "shadow state diverged from the primary: {}",
+                    divergence
+                );
+            }
+            (None, Some(_)) if secondary.is_some() => {
+                tracing::info!(
+                    block_number,
+                    block_hash = ?block.hash,
+                    "shadow state matches the primary again"
+                );
+            }
+            _ => {}
+        }
+        Self {
+            primary,
+            secondary,
+            divergence,
+        }
+    }
+}
+
+/// First difference between the JSON forms of the states, if any
+fn divergence<P: Serialize, S: Serialize>(
+    primary: &P,
+    secondary: &S,
+) -> Option<String> {
+    let primary = match serde_json::to_value(primary) {
+        Ok(primary) => primary,
+        Err(e) => {
+            return Some(format!("primary state doesn't serialize: {}", e))
+        }
+    };
+    let secondary = match serde_json::to_value(secondary) {
+        Ok(secondary) => secondary,
+        Err(e) => {
+            return Some(format!("shadow state doesn't serialize: {}", e))
+        }
+    };
+    first_difference(".", &primary, &secondary)
+}
+
+fn first_difference(
+    path: &str,
+    primary: &Value,
+    secondary: &Value,
+) -> Option<String> {
+    match (primary, secondary) {
+        (Value::Object(primary), Value::Object(secondary)) => {
+            let mut keys: Vec<_> =
+                primary.keys().chain(secondary.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let path = child_path(path, key);
+                match (primary.get(key), secondary.get(key)) {
+                    (Some(primary), Some(secondary)) => {
+                        first_difference(&path, primary, secondary)
+                    }
+                    (Some(_), None) => {
+                        Some(format!("`{}` is missing from the shadow", path))
+                    }
+                    (None, _) => {
+                        Some(format!("`{}` is missing from the primary", path))
+                    }
+                }
+            })
+        }
+        (Value::Array(primary_items), Value::Array(secondary_items)) => {
+            primary_items
+                .iter()
+                .zip(secondary_items)
+                .enumerate()
+                .find_map(|(index, (primary, secondary))| {
+                    let path = format!("{}[{}]", parent_path(path), index);
+                    first_difference(&path, primary, secondary)
+                })
+                .or_else(|| {
+                    (primary_items.len() != secondary_items.len()).then(|| {
+                        format!(
+                            "`{}` has {} items in the primary but {} in the shadow",
+                            path,
+                            primary_items.len(),
+                            secondary_items.len()
+                        )
+                    })
+                })
+        }
+        _ if primary != secondary => Some(format!(
+            "`{}` is {} in the primary but {} in the shadow",
+            path, primary, secondary
+        )),
+        _ => None,
+    }
+}
+
+/// Path of a field in the jq-style syntax of the state inspector
+fn child_path(path: &str, key: &str) -> String {
+    let parent = parent_path(path);
+    let is_identifier = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_identifier {
+        format!("{}.{}", parent, key)
+    } else {
+        format!("{}[{:?}]", parent, key)
+    }
+}
+
+/// The root `.` is dropped so the path of its children doesn't repeat it
+fn parent_path(path: &str) -> &str {
+    path.trim_end_matches('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth_state_fold_types::ethers::types::{Bloom, H256};
+    use serde_json::json;
+
+    fn block(number: u64) -> Block {
+        Block {
+            hash: H256::repeat_byte(number as u8),
+            number: number.into(),
+            parent_hash: H256::repeat_byte(0),
+            timestamp: 1000.into(),
+            logs_bloom: Bloom::default(),
+        }
+    }
+
+    #[test]
+    fn it_finds_the_first_difference() {
+        let primary = json!({
+            "dapp_input_boxes": {"0xab": {"inputs": [1, 2, 3]}},
+            "dapp_address": "0x01",
+        });
+        assert_eq!(first_difference(".", &primary, &primary), None);
+
+        let secondary = json!({
+            "dapp_input_boxes": {"0xab": {"inputs": [1, 4, 3]}},
+            "dapp_address": "0x02",
+        });
+        assert_eq!(
+            first_difference(".", &primary, &secondary).unwrap(),
+            "`.dapp_address` is \"0x01\" in the primary but \"0x02\" in the shadow"
+        );
+
+        let secondary = json!({
+            "dapp_input_boxes": {"0xab": {"inputs": [1, 2]}},
+            "dapp_address": "0x01",
+        });
+        assert_eq!(
+            first_difference(".", &primary, &secondary).unwrap(),
+            "`.dapp_input_boxes[\"0xab\"].inputs` has 3 items in the primary but 2 in the shadow"
+        );
+
+        let secondary = json!({"dapp_input_boxes": {}, "dapp_address": "0x01"});
+        assert_eq!(
+            first_difference(".", &primary, &secondary).unwrap(),
+            "`.dapp_input_boxes[\"0xab\"]` is missing from the shadow"
+        );
+    }
+
+    #[test]
+    fn it_serves_the_primary_state() {
+        let shadow = Shadow::compare(
+            json!({"inputs": [1]}),
+            Some(Ok::<_, String>(json!({"inputs": [2]}))),
+            None,
+            &block(1),
+        );
+        assert_eq!(
+            serde_json::to_value(&shadow).unwrap(),
+            json!({"inputs": [1]})
+        );
+        assert_eq!(
+            shadow.divergence.unwrap(),
+            "`.inputs[0]` is 1 in the primary but 2 in the shadow"
+        );
+    }
+
+    #[test]
+    fn it_turns_off_a_failed_secondary() {
+        let shadow = Shadow::<Value, Value>::compare(
+            json!({"inputs": [1]}),
+            Some(Err("no provider")),
+            Some("`.inputs[0]` is 1 in the primary but 2 in the shadow"),
+            &block(2),
+        );
+        assert!(shadow.secondary.is_none());
+        assert!(shadow.divergence.is_none());
+        assert_eq!(
+            serde_json::to_value(&shadow).unwrap(),
+            json!({"inputs": [1]})
+        );
+    }
+}
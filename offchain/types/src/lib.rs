@@ -1,11 +1,14 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+pub mod api;
 pub mod blockchain_config;
+pub mod canonical;
 pub mod error;
 pub use error::*;
 
 pub mod foldables;
+pub mod metrics;
 
 pub mod user_data;
 pub mod utils;
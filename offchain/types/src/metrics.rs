@@ -0,0 +1,167 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Metrics of the syncs and folds of the foldables, labeled by delegate,
+//! which the state server registers and serves
+
+use prometheus_client::{
+    encoding::EncodeLabelSet,
+    metrics::{
+        counter::Counter,
+        family::Family,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use std::time::Duration;
+
+const METRICS_PREFIX: &str = "cartesi_rollups_state_fold";
+
+fn prefixed_metrics(name: &str) -> String {
+    format!("{}_{}", METRICS_PREFIX, name)
+}
+
+/// Foldable whose events are queried
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delegate {
+    InputBox,
+    History,
+    DAppFactory,
+}
+
+impl Delegate {
+    fn as_str(self) -> &'static str {
+        match self {
+            Delegate::InputBox => "input_box",
+            Delegate::History => "history",
+            Delegate::DAppFactory => "dapp_factory",
+        }
+    }
+}
+
+/// How a state was brought up to a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoldPath {
+    /// Synced from genesis
+    Sync,
+    /// Synced from a checkpoint
+    Resume,
+    /// Folded from the state of the parent block
+    Fold,
+    /// Folded without querying, since the bloom filter of the block rules
+    /// out its events
+    Skip,
+}
+
+impl FoldPath {
+    fn as_str(self) -> &'static str {
+        match self {
+            FoldPath::Sync => "sync",
+            FoldPath::Resume => "resume",
+            FoldPath::Fold => "fold",
+            FoldPath::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct DelegateLabels {
+    delegate: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PathLabels {
+    delegate: &'static str,
+    path: &'static str,
+}
+
+type Histograms<L> = Family<L, Histogram, fn() -> Histogram>;
+
+fn duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.01, 2.0, 14))
+}
+
+#[derive(Clone, Debug)]
+pub struct FoldMetrics {
+    steps: Family<PathLabels, Counter>,
+    step_duration: Histograms<PathLabels>,
+    event_query_duration: Histograms<DelegateLabels>,
+}
+
+impl Default for FoldMetrics {
+    fn default() -> Self {
+        Self {
+            steps: Family::default(),
+            step_duration: Family::new_with_constructor(duration_histogram),
+            event_query_duration: Family::new_with_constructor(
+                duration_histogram,
+            ),
+        }
+    }
+}
+
+impl FoldMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        registry.register(
+            prefixed_metrics("steps"),
+            "Blocks each delegate was synced or folded to, by path",
+            self.steps.clone(),
+        );
+        registry.register(
+            prefixed_metrics("step_duration_seconds"),
+            "Time taken to sync or fold each delegate to a block, by path",
+            self.step_duration.clone(),
+        );
+        registry.register(
+            prefixed_metrics("event_query_duration_seconds"),
+            "Time taken by the event queries of each delegate",
+            self.event_query_duration.clone(),
+        );
+    }
+
+    pub fn step(&self, delegate: Delegate, path: FoldPath, elapsed: Duration) {
+        let labels = PathLabels {
+            delegate: delegate.as_str(),
+            path: path.as_str(),
+        };
+        self.steps.get_or_create(&labels).inc();
+        self.step_duration
+            .get_or_create(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn event_query(&self, delegate: Delegate, elapsed: Duration) {
+        self.event_query_duration
+            .get_or_create(&DelegateLabels {
+                delegate: delegate.as_str(),
+            })
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+
+    #[test]
+    fn it_labels_the_steps_by_delegate_and_path() {
+        let metrics = FoldMetrics::default();
+        let mut registry = Registry::default();
+        metrics.register(&mut registry);
+        metrics.step(Delegate::History, FoldPath::Fold, Duration::ZERO);
+        metrics.step(Delegate::History, FoldPath::Fold, Duration::ZERO);
+        metrics.step(Delegate::InputBox, FoldPath::Skip, Duration::ZERO);
+
+        let mut encoded = String::new();
+        encode(&mut encoded, &registry).unwrap();
+        assert!(encoded.contains(
+            "cartesi_rollups_state_fold_steps_total\
+            {delegate=\"history\",path=\"fold\"} 2"
+        ));
+        assert!(encoded.contains(
+            "cartesi_rollups_state_fold_steps_total\
+            {delegate=\"input_box\",path=\"skip\"} 1"
+        ));
+    }
+}
@@ -1,16 +1,74 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use eth_state_fold_types::ethers::types::Address;
+use eth_state_fold_types::ethers::{signers::LocalWallet, types::Address};
 
-use std::{collections::HashSet, sync::Arc};
+use crate::metrics::FoldMetrics;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Key of a sync or fold in flight: the number of the block it folds up to
+/// and a sequence number
+pub type InFlightKey = (u64, u64);
 
 #[derive(Debug, Default)]
 pub struct UserData {
     addresses: HashSet<Arc<Address>>,
+    /// Tokens of the syncs and folds in flight, by the number of the block
+    /// they fold up to, which abort them once cancelled
+    in_flight: BTreeMap<InFlightKey, CancellationToken>,
+    next_in_flight: u64,
+    /// Every how many blocks the folded events are checked against the
+    /// block receipts; zero turns the checks off
+    gap_check_interval: u64,
+    /// Where the states are checkpointed, so syncs resume from them
+    checkpoint_dir: Option<PathBuf>,
+    /// Every how many blocks the folded states are checkpointed
+    checkpoint_interval: u64,
+    /// How many of the last checkpoints of each state are kept, to roll
+    /// back to when the later ones are reorged out
+    checkpoint_history: usize,
+    /// Key of the operator that signs the manifests of the checkpoints
+    checkpoint_signer: Option<LocalWallet>,
+    metrics: FoldMetrics,
 }
 
 impl UserData {
+    pub fn with_gap_check_interval(mut self, blocks: u64) -> Self {
+        self.gap_check_interval = blocks;
+        self
+    }
+
+    pub fn with_checkpoints(
+        mut self,
+        dir: PathBuf,
+        blocks: u64,
+        history: usize,
+    ) -> Self {
+        self.checkpoint_dir = Some(dir);
+        self.checkpoint_interval = blocks;
+        self.checkpoint_history = history;
+        self
+    }
+
+    /// Signs the manifests of the checkpoints with the key, and only resumes
+    /// from checkpoints whose manifest it signed
+    pub fn with_checkpoint_signer(mut self, wallet: LocalWallet) -> Self {
+        self.checkpoint_signer = Some(wallet);
+        self
+    }
+
+    /// Records the syncs, folds and event queries in `metrics`
+    pub fn with_metrics(mut self, metrics: FoldMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
     pub fn get(&mut self, address: Address) -> Arc<Address> {
         // Method `get_or_insert` of HashSet is still unstable
         match self.addresses.get(&address) {
@@ -22,4 +80,123 @@ impl UserData {
             }
         }
     }
+
+    /// Tracks a sync or fold of the chain up to `block_number`, returning its
+    /// key, to finish it with, and the token that aborts it once cancelled
+    pub fn start_in_flight(
+        &mut self,
+        block_number: u64,
+    ) -> (InFlightKey, CancellationToken) {
+        let key = (block_number, self.next_in_flight);
+        self.next_in_flight += 1;
+        let token = CancellationToken::new();
+        self.in_flight.insert(key, token.clone());
+        (key, token)
+    }
+
+    pub fn finish_in_flight(&mut self, key: InFlightKey) {
+        self.in_flight.remove(&key);
+    }
+
+    /// Aborts the syncs and folds in flight, such as on shutdown. The ones
+    /// started afterwards run as usual.
+    pub fn cancel_in_flight(&mut self) {
+        for token in std::mem::take(&mut self.in_flight).into_values() {
+            token.cancel();
+        }
+    }
+
+    /// Aborts the syncs and folds in flight up to `block_number` or a later
+    /// block, which were reorged out, and returns how many. The ones up to
+    /// earlier blocks are still in the chain and keep running.
+    pub fn cancel_in_flight_from(&mut self, block_number: u64) -> usize {
+        let reorged = self.in_flight.split_off(&(block_number, 0));
+        for token in reorged.values() {
+            token.cancel();
+        }
+        reorged.len()
+    }
+
+    pub fn metrics(&self) -> &FoldMetrics {
+        &self.metrics
+    }
+
+    /// Whether the events folded from the block are checked against its
+    /// receipts
+    pub fn samples_for_gaps(&self, block_number: u64) -> bool {
+        self.gap_check_interval != 0
+            && block_number % self.gap_check_interval == 0
+    }
+
+    /// Directory of the checkpoints, if they are on
+    pub fn checkpoint_dir(&self) -> Option<PathBuf> {
+        self.checkpoint_dir.clone()
+    }
+
+    /// How many checkpoints of each state are kept, which is at least one
+    pub fn checkpoint_history(&self) -> usize {
+        self.checkpoint_history.max(1)
+    }
+
+    pub fn checkpoint_signer(&self) -> Option<&LocalWallet> {
+        self.checkpoint_signer.as_ref()
+    }
+
+    /// Whether the state folded from the block is checkpointed
+    pub fn checkpoints_at(&self, block_number: u64) -> bool {
+        self.checkpoint_dir.is_some()
+            && self.checkpoint_interval != 0
+            && block_number % self.checkpoint_interval == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_cancels_only_the_folds_in_flight() {
+        let mut user_data = UserData::default();
+        let (_, in_flight) = user_data.start_in_flight(10);
+        user_data.cancel_in_flight();
+        assert!(in_flight.is_cancelled());
+        assert!(!user_data.start_in_flight(10).1.is_cancelled());
+    }
+
+    #[test]
+    fn it_cancels_only_the_folds_of_reorged_blocks() {
+        let mut user_data = UserData::default();
+        let (_, before) = user_data.start_in_flight(9);
+        let (_, at) = user_data.start_in_flight(10);
+        let (_, after) = user_data.start_in_flight(11);
+        let (finished, finished_token) = user_data.start_in_flight(12);
+        user_data.finish_in_flight(finished);
+
+        assert_eq!(user_data.cancel_in_flight_from(10), 2);
+        assert!(!before.is_cancelled());
+        assert!(at.is_cancelled());
+        assert!(after.is_cancelled());
+        assert!(!finished_token.is_cancelled());
+
+        user_data.cancel_in_flight();
+        assert!(before.is_cancelled());
+    }
+
+    #[test]
+    fn it_samples_blocks_for_gap_checks() {
+        assert!(!UserData::default().samples_for_gaps(0));
+        let user_data = UserData::default().with_gap_check_interval(10);
+        assert!(user_data.samples_for_gaps(20));
+        assert!(!user_data.samples_for_gaps(21));
+    }
+
+    #[test]
+    fn it_checkpoints_at_the_interval() {
+        assert!(!UserData::default().checkpoints_at(0));
+        let user_data =
+            UserData::default().with_checkpoints("checkpoints".into(), 100, 0);
+        assert!(user_data.checkpoints_at(1000));
+        assert!(!user_data.checkpoints_at(1001));
+        assert_eq!(user_data.checkpoint_history(), 1);
+    }
 }
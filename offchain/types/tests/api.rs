@@ -0,0 +1,103 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold_types::{
+    ethers::types::{Address, Bloom, H256},
+    Block,
+};
+use im::{hashmap, vector};
+use std::fs::read_to_string;
+use std::sync::Arc;
+use types::api::v1;
+use types::canonical::to_canonical_json;
+use types::foldables::{
+    Claim, DAppClaims, DAppInputBox, History, Input, InputBox,
+};
+
+/// Compatibility suite of the stable API. The golden files pin the JSON of
+/// each released version; a change to them is a breaking change, and only
+/// happens in a new version module.
+const GOLDEN_PATH: &str = "tests/golden/";
+
+fn golden(name: &str) -> String {
+    let path = format!("{}{}.json", GOLDEN_PATH, name);
+    read_to_string(path).expect("failed to read golden file")
+}
+
+fn address(byte: u8) -> Arc<Address> {
+    Arc::new(Address::repeat_byte(byte))
+}
+
+fn block() -> Arc<Block> {
+    Arc::new(Block {
+        hash: H256::repeat_byte(0x22),
+        number: 7.into(),
+        parent_hash: H256::repeat_byte(0x33),
+        timestamp: 1000.into(),
+        logs_bloom: Bloom::default(),
+    })
+}
+
+fn input(dapp: u8, payload: &[u8]) -> Arc<Input> {
+    Arc::new(Input {
+        sender: address(0x11),
+        payload: payload.to_vec(),
+        block_added: block(),
+        dapp: address(dapp),
+        tx_hash: Arc::new(H256::repeat_byte(0x44)),
+    })
+}
+
+fn rollups_state() -> v1::RollupsState {
+    let input_box = InputBox {
+        dapp_address: address(0xaa),
+        input_box_address: address(0xbb),
+        dapp_input_boxes: Arc::new(hashmap! {
+            address(0xaa) => Arc::new(DAppInputBox {
+                inputs: vector![input(0xaa, b"\x01\x02"), input(0xaa, b"")],
+            }),
+            address(0xcc) => Arc::new(DAppInputBox {
+                inputs: vector![input(0xcc, b"\x03")],
+            }),
+        }),
+    };
+    let history = History {
+        dapp_address: address(0xaa),
+        history_address: address(0xdd),
+        dapp_claims: Arc::new(hashmap! {
+            address(0xaa) => Arc::new(DAppClaims {
+                claims: vector![Arc::new(Claim {
+                    epoch_hash: H256::repeat_byte(0x55),
+                    first_index: 0,
+                    last_index: 4,
+                    block_added: block(),
+                    tx_hash: Arc::new(H256::repeat_byte(0x44)),
+                })],
+            }),
+        }),
+    };
+    v1::RollupsState::new(&input_box, &history)
+}
+
+#[test]
+fn test_v1_json_is_stable() {
+    let json = to_canonical_json(&rollups_state()).unwrap();
+    assert_eq!(json, golden("api_v1_rollups_state").trim_end());
+}
+
+#[test]
+fn test_v1_reads_released_documents() {
+    let state: v1::RollupsState =
+        serde_json::from_str(&golden("api_v1_rollups_state")).unwrap();
+    assert_eq!(state, rollups_state());
+}
+
+#[test]
+fn test_v1_ignores_fields_of_later_versions() {
+    let mut json: serde_json::Value =
+        serde_json::from_str(&golden("api_v1_rollups_state")).unwrap();
+    json["added_later"] = serde_json::json!({"any": "value"});
+    json["inputs"][0]["added_later"] = serde_json::json!(1);
+    let state: v1::RollupsState = serde_json::from_value(json).unwrap();
+    assert_eq!(state, rollups_state());
+}
@@ -0,0 +1,109 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use eth_state_fold_types::{
+    ethers::types::{Address, Bloom, H256},
+    Block,
+};
+use im::{hashmap, vector};
+use std::fs::read_to_string;
+use std::sync::Arc;
+use types::canonical::to_canonical_json;
+use types::foldables::{
+    Claim, DAppClaims, DAppFactory, DAppFactoryInitialState, DAppInputBox,
+    DeployedDApp, History, Input, InputBox,
+};
+
+/// Canonical JSON of the states released so far. A state whose canonical
+/// JSON changes breaks the external tools that diff them, so the golden
+/// files are only updated along with a note in the changelog.
+const GOLDEN_PATH: &str = "tests/golden/";
+
+fn assert_golden(name: &str, json: String) {
+    let path = format!("{}{}.json", GOLDEN_PATH, name);
+    let golden = read_to_string(&path).expect("failed to read golden file");
+    assert_eq!(json, golden.trim_end(), "{} changed", path);
+}
+
+fn address(byte: u8) -> Arc<Address> {
+    Arc::new(Address::repeat_byte(byte))
+}
+
+fn block() -> Arc<Block> {
+    Arc::new(Block {
+        hash: H256::repeat_byte(0x22),
+        number: 7.into(),
+        parent_hash: H256::repeat_byte(0x33),
+        timestamp: 1000.into(),
+        logs_bloom: Bloom::default(),
+    })
+}
+
+fn input(dapp: u8, payload: &[u8]) -> Arc<Input> {
+    Arc::new(Input {
+        sender: address(0x11),
+        payload: payload.to_vec(),
+        block_added: block(),
+        dapp: address(dapp),
+        tx_hash: Arc::new(H256::repeat_byte(0x44)),
+    })
+}
+
+#[test]
+fn test_input_box() {
+    // Inserted out of order, which the JSON must not depend on
+    let input_box = InputBox {
+        dapp_address: address(0xaa),
+        input_box_address: address(0xbb),
+        dapp_input_boxes: Arc::new(hashmap! {
+            address(0xcc) => Arc::new(DAppInputBox {
+                inputs: vector![input(0xcc, b"\x03")],
+            }),
+            address(0xaa) => Arc::new(DAppInputBox {
+                inputs: vector![input(0xaa, b"\x01\x02")],
+            }),
+        }),
+    };
+    assert_golden("input_box", to_canonical_json(&input_box).unwrap());
+}
+
+#[test]
+fn test_history() {
+    let claim = Arc::new(Claim {
+        epoch_hash: H256::repeat_byte(0x55),
+        first_index: 0,
+        last_index: 4,
+        block_added: block(),
+        tx_hash: Arc::new(H256::repeat_byte(0x44)),
+    });
+    let history = History {
+        dapp_address: address(0xaa),
+        history_address: address(0xdd),
+        dapp_claims: Arc::new(hashmap! {
+            address(0xaa) => Arc::new(DAppClaims {
+                claims: vector![claim],
+            }),
+        }),
+    };
+    assert_golden("history", to_canonical_json(&history).unwrap());
+}
+
+#[test]
+fn test_dapp_factory() {
+    let factory = DAppFactory {
+        initial_state: Arc::new(DAppFactoryInitialState {
+            factory_address: address(0xff),
+            dapp_owner: Some(address(0x01)),
+            template_hash: None,
+        }),
+        dapps: vector![Arc::new(DeployedDApp {
+            dapp_address: address(0xaa),
+            dapp_owner: address(0x01),
+            consensus_address: address(0xee),
+            template_hash: H256::repeat_byte(0x66),
+            block_added: block(),
+            tx_hash: Arc::new(H256::repeat_byte(0x44)),
+        })],
+    };
+    assert_golden("dapp_factory", to_canonical_json(&factory).unwrap());
+}
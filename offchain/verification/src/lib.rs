@@ -0,0 +1,378 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Verification of the outputs served by the node, as done on-chain by the
+//! DApp contract, so clients such as DApp front-ends can check the proofs
+//! of the reader API themselves.
+//!
+//! The crate is `no_std` without the default `std` feature and only needs
+//! Keccak-256. With the `wasm` feature it also exports JS bindings of the
+//! functions, for WebAssembly builds.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod merkle;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use core::fmt;
+use sha3::{Digest, Keccak256};
+
+pub use merkle::{root_after_replacement, root_from_hash};
+
+pub const HASH_SIZE: usize = 32;
+pub const ADDRESS_SIZE: usize = 20;
+
+/// Keccak-256 hash
+pub type Hash = [u8; HASH_SIZE];
+
+/// Log2 of the size of a hash, which is a leaf of the output trees
+pub const LOG2_HASH_SIZE: u32 = 5;
+
+/// Log2 of the size of a machine word, which is a leaf of the tree of the
+/// bytes of an output hash
+pub const LOG2_WORD_SIZE: u32 = 3;
+
+/// Log2 of the size of the output hashes of an input, up to 2^16 of them
+pub const LOG2_OUTPUT_HASHES_SIZE: u32 = 16 + LOG2_HASH_SIZE;
+
+/// Log2 of the size of the output hashes roots of an epoch, up to 2^32 of
+/// them
+pub const LOG2_EPOCH_OUTPUTS_SIZE: u32 = 32 + LOG2_HASH_SIZE;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The epoch hash isn't the one of the epoch roots of the proof
+    EpochHashMismatch,
+    /// The output hashes root of the proof isn't in the epoch
+    OutputsEpochRootMismatch,
+    /// The output isn't in the output hashes of the proof
+    OutputHashesRootMismatch,
+    /// The replacement is not aligned to its position in the tree
+    MisalignedPosition,
+    /// The number of siblings doesn't match the sizes of the tree
+    SiblingCountMismatch { expected: usize, got: usize },
+    /// The upper 128 bits of the voucher position don't fit an index
+    PositionOutOfRange,
+    /// A value doesn't have the expected number of bytes
+    InvalidLength { expected: usize, got: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EpochHashMismatch => {
+                write!(f, "epoch hash doesn't match the proof")
+            }
+            Error::OutputsEpochRootMismatch => {
+                write!(f, "output hashes root isn't in the outputs epoch root")
+            }
+            Error::OutputHashesRootMismatch => {
+                write!(f, "output hash isn't in the output hashes root")
+            }
+            Error::MisalignedPosition => {
+                write!(f, "position is not aligned to the replacement")
+            }
+            Error::SiblingCountMismatch { expected, got } => write!(
+                f,
+                "expected {} siblings in the proof but got {}",
+                expected, got
+            ),
+            Error::PositionOutOfRange => {
+                write!(f, "voucher position is out of range")
+            }
+            Error::InvalidLength { expected, got } => {
+                write!(f, "expected {} bytes but got {}", expected, got)
+            }
+        }
+    }
+}
+
+/// Kind of an output, whose epoch root is in the epoch hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputKind {
+    Voucher,
+    Notice,
+}
+
+/// Proof that an output was produced by an input of an epoch, as returned
+/// by the reader API; the siblings go from the leaves to the root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputValidityProof {
+    pub input_index_within_epoch: u64,
+    pub output_index_within_input: u64,
+    pub output_hashes_root_hash: Hash,
+    pub vouchers_epoch_root_hash: Hash,
+    pub notices_epoch_root_hash: Hash,
+    pub machine_state_hash: Hash,
+    pub output_hash_in_output_hashes_siblings: alloc::vec::Vec<Hash>,
+    pub output_hashes_in_epoch_siblings: alloc::vec::Vec<Hash>,
+}
+
+impl OutputValidityProof {
+    /// Hash of the epoch the proof is for
+    pub fn epoch_hash(&self) -> Hash {
+        epoch_hash(
+            &self.vouchers_epoch_root_hash,
+            &self.notices_epoch_root_hash,
+            &self.machine_state_hash,
+        )
+    }
+
+    /// Checks that the output with the hash was produced in the epoch with
+    /// the given hash, the same way as the DApp contract does before
+    /// executing a voucher or validating a notice
+    pub fn verify(
+        &self,
+        kind: OutputKind,
+        output_hash: &Hash,
+        epoch_hash: &Hash,
+    ) -> Result<(), Error> {
+        if self.epoch_hash() != *epoch_hash {
+            return Err(Error::EpochHashMismatch);
+        }
+
+        let outputs_epoch_root_hash = match kind {
+            OutputKind::Voucher => &self.vouchers_epoch_root_hash,
+            OutputKind::Notice => &self.notices_epoch_root_hash,
+        };
+        let root = root_after_replacement(
+            self.input_index_within_epoch << LOG2_HASH_SIZE,
+            LOG2_HASH_SIZE,
+            LOG2_EPOCH_OUTPUTS_SIZE,
+            self.output_hashes_root_hash,
+            &self.output_hashes_in_epoch_siblings,
+        )?;
+        if root != *outputs_epoch_root_hash {
+            return Err(Error::OutputsEpochRootMismatch);
+        }
+
+        let root = root_after_replacement(
+            self.output_index_within_input << LOG2_HASH_SIZE,
+            LOG2_HASH_SIZE,
+            LOG2_OUTPUT_HASHES_SIZE,
+            root_from_hash(output_hash),
+            &self.output_hash_in_output_hashes_siblings,
+        )?;
+        if root != self.output_hashes_root_hash {
+            return Err(Error::OutputHashesRootMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Hash of an epoch, which is the claim submitted for it
+pub fn epoch_hash(
+    vouchers_epoch_root_hash: &Hash,
+    notices_epoch_root_hash: &Hash,
+    machine_state_hash: &Hash,
+) -> Hash {
+    let mut hasher = Keccak256::new();
+    hasher.update(vouchers_epoch_root_hash);
+    hasher.update(notices_epoch_root_hash);
+    hasher.update(machine_state_hash);
+    hasher.finalize().into()
+}
+
+/// Hash of a voucher, the keccak of its ABI-encoded destination and payload
+pub fn voucher_hash(destination: &[u8; ADDRESS_SIZE], payload: &[u8]) -> Hash {
+    let mut hasher = Keccak256::new();
+    hasher.update([0; HASH_SIZE - ADDRESS_SIZE]);
+    hasher.update(destination);
+    hasher.update(word(0x40));
+    update_with_bytes(&mut hasher, payload);
+    hasher.finalize().into()
+}
+
+/// Hash of a notice, the keccak of its ABI-encoded payload
+pub fn notice_hash(payload: &[u8]) -> Hash {
+    let mut hasher = Keccak256::new();
+    hasher.update(word(0x20));
+    update_with_bytes(&mut hasher, payload);
+    hasher.finalize().into()
+}
+
+fn word(value: u64) -> Hash {
+    let mut word = [0; HASH_SIZE];
+    word[HASH_SIZE - 8..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// Length-prefixed and padded to a whole number of words
+fn update_with_bytes(hasher: &mut Keccak256, bytes: &[u8]) {
+    hasher.update(word(bytes.len() as u64));
+    hasher.update(bytes);
+    let padding = (HASH_SIZE - bytes.len() % HASH_SIZE) % HASH_SIZE;
+    hasher.update(&[0; HASH_SIZE][..padding]);
+}
+
+/// Position of a voucher in the bitmask of the executed vouchers of the
+/// DApp contract, which is `output_index << 128 | input_index`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoucherPosition {
+    pub input_index: u64,
+    pub output_index: u64,
+}
+
+impl VoucherPosition {
+    /// Big-endian 256-bit position
+    pub fn encode(&self) -> Hash {
+        let mut position = [0; HASH_SIZE];
+        position[8..16].copy_from_slice(&self.output_index.to_be_bytes());
+        position[24..].copy_from_slice(&self.input_index.to_be_bytes());
+        position
+    }
+
+    pub fn decode(position: &Hash) -> Result<Self, Error> {
+        let fits = position[..8] == [0; 8] && position[16..24] == [0; 8];
+        if !fits {
+            return Err(Error::PositionOutOfRange);
+        }
+        let mut output_index = [0; 8];
+        output_index.copy_from_slice(&position[8..16]);
+        let mut input_index = [0; 8];
+        input_index.copy_from_slice(&position[24..]);
+        Ok(Self {
+            input_index: u64::from_be_bytes(input_index),
+            output_index: u64::from_be_bytes(output_index),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn hash(hex: &str) -> Hash {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    /// Hashes of the empty subtrees of log2 sizes from the hash size up
+    fn pristine_siblings(count: usize) -> Vec<Hash> {
+        let mut siblings = Vec::with_capacity(count);
+        let mut sibling = [0; HASH_SIZE];
+        for _ in 0..count {
+            siblings.push(sibling);
+            let mut hasher = Keccak256::new();
+            hasher.update(sibling);
+            hasher.update(sibling);
+            sibling = hasher.finalize().into();
+        }
+        siblings
+    }
+
+    fn proof(output_hash: &Hash) -> OutputValidityProof {
+        let output_hashes_siblings = pristine_siblings(16);
+        let output_hashes_root_hash = root_after_replacement(
+            1 << LOG2_HASH_SIZE,
+            LOG2_HASH_SIZE,
+            LOG2_OUTPUT_HASHES_SIZE,
+            root_from_hash(output_hash),
+            &output_hashes_siblings,
+        )
+        .unwrap();
+        let epoch_siblings = pristine_siblings(32);
+        let vouchers_epoch_root_hash = root_after_replacement(
+            2 << LOG2_HASH_SIZE,
+            LOG2_HASH_SIZE,
+            LOG2_EPOCH_OUTPUTS_SIZE,
+            output_hashes_root_hash,
+            &epoch_siblings,
+        )
+        .unwrap();
+        OutputValidityProof {
+            input_index_within_epoch: 2,
+            output_index_within_input: 1,
+            output_hashes_root_hash,
+            vouchers_epoch_root_hash,
+            notices_epoch_root_hash: [0x11; HASH_SIZE],
+            machine_state_hash: [0x22; HASH_SIZE],
+            output_hash_in_output_hashes_siblings: output_hashes_siblings,
+            output_hashes_in_epoch_siblings: epoch_siblings,
+        }
+    }
+
+    #[test]
+    fn it_hashes_the_outputs() {
+        let destination = [0x55; ADDRESS_SIZE];
+        assert_eq!(
+            voucher_hash(&destination, b"hello world"),
+            hash(
+                "61a61380d2a3b5e2b09a5ff259a2e1048da1989bdd6d6ecc69594cfbedc01278"
+            )
+        );
+        assert_eq!(
+            notice_hash(b"hello world"),
+            hash(
+                "d9f29a4e347ad89dc70490124ee6975fbc0693c7e72d6bc383673bfd0e8841f2"
+            )
+        );
+    }
+
+    #[test]
+    fn it_verifies_an_output_of_the_epoch() {
+        let output_hash = voucher_hash(&[0x55; ADDRESS_SIZE], b"hello world");
+        let proof = proof(&output_hash);
+        let epoch_hash = proof.epoch_hash();
+        assert_eq!(
+            proof.verify(OutputKind::Voucher, &output_hash, &epoch_hash),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn it_rejects_outputs_not_in_the_epoch() {
+        let output_hash = voucher_hash(&[0x55; ADDRESS_SIZE], b"hello world");
+        let proof = proof(&output_hash);
+        let epoch_hash = proof.epoch_hash();
+        assert_eq!(
+            proof.verify(OutputKind::Voucher, &output_hash, &[0; HASH_SIZE]),
+            Err(Error::EpochHashMismatch)
+        );
+        assert_eq!(
+            proof.verify(OutputKind::Notice, &output_hash, &epoch_hash),
+            Err(Error::OutputsEpochRootMismatch)
+        );
+        let other_hash = voucher_hash(&[0x55; ADDRESS_SIZE], b"hello");
+        assert_eq!(
+            proof.verify(OutputKind::Voucher, &other_hash, &epoch_hash),
+            Err(Error::OutputHashesRootMismatch)
+        );
+
+        let mut truncated = proof.clone();
+        truncated.output_hashes_in_epoch_siblings.pop();
+        assert_eq!(
+            truncated.verify(OutputKind::Voucher, &output_hash, &epoch_hash),
+            Err(Error::SiblingCountMismatch {
+                expected: 32,
+                got: 31
+            })
+        );
+    }
+
+    #[test]
+    fn it_encodes_the_voucher_position() {
+        let position = VoucherPosition {
+            input_index: 7,
+            output_index: 3,
+        };
+        let encoded = position.encode();
+        assert_eq!(
+            encoded,
+            hash(
+                "0000000000000000000000000000000300000000000000000000000000000007"
+            )
+        );
+        assert_eq!(VoucherPosition::decode(&encoded), Ok(position));
+
+        let mut out_of_range = encoded;
+        out_of_range[20] = 1;
+        assert_eq!(
+            VoucherPosition::decode(&out_of_range),
+            Err(Error::PositionOutOfRange)
+        );
+    }
+}
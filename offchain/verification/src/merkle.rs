@@ -0,0 +1,105 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use sha3::{Digest, Keccak256};
+
+use crate::{Error, Hash, HASH_SIZE, LOG2_WORD_SIZE};
+
+fn concat_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Keccak256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Root of the tree of a hash taken as 32 bytes of data, whose leaves are
+/// the keccaks of its four words, which is how an output hash is stored in
+/// the memory of the machine
+pub fn root_from_hash(hash: &Hash) -> Hash {
+    const WORD_SIZE: usize = 1 << LOG2_WORD_SIZE;
+    let words: [Hash; HASH_SIZE / WORD_SIZE] = core::array::from_fn(|i| {
+        Keccak256::digest(&hash[i * WORD_SIZE..(i + 1) * WORD_SIZE]).into()
+    });
+    concat_hash(
+        &concat_hash(&words[0], &words[1]),
+        &concat_hash(&words[2], &words[3]),
+    )
+}
+
+/// Root of a tree of size `2^log2_root_size` after the node of size
+/// `2^log2_replacement_size` at `position` is replaced, given the siblings
+/// of the node from the bottom up
+pub fn root_after_replacement(
+    position: u64,
+    log2_replacement_size: u32,
+    log2_root_size: u32,
+    replacement: Hash,
+    siblings: &[Hash],
+) -> Result<Hash, Error> {
+    let expected = log2_root_size.saturating_sub(log2_replacement_size);
+    if siblings.len() != expected as usize {
+        return Err(Error::SiblingCountMismatch {
+            expected: expected as usize,
+            got: siblings.len(),
+        });
+    }
+    let size = 1_u64
+        .checked_shl(log2_replacement_size)
+        .ok_or(Error::MisalignedPosition)?;
+    if position & (size - 1) != 0 {
+        return Err(Error::MisalignedPosition);
+    }
+    let mut root = replacement;
+    for (level, sibling) in siblings.iter().enumerate() {
+        let bit = log2_replacement_size as usize + level;
+        let is_left = bit >= 64 || position & (1 << bit) == 0;
+        root = if is_left {
+            concat_hash(&root, sibling)
+        } else {
+            concat_hash(sibling, &root)
+        };
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_the_tree_of_the_words_of_a_hash() {
+        let hash: Hash = core::array::from_fn(|i| i as u8);
+        let expected: Hash = hex::decode(
+            "b609db113d59e90c2c1336dfa032e23c9eaaebe20c667081b0d6ae3926605e5c",
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        assert_eq!(root_from_hash(&hash), expected);
+    }
+
+    #[test]
+    fn it_replaces_a_node_at_its_position() {
+        let leaves: [Hash; 4] = core::array::from_fn(|i| [i as u8; 32]);
+        let root = concat_hash(
+            &concat_hash(&leaves[0], &leaves[1]),
+            &concat_hash(&leaves[2], &leaves[3]),
+        );
+        let siblings = [leaves[3], concat_hash(&leaves[0], &leaves[1])];
+        assert_eq!(
+            root_after_replacement(2 << 5, 5, 7, leaves[2], &siblings),
+            Ok(root)
+        );
+        assert_eq!(
+            root_after_replacement(3, 5, 7, leaves[2], &siblings),
+            Err(Error::MisalignedPosition)
+        );
+        assert_eq!(
+            root_after_replacement(2 << 5, 5, 8, leaves[2], &siblings),
+            Err(Error::SiblingCountMismatch {
+                expected: 3,
+                got: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,157 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! JS bindings, which take the byte strings of the reader API decoded into
+//! `Uint8Array`s and the lists of sibling hashes concatenated into one
+
+use alloc::{string::ToString, vec::Vec};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    Error, Hash, OutputKind, VoucherPosition, ADDRESS_SIZE, HASH_SIZE,
+};
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> Self {
+        JsError::new(&error.to_string()).into()
+    }
+}
+
+fn bytes<const N: usize>(value: &[u8]) -> Result<[u8; N], Error> {
+    value.try_into().map_err(|_| Error::InvalidLength {
+        expected: N,
+        got: value.len(),
+    })
+}
+
+fn hashes(value: &[u8]) -> Result<Vec<Hash>, Error> {
+    value.chunks(HASH_SIZE).map(bytes).collect()
+}
+
+#[wasm_bindgen(js_name = voucherHash)]
+pub fn voucher_hash(
+    destination: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let destination = bytes::<ADDRESS_SIZE>(destination)?;
+    Ok(crate::voucher_hash(&destination, payload).to_vec())
+}
+
+#[wasm_bindgen(js_name = noticeHash)]
+pub fn notice_hash(payload: &[u8]) -> Vec<u8> {
+    crate::notice_hash(payload).to_vec()
+}
+
+#[wasm_bindgen(js_name = epochHash)]
+pub fn epoch_hash(
+    vouchers_epoch_root_hash: &[u8],
+    notices_epoch_root_hash: &[u8],
+    machine_state_hash: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    Ok(crate::epoch_hash(
+        &bytes(vouchers_epoch_root_hash)?,
+        &bytes(notices_epoch_root_hash)?,
+        &bytes(machine_state_hash)?,
+    )
+    .to_vec())
+}
+
+/// Voucher position in the bitmask of the executed vouchers
+#[wasm_bindgen(js_name = VoucherPosition)]
+pub struct JsVoucherPosition {
+    #[wasm_bindgen(js_name = inputIndex)]
+    pub input_index: u64,
+    #[wasm_bindgen(js_name = outputIndex)]
+    pub output_index: u64,
+}
+
+#[wasm_bindgen(js_name = decodeVoucherPosition)]
+pub fn decode_voucher_position(
+    position: &[u8],
+) -> Result<JsVoucherPosition, JsValue> {
+    let position = VoucherPosition::decode(&bytes(position)?)?;
+    Ok(JsVoucherPosition {
+        input_index: position.input_index,
+        output_index: position.output_index,
+    })
+}
+
+#[wasm_bindgen(js_name = encodeVoucherPosition)]
+pub fn encode_voucher_position(input_index: u64, output_index: u64) -> Vec<u8> {
+    VoucherPosition {
+        input_index,
+        output_index,
+    }
+    .encode()
+    .to_vec()
+}
+
+/// Output validity proof of the reader API
+#[wasm_bindgen(js_name = OutputValidityProof)]
+pub struct JsOutputValidityProof(crate::OutputValidityProof);
+
+#[wasm_bindgen(js_class = OutputValidityProof)]
+impl JsOutputValidityProof {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        input_index_within_epoch: u64,
+        output_index_within_input: u64,
+        output_hashes_root_hash: &[u8],
+        vouchers_epoch_root_hash: &[u8],
+        notices_epoch_root_hash: &[u8],
+        machine_state_hash: &[u8],
+        output_hash_in_output_hashes_siblings: &[u8],
+        output_hashes_in_epoch_siblings: &[u8],
+    ) -> Result<JsOutputValidityProof, JsValue> {
+        Ok(Self(crate::OutputValidityProof {
+            input_index_within_epoch,
+            output_index_within_input,
+            output_hashes_root_hash: bytes(output_hashes_root_hash)?,
+            vouchers_epoch_root_hash: bytes(vouchers_epoch_root_hash)?,
+            notices_epoch_root_hash: bytes(notices_epoch_root_hash)?,
+            machine_state_hash: bytes(machine_state_hash)?,
+            output_hash_in_output_hashes_siblings: hashes(
+                output_hash_in_output_hashes_siblings,
+            )?,
+            output_hashes_in_epoch_siblings: hashes(
+                output_hashes_in_epoch_siblings,
+            )?,
+        }))
+    }
+
+    #[wasm_bindgen(js_name = epochHash)]
+    pub fn epoch_hash(&self) -> Vec<u8> {
+        self.0.epoch_hash().to_vec()
+    }
+
+    /// Throws unless the voucher is in the epoch with the given hash
+    #[wasm_bindgen(js_name = verifyVoucher)]
+    pub fn verify_voucher(
+        &self,
+        destination: &[u8],
+        payload: &[u8],
+        epoch_hash: &[u8],
+    ) -> Result<(), JsValue> {
+        let output_hash = crate::voucher_hash(&bytes(destination)?, payload);
+        self.0.verify(
+            OutputKind::Voucher,
+            &output_hash,
+            &bytes(epoch_hash)?,
+        )?;
+        Ok(())
+    }
+
+    /// Throws unless the notice is in the epoch with the given hash
+    #[wasm_bindgen(js_name = verifyNotice)]
+    pub fn verify_notice(
+        &self,
+        payload: &[u8],
+        epoch_hash: &[u8],
+    ) -> Result<(), JsValue> {
+        let output_hash = crate::notice_hash(payload);
+        self.0
+            .verify(OutputKind::Notice, &output_hash, &bytes(epoch_hash)?)?;
+        Ok(())
+    }
+}